@@ -12,3 +12,5 @@ def run_command(cmd):
 
 run_command("cargo fmt --check")
 run_command("cargo test")
+# the fuzz targets are not part of the workspace, they should still build
+run_command("cargo check --manifest-path fuzz/Cargo.toml")
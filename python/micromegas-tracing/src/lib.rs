@@ -0,0 +1,175 @@
+//! Python bindings to micromegas tracing
+//!
+//! Events are recorded in the buffers of the process like with the rust macros and sent by the
+//! http sink to `MICROMEGAS_TELEMETRY_URL`, without going through the interpreter.
+use micromegas_telemetry_sink::{TelemetryGuard, TelemetryGuardBuilder};
+use micromegas_tracing::dispatch::{
+    float_metric, flush_log_buffer, flush_metrics_buffer, flush_thread_buffer,
+    init_named_thread_stream, init_thread_stream, int_metric, log_interop, on_begin_named_scope,
+    on_end_named_scope, set_process_property,
+};
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::levels::{Level, LevelFilter, Verbosity};
+use micromegas_tracing::logs::{LogMetadata, FILTER_LEVEL_UNSET_VALUE};
+use micromegas_tracing::metrics::make_metric_metadata;
+use micromegas_tracing::spans::SpanLocation;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU32;
+
+static PYTHON_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Min,
+    target: "python",
+    module_path: "python",
+    file: "python",
+    line: 0,
+};
+
+/// Keeps the telemetry alive, flushed and stopped by `shutdown` or at the end of a `with` block
+#[pyclass(unsendable)]
+struct Telemetry {
+    guard: Option<TelemetryGuard>,
+}
+
+#[pymethods]
+impl Telemetry {
+    fn shutdown(&mut self) {
+        self.guard = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.shutdown();
+        false
+    }
+}
+
+/// Span of the calling thread, recorded between `__enter__` and `__exit__`
+#[pyclass]
+struct Span {
+    name: &'static str,
+}
+
+#[pymethods]
+impl Span {
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        init_thread_stream();
+        on_begin_named_scope(&PYTHON_SPAN_LOCATION, slf.name);
+        slf
+    }
+
+    fn __exit__(&self, _exc_type: PyObject, _exc_value: PyObject, _traceback: PyObject) -> bool {
+        on_end_named_scope(&PYTHON_SPAN_LOCATION, self.name);
+        false
+    }
+}
+
+/// Starts sending telemetry, `max_level` filtering the logs: fatal, error, warn, info, debug or trace
+#[pyfunction]
+#[pyo3(signature = (max_level = "info"))]
+fn init(max_level: &str) -> PyResult<Telemetry> {
+    let max_level = LevelFilter::from_str(max_level)
+        .map_err(|_| PyValueError::new_err(format!("invalid max_level {max_level}")))?;
+    let guard = TelemetryGuardBuilder::default()
+        .with_local_sink_max_level(max_level)
+        .build()
+        .map_err(|e| PyRuntimeError::new_err(format!("{e:?}")))?;
+    Ok(Telemetry { guard: Some(guard) })
+}
+
+/// Adds a property to the process, to be called before `init`
+#[pyfunction(name = "set_process_property")]
+fn py_set_process_property(key: &str, value: &str) {
+    set_process_property(key, value);
+}
+
+/// Names the stream of the calling thread, to be called before its first span
+#[pyfunction]
+fn register_thread(name: &str) {
+    init_named_thread_stream(name);
+}
+
+/// Sends the spans of the calling thread, to be called before the thread exits
+#[pyfunction]
+fn unregister_thread() {
+    flush_thread_buffer();
+}
+
+/// `level`: 1 fatal, 2 error, 3 warn, 4 info, 5 debug, 6 trace
+#[pyfunction]
+#[pyo3(signature = (level, message, target = "python"))]
+fn log(level: u32, message: &str, target: &str) -> PyResult<()> {
+    let level = Level::from_value(level)
+        .ok_or_else(|| PyValueError::new_err(format!("invalid log level {level}")))?;
+    let metadata = LogMetadata {
+        level,
+        level_filter: AtomicU32::new(FILTER_LEVEL_UNSET_VALUE),
+        fmt_str: "",
+        target,
+        module_path: "python",
+        file: "python",
+        line: 0,
+    };
+    log_interop(&metadata, format_args!("{message}"));
+    Ok(())
+}
+
+/// Metric names and units are interned: they should come from a bounded set
+#[pyfunction(name = "int_metric")]
+fn py_int_metric(name: &str, unit: &str, value: u64) {
+    int_metric(
+        make_metric_metadata(intern_string(name), intern_string(unit), "python"),
+        value,
+    );
+}
+
+#[pyfunction(name = "float_metric")]
+fn py_float_metric(name: &str, unit: &str, value: f64) {
+    float_metric(
+        make_metric_metadata(intern_string(name), intern_string(unit), "python"),
+        value,
+    );
+}
+
+/// `with span("name"):` records the block as a span of the calling thread.
+/// Span names are interned: they should come from a bounded set
+#[pyfunction]
+fn span(name: &str) -> Span {
+    Span {
+        name: intern_string(name),
+    }
+}
+
+/// Sends the buffered logs and metrics, and the spans of the calling thread
+#[pyfunction]
+fn flush() {
+    flush_log_buffer();
+    flush_metrics_buffer();
+    flush_thread_buffer();
+}
+
+#[pymodule]
+#[pyo3(name = "micromegas_tracing")]
+fn micromegas_tracing_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Telemetry>()?;
+    m.add_class::<Span>()?;
+    m.add_function(wrap_pyfunction!(init, m)?)?;
+    m.add_function(wrap_pyfunction!(py_set_process_property, m)?)?;
+    m.add_function(wrap_pyfunction!(register_thread, m)?)?;
+    m.add_function(wrap_pyfunction!(unregister_thread, m)?)?;
+    m.add_function(wrap_pyfunction!(log, m)?)?;
+    m.add_function(wrap_pyfunction!(py_int_metric, m)?)?;
+    m.add_function(wrap_pyfunction!(py_float_metric, m)?)?;
+    m.add_function(wrap_pyfunction!(span, m)?)?;
+    m.add_function(wrap_pyfunction!(flush, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+import argparse
+import datetime
+import importlib
+import json
+import os
+import sys
+from query_processes import parse_time_delta
+from micromegas import frame_budget
+
+
+def main():
+    parser = argparse.ArgumentParser(
+        prog="frame_budget_report",
+        description="Checks span durations against frame budgets, exits with 1 when a budget is exceeded",
+        epilog="If you are in a corporate environment, you may need to set the MICROMEGAS_PYTHON_MODULE_WRAPPER environment variable to specify the python module responsible to authenticate your requests.",
+    )
+    parser.add_argument("config", help="json budget configuration")
+    parser.add_argument("--since", default="1h", help="[number][m|h|d]")
+    parser.add_argument("--exe", help="substring of the executable path")
+    parser.add_argument("--process-id")
+    parser.add_argument(
+        "--json", action="store_true", help="machine-readable output, for CI"
+    )
+    args = parser.parse_args()
+    config = frame_budget.load_budget_config(args.config)
+
+    micromegas_module_name = os.environ.get(
+        "MICROMEGAS_PYTHON_MODULE_WRAPPER", "micromegas"
+    )
+    micromegas_module = importlib.import_module(micromegas_module_name)
+    client = micromegas_module.connect()
+    now = datetime.datetime.now(datetime.timezone.utc)
+    begin = now - parse_time_delta(args.since)
+    report = frame_budget.make_report(
+        client, config, begin, now, exe=args.exe, process_id=args.process_id
+    )
+    if args.json:
+        print(json.dumps(report, indent=2))
+    else:
+        print(frame_budget.format_report(report))
+    sys.exit(0 if report["passed"] else 1)
+
+
+if __name__ == "__main__":
+    main()
@@ -2,8 +2,13 @@ import os
 from . import request
 from . import client
 from . import perfetto
+from . import frame_budget
 
 def connect():
     "connect to the analytics service using default values"
     BASE_URL = "http://localhost:8082/"
-    return client.Client(BASE_URL)
+    headers = {}
+    api_key = os.environ.get("MICROMEGAS_API_KEY")
+    if api_key:
+        headers["Authorization"] = "Bearer " + api_key
+    return client.Client(BASE_URL, headers=headers)
@@ -12,12 +12,31 @@ def format_datetime(value):
         case nonetype:
             return None
     raise RuntimeError("value of unknown type in format_datetime")
+
+
+def format_time_ranges(time_ranges):
+    return [
+        {"begin": format_datetime(begin), "end": format_datetime(end)}
+        for begin, end in time_ranges or []
+    ]
             
 
 class Client:
-    def __init__(self, base_url, headers={}):
+    def __init__(
+        self, base_url, headers={}, query_tag=None, query_timeout_ms=None, partial_results=False
+    ):
         self.analytics_base_url = base_url + "analytics/"
-        self.headers = headers
+        self.headers = dict(headers)
+        if query_tag is not None:
+            # attributes the load of this client's queries on the server
+            self.headers["x-micromegas-query-tag"] = query_tag
+        if query_timeout_ms is not None:
+            # applies to the span, thread event, log and metric queries
+            # with partial_results, the rows produced before the timeout are returned and df.attrs["truncated"] is set
+            self.headers["x-micromegas-query-timeout-ms"] = str(query_timeout_ms)
+            self.headers["x-micromegas-query-timeout-mode"] = (
+                "partial" if partial_results else "abort"
+            )
 
     def find_process(self, process_id):
         return request.request(
@@ -48,12 +67,13 @@ class Client:
             headers=self.headers,
         )
 
-    def query_blocks(self, begin, end, limit, stream_id):
+    def query_blocks(self, begin, end, limit, stream_id, process_id=None):
         args = {
             "begin": format_datetime(begin),
             "end": format_datetime(end),
             "limit": limit,
             "stream_id": stream_id,
+            "process_id": process_id,
         }
 
         return request.request(
@@ -62,9 +82,40 @@ class Client:
             headers=self.headers,
         )
 
-    def query_spans(self, begin, end, limit, stream_id):
+    def query_spans(
+        self,
+        begin,
+        end,
+        limit,
+        stream_id,
+        name_filter=None,
+        target_filter=None,
+        min_duration_ns=None,
+        session_id=None,
+        register_as=None,
+    ):
+        "with a session_id, the spans are also registered in that sql session as register_as (or spans) and feed its span_depth, span_ancestor and span_ancestor_name functions"
+        args = {
+            "begin": format_datetime(begin),
+            "end": format_datetime(end),
+            "limit": limit,
+            "stream_id": stream_id,
+            "name_filter": name_filter,
+            "target_filter": target_filter,
+            "min_duration_ns": min_duration_ns,
+        }
+        if session_id is not None:
+            args["session_id"] = str(session_id)
+            args["register_as"] = register_as
         return request.request(
             self.analytics_base_url + "query_spans",
+            args,
+            headers=self.headers,
+        )
+
+    def query_thread_events(self, begin, end, limit, stream_id):
+        return request.request(
+            self.analytics_base_url + "query_thread_events",
             {
                 "begin": format_datetime(begin),
                 "end": format_datetime(end),
@@ -74,9 +125,10 @@ class Client:
             headers=self.headers,
         )
 
-    def query_thread_events(self, begin, end, limit, stream_id):
+    def query_span_events(self, begin, end, limit, stream_id):
+        "markers recorded with span_event!, with the id of the span they were recorded in"
         return request.request(
-            self.analytics_base_url + "query_thread_events",
+            self.analytics_base_url + "query_span_events",
             {
                 "begin": format_datetime(begin),
                 "end": format_datetime(end),
@@ -86,26 +138,272 @@ class Client:
             headers=self.headers,
         )
 
-    def query_log_entries(self, begin, end, limit, stream_id):
+    def query_log_entries(self, begin, end, limit, stream_id, time_ranges=None, view=None):
+        """time_ranges: optional list of (begin, end) within [begin, end)
+        view: optional versioned view name pinning the columns, i.e. log_entries_v1"""
+        args = {
+            "begin": format_datetime(begin),
+            "end": format_datetime(end),
+            "limit": limit,
+            "stream_id": stream_id,
+            "time_ranges": format_time_ranges(time_ranges),
+        }
+        if view is not None:
+            args["view"] = view
         return request.request(
             self.analytics_base_url + "query_log_entries",
+            args,
+            headers=self.headers,
+        )
+
+    def query_metrics(self, begin, end, limit, stream_id, time_ranges=None, view=None):
+        """time_ranges: optional list of (begin, end) within [begin, end)
+        view: optional versioned view name pinning the columns, i.e. measures"""
+        args = {
+            "begin": format_datetime(begin),
+            "end": format_datetime(end),
+            "limit": limit,
+            "stream_id": stream_id,
+            "time_ranges": format_time_ranges(time_ranges),
+        }
+        if view is not None:
+            args["view"] = view
+        return request.request(
+            self.analytics_base_url + "query_metrics",
+            args,
+            headers=self.headers,
+        )
+
+    def query_property_histogram(self, begin, end, limit, view, key):
+        return request.request(
+            self.analytics_base_url + "query_property_histogram",
             {
                 "begin": format_datetime(begin),
                 "end": format_datetime(end),
                 "limit": limit,
-                "stream_id": stream_id,
+                "view": view,
+                "key": key,
             },
             headers=self.headers,
         )
 
-    def query_metrics(self, begin, end, limit, stream_id):
+    def query_view(
+        self, view, begin, end, limit, session_id=None, register_as=None, time_ranges=None
+    ):
+        "with a session_id, the rows are also registered in that sql session as register_as (or the view name)"
+        args = {
+            "view": view,
+            "begin": format_datetime(begin),
+            "end": format_datetime(end),
+            "limit": limit,
+            "time_ranges": format_time_ranges(time_ranges),
+        }
+        if session_id is not None:
+            args["session_id"] = str(session_id)
+            args["register_as"] = register_as
         return request.request(
-            self.analytics_base_url + "query_metrics",
+            self.analytics_base_url + "query_view",
+            args,
+            headers=self.headers,
+        )
+
+    def create_sql_session(self):
+        "sql session keeping temporary views and registered results between queries"
+        df = request.request(
+            self.analytics_base_url + "create_sql_session",
+            {},
+            headers=self.headers,
+        )
+        return df["session_id"][0]
+
+    def execute_sql(self, session_id, sql, register_as=None, begin=None, end=None):
+        """runs the statement in the session, keeping the result as a table named register_as if provided
+        the statement can refer to begin and end as @query_begin and @query_end"""
+        return request.request(
+            self.analytics_base_url + "execute_sql",
             {
+                "session_id": str(session_id),
+                "sql": sql,
+                "register_as": register_as,
                 "begin": format_datetime(begin),
                 "end": format_datetime(end),
-                "limit": limit,
+            },
+            headers=self.headers,
+        )
+
+    def export_sql(self, session_id, sql, rows_per_file=None, begin=None, end=None):
+        """writes the result to parquet files in the object store, returns their paths, row counts and pre-signed urls
+        the statement can refer to begin and end as @query_begin and @query_end"""
+        return request.request(
+            self.analytics_base_url + "export_sql",
+            {
+                "session_id": str(session_id),
+                "sql": sql,
+                "rows_per_file": rows_per_file,
+                "begin": format_datetime(begin),
+                "end": format_datetime(end),
+            },
+            headers=self.headers,
+        )
+
+    def close_sql_session(self, session_id):
+        return request.request(
+            self.analytics_base_url + "close_sql_session",
+            {"session_id": str(session_id)},
+            headers=self.headers,
+        )
+
+    def align_measures(self, process_a, process_b, metric, bucket_seconds):
+        "averages of a metric in two processes, in buckets relative to the start of each process"
+        return request.request(
+            self.analytics_base_url + "align_measures",
+            {
+                "process_a": process_a,
+                "process_b": process_b,
+                "metric": metric,
+                "bucket_seconds": bucket_seconds,
+            },
+            headers=self.headers,
+        )
+
+    def create_annotation(self, label, begin, end=None, properties={}):
+        "labels a time range, without end for instant markers like deploys"
+        return request.request(
+            self.analytics_base_url + "create_annotation",
+            {
+                "label": label,
+                "begin": format_datetime(begin),
+                "end": format_datetime(end),
+                "properties": properties,
+            },
+            headers=self.headers,
+        )
+
+    def update_annotation(self, annotation_id, label, begin, end=None, properties={}):
+        return request.request(
+            self.analytics_base_url + "update_annotation",
+            {
+                "annotation_id": annotation_id,
+                "label": label,
+                "begin": format_datetime(begin),
+                "end": format_datetime(end),
+                "properties": properties,
+            },
+            headers=self.headers,
+        )
+
+    def delete_annotation(self, annotation_id):
+        return request.request(
+            self.analytics_base_url + "delete_annotation",
+            {"annotation_id": annotation_id},
+            headers=self.headers,
+        )
+
+    def query_annotations(self, begin, end, limit):
+        "annotations overlapping the time range"
+        return request.request(
+            self.analytics_base_url + "query_annotations",
+            {"begin": format_datetime(begin), "end": format_datetime(end), "limit": limit},
+            headers=self.headers,
+        )
+
+    def create_screen(self, name, definition):
+        "saves a dashboard: data_sources, cells bound to them and an optional process_selector"
+        return request.request(
+            self.analytics_base_url + "create_screen",
+            {"name": name, "definition": definition},
+            headers=self.headers,
+        )
+
+    def update_screen(self, screen_id, name, definition):
+        "only the api key that created the screen can change it"
+        return request.request(
+            self.analytics_base_url + "update_screen",
+            {"screen_id": screen_id, "name": name, "definition": definition},
+            headers=self.headers,
+        )
+
+    def delete_screen(self, screen_id):
+        return request.request(
+            self.analytics_base_url + "delete_screen",
+            {"screen_id": screen_id},
+            headers=self.headers,
+        )
+
+    def find_screen(self, screen_id):
+        "the definition is in the json column definition"
+        return request.request(
+            self.analytics_base_url + "find_screen",
+            {"screen_id": screen_id},
+            headers=self.headers,
+        )
+
+    def list_screens(self):
+        return request.request(
+            self.analytics_base_url + "list_screens",
+            {},
+            headers=self.headers,
+        )
+
+    def query_exit_rates(self, begin, end, limit):
+        "panics and non-zero exit codes per executable, for the processes started in the time range"
+        return request.request(
+            self.analytics_base_url + "query_exit_rates",
+            {"begin": format_datetime(begin), "end": format_datetime(end), "limit": limit},
+            headers=self.headers,
+        )
+
+    def query_attachments(self, process_id):
+        "attachments sent by the process (screenshots, savegames, ...), without their content"
+        return request.request(
+            self.analytics_base_url + "query_attachments",
+            {"process_id": process_id},
+            headers=self.headers,
+        )
+
+    def fetch_attachment(self, attachment_id):
+        "content of the attachment, as bytes"
+        return request.bytes_request(
+            self.analytics_base_url + "fetch_attachment",
+            {"attachment_id": attachment_id},
+            headers=self.headers,
+        )
+
+    def query_tag_loads(self):
+        return request.request(
+            self.analytics_base_url + "query_tag_loads",
+            {},
+            headers=self.headers,
+        )
+
+    def tail_log_entries(self, process_id):
+        return request.streamed_request(
+            self.analytics_base_url + "tail_log_entries",
+            {"process_id": process_id},
+            headers=self.headers,
+        )
+
+    def slow_queries(self):
+        return request.request(
+            self.analytics_base_url + "slow_queries",
+            {},
+            headers=self.headers,
+        )
+
+    def query_heatmap(self):
+        return request.request(
+            self.analytics_base_url + "query_heatmap",
+            {},
+            headers=self.headers,
+        )
+
+    def subscribe_blocks(self, process_id=None, stream_id=None, include_payload_path=False):
+        return request.streamed_request(
+            self.analytics_base_url + "subscribe_blocks",
+            {
+                "process_id": process_id,
                 "stream_id": stream_id,
+                "include_payload_path": include_payload_path,
             },
             headers=self.headers,
         )
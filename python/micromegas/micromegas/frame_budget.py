@@ -0,0 +1,178 @@
+import json
+import pandas as pd
+from tabulate import tabulate
+
+# Frame budget regression detection: compares the durations of spans to their budgets.
+#
+# Budget configuration, in json:
+# {
+#     "budgets": [
+#         {"span": "frame", "max_ms": 16.6, "percentile": 0.95},
+#         {"span": "render", "max_ms": 8}
+#     ],
+#     "max_offenders": 10
+# }
+
+DEFAULT_PERCENTILE = 0.99
+REPORTED_PERCENTILES = [0.5, 0.9, 0.95, 0.99]
+
+
+def load_budget_config(path):
+    with open(path) as f:
+        config = json.load(f)
+    for budget in config["budgets"]:
+        if "span" not in budget or "max_ms" not in budget:
+            raise RuntimeError("budget entries need a span and a max_ms: {}".format(budget))
+    return config
+
+
+def find_processes(client, begin, end, limit, exe=None, process_id=None):
+    if process_id is not None:
+        return client.find_process(process_id)
+    df_processes = client.query_processes(begin, end, limit)
+    if exe is not None and not df_processes.empty:
+        df_processes = df_processes[df_processes["exe"].str.contains(exe, regex=False)]
+    return df_processes
+
+
+def fetch_process_spans(client, process_id, begin, end, span_names, max_spans_per_thread):
+    df_streams = client.query_streams(
+        begin, end, limit=1024, tag_filter="cpu", process_id=process_id
+    )
+    all_spans = []
+    for _, stream in df_streams.iterrows():
+        df_spans = client.query_spans(
+            begin, end, limit=max_spans_per_thread, stream_id=stream["stream_id"]
+        )
+        if df_spans.empty:
+            continue
+        if df_spans.shape[0] == max_spans_per_thread:
+            print(
+                "Warning: span limit reached for stream {}".format(stream["stream_id"])
+            )
+        df_spans = df_spans[df_spans["name"].isin(span_names)]
+        df_spans = df_spans.assign(
+            process_id=process_id, stream_id=stream["stream_id"]
+        )
+        all_spans.append(df_spans)
+    if not all_spans:
+        return pd.DataFrame()
+    return pd.concat(all_spans, ignore_index=True)
+
+
+def evaluate_budget(df_spans, budget, max_offenders):
+    span_name = budget["span"]
+    max_ms = float(budget["max_ms"])
+    percentile = float(budget.get("percentile", DEFAULT_PERCENTILE))
+    result = {
+        "span": span_name,
+        "max_ms": max_ms,
+        "percentile": percentile,
+        "count": 0,
+        "percentiles_ms": {},
+        "measured_ms": None,
+        "passed": True,
+        "offenders": [],
+    }
+    if df_spans.empty:
+        return result
+    df_budget_spans = df_spans[df_spans["name"] == span_name]
+    if df_budget_spans.empty:
+        return result
+    durations_ms = df_budget_spans["duration"].astype("int64") / 1_000_000.0
+    result["count"] = int(durations_ms.shape[0])
+    result["percentiles_ms"] = {
+        str(p): float(durations_ms.quantile(p)) for p in REPORTED_PERCENTILES
+    }
+    result["percentiles_ms"]["max"] = float(durations_ms.max())
+    result["measured_ms"] = float(durations_ms.quantile(percentile))
+    result["passed"] = result["measured_ms"] <= max_ms
+    offenders = df_budget_spans.assign(duration_ms=durations_ms)
+    offenders = offenders[offenders["duration_ms"] > max_ms]
+    offenders = offenders.sort_values("duration_ms", ascending=False).head(max_offenders)
+    result["offenders"] = [
+        {
+            "process_id": str(row["process_id"]),
+            "stream_id": str(row["stream_id"]),
+            "begin": pd.Timestamp(row["begin"]).isoformat(),
+            "duration_ms": float(row["duration_ms"]),
+        }
+        for _, row in offenders.iterrows()
+    ]
+    return result
+
+
+def make_report(
+    client,
+    config,
+    begin,
+    end,
+    exe=None,
+    process_id=None,
+    max_processes=64,
+    max_spans_per_thread=1024 * 1024,
+):
+    """
+    Checks the spans of the selected processes against the budgets of the config.
+    Returns a dictionary that can be serialized in json.
+    """
+    span_names = [budget["span"] for budget in config["budgets"]]
+    max_offenders = int(config.get("max_offenders", 10))
+    df_processes = find_processes(
+        client, begin, end, max_processes, exe=exe, process_id=process_id
+    )
+    process_spans = []
+    for _, process in df_processes.iterrows():
+        process_spans.append(
+            fetch_process_spans(
+                client,
+                process["process_id"],
+                begin,
+                end,
+                span_names,
+                max_spans_per_thread,
+            )
+        )
+    process_spans = [df for df in process_spans if not df.empty]
+    df_spans = (
+        pd.concat(process_spans, ignore_index=True) if process_spans else pd.DataFrame()
+    )
+    budgets = [
+        evaluate_budget(df_spans, budget, max_offenders) for budget in config["budgets"]
+    ]
+    return {
+        "passed": all(b["passed"] for b in budgets),
+        "begin": pd.Timestamp(begin).isoformat(),
+        "end": pd.Timestamp(end).isoformat(),
+        "processes": [str(p) for p in df_processes["process_id"]]
+        if not df_processes.empty
+        else [],
+        "budgets": budgets,
+    }
+
+
+def format_report(report):
+    "human readable version of the report"
+    rows = []
+    for budget in report["budgets"]:
+        row = {
+            "span": budget["span"],
+            "result": "PASS" if budget["passed"] else "FAIL",
+            "budget_ms": budget["max_ms"],
+            "percentile": budget["percentile"],
+            "measured_ms": budget["measured_ms"],
+            "count": budget["count"],
+        }
+        row.update(budget["percentiles_ms"])
+        rows.append(row)
+    lines = [
+        "frame budget report: {}".format("PASS" if report["passed"] else "FAIL"),
+        "processes: {}".format(len(report["processes"])),
+        tabulate(rows, headers="keys"),
+    ]
+    for budget in report["budgets"]:
+        if budget["offenders"]:
+            lines.append("")
+            lines.append("worst offenders for {}:".format(budget["span"]))
+            lines.append(tabulate(budget["offenders"], headers="keys"))
+    return "\n".join(lines)
@@ -38,7 +38,11 @@ class Writer:
         packet.track_descriptor.process.process_name = exe
         self.packets.append(packet)
 
-    def append_thread(self, stream_id, thread_name, thread_id):
+    def append_thread(self, stream_id, thread_name, thread_id, span_filter=None):
+        """
+        span_filter: optional name_filter and target_filter glob patterns and min_duration_ns,
+        applied by the server to the spans of the thread
+        """
         from protos.perfetto.trace import trace_pb2, trace_packet_pb2, track_event
 
         df_blocks = self.client.query_blocks(
@@ -61,7 +65,7 @@ class Writer:
 
         max_rows = 1024 * 1024
         df_spans = self.client.query_spans(
-            begin, end, limit=max_rows, stream_id=stream_id
+            begin, end, limit=max_rows, stream_id=stream_id, **(span_filter or {})
         )
         nb_rows = df_spans.shape[0]
         if nb_rows == max_rows:
@@ -123,7 +127,7 @@ def get_process_cpu_streams(client, process_id):
     return df_streams
 
 
-def write_process_trace(client, process_id, trace_filepath):
+def write_process_trace(client, process_id, trace_filepath, **span_filter):
     process_df = client.find_process(process_id)
     assert process_df.shape[0] == 1
     process = process_df.iloc[0]
@@ -131,5 +135,7 @@ def write_process_trace(client, process_id, trace_filepath):
     writer = Writer(client, process_id, process["exe"])
     for index, stream in tqdm(list(streams.iterrows())):
         stream_id = stream["thread_id"]
-        writer.append_thread(stream["stream_id"], stream["thread_name"], stream_id)
+        writer.append_thread(
+            stream["stream_id"], stream["thread_name"], stream_id, span_filter
+        )
     writer.write_file(trace_filepath)
@@ -1,5 +1,6 @@
 import cbor2
 import io
+import json
 import pyarrow.parquet as pq
 import requests
 
@@ -17,4 +18,41 @@ def request(url, args, headers={}):
             )
         )
     table = pq.read_table(io.BytesIO(response.content))
-    return table.to_pandas()
+    df = table.to_pandas()
+    # set by the server when a query timeout in partial mode cut the result short
+    metadata = table.schema.metadata or {}
+    df.attrs["truncated"] = metadata.get(b"truncated") == b"true"
+    return df
+
+
+def bytes_request(url, args, headers={}):
+    response = requests.post(
+        url,
+        headers=headers,
+        data=cbor2.dumps(args),
+    )
+    if response.status_code != 200:
+        raise Exception(
+            "http request url={2} failed with code={0} text={1}".format(
+                response.status_code, response.text, url
+            )
+        )
+    return response.content
+
+
+def streamed_request(url, args, headers={}):
+    response = requests.post(
+        url,
+        headers=headers,
+        data=cbor2.dumps(args),
+        stream=True,
+    )
+    if response.status_code != 200:
+        raise Exception(
+            "http request url={2} failed with code={0} text={1}".format(
+                response.status_code, response.text, url
+            )
+        )
+    for line in response.iter_lines():
+        if line:
+            yield json.loads(line)
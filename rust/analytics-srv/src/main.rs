@@ -5,19 +5,43 @@
 //! Env variables:
 //!  - `MICROMEGAS_SQL_CONNECTION_STRING` : postgresql server
 //!  - `MICROMEGAS_OBJECT_STORE_URI` : payloads, partitions
+//!  - `MICROMEGAS_VIEWS_CONFIG` : optional json file declaring views, see `view_config`
+//!  - `MICROMEGAS_API_KEYS_FILE` or `MICROMEGAS_API_KEYS` : optional keyring, see `api_key_auth`
+//!  - `MICROMEGAS_CONFIG_FILE` : optional json file of the other settings, see `micromegas::config`
+//!  - `MICROMEGAS_ZSTD_DICTIONARIES` : optional directory of the zstd dictionaries used by the clients, see `compression`
 
 use anyhow::{Context, Result};
+use axum::body::{Body, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
 use axum::response::Response;
 use axum::routing::post;
 use axum::{Extension, Router};
 use clap::Parser;
 use micromegas::analytics::analytics_service::AnalyticsService;
+use micromegas::analytics::query_log::QueryLogEntry;
+use micromegas::analytics::query_tags::{sanitize_query_tag, CLIENT_INFO_HEADER, QUERY_TAG_HEADER};
+use micromegas::analytics::query_timeout::{
+    QueryTimeout, QUERY_TIMEOUT_HEADER, QUERY_TIMEOUT_MODE_HEADER,
+};
+use micromegas::analytics::view_config::{load_views_config, ViewRegistry, ViewsConfig};
+use micromegas::config::ServerConfig;
 use micromegas::ingestion::data_lake_connection::DataLakeConnection;
+use micromegas::server_tls::{make_tls_acceptor, serve_tls, ServerTlsConfig};
+use micromegas::servers::api_key_auth::{ApiKeyAuthLayer, ApiKeyAuthProvider, AuthenticatedKey};
+use micromegas::servers::observability_layer::ObservabilityLayer;
+use micromegas::sqlx::types::chrono::Utc;
 use micromegas::telemetry::blob_storage::BlobStorage;
+use micromegas::telemetry::compression::load_zstd_dictionaries;
+use micromegas::telemetry_sink::system_monitor::spawn_system_monitor;
 use micromegas::telemetry_sink::TelemetryGuardBuilder;
 use micromegas::tracing::prelude::*;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower_http::compression::CompressionLayer;
 
 #[derive(Parser, Debug)]
 #[clap(name = "Analytics Server")]
@@ -25,6 +49,53 @@ use std::sync::Arc;
 struct Cli {
     #[clap(long, default_value = "127.0.0.1:8082")]
     listen_endpoint: SocketAddr,
+
+    /// time to live of the cached empty results, zero to disable the cache
+    #[clap(long, default_value_t = 10)]
+    negative_cache_ttl_seconds: u64,
+
+    /// maximum time to live of the empty results recomputed repeatedly
+    #[clap(long, default_value_t = 300)]
+    negative_cache_max_ttl_seconds: u64,
+
+    /// queries taking at least this long are kept in the slow query log
+    #[clap(long, default_value_t = 1000)]
+    slow_query_threshold_ms: u64,
+
+    /// number of queries kept in the slow query log, zero to disable it
+    #[clap(long, default_value_t = 1024)]
+    max_slow_queries: usize,
+
+    /// serves https with this pem certificate chain
+    #[clap(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// pem private key of the certificate
+    #[clap(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// requires clients to present a certificate signed by this pem certificate authority
+    #[clap(long, requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// json settings, overridden by the environment variables, see `micromegas::config`
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// compresses the responses with zstd or gzip for the clients accepting them,
+    /// worth it over slow links where the parquet compression of the views is not enough
+    #[clap(long)]
+    compress_responses: bool,
+}
+
+impl Cli {
+    fn tls_config(&self) -> Option<ServerTlsConfig> {
+        Some(ServerTlsConfig {
+            cert: self.tls_cert.clone()?,
+            key: self.tls_key.clone()?,
+            client_ca: self.tls_client_ca.clone(),
+        })
+    }
 }
 
 fn bytes_response(result: Result<bytes::Bytes>) -> Response {
@@ -40,6 +111,19 @@ fn bytes_response(result: Result<bytes::Bytes>) -> Response {
     }
 }
 
+fn parse_query_timeout(headers: &HeaderMap) -> Result<Option<QueryTimeout>> {
+    let Some(timeout) = headers.get(QUERY_TIMEOUT_HEADER) else {
+        return Ok(None);
+    };
+    let mode = headers
+        .get(QUERY_TIMEOUT_MODE_HEADER)
+        .map(|value| value.to_str())
+        .transpose()
+        .with_context(|| "reading query timeout mode")?;
+    let timeout = timeout.to_str().with_context(|| "reading query timeout")?;
+    Ok(Some(QueryTimeout::parse(timeout, mode)?))
+}
+
 async fn find_process_request(
     Extension(service): Extension<AnalyticsService>,
     body: bytes::Bytes,
@@ -94,12 +178,17 @@ async fn query_blocks_request(
 
 async fn query_spans_request(
     Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
     body: bytes::Bytes,
 ) -> Response {
     info!("query_spans_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
     bytes_response(
         service
-            .query_spans(body)
+            .query_spans(body, timeout)
             .await
             .with_context(|| "query_spans"),
     )
@@ -107,25 +196,53 @@ async fn query_spans_request(
 
 async fn query_thread_events_request(
     Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
     body: bytes::Bytes,
 ) -> Response {
     info!("query_thread_events_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
     bytes_response(
         service
-            .query_thread_events(body)
+            .query_thread_events(body, timeout)
             .await
             .with_context(|| "query_thread_events"),
     )
 }
 
+async fn query_span_events_request(
+    Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_span_events_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
+    bytes_response(
+        service
+            .query_span_events(body, timeout)
+            .await
+            .with_context(|| "query_span_events"),
+    )
+}
+
 async fn query_log_entries_request(
     Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
     body: bytes::Bytes,
 ) -> Response {
     info!("query_log_entries_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
     bytes_response(
         service
-            .query_log_entries(body)
+            .query_log_entries(body, timeout)
             .await
             .with_context(|| "query_log_entries"),
     )
@@ -133,23 +250,422 @@ async fn query_log_entries_request(
 
 async fn query_metrics_request(
     Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
     body: bytes::Bytes,
 ) -> Response {
     info!("query_metrics_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
     bytes_response(
         service
-            .query_metrics(body)
+            .query_metrics(body, timeout)
             .await
             .with_context(|| "query_metrics"),
     )
 }
 
+async fn query_property_histogram_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_property_histogram_request");
+    bytes_response(
+        service
+            .query_property_histogram(body)
+            .await
+            .with_context(|| "query_property_histogram"),
+    )
+}
+
+async fn align_measures_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("align_measures_request");
+    bytes_response(
+        service
+            .align_measures(body)
+            .await
+            .with_context(|| "align_measures"),
+    )
+}
+
+async fn create_sql_session_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("create_sql_session_request");
+    bytes_response(
+        service
+            .create_sql_session(body)
+            .await
+            .with_context(|| "create_sql_session"),
+    )
+}
+
+async fn execute_sql_request(
+    Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    info!("execute_sql_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
+    bytes_response(
+        service
+            .execute_sql(body, timeout)
+            .await
+            .with_context(|| "execute_sql"),
+    )
+}
+
+async fn export_sql_request(
+    Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    info!("export_sql_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
+    bytes_response(
+        service
+            .export_sql(body, timeout)
+            .await
+            .with_context(|| "export_sql"),
+    )
+}
+
+async fn close_sql_session_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("close_sql_session_request");
+    bytes_response(
+        service
+            .close_sql_session(body)
+            .await
+            .with_context(|| "close_sql_session"),
+    )
+}
+
+async fn create_annotation_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("create_annotation_request");
+    bytes_response(
+        service
+            .create_annotation(body)
+            .await
+            .with_context(|| "create_annotation"),
+    )
+}
+
+async fn update_annotation_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("update_annotation_request");
+    bytes_response(
+        service
+            .update_annotation(body)
+            .await
+            .with_context(|| "update_annotation"),
+    )
+}
+
+async fn delete_annotation_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("delete_annotation_request");
+    bytes_response(
+        service
+            .delete_annotation(body)
+            .await
+            .with_context(|| "delete_annotation"),
+    )
+}
+
+async fn query_annotations_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_annotations_request");
+    bytes_response(
+        service
+            .query_annotations(body)
+            .await
+            .with_context(|| "query_annotations"),
+    )
+}
+
+/// Name of the api key of the request, `None` when the server does not authenticate
+fn key_name(key: &Option<Extension<AuthenticatedKey>>) -> Option<&str> {
+    key.as_ref().map(|Extension(key)| key.0.as_str())
+}
+
+async fn create_screen_request(
+    Extension(service): Extension<AnalyticsService>,
+    key: Option<Extension<AuthenticatedKey>>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("create_screen_request");
+    bytes_response(
+        service
+            .create_screen(key_name(&key), body)
+            .await
+            .with_context(|| "create_screen"),
+    )
+}
+
+async fn update_screen_request(
+    Extension(service): Extension<AnalyticsService>,
+    key: Option<Extension<AuthenticatedKey>>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("update_screen_request");
+    bytes_response(
+        service
+            .update_screen(key_name(&key), body)
+            .await
+            .with_context(|| "update_screen"),
+    )
+}
+
+async fn delete_screen_request(
+    Extension(service): Extension<AnalyticsService>,
+    key: Option<Extension<AuthenticatedKey>>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("delete_screen_request");
+    bytes_response(
+        service
+            .delete_screen(key_name(&key), body)
+            .await
+            .with_context(|| "delete_screen"),
+    )
+}
+
+async fn find_screen_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("find_screen_request");
+    bytes_response(
+        service
+            .find_screen(body)
+            .await
+            .with_context(|| "find_screen"),
+    )
+}
+
+async fn list_screens_request(Extension(service): Extension<AnalyticsService>) -> Response {
+    info!("list_screens_request");
+    bytes_response(service.list_screens().await.with_context(|| "list_screens"))
+}
+
+async fn query_exit_rates_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_exit_rates_request");
+    bytes_response(
+        service
+            .query_exit_rates(body)
+            .await
+            .with_context(|| "query_exit_rates"),
+    )
+}
+
+async fn query_attachments_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_attachments_request");
+    bytes_response(
+        service
+            .query_attachments(body)
+            .await
+            .with_context(|| "query_attachments"),
+    )
+}
+
+async fn fetch_attachment_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("fetch_attachment_request");
+    bytes_response(
+        service
+            .fetch_attachment(body)
+            .await
+            .with_context(|| "fetch_attachment"),
+    )
+}
+
+async fn query_view_request(
+    Extension(service): Extension<AnalyticsService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    info!("query_view_request");
+    let timeout = match parse_query_timeout(&headers) {
+        Ok(timeout) => timeout,
+        Err(e) => return bytes_response(Err(e)),
+    };
+    bytes_response(
+        service
+            .query_view(body, timeout)
+            .await
+            .with_context(|| "query_view"),
+    )
+}
+
+async fn subscribe_blocks_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("subscribe_blocks_request");
+    match service
+        .subscribe_blocks(body)
+        .await
+        .with_context(|| "subscribe_blocks")
+    {
+        Ok(stream) => Response::builder()
+            .status(200)
+            .header("content-type", "application/x-ndjson")
+            .body(Body::from_stream(stream))
+            .unwrap(),
+        Err(e) => bytes_response(Err(e)),
+    }
+}
+
+async fn tail_log_entries_request(
+    Extension(service): Extension<AnalyticsService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("tail_log_entries_request");
+    match service
+        .tail_log_entries(body)
+        .await
+        .with_context(|| "tail_log_entries")
+    {
+        Ok(stream) => Response::builder()
+            .status(200)
+            .header("content-type", "application/x-ndjson")
+            .body(Body::from_stream(stream))
+            .unwrap(),
+        Err(e) => bytes_response(Err(e)),
+    }
+}
+
+async fn query_tag_loads_request(Extension(service): Extension<AnalyticsService>) -> Response {
+    info!("query_tag_loads_request");
+    bytes_response(
+        service
+            .query_tag_loads()
+            .await
+            .with_context(|| "query_tag_loads"),
+    )
+}
+
+async fn slow_queries_request(Extension(service): Extension<AnalyticsService>) -> Response {
+    info!("slow_queries_request");
+    bytes_response(service.slow_queries().await.with_context(|| "slow_queries"))
+}
+
+async fn query_heatmap_request(Extension(service): Extension<AnalyticsService>) -> Response {
+    info!("query_heatmap_request");
+    bytes_response(
+        service
+            .query_heatmap_cells()
+            .await
+            .with_context(|| "query_heatmap"),
+    )
+}
+
+/// Attributes each request to the query tag sent by the client and records it in the query log
+async fn record_query(
+    State(service): State<AnalyticsService>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let tag = sanitize_query_tag(
+        headers
+            .get(QUERY_TAG_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+    let client_info = headers
+        .get(CLIENT_INFO_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+    let route = request.uri().path().to_owned();
+    let begin_time = Utc::now();
+    let begin = Instant::now();
+    let in_flight = service.query_log().begin_query();
+    let concurrency = in_flight.concurrency();
+    let response = next.run(request).await;
+    drop(in_flight);
+    // streamed responses have no exact size
+    let response_bytes = response.body().size_hint().exact().unwrap_or_default();
+    let duration = begin.elapsed();
+    let success = response.status().is_success();
+    service.query_tag_stats().record(
+        &tag,
+        client_info.as_deref(),
+        &route,
+        success,
+        duration,
+        response_bytes,
+    );
+    service.query_log().record(QueryLogEntry {
+        begin: begin_time,
+        route,
+        query_tag: tag,
+        duration,
+        response_bytes,
+        success,
+        concurrency,
+    });
+    response
+}
+
 async fn serve_http(
     args: &Cli,
+    config: &ServerConfig,
     lake: DataLakeConnection,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let service = AnalyticsService::new(lake);
-    let app = Router::new()
+    let views_config = match &config.views_config {
+        Some(path) => load_views_config(path)?,
+        None => ViewsConfig::default(),
+    };
+    let views = ViewRegistry::from_config(&views_config)?;
+    if let Some(directory) = &config.zstd_dictionaries {
+        let nb_dictionaries = load_zstd_dictionaries(directory)?;
+        info!("loaded {nb_dictionaries} zstd dictionaries");
+    }
+    for view in views.views() {
+        info!("serving view {}", view.name);
+    }
+    let service = AnalyticsService::new(lake)
+        .with_views(views)
+        .with_negative_cache_ttl(
+            Duration::from_secs(args.negative_cache_ttl_seconds),
+            Duration::from_secs(args.negative_cache_max_ttl_seconds),
+        )
+        .with_slow_query_log(
+            Duration::from_millis(args.slow_query_threshold_ms),
+            args.max_slow_queries,
+        );
+    let mut app = Router::new()
         .route("/analytics/find_process", post(find_process_request))
         .route("/analytics/query_processes", post(query_processes_request))
         .route("/analytics/query_streams", post(query_streams_request))
@@ -164,12 +680,97 @@ async fn serve_http(
             "/analytics/query_thread_events",
             post(query_thread_events_request),
         )
+        .route(
+            "/analytics/query_span_events",
+            post(query_span_events_request),
+        )
+        .route(
+            "/analytics/query_property_histogram",
+            post(query_property_histogram_request),
+        )
+        .route("/analytics/query_view", post(query_view_request))
+        .route("/analytics/align_measures", post(align_measures_request))
+        .route(
+            "/analytics/create_sql_session",
+            post(create_sql_session_request),
+        )
+        .route("/analytics/execute_sql", post(execute_sql_request))
+        .route("/analytics/export_sql", post(export_sql_request))
+        .route(
+            "/analytics/close_sql_session",
+            post(close_sql_session_request),
+        )
+        .route(
+            "/analytics/create_annotation",
+            post(create_annotation_request),
+        )
+        .route(
+            "/analytics/update_annotation",
+            post(update_annotation_request),
+        )
+        .route(
+            "/analytics/delete_annotation",
+            post(delete_annotation_request),
+        )
+        .route(
+            "/analytics/query_annotations",
+            post(query_annotations_request),
+        )
+        .route("/analytics/create_screen", post(create_screen_request))
+        .route("/analytics/update_screen", post(update_screen_request))
+        .route("/analytics/delete_screen", post(delete_screen_request))
+        .route("/analytics/find_screen", post(find_screen_request))
+        .route("/analytics/list_screens", post(list_screens_request))
+        .route(
+            "/analytics/query_exit_rates",
+            post(query_exit_rates_request),
+        )
+        .route(
+            "/analytics/query_attachments",
+            post(query_attachments_request),
+        )
+        .route(
+            "/analytics/fetch_attachment",
+            post(fetch_attachment_request),
+        )
+        .route("/analytics/query_tag_loads", post(query_tag_loads_request))
+        .route("/analytics/slow_queries", post(slow_queries_request))
+        .route("/analytics/query_heatmap", post(query_heatmap_request));
+    if args.compress_responses {
+        // the encoding is negotiated with the accept-encoding header of each request
+        app = app.layer(CompressionLayer::new());
+    }
+    // streamed responses are not compressed, the encoder would hold back the lines
+    app = app
+        .route(
+            "/analytics/subscribe_blocks",
+            post(subscribe_blocks_request),
+        )
+        .route(
+            "/analytics/tail_log_entries",
+            post(tail_log_entries_request),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            service.clone(),
+            record_query,
+        ))
         .layer(Extension(service));
+    if let Some(provider) = ApiKeyAuthProvider::from_env()? {
+        let provider = Arc::new(provider);
+        provider.spawn_reload_tasks(Duration::from_secs(10));
+        app = app.layer(ApiKeyAuthLayer::new(provider));
+    }
+    let app = app.layer(ObservabilityLayer::default().with_per_route_spans(true));
     let listener = tokio::net::TcpListener::bind(args.listen_endpoint)
         .await
         .unwrap();
-    info!("serving on {}", &args.listen_endpoint);
-    axum::serve(listener, app).await.unwrap();
+    if let Some(tls_config) = args.tls_config() {
+        info!("serving https on {}", &args.listen_endpoint);
+        serve_tls(listener, make_tls_acceptor(&tls_config)?, app).await?;
+    } else {
+        info!("serving on {}", &args.listen_endpoint);
+        axum::serve(listener, app).await.unwrap();
+    }
 
     Ok(())
 }
@@ -195,12 +796,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_ctrlc_handling()
         .with_local_sink_max_level(LevelFilter::Debug)
         .build();
+    spawn_system_monitor();
     let args = Cli::parse();
-    let connection_string = std::env::var("MICROMEGAS_SQL_CONNECTION_STRING")
-        .with_context(|| "reading MICROMEGAS_SQL_CONNECTION_STRING")?;
-    let object_store_uri = std::env::var("MICROMEGAS_OBJECT_STORE_URI")
-        .with_context(|| "reading MICROMEGAS_OBJECT_STORE_URI")?;
-    let data_lake = connect_to_data_lake(&connection_string, &object_store_uri).await?;
-    serve_http(&args, data_lake).await?;
+    let config = ServerConfig::load(args.config.as_deref())?;
+    let data_lake =
+        connect_to_data_lake(&config.sql_connection_string, &config.object_store_uri).await?;
+    serve_http(&args, &config, data_lake).await?;
     Ok(())
 }
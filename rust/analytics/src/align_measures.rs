@@ -0,0 +1,148 @@
+//! Side by side comparison of a metric in two processes, i.e. two runs of the same benchmark
+//!
+//! The time of each measure is made relative to the start of its process,
+//! then the measures are averaged in buckets of the same size for both processes.
+use crate::{
+    measure::for_each_measure_in_block,
+    metadata::{find_process, find_stream, find_stream_blocks_in_range},
+    time::ConvertTicks,
+};
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{ArrayRef, Float64Array, Int64Array, UInt64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use micromegas_tracing::prelude::*;
+use sqlx::Row;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BucketStats {
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl BucketStats {
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+}
+
+/// Buckets indexed by the offset of their beginning relative to the process start, in nanoseconds
+pub type MeasureBuckets = BTreeMap<i64, BucketStats>;
+
+#[span_fn]
+pub async fn process_measure_buckets(
+    data_lake: &DataLakeConnection,
+    process_id: sqlx::types::Uuid,
+    metric: &str,
+    bucket_ns: i64,
+) -> Result<MeasureBuckets> {
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let process_info = find_process(&mut connection, &process_id)
+        .await
+        .with_context(|| "find_process")?;
+    let sql = "SELECT stream_id
+         FROM streams
+         WHERE process_id = $1
+         AND array_position(tags, 'metrics') is not NULL;";
+    let rows = instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(process_id)
+            .fetch_all(&mut *connection),
+    )
+    .await
+    .with_context(|| "select metrics streams")?;
+    let convert_ticks = ConvertTicks::new(&process_info);
+    let process_start_ns = process_info
+        .start_time
+        .timestamp_nanos_opt()
+        .unwrap_or_default();
+    let mut buckets = MeasureBuckets::new();
+    for row in rows {
+        let stream_id: sqlx::types::Uuid = row.try_get("stream_id")?;
+        let stream_info = find_stream(&mut connection, stream_id)
+            .await
+            .with_context(|| "find_stream")?;
+        let blocks = find_stream_blocks_in_range(&mut connection, stream_id, i64::MIN, i64::MAX)
+            .await
+            .with_context(|| "find_stream_blocks_in_range")?;
+        for block in &blocks {
+            for_each_measure_in_block(
+                data_lake.blob_storage.clone(),
+                &convert_ticks,
+                &stream_info,
+                block,
+                |measure| {
+                    if *measure.name == metric {
+                        let offset = measure.time - process_start_ns;
+                        let bucket = buckets
+                            .entry(offset.div_euclid(bucket_ns) * bucket_ns)
+                            .or_default();
+                        bucket.sum += measure.value;
+                        bucket.count += 1;
+                    }
+                    Ok(true)
+                },
+            )
+            .await
+            .with_context(|| "for_each_measure_in_block")?;
+        }
+    }
+    Ok(buckets)
+}
+
+/// One row per bucket present in either process, with the mean of each and their difference (b - a)
+pub fn align_buckets(a: &MeasureBuckets, b: &MeasureBuckets) -> Result<RecordBatch> {
+    let mut offsets = a.keys().chain(b.keys()).copied().collect::<Vec<i64>>();
+    offsets.sort_unstable();
+    offsets.dedup();
+    let empty = BucketStats::default();
+    let stats_a: Vec<&BucketStats> = offsets.iter().map(|o| a.get(o).unwrap_or(&empty)).collect();
+    let stats_b: Vec<&BucketStats> = offsets.iter().map(|o| b.get(o).unwrap_or(&empty)).collect();
+    let delta: Float64Array = stats_a
+        .iter()
+        .zip(&stats_b)
+        .map(|(a, b)| Some(b.mean()? - a.mean()?))
+        .collect();
+    let schema = Schema::new(vec![
+        Field::new("offset_ns", DataType::Int64, false),
+        Field::new("value_a", DataType::Float64, true),
+        Field::new("value_b", DataType::Float64, true),
+        Field::new("count_a", DataType::UInt64, false),
+        Field::new("count_b", DataType::UInt64, false),
+        Field::new("delta", DataType::Float64, true),
+    ]);
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(Int64Array::from(offsets)),
+        Arc::new(stats_a.iter().map(|s| s.mean()).collect::<Float64Array>()),
+        Arc::new(stats_b.iter().map(|s| s.mean()).collect::<Float64Array>()),
+        Arc::new(stats_a.iter().map(|s| s.count).collect::<UInt64Array>()),
+        Arc::new(stats_b.iter().map(|s| s.count).collect::<UInt64Array>()),
+        Arc::new(delta),
+    ];
+    RecordBatch::try_new(Arc::new(schema), columns).with_context(|| "building aligned measures")
+}
+
+pub async fn align_measures(
+    data_lake: &DataLakeConnection,
+    process_a: sqlx::types::Uuid,
+    process_b: sqlx::types::Uuid,
+    metric: &str,
+    bucket_ns: i64,
+) -> Result<RecordBatch> {
+    if bucket_ns <= 0 {
+        anyhow::bail!("bucket size should be positive");
+    }
+    let buckets_a = process_measure_buckets(data_lake, process_a, metric, bucket_ns)
+        .await
+        .with_context(|| "reading measures of process a")?;
+    let buckets_b = process_measure_buckets(data_lake, process_b, metric, bucket_ns)
+        .await
+        .with_context(|| "reading measures of process b")?;
+    align_buckets(&buckets_a, &buckets_b)
+}
@@ -1,20 +1,56 @@
 use anyhow::{Context, Result};
 use bytes::Buf;
 use bytes::BufMut;
-use datafusion::parquet::basic::Compression;
+use datafusion::arrow::array::{ArrayRef, StringArray};
 use datafusion::parquet::file::properties::WriterProperties;
-use datafusion::parquet::file::properties::WriterVersion;
 use datafusion::{arrow::record_batch::RecordBatch, parquet::arrow::ArrowWriter};
+use futures::Stream;
+use micromegas_ingestion::annotations::Annotation;
 use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::screens::{Screen, ScreenDefinition};
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use micromegas_tracing::prelude::*;
 use serde::Deserialize;
+use sqlx::types::chrono::Utc;
 use sqlx::types::chrono::{DateTime, FixedOffset};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
 use uuid::Uuid;
 
+use crate::block_subscription::{
+    subscribe_new_blocks, tail_log_entries, BlockFilter, NewBlocksBroadcast,
+};
+use crate::dfext::block_checksums::register_verify_block_checksums;
+use crate::dfext::block_payload_urls::register_block_payload_urls;
+use crate::dfext::sample_spans::register_sample_spans;
+use crate::dfext::stream_metadata::register_stream_metadata;
+use crate::negative_cache::{EmptyResultKey, NegativeCache};
+use crate::parquet_config::default_writer_properties;
+use crate::query_heatmap::QueryHeatmap;
+use crate::query_log::QueryLog;
+use crate::query_tags::QueryTagStats;
+use crate::query_timeout::{QueryDeadline, QueryTimeout};
+use crate::span_filter::SpanFilter;
 use crate::sql_arrow_bridge::rows_to_record_batch;
+use crate::sql_export::{export_sql, DEFAULT_ROWS_PER_FILE};
+use crate::sql_session::{execute_sql, register_result, with_query_range, QueryRange, SqlSessions};
+use crate::stream_query_options::StreamQueryOptions;
+use crate::time_ranges::{parse_time_ranges, query_time_ranges, TimeRangeArg};
+use crate::view_config::ViewRegistry;
+use crate::view_versions::{resolve_view_version, ViewVersion};
 
 #[derive(Debug, Clone)]
 pub struct AnalyticsService {
     data_lake: DataLakeConnection,
+    views: Arc<ViewRegistry>,
+    query_tag_stats: Arc<QueryTagStats>,
+    query_log: Arc<QueryLog>,
+    query_heatmap: Arc<QueryHeatmap>,
+    negative_cache: Arc<NegativeCache>,
+    sql_sessions: Arc<SqlSessions>,
+    new_blocks: Arc<NewBlocksBroadcast>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,17 +78,38 @@ pub struct QueryStreamsRequest {
 
 #[derive(Debug, Deserialize)]
 pub struct QueryBlocksRequest {
-    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
-    pub stream_id: Uuid,
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub stream_id: Option<Uuid>,
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub process_id: Option<Uuid>,
+    pub begin: Option<String>,
+    pub end: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct QuerySpansRequest {
+    /// spans read from the stream, before filtering
     pub limit: i64,
     pub begin: String,
     pub end: String,
     #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
     pub stream_id: Uuid,
+    #[serde(flatten)]
+    pub filter: SpanFilter,
+    /// registers the filtered spans in this sql session under the name `register_as`,
+    /// `spans` by default, and adds all of them to the span hierarchy of the session
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub session_id: Option<Uuid>,
+    pub register_as: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -64,13 +121,28 @@ pub struct QueryThreadEventsRequest {
     pub stream_id: Uuid,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct QuerySpanEventsRequest {
+    pub limit: i64,
+    pub begin: String,
+    pub end: String,
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub stream_id: Uuid,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct QueryLogEntriesRequest {
     pub limit: i64,
     pub begin: String,
     pub end: String,
+    /// optional disjoint ranges within [begin, end), see `time_ranges`
+    #[serde(default)]
+    pub time_ranges: Vec<TimeRangeArg>,
     #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
     pub stream_id: Uuid,
+    /// versioned name of the view the rows follow, i.e. `log_entries_v1`, the latest version by default
+    #[serde(default)]
+    pub view: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -78,13 +150,300 @@ pub struct QueryMetricsRequest {
     pub limit: i64,
     pub begin: String,
     pub end: String,
+    /// optional disjoint ranges within [begin, end), see `time_ranges`
+    #[serde(default)]
+    pub time_ranges: Vec<TimeRangeArg>,
     #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
     pub stream_id: Uuid,
+    /// versioned name of the view the rows follow, the latest version by default
+    #[serde(default)]
+    pub view: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryPropertyHistogramRequest {
+    pub view: String,
+    pub key: String,
+    pub limit: i64,
+    pub begin: String,
+    pub end: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryViewRequest {
+    pub view: String,
+    pub limit: i64,
+    pub begin: String,
+    pub end: String,
+    /// optional disjoint ranges within [begin, end), see `time_ranges`
+    #[serde(default)]
+    pub time_ranges: Vec<TimeRangeArg>,
+    /// registers the rows in this sql session under the name `register_as`
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub session_id: Option<Uuid>,
+    pub register_as: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteSqlRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub session_id: Uuid,
+    pub sql: String,
+    /// keeps the result available to the next queries of the session under that name
+    pub register_as: Option<String>,
+    /// optional rfc3339 range, visible to the statement as `@query_begin` and `@query_end`
+    #[serde(default)]
+    pub begin: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportSqlRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub session_id: Uuid,
+    pub sql: String,
+    /// defaults to `DEFAULT_ROWS_PER_FILE`
+    pub rows_per_file: Option<usize>,
+    /// optional rfc3339 range, visible to the statement as `@query_begin` and `@query_end`
+    #[serde(default)]
+    pub begin: Option<String>,
+    #[serde(default)]
+    pub end: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CloseSqlSessionRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub session_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlignMeasuresRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub process_a: Uuid,
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub process_b: Uuid,
+    pub metric: String,
+    pub bucket_seconds: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotationRequest {
+    /// absent when creating an annotation
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub annotation_id: Option<Uuid>,
+    pub label: String,
+    pub begin: String,
+    /// absent for instant markers
+    pub end: Option<String>,
+    #[serde(default)]
+    pub properties: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAnnotationRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub annotation_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryAnnotationsRequest {
+    pub begin: String,
+    pub end: String,
+    pub limit: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScreenRequest {
+    /// absent when creating a screen
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub screen_id: Option<Uuid>,
+    pub name: String,
+    pub definition: ScreenDefinition,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ScreenIdRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub screen_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryExitRatesRequest {
+    pub begin: String,
+    pub end: String,
+    pub limit: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryAttachmentsRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub process_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FetchAttachmentRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub attachment_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TailLogEntriesRequest {
+    #[serde(deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string")]
+    pub process_id: Uuid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscribeBlocksRequest {
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub process_id: Option<Uuid>,
+    #[serde(
+        default,
+        deserialize_with = "micromegas_transit::uuid_utils::opt_uuid_from_string"
+    )]
+    pub stream_id: Option<Uuid>,
+    #[serde(default)]
+    pub include_payload_path: bool,
 }
 
 impl AnalyticsService {
     pub fn new(data_lake: DataLakeConnection) -> Self {
-        Self { data_lake }
+        Self {
+            new_blocks: Arc::new(NewBlocksBroadcast::new(data_lake.db_pool.clone())),
+            data_lake,
+            views: Arc::new(ViewRegistry::default()),
+            query_tag_stats: Arc::new(QueryTagStats::default()),
+            query_log: Arc::new(QueryLog::default()),
+            query_heatmap: Arc::new(QueryHeatmap::default()),
+            negative_cache: Arc::new(NegativeCache::default()),
+            sql_sessions: Arc::new(SqlSessions::default()),
+        }
+    }
+
+    /// Sql sessions not used for that long are closed along with their views and registered results
+    #[must_use]
+    pub fn with_sql_session_idle_timeout(mut self, idle_timeout: std::time::Duration) -> Self {
+        self.sql_sessions = Arc::new(SqlSessions::new(idle_timeout));
+        self
+    }
+
+    /// Time to live of the empty results, doubled each time the same empty result is recomputed, zero to disable
+    #[must_use]
+    pub fn with_negative_cache_ttl(
+        mut self,
+        ttl: std::time::Duration,
+        max_ttl: std::time::Duration,
+    ) -> Self {
+        self.negative_cache = Arc::new(NegativeCache::new(ttl, max_ttl));
+        self
+    }
+
+    /// Queries taking at least `threshold` are kept in the slow query log, up to `max_slow_queries`
+    #[must_use]
+    pub fn with_slow_query_log(
+        mut self,
+        threshold: std::time::Duration,
+        max_slow_queries: usize,
+    ) -> Self {
+        self.query_log = Arc::new(QueryLog::new(threshold, max_slow_queries));
+        self
+    }
+
+    /// Serializes with the parquet settings configured for the view
+    fn serialize_view(&self, view_name: &str, record_batch: &RecordBatch) -> Result<bytes::Bytes> {
+        let (record_batch, props) = self.views.prepare_batch(view_name, record_batch)?;
+        serialize_record_batch_with_properties(&record_batch, props)
+    }
+
+    /// Version of the view requested by name, None when the view is not versioned
+    fn requested_view_version(
+        view: &str,
+        requested: Option<&str>,
+    ) -> Result<Option<&'static ViewVersion>> {
+        let version = resolve_view_version(view, requested.unwrap_or(view))?;
+        if let Some(ViewVersion {
+            name,
+            deprecated: Some(reason),
+            ..
+        }) = version
+        {
+            warn!("deprecated view {name} requested: {reason}");
+        }
+        Ok(version)
+    }
+
+    /// Skips the query if it was recently found to have no data, flags the result if it was cut short by the deadline
+    async fn query_stream_view<F>(
+        &self,
+        view: &'static str,
+        stream_id: Uuid,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        deadline: &QueryDeadline,
+        query: F,
+    ) -> Result<RecordBatch>
+    where
+        F: Future<Output = Result<RecordBatch>>,
+    {
+        let started = Instant::now();
+        let key = EmptyResultKey {
+            view,
+            stream_id,
+            begin,
+            end,
+        };
+        let result = match self.negative_cache.get(&key, started) {
+            Some(batch) => Ok(batch),
+            None => deadline.abort_at_deadline(query).await,
+        };
+        self.query_heatmap.record(
+            view,
+            &stream_id.to_string(),
+            begin,
+            end,
+            result.is_ok(),
+            started.elapsed(),
+        );
+        let batch = result?;
+        if !deadline.is_truncated() {
+            self.negative_cache.insert(key, &batch, Instant::now());
+        }
+        deadline.finish(batch)
+    }
+
+    /// Load accumulated by query tag, fed by the server handling the requests
+    pub fn query_tag_stats(&self) -> &QueryTagStats {
+        &self.query_tag_stats
+    }
+
+    /// Log of the queries, fed by the server handling the requests
+    pub fn query_log(&self) -> &QueryLog {
+        &self.query_log
+    }
+
+    /// Hits of the views per instance and day, fed by the queries of the views
+    pub fn query_heatmap(&self) -> &QueryHeatmap {
+        &self.query_heatmap
+    }
+
+    /// Serves the views of a registry, usually built from a configuration file
+    #[must_use]
+    pub fn with_views(mut self, views: ViewRegistry) -> Self {
+        self.views = Arc::new(views);
+        self
     }
 
     pub async fn find_process(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
@@ -92,8 +451,7 @@ impl AnalyticsService {
             ciborium::from_reader(body.reader()).with_context(|| "parsing FindProcessRequest")?;
 
         let mut connection = self.data_lake.db_pool.acquire().await?;
-        let rows = sqlx::query(
-            "SELECT process_id,
+        let sql = "SELECT process_id,
                     exe,
                     username,
                     realname,
@@ -107,10 +465,13 @@ impl AnalyticsService {
                     parent_process_id,
                     properties
              FROM processes
-             WHERE process_id = $1",
+             WHERE process_id = $1";
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(request.process_id)
+                .fetch_all(&mut *connection),
         )
-        .bind(request.process_id)
-        .fetch_all(&mut *connection)
         .await?;
         drop(connection);
         serialize_record_batch(
@@ -128,8 +489,7 @@ impl AnalyticsService {
             .with_context(|| "parsing end time range")?;
 
         let mut connection = self.data_lake.db_pool.acquire().await?;
-        let rows = sqlx::query(
-            "SELECT process_id,
+        let sql = "SELECT process_id,
                     exe,
                     username,
                     realname,
@@ -146,12 +506,15 @@ impl AnalyticsService {
              WHERE start_time >= $1
              AND start_time < $2
              ORDER BY start_time
-             LIMIT $3",
+             LIMIT $3";
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(begin)
+                .bind(end)
+                .bind(request.limit)
+                .fetch_all(&mut *connection),
         )
-        .bind(begin)
-        .bind(end)
-        .bind(request.limit)
-        .fetch_all(&mut *connection)
         .await?;
         drop(connection);
         serialize_record_batch(
@@ -228,7 +591,7 @@ impl AnalyticsService {
         }
         query = query.bind(request.limit);
         let mut connection = self.data_lake.db_pool.acquire().await?;
-        let rows = query.fetch_all(&mut *connection).await?;
+        let rows = instrument_query(&sql, query.fetch_all(&mut *connection)).await?;
         drop(connection);
         serialize_record_batch(
             &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
@@ -238,8 +601,47 @@ impl AnalyticsService {
     pub async fn query_blocks(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
         let request: QueryBlocksRequest =
             ciborium::from_reader(body.reader()).with_context(|| "parsing QueryBlocksRequest")?;
-        let mut connection = self.data_lake.db_pool.acquire().await?;
-        let sql = "SELECT block_id,
+        if request.stream_id.is_none() && request.process_id.is_none() {
+            anyhow::bail!("stream_id or process_id have to be provided");
+        }
+        let mut conditions = vec![];
+        if request.stream_id.is_some() {
+            conditions.push(format!(
+                "(stream_id = {})",
+                format_postgres_placeholder(conditions.len())
+            ));
+        }
+        if request.process_id.is_some() {
+            conditions.push(format!(
+                "(process_id = {})",
+                format_postgres_placeholder(conditions.len())
+            ));
+        }
+        let mut begin_time = None;
+        if let Some(time_str) = &request.begin {
+            begin_time = Some(
+                DateTime::<FixedOffset>::parse_from_rfc3339(time_str)
+                    .with_context(|| "parsing begin time range")?,
+            );
+            conditions.push(format!(
+                "(end_time >= {})",
+                format_postgres_placeholder(conditions.len())
+            ));
+        }
+        let mut end_time = None;
+        if let Some(time_str) = &request.end {
+            end_time = Some(
+                DateTime::<FixedOffset>::parse_from_rfc3339(time_str)
+                    .with_context(|| "parsing end time range")?,
+            );
+            conditions.push(format!(
+                "(begin_time < {})",
+                format_postgres_placeholder(conditions.len())
+            ));
+        }
+        let joined_conditions = conditions.join(" AND ");
+        let sql = format!(
+            "SELECT block_id,
                     stream_id,
                     process_id,
                     begin_time,
@@ -248,111 +650,764 @@ impl AnalyticsService {
                     end_ticks,
                     nb_objects,
                     object_offset,
-                    payload_size
+                    payload_size,
+                    insert_time,
+                    clock_skew_ms
              FROM blocks
-             WHERE stream_id = $1
-             ORDER BY begin_time;";
-        let rows = sqlx::query(sql)
-            .bind(request.stream_id)
-            .fetch_all(&mut *connection)
-            .await?;
+             WHERE {joined_conditions}
+             ORDER BY begin_time;"
+        );
+        let mut query = sqlx::query(&sql);
+        if request.stream_id.is_some() {
+            query = query.bind(request.stream_id);
+        }
+        if request.process_id.is_some() {
+            query = query.bind(request.process_id);
+        }
+        if let Some(begin) = begin_time {
+            query = query.bind(begin);
+        }
+        if let Some(end) = end_time {
+            query = query.bind(end);
+        }
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(&sql, query.fetch_all(&mut *connection)).await?;
         drop(connection);
         serialize_record_batch(
             &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
         )
     }
 
-    pub async fn query_spans(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+    pub async fn query_spans(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
         let request: QuerySpansRequest =
             ciborium::from_reader(body.reader()).with_context(|| "parsing QuerySpansRequest")?;
         let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
             .with_context(|| "parsing begin time range")?;
         let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
             .with_context(|| "parsing end time range")?;
-        serialize_record_batch(
-            &crate::query_spans::query_spans(
-                &self.data_lake,
-                request.limit,
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        let spans = self
+            .query_stream_view(
+                "spans",
                 request.stream_id,
                 begin.into(),
                 end.into(),
+                &deadline,
+                crate::query_spans::query_spans(
+                    &self.data_lake,
+                    request.limit,
+                    request.stream_id,
+                    begin.into(),
+                    end.into(),
+                    &deadline,
+                ),
             )
             .await
-            .with_context(|| "query_spans")?,
-        )
+            .with_context(|| "query_spans")?;
+        let filtered = request.filter.filter(&spans)?;
+        if let Some(session_id) = &request.session_id {
+            let now = Instant::now();
+            self.sql_sessions
+                .add_spans(session_id, &request.stream_id.to_string(), &spans, now)?;
+            let name = request.register_as.as_deref().unwrap_or("spans");
+            register_result(
+                &self.sql_sessions.get(session_id, now)?,
+                name,
+                filtered.clone(),
+            )?;
+        }
+        self.serialize_view("spans", &filtered)
     }
 
-    pub async fn query_thread_events(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+    pub async fn query_thread_events(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
         let request: QueryThreadEventsRequest = ciborium::from_reader(body.reader())
             .with_context(|| "parsing QueryThreadEventsRequest")?;
         let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
             .with_context(|| "parsing begin time range")?;
         let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
             .with_context(|| "parsing end time range")?;
-        serialize_record_batch(
-            &crate::query_thread_events::query_thread_events(
-                &self.data_lake,
-                request.limit,
-                request.stream_id,
-                begin.into(),
-                end.into(),
-            )
-            .await
-            .with_context(|| "query_thread_events")?,
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        self.serialize_view(
+            "thread_events",
+            &self
+                .query_stream_view(
+                    "thread_events",
+                    request.stream_id,
+                    begin.into(),
+                    end.into(),
+                    &deadline,
+                    crate::query_thread_events::query_thread_events(
+                        &self.data_lake,
+                        request.limit,
+                        request.stream_id,
+                        begin.into(),
+                        end.into(),
+                        &deadline,
+                    ),
+                )
+                .await
+                .with_context(|| "query_thread_events")?,
+        )
+    }
+
+    /// Markers recorded with `span_event!`, with the id of their span
+    pub async fn query_span_events(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
+        let request: QuerySpanEventsRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing QuerySpanEventsRequest")?;
+        let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
+            .with_context(|| "parsing begin time range")?;
+        let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
+            .with_context(|| "parsing end time range")?;
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        self.serialize_view(
+            "span_events",
+            &self
+                .query_stream_view(
+                    "span_events",
+                    request.stream_id,
+                    begin.into(),
+                    end.into(),
+                    &deadline,
+                    crate::query_span_events::query_span_events(
+                        &self.data_lake,
+                        request.limit,
+                        request.stream_id,
+                        begin.into(),
+                        end.into(),
+                        &deadline,
+                    ),
+                )
+                .await
+                .with_context(|| "query_span_events")?,
         )
     }
 
-    pub async fn query_log_entries(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+    pub async fn query_log_entries(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
         let request: QueryLogEntriesRequest = ciborium::from_reader(body.reader())
             .with_context(|| "parsing QueryLogEntriesRequest")?;
+        let version = Self::requested_view_version("log_entries", request.view.as_deref())?;
+        let (bounds, ranges) =
+            parse_time_ranges(&request.begin, &request.end, &request.time_ranges)?;
+        let deadline = &QueryDeadline::new(timeout.as_ref());
+        let options = &StreamQueryOptions {
+            promoted_properties: self.views.promoted_properties(),
+            with_ticks: self.views.tick_columns(),
+            deadline,
+        };
+        let stream_id = request.stream_id;
+        let batch = query_time_ranges(bounds, &ranges, request.limit, move |range, limit| {
+            self.query_stream_view(
+                "log_entries",
+                stream_id,
+                range.begin,
+                range.end,
+                deadline,
+                crate::query_log_entries::query_log_entries(
+                    &self.data_lake,
+                    stream_id,
+                    range.begin,
+                    range.end,
+                    limit,
+                    options,
+                ),
+            )
+        })
+        .await
+        .with_context(|| "query_log_entries")?;
+        let batch = match version {
+            Some(version) => version.translate(&batch)?,
+            None => batch,
+        };
+        self.serialize_view("log_entries", &batch)
+    }
+
+    pub async fn query_metrics(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
+        let request: QueryMetricsRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing QueryMetricsRequest")?;
+        let version = Self::requested_view_version("measures", request.view.as_deref())?;
+        let (bounds, ranges) =
+            parse_time_ranges(&request.begin, &request.end, &request.time_ranges)?;
+        let deadline = &QueryDeadline::new(timeout.as_ref());
+        let options = &StreamQueryOptions {
+            promoted_properties: self.views.promoted_properties(),
+            with_ticks: self.views.tick_columns(),
+            deadline,
+        };
+        let stream_id = request.stream_id;
+        let batch = query_time_ranges(bounds, &ranges, request.limit, move |range, limit| {
+            self.query_stream_view(
+                "measures",
+                stream_id,
+                range.begin,
+                range.end,
+                deadline,
+                crate::query_metrics::query_metrics(
+                    &self.data_lake,
+                    limit,
+                    stream_id,
+                    range.begin,
+                    range.end,
+                    options,
+                ),
+            )
+        })
+        .await
+        .with_context(|| "query_metrics")?;
+        let batch = match version {
+            Some(version) => version.translate(&batch)?,
+            None => batch,
+        };
+        self.serialize_view("measures", &batch)
+    }
+
+    pub async fn subscribe_blocks(
+        &self,
+        body: bytes::Bytes,
+    ) -> Result<impl Stream<Item = Result<bytes::Bytes>>> {
+        let request: SubscribeBlocksRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing SubscribeBlocksRequest")?;
+        subscribe_new_blocks(
+            &self.new_blocks,
+            BlockFilter {
+                process_id: request.process_id,
+                stream_id: request.stream_id,
+                include_payload_path: request.include_payload_path,
+            },
+        )
+        .await
+        .with_context(|| "subscribe_new_blocks")
+    }
+
+    pub async fn tail_log_entries(
+        &self,
+        body: bytes::Bytes,
+    ) -> Result<impl Stream<Item = Result<bytes::Bytes>>> {
+        let request: TailLogEntriesRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing TailLogEntriesRequest")?;
+        tail_log_entries(self.data_lake.clone(), &self.new_blocks, request.process_id)
+            .await
+            .with_context(|| "tail_log_entries")
+    }
+
+    pub async fn query_property_histogram(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: QueryPropertyHistogramRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing QueryPropertyHistogramRequest")?;
         let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
             .with_context(|| "parsing begin time range")?;
         let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
             .with_context(|| "parsing end time range")?;
         serialize_record_batch(
-            &crate::query_log_entries::query_log_entries(
+            &crate::property_histogram::query_property_histogram(
                 &self.data_lake,
-                request.stream_id,
+                self.views.find_view(&request.view)?,
+                &request.key,
                 begin.into(),
                 end.into(),
                 request.limit,
             )
             .await
-            .with_context(|| "query_log_entries")?,
+            .with_context(|| "query_property_histogram")?,
         )
     }
 
-    pub async fn query_metrics(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
-        let request: QueryMetricsRequest =
-            ciborium::from_reader(body.reader()).with_context(|| "parsing QueryMetricsRequest")?;
+    /// The rows of a view can't be truncated, the query fails at the deadline in both timeout modes
+    pub async fn query_view(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
+        let request: QueryViewRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing QueryViewRequest")?;
+        let (_bounds, ranges) =
+            parse_time_ranges(&request.begin, &request.end, &request.time_ranges)?;
+        let started = Instant::now();
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        let result = deadline
+            .bound(crate::query_view::query_view(
+                &self.data_lake,
+                self.views.find_view(&request.view)?,
+                &ranges,
+                request.limit,
+            ))
+            .await
+            .and_then(|batch| batch.context("query timed out"));
+        for range in &ranges {
+            self.query_heatmap.record(
+                &request.view,
+                "",
+                range.begin,
+                range.end,
+                result.is_ok(),
+                started.elapsed(),
+            );
+        }
+        let batch = result.with_context(|| "query_view")?;
+        if let Some(session_id) = &request.session_id {
+            let name = request.register_as.as_deref().unwrap_or(&request.view);
+            let ctx = self.sql_sessions.get(session_id, Instant::now())?;
+            register_result(&ctx, name, batch.clone())?;
+        }
+        self.serialize_view(&request.view, &batch)
+    }
+
+    /// Returns the id of a new sql session in a `session_id` column
+    pub async fn create_sql_session(&self, _body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let now = Instant::now();
+        let session_id = self.sql_sessions.create(now)?;
+        let ctx = self.sql_sessions.get(&session_id, now)?;
+        register_block_payload_urls(&ctx, self.data_lake.clone());
+        register_stream_metadata(&ctx, self.data_lake.clone());
+        register_verify_block_checksums(&ctx, self.data_lake.clone());
+        register_sample_spans(&ctx, self.data_lake.clone());
+        serialize_record_batch(&session_id_record_batch(&session_id)?)
+    }
+
+    /// Runs a statement in a sql session, where views and registered results of previous statements are visible.
+    /// The statement fails at the deadline in both timeout modes
+    pub async fn execute_sql(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
+        let request: ExecuteSqlRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ExecuteSqlRequest")?;
+        let range = QueryRange::parse(request.begin.as_deref(), request.end.as_deref())?;
+        let ctx = with_query_range(
+            &self.sql_sessions.get(&request.session_id, Instant::now())?,
+            range,
+        );
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        serialize_record_batch(
+            &deadline
+                .bound(execute_sql(
+                    &ctx,
+                    &request.sql,
+                    request.register_as.as_deref(),
+                ))
+                .await
+                .with_context(|| "execute_sql")?
+                .context("query timed out")?,
+        )
+    }
+
+    /// Writes the result of a statement of a sql session to parquet files in the object store,
+    /// returns the manifest of the files
+    pub async fn export_sql(
+        &self,
+        body: bytes::Bytes,
+        timeout: Option<QueryTimeout>,
+    ) -> Result<bytes::Bytes> {
+        let request: ExportSqlRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ExportSqlRequest")?;
+        let range = QueryRange::parse(request.begin.as_deref(), request.end.as_deref())?;
+        let ctx = with_query_range(
+            &self.sql_sessions.get(&request.session_id, Instant::now())?,
+            range,
+        );
+        let deadline = QueryDeadline::new(timeout.as_ref());
+        serialize_record_batch(
+            &deadline
+                .bound(export_sql(
+                    &ctx,
+                    &request.sql,
+                    &self.data_lake.blob_storage,
+                    request.rows_per_file.unwrap_or(DEFAULT_ROWS_PER_FILE),
+                ))
+                .await
+                .with_context(|| "export_sql")?
+                .context("query timed out")?,
+        )
+    }
+
+    pub async fn close_sql_session(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: CloseSqlSessionRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing CloseSqlSessionRequest")?;
+        if !self.sql_sessions.close(&request.session_id) {
+            anyhow::bail!("sql session {} not found", request.session_id);
+        }
+        serialize_record_batch(&session_id_record_batch(&request.session_id)?)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub async fn align_measures(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: AlignMeasuresRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing AlignMeasuresRequest")?;
+        serialize_record_batch(
+            &crate::align_measures::align_measures(
+                &self.data_lake,
+                request.process_a,
+                request.process_b,
+                &request.metric,
+                (request.bucket_seconds * 1_000_000_000.0).round() as i64,
+            )
+            .await
+            .with_context(|| "align_measures")?,
+        )
+    }
+
+    async fn annotation_record_batch(&self, annotation_id: Uuid) -> Result<RecordBatch> {
+        let sql = "SELECT annotation_id, label, begin_time, end_time, properties, insert_time
+             FROM annotations
+             WHERE annotation_id = $1;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(annotation_id)
+                .fetch_all(&mut *connection),
+        )
+        .await?;
+        if rows.is_empty() {
+            anyhow::bail!("annotation {annotation_id} not found");
+        }
+        rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")
+    }
+
+    fn parse_annotation(request: AnnotationRequest) -> Result<Annotation> {
+        let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
+            .with_context(|| "parsing annotation begin")?;
+        let end = request
+            .end
+            .map(|end| DateTime::<FixedOffset>::parse_from_rfc3339(&end))
+            .transpose()
+            .with_context(|| "parsing annotation end")?;
+        let mut annotation = Annotation::new(
+            request.label,
+            begin.into(),
+            end.map(Into::into),
+            request.properties,
+        )?;
+        if let Some(annotation_id) = request.annotation_id {
+            annotation.annotation_id = annotation_id;
+        }
+        Ok(annotation)
+    }
+
+    pub async fn create_annotation(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: AnnotationRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing AnnotationRequest")?;
+        if request.annotation_id.is_some() {
+            anyhow::bail!("annotation_id is assigned by the server");
+        }
+        let annotation = Self::parse_annotation(request)?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::annotations::insert_annotation(&mut connection, &annotation).await?;
+        drop(connection);
+        serialize_record_batch(
+            &self
+                .annotation_record_batch(annotation.annotation_id)
+                .await?,
+        )
+    }
+
+    pub async fn update_annotation(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: AnnotationRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing AnnotationRequest")?;
+        if request.annotation_id.is_none() {
+            anyhow::bail!("annotation_id has to be provided");
+        }
+        let annotation = Self::parse_annotation(request)?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::annotations::update_annotation(&mut connection, &annotation).await?;
+        drop(connection);
+        serialize_record_batch(
+            &self
+                .annotation_record_batch(annotation.annotation_id)
+                .await?,
+        )
+    }
+
+    /// Returns the deleted annotation
+    pub async fn delete_annotation(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: DeleteAnnotationRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing DeleteAnnotationRequest")?;
+        let deleted = self.annotation_record_batch(request.annotation_id).await?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::annotations::delete_annotation(
+            &mut connection,
+            request.annotation_id,
+        )
+        .await?;
+        serialize_record_batch(&deleted)
+    }
+
+    /// Annotations overlapping the time range
+    pub async fn query_annotations(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: QueryAnnotationsRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing QueryAnnotationsRequest")?;
         let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
             .with_context(|| "parsing begin time range")?;
         let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
             .with_context(|| "parsing end time range")?;
+        let sql = "SELECT annotation_id, label, begin_time, end_time, properties, insert_time
+             FROM annotations
+             WHERE end_time >= $1
+             AND begin_time < $2
+             ORDER BY begin_time
+             LIMIT $3;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(begin)
+                .bind(end)
+                .bind(request.limit)
+                .fetch_all(&mut *connection),
+        )
+        .await?;
+        drop(connection);
         serialize_record_batch(
-            &crate::query_metrics::query_metrics(
-                &self.data_lake,
-                request.limit,
-                request.stream_id,
-                begin.into(),
-                end.into(),
-            )
-            .await
-            .with_context(|| "query_log_entries")?,
+            &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
+        )
+    }
+
+    async fn screen_record_batch(&self, screen_id: Uuid) -> Result<RecordBatch> {
+        let sql = "SELECT screen_id, name, COALESCE(owner, '') AS owner, definition, insert_time, update_time
+             FROM screens
+             WHERE screen_id = $1;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql).bind(screen_id).fetch_all(&mut *connection),
+        )
+        .await?;
+        if rows.is_empty() {
+            anyhow::bail!("screen {screen_id} not found");
+        }
+        rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")
+    }
+
+    /// Fails if the screen belongs to another api key, see `Screen::can_be_modified_by`
+    async fn find_modifiable_screen(
+        &self,
+        screen_id: Uuid,
+        key_name: Option<&str>,
+    ) -> Result<Screen> {
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let screen = micromegas_ingestion::screens::find_screen(&mut connection, screen_id).await?;
+        if !screen.can_be_modified_by(key_name) {
+            anyhow::bail!("screen {screen_id} belongs to another api key");
+        }
+        Ok(screen)
+    }
+
+    /// `key_name` is the name of the api key of the request, it becomes the owner of the screen
+    pub async fn create_screen(
+        &self,
+        key_name: Option<&str>,
+        body: bytes::Bytes,
+    ) -> Result<bytes::Bytes> {
+        let request: ScreenRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ScreenRequest")?;
+        if request.screen_id.is_some() {
+            anyhow::bail!("screen_id is assigned by the server");
+        }
+        let screen = Screen::new(
+            request.name,
+            key_name.map(str::to_owned),
+            request.definition,
+        )?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::screens::insert_screen(&mut connection, &screen).await?;
+        drop(connection);
+        serialize_record_batch(&self.screen_record_batch(screen.screen_id).await?)
+    }
+
+    pub async fn update_screen(
+        &self,
+        key_name: Option<&str>,
+        body: bytes::Bytes,
+    ) -> Result<bytes::Bytes> {
+        let request: ScreenRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ScreenRequest")?;
+        let screen_id = request
+            .screen_id
+            .with_context(|| "screen_id has to be provided")?;
+        let mut screen = self.find_modifiable_screen(screen_id, key_name).await?;
+        screen.name = request.name;
+        screen.definition = request.definition;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::screens::update_screen(&mut connection, &screen).await?;
+        drop(connection);
+        serialize_record_batch(&self.screen_record_batch(screen_id).await?)
+    }
+
+    /// Returns the deleted screen
+    pub async fn delete_screen(
+        &self,
+        key_name: Option<&str>,
+        body: bytes::Bytes,
+    ) -> Result<bytes::Bytes> {
+        let request: ScreenIdRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ScreenIdRequest")?;
+        self.find_modifiable_screen(request.screen_id, key_name)
+            .await?;
+        let deleted = self.screen_record_batch(request.screen_id).await?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        micromegas_ingestion::screens::delete_screen(&mut connection, request.screen_id).await?;
+        serialize_record_batch(&deleted)
+    }
+
+    /// The definition is in the json column `definition`
+    pub async fn find_screen(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: ScreenIdRequest =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ScreenIdRequest")?;
+        serialize_record_batch(&self.screen_record_batch(request.screen_id).await?)
+    }
+
+    /// Screens ordered by name, without their definitions.
+    /// The owner of the screens created without authentication is empty.
+    pub async fn list_screens(&self) -> Result<bytes::Bytes> {
+        let sql = "SELECT screen_id, name, COALESCE(owner, '') AS owner, insert_time, update_time
+             FROM screens
+             ORDER BY name;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(sql, sqlx::query(sql).fetch_all(&mut *connection)).await?;
+        drop(connection);
+        serialize_record_batch(
+            &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
+        )
+    }
+
+    /// Abnormal exits per executable, for the processes started in the time range
+    ///
+    /// An exit is abnormal if the process panicked or reported a non-zero exit code.
+    /// Processes that were killed or crashed without unwinding never report their exit:
+    /// they are counted in `nb_processes` but not in `nb_exits`.
+    pub async fn query_exit_rates(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: QueryExitRatesRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing QueryExitRatesRequest")?;
+        let begin = DateTime::<FixedOffset>::parse_from_rfc3339(&request.begin)
+            .with_context(|| "parsing begin time range")?;
+        let end = DateTime::<FixedOffset>::parse_from_rfc3339(&request.end)
+            .with_context(|| "parsing end time range")?;
+        let sql = "WITH exits AS (
+               SELECT exe,
+                      (SELECT value FROM unnest(properties) WHERE key = 'exit_time') AS exit_time,
+                      (SELECT value FROM unnest(properties) WHERE key = 'exit_code') AS exit_code,
+                      (SELECT value FROM unnest(properties) WHERE key = 'panicked') AS panicked
+               FROM processes
+               WHERE start_time >= $1
+               AND start_time < $2
+             ), counts AS (
+               SELECT exe,
+                      COUNT(*) AS nb_processes,
+                      COUNT(exit_time) AS nb_exits,
+                      COUNT(*) FILTER (WHERE panicked = 'true') AS nb_panics,
+                      COUNT(*) FILTER (WHERE exit_code <> '0') AS nb_error_exit_codes,
+                      COUNT(*) FILTER (WHERE panicked = 'true' OR exit_code <> '0') AS nb_abnormal_exits
+               FROM exits
+               GROUP BY exe
+             )
+             SELECT exe, nb_processes, nb_exits, nb_panics, nb_error_exit_codes, nb_abnormal_exits,
+                    nb_abnormal_exits::FLOAT8 / nb_processes AS abnormal_exit_rate
+             FROM counts
+             ORDER BY abnormal_exit_rate DESC, nb_processes DESC
+             LIMIT $3;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(begin)
+                .bind(end)
+                .bind(request.limit)
+                .fetch_all(&mut *connection),
+        )
+        .await?;
+        drop(connection);
+        serialize_record_batch(
+            &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
         )
     }
+
+    /// Attachments of a process, without their content
+    pub async fn query_attachments(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: QueryAttachmentsRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing QueryAttachmentsRequest")?;
+        let sql = "SELECT attachment_id, process_id, name, content_type, size, insert_time
+             FROM attachments
+             WHERE process_id = $1
+             ORDER BY insert_time;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(request.process_id)
+                .fetch_all(&mut *connection),
+        )
+        .await?;
+        drop(connection);
+        serialize_record_batch(
+            &rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")?,
+        )
+    }
+
+    /// Raw content of the attachment, as it was sent
+    pub async fn fetch_attachment(&self, body: bytes::Bytes) -> Result<bytes::Bytes> {
+        let request: FetchAttachmentRequest = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing FetchAttachmentRequest")?;
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let attachment = micromegas_ingestion::attachments::find_attachment(
+            &mut connection,
+            request.attachment_id,
+        )
+        .await?;
+        drop(connection);
+        micromegas_ingestion::attachments::read_attachment(&self.data_lake, &attachment).await
+    }
+
+    pub async fn query_tag_loads(&self) -> Result<bytes::Bytes> {
+        serialize_record_batch(&self.query_tag_stats.to_record_batch()?)
+    }
+
+    pub async fn slow_queries(&self) -> Result<bytes::Bytes> {
+        serialize_record_batch(&self.query_log.slow_queries_record_batch()?)
+    }
+
+    pub async fn query_heatmap_cells(&self) -> Result<bytes::Bytes> {
+        serialize_record_batch(&self.query_heatmap.to_record_batch()?)
+    }
 }
 
 fn format_postgres_placeholder(index: usize) -> String {
     format!("${}", index + 1)
 }
 
+fn session_id_record_batch(session_id: &Uuid) -> Result<RecordBatch> {
+    let session_ids: ArrayRef = Arc::new(StringArray::from(vec![session_id.to_string()]));
+    RecordBatch::try_from_iter(vec![("session_id", session_ids)])
+        .with_context(|| "building session record batch")
+}
+
 fn serialize_record_batch(record_batch: &RecordBatch) -> Result<bytes::Bytes> {
+    serialize_record_batch_with_properties(record_batch, default_writer_properties())
+}
+
+fn serialize_record_batch_with_properties(
+    record_batch: &RecordBatch,
+    props: WriterProperties,
+) -> Result<bytes::Bytes> {
     let mut buffer_writer = bytes::BytesMut::with_capacity(1024).writer();
-    let props = WriterProperties::builder()
-        .set_writer_version(WriterVersion::PARQUET_2_0)
-        .set_compression(Compression::LZ4_RAW)
-        .build();
     let mut arrow_writer =
         ArrowWriter::try_new(&mut buffer_writer, record_batch.schema(), Some(props))?;
     arrow_writer.write(record_batch)?;
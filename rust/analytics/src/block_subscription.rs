@@ -0,0 +1,264 @@
+use crate::log_entry::log_entry_from_value;
+use crate::metadata::{find_process, find_stream};
+use crate::time::ConvertTicks;
+use crate::{fetch_block_payload, parse_block};
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use futures::{Stream, StreamExt};
+use micromegas_ingestion::block_notifications::{NewBlockNotification, NEW_BLOCKS_CHANNEL};
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::stream_tags::StreamTag;
+use serde::Serialize;
+use sqlx::postgres::PgListener;
+use sqlx::types::chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+/// Which new blocks a subscriber is interested in
+#[derive(Debug, Clone, Default)]
+pub struct BlockFilter {
+    pub process_id: Option<Uuid>,
+    pub stream_id: Option<Uuid>,
+    pub include_payload_path: bool,
+}
+
+impl BlockFilter {
+    fn matches(&self, notification: &NewBlockNotification) -> bool {
+        (self.process_id.is_none() || self.process_id == Some(notification.process_id))
+            && (self.stream_id.is_none() || self.stream_id == Some(notification.stream_id))
+    }
+}
+
+/// Fans out the new block notifications received on a single database connection
+///
+/// The listener is started by the first subscriber and stopped when a notification finds
+/// no subscriber left: the subscribers do not hold connections of the pool.
+#[derive(Debug)]
+pub struct NewBlocksBroadcast {
+    pool: sqlx::PgPool,
+    sender: Arc<tokio::sync::Mutex<Option<broadcast::Sender<NewBlockNotification>>>>,
+}
+
+/// Notifications buffered for each subscriber, a slower subscriber misses the older ones
+const NEW_BLOCKS_CAPACITY: usize = 1024;
+
+impl NewBlocksBroadcast {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self {
+            pool,
+            sender: Arc::new(tokio::sync::Mutex::new(None)),
+        }
+    }
+
+    async fn subscribe(&self) -> Result<broadcast::Receiver<NewBlockNotification>> {
+        let mut sender = self.sender.lock().await;
+        if let Some(sender) = sender.as_ref() {
+            return Ok(sender.subscribe());
+        }
+        let mut listener = PgListener::connect_with(&self.pool)
+            .await
+            .with_context(|| "connecting listener")?;
+        listener
+            .listen(NEW_BLOCKS_CHANNEL)
+            .await
+            .with_context(|| "listening to new blocks")?;
+        let (new_sender, receiver) = broadcast::channel(NEW_BLOCKS_CAPACITY);
+        *sender = Some(new_sender);
+        tokio::spawn(forward_new_blocks(listener, self.sender.clone()));
+        Ok(receiver)
+    }
+}
+
+async fn forward_new_blocks(
+    mut listener: PgListener,
+    sender: Arc<tokio::sync::Mutex<Option<broadcast::Sender<NewBlockNotification>>>>,
+) {
+    loop {
+        let message = listener.recv().await;
+        let mut sender = sender.lock().await;
+        let notification = match message {
+            Ok(notification) => notification,
+            Err(e) => {
+                // dropping the sender ends the streams of the subscribers
+                error!("error receiving block notification: {e:?}");
+                *sender = None;
+                return;
+            }
+        };
+        let Some(block_sender) = sender.as_ref() else {
+            return;
+        };
+        if block_sender.receiver_count() == 0 {
+            *sender = None;
+            return;
+        }
+        match serde_json::from_str(notification.payload()) {
+            Ok(block) => {
+                let _ = block_sender.send(block);
+            }
+            Err(e) => warn!("ignoring malformed block notification: {e}"),
+        }
+    }
+}
+
+async fn listen_new_blocks(
+    new_blocks: &NewBlocksBroadcast,
+    filter: BlockFilter,
+) -> Result<impl Stream<Item = Result<NewBlockNotification>>> {
+    let receiver = new_blocks.subscribe().await?;
+    Ok(futures::stream::unfold(receiver, move |mut receiver| {
+        let filter = filter.clone();
+        async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(block) if filter.matches(&block) => return Some((Ok(block), receiver)),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(nb_missed)) => {
+                        return Some((
+                            Err(anyhow::anyhow!(
+                                "subscriber too slow, missed {nb_missed} block notifications"
+                            )),
+                            receiver,
+                        ))
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        }
+    }))
+}
+
+fn json_line<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Streams the blocks committed after the subscription, one json object per line
+pub async fn subscribe_new_blocks(
+    new_blocks: &NewBlocksBroadcast,
+    filter: BlockFilter,
+) -> Result<impl Stream<Item = Result<bytes::Bytes>>> {
+    let include_payload_path = filter.include_payload_path;
+    Ok(listen_new_blocks(new_blocks, filter)
+        .await?
+        .map(move |block| {
+            let mut block = block?;
+            if include_payload_path {
+                block.payload_path = Some(format!(
+                    "blobs/{}/{}/{}",
+                    block.process_id, block.stream_id, block.block_id
+                ));
+            }
+            json_line(&block)
+                .map(bytes::Bytes::from)
+                .with_context(|| "encoding block notification")
+        }))
+}
+
+#[derive(Debug, Serialize)]
+struct TailedLogEntry<'a> {
+    /// RFC 3339
+    time: String,
+    level: i32,
+    target: &'a str,
+    msg: &'a str,
+}
+
+/// Log streams of the process, `None` for its other streams
+type LogStreamCache = Arc<Mutex<HashMap<Uuid, Option<Arc<StreamInfo>>>>>;
+
+async fn find_log_stream(
+    data_lake: &DataLakeConnection,
+    cache: &LogStreamCache,
+    stream_id: Uuid,
+) -> Result<Option<Arc<StreamInfo>>> {
+    if let Some(stream) = cache.lock().unwrap().get(&stream_id) {
+        return Ok(stream.clone());
+    }
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let stream = find_stream(&mut connection, stream_id)
+        .await
+        .with_context(|| "find_stream")?;
+    drop(connection);
+    let log_stream = stream
+        .tags
+        .iter()
+        .any(|tag| tag == StreamTag::Log.as_str())
+        .then(|| Arc::new(stream));
+    cache.lock().unwrap().insert(stream_id, log_stream.clone());
+    Ok(log_stream)
+}
+
+async fn encode_block_log_entries(
+    data_lake: &DataLakeConnection,
+    cache: &LogStreamCache,
+    convert_ticks: &ConvertTicks,
+    block: NewBlockNotification,
+) -> Result<bytes::Bytes> {
+    let Some(stream) = find_log_stream(data_lake, cache, block.stream_id).await? else {
+        return Ok(bytes::Bytes::new());
+    };
+    let payload = fetch_block_payload(
+        data_lake.blob_storage.clone(),
+        block.process_id,
+        block.stream_id,
+        block.block_id,
+        block.checksum,
+    )
+    .await?;
+    let mut lines = Vec::new();
+    parse_block(&stream, &payload, |val| {
+        if let Some(entry) = log_entry_from_value(convert_ticks, &val)? {
+            lines.extend(json_line(&TailedLogEntry {
+                time: Utc
+                    .timestamp_nanos(entry.time)
+                    .to_rfc3339_opts(SecondsFormat::Nanos, true),
+                level: entry.level,
+                target: &entry.target,
+                msg: &entry.msg,
+            })?);
+        }
+        Ok(true)
+    })
+    .with_context(|| "parse_block")?;
+    Ok(bytes::Bytes::from(lines))
+}
+
+/// Streams the log entries of the process as their blocks are ingested, one json object per line
+pub async fn tail_log_entries(
+    data_lake: DataLakeConnection,
+    new_blocks: &NewBlocksBroadcast,
+    process_id: Uuid,
+) -> Result<impl Stream<Item = Result<bytes::Bytes>>> {
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let process = find_process(&mut connection, &process_id)
+        .await
+        .with_context(|| "find_process")?;
+    drop(connection);
+    let convert_ticks = Arc::new(ConvertTicks::new(&process));
+    let cache = LogStreamCache::default();
+    let filter = BlockFilter {
+        process_id: Some(process_id),
+        ..BlockFilter::default()
+    };
+    let blocks = listen_new_blocks(new_blocks, filter).await?;
+    Ok(blocks
+        .then(move |block| {
+            let data_lake = data_lake.clone();
+            let cache = cache.clone();
+            let convert_ticks = convert_ticks.clone();
+            async move {
+                encode_block_log_entries(&data_lake, &cache, &convert_ticks, block?)
+                    .await
+                    .with_context(|| "encode_block_log_entries")
+            }
+        })
+        // blocks of the other streams have no log entries
+        .filter(|chunk| std::future::ready(!matches!(chunk, Ok(bytes) if bytes.is_empty()))))
+}
@@ -0,0 +1,248 @@
+use datafusion::arrow::array::{ArrayRef, AsArray, BooleanArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    PartitionEvaluator, Signature, Volatility, WindowUDF, WindowUDFImpl,
+};
+use std::any::Any;
+use std::sync::Arc;
+
+/// Scale of the median absolute deviation, making it comparable to the standard deviation of a
+/// normal distribution
+const MAD_SCALE: f64 = 1.4826;
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+fn present(values: &[Option<f64>]) -> Vec<f64> {
+    values.iter().flatten().copied().collect()
+}
+
+/// Values further than `threshold` standard deviations from the mean, null values stay null
+#[allow(clippy::cast_precision_loss)]
+pub fn zscore_anomalies(values: &[Option<f64>], threshold: f64) -> Vec<Option<bool>> {
+    let observed = present(values);
+    if observed.is_empty() {
+        return vec![None; values.len()];
+    }
+    let mean = observed.iter().sum::<f64>() / observed.len() as f64;
+    let variance = observed
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / observed.len() as f64;
+    let stddev = variance.sqrt();
+    values
+        .iter()
+        .map(|value| value.map(|value| stddev > 0.0 && (value - mean).abs() / stddev > threshold))
+        .collect()
+}
+
+/// Values further than `threshold` scaled median absolute deviations from the median,
+/// null values stay null
+///
+/// Unlike the z-score, the median and its deviation are not skewed by the anomalies themselves.
+/// When most values are equal the deviation is zero and any other value is an anomaly.
+pub fn mad_anomalies(values: &[Option<f64>], threshold: f64) -> Vec<Option<bool>> {
+    let mut observed = present(values);
+    let Some(center) = median(&mut observed) else {
+        return vec![None; values.len()];
+    };
+    let mut deviations: Vec<f64> = observed
+        .iter()
+        .map(|value| (value - center).abs())
+        .collect();
+    let mad = median(&mut deviations).unwrap_or_default() * MAD_SCALE;
+    values
+        .iter()
+        .map(|value| {
+            value.map(|value| {
+                let deviation = (value - center).abs();
+                if mad > 0.0 {
+                    deviation / mad > threshold
+                } else {
+                    deviation > 0.0
+                }
+            })
+        })
+        .collect()
+}
+
+/// Anomalies of a series with a cycle of `period` rows, i.e. 24 hourly buckets
+///
+/// The seasonal component of each phase of the cycle is the median of its values: what remains
+/// once it is subtracted is compared to its median absolute deviation, like `mad_anomalies`.
+pub fn seasonal_anomalies(
+    values: &[Option<f64>],
+    period: usize,
+    threshold: f64,
+) -> Vec<Option<bool>> {
+    let period = period.max(1);
+    let seasonal: Vec<Option<f64>> = (0..period)
+        .map(|phase| {
+            let mut phase_values: Vec<f64> = values
+                .iter()
+                .skip(phase)
+                .step_by(period)
+                .flatten()
+                .copied()
+                .collect();
+            median(&mut phase_values)
+        })
+        .collect();
+    let residuals: Vec<Option<f64>> = values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| Some(value.as_ref()? - seasonal[index % period]?))
+        .collect();
+    mad_anomalies(&residuals, threshold)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnomalyMethod {
+    ZScore,
+    Mad,
+    Seasonal,
+}
+
+impl AnomalyMethod {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ZScore => "zscore_anomaly",
+            Self::Mad => "mad_anomaly",
+            Self::Seasonal => "seasonal_anomaly",
+        }
+    }
+
+    fn arg_names(self) -> &'static str {
+        match self {
+            Self::ZScore | Self::Mad => "value, threshold",
+            Self::Seasonal => "value, period, threshold",
+        }
+    }
+
+    fn nb_args(self) -> usize {
+        match self {
+            Self::ZScore | Self::Mad => 2,
+            Self::Seasonal => 3,
+        }
+    }
+}
+
+/// `zscore_anomaly(value, threshold) OVER (ORDER BY time)`,
+/// `mad_anomaly(value, threshold) OVER (ORDER BY time)`,
+/// `seasonal_anomaly(value, period, threshold) OVER (ORDER BY time)`
+///
+/// True for the values of the partition detected as anomalies: thresholds are in standard
+/// deviations, a common choice being 3 for `zscore_anomaly` and 3.5 for the others.
+#[derive(Debug)]
+struct Anomaly {
+    method: AnomalyMethod,
+    signature: Signature,
+}
+
+impl WindowUDFImpl for Anomaly {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.method.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Boolean)
+    }
+
+    fn partition_evaluator(&self) -> Result<Box<dyn PartitionEvaluator>> {
+        Ok(Box::new(AnomalyEvaluator {
+            method: self.method,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct AnomalyEvaluator {
+    method: AnomalyMethod,
+}
+
+fn constant_arg<T: Copy>(method: AnomalyMethod, arg_name: &str, values: &[Option<T>]) -> Result<T> {
+    values.first().copied().flatten().ok_or_else(|| {
+        DataFusionError::Plan(format!("{}: {arg_name} can't be null", method.name()))
+    })
+}
+
+impl PartitionEvaluator for AnomalyEvaluator {
+    fn evaluate_all(&mut self, values: &[ArrayRef], num_rows: usize) -> Result<ArrayRef> {
+        let method = self.method;
+        if values.len() != method.nb_args() {
+            return Err(DataFusionError::Plan(format!(
+                "{} expects {} arguments: {}",
+                method.name(),
+                method.nb_args(),
+                method.arg_names()
+            )));
+        }
+        if num_rows == 0 {
+            return Ok(Arc::new(BooleanArray::from(Vec::<bool>::new())));
+        }
+        let series = cast(&values[0], &DataType::Float64)?;
+        let series: Vec<Option<f64>> = series.as_primitive::<Float64Type>().iter().collect();
+        let thresholds = cast(values.last().unwrap(), &DataType::Float64)?;
+        let thresholds: Vec<Option<f64>> =
+            thresholds.as_primitive::<Float64Type>().iter().collect();
+        let threshold = constant_arg(method, "threshold", &thresholds)?;
+        let anomalies = match method {
+            AnomalyMethod::ZScore => zscore_anomalies(&series, threshold),
+            AnomalyMethod::Mad => mad_anomalies(&series, threshold),
+            AnomalyMethod::Seasonal => {
+                let periods = cast(&values[1], &DataType::Int64)?;
+                let periods: Vec<Option<i64>> =
+                    periods.as_primitive::<Int64Type>().iter().collect();
+                let period = constant_arg(method, "period", &periods)?;
+                if period <= 0 {
+                    return Err(DataFusionError::Plan(format!(
+                        "{}: period should be a positive number of rows",
+                        method.name()
+                    )));
+                }
+                seasonal_anomalies(&series, period as usize, threshold)
+            }
+        };
+        Ok(Arc::new(BooleanArray::from(anomalies)))
+    }
+}
+
+fn anomaly_udwf(method: AnomalyMethod) -> WindowUDF {
+    WindowUDF::new_from_impl(Anomaly {
+        method,
+        signature: Signature::any(method.nb_args(), Volatility::Immutable),
+    })
+}
+
+pub fn zscore_anomaly_udwf() -> WindowUDF {
+    anomaly_udwf(AnomalyMethod::ZScore)
+}
+
+pub fn mad_anomaly_udwf() -> WindowUDF {
+    anomaly_udwf(AnomalyMethod::Mad)
+}
+
+pub fn seasonal_anomaly_udwf() -> WindowUDF {
+    anomaly_udwf(AnomalyMethod::Seasonal)
+}
@@ -0,0 +1,181 @@
+use super::to_datafusion_error;
+use crate::fetch_block_payload;
+use anyhow::Context;
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringBuilder, TimestampNanosecondBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use micromegas_telemetry::block_wire_format::verify_payload_checksum;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::Row;
+use std::any::Any;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn block_checksums_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("block_id", DataType::Utf8, false),
+        Field::new("stream_id", DataType::Utf8, false),
+        Field::new(
+            "insert_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            true,
+        ),
+        Field::new("status", DataType::Utf8, false),
+        Field::new("error", DataType::Utf8, true),
+    ]))
+}
+
+/// `verify_block_checksums(process_id)`: reads every block of the process and checks its payload
+///
+/// The `status` of each block is `ok`, `unchecked` when the client sent no checksum,
+/// `mismatch` when the payload changed after it was ingested, or `unreadable`.
+/// Blocks corrupted before they reached the ingestion service are rejected there.
+#[derive(Debug)]
+pub struct VerifyBlockChecksums {
+    data_lake: DataLakeConnection,
+}
+
+impl VerifyBlockChecksums {
+    pub fn new(data_lake: DataLakeConnection) -> Self {
+        Self { data_lake }
+    }
+}
+
+impl TableFunctionImpl for VerifyBlockChecksums {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let process_id = match args {
+            [Expr::Literal(ScalarValue::Utf8(Some(text)))] => {
+                Uuid::parse_str(text).map_err(|e| {
+                    DataFusionError::Plan(format!(
+                        "verify_block_checksums: invalid process_id: {e}"
+                    ))
+                })?
+            }
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "verify_block_checksums expects a process_id string".into(),
+                ))
+            }
+        };
+        Ok(Arc::new(BlockChecksumsTable {
+            data_lake: self.data_lake.clone(),
+            process_id,
+            schema: block_checksums_schema(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct BlockChecksumsTable {
+    data_lake: DataLakeConnection,
+    process_id: Uuid,
+    schema: SchemaRef,
+}
+
+impl BlockChecksumsTable {
+    async fn make_batch(&self) -> anyhow::Result<RecordBatch> {
+        let sql = "SELECT block_id, stream_id, insert_time, checksum
+             FROM blocks
+             WHERE process_id = $1
+             ORDER BY begin_time;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(self.process_id)
+                .fetch_all(&mut *connection),
+        )
+        .await
+        .with_context(|| "listing blocks of process")?;
+        drop(connection);
+        let mut block_ids = StringBuilder::new();
+        let mut stream_ids = StringBuilder::new();
+        let mut insert_times = TimestampNanosecondBuilder::with_capacity(rows.len());
+        let mut statuses = StringBuilder::new();
+        let mut errors = StringBuilder::new();
+        for row in rows {
+            let block_id: Uuid = row.try_get("block_id")?;
+            let stream_id: Uuid = row.try_get("stream_id")?;
+            let insert_time: Option<DateTime<Utc>> = row.try_get("insert_time")?;
+            let checksum: Option<i64> = row.try_get("checksum")?;
+            let payload = fetch_block_payload(
+                self.data_lake.blob_storage.clone(),
+                self.process_id,
+                stream_id,
+                block_id,
+                // verified below, to tell mismatches from unreadable payloads
+                None,
+            )
+            .await;
+            let (status, error) = match payload {
+                Err(e) => ("unreadable", Some(format!("{e:?}"))),
+                Ok(_) if checksum.is_none() => ("unchecked", None),
+                Ok(payload) => match verify_payload_checksum(&block_id, &payload, checksum) {
+                    Ok(()) => ("ok", None),
+                    Err(e) => ("mismatch", Some(e.to_string())),
+                },
+            };
+            block_ids.append_value(block_id.to_string());
+            stream_ids.append_value(stream_id.to_string());
+            insert_times.append_option(insert_time.and_then(|time| time.timestamp_nanos_opt()));
+            statuses.append_value(status);
+            errors.append_option(error);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(block_ids.finish()),
+            Arc::new(stream_ids.finish()),
+            Arc::new(insert_times.finish().with_timezone_utc()),
+            Arc::new(statuses.finish()),
+            Arc::new(errors.finish()),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for BlockChecksumsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch().await.map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `verify_block_checksums` available to the queries of the context
+pub fn register_verify_block_checksums(ctx: &SessionContext, data_lake: DataLakeConnection) {
+    ctx.register_udtf(
+        "verify_block_checksums",
+        Arc::new(VerifyBlockChecksums::new(data_lake)),
+    );
+}
@@ -0,0 +1,208 @@
+use super::to_datafusion_error;
+use anyhow::Context;
+use async_trait::async_trait;
+use datafusion::arrow::array::{
+    ArrayRef, Int64Builder, RecordBatch, StringBuilder, TimestampNanosecondBuilder,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use sqlx::types::chrono::{DateTime, Utc};
+use sqlx::Row;
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
+/// Longest validity accepted by s3 for urls signed with long term credentials
+const MAX_EXPIRATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+fn expiration_arg(expr: &Expr) -> Result<Duration> {
+    let seconds = match expr {
+        Expr::Literal(ScalarValue::Int64(Some(seconds))) => *seconds,
+        other => {
+            return Err(DataFusionError::Plan(format!(
+                "block_payload_urls: expiration should be a number of seconds, found {other}"
+            )))
+        }
+    };
+    let expiration = u64::try_from(seconds)
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+        .ok_or_else(|| {
+            DataFusionError::Plan("block_payload_urls: expiration should be positive".into())
+        })?;
+    Ok(expiration.min(MAX_EXPIRATION))
+}
+
+fn payload_urls_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("block_id", DataType::Utf8, false),
+        Field::new("stream_id", DataType::Utf8, false),
+        Field::new(
+            "begin_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new(
+            "end_time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("payload_size", DataType::Int64, false),
+        Field::new("url", DataType::Utf8, false),
+    ]))
+}
+
+/// `block_payload_urls(process_id [, expiration_seconds])`: one pre-signed url per block of the process
+///
+/// Large payloads can then be downloaded directly from the object store instead of going
+/// through the query engine.
+#[derive(Debug)]
+pub struct BlockPayloadUrls {
+    data_lake: DataLakeConnection,
+}
+
+impl BlockPayloadUrls {
+    pub fn new(data_lake: DataLakeConnection) -> Self {
+        Self { data_lake }
+    }
+}
+
+impl TableFunctionImpl for BlockPayloadUrls {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let (process_id, expiration) = match args {
+            [process_id] => (process_id, DEFAULT_EXPIRATION),
+            [process_id, expiration] => (process_id, expiration_arg(expiration)?),
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "block_payload_urls expects a process_id and an optional expiration".into(),
+                ))
+            }
+        };
+        let process_id = match process_id {
+            Expr::Literal(ScalarValue::Utf8(Some(text))) => Uuid::parse_str(text).map_err(|e| {
+                DataFusionError::Plan(format!("block_payload_urls: invalid process_id: {e}"))
+            })?,
+            other => {
+                return Err(DataFusionError::Plan(format!(
+                    "block_payload_urls: process_id should be a string, found {other}"
+                )))
+            }
+        };
+        Ok(Arc::new(BlockPayloadUrlsTable {
+            data_lake: self.data_lake.clone(),
+            process_id,
+            expiration,
+            schema: payload_urls_schema(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct BlockPayloadUrlsTable {
+    data_lake: DataLakeConnection,
+    process_id: Uuid,
+    expiration: Duration,
+    schema: SchemaRef,
+}
+
+impl BlockPayloadUrlsTable {
+    async fn make_batch(&self) -> anyhow::Result<RecordBatch> {
+        let sql = "SELECT block_id, stream_id, begin_time, end_time, payload_size
+             FROM blocks
+             WHERE process_id = $1
+             ORDER BY begin_time;";
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let rows = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(self.process_id)
+                .fetch_all(&mut *connection),
+        )
+        .await
+        .with_context(|| "listing blocks of process")?;
+        drop(connection);
+        let mut block_ids = StringBuilder::new();
+        let mut stream_ids = StringBuilder::new();
+        let mut begin_times = TimestampNanosecondBuilder::with_capacity(rows.len());
+        let mut end_times = TimestampNanosecondBuilder::with_capacity(rows.len());
+        let mut payload_sizes = Int64Builder::with_capacity(rows.len());
+        let mut urls = StringBuilder::new();
+        for row in rows {
+            let block_id: Uuid = row.try_get("block_id")?;
+            let stream_id: Uuid = row.try_get("stream_id")?;
+            let begin_time: DateTime<Utc> = row.try_get("begin_time")?;
+            let end_time: DateTime<Utc> = row.try_get("end_time")?;
+            let obj_path = format!("blobs/{}/{stream_id}/{block_id}", self.process_id);
+            let url = self
+                .data_lake
+                .blob_storage
+                .signed_url(&obj_path, self.expiration)
+                .await?;
+            block_ids.append_value(block_id.to_string());
+            stream_ids.append_value(stream_id.to_string());
+            begin_times.append_value(begin_time.timestamp_nanos_opt().unwrap_or_default());
+            end_times.append_value(end_time.timestamp_nanos_opt().unwrap_or_default());
+            payload_sizes.append_value(row.try_get("payload_size")?);
+            urls.append_value(url.as_str());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(block_ids.finish()),
+            Arc::new(stream_ids.finish()),
+            Arc::new(begin_times.finish().with_timezone_utc()),
+            Arc::new(end_times.finish().with_timezone_utc()),
+            Arc::new(payload_sizes.finish()),
+            Arc::new(urls.finish()),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for BlockPayloadUrlsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch().await.map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `block_payload_urls` available to the queries of the context
+pub fn register_block_payload_urls(ctx: &SessionContext, data_lake: DataLakeConnection) {
+    ctx.register_udtf(
+        "block_payload_urls",
+        Arc::new(BlockPayloadUrls::new(data_lake)),
+    );
+}
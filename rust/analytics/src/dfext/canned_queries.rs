@@ -0,0 +1,209 @@
+use super::time_buckets::interval_arg;
+use crate::sql_session::plan_client_sql;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::view::ViewTable;
+use datafusion::datasource::TableProvider;
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// name of a table of the session
+    Table,
+    /// string compared with the values of a column
+    Text,
+    /// positive number
+    Count,
+    /// INTERVAL or number of nanoseconds, used as an INTERVAL
+    Interval,
+    /// INTERVAL or number of nanoseconds, used as a number of nanoseconds
+    Duration,
+}
+
+/// Parameterized query answering a common question without knowing the schema of the views
+///
+/// `{0}`, `{1}`... in the sql are replaced by the arguments, in the order of the parameters.
+#[derive(Debug)]
+pub struct CannedQuery {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub params: &'static [(&'static str, ParamKind)],
+    pub sql: &'static str,
+}
+
+const CANNED_QUERIES: &[CannedQuery] = &[
+    CannedQuery {
+        name: "top_error_targets",
+        description: "targets logging the most errors, in a table of log_entries",
+        params: &[("log_entries_table", ParamKind::Table), ("nb_targets", ParamKind::Count)],
+        sql: "SELECT CAST(target AS VARCHAR) AS target, count(*) AS nb_errors, min(time) AS first_time, max(time) AS last_time
+              FROM {0}
+              WHERE level <= 2
+              GROUP BY 1
+              ORDER BY nb_errors DESC, target
+              LIMIT {1}",
+    },
+    CannedQuery {
+        name: "frame_spikes",
+        description: "spans of that name lasting at least min_duration, longest first, in a table of spans",
+        params: &[
+            ("spans_table", ParamKind::Table),
+            ("span_name", ParamKind::Text),
+            ("min_duration", ParamKind::Duration),
+        ],
+        sql: r#"SELECT begin, "end", CAST("end" AS BIGINT) - CAST(begin AS BIGINT) AS duration_ns
+                FROM {0}
+                WHERE name = {1}
+                AND CAST("end" AS BIGINT) - CAST(begin AS BIGINT) >= {2}
+                ORDER BY duration_ns DESC, begin"#,
+    },
+    CannedQuery {
+        name: "fleet_overview",
+        description: "processes, computers and users of each executable, in a table of processes",
+        params: &[("processes_table", ParamKind::Table)],
+        sql: "SELECT exe, count(*) AS nb_processes, count(DISTINCT computer) AS nb_computers,
+                     count(DISTINCT username) AS nb_users, min(start_time) AS first_start, max(start_time) AS last_start
+              FROM {0}
+              GROUP BY exe
+              ORDER BY nb_processes DESC, exe",
+    },
+    CannedQuery {
+        name: "process_health",
+        description: "errors, warnings and entries per time bucket, in a table of log_entries",
+        params: &[("log_entries_table", ParamKind::Table), ("interval", ParamKind::Interval)],
+        sql: "SELECT date_bin({1}, time) AS time_bucket,
+                     sum(CASE WHEN level <= 2 THEN 1 ELSE 0 END) AS nb_errors,
+                     sum(CASE WHEN level = 3 THEN 1 ELSE 0 END) AS nb_warnings,
+                     count(*) AS nb_entries
+              FROM {0}
+              GROUP BY 1
+              ORDER BY 1",
+    },
+    CannedQuery {
+        name: "anomalies",
+        description: "values of the metric detected as anomalies by mad_anomaly, in a table of measures",
+        params: &[("measures_table", ParamKind::Table), ("metric_name", ParamKind::Text)],
+        sql: "SELECT time, value
+              FROM (SELECT time, value, mad_anomaly(value, 3.5) OVER (ORDER BY time) AS is_anomaly
+                    FROM {0}
+                    WHERE name = {1})
+              WHERE is_anomaly
+              ORDER BY time",
+    },
+];
+
+pub fn canned_queries() -> &'static [CannedQuery] {
+    CANNED_QUERIES
+}
+
+pub fn find_canned_query(name: &str) -> Option<&'static CannedQuery> {
+    CANNED_QUERIES.iter().find(|query| query.name == name)
+}
+
+fn render_arg(query: &CannedQuery, param: &str, kind: ParamKind, expr: &Expr) -> Result<String> {
+    let name = query.name;
+    match (kind, expr) {
+        (ParamKind::Table, Expr::Literal(ScalarValue::Utf8(Some(table))))
+            if !table.is_empty() && !table.contains('"') =>
+        {
+            Ok(format!("\"{table}\""))
+        }
+        (ParamKind::Text, Expr::Literal(ScalarValue::Utf8(Some(text)))) => {
+            Ok(format!("'{}'", text.replace('\'', "''")))
+        }
+        (ParamKind::Count, Expr::Literal(ScalarValue::Int64(Some(count)))) if *count > 0 => {
+            Ok(count.to_string())
+        }
+        (ParamKind::Interval, expr) => Ok(format!(
+            "INTERVAL '{} nanoseconds'",
+            interval_arg(expr, name)?
+        )),
+        (ParamKind::Duration, expr) => Ok(interval_arg(expr, name)?.to_string()),
+        (kind, other) => Err(DataFusionError::Plan(format!(
+            "{name}: {param} should be a {kind:?}, found {other}"
+        ))),
+    }
+}
+
+/// Sql of the canned query with these arguments, i.e. `[lit("log_entries"), lit(10)]`
+pub fn canned_query_sql(query: &CannedQuery, args: &[Expr]) -> Result<String> {
+    if args.len() != query.params.len() {
+        let names: Vec<&str> = query.params.iter().map(|(name, _)| *name).collect();
+        return Err(DataFusionError::Plan(format!(
+            "{} expects {} arguments: {}",
+            query.name,
+            names.len(),
+            names.join(", ")
+        )));
+    }
+    let rendered = query
+        .params
+        .iter()
+        .zip(args)
+        .map(|((param, kind), arg)| render_arg(query, param, *kind, arg))
+        .collect::<Result<Vec<_>>>()?;
+    // a single pass, placeholders in the arguments are left as they are
+    let mut sql = String::with_capacity(query.sql.len());
+    let mut rest = query.sql;
+    while let Some((before, after)) = rest.split_once('{') {
+        let (index, after) = after
+            .split_once('}')
+            .and_then(|(index, after)| Some((index.parse::<usize>().ok()?, after)))
+            .ok_or_else(|| {
+                DataFusionError::Internal(format!("{}: invalid placeholder", query.name))
+            })?;
+        sql.push_str(before);
+        sql.push_str(rendered.get(index).ok_or_else(|| {
+            DataFusionError::Internal(format!("{}: unknown parameter {index}", query.name))
+        })?);
+        rest = after;
+    }
+    sql.push_str(rest);
+    Ok(sql)
+}
+
+/// Table function running a canned query over the tables of the session
+pub struct CannedQueryFunction {
+    query: &'static CannedQuery,
+    session_state: Box<dyn Fn() -> Option<SessionState> + Send + Sync>,
+}
+
+impl std::fmt::Debug for CannedQueryFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CannedQueryFunction")
+            .field("query", &self.query.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CannedQueryFunction {
+    /// Tables are looked up in the context, which is only weakly referenced
+    pub fn new(query: &'static CannedQuery, ctx: &SessionContext) -> Self {
+        let state = ctx.state_weak_ref();
+        Self {
+            query,
+            session_state: Box::new(move || state.upgrade().map(|state| state.read().clone())),
+        }
+    }
+}
+
+impl TableFunctionImpl for CannedQueryFunction {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let sql = canned_query_sql(self.query, args)?;
+        let state = (self.session_state)()
+            .ok_or_else(|| DataFusionError::Plan(format!("{}: session closed", self.query.name)))?;
+        // tables registered in sessions are in memory: planning does not wait
+        let plan = futures::executor::block_on(plan_client_sql(&state, &sql))?;
+        Ok(Arc::new(ViewTable::try_new(plan, Some(sql))?))
+    }
+}
+
+/// Makes the canned queries available to the queries of the context, as table functions
+pub fn register_canned_queries(ctx: &SessionContext) {
+    for query in CANNED_QUERIES {
+        ctx.register_udtf(query.name, Arc::new(CannedQueryFunction::new(query, ctx)));
+    }
+}
@@ -0,0 +1,36 @@
+use super::to_datafusion_error;
+use crate::view_docs::{describe_view, find_view_doc, list_documented_views};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::Expr;
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+/// `describe_view('log_entries')`: columns of the view with their types and descriptions,
+/// followed by example queries
+///
+/// Without argument, lists the documented views.
+#[derive(Debug)]
+pub struct DescribeView {}
+
+impl TableFunctionImpl for DescribeView {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let batch = match args {
+            [] => list_documented_views(),
+            [Expr::Literal(ScalarValue::Utf8(Some(view_name)))] => {
+                find_view_doc(view_name).and_then(|doc| describe_view(&doc))
+            }
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "describe_view expects the name of a view".into(),
+                ))
+            }
+        }
+        .map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemTable::try_new(
+            batch.schema(),
+            vec![vec![batch]],
+        )?))
+    }
+}
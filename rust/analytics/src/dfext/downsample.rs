@@ -0,0 +1,198 @@
+use crate::sql_session::plan_client_sql;
+use async_trait::async_trait;
+use datafusion::arrow::array::{
+    Array, AsArray, Float64Array, RecordBatch, TimestampNanosecondArray,
+};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    DataType, Field, Float64Type, Schema, SchemaRef, TimeUnit, TimestampNanosecondType,
+};
+use datafusion::dataframe::DataFrame;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use std::any::Any;
+use std::sync::Arc;
+
+/// Indices of the points kept by the largest-triangle-three-buckets algorithm
+///
+/// The first and last points are always kept, the others are split in `max_points - 2`
+/// buckets from which the point forming the largest triangle with its neighbours is picked.
+/// Points are expected to be sorted by time.
+pub fn lttb_indices(times: &[f64], values: &[f64], max_points: usize) -> Vec<usize> {
+    let nb_points = times.len().min(values.len());
+    if max_points >= nb_points {
+        return (0..nb_points).collect();
+    }
+    if max_points < 3 {
+        return [0, nb_points - 1].into_iter().take(max_points).collect();
+    }
+    let nb_buckets = max_points - 2;
+    #[allow(clippy::cast_precision_loss)]
+    let bucket_size = (nb_points - 2) as f64 / nb_buckets as f64;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let bucket_begin = |bucket: usize| (bucket as f64 * bucket_size) as usize + 1;
+    let mut kept = Vec::with_capacity(max_points);
+    let mut previous = 0;
+    kept.push(previous);
+    for bucket in 0..nb_buckets {
+        // average of the next bucket, the last point for the last bucket
+        let next_begin = bucket_begin(bucket + 1);
+        let next_end = bucket_begin(bucket + 2).min(nb_points);
+        let (avg_time, avg_value) = if next_begin < next_end {
+            #[allow(clippy::cast_precision_loss)]
+            let count = (next_end - next_begin) as f64;
+            (
+                times[next_begin..next_end].iter().sum::<f64>() / count,
+                values[next_begin..next_end].iter().sum::<f64>() / count,
+            )
+        } else {
+            (times[nb_points - 1], values[nb_points - 1])
+        };
+        let mut best = bucket_begin(bucket);
+        let mut best_area = -1.0;
+        for candidate in bucket_begin(bucket)..next_begin.min(nb_points - 1) {
+            let area = ((times[previous] - avg_time) * (values[candidate] - values[previous])
+                - (times[previous] - times[candidate]) * (avg_value - values[previous]))
+                .abs();
+            if area > best_area {
+                best_area = area;
+                best = candidate;
+            }
+        }
+        kept.push(best);
+        previous = best;
+    }
+    kept.push(nb_points - 1);
+    kept
+}
+
+fn downsampled_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("value", DataType::Float64, false),
+    ]))
+}
+
+/// `downsample(sql, max_points)`: series of at most `max_points` keeping the shape of the original
+///
+/// The query should return the time in its first column and the value in its second, ordered by time.
+/// Points are selected with the largest-triangle-three-buckets algorithm, so peaks survive
+/// the reduction, unlike with averages per bucket.
+#[derive(Debug)]
+pub struct Downsample {}
+
+impl TableFunctionImpl for Downsample {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let [sql, max_points] = args else {
+            return Err(DataFusionError::Plan(
+                "downsample expects 2 arguments: sql, max_points".into(),
+            ));
+        };
+        let Expr::Literal(ScalarValue::Utf8(Some(sql))) = sql else {
+            return Err(DataFusionError::Plan(format!(
+                "downsample: sql should be a string, found {sql}"
+            )));
+        };
+        let max_points = match max_points {
+            Expr::Literal(ScalarValue::Int64(Some(max_points))) if *max_points >= 3 => {
+                usize::try_from(*max_points).map_err(|e| DataFusionError::Plan(e.to_string()))?
+            }
+            other => {
+                return Err(DataFusionError::Plan(format!(
+                    "downsample: max_points should be a number greater than 2, found {other}"
+                )))
+            }
+        };
+        Ok(Arc::new(DownsampledTable {
+            sql: sql.clone(),
+            max_points,
+            schema: downsampled_schema(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct DownsampledTable {
+    sql: String,
+    max_points: usize,
+    schema: SchemaRef,
+}
+
+impl DownsampledTable {
+    async fn make_batch(&self, state: &SessionState) -> Result<RecordBatch> {
+        let plan = plan_client_sql(state, &self.sql).await?;
+        let batches = DataFrame::new(state.clone(), plan).collect().await?;
+        let mut times = vec![];
+        let mut values = vec![];
+        for batch in batches {
+            if batch.num_columns() < 2 {
+                return Err(DataFusionError::Plan(
+                    "downsample: the query should return a time and a value column".into(),
+                ));
+            }
+            let time_column = cast(
+                batch.column(0),
+                &DataType::Timestamp(TimeUnit::Nanosecond, None),
+            )?;
+            let value_column = cast(batch.column(1), &DataType::Float64)?;
+            let time_column = time_column.as_primitive::<TimestampNanosecondType>();
+            let value_column = value_column.as_primitive::<Float64Type>();
+            for row in 0..batch.num_rows() {
+                if time_column.is_valid(row) && value_column.is_valid(row) {
+                    times.push(time_column.value(row));
+                    values.push(value_column.value(row));
+                }
+            }
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let float_times: Vec<f64> = times.iter().map(|t| *t as f64).collect();
+        let kept = lttb_indices(&float_times, &values, self.max_points);
+        let times: TimestampNanosecondArray =
+            kept.iter().map(|i| times[*i]).collect::<Vec<_>>().into();
+        let values: Float64Array = kept.iter().map(|i| values[*i]).collect::<Vec<_>>().into();
+        Ok(RecordBatch::try_new(
+            self.schema.clone(),
+            vec![Arc::new(times.with_timezone_utc()), Arc::new(values)],
+        )?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for DownsampledTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch(state).await?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
@@ -0,0 +1,248 @@
+use crate::sql_session::plan_client_sql;
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, AsArray, RecordBatch, UInt64Array};
+use datafusion::arrow::compute::{cast, concat_batches, take};
+use datafusion::arrow::datatypes::{
+    DataType, Field, Schema, SchemaRef, TimeUnit, TimestampNanosecondType,
+};
+use datafusion::dataframe::DataFrame;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use std::any::Any;
+use std::sync::Arc;
+
+/// Index of the innermost span enclosing each event, `None` for events outside of all spans
+///
+/// Events are expected to be sorted by time and spans by begin time, then depth. Spans of a
+/// thread are nested, which allows a single sweep keeping the stack of the open spans
+/// instead of comparing every event with every span.
+pub fn enclosing_spans(
+    event_times: &[i64],
+    span_begins: &[i64],
+    span_ends: &[i64],
+) -> Vec<Option<usize>> {
+    let mut open: Vec<usize> = vec![];
+    let mut next_span = 0;
+    let mut result = Vec::with_capacity(event_times.len());
+    for time in event_times {
+        while next_span < span_begins.len() && span_begins[next_span] <= *time {
+            while open
+                .last()
+                .is_some_and(|top| span_ends[*top] < span_begins[next_span])
+            {
+                open.pop();
+            }
+            open.push(next_span);
+            next_span += 1;
+        }
+        while open.last().is_some_and(|top| span_ends[*top] < *time) {
+            open.pop();
+        }
+        result.push(open.last().copied());
+    }
+    result
+}
+
+fn table_name_arg(expr: &Expr, arg_name: &str) -> Result<String> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(name))) if !name.contains('"') => Ok(name.clone()),
+        other => Err(DataFusionError::Plan(format!(
+            "events_within_spans: {arg_name} should be a table name, found {other}"
+        ))),
+    }
+}
+
+fn output_schema(events_schema: &Schema) -> SchemaRef {
+    let mut fields: Vec<Field> = events_schema
+        .fields()
+        .iter()
+        .map(|f| f.as_ref().clone())
+        .collect();
+    fields.push(Field::new("span_id", DataType::Int64, false));
+    fields.push(Field::new("span_depth", DataType::UInt32, false));
+    fields.push(Field::new("span_name", DataType::Utf8, false));
+    Arc::new(Schema::new(fields))
+}
+
+/// `events_within_spans(events_table, spans_table [, span_filter])`: events of the table with
+/// the id, depth and name of the innermost span containing them
+///
+/// The events table needs a `time` column and the spans table the `id`, `depth`, `name`,
+/// `begin` and `end` columns of the spans of a single thread. `span_filter` is a sql predicate
+/// restricting the spans that events can be assigned to. Events outside of the spans are dropped.
+pub struct EventsWithinSpans {
+    session_state: Box<dyn Fn() -> Option<SessionState> + Send + Sync>,
+}
+
+impl std::fmt::Debug for EventsWithinSpans {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventsWithinSpans").finish_non_exhaustive()
+    }
+}
+
+impl EventsWithinSpans {
+    /// Tables are looked up in the context, which is only weakly referenced
+    pub fn new(ctx: &SessionContext) -> Self {
+        let state = ctx.state_weak_ref();
+        Self {
+            session_state: Box::new(move || state.upgrade().map(|state| state.read().clone())),
+        }
+    }
+}
+
+impl TableFunctionImpl for EventsWithinSpans {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let (events_table, spans_table, span_filter) = match args {
+            [events, spans] => (events, spans, None),
+            [events, spans, Expr::Literal(ScalarValue::Utf8(Some(filter)))] => {
+                (events, spans, Some(filter.clone()))
+            }
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "events_within_spans expects 2 or 3 arguments".into(),
+                ))
+            }
+        };
+        let events_table = table_name_arg(events_table, "events_table")?;
+        let spans_table = table_name_arg(spans_table, "spans_table")?;
+        let state = (self.session_state)()
+            .ok_or_else(|| DataFusionError::Plan("events_within_spans: session closed".into()))?;
+        let options = state.config_options();
+        let schema_provider = state
+            .catalog_list()
+            .catalog(&options.catalog.default_catalog)
+            .and_then(|catalog| catalog.schema(&options.catalog.default_schema))
+            .ok_or_else(|| {
+                DataFusionError::Plan("events_within_spans: no default schema".into())
+            })?;
+        // tables registered in sessions are in memory: resolving them does not wait
+        let events_provider = futures::executor::block_on(schema_provider.table(&events_table))?
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "events_within_spans: table {events_table} not found"
+                ))
+            })?;
+        let events_schema = events_provider.schema();
+        events_schema.field_with_name("time")?;
+        Ok(Arc::new(EventsWithinSpansTable {
+            events_table,
+            spans_table,
+            span_filter,
+            schema: output_schema(&events_schema),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct EventsWithinSpansTable {
+    events_table: String,
+    spans_table: String,
+    span_filter: Option<String>,
+    schema: SchemaRef,
+}
+
+pub(crate) async fn collect_sql(state: &SessionState, sql: &str) -> Result<RecordBatch> {
+    let plan = plan_client_sql(state, sql).await?;
+    let df = DataFrame::new(state.clone(), plan);
+    let schema: SchemaRef = Arc::new(df.schema().into());
+    let batches = df.collect().await?;
+    Ok(concat_batches(&schema, &batches)?)
+}
+
+pub(crate) fn time_values(column: &ArrayRef) -> Result<Vec<i64>> {
+    let column = cast(column, &DataType::Timestamp(TimeUnit::Nanosecond, None))?;
+    Ok(column
+        .as_primitive::<TimestampNanosecondType>()
+        .values()
+        .to_vec())
+}
+
+impl EventsWithinSpansTable {
+    async fn make_batch(&self, state: &SessionState) -> Result<RecordBatch> {
+        let filter = self
+            .span_filter
+            .as_ref()
+            .map(|filter| format!("WHERE {filter}"))
+            .unwrap_or_default();
+        let spans = collect_sql(
+            state,
+            &format!(
+                r#"SELECT id, CAST(depth AS INT UNSIGNED) AS depth, CAST(name AS VARCHAR) AS name, begin, "end"
+                   FROM "{}" {filter}
+                   ORDER BY begin, depth;"#,
+                self.spans_table
+            ),
+        )
+        .await?;
+        let events = collect_sql(
+            state,
+            &format!(
+                r#"SELECT * FROM "{}" WHERE time IS NOT NULL ORDER BY time;"#,
+                self.events_table
+            ),
+        )
+        .await?;
+        let enclosing = enclosing_spans(
+            &time_values(events.column(events.schema().index_of("time")?))?,
+            &time_values(spans.column(3))?,
+            &time_values(spans.column(4))?,
+        );
+        let (event_indices, span_indices): (Vec<u64>, Vec<u64>) = enclosing
+            .iter()
+            .enumerate()
+            .filter_map(|(event, span)| span.map(|span| (event as u64, span as u64)))
+            .unzip();
+        let event_indices = UInt64Array::from(event_indices);
+        let span_indices = UInt64Array::from(span_indices);
+        let mut columns = vec![];
+        for column in events.columns() {
+            columns.push(take(column, &event_indices, None)?);
+        }
+        let span_ids = cast(spans.column(0), &DataType::Int64)?;
+        for column in [&span_ids, spans.column(1), spans.column(2)] {
+            columns.push(take(column, &span_indices, None)?);
+        }
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for EventsWithinSpansTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch(state).await?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `events_within_spans` available to the queries of the context
+pub fn register_events_within_spans(ctx: &SessionContext) {
+    ctx.register_udtf("events_within_spans", Arc::new(EventsWithinSpans::new(ctx)));
+}
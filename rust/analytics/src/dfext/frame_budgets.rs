@@ -0,0 +1,305 @@
+use super::events_within_spans::{collect_sql, time_values};
+use super::to_datafusion_error;
+use anyhow::Context;
+use async_trait::async_trait;
+use datafusion::arrow::array::{Float64Array, RecordBatch, StringArray, UInt64Array};
+use datafusion::arrow::compute::take;
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Parses the `frame_budgets` property of a process, i.e. `Physics=4;Render=8`
+pub fn parse_frame_budgets(budgets: &str) -> anyhow::Result<Vec<(String, f64)>> {
+    budgets
+        .split(';')
+        .filter(|budget| !budget.trim().is_empty())
+        .map(|budget| {
+            let (span_name, max_ms) = budget
+                .split_once('=')
+                .with_context(|| format!("frame budget {budget:?} should be name=ms"))?;
+            let max_ms: f64 = max_ms
+                .trim()
+                .parse()
+                .with_context(|| format!("parsing frame budget {budget:?}"))?;
+            Ok((span_name.trim().to_owned(), max_ms))
+        })
+        .collect()
+}
+
+/// Time spent in spans of each name, per frame: `(frame index, span name index, nanoseconds)`
+///
+/// Frames are expected to be sorted by begin time and not to overlap. Spans are expected to be
+/// sorted by begin time, then depth: a span nested in a span of the same name is not counted
+/// twice. Spans crossing the boundaries of frames are ignored.
+pub fn time_spent_per_frame(
+    frame_begins: &[i64],
+    frame_ends: &[i64],
+    span_names: &[usize],
+    span_begins: &[i64],
+    span_ends: &[i64],
+) -> Vec<(usize, usize, i64)> {
+    let mut spent: Vec<(usize, usize, i64)> = vec![];
+    // per name, end of the last span counted in the current frame
+    let mut counted_until: HashMap<usize, i64> = HashMap::new();
+    let mut frame = 0;
+    for span in 0..span_begins.len() {
+        let begin = span_begins[span];
+        let end = span_ends[span];
+        while frame < frame_begins.len() && frame_ends[frame] < begin {
+            frame += 1;
+            counted_until.clear();
+        }
+        if frame == frame_begins.len() {
+            break;
+        }
+        if begin < frame_begins[frame] || end > frame_ends[frame] {
+            continue;
+        }
+        let name = span_names[span];
+        if counted_until.get(&name).is_some_and(|until| begin < *until) {
+            continue;
+        }
+        counted_until.insert(name, end);
+        match spent
+            .iter_mut()
+            .rev()
+            .take_while(|(f, _, _)| *f == frame)
+            .find(|(_, n, _)| *n == name)
+        {
+            Some((_, _, ns)) => *ns += end - begin,
+            None => spent.push((frame, name, end - begin)),
+        }
+    }
+    spent
+}
+
+fn string_arg(expr: &Expr, arg_name: &str) -> Result<String> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(value))) => Ok(value.clone()),
+        other => Err(DataFusionError::Plan(format!(
+            "frame_budget_violations: {arg_name} should be a string, found {other}"
+        ))),
+    }
+}
+
+fn output_schema(spans_schema: &Schema) -> Result<SchemaRef> {
+    let begin_type = spans_schema.field_with_name("begin")?.data_type().clone();
+    let end_type = spans_schema.field_with_name("end")?.data_type().clone();
+    Ok(Arc::new(Schema::new(vec![
+        Field::new("frame_begin", begin_type, false),
+        Field::new("frame_end", end_type, false),
+        Field::new("span_name", DataType::Utf8, false),
+        Field::new("spent_ms", DataType::Float64, false),
+        Field::new("budget_ms", DataType::Float64, false),
+    ])))
+}
+
+/// `frame_budget_violations(spans_table, frame_span_name, budgets)`: frames in which spans took
+/// more time than their budget
+///
+/// The spans table needs the `name`, `depth`, `begin` and `end` columns of the spans of a single
+/// thread, the frames being the spans named `frame_span_name`. `budgets` is the `frame_budgets`
+/// property declared by the process, i.e. `Physics=4;Render=8`. There is a row per frame and
+/// span name exceeding its budget, with the milliseconds spent in the spans of that name.
+pub struct FrameBudgetViolations {
+    session_state: Box<dyn Fn() -> Option<SessionState> + Send + Sync>,
+}
+
+impl std::fmt::Debug for FrameBudgetViolations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameBudgetViolations")
+            .finish_non_exhaustive()
+    }
+}
+
+impl FrameBudgetViolations {
+    /// Tables are looked up in the context, which is only weakly referenced
+    pub fn new(ctx: &SessionContext) -> Self {
+        let state = ctx.state_weak_ref();
+        Self {
+            session_state: Box::new(move || state.upgrade().map(|state| state.read().clone())),
+        }
+    }
+}
+
+impl TableFunctionImpl for FrameBudgetViolations {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let [spans_table, frame_span_name, budgets] = args else {
+            return Err(DataFusionError::Plan(
+                "frame_budget_violations expects 3 arguments".into(),
+            ));
+        };
+        let spans_table = string_arg(spans_table, "spans_table")?;
+        if spans_table.contains('"') {
+            return Err(DataFusionError::Plan(format!(
+                "frame_budget_violations: invalid table name {spans_table}"
+            )));
+        }
+        let frame_span_name = string_arg(frame_span_name, "frame_span_name")?;
+        let budgets =
+            parse_frame_budgets(&string_arg(budgets, "budgets")?).map_err(to_datafusion_error)?;
+        let state = (self.session_state)().ok_or_else(|| {
+            DataFusionError::Plan("frame_budget_violations: session closed".into())
+        })?;
+        let options = state.config_options();
+        let schema_provider = state
+            .catalog_list()
+            .catalog(&options.catalog.default_catalog)
+            .and_then(|catalog| catalog.schema(&options.catalog.default_schema))
+            .ok_or_else(|| {
+                DataFusionError::Plan("frame_budget_violations: no default schema".into())
+            })?;
+        // tables registered in sessions are in memory: resolving them does not wait
+        let spans_provider = futures::executor::block_on(schema_provider.table(&spans_table))?
+            .ok_or_else(|| {
+                DataFusionError::Plan(format!(
+                    "frame_budget_violations: table {spans_table} not found"
+                ))
+            })?;
+        Ok(Arc::new(FrameBudgetViolationsTable {
+            spans_table,
+            frame_span_name,
+            budgets,
+            schema: output_schema(&spans_provider.schema())?,
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct FrameBudgetViolationsTable {
+    spans_table: String,
+    frame_span_name: String,
+    budgets: Vec<(String, f64)>,
+    schema: SchemaRef,
+}
+
+fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl FrameBudgetViolationsTable {
+    async fn make_batch(&self, state: &SessionState) -> Result<RecordBatch> {
+        let frames = collect_sql(
+            state,
+            &format!(
+                r#"SELECT begin, "end" FROM "{}"
+                   WHERE CAST(name AS VARCHAR) = {}
+                   ORDER BY begin;"#,
+                self.spans_table,
+                sql_string(&self.frame_span_name)
+            ),
+        )
+        .await?;
+        let names: Vec<String> = self
+            .budgets
+            .iter()
+            .map(|(span_name, _)| sql_string(span_name))
+            .collect();
+        if names.is_empty() {
+            return Ok(RecordBatch::new_empty(self.schema.clone()));
+        }
+        let spans = collect_sql(
+            state,
+            &format!(
+                r#"SELECT CAST(name AS VARCHAR) AS name, begin, "end" FROM "{}"
+                   WHERE CAST(name AS VARCHAR) IN ({})
+                   ORDER BY begin, depth;"#,
+                self.spans_table,
+                names.join(", ")
+            ),
+        )
+        .await?;
+        let span_names: Vec<usize> = spans
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| DataFusionError::Internal("span names should be strings".into()))?
+            .iter()
+            .map(|name| {
+                self.budgets
+                    .iter()
+                    .position(|(span_name, _)| Some(span_name.as_str()) == name)
+                    .unwrap_or_default()
+            })
+            .collect();
+        let spent = time_spent_per_frame(
+            &time_values(frames.column(0))?,
+            &time_values(frames.column(1))?,
+            &span_names,
+            &time_values(spans.column(1))?,
+            &time_values(spans.column(2))?,
+        );
+        let mut frame_indices = vec![];
+        let mut violating_names = vec![];
+        let mut spent_ms = vec![];
+        let mut budget_ms = vec![];
+        for (frame, name, ns) in spent {
+            let (span_name, max_ms) = &self.budgets[name];
+            let ms = ns as f64 / 1_000_000.0;
+            if ms > *max_ms {
+                frame_indices.push(frame as u64);
+                violating_names.push(span_name.clone());
+                spent_ms.push(ms);
+                budget_ms.push(*max_ms);
+            }
+        }
+        let frame_indices = UInt64Array::from(frame_indices);
+        Ok(RecordBatch::try_new(
+            self.schema.clone(),
+            vec![
+                take(frames.column(0), &frame_indices, None)?,
+                take(frames.column(1), &frame_indices, None)?,
+                Arc::new(StringArray::from(violating_names)),
+                Arc::new(Float64Array::from(spent_ms)),
+                Arc::new(Float64Array::from(budget_ms)),
+            ],
+        )?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for FrameBudgetViolationsTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch(state).await?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `frame_budget_violations` available to the queries of the context
+pub fn register_frame_budget_violations(ctx: &SessionContext) {
+    ctx.register_udtf(
+        "frame_budget_violations",
+        Arc::new(FrameBudgetViolations::new(ctx)),
+    );
+}
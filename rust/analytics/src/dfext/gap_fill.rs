@@ -0,0 +1,148 @@
+use datafusion::arrow::array::{ArrayRef, AsArray, Float64Array};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::{
+    PartitionEvaluator, Signature, Volatility, WindowUDF, WindowUDFImpl,
+};
+use std::any::Any;
+use std::str::FromStr;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFillStrategy {
+    /// last observation carried forward
+    Locf,
+    /// linear interpolation between the surrounding observations, in time
+    Linear,
+    /// gaps stay null
+    Null,
+}
+
+impl FromStr for GapFillStrategy {
+    type Err = DataFusionError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "locf" => Ok(Self::Locf),
+            "linear" => Ok(Self::Linear),
+            "null" => Ok(Self::Null),
+            other => Err(DataFusionError::Plan(format!(
+                "unknown gap_fill strategy {other}, expected locf, linear or null"
+            ))),
+        }
+    }
+}
+
+/// Fills the null values of a series ordered by time
+#[allow(clippy::cast_precision_loss)]
+pub fn fill_gaps(
+    times: &[i64],
+    values: &[Option<f64>],
+    strategy: GapFillStrategy,
+) -> Vec<Option<f64>> {
+    match strategy {
+        GapFillStrategy::Null => values.to_vec(),
+        GapFillStrategy::Locf => {
+            let mut last = None;
+            values
+                .iter()
+                .map(|value| {
+                    if value.is_some() {
+                        last = *value;
+                    }
+                    last
+                })
+                .collect()
+        }
+        GapFillStrategy::Linear => {
+            let mut filled = values.to_vec();
+            let mut previous: Option<(usize, f64)> = None;
+            for index in 0..values.len() {
+                let Some(value) = values[index] else {
+                    continue;
+                };
+                if let Some((previous, previous_value)) = previous {
+                    let time_span = (times[index] - times[previous]) as f64;
+                    for (gap, filled_value) in
+                        filled.iter_mut().enumerate().take(index).skip(previous + 1)
+                    {
+                        let ratio = if time_span > 0.0 {
+                            (times[gap] - times[previous]) as f64 / time_span
+                        } else {
+                            0.0
+                        };
+                        *filled_value = Some(previous_value + (value - previous_value) * ratio);
+                    }
+                }
+                previous = Some((index, value));
+            }
+            // gaps before the first and after the last observation can't be interpolated
+            filled
+        }
+    }
+}
+
+/// `gap_fill(time_bucket, value, strategy) OVER (ORDER BY time_bucket)`
+///
+/// Missing buckets have to be present as rows with a null value,
+/// which is what a left join on `time_buckets` provides.
+#[derive(Debug)]
+struct GapFill {
+    signature: Signature,
+}
+
+impl WindowUDFImpl for GapFill {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "gap_fill"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn partition_evaluator(&self) -> Result<Box<dyn PartitionEvaluator>> {
+        Ok(Box::new(GapFillEvaluator {}))
+    }
+}
+
+#[derive(Debug)]
+struct GapFillEvaluator {}
+
+impl PartitionEvaluator for GapFillEvaluator {
+    fn evaluate_all(&mut self, values: &[ArrayRef], num_rows: usize) -> Result<ArrayRef> {
+        if values.len() != 3 {
+            return Err(DataFusionError::Plan(
+                "gap_fill expects 3 arguments: time_bucket, value, strategy".into(),
+            ));
+        }
+        let times = cast(&values[0], &DataType::Int64)?;
+        let times = times.as_primitive::<Int64Type>();
+        let series = cast(&values[1], &DataType::Float64)?;
+        let series = series.as_primitive::<Float64Type>();
+        let strategies = cast(&values[2], &DataType::Utf8)?;
+        let strategies = strategies.as_string::<i32>();
+        if num_rows == 0 {
+            return Ok(Arc::new(Float64Array::from(Vec::<f64>::new())));
+        }
+        let strategy = GapFillStrategy::from_str(strategies.value(0))?;
+        let times: Vec<i64> = times.iter().map(Option::unwrap_or_default).collect();
+        let series: Vec<Option<f64>> = series.iter().collect();
+        let filled = fill_gaps(&times, &series, strategy);
+        Ok(Arc::new(Float64Array::from(filled)))
+    }
+}
+
+pub fn gap_fill_udwf() -> WindowUDF {
+    WindowUDF::new_from_impl(GapFill {
+        signature: Signature::any(3, Volatility::Immutable),
+    })
+}
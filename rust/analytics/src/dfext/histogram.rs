@@ -0,0 +1,424 @@
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{
+    Array, ArrayRef, AsArray, Float64Array, ListArray, StringArray, StructArray, UInt64Array,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Fields, Float64Type, UInt64Type};
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+/// Bins allocated for each histogram, the bin count comes from the queries of the users
+pub const MAX_HISTOGRAM_BINS: usize = 10_000;
+
+/// How the values are distributed among the bins of a histogram
+///
+/// Values under the start of the range are counted in the first bin,
+/// values past the end are counted in the last one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HistogramLayout {
+    /// bins of equal width between start and end
+    Linear { start: f64, end: f64 },
+    /// bin i covers [start * growth_factor^i, start * growth_factor^(i+1)),
+    /// suited to latencies spanning several orders of magnitude
+    Exponential { start: f64, growth_factor: f64 },
+}
+
+/// Layout family, the parameters being supplied separately as SQL arguments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutKind {
+    Linear,
+    Exponential,
+}
+
+impl LayoutKind {
+    /// second_param is the end of the range for linear layouts, the growth factor for exponential ones
+    pub fn layout(self, start: f64, second_param: f64) -> HistogramLayout {
+        match self {
+            LayoutKind::Linear => HistogramLayout::Linear {
+                start,
+                end: second_param,
+            },
+            LayoutKind::Exponential => HistogramLayout::Exponential {
+                start,
+                growth_factor: second_param,
+            },
+        }
+    }
+}
+
+impl HistogramLayout {
+    pub fn validate(&self, nb_bins: usize) -> Result<()> {
+        if nb_bins == 0 {
+            anyhow::bail!("histogram needs at least one bin");
+        }
+        if nb_bins > MAX_HISTOGRAM_BINS {
+            anyhow::bail!("histogram has {nb_bins} bins, the maximum is {MAX_HISTOGRAM_BINS}");
+        }
+        match *self {
+            HistogramLayout::Linear { start, end } => {
+                if start.is_nan() || end.is_nan() || start >= end {
+                    anyhow::bail!("linear histogram range is empty: [{start}, {end})");
+                }
+            }
+            HistogramLayout::Exponential {
+                start,
+                growth_factor,
+            } => {
+                if start.is_nan() || start <= 0.0 {
+                    anyhow::bail!("exponential histogram start must be positive: {start}");
+                }
+                if growth_factor.is_nan() || growth_factor <= 1.0 {
+                    anyhow::bail!(
+                        "exponential histogram growth factor must be greater than 1: {growth_factor}"
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            HistogramLayout::Linear { .. } => "linear",
+            HistogramLayout::Exponential { .. } => "exponential",
+        }
+    }
+
+    /// lower bound of a bin, with bin == nb_bins giving the upper bound of the last bin
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    pub fn bin_lower_bound(&self, nb_bins: usize, bin: usize) -> f64 {
+        match *self {
+            HistogramLayout::Linear { start, end } => {
+                start + (end - start) * bin as f64 / nb_bins as f64
+            }
+            HistogramLayout::Exponential {
+                start,
+                growth_factor,
+            } => start * growth_factor.powi(bin as i32),
+        }
+    }
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn bin_index(&self, nb_bins: usize, value: f64) -> usize {
+        let position = match *self {
+            HistogramLayout::Linear { start, end } => {
+                (value - start) / (end - start) * nb_bins as f64
+            }
+            HistogramLayout::Exponential {
+                start,
+                growth_factor,
+            } => {
+                if value <= start {
+                    0.0
+                } else {
+                    (value / start).ln() / growth_factor.ln()
+                }
+            }
+        };
+        if position.is_nan() || position < 0.0 {
+            0
+        } else {
+            (position.floor() as usize).min(nb_bins - 1)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub layout: HistogramLayout,
+    pub bins: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Histogram {
+    pub fn new(layout: HistogramLayout, nb_bins: usize) -> Result<Self> {
+        layout.validate(nb_bins)?;
+        Ok(Self {
+            layout,
+            bins: vec![0; nb_bins],
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        })
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        let index = self.layout.bin_index(self.bins.len(), value);
+        self.bins[index] += 1;
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) -> Result<()> {
+        if self.layout != other.layout || self.bins.len() != other.bins.len() {
+            anyhow::bail!(
+                "can't merge histograms with different layouts: {:?}x{} and {:?}x{}",
+                self.layout,
+                self.bins.len(),
+                other.layout,
+                other.bins.len()
+            );
+        }
+        for (bin, other_bin) in self.bins.iter_mut().zip(&other.bins) {
+            *bin += other_bin;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        Ok(())
+    }
+
+    /// bounds of a bin narrowed to the observed values
+    fn bin_bounds(&self, bin: usize) -> (f64, f64) {
+        let nb_bins = self.bins.len();
+        let mut lower = self.layout.bin_lower_bound(nb_bins, bin);
+        let mut upper = self.layout.bin_lower_bound(nb_bins, bin + 1);
+        if bin == 0 {
+            lower = self.min;
+        }
+        if bin == nb_bins - 1 {
+            upper = self.max;
+        }
+        (lower.max(self.min), upper.min(self.max))
+    }
+
+    /// Estimates the value at quantile q, assuming values are spread uniformly within each bin
+    #[allow(clippy::cast_precision_loss)]
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 || !(0.0..=1.0).contains(&q) {
+            return None;
+        }
+        let target = q * self.count as f64;
+        let mut cumulative = 0.0;
+        for (index, bin) in self.bins.iter().enumerate() {
+            if *bin == 0 {
+                continue;
+            }
+            let next_cumulative = cumulative + *bin as f64;
+            if next_cumulative >= target {
+                let (lower, upper) = self.bin_bounds(index);
+                let ratio = (target - cumulative) / *bin as f64;
+                return Some(lower + (upper - lower) * ratio);
+            }
+            cumulative = next_cumulative;
+        }
+        Some(self.max)
+    }
+
+    /// Redistributes the counts in a different layout, assuming values are spread uniformly
+    /// within each bin. Total count, sum, min and max are preserved.
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn convert(&self, layout: HistogramLayout, nb_bins: usize) -> Result<Histogram> {
+        let mut converted = Histogram::new(layout, nb_bins)?;
+        converted.count = self.count;
+        converted.sum = self.sum;
+        converted.min = self.min;
+        converted.max = self.max;
+        let mut fractional_bins = vec![0.0; nb_bins];
+        for (index, bin) in self.bins.iter().enumerate() {
+            if *bin == 0 {
+                continue;
+            }
+            let (lower, upper) = self.bin_bounds(index);
+            let first = layout.bin_index(nb_bins, lower);
+            let last = layout.bin_index(nb_bins, upper);
+            if first == last || upper <= lower {
+                fractional_bins[first] += *bin as f64;
+                continue;
+            }
+            for (dest, fractional_bin) in fractional_bins
+                .iter_mut()
+                .enumerate()
+                .take(last + 1)
+                .skip(first)
+            {
+                let dest_lower = if dest == first {
+                    lower
+                } else {
+                    layout.bin_lower_bound(nb_bins, dest)
+                };
+                let dest_upper = if dest == last {
+                    upper
+                } else {
+                    layout.bin_lower_bound(nb_bins, dest + 1)
+                };
+                *fractional_bin += *bin as f64 * (dest_upper - dest_lower) / (upper - lower);
+            }
+        }
+        // cumulative rounding keeps the total count exact
+        let mut cumulative = 0.0;
+        let mut assigned = 0;
+        for (dest, fractional_bin) in fractional_bins.iter().enumerate() {
+            cumulative += fractional_bin;
+            let rounded = (cumulative.round() as u64).min(self.count);
+            converted.bins[dest] = rounded - assigned;
+            assigned = rounded;
+        }
+        if let Some(last) = converted.bins.last_mut() {
+            *last += self.count - assigned;
+        }
+        Ok(converted)
+    }
+}
+
+pub fn histogram_data_type() -> DataType {
+    DataType::Struct(histogram_fields())
+}
+
+fn histogram_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("layout", DataType::Utf8, false),
+        Field::new("start", DataType::Float64, false),
+        Field::new("end", DataType::Float64, false),
+        Field::new("growth_factor", DataType::Float64, false),
+        Field::new("count", DataType::UInt64, false),
+        Field::new("sum", DataType::Float64, false),
+        Field::new("min", DataType::Float64, false),
+        Field::new("max", DataType::Float64, false),
+        Field::new(
+            "bins",
+            DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))),
+            false,
+        ),
+    ])
+}
+
+/// Builds an array of histograms, None entries being null
+pub fn histograms_to_array(histograms: &[Option<Histogram>]) -> Result<StructArray> {
+    let mut layouts = vec![];
+    let mut starts = vec![];
+    let mut ends = vec![];
+    let mut growth_factors = vec![];
+    let mut counts = vec![];
+    let mut sums = vec![];
+    let mut mins = vec![];
+    let mut maxs = vec![];
+    let mut bins = vec![];
+    for histogram in histograms {
+        if let Some(histogram) = histogram {
+            let (start, growth_factor) = match histogram.layout {
+                HistogramLayout::Linear { start, .. } => (start, 1.0),
+                HistogramLayout::Exponential {
+                    start,
+                    growth_factor,
+                } => (start, growth_factor),
+            };
+            layouts.push(histogram.layout.name());
+            starts.push(start);
+            ends.push(
+                histogram
+                    .layout
+                    .bin_lower_bound(histogram.bins.len(), histogram.bins.len()),
+            );
+            growth_factors.push(growth_factor);
+            counts.push(histogram.count);
+            sums.push(histogram.sum);
+            mins.push(histogram.min);
+            maxs.push(histogram.max);
+            bins.push(Some(
+                histogram.bins.iter().map(|b| Some(*b)).collect::<Vec<_>>(),
+            ));
+        } else {
+            layouts.push("");
+            starts.push(0.0);
+            ends.push(0.0);
+            growth_factors.push(0.0);
+            counts.push(0);
+            sums.push(0.0);
+            mins.push(0.0);
+            maxs.push(0.0);
+            bins.push(Some(vec![]));
+        }
+    }
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(layouts)),
+        Arc::new(Float64Array::from(starts)),
+        Arc::new(Float64Array::from(ends)),
+        Arc::new(Float64Array::from(growth_factors)),
+        Arc::new(UInt64Array::from(counts)),
+        Arc::new(Float64Array::from(sums)),
+        Arc::new(Float64Array::from(mins)),
+        Arc::new(Float64Array::from(maxs)),
+        Arc::new(ListArray::from_iter_primitive::<UInt64Type, _, _>(bins)),
+    ];
+    let nulls = histograms.iter().map(Option::is_some).collect::<Vec<_>>();
+    StructArray::try_new(histogram_fields(), columns, Some(nulls.into()))
+        .with_context(|| "building histogram array")
+}
+
+pub fn histogram_to_scalar(histogram: Option<Histogram>) -> Result<ScalarValue> {
+    Ok(ScalarValue::Struct(Arc::new(histograms_to_array(&[
+        histogram,
+    ])?)))
+}
+
+/// Reads the histogram at an index of an array built by `histograms_to_array`
+pub fn histogram_from_array(array: &StructArray, index: usize) -> Result<Option<Histogram>> {
+    if array.is_null(index) {
+        return Ok(None);
+    }
+    let column = |name: &str| {
+        array
+            .column_by_name(name)
+            .with_context(|| format!("histogram column {name} missing"))
+    };
+    let float = |name: &str| -> Result<f64> {
+        Ok(column(name)?
+            .as_primitive_opt::<Float64Type>()
+            .with_context(|| format!("histogram column {name} should be float64"))?
+            .value(index))
+    };
+    let layout_name = column("layout")?
+        .as_string_opt::<i32>()
+        .with_context(|| "histogram layout should be a string")?
+        .value(index);
+    let start = float("start")?;
+    let layout = match layout_name {
+        "linear" => HistogramLayout::Linear {
+            start,
+            end: float("end")?,
+        },
+        "exponential" => HistogramLayout::Exponential {
+            start,
+            growth_factor: float("growth_factor")?,
+        },
+        other => anyhow::bail!("unknown histogram layout {other}"),
+    };
+    let bins_list = column("bins")?
+        .as_list_opt::<i32>()
+        .with_context(|| "histogram bins should be a list")?
+        .value(index);
+    let bins = bins_list
+        .as_primitive_opt::<UInt64Type>()
+        .with_context(|| "histogram bins should be uint64")?
+        .values()
+        .to_vec();
+    Ok(Some(Histogram {
+        layout,
+        bins,
+        count: column("count")?
+            .as_primitive_opt::<UInt64Type>()
+            .with_context(|| "histogram count should be uint64")?
+            .value(index),
+        sum: float("sum")?,
+        min: float("min")?,
+        max: float("max")?,
+    }))
+}
@@ -0,0 +1,201 @@
+use super::histogram::{
+    histogram_data_type, histogram_from_array, histogram_to_scalar, Histogram, LayoutKind,
+};
+use super::to_datafusion_error;
+use datafusion::arrow::array::{Array, ArrayRef, AsArray};
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::error::Result;
+use datafusion::logical_expr::{
+    Accumulator, AggregateUDF, AggregateUDFImpl, Signature, Volatility,
+};
+use datafusion::scalar::ScalarValue;
+use std::any::Any;
+
+/// Aggregates values into a histogram whose layout is given by the first three arguments
+#[derive(Debug)]
+struct MakeHistogram {
+    name: &'static str,
+    kind: LayoutKind,
+    signature: Signature,
+}
+
+impl MakeHistogram {
+    fn new(name: &'static str, kind: LayoutKind) -> Self {
+        Self {
+            name,
+            kind,
+            signature: Signature::exact(
+                vec![
+                    DataType::Float64,
+                    DataType::Float64,
+                    DataType::Int64,
+                    DataType::Float64,
+                ],
+                Volatility::Immutable,
+            ),
+        }
+    }
+}
+
+impl AggregateUDFImpl for MakeHistogram {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(histogram_data_type())
+    }
+
+    fn accumulator(&self, _arg: &DataType) -> Result<Box<dyn Accumulator>> {
+        Ok(Box::new(HistogramAccumulator {
+            kind: Some(self.kind),
+            histogram: None,
+        }))
+    }
+
+    fn state_type(&self, _return_type: &DataType) -> Result<Vec<DataType>> {
+        Ok(vec![histogram_data_type()])
+    }
+}
+
+/// Merges histograms sharing the same layout
+#[derive(Debug)]
+struct SumHistograms {
+    signature: Signature,
+}
+
+impl AggregateUDFImpl for SumHistograms {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "sum_histograms"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(histogram_data_type())
+    }
+
+    fn accumulator(&self, _arg: &DataType) -> Result<Box<dyn Accumulator>> {
+        Ok(Box::new(HistogramAccumulator {
+            kind: None,
+            histogram: None,
+        }))
+    }
+
+    fn state_type(&self, _return_type: &DataType) -> Result<Vec<DataType>> {
+        Ok(vec![histogram_data_type()])
+    }
+}
+
+#[derive(Debug)]
+struct HistogramAccumulator {
+    // None when the inputs are already histograms
+    kind: Option<LayoutKind>,
+    histogram: Option<Histogram>,
+}
+
+impl HistogramAccumulator {
+    fn merge_histograms(&mut self, histograms: &ArrayRef) -> Result<()> {
+        let histograms = histograms.as_struct();
+        for index in 0..histograms.len() {
+            let Some(other) =
+                histogram_from_array(histograms, index).map_err(to_datafusion_error)?
+            else {
+                continue;
+            };
+            if let Some(histogram) = &mut self.histogram {
+                histogram.merge(&other).map_err(to_datafusion_error)?;
+            } else {
+                self.histogram = Some(other);
+            }
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn insert_values(&mut self, kind: LayoutKind, columns: &[ArrayRef]) -> Result<()> {
+        let first_param = columns[0].as_primitive::<Float64Type>();
+        let second_param = columns[1].as_primitive::<Float64Type>();
+        let nb_bins = columns[2].as_primitive::<Int64Type>();
+        let values = columns[3].as_primitive::<Float64Type>();
+        for index in 0..values.len() {
+            if values.is_null(index) {
+                continue;
+            }
+            if self.histogram.is_none() {
+                let layout = kind.layout(first_param.value(index), second_param.value(index));
+                let nb_bins = nb_bins.value(index).max(0) as usize;
+                self.histogram =
+                    Some(Histogram::new(layout, nb_bins).map_err(to_datafusion_error)?);
+            }
+            if let Some(histogram) = &mut self.histogram {
+                histogram.insert(values.value(index));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Accumulator for HistogramAccumulator {
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        match self.kind {
+            Some(kind) => self.insert_values(kind, values),
+            None => self.merge_histograms(&values[0]),
+        }
+    }
+
+    fn evaluate(&mut self) -> Result<ScalarValue> {
+        histogram_to_scalar(self.histogram.clone()).map_err(to_datafusion_error)
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self)
+            + self
+                .histogram
+                .as_ref()
+                .map(|h| h.bins.capacity() * std::mem::size_of::<u64>())
+                .unwrap_or_default()
+    }
+
+    fn state(&mut self) -> Result<Vec<ScalarValue>> {
+        Ok(vec![self.evaluate()?])
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        self.merge_histograms(&states[0])
+    }
+}
+
+/// `make_histogram(start, end, nb_bins, value)`: bins of equal width
+pub fn make_histogram_udaf() -> AggregateUDF {
+    AggregateUDF::new_from_impl(MakeHistogram::new("make_histogram", LayoutKind::Linear))
+}
+
+/// `make_exponential_histogram(start, growth_factor, nb_bins, value)`: bin widths growing geometrically
+pub fn make_exponential_histogram_udaf() -> AggregateUDF {
+    AggregateUDF::new_from_impl(MakeHistogram::new(
+        "make_exponential_histogram",
+        LayoutKind::Exponential,
+    ))
+}
+
+/// `sum_histograms(histogram)`
+pub fn sum_histograms_udaf() -> AggregateUDF {
+    AggregateUDF::new_from_impl(SumHistograms {
+        signature: Signature::any(1, Volatility::Immutable),
+    })
+}
@@ -0,0 +1,139 @@
+use super::histogram::{
+    histogram_data_type, histogram_from_array, histograms_to_array, Histogram, LayoutKind,
+};
+use super::to_datafusion_error;
+use datafusion::arrow::array::{Array, AsArray, Float64Array};
+use datafusion::arrow::datatypes::{DataType, Float64Type, Int64Type};
+use datafusion::error::Result;
+use datafusion::logical_expr::{
+    ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, TypeSignature, Volatility,
+};
+use std::any::Any;
+use std::sync::Arc;
+
+fn histogram_signature(extra_args: Vec<DataType>) -> Signature {
+    // the histogram struct is matched by position, the other arguments are coerced
+    let mut args = vec![histogram_data_type()];
+    args.extend(extra_args);
+    Signature::one_of(vec![TypeSignature::Exact(args)], Volatility::Immutable)
+}
+
+/// `quantile_from_histogram(histogram, q)`
+#[derive(Debug)]
+struct QuantileFromHistogram {
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for QuantileFromHistogram {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "quantile_from_histogram"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Float64)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let histograms = arrays[0].as_struct();
+        let quantiles = arrays[1].as_primitive::<Float64Type>();
+        let mut results = Vec::with_capacity(histograms.len());
+        for index in 0..histograms.len() {
+            let histogram = histogram_from_array(histograms, index).map_err(to_datafusion_error)?;
+            results.push(match histogram {
+                Some(histogram) if !quantiles.is_null(index) => {
+                    histogram.quantile(quantiles.value(index))
+                }
+                _ => None,
+            });
+        }
+        Ok(ColumnarValue::Array(Arc::new(Float64Array::from(results))))
+    }
+}
+
+/// Converts histograms to another layout
+#[derive(Debug)]
+struct ConvertHistogram {
+    name: &'static str,
+    kind: LayoutKind,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for ConvertHistogram {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(histogram_data_type())
+    }
+
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let histograms = arrays[0].as_struct();
+        let first_param = arrays[1].as_primitive::<Float64Type>();
+        let second_param = arrays[2].as_primitive::<Float64Type>();
+        let nb_bins = arrays[3].as_primitive::<Int64Type>();
+        let mut results: Vec<Option<Histogram>> = Vec::with_capacity(histograms.len());
+        for index in 0..histograms.len() {
+            let Some(histogram) =
+                histogram_from_array(histograms, index).map_err(to_datafusion_error)?
+            else {
+                results.push(None);
+                continue;
+            };
+            let layout = self
+                .kind
+                .layout(first_param.value(index), second_param.value(index));
+            let nb_bins = nb_bins.value(index).max(0) as usize;
+            results.push(Some(
+                histogram
+                    .convert(layout, nb_bins)
+                    .map_err(to_datafusion_error)?,
+            ));
+        }
+        let array = histograms_to_array(&results).map_err(to_datafusion_error)?;
+        Ok(ColumnarValue::Array(Arc::new(array)))
+    }
+}
+
+pub fn quantile_from_histogram_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(QuantileFromHistogram {
+        signature: histogram_signature(vec![DataType::Float64]),
+    })
+}
+
+/// `histogram_to_linear(histogram, start, end, nb_bins)`
+pub fn histogram_to_linear_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(ConvertHistogram {
+        name: "histogram_to_linear",
+        kind: LayoutKind::Linear,
+        signature: histogram_signature(vec![DataType::Float64, DataType::Float64, DataType::Int64]),
+    })
+}
+
+/// `histogram_to_exponential(histogram, start, growth_factor, nb_bins)`
+pub fn histogram_to_exponential_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(ConvertHistogram {
+        name: "histogram_to_exponential",
+        kind: LayoutKind::Exponential,
+        signature: histogram_signature(vec![DataType::Float64, DataType::Float64, DataType::Int64]),
+    })
+}
@@ -0,0 +1,109 @@
+use datafusion::arrow::array::{AsArray, StringArray};
+use datafusion::arrow::datatypes::{DataType, Float64Type};
+use datafusion::error::Result;
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use std::any::Any;
+use std::sync::Arc;
+
+/// Three significant digits, without trailing zeros
+fn format_significant(value: f64) -> String {
+    let abs = value.abs();
+    let text = if abs >= 100.0 {
+        format!("{value:.0}")
+    } else if abs >= 10.0 {
+        format!("{value:.1}")
+    } else {
+        format!("{value:.2}")
+    };
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_owned()
+    } else {
+        text
+    }
+}
+
+const DURATION_UNITS: [(f64, &str); 7] = [
+    (86_400_000_000_000.0, "d"),
+    (3_600_000_000_000.0, "h"),
+    (60_000_000_000.0, "min"),
+    (1_000_000_000.0, "s"),
+    (1_000_000.0, "ms"),
+    (1_000.0, "µs"),
+    (1.0, "ns"),
+];
+
+/// Humanized duration, i.e. `1.24 s`
+pub fn format_duration(nanoseconds: f64) -> String {
+    let abs = nanoseconds.abs();
+    let (scale, unit) = DURATION_UNITS
+        .iter()
+        .find(|(scale, _unit)| abs >= *scale)
+        .unwrap_or(&DURATION_UNITS[DURATION_UNITS.len() - 1]);
+    format!("{} {unit}", format_significant(nanoseconds / scale))
+}
+
+const BYTE_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Humanized size with binary prefixes, i.e. `3.1 GiB`
+pub fn format_bytes(bytes: f64) -> String {
+    let mut value = bytes;
+    let mut unit_index = 0;
+    while value.abs() >= 1024.0 && unit_index < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit_index += 1;
+    }
+    if unit_index == 0 {
+        format!("{value:.0} B")
+    } else {
+        format!("{} {}", format_significant(value), BYTE_UNITS[unit_index])
+    }
+}
+
+/// `format_duration(nanoseconds)` and `format_bytes(n)`
+#[derive(Debug)]
+struct FormatNumber {
+    name: &'static str,
+    format: fn(f64) -> String,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for FormatNumber {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let values = arrays[0].as_primitive::<Float64Type>();
+        let results: StringArray = values.iter().map(|value| value.map(self.format)).collect();
+        Ok(ColumnarValue::Array(Arc::new(results)))
+    }
+}
+
+fn make_format_udf(name: &'static str, format: fn(f64) -> String) -> ScalarUDF {
+    ScalarUDF::new_from_impl(FormatNumber {
+        name,
+        format,
+        signature: Signature::uniform(1, vec![DataType::Float64], Volatility::Immutable),
+    })
+}
+
+pub fn format_duration_udf() -> ScalarUDF {
+    make_format_udf("format_duration", format_duration)
+}
+
+pub fn format_bytes_udf() -> ScalarUDF {
+    make_format_udf("format_bytes", format_bytes)
+}
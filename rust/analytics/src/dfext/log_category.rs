@@ -0,0 +1,65 @@
+use datafusion::arrow::array::{AsArray, StringArray};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::error::Result;
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use std::any::Any;
+use std::sync::Arc;
+
+/// Category of an Unreal-style message, i.e. `LogNet` in `[LogNet] connection lost`
+///
+/// The first bracketed identifier is the category: the timestamps and frame numbers
+/// that precede it in Unreal log lines, like `[2024.05.01-10.00.00:000][  0]`, are skipped.
+pub fn log_category(msg: &str) -> Option<&str> {
+    let mut rest = msg;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let close = rest.find(']')?;
+        let candidate = &rest[..close];
+        if candidate.starts_with(|c: char| c.is_ascii_alphabetic())
+            && candidate
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Some(candidate);
+        }
+        rest = &rest[close + 1..];
+    }
+    None
+}
+
+/// `log_category(msg)`
+#[derive(Debug)]
+struct LogCategory {
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for LogCategory {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "log_category"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let msgs = arrays[0].as_string::<i32>();
+        let results: StringArray = msgs.iter().map(|msg| msg.and_then(log_category)).collect();
+        Ok(ColumnarValue::Array(Arc::new(results)))
+    }
+}
+
+pub fn log_category_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(LogCategory {
+        signature: Signature::uniform(1, vec![DataType::Utf8], Volatility::Immutable),
+    })
+}
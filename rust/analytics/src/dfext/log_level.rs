@@ -0,0 +1,101 @@
+use datafusion::arrow::array::{AsArray, Int32Array, StringArray};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Int64Type};
+use datafusion::error::Result;
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use micromegas_tracing::levels::Level;
+use std::any::Any;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Name of a level of the `level` column of log entries, i.e. `WARN` for 3
+pub fn level_name(level: i64) -> Option<&'static str> {
+    let level = Level::from_value(u32::try_from(level).ok()?)?;
+    Some(level.as_str())
+}
+
+/// Value of a level in the `level` column of log entries, i.e. 3 for `warn`, ignoring the case
+pub fn level_value(name: &str) -> Option<i32> {
+    Level::from_str(name).ok().map(|level| level as i32)
+}
+
+/// `level_name(level)`
+#[derive(Debug)]
+struct LevelName {
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for LevelName {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "level_name"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Utf8)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let levels = cast(&arrays[0], &DataType::Int64)?;
+        let results: StringArray = levels
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|level| level.and_then(level_name))
+            .collect();
+        Ok(ColumnarValue::Array(Arc::new(results)))
+    }
+}
+
+/// `level_value(name)`
+#[derive(Debug)]
+struct LevelValue {
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for LevelValue {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        "level_value"
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(DataType::Int32)
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let names = arrays[0].as_string::<i32>();
+        let results: Int32Array = names
+            .iter()
+            .map(|name| name.and_then(level_value))
+            .collect();
+        Ok(ColumnarValue::Array(Arc::new(results)))
+    }
+}
+
+pub fn level_name_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(LevelName {
+        signature: Signature::any(1, Volatility::Immutable),
+    })
+}
+
+pub fn level_value_udf() -> ScalarUDF {
+    ScalarUDF::new_from_impl(LevelValue {
+        signature: Signature::uniform(1, vec![DataType::Utf8], Volatility::Immutable),
+    })
+}
@@ -0,0 +1,73 @@
+//! dfext: extensions to datafusion, registered in the session contexts used to query the data lake
+
+/// Window functions detecting anomalies in time series: z-score, median absolute deviation, seasonal
+pub mod anomalies;
+/// Table function verifying the checksums of the payloads of the blocks of a process
+pub mod block_checksums;
+/// Table function listing the blocks of a process with pre-signed urls to their payloads
+pub mod block_payload_urls;
+/// Parameterized queries for common investigations, as table functions
+pub mod canned_queries;
+/// Table function documenting the columns of the views, with example queries
+pub mod describe_view;
+/// Table function reducing a time series to a budget of points, preserving its shape
+pub mod downsample;
+/// Table function assigning point events to the innermost span containing them
+pub mod events_within_spans;
+/// Table function comparing the time spent in spans per frame to the budgets of the process
+pub mod frame_budgets;
+/// Fills the gaps of time series: last observation carried forward or linear interpolation
+pub mod gap_fill;
+/// Histogram representation with linear and exponential bucket layouts
+pub mod histogram;
+/// Aggregate functions building and merging histograms
+pub mod histogram_udaf;
+/// Scalar functions reading and converting histograms
+pub mod histogram_udf;
+/// Human readable durations and sizes
+pub mod humanize;
+/// Categories embedded in log messages, i.e. `[LogNet]`
+pub mod log_category;
+/// Names of the log levels and back, i.e. `WARN` for 3
+pub mod log_level;
+/// Table function sampling the blocks of a thread before reading their spans
+pub mod sample_spans;
+/// Table function counting spans per time bucket and duration bin, for heatmaps
+pub mod span_heatmap;
+/// Depth and ancestors of spans, looked up in their call trees
+pub mod span_hierarchy;
+/// Table function describing the streams of a process and the layout of their events
+pub mod stream_metadata;
+/// Table function generating regular time buckets
+pub mod time_buckets;
+
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SessionContext;
+use std::sync::Arc;
+
+pub(crate) fn to_datafusion_error(e: anyhow::Error) -> DataFusionError {
+    DataFusionError::External(e.into())
+}
+
+/// Makes the micromegas functions available to sql queries
+pub fn register_extension_functions(ctx: &SessionContext) {
+    ctx.register_udaf(histogram_udaf::make_histogram_udaf());
+    ctx.register_udaf(histogram_udaf::make_exponential_histogram_udaf());
+    ctx.register_udaf(histogram_udaf::sum_histograms_udaf());
+    ctx.register_udf(histogram_udf::quantile_from_histogram_udf());
+    ctx.register_udf(histogram_udf::histogram_to_linear_udf());
+    ctx.register_udf(histogram_udf::histogram_to_exponential_udf());
+    ctx.register_udf(humanize::format_duration_udf());
+    ctx.register_udf(humanize::format_bytes_udf());
+    ctx.register_udf(log_category::log_category_udf());
+    ctx.register_udf(log_level::level_name_udf());
+    ctx.register_udf(log_level::level_value_udf());
+    ctx.register_udwf(gap_fill::gap_fill_udwf());
+    ctx.register_udwf(anomalies::zscore_anomaly_udwf());
+    ctx.register_udwf(anomalies::mad_anomaly_udwf());
+    ctx.register_udwf(anomalies::seasonal_anomaly_udwf());
+    ctx.register_udtf("time_buckets", Arc::new(time_buckets::TimeBuckets {}));
+    ctx.register_udtf("downsample", Arc::new(downsample::Downsample {}));
+    ctx.register_udtf("describe_view", Arc::new(describe_view::DescribeView {}));
+    ctx.register_udtf("span_heatmap", Arc::new(span_heatmap::SpanHeatmap {}));
+}
@@ -0,0 +1,168 @@
+use super::to_datafusion_error;
+use crate::query_timeout::QueryDeadline;
+use crate::sample_spans::{sample_spans, SampleSize, SamplingStrategy};
+use crate::span_table::spans_schema;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use std::any::Any;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn time_arg(expr: &Expr, name: &str) -> Result<DateTime<Utc>> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(text))) => DateTime::parse_from_rfc3339(text)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|e| DataFusionError::Plan(format!("sample_spans: invalid {name}: {e}"))),
+        Expr::Literal(ScalarValue::TimestampNanosecond(Some(nanos), _)) => {
+            Ok(DateTime::from_timestamp_nanos(*nanos))
+        }
+        other => Err(DataFusionError::Plan(format!(
+            "sample_spans: {name} should be a rfc3339 string or a timestamp, found {other}"
+        ))),
+    }
+}
+
+fn size_arg(expr: &Expr) -> Result<SampleSize> {
+    match expr {
+        Expr::Literal(ScalarValue::Float64(Some(fraction))) if (0.0..=1.0).contains(fraction) => {
+            Ok(SampleSize::Fraction(*fraction))
+        }
+        Expr::Literal(ScalarValue::Int64(Some(max_rows))) if *max_rows >= 0 => {
+            Ok(SampleSize::MaxRows(*max_rows))
+        }
+        other => Err(DataFusionError::Plan(format!(
+            "sample_spans: size should be a fraction between 0 and 1 or a number of rows, found {other}"
+        ))),
+    }
+}
+
+/// `sample_spans(stream_id, begin, end, size [, strategy])`: statistical subsample of the spans
+/// of a thread
+///
+/// `size` is either the fraction of the blocks to read, i.e. `0.1`, or the approximate number of
+/// spans to return, i.e. `10000`. `strategy` is `uniform` (the default) or `random`,
+/// see `crate::sample_spans`.
+#[derive(Debug)]
+pub struct SampleSpans {
+    data_lake: DataLakeConnection,
+}
+
+impl SampleSpans {
+    pub fn new(data_lake: DataLakeConnection) -> Self {
+        Self { data_lake }
+    }
+}
+
+impl TableFunctionImpl for SampleSpans {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let (stream_id, begin, end, size, strategy) = match args {
+            [stream_id, begin, end, size] => (stream_id, begin, end, size, None),
+            [stream_id, begin, end, size, strategy] => {
+                (stream_id, begin, end, size, Some(strategy))
+            }
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "sample_spans expects stream_id, begin, end, size and an optional strategy"
+                        .into(),
+                ))
+            }
+        };
+        let stream_id = match stream_id {
+            Expr::Literal(ScalarValue::Utf8(Some(text))) => Uuid::parse_str(text).map_err(|e| {
+                DataFusionError::Plan(format!("sample_spans: invalid stream_id: {e}"))
+            })?,
+            other => {
+                return Err(DataFusionError::Plan(format!(
+                    "sample_spans: stream_id should be a string, found {other}"
+                )))
+            }
+        };
+        let strategy = match strategy {
+            None => SamplingStrategy::Uniform,
+            Some(Expr::Literal(ScalarValue::Utf8(Some(text)))) => text
+                .parse()
+                .map_err(|e| DataFusionError::Plan(format!("sample_spans: {e}")))?,
+            Some(other) => {
+                return Err(DataFusionError::Plan(format!(
+                    "sample_spans: strategy should be a string, found {other}"
+                )))
+            }
+        };
+        Ok(Arc::new(SampleSpansTable {
+            data_lake: self.data_lake.clone(),
+            stream_id,
+            begin: time_arg(begin, "begin")?,
+            end: time_arg(end, "end")?,
+            size: size_arg(size)?,
+            strategy,
+            schema: Arc::new(spans_schema()),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct SampleSpansTable {
+    data_lake: DataLakeConnection,
+    stream_id: Uuid,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    size: SampleSize,
+    strategy: SamplingStrategy,
+    schema: SchemaRef,
+}
+
+#[async_trait]
+impl TableProvider for SampleSpansTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        // the timeout of the client applies to the whole statement, see `AnalyticsService::execute_sql`
+        let batch = sample_spans(
+            &self.data_lake,
+            self.stream_id,
+            self.begin,
+            self.end,
+            self.size,
+            self.strategy,
+            &QueryDeadline::unbounded(),
+        )
+        .await
+        .map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `sample_spans` available to the queries of the context
+pub fn register_sample_spans(ctx: &SessionContext, data_lake: DataLakeConnection) {
+    ctx.register_udtf("sample_spans", Arc::new(SampleSpans::new(data_lake)));
+}
@@ -0,0 +1,228 @@
+use super::histogram::HistogramLayout;
+use super::time_buckets::interval_arg;
+use super::to_datafusion_error;
+use crate::sql_session::plan_client_sql;
+use async_trait::async_trait;
+use datafusion::arrow::array::{
+    Array, AsArray, Int64Array, RecordBatch, TimestampNanosecondArray, UInt64Array,
+};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{
+    DataType, Field, Schema, SchemaRef, TimeUnit, TimestampNanosecondType,
+};
+use datafusion::dataframe::DataFrame;
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::SessionState;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[allow(clippy::cast_precision_loss)]
+fn duration_layout(max_duration: i64) -> HistogramLayout {
+    HistogramLayout::Linear {
+        start: 0.0,
+        end: max_duration as f64,
+    }
+}
+
+/// Cells of a heatmap of durations over time: (time bucket, duration bin) -> number of spans
+///
+/// Spans are assigned to the time bucket of their begin, buckets are aligned on multiples of
+/// `interval` since the epoch. Durations over `max_duration` are counted in the last bin.
+/// Empty cells are omitted.
+pub fn duration_heatmap(
+    begins: &[i64],
+    ends: &[i64],
+    interval: i64,
+    max_duration: i64,
+    nb_duration_bins: usize,
+) -> anyhow::Result<BTreeMap<(i64, usize), u64>> {
+    let layout = duration_layout(max_duration);
+    layout.validate(nb_duration_bins)?;
+    let mut cells = BTreeMap::new();
+    for (begin, end) in begins.iter().zip(ends) {
+        let time_bucket = begin.div_euclid(interval) * interval;
+        #[allow(clippy::cast_precision_loss)]
+        let duration_bin = layout.bin_index(nb_duration_bins, (end - begin) as f64);
+        *cells.entry((time_bucket, duration_bin)).or_default() += 1;
+    }
+    Ok(cells)
+}
+
+fn heatmap_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new(
+            "time_bucket",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("duration_bin", DataType::UInt64, false),
+        Field::new("min_duration", DataType::Int64, false),
+        Field::new("max_duration", DataType::Int64, false),
+        Field::new("count", DataType::UInt64, false),
+    ]))
+}
+
+/// `span_heatmap(spans_table, span_name, interval, max_duration, nb_duration_bins)`: number of
+/// spans of that name per time bucket and duration bin, to drive heatmaps without transferring
+/// the spans
+///
+/// The spans table needs the `name`, `begin` and `end` columns of the spans, of one or many
+/// processes. Durations are split in `nb_duration_bins` bins of equal width between 0 and
+/// `max_duration`, an INTERVAL or a number of nanoseconds like `interval`.
+/// `min_duration` and `max_duration` are the bounds of the bin in nanoseconds, the last bin also
+/// counts the longer spans. Only the non-empty cells are returned.
+#[derive(Debug)]
+pub struct SpanHeatmap {}
+
+fn string_arg(expr: &Expr, arg_name: &str) -> Result<String> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(text))) => Ok(text.clone()),
+        other => Err(DataFusionError::Plan(format!(
+            "span_heatmap: {arg_name} should be a string, found {other}"
+        ))),
+    }
+}
+
+impl TableFunctionImpl for SpanHeatmap {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let [spans_table, span_name, interval, max_duration, nb_duration_bins] = args else {
+            return Err(DataFusionError::Plan(
+                "span_heatmap expects 5 arguments: spans_table, span_name, interval, max_duration, nb_duration_bins".into(),
+            ));
+        };
+        let spans_table = string_arg(spans_table, "spans_table")?;
+        if spans_table.contains('"') {
+            return Err(DataFusionError::Plan(format!(
+                "span_heatmap: invalid table name {spans_table}"
+            )));
+        }
+        let nb_duration_bins = match nb_duration_bins {
+            Expr::Literal(ScalarValue::Int64(Some(nb_bins))) if *nb_bins > 0 => {
+                usize::try_from(*nb_bins).map_err(|e| DataFusionError::Plan(e.to_string()))?
+            }
+            other => {
+                return Err(DataFusionError::Plan(format!(
+                    "span_heatmap: nb_duration_bins should be a positive number, found {other}"
+                )))
+            }
+        };
+        Ok(Arc::new(SpanHeatmapTable {
+            spans_table,
+            span_name: string_arg(span_name, "span_name")?,
+            interval: interval_arg(interval, "span_heatmap")?,
+            max_duration: interval_arg(max_duration, "span_heatmap")?,
+            nb_duration_bins,
+            schema: heatmap_schema(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct SpanHeatmapTable {
+    spans_table: String,
+    span_name: String,
+    interval: i64,
+    max_duration: i64,
+    nb_duration_bins: usize,
+    schema: SchemaRef,
+}
+
+impl SpanHeatmapTable {
+    async fn make_batch(&self, state: &SessionState) -> Result<RecordBatch> {
+        let sql = format!(
+            r#"SELECT begin, "end" FROM "{}" WHERE name = '{}';"#,
+            self.spans_table,
+            self.span_name.replace('\'', "''")
+        );
+        let plan = plan_client_sql(state, &sql).await?;
+        let batches = DataFrame::new(state.clone(), plan).collect().await?;
+        let mut begins = vec![];
+        let mut ends = vec![];
+        for batch in batches {
+            let begin_column = cast(
+                batch.column(0),
+                &DataType::Timestamp(TimeUnit::Nanosecond, None),
+            )?;
+            let end_column = cast(
+                batch.column(1),
+                &DataType::Timestamp(TimeUnit::Nanosecond, None),
+            )?;
+            let begin_column = begin_column.as_primitive::<TimestampNanosecondType>();
+            let end_column = end_column.as_primitive::<TimestampNanosecondType>();
+            for row in 0..batch.num_rows() {
+                if begin_column.is_valid(row) && end_column.is_valid(row) {
+                    begins.push(begin_column.value(row));
+                    ends.push(end_column.value(row));
+                }
+            }
+        }
+        let cells = duration_heatmap(
+            &begins,
+            &ends,
+            self.interval,
+            self.max_duration,
+            self.nb_duration_bins,
+        )
+        .map_err(to_datafusion_error)?;
+        let layout = duration_layout(self.max_duration);
+        #[allow(clippy::cast_possible_truncation)]
+        let bin_bound =
+            |bin: usize| layout.bin_lower_bound(self.nb_duration_bins, bin).round() as i64;
+        let time_buckets: TimestampNanosecondArray = cells
+            .keys()
+            .map(|(time, _)| *time)
+            .collect::<Vec<_>>()
+            .into();
+        let duration_bins: UInt64Array = cells.keys().map(|(_, bin)| *bin as u64).collect();
+        let min_durations: Int64Array = cells.keys().map(|(_, bin)| bin_bound(*bin)).collect();
+        let max_durations: Int64Array = cells.keys().map(|(_, bin)| bin_bound(bin + 1)).collect();
+        let counts: UInt64Array = cells.values().copied().collect();
+        Ok(RecordBatch::try_new(
+            self.schema.clone(),
+            vec![
+                Arc::new(time_buckets.with_timezone_utc()),
+                Arc::new(duration_bins),
+                Arc::new(min_durations),
+                Arc::new(max_durations),
+                Arc::new(counts),
+            ],
+        )?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for SpanHeatmapTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch(state).await?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
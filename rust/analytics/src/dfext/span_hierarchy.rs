@@ -0,0 +1,254 @@
+use crate::call_tree::{CallTree, CallTreeNode};
+use anyhow::Context;
+use datafusion::arrow::array::{Array, AsArray, Int64Builder, StringBuilder, UInt32Builder};
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::{DataType, Int64Type, UInt32Type};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::Result;
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::{ColumnarValue, ScalarUDF, ScalarUDFImpl, Signature, Volatility};
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug)]
+struct SpanNode {
+    parent: i64,
+    depth: u32,
+    name: Arc<String>,
+}
+
+/// Parent links of the spans of call trees, indexed by process and span id
+///
+/// Span ids are only unique within a call tree: trees of different threads of a process
+/// should be added under different keys, i.e. their stream id.
+#[derive(Debug, Default)]
+pub struct SpanHierarchy {
+    trees: HashMap<String, HashMap<i64, SpanNode>>,
+}
+
+impl SpanHierarchy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_call_tree(&mut self, process_id: &str, tree: &CallTree) {
+        let nodes = self.trees.entry(process_id.to_owned()).or_default();
+        if let Some(root) = &tree.call_tree_root {
+            add_node(nodes, tree, root, -1, 0);
+        }
+    }
+
+    /// Adds the rows of a `spans` view: `id`, `parent`, `depth` and `name` columns
+    pub fn add_spans(&mut self, process_id: &str, spans: &RecordBatch) -> anyhow::Result<()> {
+        let column = |name: &str| {
+            spans
+                .column_by_name(name)
+                .with_context(|| format!("missing {name} column"))
+        };
+        let ids = column("id")?.as_primitive::<Int64Type>();
+        let parents = column("parent")?.as_primitive::<Int64Type>();
+        let depths = column("depth")?.as_primitive::<UInt32Type>();
+        let names = cast(column("name")?, &DataType::Utf8).with_context(|| "casting names")?;
+        let names = names.as_string::<i32>();
+        let nodes = self.trees.entry(process_id.to_owned()).or_default();
+        for row in 0..spans.num_rows() {
+            nodes.insert(
+                ids.value(row),
+                SpanNode {
+                    parent: parents.value(row),
+                    depth: depths.value(row),
+                    name: Arc::new(names.value(row).to_owned()),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    pub fn depth(&self, process_id: &str, span_id: i64) -> Option<u32> {
+        self.trees.get(process_id)?.get(&span_id).map(|n| n.depth)
+    }
+
+    /// Ancestor of the span at depth `level`, the span itself if it is at that depth
+    pub fn ancestor(&self, process_id: &str, span_id: i64, level: i64) -> Option<i64> {
+        let nodes = self.trees.get(process_id)?;
+        let level = u32::try_from(level).ok()?;
+        let mut id = span_id;
+        let mut node = nodes.get(&id)?;
+        if level > node.depth {
+            return None;
+        }
+        while node.depth > level {
+            id = node.parent;
+            node = nodes.get(&id)?;
+        }
+        Some(id)
+    }
+
+    pub fn ancestor_name(&self, process_id: &str, span_id: i64, level: i64) -> Option<&str> {
+        let id = self.ancestor(process_id, span_id, level)?;
+        self.trees
+            .get(process_id)?
+            .get(&id)
+            .map(|n| n.name.as_str())
+    }
+}
+
+fn add_node(
+    nodes: &mut HashMap<i64, SpanNode>,
+    tree: &CallTree,
+    node: &CallTreeNode,
+    parent: i64,
+    depth: u32,
+) {
+    let span_id = node.id.unwrap_or(-1);
+    let name = tree
+        .scopes
+        .get(&node.hash)
+        .map(|scope| scope.name.clone())
+        .unwrap_or_default();
+    nodes.insert(
+        span_id,
+        SpanNode {
+            parent,
+            depth,
+            name,
+        },
+    );
+    for child in &node.children {
+        add_node(nodes, tree, child, span_id, depth + 1);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum HierarchyFunction {
+    Depth,
+    Ancestor,
+    AncestorName,
+}
+
+/// `span_depth(process_id, span_id)`, `span_ancestor(process_id, span_id, level)`
+/// and `span_ancestor_name(process_id, span_id, level)`
+#[derive(Debug)]
+struct SpanHierarchyUdf {
+    name: &'static str,
+    function: HierarchyFunction,
+    hierarchy: Arc<RwLock<SpanHierarchy>>,
+    signature: Signature,
+}
+
+impl ScalarUDFImpl for SpanHierarchyUdf {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> Result<DataType> {
+        Ok(match self.function {
+            HierarchyFunction::Depth => DataType::UInt32,
+            HierarchyFunction::Ancestor => DataType::Int64,
+            HierarchyFunction::AncestorName => DataType::Utf8,
+        })
+    }
+
+    fn invoke(&self, args: &[ColumnarValue]) -> Result<ColumnarValue> {
+        let arrays = ColumnarValue::values_to_arrays(args)?;
+        let hierarchy = self.hierarchy.read().unwrap();
+        let process_ids = arrays[0].as_string::<i32>();
+        let span_ids = arrays[1].as_primitive::<Int64Type>();
+        let rows = (0..process_ids.len()).map(|i| {
+            if process_ids.is_null(i) || span_ids.is_null(i) {
+                None
+            } else {
+                Some((process_ids.value(i), span_ids.value(i)))
+            }
+        });
+        let result: Arc<dyn Array> = match self.function {
+            HierarchyFunction::Depth => {
+                let mut builder = UInt32Builder::with_capacity(process_ids.len());
+                for row in rows {
+                    builder.append_option(row.and_then(|(p, s)| hierarchy.depth(p, s)));
+                }
+                Arc::new(builder.finish())
+            }
+            HierarchyFunction::Ancestor => {
+                let levels = arrays[2].as_primitive::<Int64Type>();
+                let mut builder = Int64Builder::with_capacity(process_ids.len());
+                for (i, row) in rows.enumerate() {
+                    builder.append_option(row.and_then(|(p, s)| {
+                        levels
+                            .is_valid(i)
+                            .then(|| hierarchy.ancestor(p, s, levels.value(i)))
+                            .flatten()
+                    }));
+                }
+                Arc::new(builder.finish())
+            }
+            HierarchyFunction::AncestorName => {
+                let levels = arrays[2].as_primitive::<Int64Type>();
+                let mut builder = StringBuilder::new();
+                for (i, row) in rows.enumerate() {
+                    builder.append_option(row.and_then(|(p, s)| {
+                        levels
+                            .is_valid(i)
+                            .then(|| hierarchy.ancestor_name(p, s, levels.value(i)))
+                            .flatten()
+                    }));
+                }
+                Arc::new(builder.finish())
+            }
+        };
+        Ok(ColumnarValue::Array(result))
+    }
+}
+
+fn make_hierarchy_udf(
+    name: &'static str,
+    function: HierarchyFunction,
+    hierarchy: Arc<RwLock<SpanHierarchy>>,
+) -> ScalarUDF {
+    let arg_types = match function {
+        HierarchyFunction::Depth => vec![DataType::Utf8, DataType::Int64],
+        HierarchyFunction::Ancestor | HierarchyFunction::AncestorName => {
+            vec![DataType::Utf8, DataType::Int64, DataType::Int64]
+        }
+    };
+    ScalarUDF::new_from_impl(SpanHierarchyUdf {
+        name,
+        function,
+        hierarchy,
+        signature: Signature::exact(arg_types, Volatility::Immutable),
+    })
+}
+
+/// Makes `span_depth`, `span_ancestor` and `span_ancestor_name` available to the queries
+/// of the context, answered from the call trees of the hierarchy
+///
+/// The hierarchy can grow after the registration, sql sessions add the spans they query.
+pub fn register_span_hierarchy_functions(
+    ctx: &SessionContext,
+    hierarchy: Arc<RwLock<SpanHierarchy>>,
+) {
+    ctx.register_udf(make_hierarchy_udf(
+        "span_depth",
+        HierarchyFunction::Depth,
+        hierarchy.clone(),
+    ));
+    ctx.register_udf(make_hierarchy_udf(
+        "span_ancestor",
+        HierarchyFunction::Ancestor,
+        hierarchy.clone(),
+    ));
+    ctx.register_udf(make_hierarchy_udf(
+        "span_ancestor_name",
+        HierarchyFunction::AncestorName,
+        hierarchy,
+    ));
+}
@@ -0,0 +1,139 @@
+use super::to_datafusion_error;
+use crate::metadata::list_process_streams;
+use anyhow::Context;
+use async_trait::async_trait;
+use datafusion::arrow::array::{ArrayRef, RecordBatch, StringBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{TableProvider, TableType};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::execution::context::{SessionContext, SessionState};
+use datafusion::logical_expr::Expr;
+use datafusion::physical_plan::memory::MemoryExec;
+use datafusion::physical_plan::ExecutionPlan;
+use datafusion::scalar::ScalarValue;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use std::any::Any;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn stream_metadata_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("stream_id", DataType::Utf8, false),
+        Field::new("tags", DataType::Utf8, false),
+        Field::new("properties", DataType::Utf8, false),
+        Field::new("dependencies_metadata", DataType::Utf8, false),
+        Field::new("objects_metadata", DataType::Utf8, false),
+    ]))
+}
+
+/// `stream_metadata(process_id)`: one row per stream of the process, its metadata as json
+///
+/// `objects_metadata` and `dependencies_metadata` describe the layout of the events of the
+/// stream as they were sent by the instrumented process, which helps debugging wire format
+/// mismatches.
+#[derive(Debug)]
+pub struct StreamMetadata {
+    data_lake: DataLakeConnection,
+}
+
+impl StreamMetadata {
+    pub fn new(data_lake: DataLakeConnection) -> Self {
+        Self { data_lake }
+    }
+}
+
+impl TableFunctionImpl for StreamMetadata {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let process_id = match args {
+            [Expr::Literal(ScalarValue::Utf8(Some(text)))] => {
+                Uuid::parse_str(text).map_err(|e| {
+                    DataFusionError::Plan(format!("stream_metadata: invalid process_id: {e}"))
+                })?
+            }
+            _ => {
+                return Err(DataFusionError::Plan(
+                    "stream_metadata expects a process_id string".into(),
+                ))
+            }
+        };
+        Ok(Arc::new(StreamMetadataTable {
+            data_lake: self.data_lake.clone(),
+            process_id,
+            schema: stream_metadata_schema(),
+        }))
+    }
+}
+
+#[derive(Debug)]
+struct StreamMetadataTable {
+    data_lake: DataLakeConnection,
+    process_id: Uuid,
+    schema: SchemaRef,
+}
+
+impl StreamMetadataTable {
+    async fn make_batch(&self) -> anyhow::Result<RecordBatch> {
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let streams = list_process_streams(&mut connection, self.process_id)
+            .await
+            .with_context(|| "list_process_streams")?;
+        drop(connection);
+        let mut stream_ids = StringBuilder::new();
+        let mut tags = StringBuilder::new();
+        let mut properties = StringBuilder::new();
+        let mut dependencies_metadata = StringBuilder::new();
+        let mut objects_metadata = StringBuilder::new();
+        for stream in &streams {
+            stream_ids.append_value(stream.stream_id.to_string());
+            tags.append_value(serde_json::to_string(&stream.tags)?);
+            properties.append_value(serde_json::to_string(&stream.properties)?);
+            dependencies_metadata
+                .append_value(serde_json::to_string(&stream.dependencies_metadata)?);
+            objects_metadata.append_value(serde_json::to_string(&stream.objects_metadata)?);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(stream_ids.finish()),
+            Arc::new(tags.finish()),
+            Arc::new(properties.finish()),
+            Arc::new(dependencies_metadata.finish()),
+            Arc::new(objects_metadata.finish()),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+#[async_trait]
+impl TableProvider for StreamMetadataTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        TableType::Temporary
+    }
+
+    async fn scan(
+        &self,
+        _state: &SessionState,
+        projection: Option<&Vec<usize>>,
+        _filters: &[Expr],
+        _limit: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let batch = self.make_batch().await.map_err(to_datafusion_error)?;
+        Ok(Arc::new(MemoryExec::try_new(
+            &[vec![batch]],
+            self.schema.clone(),
+            projection.cloned(),
+        )?))
+    }
+}
+
+/// Makes `stream_metadata` available to the queries of the context
+pub fn register_stream_metadata(ctx: &SessionContext, data_lake: DataLakeConnection) {
+    ctx.register_udtf("stream_metadata", Arc::new(StreamMetadata::new(data_lake)));
+}
@@ -0,0 +1,86 @@
+use chrono::DateTime;
+use datafusion::arrow::array::{RecordBatch, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::{DataType, Field, IntervalMonthDayNanoType, Schema, TimeUnit};
+use datafusion::datasource::function::TableFunctionImpl;
+use datafusion::datasource::{MemTable, TableProvider};
+use datafusion::error::{DataFusionError, Result};
+use datafusion::logical_expr::Expr;
+use datafusion::scalar::ScalarValue;
+use std::sync::Arc;
+
+/// Generating more rows than this is most likely a mistake in the interval
+const MAX_BUCKETS: i64 = 10_000_000;
+
+fn timestamp_arg(expr: &Expr, name: &str) -> Result<i64> {
+    match expr {
+        Expr::Literal(ScalarValue::Utf8(Some(text))) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .and_then(|time| time.timestamp_nanos_opt())
+            .ok_or_else(|| DataFusionError::Plan(format!("time_buckets: invalid {name} {text}"))),
+        Expr::Literal(ScalarValue::TimestampNanosecond(Some(nanos), _)) => Ok(*nanos),
+        other => Err(DataFusionError::Plan(format!(
+            "time_buckets: {name} should be a rfc3339 string or a timestamp, found {other}"
+        ))),
+    }
+}
+
+pub(crate) fn interval_arg(expr: &Expr, function_name: &str) -> Result<i64> {
+    let nanos = match expr {
+        Expr::Literal(ScalarValue::IntervalMonthDayNano(Some(interval))) => {
+            let (months, days, nanos) = IntervalMonthDayNanoType::to_parts(*interval);
+            if months != 0 {
+                return Err(DataFusionError::Plan(
+                    format!("{function_name}: intervals in months have a variable length"),
+                ));
+            }
+            i64::from(days) * 24 * 3600 * 1_000_000_000 + nanos
+        }
+        Expr::Literal(ScalarValue::Int64(Some(nanos))) => *nanos,
+        other => Err(DataFusionError::Plan(format!(
+            "{function_name}: interval should be an INTERVAL or a number of nanoseconds, found {other}"
+        )))?,
+    };
+    if nanos <= 0 {
+        return Err(DataFusionError::Plan(format!(
+            "{function_name}: interval should be positive"
+        )));
+    }
+    Ok(nanos)
+}
+
+/// `time_buckets(begin, end, interval)`: one row per bucket in [begin, end), in a `bucket` column
+///
+/// Left join a time series on it to get null values for the buckets without data.
+#[derive(Debug)]
+pub struct TimeBuckets {}
+
+impl TableFunctionImpl for TimeBuckets {
+    fn call(&self, args: &[Expr]) -> Result<Arc<dyn TableProvider>> {
+        let [begin, end, interval] = args else {
+            return Err(DataFusionError::Plan(
+                "time_buckets expects 3 arguments: begin, end, interval".into(),
+            ));
+        };
+        let begin = timestamp_arg(begin, "begin")?;
+        let end = timestamp_arg(end, "end")?;
+        let interval = interval_arg(interval, "time_buckets")?;
+        let nb_buckets = (end - begin).max(0) / interval;
+        if nb_buckets > MAX_BUCKETS {
+            return Err(DataFusionError::Plan(format!(
+                "time_buckets: too many buckets ({nb_buckets})"
+            )));
+        }
+        let buckets: TimestampNanosecondArray = (0..nb_buckets)
+            .map(|index| begin + index * interval)
+            .collect::<Vec<i64>>()
+            .into();
+        let buckets = buckets.with_timezone_utc();
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "bucket",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(buckets)])?;
+        Ok(Arc::new(MemTable::try_new(schema, vec![vec![batch]])?))
+    }
+}
@@ -3,32 +3,59 @@
 // crate-specific lint exceptions:
 #![allow(clippy::missing_errors_doc)]
 
+pub mod align_measures;
 pub mod analytics_service;
 pub mod arrow_utils;
+pub mod block_subscription;
 pub mod call_tree;
+pub mod dfext;
 pub mod log_entries_table;
 pub mod log_entry;
+pub mod log_export;
 pub mod measure;
 pub mod metadata;
 pub mod metrics_table;
+pub mod negative_cache;
+pub mod parquet_config;
+pub mod promoted_properties;
+pub mod property_histogram;
+pub mod query_heatmap;
+pub mod query_log;
 pub mod query_log_entries;
 pub mod query_metrics;
+pub mod query_span_events;
 pub mod query_spans;
+pub mod query_tags;
 pub mod query_thread_events;
+pub mod query_timeout;
+pub mod query_view;
+pub mod sample_spans;
 pub mod scope;
+pub mod span_events_table;
+pub mod span_filter;
 pub mod span_table;
 pub mod sql_arrow_bridge;
+pub mod sql_export;
+pub mod sql_session;
+pub mod stream_query_options;
 pub mod thread_block_processor;
 pub mod thread_events_table;
 pub mod time;
+pub mod time_ranges;
+pub mod view_config;
+pub mod view_docs;
+pub mod view_versions;
 
 use anyhow::{Context, Result};
 use metadata::{map_row_block, process_from_row};
 use micromegas_telemetry::blob_storage::BlobStorage;
-use micromegas_telemetry::compression::decompress;
+use micromegas_telemetry::block_wire_format::verify_payload_checksum;
+use micromegas_telemetry::compression::{decompress_with_codec, COMPRESSION_PROPERTY};
 use micromegas_telemetry::stream_info::StreamInfo;
 use micromegas_telemetry::types::block::BlockMetadata;
+use micromegas_telemetry::wire_format::stream_wire_format_version;
 use micromegas_tracing::prelude::*;
+use micromegas_tracing::stream_tags::StreamTag;
 use micromegas_transit::{parse_object_buffer, read_dependencies, UserDefinedType, Value};
 use sqlx::Row;
 use std::sync::Arc;
@@ -286,7 +313,7 @@ pub async fn find_process_log_streams(
     connection: &mut sqlx::PgConnection,
     process_id: &sqlx::types::Uuid,
 ) -> Result<Vec<StreamInfo>> {
-    find_process_streams_tagged(connection, process_id, "log").await
+    find_process_streams_tagged(connection, process_id, StreamTag::Log.as_str()).await
 }
 
 #[span_fn]
@@ -294,7 +321,7 @@ pub async fn find_process_thread_streams(
     connection: &mut sqlx::PgConnection,
     process_id: &sqlx::types::Uuid,
 ) -> Result<Vec<StreamInfo>> {
-    find_process_streams_tagged(connection, process_id, "cpu").await
+    find_process_streams_tagged(connection, process_id, StreamTag::Cpu.as_str()).await
 }
 
 #[span_fn]
@@ -302,7 +329,7 @@ pub async fn find_process_metrics_streams(
     connection: &mut sqlx::PgConnection,
     process_id: &sqlx::types::Uuid,
 ) -> Result<Vec<StreamInfo>> {
-    find_process_streams_tagged(connection, process_id, "metrics").await
+    find_process_streams_tagged(connection, process_id, StreamTag::Metrics.as_str()).await
 }
 
 #[span_fn]
@@ -349,7 +376,7 @@ pub async fn find_block(
     block_id: &str,
 ) -> Result<BlockMetadata> {
     let row = sqlx::query(
-        "SELECT block_id, stream_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size
+        "SELECT block_id, stream_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size, checksum
          FROM blocks
          WHERE block_id = ?
          ;",
@@ -367,7 +394,7 @@ pub async fn find_stream_blocks(
     stream_id: &sqlx::types::Uuid,
 ) -> Result<Vec<BlockMetadata>> {
     let rows = sqlx::query(
-        "SELECT block_id, stream_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size
+        "SELECT block_id, stream_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size, checksum
          FROM blocks
          WHERE stream_id = ?
          ORDER BY begin_time;",
@@ -383,12 +410,14 @@ pub async fn find_stream_blocks(
     Ok(blocks)
 }
 
+/// Reads the payload of a block, failing if it does not match the checksum recorded for the block
 #[span_fn]
 pub async fn fetch_block_payload(
     blob_storage: Arc<BlobStorage>,
     process_id: sqlx::types::Uuid,
     stream_id: sqlx::types::Uuid,
     block_id: sqlx::types::Uuid,
+    checksum: Option<i64>,
 ) -> Result<micromegas_telemetry::block_wire_format::BlockPayload> {
     let obj_path = format!("blobs/{process_id}/{stream_id}/{block_id}");
     let buffer: Vec<u8> = blob_storage
@@ -401,6 +430,7 @@ pub async fn fetch_block_payload(
         let payload: micromegas_telemetry::block_wire_format::BlockPayload =
             ciborium::from_reader(&buffer[..])
                 .with_context(|| format!("reading payload {}", &block_id))?;
+        verify_payload_checksum(&block_id, &payload, checksum)?;
         Ok(payload)
     }
 }
@@ -415,17 +445,40 @@ pub fn parse_block<F>(
 where
     F: FnMut(Value) -> Result<bool>,
 {
+    let version = stream_wire_format_version(&stream.properties)
+        .with_context(|| format!("stream {}", stream.stream_id))?;
+    match version {
+        // v2 only added the checksum next to the payload, which is encoded the same way
+        1 | 2 => parse_block_v1(stream, payload, fun),
+        version => anyhow::bail!("no parser for wire format version {version}"),
+    }
+}
+
+fn parse_block_v1<F>(
+    stream: &StreamInfo,
+    payload: &micromegas_telemetry::block_wire_format::BlockPayload,
+    fun: F,
+) -> Result<bool>
+where
+    F: FnMut(Value) -> Result<bool>,
+{
+    let codec = stream
+        .properties
+        .get(COMPRESSION_PROPERTY)
+        .map(String::as_str);
     let dep_udts = &stream.dependencies_metadata;
     let dependencies = read_dependencies(
         dep_udts,
-        &decompress(&payload.dependencies).with_context(|| "decompressing dependencies payload")?,
+        &decompress_with_codec(codec, &payload.dependencies)
+            .with_context(|| "decompressing dependencies payload")?,
     )
     .with_context(|| "reading dependencies")?;
     let obj_udts = &stream.objects_metadata;
     let continue_iterating = parse_object_buffer(
         &dependencies,
         obj_udts,
-        &decompress(&payload.objects).with_context(|| "decompressing objects payload")?,
+        &decompress_with_codec(codec, &payload.objects)
+            .with_context(|| "decompressing objects payload")?,
         fun,
     )
     .with_context(|| "parsing object buffer")?;
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use datafusion::arrow::array::ArrayBuilder;
+use datafusion::arrow::array::ArrayRef;
 use datafusion::arrow::array::PrimitiveBuilder;
 use datafusion::arrow::array::StringBuilder;
 use datafusion::arrow::array::StringDictionaryBuilder;
@@ -9,18 +10,25 @@ use datafusion::arrow::datatypes::DataType;
 use datafusion::arrow::datatypes::Field;
 use datafusion::arrow::datatypes::Int16Type;
 use datafusion::arrow::datatypes::Int32Type;
+use datafusion::arrow::datatypes::Int64Type;
 use datafusion::arrow::datatypes::Schema;
 use datafusion::arrow::datatypes::TimeUnit;
 use datafusion::arrow::datatypes::TimestampNanosecondType;
 use datafusion::arrow::record_batch::RecordBatch;
 
+use crate::dfext::log_category::log_category;
 use crate::log_entry::LogEntry;
+use crate::view_docs::ViewDoc;
 
 pub struct LogEntriesRecordBuilder {
     pub times: PrimitiveBuilder<TimestampNanosecondType>,
     pub targets: StringDictionaryBuilder<Int16Type>,
     pub levels: PrimitiveBuilder<Int32Type>,
     pub msgs: StringBuilder,
+    /// extracted from the messages, see `with_categories`
+    pub categories: Option<StringDictionaryBuilder<Int16Type>>,
+    /// raw timestamps the times are converted from, see `with_ticks`
+    pub ticks: Option<PrimitiveBuilder<Int64Type>>,
 }
 
 impl LogEntriesRecordBuilder {
@@ -30,9 +38,23 @@ impl LogEntriesRecordBuilder {
             targets: StringDictionaryBuilder::new(),
             levels: PrimitiveBuilder::with_capacity(capacity),
             msgs: StringBuilder::new(),
+            categories: None,
+            ticks: None,
         }
     }
 
+    /// Adds a `category` column holding the bracketed category of each message, if any
+    pub fn with_categories(mut self) -> Self {
+        self.categories = Some(StringDictionaryBuilder::new());
+        self
+    }
+
+    /// Adds a `ticks` column holding the raw timestamp of each entry, to diagnose time conversion
+    pub fn with_ticks(mut self) -> Self {
+        self.ticks = Some(PrimitiveBuilder::new());
+        self
+    }
+
     pub fn len(&self) -> i64 {
         self.times.len() as i64
     }
@@ -46,33 +68,95 @@ impl LogEntriesRecordBuilder {
         self.targets.append_value(&*row.target);
         self.levels.append_value(row.level);
         self.msgs.append_value(&*row.msg);
+        if let Some(categories) = &mut self.categories {
+            categories.append_option(log_category(&row.msg));
+        }
+        if let Some(ticks) = &mut self.ticks {
+            ticks.append_value(row.ticks);
+        }
         Ok(())
     }
 
     pub fn finish(mut self) -> Result<RecordBatch> {
-        let schema = Schema::new(vec![
-            Field::new(
-                "time",
-                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
-                false,
+        let schema = log_entries_schema(self.categories.is_some(), self.ticks.is_some());
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.times.finish().with_timezone_utc()),
+            Arc::new(self.targets.finish()),
+            Arc::new(self.levels.finish()),
+            Arc::new(self.msgs.finish()),
+        ];
+        if let Some(mut categories) = self.categories {
+            columns.push(Arc::new(categories.finish()));
+        }
+        if let Some(mut ticks) = self.ticks {
+            columns.push(Arc::new(ticks.finish()));
+        }
+        RecordBatch::try_new(Arc::new(schema), columns).with_context(|| "building record batch")
+    }
+}
+
+/// Schema of the `log_entries` view, `category` is only present for streams declaring it
+/// and `ticks` when the views are configured with `tick_columns`
+pub fn log_entries_schema(with_categories: bool, with_ticks: bool) -> Schema {
+    let mut fields = vec![
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new(
+            "target",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new("level", DataType::Int32, false),
+        Field::new("msg", DataType::Utf8, false),
+    ];
+    if with_categories {
+        fields.push(Field::new(
+            "category",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            true,
+        ));
+    }
+    if with_ticks {
+        fields.push(Field::new("ticks", DataType::Int64, false));
+    }
+    Schema::new(fields)
+}
+
+pub fn log_entries_view_doc() -> ViewDoc {
+    ViewDoc {
+        name: "log_entries",
+        description: "log entries of a stream, in a time range",
+        deprecated: None,
+        schema: log_entries_schema(true, true),
+        columns: &[
+            ("time", "time of the entry"),
+            ("target", "module path or category of the code that logged the entry"),
+            (
+                "level",
+                "severity: 1=fatal, 2=error, 3=warn, 4=info, 5=debug, 6=trace, see level_name and level_value",
+            ),
+            ("msg", "message of the entry"),
+            (
+                "category",
+                "bracketed category at the start of the message, i.e. LogNet, for the streams declaring them",
+            ),
+            (
+                "ticks",
+                "raw timestamp of the entry the time is converted from, when the views are configured with tick_columns",
+            ),
+        ],
+        examples: &[
+            (
+                "warnings and errors, most recent first",
+                "SELECT time, level_name(level) AS level, target, msg FROM log_entries WHERE level <= level_value('warn') ORDER BY time DESC",
             ),
-            Field::new(
-                "target",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
+            (
+                "number of entries per target and level",
+                "SELECT target, level_name(level) AS level, count(*) AS nb_entries FROM log_entries GROUP BY target, level ORDER BY nb_entries DESC",
             ),
-            Field::new("level", DataType::Int32, false),
-            Field::new("msg", DataType::Utf8, false),
-        ]);
-        RecordBatch::try_new(
-            Arc::new(schema),
-            vec![
-                Arc::new(self.times.finish().with_timezone_utc()),
-                Arc::new(self.targets.finish()),
-                Arc::new(self.levels.finish()),
-                Arc::new(self.msgs.finish()),
-            ],
-        )
-        .with_context(|| "building record batch")
+        ],
     }
 }
@@ -9,6 +9,8 @@ use std::sync::Arc;
 
 pub struct LogEntry {
     pub time: i64,
+    /// raw timestamp `time` is converted from
+    pub ticks: i64,
     pub level: i32,
     pub target: Arc<String>,
     pub msg: Arc<String>,
@@ -36,6 +38,7 @@ pub fn log_entry_from_value(convert_ticks: &ConvertTicks, val: &Value) -> Result
                     .with_context(|| "reading fmt_str from LogStaticStrEvent")?;
                 Ok(Some(LogEntry {
                     time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
                     level: level as i32,
                     target,
                     msg,
@@ -59,6 +62,7 @@ pub fn log_entry_from_value(convert_ticks: &ConvertTicks, val: &Value) -> Result
                     .with_context(|| "reading msg from LogStringEvent")?;
                 Ok(Some(LogEntry {
                     time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
                     level: level as i32,
                     target,
                     msg,
@@ -79,6 +83,7 @@ pub fn log_entry_from_value(convert_ticks: &ConvertTicks, val: &Value) -> Result
                     .with_context(|| format!("reading msg from {}", obj.type_name.as_str()))?;
                 Ok(Some(LogEntry {
                     time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
                     level: level as i32,
                     target,
                     msg,
@@ -107,6 +112,7 @@ pub async fn for_each_log_entry_in_block<Predicate: FnMut(LogEntry) -> Result<bo
         stream.process_id,
         stream.stream_id,
         block.block_id,
+        block.checksum,
     )
     .await?;
     parse_block(stream, &payload, |val| {
@@ -0,0 +1,300 @@
+//! Incremental export of the log entries matching a filter, i.e. to mirror warnings in a SIEM
+//!
+//! Blocks of log streams are exported in the order they were inserted. The checkpoint of an
+//! export records the last exported block and is saved once the destination accepted its
+//! entries: a failure in between sends them again, so the delivery is at least once.
+use crate::log_entry::{log_entry_from_value, LogEntry};
+use crate::metadata::{find_process, find_stream};
+use crate::time::ConvertTicks;
+use crate::{fetch_block_payload, parse_block};
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_tracing::prelude::*;
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, TimeZone, Utc};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// processes and streams kept in the caches of an exporter before they are cleared
+const MAX_CACHED_ENTRIES: usize = 16 * 1024;
+
+/// Errors of the database or the object store, which may go away when retried
+///
+/// A missing row or payload won't appear later, and a payload that can't be parsed never will.
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<sqlx::Error>()
+            .is_some_and(|e| !matches!(e, sqlx::Error::RowNotFound))
+            || cause
+                .downcast_ref::<object_store::Error>()
+                .is_some_and(|e| !matches!(e, object_store::Error::NotFound { .. }))
+    })
+}
+
+/// Which log entries are exported
+#[derive(Debug, Clone)]
+pub struct LogExportFilter {
+    /// least severe level exported, i.e. `Level::Warn` exports warnings, errors and fatal entries
+    pub max_level: Level,
+    /// targets starting with one of these prefixes, all targets if empty
+    pub target_prefixes: Vec<String>,
+}
+
+impl LogExportFilter {
+    pub fn matches(&self, level: i32, target: &str) -> bool {
+        level > 0
+            && level <= self.max_level as i32
+            && (self.target_prefixes.is_empty()
+                || self
+                    .target_prefixes
+                    .iter()
+                    .any(|prefix| target.starts_with(prefix.as_str())))
+    }
+}
+
+/// Position of an export in the sequence of inserted blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogExportCheckpoint {
+    pub insert_time: DateTime<Utc>,
+    pub block_id: Uuid,
+}
+
+impl LogExportCheckpoint {
+    /// Exports the blocks inserted from now on
+    pub fn starting_now() -> Self {
+        Self {
+            insert_time: Utc::now(),
+            block_id: Uuid::nil(),
+        }
+    }
+}
+
+pub async fn read_checkpoint(
+    pool: &sqlx::PgPool,
+    name: &str,
+) -> Result<Option<LogExportCheckpoint>> {
+    let row = sqlx::query(
+        "SELECT insert_time, block_id
+         FROM log_export_checkpoints
+         WHERE name = $1;",
+    )
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+    .with_context(|| "select from log_export_checkpoints")?;
+    row.map(|row| {
+        Ok(LogExportCheckpoint {
+            insert_time: row.try_get("insert_time")?,
+            block_id: row.try_get("block_id")?,
+        })
+    })
+    .transpose()
+}
+
+pub async fn write_checkpoint(
+    pool: &sqlx::PgPool,
+    name: &str,
+    checkpoint: &LogExportCheckpoint,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO log_export_checkpoints VALUES($1, $2, $3, now())
+         ON CONFLICT (name) DO UPDATE
+         SET insert_time = EXCLUDED.insert_time,
+             block_id = EXCLUDED.block_id,
+             update_time = EXCLUDED.update_time;",
+    )
+    .bind(name)
+    .bind(checkpoint.insert_time)
+    .bind(checkpoint.block_id)
+    .execute(pool)
+    .await
+    .with_context(|| "upserting log_export_checkpoints")?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct LogBlock {
+    pub block_id: Uuid,
+    pub process_id: Uuid,
+    pub stream_id: Uuid,
+    pub insert_time: DateTime<Utc>,
+    pub checksum: Option<i64>,
+}
+
+impl LogBlock {
+    pub fn checkpoint(&self) -> LogExportCheckpoint {
+        LogExportCheckpoint {
+            insert_time: self.insert_time,
+            block_id: self.block_id,
+        }
+    }
+}
+
+/// Blocks of log streams inserted after the checkpoint and at least `settle_delay` ago, oldest first
+///
+/// Blocks are inserted by concurrent transactions: the delay gives those that were slow to
+/// commit the time to become visible before the export moves past their insert_time.
+pub async fn next_log_blocks(
+    pool: &sqlx::PgPool,
+    checkpoint: &LogExportCheckpoint,
+    settle_delay: chrono::Duration,
+    limit: i64,
+) -> Result<Vec<LogBlock>> {
+    let rows = sqlx::query(
+        "SELECT blocks.block_id, blocks.process_id, blocks.stream_id, blocks.insert_time,
+                blocks.checksum
+         FROM blocks, streams
+         WHERE blocks.stream_id = streams.stream_id
+         AND 'log' = ANY(streams.tags)
+         AND (blocks.insert_time, blocks.block_id) > ($1, $2)
+         AND blocks.insert_time <= $3
+         ORDER BY blocks.insert_time, blocks.block_id
+         LIMIT $4;",
+    )
+    .bind(checkpoint.insert_time)
+    .bind(checkpoint.block_id)
+    .bind(Utc::now() - settle_delay)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+    .with_context(|| "listing log blocks to export")?;
+    rows.iter()
+        .map(|row| {
+            Ok(LogBlock {
+                block_id: row.try_get("block_id")?,
+                process_id: row.try_get("process_id")?,
+                stream_id: row.try_get("stream_id")?,
+                insert_time: row.try_get("insert_time")?,
+                checksum: row.try_get("checksum")?,
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+struct ExportedLogEntry<'a> {
+    /// RFC 3339
+    time: String,
+    level: i32,
+    target: &'a str,
+    msg: &'a str,
+    process_id: String,
+    exe: &'a str,
+    computer: &'a str,
+    username: &'a str,
+}
+
+struct ExportedProcess {
+    info: ProcessInfo,
+    convert_ticks: ConvertTicks,
+}
+
+/// Encodes the exported log entries of blocks as json lines
+pub struct LogExporter {
+    data_lake: DataLakeConnection,
+    filter: LogExportFilter,
+    processes: HashMap<Uuid, Arc<ExportedProcess>>,
+    streams: HashMap<Uuid, Arc<StreamInfo>>,
+}
+
+impl LogExporter {
+    pub fn new(data_lake: DataLakeConnection, filter: LogExportFilter) -> Self {
+        Self {
+            data_lake,
+            filter,
+            processes: HashMap::new(),
+            streams: HashMap::new(),
+        }
+    }
+
+    async fn process(&mut self, process_id: Uuid) -> Result<Arc<ExportedProcess>> {
+        if let Some(process) = self.processes.get(&process_id) {
+            return Ok(process.clone());
+        }
+        if self.processes.len() >= MAX_CACHED_ENTRIES {
+            self.processes.clear();
+        }
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let info = find_process(&mut connection, &process_id)
+            .await
+            .with_context(|| "find_process")?;
+        let process = Arc::new(ExportedProcess {
+            convert_ticks: ConvertTicks::new(&info),
+            info,
+        });
+        self.processes.insert(process_id, process.clone());
+        Ok(process)
+    }
+
+    async fn stream(&mut self, stream_id: Uuid) -> Result<Arc<StreamInfo>> {
+        if let Some(stream) = self.streams.get(&stream_id) {
+            return Ok(stream.clone());
+        }
+        if self.streams.len() >= MAX_CACHED_ENTRIES {
+            self.streams.clear();
+        }
+        let mut connection = self.data_lake.db_pool.acquire().await?;
+        let stream = Arc::new(
+            find_stream(&mut connection, stream_id)
+                .await
+                .with_context(|| "find_stream")?,
+        );
+        self.streams.insert(stream_id, stream.clone());
+        Ok(stream)
+    }
+
+    /// Appends the entries of the block that match the filter to `lines`, returns their number
+    pub async fn encode_block(&mut self, block: &LogBlock, lines: &mut Vec<u8>) -> Result<usize> {
+        let process = self.process(block.process_id).await?;
+        let stream = self.stream(block.stream_id).await?;
+        let payload = fetch_block_payload(
+            self.data_lake.blob_storage.clone(),
+            block.process_id,
+            block.stream_id,
+            block.block_id,
+            block.checksum,
+        )
+        .await?;
+        let process_id = block.process_id.to_string();
+        let mut nb_entries = 0;
+        parse_block(&stream, &payload, |val| {
+            let Some(LogEntry {
+                time,
+                level,
+                target,
+                msg,
+                ..
+            }) = log_entry_from_value(&process.convert_ticks, &val)?
+            else {
+                return Ok(true);
+            };
+            if self.filter.matches(level, &target) {
+                serde_json::to_writer(
+                    &mut *lines,
+                    &ExportedLogEntry {
+                        time: Utc
+                            .timestamp_nanos(time)
+                            .to_rfc3339_opts(SecondsFormat::Nanos, true),
+                        level,
+                        target: &target,
+                        msg: &msg,
+                        process_id: process_id.clone(),
+                        exe: &process.info.exe,
+                        computer: &process.info.computer,
+                        username: &process.info.username,
+                    },
+                )?;
+                lines.push(b'\n');
+                nb_entries += 1;
+            }
+            Ok(true)
+        })
+        .with_context(|| "parse_block")?;
+        Ok(nb_entries)
+    }
+}
@@ -9,6 +9,8 @@ use std::sync::Arc;
 
 pub struct Measure {
     pub time: i64,
+    /// raw timestamp `time` is converted from
+    pub ticks: i64,
     pub target: Arc<String>,
     pub name: Arc<String>,
     pub unit: Arc<String>,
@@ -39,6 +41,7 @@ pub fn measure_from_value(convert_ticks: &ConvertTicks, val: &Value) -> Result<O
                     .with_context(|| "reading unit from FloatMetricEvent")?;
                 Ok(Some(Measure {
                     time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
                     target,
                     name,
                     unit,
@@ -66,12 +69,45 @@ pub fn measure_from_value(convert_ticks: &ConvertTicks, val: &Value) -> Result<O
                     .with_context(|| "reading unit from IntegerMetricEvent")?;
                 Ok(Some(Measure {
                     time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
                     target,
                     name,
                     unit,
                     value: value as f64,
                 }))
             }
+            "AggregatedMetricEvent" => {
+                // pre-aggregated by the instrumented process, measured by the mean of the values
+                let ticks = obj
+                    .get::<i64>("time")
+                    .with_context(|| "reading time from AggregatedMetricEvent")?;
+                let sum = obj
+                    .get::<f64>("sum")
+                    .with_context(|| "reading sum from AggregatedMetricEvent")?;
+                let count = obj
+                    .get::<u64>("count")
+                    .with_context(|| "reading count from AggregatedMetricEvent")?;
+                let desc = obj
+                    .get::<Arc<micromegas_transit::Object>>("desc")
+                    .with_context(|| "reading desc from AggregatedMetricEvent")?;
+                let target = desc
+                    .get::<Arc<String>>("target")
+                    .with_context(|| "reading target from AggregatedMetricEvent")?;
+                let name = desc
+                    .get::<Arc<String>>("name")
+                    .with_context(|| "reading name from AggregatedMetricEvent")?;
+                let unit = desc
+                    .get::<Arc<String>>("unit")
+                    .with_context(|| "reading unit from AggregatedMetricEvent")?;
+                Ok(Some(Measure {
+                    time: convert_ticks.ticks_to_nanoseconds(ticks),
+                    ticks,
+                    target,
+                    name,
+                    unit,
+                    value: sum / count.max(1) as f64,
+                }))
+            }
             _ => {
                 warn!("unknown metric event {:?}", obj);
                 Ok(None)
@@ -95,6 +131,7 @@ pub async fn for_each_measure_in_block<Predicate: FnMut(Measure) -> Result<bool>
         stream.process_id,
         stream.stream_id,
         block.block_id,
+        block.checksum,
     )
     .await?;
     let continue_iterating = parse_block(stream, &payload, |val| {
@@ -1,25 +1,14 @@
 use anyhow::{Context, Result};
+use micromegas_ingestion::sql_instrumentation::instrument_query;
 use micromegas_ingestion::sql_property;
 use micromegas_telemetry::{stream_info::StreamInfo, types::block::BlockMetadata};
 use micromegas_tracing::prelude::*;
 use micromegas_transit::UserDefinedType;
+use serde::Serialize;
 use sqlx::Row;
 
-#[span_fn]
-pub async fn find_stream(
-    connection: &mut sqlx::PgConnection,
-    stream_id: sqlx::types::Uuid,
-) -> Result<StreamInfo> {
-    let row = sqlx::query(
-        "SELECT process_id, dependencies_metadata, objects_metadata, tags, properties
-         FROM streams
-         WHERE stream_id = $1
-         ;",
-    )
-    .bind(stream_id)
-    .fetch_one(connection)
-    .await
-    .with_context(|| "select from streams")?;
+/// Decodes a row with the `stream_id, process_id, dependencies_metadata, objects_metadata, tags, properties` columns
+pub fn stream_from_row(row: &sqlx::postgres::PgRow) -> Result<StreamInfo> {
     let dependencies_metadata_buffer: Vec<u8> = row.try_get("dependencies_metadata")?;
     let dependencies_metadata: Vec<UserDefinedType> =
         ciborium::from_reader(&dependencies_metadata_buffer[..])
@@ -31,7 +20,7 @@ pub async fn find_stream(
     let tags: Vec<String> = row.try_get("tags")?;
     let properties: Vec<sql_property::Property> = row.try_get("properties")?;
     Ok(StreamInfo {
-        stream_id,
+        stream_id: row.try_get("stream_id")?,
         process_id: row.try_get("process_id")?,
         dependencies_metadata,
         objects_metadata,
@@ -40,6 +29,56 @@ pub async fn find_stream(
     })
 }
 
+#[span_fn]
+pub async fn find_stream(
+    connection: &mut sqlx::PgConnection,
+    stream_id: sqlx::types::Uuid,
+) -> Result<StreamInfo> {
+    let sql =
+        "SELECT stream_id, process_id, dependencies_metadata, objects_metadata, tags, properties
+         FROM streams
+         WHERE stream_id = $1
+         ;";
+    let row = instrument_query(sql, sqlx::query(sql).bind(stream_id).fetch_one(connection))
+        .await
+        .with_context(|| "select from streams")?;
+    stream_from_row(&row)
+}
+
+/// Streams of the process, in the order they were inserted
+#[span_fn]
+pub async fn list_process_streams(
+    connection: &mut sqlx::PgConnection,
+    process_id: sqlx::types::Uuid,
+) -> Result<Vec<StreamInfo>> {
+    let sql =
+        "SELECT stream_id, process_id, dependencies_metadata, objects_metadata, tags, properties
+         FROM streams
+         WHERE process_id = $1
+         ORDER BY insert_time;";
+    let rows = instrument_query(sql, sqlx::query(sql).bind(process_id).fetch_all(connection))
+        .await
+        .with_context(|| "select from streams")?;
+    rows.iter().map(stream_from_row).collect()
+}
+
+/// A process and the streams it emitted, with the layout of their events
+#[derive(Debug, Serialize)]
+pub struct ProcessMetadata {
+    pub process: ProcessInfo,
+    pub streams: Vec<StreamInfo>,
+}
+
+pub async fn fetch_process_metadata(
+    connection: &mut sqlx::PgConnection,
+    process_id: sqlx::types::Uuid,
+) -> Result<ProcessMetadata> {
+    Ok(ProcessMetadata {
+        process: find_process(connection, &process_id).await?,
+        streams: list_process_streams(connection, process_id).await?,
+    })
+}
+
 #[span_fn]
 pub fn process_from_row(row: &sqlx::postgres::PgRow) -> Result<ProcessInfo> {
     let properties: Vec<sql_property::Property> = row.try_get("properties")?;
@@ -64,8 +103,7 @@ pub async fn find_process(
     connection: &mut sqlx::PgConnection,
     process_id: &sqlx::types::Uuid,
 ) -> Result<ProcessInfo> {
-    let row = sqlx::query(
-        "SELECT process_id,
+    let sql = "SELECT process_id,
                 exe,
                 username,
                 realname,
@@ -78,12 +116,10 @@ pub async fn find_process(
                 parent_process_id,
                 properties
          FROM processes
-         WHERE process_id = $1;",
-    )
-    .bind(process_id)
-    .fetch_one(connection)
-    .await
-    .with_context(|| "select from processes")?;
+         WHERE process_id = $1;";
+    let row = instrument_query(sql, sqlx::query(sql).bind(process_id).fetch_one(connection))
+        .await
+        .with_context(|| "select from processes")?;
     process_from_row(&row)
 }
 
@@ -100,6 +136,7 @@ pub fn map_row_block(row: &sqlx::postgres::PgRow) -> Result<BlockMetadata> {
         nb_objects: row.try_get("nb_objects")?,
         object_offset: row.try_get("object_offset")?,
         payload_size: row.try_get("payload_size")?,
+        checksum: row.try_get("checksum")?,
     })
 }
 
@@ -110,18 +147,20 @@ pub async fn find_stream_blocks_in_range(
     begin_ticks: i64,
     end_ticks: i64,
 ) -> Result<Vec<BlockMetadata>> {
-    let rows = sqlx::query(
-        "SELECT block_id, stream_id, process_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size
+    let sql = "SELECT block_id, stream_id, process_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects, object_offset, payload_size, checksum
          FROM blocks
          WHERE stream_id = $1
          AND begin_ticks <= $2
          AND end_ticks >= $3
-         ORDER BY begin_ticks;",
+         ORDER BY begin_ticks;";
+    let rows = instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(stream_id)
+            .bind(end_ticks)
+            .bind(begin_ticks)
+            .fetch_all(connection),
     )
-    .bind(stream_id)
-    .bind(end_ticks)
-    .bind(begin_ticks)
-    .fetch_all(connection)
     .await
     .with_context(|| "find_stream_blocks")?;
     let mut blocks = Vec::new();
@@ -1,9 +1,11 @@
 use crate::measure::Measure;
+use crate::view_docs::ViewDoc;
 use anyhow::{Context, Result};
 use datafusion::arrow::{
-    array::{PrimitiveBuilder, StringDictionaryBuilder},
+    array::{ArrayRef, PrimitiveBuilder, StringDictionaryBuilder},
     datatypes::{
-        DataType, Field, Float64Type, Int16Type, Schema, TimeUnit, TimestampNanosecondType,
+        DataType, Field, Float64Type, Int16Type, Int64Type, Schema, TimeUnit,
+        TimestampNanosecondType,
     },
     record_batch::RecordBatch,
 };
@@ -15,6 +17,8 @@ pub struct MetricsRecordBuilder {
     pub names: StringDictionaryBuilder<Int16Type>,
     pub units: StringDictionaryBuilder<Int16Type>,
     pub values: PrimitiveBuilder<Float64Type>,
+    /// raw timestamps the times are converted from, see `with_ticks`
+    pub ticks: Option<PrimitiveBuilder<Int64Type>>,
 }
 
 impl MetricsRecordBuilder {
@@ -25,52 +29,102 @@ impl MetricsRecordBuilder {
             names: StringDictionaryBuilder::new(),
             units: StringDictionaryBuilder::new(),
             values: PrimitiveBuilder::with_capacity(capacity),
+            ticks: None,
         }
     }
 
+    /// Adds a `ticks` column holding the raw timestamp of each measure, to diagnose time conversion
+    pub fn with_ticks(mut self) -> Self {
+        self.ticks = Some(PrimitiveBuilder::new());
+        self
+    }
+
     pub fn append(&mut self, row: &Measure) -> Result<()> {
         self.times.append_value(row.time);
         self.targets.append_value(&*row.target);
         self.names.append_value(&*row.name);
         self.units.append_value(&*row.unit);
         self.values.append_value(row.value);
+        if let Some(ticks) = &mut self.ticks {
+            ticks.append_value(row.ticks);
+        }
         Ok(())
     }
 
     pub fn finish(mut self) -> Result<RecordBatch> {
-        let schema = Schema::new(vec![
-            Field::new(
-                "time",
-                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
-                false,
-            ),
-            Field::new(
-                "target",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
+        let schema = measures_schema(self.ticks.is_some());
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.times.finish().with_timezone_utc()),
+            Arc::new(self.targets.finish()),
+            Arc::new(self.names.finish()),
+            Arc::new(self.units.finish()),
+            Arc::new(self.values.finish()),
+        ];
+        if let Some(mut ticks) = self.ticks {
+            columns.push(Arc::new(ticks.finish()));
+        }
+        RecordBatch::try_new(Arc::new(schema), columns).with_context(|| "building record batch")
+    }
+}
+
+/// Schema of the `measures` view, `ticks` is only present when the views are configured with
+/// `tick_columns`
+pub fn measures_schema(with_ticks: bool) -> Schema {
+    let mut fields = vec![
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new(
+            "target",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "name",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "unit",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new("value", DataType::Float64, false),
+    ];
+    if with_ticks {
+        fields.push(Field::new("ticks", DataType::Int64, false));
+    }
+    Schema::new(fields)
+}
+
+pub fn measures_view_doc() -> ViewDoc {
+    ViewDoc {
+        name: "measures",
+        description: "values of the metrics of a stream, in a time range",
+        deprecated: None,
+        schema: measures_schema(true),
+        columns: &[
+            ("time", "time of the measure"),
+            ("target", "module path of the code that recorded the measure"),
+            ("name", "name of the metric"),
+            ("unit", "unit of the value, i.e. bytes or ticks"),
+            ("value", "measured value, integer metrics are converted to floats"),
+            (
+                "ticks",
+                "raw timestamp of the measure the time is converted from, when the views are configured with tick_columns",
             ),
-            Field::new(
-                "name",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
+        ],
+        examples: &[
+            (
+                "statistics of each metric",
+                "SELECT name, unit, min(value), avg(value), max(value) FROM measures GROUP BY name, unit",
             ),
-            Field::new(
-                "unit",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
+            (
+                "one metric averaged per minute",
+                "SELECT date_bin(INTERVAL '1 minute', time) AS minute, avg(value) FROM measures WHERE name = 'cpu_usage' GROUP BY minute ORDER BY minute",
             ),
-            Field::new("value", DataType::Float64, false),
-        ]);
-        RecordBatch::try_new(
-            Arc::new(schema),
-            vec![
-                Arc::new(self.times.finish().with_timezone_utc()),
-                Arc::new(self.targets.finish()),
-                Arc::new(self.names.finish()),
-                Arc::new(self.units.finish()),
-                Arc::new(self.values.finish()),
-            ],
-        )
-        .with_context(|| "building record batch")
+        ],
     }
 }
@@ -0,0 +1,98 @@
+//! Remembers the queries that found no data, so that clients hammering a stream without data don't load the database
+//!
+//! The time to live of an entry doubles each time the same empty result is computed again,
+//! up to a maximum, and is reset when the entry is evicted without being renewed.
+use datafusion::arrow::record_batch::RecordBatch;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_TTL: Duration = Duration::from_secs(300);
+const MAX_ENTRIES: usize = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct EmptyResultKey {
+    pub view: &'static str,
+    pub stream_id: Uuid,
+    pub begin: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+struct EmptyResultEntry {
+    /// empty, but with the schema of the view
+    batch: RecordBatch,
+    expires: Instant,
+    nb_misses: u32,
+}
+
+#[derive(Debug)]
+pub struct NegativeCache {
+    ttl: Duration,
+    max_ttl: Duration,
+    entries: Mutex<HashMap<EmptyResultKey, EmptyResultEntry>>,
+}
+
+impl Default for NegativeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TTL, DEFAULT_MAX_TTL)
+    }
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration, max_ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_ttl: max_ttl.max(ttl),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the empty result if it is still valid at `now`
+    pub fn get(&self, key: &EmptyResultKey, now: Instant) -> Option<RecordBatch> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|entry| entry.expires > now)
+            .map(|entry| entry.batch.clone())
+    }
+
+    /// Records that the query found no data, ignored if the batch has rows
+    pub fn insert(&self, key: EmptyResultKey, batch: &RecordBatch, now: Instant) {
+        if self.ttl.is_zero() || batch.num_rows() > 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_key, entry| entry.expires > now);
+            if entries.len() >= MAX_ENTRIES {
+                return;
+            }
+        }
+        let nb_misses = entries.get(&key).map_or(1, |entry| entry.nb_misses + 1);
+        let ttl = self
+            .ttl
+            .checked_mul(1 << (nb_misses - 1).min(16))
+            .unwrap_or(self.max_ttl)
+            .min(self.max_ttl);
+        entries.insert(
+            key,
+            EmptyResultEntry {
+                batch: batch.clone(),
+                expires: now + ttl,
+                nb_misses,
+            },
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
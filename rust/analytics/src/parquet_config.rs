@@ -0,0 +1,232 @@
+//! Parquet writer settings of the results of each view, declared in the `parquet` section of the views configuration
+//!
+//! ```json
+//! {
+//!     "parquet": {
+//!         "spans": { "compression": "zstd(6)", "max_row_group_size": 65536 },
+//!         "log_entries": { "compression": "lz4_raw", "column_encodings": { "time": "delta_binary_packed" } },
+//!         "blocks": { "bloom_filter_columns": ["block_id", "stream_id", "process_id"], "bloom_filter_ndv": 100000 },
+//!         "processes": { "sort_order": ["exe", "start_time DESC"] }
+//!     }
+//! }
+//! ```
+//!
+//! Bloom filters let readers skip the row groups that can't contain an id without scanning them.
+//! They are sized for `bloom_filter_ndv` distinct values per row group, a million by default,
+//! which is wasteful for small results: enable them on the id columns of large views.
+//!
+//! Rows sorted by a `sort_order` have narrow min/max statistics per row group and page, so
+//! selective readers skip most of them. The order is recorded in the `sorting_columns` of the
+//! row groups, readers can rely on it instead of sorting again.
+use anyhow::{Context, Result};
+use datafusion::arrow::array::ArrayRef;
+use datafusion::arrow::compute::{lexsort_to_indices, take, SortColumn, SortOptions};
+use datafusion::arrow::datatypes::{DataType, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::basic::{Compression, Encoding};
+use datafusion::parquet::file::properties::{
+    WriterProperties, WriterPropertiesBuilder, WriterVersion,
+};
+use datafusion::parquet::format::SortingColumn;
+use datafusion::parquet::schema::types::ColumnPath;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Views served by the stream endpoints, configurable like the views of the registry
+pub const STREAM_VIEWS: [&str; 4] = ["spans", "thread_events", "log_entries", "measures"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParquetWriterConfig {
+    /// `lz4_raw` by default, `zstd(level)`, `snappy`, `gzip(level)`, `uncompressed`, ...
+    pub compression: Option<String>,
+    pub dictionary_enabled: Option<bool>,
+    /// column name -> encoding, i.e. `delta_binary_packed`
+    #[serde(default)]
+    pub column_encodings: BTreeMap<String, String>,
+    pub max_row_group_size: Option<usize>,
+    /// high-cardinality columns used in point lookups, i.e. `process_id`
+    #[serde(default)]
+    pub bloom_filter_columns: Vec<String>,
+    /// false positive probability, in (0, 1)
+    pub bloom_filter_fpp: Option<f64>,
+    /// expected number of distinct values per row group
+    pub bloom_filter_ndv: Option<u64>,
+    /// columns the rows are sorted by before they are written, i.e. `["process_id", "time DESC"]`
+    #[serde(default)]
+    pub sort_order: Vec<String>,
+}
+
+/// Column of a sort order, nulls come last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl SortKey {
+    /// `column`, `column ASC` or `column DESC`
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut words = text.split_whitespace();
+        let column = words
+            .next()
+            .with_context(|| "empty column in sort order")?
+            .to_owned();
+        let descending = match words.next().map(str::to_ascii_uppercase).as_deref() {
+            None | Some("ASC") => false,
+            Some("DESC") => true,
+            Some(other) => anyhow::bail!("invalid direction {other} for column {column}"),
+        };
+        if words.next().is_some() {
+            anyhow::bail!("invalid sort key {text:?}");
+        }
+        Ok(Self { column, descending })
+    }
+}
+
+impl ParquetWriterConfig {
+    pub fn writer_properties(&self) -> Result<WriterProperties> {
+        Ok(self.writer_properties_builder()?.build())
+    }
+
+    pub fn sort_keys(&self) -> Result<Vec<SortKey>> {
+        self.sort_order
+            .iter()
+            .map(|key| SortKey::parse(key))
+            .collect()
+    }
+
+    /// Sorts the rows by the sort order, if any, and returns the settings to write them with
+    pub fn prepare_batch(&self, batch: &RecordBatch) -> Result<(RecordBatch, WriterProperties)> {
+        let keys = self.sort_keys()?;
+        if keys.is_empty() {
+            return Ok((batch.clone(), self.writer_properties()?));
+        }
+        let sorting_columns = sorting_columns(batch.schema_ref(), &keys)?;
+        let sorted = sort_batch(batch, &keys)?;
+        let props = self
+            .writer_properties_builder()?
+            .set_sorting_columns(Some(sorting_columns))
+            .build();
+        Ok((sorted, props))
+    }
+
+    fn writer_properties_builder(&self) -> Result<WriterPropertiesBuilder> {
+        let mut builder = default_writer_properties_builder();
+        if let Some(compression) = &self.compression {
+            let compression: Compression = compression
+                .parse()
+                .with_context(|| format!("parsing compression {compression}"))?;
+            builder = builder.set_compression(compression);
+        }
+        if let Some(enabled) = self.dictionary_enabled {
+            builder = builder.set_dictionary_enabled(enabled);
+        }
+        for (column, encoding) in &self.column_encodings {
+            let encoding: Encoding = encoding
+                .parse()
+                .with_context(|| format!("parsing encoding of column {column}"))?;
+            // the writer panics when asked for a dictionary encoding this way
+            if matches!(
+                encoding,
+                Encoding::PLAIN_DICTIONARY | Encoding::RLE_DICTIONARY
+            ) {
+                anyhow::bail!(
+                    "column {column}: dictionary encodings are selected with dictionary_enabled"
+                );
+            }
+            builder = builder.set_column_encoding(ColumnPath::from(column.as_str()), encoding);
+        }
+        if let Some(size) = self.max_row_group_size {
+            if size == 0 {
+                anyhow::bail!("max_row_group_size should be positive");
+            }
+            builder = builder.set_max_row_group_size(size);
+        }
+        if let Some(fpp) = self.bloom_filter_fpp {
+            // the writer panics outside of this range
+            if fpp.is_nan() || fpp <= 0.0 || fpp >= 1.0 {
+                anyhow::bail!("bloom_filter_fpp should be between 0 and 1 exclusive");
+            }
+        }
+        for column in &self.bloom_filter_columns {
+            let path = ColumnPath::from(column.as_str());
+            builder = builder.set_column_bloom_filter_enabled(path.clone(), true);
+            if let Some(fpp) = self.bloom_filter_fpp {
+                builder = builder.set_column_bloom_filter_fpp(path.clone(), fpp);
+            }
+            if let Some(ndv) = self.bloom_filter_ndv {
+                builder = builder.set_column_bloom_filter_ndv(path, ndv);
+            }
+        }
+        self.sort_keys()?;
+        Ok(builder)
+    }
+}
+
+pub fn sort_batch(batch: &RecordBatch, keys: &[SortKey]) -> Result<RecordBatch> {
+    let mut sort_columns = vec![];
+    for key in keys {
+        let values = batch
+            .column_by_name(&key.column)
+            .with_context(|| format!("sort column {} not found", key.column))?;
+        sort_columns.push(SortColumn {
+            values: values.clone(),
+            options: Some(SortOptions {
+                descending: key.descending,
+                nulls_first: false,
+            }),
+        });
+    }
+    let indices = lexsort_to_indices(&sort_columns, None).with_context(|| "sorting rows")?;
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<Result<Vec<ArrayRef>, _>>()?;
+    RecordBatch::try_new(batch.schema(), columns).with_context(|| "building sorted batch")
+}
+
+/// Number of parquet leaf columns storing a field
+fn nb_leaves(data_type: &DataType) -> usize {
+    match data_type {
+        DataType::Struct(fields) => fields.iter().map(|f| nb_leaves(f.data_type())).sum(),
+        DataType::List(field)
+        | DataType::LargeList(field)
+        | DataType::FixedSizeList(field, _)
+        | DataType::Map(field, _) => nb_leaves(field.data_type()),
+        _ => 1,
+    }
+}
+
+/// The sort order as recorded in the row groups, where columns are numbered by parquet leaf
+fn sorting_columns(schema: &Schema, keys: &[SortKey]) -> Result<Vec<SortingColumn>> {
+    keys.iter()
+        .map(|key| {
+            let index = schema
+                .index_of(&key.column)
+                .with_context(|| format!("sort column {} not found", key.column))?;
+            if schema.field(index).data_type().is_nested() {
+                anyhow::bail!("can't sort by nested column {}", key.column);
+            }
+            let leaf: usize = schema.fields()[..index]
+                .iter()
+                .map(|f| nb_leaves(f.data_type()))
+                .sum();
+            Ok(SortingColumn {
+                column_idx: i32::try_from(leaf)?,
+                descending: key.descending,
+                nulls_first: false,
+            })
+        })
+        .collect()
+}
+
+fn default_writer_properties_builder() -> WriterPropertiesBuilder {
+    WriterProperties::builder()
+        .set_writer_version(WriterVersion::PARQUET_2_0)
+        .set_compression(Compression::LZ4_RAW)
+}
+
+pub fn default_writer_properties() -> WriterProperties {
+    default_writer_properties_builder().build()
+}
@@ -0,0 +1,98 @@
+//! Process properties promoted to columns of the views, declared per deployment
+//!
+//! ```json
+//! {
+//!     "promoted_properties": ["version", "platform", "region"]
+//! }
+//! ```
+//!
+//! Each promoted property becomes a column of the `processes`, `log_entries` and `measures`
+//! views, named after the property and null for the processes without it. Grouping and
+//! filtering on these columns does not go through the properties list.
+use crate::log_entries_table::log_entries_schema;
+use crate::metrics_table::measures_schema;
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{ArrayRef, StringArray};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Columns of the processes table
+const PROCESS_COLUMNS: &[&str] = &[
+    "process_id",
+    "exe",
+    "username",
+    "realname",
+    "computer",
+    "distro",
+    "cpu_brand",
+    "tsc_frequency",
+    "start_time",
+    "start_ticks",
+    "insert_time",
+    "parent_process_id",
+    "properties",
+];
+
+fn is_valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Promoted properties are spliced into sql statements as column names, they can't shadow the
+/// columns of the views
+pub fn validate_promoted_properties(names: &[String]) -> Result<()> {
+    let mut columns: Vec<String> = PROCESS_COLUMNS.iter().map(|&c| c.to_owned()).collect();
+    for schema in [log_entries_schema(true, true), measures_schema(true)] {
+        columns.extend(schema.fields().iter().map(|f| f.name().clone()));
+    }
+    for name in names {
+        if !is_valid_identifier(name) {
+            anyhow::bail!("invalid promoted property {name:?}");
+        }
+        if columns.contains(name) {
+            anyhow::bail!("promoted property {name} conflicts with a column of the views");
+        }
+        columns.push(name.clone());
+    }
+    Ok(())
+}
+
+/// Select list of the processes table with the promoted properties as columns
+pub fn processes_select_list(names: &[String]) -> String {
+    let mut select_list = String::from("*");
+    for name in names {
+        select_list.push_str(&format!(
+            ", (SELECT p.value FROM unnest(properties) AS p WHERE p.key = '{name}' LIMIT 1) AS {name}"
+        ));
+    }
+    select_list
+}
+
+/// Appends the promoted properties of the process to the rows of one of its streams
+pub fn append_property_columns(
+    batch: RecordBatch,
+    properties: &HashMap<String, String>,
+    names: &[String],
+) -> Result<RecordBatch> {
+    if names.is_empty() {
+        return Ok(batch);
+    }
+    let mut fields: Vec<Field> = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.as_ref().clone())
+        .collect();
+    let mut columns = batch.columns().to_vec();
+    for name in names {
+        fields.push(Field::new(name, DataType::Utf8, true));
+        let value = properties.get(name).map(String::as_str);
+        let column: ArrayRef = Arc::new(StringArray::from(vec![value; batch.num_rows()]));
+        columns.push(column);
+    }
+    let schema = Schema::new_with_metadata(fields, batch.schema().metadata().clone());
+    RecordBatch::try_new(Arc::new(schema), columns).with_context(|| "appending promoted properties")
+}
@@ -0,0 +1,51 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use micromegas_tracing::prelude::*;
+
+use crate::sql_arrow_bridge::rows_to_record_batch;
+use crate::view_config::ViewDefinition;
+
+/// Counts the occurrences of each value of a property key in the rows of a view
+///
+/// The properties are unnested and aggregated in the database so that only
+/// the distinct values travel back, which is what filter dropdowns need.
+#[span_fn]
+pub async fn query_property_histogram(
+    data_lake: &DataLakeConnection,
+    view: &ViewDefinition,
+    key: &str,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    limit: i64,
+) -> Result<RecordBatch> {
+    let view_sql = &view.sql;
+    let time_column = &view.time_column;
+    let sql = format!(
+        "SELECT p.value AS value,
+                count(*) AS count
+         FROM ({view_sql}) AS v, unnest(v.properties) AS p
+         WHERE p.key = $1
+         AND v.{time_column} >= $2
+         AND v.{time_column} < $3
+         GROUP BY p.value
+         ORDER BY count DESC, p.value
+         LIMIT $4;"
+    );
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let rows = instrument_query(
+        &sql,
+        sqlx::query(&sql)
+            .bind(key)
+            .bind(begin)
+            .bind(end)
+            .bind(limit)
+            .fetch_all(&mut *connection),
+    )
+    .await
+    .with_context(|| "fetching property histogram")?;
+    drop(connection);
+    rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")
+}
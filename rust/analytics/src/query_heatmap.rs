@@ -0,0 +1,157 @@
+//! Hits of the views, to guide which ones deserve to be materialized eagerly
+//!
+//! Each query of a view is counted in the days of the time range it covers, along with its
+//! latency. Views scoped to a stream are counted per stream, the other views have an empty
+//! instance. The cells are kept in memory since the server started and served as the
+//! `query_heatmap` table, the oldest days are forgotten first.
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{ArrayRef, StringArray, TimestampNanosecondArray, UInt64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_MAX_CELLS: usize = 100_000;
+const NS_PER_DAY: i64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HeatmapCell {
+    pub view: String,
+    /// stream_id of the stream-scoped views, empty for the others
+    pub instance: String,
+    /// beginning of the queried day, in nanoseconds since the epoch
+    pub day: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CellHits {
+    pub nb_queries: u64,
+    pub nb_errors: u64,
+    pub duration_ns: u64,
+    pub max_duration_ns: u64,
+}
+
+#[derive(Debug)]
+pub struct QueryHeatmap {
+    max_cells: usize,
+    cells: Mutex<BTreeMap<HeatmapCell, CellHits>>,
+}
+
+impl Default for QueryHeatmap {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CELLS)
+    }
+}
+
+/// Days overlapping [begin, end), the day of `begin` for empty ranges
+fn queried_days(begin: DateTime<Utc>, end: DateTime<Utc>) -> Vec<i64> {
+    let begin = begin.timestamp_nanos_opt().unwrap_or_default();
+    let end = end.timestamp_nanos_opt().unwrap_or_default();
+    let mut days = vec![begin.div_euclid(NS_PER_DAY) * NS_PER_DAY];
+    while let Some(next) = days.last().and_then(|day| day.checked_add(NS_PER_DAY)) {
+        if next >= end {
+            break;
+        }
+        days.push(next);
+    }
+    days
+}
+
+impl QueryHeatmap {
+    pub fn new(max_cells: usize) -> Self {
+        Self {
+            max_cells,
+            cells: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(
+        &self,
+        view: &str,
+        instance: &str,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        success: bool,
+        duration: Duration,
+    ) {
+        let duration_ns = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let mut cells = self.cells.lock().unwrap();
+        for day in queried_days(begin, end) {
+            let cell = HeatmapCell {
+                view: view.to_owned(),
+                instance: instance.to_owned(),
+                day,
+            };
+            if !cells.contains_key(&cell) && !make_room(&mut cells, self.max_cells, day) {
+                continue;
+            }
+            let hits = cells.entry(cell).or_default();
+            hits.nb_queries += 1;
+            hits.nb_errors += u64::from(!success);
+            hits.duration_ns += duration_ns;
+            hits.max_duration_ns = hits.max_duration_ns.max(duration_ns);
+        }
+    }
+
+    pub fn cells(&self) -> BTreeMap<HeatmapCell, CellHits> {
+        self.cells.lock().unwrap().clone()
+    }
+
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        let cells = self.cells();
+        let schema = Schema::new(vec![
+            Field::new("view", DataType::Utf8, false),
+            Field::new("instance", DataType::Utf8, false),
+            Field::new(
+                "day",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+                false,
+            ),
+            Field::new("nb_queries", DataType::UInt64, false),
+            Field::new("nb_errors", DataType::UInt64, false),
+            Field::new("duration_ns", DataType::UInt64, false),
+            Field::new("max_duration_ns", DataType::UInt64, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(cells.keys().map(|c| &c.view))),
+            Arc::new(StringArray::from_iter_values(
+                cells.keys().map(|c| &c.instance),
+            )),
+            Arc::new(
+                TimestampNanosecondArray::from_iter_values(cells.keys().map(|c| c.day))
+                    .with_timezone_utc(),
+            ),
+            Arc::new(UInt64Array::from_iter_values(
+                cells.values().map(|h| h.nb_queries),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                cells.values().map(|h| h.nb_errors),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                cells.values().map(|h| h.duration_ns),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                cells.values().map(|h| h.max_duration_ns),
+            )),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .with_context(|| "building query heatmap record batch")
+    }
+}
+
+/// Forgets a cell of the oldest day to make room for a cell of `day`, unless `day` is older
+fn make_room(cells: &mut BTreeMap<HeatmapCell, CellHits>, max_cells: usize, day: i64) -> bool {
+    if cells.len() < max_cells {
+        return true;
+    }
+    let Some(oldest) = cells.keys().min_by_key(|cell| cell.day).cloned() else {
+        return false;
+    };
+    if oldest.day >= day {
+        return false;
+    }
+    cells.remove(&oldest);
+    true
+}
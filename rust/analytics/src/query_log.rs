@@ -0,0 +1,171 @@
+//! Log of the queries served by the analytics service
+//!
+//! Every query is logged with the `query_log` target, along with its route, tag, duration,
+//! response size and completion status. The number of queries running concurrently is
+//! recorded in the `concurrent_queries` metric. Queries slower than a threshold are kept
+//! in memory and served as the `slow_queries` table.
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{
+    ArrayRef, BooleanArray, StringArray, TimestampNanosecondArray, UInt64Array,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_tracing::prelude::*;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_SLOW_THRESHOLD: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_SLOW_QUERIES: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryLogEntry {
+    pub begin: DateTime<Utc>,
+    pub route: String,
+    pub query_tag: String,
+    pub duration: Duration,
+    pub response_bytes: u64,
+    pub success: bool,
+    /// number of queries running when this one started, itself included
+    pub concurrency: u64,
+}
+
+#[derive(Debug)]
+pub struct QueryLog {
+    slow_threshold: Duration,
+    max_slow_queries: usize,
+    in_flight: Arc<AtomicU64>,
+    slow_queries: Mutex<VecDeque<QueryLogEntry>>,
+}
+
+impl Default for QueryLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_SLOW_THRESHOLD, DEFAULT_MAX_SLOW_QUERIES)
+    }
+}
+
+/// Counts a query as running until dropped
+#[derive(Debug)]
+pub struct InFlightQuery {
+    in_flight: Arc<AtomicU64>,
+    concurrency: u64,
+}
+
+impl InFlightQuery {
+    /// Number of queries running when this one started, itself included
+    pub fn concurrency(&self) -> u64 {
+        self.concurrency
+    }
+}
+
+impl Drop for InFlightQuery {
+    fn drop(&mut self) {
+        let running = self.in_flight.fetch_sub(1, Ordering::Relaxed) - 1;
+        imetric!("concurrent_queries", "count", running);
+    }
+}
+
+impl QueryLog {
+    /// Keeps the last `max_slow_queries` queries taking at least `slow_threshold`
+    pub fn new(slow_threshold: Duration, max_slow_queries: usize) -> Self {
+        Self {
+            slow_threshold,
+            max_slow_queries,
+            in_flight: Arc::new(AtomicU64::new(0)),
+            slow_queries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn slow_threshold(&self) -> Duration {
+        self.slow_threshold
+    }
+
+    /// Call when the query starts, the guard must live until the query completes
+    pub fn begin_query(&self) -> InFlightQuery {
+        let concurrency = self.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+        imetric!("concurrent_queries", "count", concurrency);
+        InFlightQuery {
+            in_flight: self.in_flight.clone(),
+            concurrency,
+        }
+    }
+
+    pub fn nb_in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, entry: QueryLogEntry) {
+        info!(
+            target: "query_log",
+            "route={} tag={} success={} bytes={} duration={:?} concurrency={}",
+            entry.route,
+            entry.query_tag,
+            entry.success,
+            entry.response_bytes,
+            entry.duration,
+            entry.concurrency
+        );
+        if entry.duration < self.slow_threshold || self.max_slow_queries == 0 {
+            return;
+        }
+        let mut slow_queries = self.slow_queries.lock().unwrap();
+        if slow_queries.len() >= self.max_slow_queries {
+            slow_queries.pop_front();
+        }
+        slow_queries.push_back(entry);
+    }
+
+    /// Oldest first
+    pub fn slow_queries(&self) -> Vec<QueryLogEntry> {
+        self.slow_queries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn slow_queries_record_batch(&self) -> Result<RecordBatch> {
+        let entries = self.slow_queries();
+        let schema = Schema::new(vec![
+            Field::new(
+                "begin",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+                false,
+            ),
+            Field::new("route", DataType::Utf8, false),
+            Field::new("query_tag", DataType::Utf8, false),
+            Field::new("duration_ns", DataType::UInt64, false),
+            Field::new("response_bytes", DataType::UInt64, false),
+            Field::new("success", DataType::Boolean, false),
+            Field::new("concurrency", DataType::UInt64, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(
+                TimestampNanosecondArray::from_iter_values(
+                    entries
+                        .iter()
+                        .map(|e| e.begin.timestamp_nanos_opt().unwrap_or_default()),
+                )
+                .with_timezone_utc(),
+            ),
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|e| &e.route),
+            )),
+            Arc::new(StringArray::from_iter_values(
+                entries.iter().map(|e| &e.query_tag),
+            )),
+            Arc::new(UInt64Array::from_iter_values(entries.iter().map(|e| {
+                u64::try_from(e.duration.as_nanos()).unwrap_or(u64::MAX)
+            }))),
+            Arc::new(UInt64Array::from_iter_values(
+                entries.iter().map(|e| e.response_bytes),
+            )),
+            Arc::new(BooleanArray::from_iter(
+                entries.iter().map(|e| Some(e.success)),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                entries.iter().map(|e| e.concurrency),
+            )),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .with_context(|| "building slow queries record batch")
+    }
+}
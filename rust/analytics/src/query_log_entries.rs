@@ -4,12 +4,17 @@ use crate::{
     log_entries_table::LogEntriesRecordBuilder,
     log_entry::for_each_log_entry_in_block,
     metadata::{find_process, find_stream, find_stream_blocks_in_range},
+    promoted_properties::append_property_columns,
+    query_timeout::QueryDeadline,
+    stream_query_options::StreamQueryOptions,
     time::ConvertTicks,
 };
 use anyhow::{Context, Result};
 use datafusion::arrow::record_batch::RecordBatch;
 use micromegas_ingestion::data_lake_connection::DataLakeConnection;
-use micromegas_telemetry::{blob_storage::BlobStorage, types::block::BlockMetadata};
+use micromegas_telemetry::{
+    blob_storage::BlobStorage, stream_info::LOG_CATEGORIES_PROPERTY, types::block::BlockMetadata,
+};
 use micromegas_tracing::prelude::*;
 use sqlx::types::chrono::{DateTime, Utc};
 
@@ -19,6 +24,7 @@ pub async fn query_log_entries(
     begin: DateTime<Utc>,
     end: DateTime<Utc>,
     limit: i64,
+    options: &StreamQueryOptions<'_>,
 ) -> Result<RecordBatch> {
     let mut connection = data_lake.db_pool.acquire().await?;
     let stream_info = find_stream(&mut connection, stream_id)
@@ -40,7 +46,7 @@ pub async fn query_log_entries(
     .with_context(|| "find_stream_blocks_in_range")?;
     drop(connection);
 
-    make_log_entries_record_batch(
+    let batch = make_log_entries_record_batch(
         &blocks,
         begin,
         end,
@@ -48,12 +54,15 @@ pub async fn query_log_entries(
         data_lake.blob_storage.clone(),
         convert_ticks,
         &stream_info,
+        options.with_ticks,
+        options.deadline,
     )
     .await
-    .with_context(|| "make_log_entries_record_batch")
+    .with_context(|| "make_log_entries_record_batch")?;
+    append_property_columns(batch, &process_info.properties, options.promoted_properties)
 }
 
-#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_precision_loss, clippy::too_many_arguments)]
 #[span_fn]
 pub async fn make_log_entries_record_batch(
     blocks: &[BlockMetadata],
@@ -63,28 +72,47 @@ pub async fn make_log_entries_record_batch(
     blob_storage: Arc<BlobStorage>,
     convert_ticks: ConvertTicks,
     stream: &micromegas_telemetry::stream_info::StreamInfo,
+    with_ticks: bool,
+    deadline: &QueryDeadline,
 ) -> Result<RecordBatch> {
     let mut record_builder = LogEntriesRecordBuilder::with_capacity(1024);
+    if with_ticks {
+        record_builder = record_builder.with_ticks();
+    }
+    if stream
+        .properties
+        .get(LOG_CATEGORIES_PROPERTY)
+        .is_some_and(|format| format == "bracketed")
+    {
+        record_builder = record_builder.with_categories();
+    }
     let begin_ns = begin.timestamp_nanos_opt().unwrap_or_default();
     let end_ns = end.timestamp_nanos_opt().unwrap_or_default();
     for block in blocks {
-        for_each_log_entry_in_block(
-            blob_storage.clone(),
-            &convert_ticks,
-            stream,
-            block,
-            |log_entry| {
-                if log_entry.time >= begin_ns
-                    && log_entry.time <= end_ns
-                    && record_builder.len() < limit
-                {
-                    record_builder.append(&log_entry)?;
-                }
-                Ok(log_entry.time <= end_ns && record_builder.len() < limit)
-            },
-        )
-        .await
-        .with_context(|| "for_each_log_entry_in_block")?;
+        if deadline.expired()? {
+            break;
+        }
+        let parsed = deadline
+            .bound(for_each_log_entry_in_block(
+                blob_storage.clone(),
+                &convert_ticks,
+                stream,
+                block,
+                |log_entry| {
+                    if log_entry.time >= begin_ns
+                        && log_entry.time <= end_ns
+                        && record_builder.len() < limit
+                    {
+                        record_builder.append(&log_entry)?;
+                    }
+                    Ok(log_entry.time <= end_ns && record_builder.len() < limit)
+                },
+            ))
+            .await
+            .with_context(|| "for_each_log_entry_in_block")?;
+        if parsed.is_none() {
+            break;
+        }
     }
     record_builder.finish()
 }
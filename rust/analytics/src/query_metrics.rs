@@ -10,6 +10,9 @@ use crate::{
     measure::for_each_measure_in_block,
     metadata::{find_process, find_stream, find_stream_blocks_in_range},
     metrics_table::MetricsRecordBuilder,
+    promoted_properties::append_property_columns,
+    query_timeout::QueryDeadline,
+    stream_query_options::StreamQueryOptions,
     time::ConvertTicks,
 };
 
@@ -19,6 +22,7 @@ pub async fn query_metrics(
     stream_id: sqlx::types::Uuid,
     begin: DateTime<Utc>,
     end: DateTime<Utc>,
+    options: &StreamQueryOptions<'_>,
 ) -> Result<RecordBatch> {
     let mut connection = data_lake.db_pool.acquire().await?;
     let stream_info = find_stream(&mut connection, stream_id)
@@ -40,7 +44,7 @@ pub async fn query_metrics(
     .with_context(|| "find_stream_blocks_in_range")?;
     drop(connection);
 
-    make_metrics_record_batch(
+    let batch = make_metrics_record_batch(
         &blocks,
         limit,
         begin,
@@ -48,12 +52,15 @@ pub async fn query_metrics(
         data_lake.blob_storage.clone(),
         convert_ticks,
         &stream_info,
+        options.with_ticks,
+        options.deadline,
     )
     .await
-    .with_context(|| "make_metrics_record_batch")
+    .with_context(|| "make_metrics_record_batch")?;
+    append_property_columns(batch, &process_info.properties, options.promoted_properties)
 }
 
-#[allow(clippy::cast_precision_loss)]
+#[allow(clippy::cast_precision_loss, clippy::too_many_arguments)]
 #[span_fn]
 pub async fn make_metrics_record_batch(
     blocks: &[BlockMetadata],
@@ -63,32 +70,41 @@ pub async fn make_metrics_record_batch(
     blob_storage: Arc<BlobStorage>,
     convert_ticks: ConvertTicks,
     stream: &micromegas_telemetry::stream_info::StreamInfo,
+    with_ticks: bool,
+    deadline: &QueryDeadline,
 ) -> Result<RecordBatch> {
     let mut record_builder = MetricsRecordBuilder::with_capacity(1024);
+    if with_ticks {
+        record_builder = record_builder.with_ticks();
+    }
     let begin_ns = begin.timestamp_nanos_opt().unwrap_or_default();
     let end_ns = end.timestamp_nanos_opt().unwrap_or_default();
     let mut nb = 0;
     for block in blocks {
-        let continue_iterating = for_each_measure_in_block(
-            blob_storage.clone(),
-            &convert_ticks,
-            stream,
-            block,
-            |measure| {
-                if measure.time < begin_ns {
-                    return Ok(true);
-                }
-                if measure.time > end_ns || nb >= limit {
-                    return Ok(false);
-                }
-                record_builder.append(&measure)?;
-                nb += 1;
-                Ok(nb < limit)
-            },
-        )
-        .await
-        .with_context(|| "for_each_measure_in_block")?;
-        if !continue_iterating {
+        if deadline.expired()? {
+            break;
+        }
+        let continue_iterating = deadline
+            .bound(for_each_measure_in_block(
+                blob_storage.clone(),
+                &convert_ticks,
+                stream,
+                block,
+                |measure| {
+                    if measure.time < begin_ns {
+                        return Ok(true);
+                    }
+                    if measure.time > end_ns || nb >= limit {
+                        return Ok(false);
+                    }
+                    record_builder.append(&measure)?;
+                    nb += 1;
+                    Ok(nb < limit)
+                },
+            ))
+            .await
+            .with_context(|| "for_each_measure_in_block")?;
+        if continue_iterating != Some(true) {
             break;
         }
     }
@@ -0,0 +1,69 @@
+use crate::{
+    metadata::{find_process, find_stream, find_stream_blocks_in_range},
+    query_timeout::QueryDeadline,
+    span_events_table::SpanEventsRecordBuilder,
+    thread_block_processor::parse_thread_block,
+    time::ConvertTicks,
+};
+use anyhow::{Context, Result};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_tracing::prelude::*;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::cmp::max;
+
+/// Markers recorded with `span_event!` in a thread stream
+#[span_fn]
+pub async fn query_span_events(
+    data_lake: &DataLakeConnection,
+    limit: i64,
+    stream_id: sqlx::types::Uuid,
+    mut begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    deadline: &QueryDeadline,
+) -> Result<RecordBatch> {
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let stream_info = find_stream(&mut connection, stream_id)
+        .await
+        .with_context(|| "find_stream")?;
+    let process_info = find_process(&mut connection, &stream_info.process_id)
+        .await
+        .with_context(|| "find_process")?;
+    let convert_ticks = ConvertTicks::new(&process_info);
+    begin = max(begin, process_info.start_time);
+    let relative_begin_ticks = convert_ticks.to_ticks(begin - process_info.start_time);
+    let relative_end_ticks = convert_ticks.to_ticks(end - process_info.start_time);
+    let blocks = find_stream_blocks_in_range(
+        &mut connection,
+        stream_id,
+        relative_begin_ticks,
+        relative_end_ticks,
+    )
+    .await
+    .with_context(|| "find_stream_blocks_in_range")?;
+    drop(connection);
+
+    let mut record_builder = SpanEventsRecordBuilder::new(
+        convert_ticks.ticks_to_nanoseconds(relative_begin_ticks + process_info.start_ticks),
+        convert_ticks.ticks_to_nanoseconds(relative_end_ticks + process_info.start_ticks),
+        limit,
+        convert_ticks,
+    );
+    for block in &blocks {
+        if deadline.expired()? {
+            break;
+        }
+        let cont = deadline
+            .bound(parse_thread_block(
+                data_lake.blob_storage.clone(),
+                &stream_info,
+                block,
+                &mut record_builder,
+            ))
+            .await?;
+        if cont != Some(true) {
+            break;
+        }
+    }
+    record_builder.finish()
+}
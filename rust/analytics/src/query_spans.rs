@@ -6,6 +6,7 @@ use std::{
 use crate::{
     call_tree::make_call_tree,
     metadata::{find_process, find_stream, find_stream_blocks_in_range},
+    query_timeout::QueryDeadline,
     span_table::SpanRecordBuilder,
     time::ConvertTicks,
 };
@@ -20,9 +21,36 @@ pub async fn query_spans(
     data_lake: &DataLakeConnection,
     limit: i64,
     stream_id: sqlx::types::Uuid,
-    mut begin: DateTime<Utc>,
+    begin: DateTime<Utc>,
     end: DateTime<Utc>,
+    deadline: &QueryDeadline,
 ) -> Result<RecordBatch> {
+    query_spans_in_selected_blocks(
+        data_lake,
+        limit,
+        stream_id,
+        begin,
+        end,
+        deadline,
+        |blocks| blocks,
+    )
+    .await
+}
+
+/// Builds the spans of the blocks kept by `select_blocks`, which is called
+/// before any payload is fetched
+pub async fn query_spans_in_selected_blocks<F>(
+    data_lake: &DataLakeConnection,
+    limit: i64,
+    stream_id: sqlx::types::Uuid,
+    mut begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    deadline: &QueryDeadline,
+    select_blocks: F,
+) -> Result<RecordBatch>
+where
+    F: FnOnce(Vec<BlockMetadata>) -> Vec<BlockMetadata>,
+{
     let mut connection = data_lake.db_pool.acquire().await?;
     let stream_info = find_stream(&mut connection, stream_id)
         .await
@@ -43,6 +71,7 @@ pub async fn query_spans(
     .await
     .with_context(|| "find_stream_blocks_in_range")?;
     drop(connection);
+    let blocks = select_blocks(blocks);
 
     let mut record_builder = SpanRecordBuilder::with_capacity(1024); //todo: replace with number of nodes
 
@@ -53,7 +82,37 @@ pub async fn query_spans(
             last_end = Some(block.end_ticks);
             blocks_to_process.push(block);
         } else {
-            let cont = append_call_tree(
+            if deadline.expired()? {
+                return record_builder
+                    .finish()
+                    .with_context(|| "finalizing span record builder");
+            }
+            let cont = deadline
+                .bound(append_call_tree(
+                    &mut record_builder,
+                    &process_info,
+                    &blocks_to_process,
+                    relative_begin_ticks,
+                    relative_end_ticks,
+                    limit,
+                    data_lake.blob_storage.clone(),
+                    &stream_info,
+                ))
+                .await?;
+            last_end = Some(block.end_ticks);
+            blocks_to_process = vec![block];
+
+            if cont != Some(true) {
+                return record_builder
+                    .finish()
+                    .with_context(|| "finalizing span record builder");
+            }
+        }
+    }
+
+    if !blocks_to_process.is_empty() && !deadline.expired()? {
+        deadline
+            .bound(append_call_tree(
                 &mut record_builder,
                 &process_info,
                 &blocks_to_process,
@@ -62,31 +121,8 @@ pub async fn query_spans(
                 limit,
                 data_lake.blob_storage.clone(),
                 &stream_info,
-            )
+            ))
             .await?;
-            blocks_to_process = vec![];
-            last_end = None;
-
-            if !cont {
-                return record_builder
-                    .finish()
-                    .with_context(|| "finalizing span record builder");
-            }
-        }
-    }
-
-    if !blocks_to_process.is_empty() {
-        append_call_tree(
-            &mut record_builder,
-            &process_info,
-            &blocks_to_process,
-            relative_begin_ticks,
-            relative_end_ticks,
-            limit,
-            data_lake.blob_storage.clone(),
-            &stream_info,
-        )
-        .await?;
         drop(blocks_to_process);
     }
 
@@ -0,0 +1,140 @@
+//! Attribution of the analytics load to the teams and dashboards sending the queries
+//!
+//! Clients tag their requests with the `x-micromegas-query-tag` header and can describe
+//! themselves with `x-micromegas-client-info`. Each request is logged with its tag,
+//! recorded in per-tag metrics and accumulated in stats kept since the server started.
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{ArrayRef, StringArray, UInt64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_tracing::dispatch::int_metric;
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::metrics::make_metric_metadata;
+use micromegas_tracing::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub const QUERY_TAG_HEADER: &str = "x-micromegas-query-tag";
+pub const CLIENT_INFO_HEADER: &str = "x-micromegas-client-info";
+
+const UNTAGGED: &str = "untagged";
+const MAX_TAG_LEN: usize = 64;
+/// Tag of the queries received once the maximum number of distinct tags is reached
+pub const OTHER_TAGS: &str = "other";
+const DEFAULT_MAX_TAGS: usize = 100;
+
+/// Keeps tags usable as metric targets: bounded length, no spaces or control characters
+pub fn sanitize_query_tag(tag: Option<&str>) -> String {
+    let sanitized: String = tag
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(MAX_TAG_LEN)
+        .collect();
+    if sanitized.is_empty() {
+        UNTAGGED.to_owned()
+    } else {
+        sanitized
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TagLoad {
+    pub nb_queries: u64,
+    pub nb_errors: u64,
+    pub response_bytes: u64,
+    pub duration_ns: u64,
+}
+
+#[derive(Debug)]
+pub struct QueryTagStats {
+    max_tags: usize,
+    loads: Mutex<BTreeMap<String, TagLoad>>,
+}
+
+impl Default for QueryTagStats {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_TAGS)
+    }
+}
+
+impl QueryTagStats {
+    /// Tags past `max_tags` distinct ones are accounted as `other`
+    pub fn new(max_tags: usize) -> Self {
+        Self {
+            max_tags,
+            loads: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(
+        &self,
+        tag: &str,
+        client_info: Option<&str>,
+        route: &str,
+        success: bool,
+        duration: Duration,
+        response_bytes: u64,
+    ) {
+        let duration_ns = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        info!(
+            "query tag={tag} client={} route={route} success={success} bytes={response_bytes} duration={duration:?}",
+            client_info.unwrap_or_default()
+        );
+        let mut loads = self.loads.lock().unwrap();
+        // tags come from the clients: their number is bounded to keep the interned
+        // metric targets and the stats from growing without limit
+        let tag = if loads.contains_key(tag) || loads.len() < self.max_tags {
+            tag
+        } else {
+            OTHER_TAGS
+        };
+        let target = intern_string(tag);
+        int_metric(
+            make_metric_metadata("tagged_query_duration", "ns", target),
+            duration_ns,
+        );
+        int_metric(
+            make_metric_metadata("tagged_query_response_size", "bytes", target),
+            response_bytes,
+        );
+        let load = loads.entry(tag.to_owned()).or_default();
+        load.nb_queries += 1;
+        load.nb_errors += u64::from(!success);
+        load.response_bytes += response_bytes;
+        load.duration_ns += duration_ns;
+    }
+
+    pub fn loads(&self) -> BTreeMap<String, TagLoad> {
+        self.loads.lock().unwrap().clone()
+    }
+
+    pub fn to_record_batch(&self) -> Result<RecordBatch> {
+        let loads = self.loads();
+        let schema = Schema::new(vec![
+            Field::new("query_tag", DataType::Utf8, false),
+            Field::new("nb_queries", DataType::UInt64, false),
+            Field::new("nb_errors", DataType::UInt64, false),
+            Field::new("response_bytes", DataType::UInt64, false),
+            Field::new("duration_ns", DataType::UInt64, false),
+        ]);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from_iter_values(loads.keys())),
+            Arc::new(UInt64Array::from_iter_values(
+                loads.values().map(|l| l.nb_queries),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                loads.values().map(|l| l.nb_errors),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                loads.values().map(|l| l.response_bytes),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                loads.values().map(|l| l.duration_ns),
+            )),
+        ];
+        RecordBatch::try_new(Arc::new(schema), columns)
+            .with_context(|| "building query tag stats record batch")
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
     metadata::{find_process, find_stream, find_stream_blocks_in_range},
+    query_timeout::QueryDeadline,
     thread_block_processor::parse_thread_block,
     thread_events_table::ThreadEventsRecordBuilder,
     time::ConvertTicks,
@@ -18,6 +19,7 @@ pub async fn query_thread_events(
     stream_id: sqlx::types::Uuid,
     mut begin: DateTime<Utc>,
     end: DateTime<Utc>,
+    deadline: &QueryDeadline,
 ) -> Result<RecordBatch> {
     let mut connection = data_lake.db_pool.acquire().await?;
     let stream_info = find_stream(&mut connection, stream_id)
@@ -52,11 +54,13 @@ pub async fn query_thread_events(
         data_lake.blob_storage.clone(),
         convert_ticks,
         &stream_info,
+        deadline,
     )
     .await
     .with_context(|| "make_thread_events_record_batch")
 }
 
+#[allow(clippy::too_many_arguments)]
 #[span_fn]
 pub async fn make_thread_events_record_batch(
     blocks: &[BlockMetadata],
@@ -66,6 +70,7 @@ pub async fn make_thread_events_record_batch(
     blob_storage: Arc<BlobStorage>,
     convert_ticks: ConvertTicks,
     stream: &micromegas_telemetry::stream_info::StreamInfo,
+    deadline: &QueryDeadline,
 ) -> Result<RecordBatch> {
     let mut record_builder = ThreadEventsRecordBuilder::new(
         begin_query_ns,
@@ -75,15 +80,18 @@ pub async fn make_thread_events_record_batch(
         1024 * 1024,
     ); // should we use limit as capacity, we would then always allocate the worst case
     for block in blocks {
-        let cont = parse_thread_block(
-            blob_storage.clone(),
-            stream,
-            block.block_id,
-            block.object_offset,
-            &mut record_builder,
-        )
-        .await?;
-        if !cont {
+        if deadline.expired()? {
+            break;
+        }
+        let cont = deadline
+            .bound(parse_thread_block(
+                blob_storage.clone(),
+                stream,
+                block,
+                &mut record_builder,
+            ))
+            .await?;
+        if cont != Some(true) {
             break;
         }
     }
@@ -0,0 +1,138 @@
+//! Per-query timeouts, requested by the clients in the `x-micromegas-query-timeout-ms` header
+//!
+//! When the deadline is reached, the query either fails (`abort` mode) or stops reading blocks
+//! and returns the rows produced so far (`partial` mode). Truncated results are flagged
+//! in the metadata of their schema.
+use anyhow::{Context, Result};
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::record_batch::RecordBatch;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const QUERY_TIMEOUT_HEADER: &str = "x-micromegas-query-timeout-ms";
+pub const QUERY_TIMEOUT_MODE_HEADER: &str = "x-micromegas-query-timeout-mode";
+/// Schema metadata key set to `true` when the rows of a result are incomplete
+pub const TRUNCATED_METADATA_KEY: &str = "truncated";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutMode {
+    Abort,
+    Partial,
+}
+
+impl FromStr for TimeoutMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(Self::Abort),
+            "partial" => Ok(Self::Partial),
+            _ => anyhow::bail!("unknown query timeout mode {s}, expected abort or partial"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTimeout {
+    pub timeout: Duration,
+    pub mode: TimeoutMode,
+}
+
+impl QueryTimeout {
+    /// Parses the values of the timeout headers, the mode defaults to `abort`
+    pub fn parse(timeout_ms: &str, mode: Option<&str>) -> Result<Self> {
+        let timeout_ms: u64 = timeout_ms
+            .trim()
+            .parse()
+            .with_context(|| format!("parsing query timeout {timeout_ms}"))?;
+        Ok(Self {
+            timeout: Duration::from_millis(timeout_ms),
+            mode: mode.map_or(Ok(TimeoutMode::Abort), str::parse)?,
+        })
+    }
+}
+
+/// Checked by the queries between blocks, and bounds the awaits on the database and object store
+#[derive(Debug)]
+pub struct QueryDeadline {
+    deadline: Option<(Instant, TimeoutMode)>,
+    truncated: AtomicBool,
+}
+
+impl QueryDeadline {
+    pub fn unbounded() -> Self {
+        Self {
+            deadline: None,
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    pub fn new(timeout: Option<&QueryTimeout>) -> Self {
+        Self {
+            deadline: timeout.map(|t| (Instant::now() + t.timeout, t.mode)),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns true if the query should stop producing rows, fails in `abort` mode
+    pub fn expired(&self) -> Result<bool> {
+        match self.deadline {
+            Some((deadline, mode)) if Instant::now() >= deadline => {
+                if mode == TimeoutMode::Abort {
+                    anyhow::bail!("query timed out");
+                }
+                self.truncated.store(true, Ordering::Relaxed);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Cancels `fut` at the deadline: fails in `abort` mode, returns `None` in `partial` mode
+    pub async fn bound<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<Option<T>> {
+        let Some((deadline, mode)) = self.deadline else {
+            return fut.await.map(Some);
+        };
+        match tokio::time::timeout_at(deadline.into(), fut).await {
+            Ok(res) => res.map(Some),
+            Err(_elapsed) => {
+                if mode == TimeoutMode::Abort {
+                    anyhow::bail!("query timed out");
+                }
+                self.truncated.store(true, Ordering::Relaxed);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Cancels `fut` at the deadline in `abort` mode, for the queries that can't return partial rows
+    pub async fn abort_at_deadline<T>(&self, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.deadline {
+            Some((deadline, TimeoutMode::Abort)) => tokio::time::timeout_at(deadline.into(), fut)
+                .await
+                .unwrap_or_else(|_elapsed| anyhow::bail!("query timed out")),
+            _ => fut.await,
+        }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+
+    /// Flags the result if the deadline cut it short
+    pub fn finish(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        if !self.is_truncated() {
+            return Ok(batch);
+        }
+        let schema = batch.schema();
+        let mut metadata = schema.metadata().clone();
+        metadata.insert(TRUNCATED_METADATA_KEY.to_owned(), "true".to_owned());
+        let schema = Schema::new_with_metadata(schema.fields().clone(), metadata);
+        batch
+            .with_schema(Arc::new(schema))
+            .with_context(|| "flagging truncated result")
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{Context, Result};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::sql_instrumentation::instrument_query;
+use micromegas_tracing::prelude::*;
+
+use crate::sql_arrow_bridge::rows_to_record_batch;
+use crate::time_ranges::TimeRange;
+use crate::view_config::ViewDefinition;
+
+/// Returns the rows of a view in any of the time ranges, ordered by the view's time column
+#[span_fn]
+pub async fn query_view(
+    data_lake: &DataLakeConnection,
+    view: &ViewDefinition,
+    ranges: &[TimeRange],
+    limit: i64,
+) -> Result<RecordBatch> {
+    let view_sql = &view.sql;
+    let time_column = &view.time_column;
+    let sql = format!(
+        "SELECT *
+         FROM ({view_sql}) AS v
+         WHERE EXISTS (
+             SELECT 1
+             FROM unnest($1::timestamptz[], $2::timestamptz[]) AS r(range_begin, range_end)
+             WHERE v.{time_column} >= r.range_begin
+             AND v.{time_column} < r.range_end)
+         ORDER BY v.{time_column}
+         LIMIT $3;"
+    );
+    let begins: Vec<_> = ranges.iter().map(|range| range.begin).collect();
+    let ends: Vec<_> = ranges.iter().map(|range| range.end).collect();
+    let mut connection = data_lake.db_pool.acquire().await?;
+    let rows = instrument_query(
+        &sql,
+        sqlx::query(&sql)
+            .bind(begins)
+            .bind(ends)
+            .bind(limit)
+            .fetch_all(&mut *connection),
+    )
+    .await
+    .with_context(|| format!("fetching rows of view {}", view.name))?;
+    drop(connection);
+    rows_to_record_batch(&rows).with_context(|| "converting rows to record batch")
+}
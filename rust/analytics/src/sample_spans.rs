@@ -0,0 +1,140 @@
+//! Statistical subsample of the spans of a thread
+//!
+//! Sampling is done on blocks before their payloads are fetched, so the cost
+//! of a sampled query is proportional to the fraction of the blocks it keeps.
+use anyhow::Result;
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_telemetry::types::block::BlockMetadata;
+use micromegas_tracing::prelude::*;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::str::FromStr;
+use xxhash_rust::xxh32::xxh32;
+
+use crate::query_spans::query_spans_in_selected_blocks;
+use crate::query_timeout::QueryDeadline;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleSize {
+    /// fraction of the blocks to keep, between 0 and 1
+    Fraction(f64),
+    /// approximate number of spans to return
+    MaxRows(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SamplingStrategy {
+    /// blocks evenly spaced in time
+    Uniform,
+    /// blocks picked from a hash of their id, stable across queries
+    Random,
+}
+
+impl FromStr for SamplingStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "uniform" => Ok(Self::Uniform),
+            "random" => Ok(Self::Random),
+            other => anyhow::bail!("unknown sampling strategy {other}"),
+        }
+    }
+}
+
+/// Fraction of the blocks needed to get the requested sample size
+#[allow(clippy::cast_precision_loss)]
+fn sampling_fraction(blocks: &[BlockMetadata], size: SampleSize) -> f64 {
+    match size {
+        SampleSize::Fraction(fraction) => fraction.clamp(0.0, 1.0),
+        SampleSize::MaxRows(max_rows) => {
+            // a span is made of a begin and an end event
+            let estimated_spans: i64 = blocks
+                .iter()
+                .map(|block| i64::from(block.nb_objects) / 2)
+                .sum();
+            if estimated_spans <= max_rows {
+                1.0
+            } else {
+                max_rows as f64 / estimated_spans as f64
+            }
+        }
+    }
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+pub fn sample_blocks(
+    blocks: Vec<BlockMetadata>,
+    size: SampleSize,
+    strategy: SamplingStrategy,
+) -> Vec<BlockMetadata> {
+    let fraction = sampling_fraction(&blocks, size);
+    if fraction >= 1.0 {
+        return blocks;
+    }
+    match strategy {
+        SamplingStrategy::Uniform => {
+            let nb_to_keep = (blocks.len() as f64 * fraction).ceil() as usize;
+            if nb_to_keep == 0 {
+                return vec![];
+            }
+            let step = blocks.len() as f64 / nb_to_keep as f64;
+            let mut next_index = 0.0;
+            blocks
+                .into_iter()
+                .enumerate()
+                .filter(|(index, _)| {
+                    if *index as f64 >= next_index {
+                        next_index += step;
+                        true
+                    } else {
+                        false
+                    }
+                })
+                .map(|(_, block)| block)
+                .collect()
+        }
+        SamplingStrategy::Random => {
+            let threshold = (f64::from(u32::MAX) * fraction) as u32;
+            blocks
+                .into_iter()
+                .filter(|block| xxh32(block.block_id.as_bytes(), 0) < threshold)
+                .collect()
+        }
+    }
+}
+
+#[span_fn]
+pub async fn sample_spans(
+    data_lake: &DataLakeConnection,
+    stream_id: sqlx::types::Uuid,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    size: SampleSize,
+    strategy: SamplingStrategy,
+    deadline: &QueryDeadline,
+) -> Result<RecordBatch> {
+    let limit = match size {
+        SampleSize::Fraction(_) => i64::MAX,
+        SampleSize::MaxRows(max_rows) => max_rows,
+    };
+    query_spans_in_selected_blocks(
+        data_lake,
+        limit,
+        stream_id,
+        begin,
+        end,
+        deadline,
+        |blocks| {
+            let nb_blocks = blocks.len();
+            let sampled = sample_blocks(blocks, size, strategy);
+            debug!("sampled {} blocks out of {nb_blocks}", sampled.len());
+            sampled
+        },
+    )
+    .await
+}
@@ -0,0 +1,198 @@
+use anyhow::{Context, Result};
+use datafusion::arrow::array::StringDictionaryBuilder;
+use datafusion::arrow::datatypes::{DataType, Int64Type, TimeUnit};
+use datafusion::arrow::{
+    array::PrimitiveBuilder,
+    datatypes::{Field, Int16Type, Schema, TimestampNanosecondType, UInt32Type},
+    record_batch::RecordBatch,
+};
+use std::sync::Arc;
+
+use crate::scope::ScopeDesc;
+use crate::thread_block_processor::ThreadBlockProcessor;
+use crate::time::ConvertTicks;
+
+struct SpanEventRow {
+    id: i64,
+    span_id: i64,
+    time: i64,
+    marker: ScopeDesc,
+    properties: Arc<String>,
+}
+
+/// Markers recorded with `span_event!`, along with the id of the span they were recorded in
+///
+/// Span ids follow the convention of the call tree: the id of the begin event of the span,
+/// or the id of its end event when the span began before the first block. Markers outside
+/// of any span have a span_id of -1.
+pub struct SpanEventsRecordBuilder {
+    begin_query_ns: i64,
+    end_query_ns: i64,
+    limit: i64,
+    convert_ticks: ConvertTicks,
+    rows: Vec<SpanEventRow>,
+    // ids of the spans open at this point of the stream, innermost last
+    open_spans: Vec<i64>,
+    // rows in a span that began before the first block, waiting for its end
+    pending: Vec<usize>,
+    done: bool,
+}
+
+impl SpanEventsRecordBuilder {
+    pub fn new(
+        begin_query_ns: i64,
+        end_query_ns: i64,
+        limit: i64,
+        convert_ticks: ConvertTicks,
+    ) -> Self {
+        Self {
+            begin_query_ns,
+            end_query_ns,
+            limit,
+            convert_ticks,
+            rows: Vec::new(),
+            open_spans: Vec::new(),
+            pending: Vec::new(),
+            done: limit <= 0,
+        }
+    }
+
+    // once the rows are collected, the thread events are still parsed to find the ids of the pending spans
+    fn keep_going(&self) -> bool {
+        !self.done || !self.pending.is_empty()
+    }
+
+    pub fn finish(self) -> Result<RecordBatch> {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("span_id", DataType::Int64, false),
+            Field::new(
+                "timestamp",
+                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+                false,
+            ),
+            Field::new("hash", DataType::UInt32, false),
+            Field::new(
+                "name",
+                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new(
+                "target",
+                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new(
+                "filename",
+                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new("line", DataType::UInt32, false),
+            Field::new(
+                "properties",
+                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+                false,
+            ),
+        ]);
+        let capacity = self.rows.len();
+        let mut ids = PrimitiveBuilder::<Int64Type>::with_capacity(capacity);
+        let mut span_ids = PrimitiveBuilder::<Int64Type>::with_capacity(capacity);
+        let mut timestamps = PrimitiveBuilder::<TimestampNanosecondType>::with_capacity(capacity);
+        let mut hashes = PrimitiveBuilder::<UInt32Type>::with_capacity(capacity);
+        let mut names = StringDictionaryBuilder::<Int16Type>::new();
+        let mut targets = StringDictionaryBuilder::<Int16Type>::new();
+        let mut filenames = StringDictionaryBuilder::<Int16Type>::new();
+        let mut lines = PrimitiveBuilder::<UInt32Type>::with_capacity(capacity);
+        let mut properties = StringDictionaryBuilder::<Int16Type>::new();
+        for row in &self.rows {
+            ids.append_value(row.id);
+            span_ids.append_value(row.span_id);
+            timestamps.append_value(row.time);
+            hashes.append_value(row.marker.hash);
+            names.append_value(&*row.marker.name);
+            targets.append_value(&*row.marker.target);
+            filenames.append_value(&*row.marker.filename);
+            lines.append_value(row.marker.line);
+            properties.append_value(&*row.properties);
+        }
+        RecordBatch::try_new(
+            Arc::new(schema),
+            vec![
+                Arc::new(ids.finish()),
+                Arc::new(span_ids.finish()),
+                Arc::new(timestamps.finish().with_timezone_utc()),
+                Arc::new(hashes.finish()),
+                Arc::new(names.finish()),
+                Arc::new(targets.finish()),
+                Arc::new(filenames.finish()),
+                Arc::new(lines.finish()),
+                Arc::new(properties.finish()),
+            ],
+        )
+        .with_context(|| "building record batch")
+    }
+}
+
+impl ThreadBlockProcessor for SpanEventsRecordBuilder {
+    fn on_begin_thread_scope(
+        &mut self,
+        _block_id: &str,
+        event_id: i64,
+        _scope: ScopeDesc,
+        _ts: i64,
+    ) -> Result<bool> {
+        self.open_spans.push(event_id);
+        Ok(self.keep_going())
+    }
+
+    fn on_end_thread_scope(
+        &mut self,
+        _block_id: &str,
+        event_id: i64,
+        _scope: ScopeDesc,
+        _ts: i64,
+    ) -> Result<bool> {
+        if self.open_spans.pop().is_none() {
+            for index in self.pending.drain(..) {
+                self.rows[index].span_id = event_id;
+            }
+        }
+        Ok(self.keep_going())
+    }
+
+    fn on_span_event(
+        &mut self,
+        _block_id: &str,
+        event_id: i64,
+        marker: ScopeDesc,
+        properties: Arc<String>,
+        ts: i64,
+    ) -> Result<bool> {
+        if self.done {
+            return Ok(self.keep_going());
+        }
+        let time = self.convert_ticks.ticks_to_nanoseconds(ts);
+        if time < self.begin_query_ns {
+            return Ok(true);
+        }
+        if time > self.end_query_ns {
+            self.done = true;
+            return Ok(self.keep_going());
+        }
+        let span_id = if let Some(span_id) = self.open_spans.last() {
+            *span_id
+        } else {
+            self.pending.push(self.rows.len());
+            -1
+        };
+        self.rows.push(SpanEventRow {
+            id: event_id,
+            span_id,
+            time,
+            marker,
+            properties,
+        });
+        self.done = self.rows.len() as i64 >= self.limit;
+        Ok(self.keep_going())
+    }
+}
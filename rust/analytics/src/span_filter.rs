@@ -0,0 +1,92 @@
+//! Filters of the spans sent to clients, cutting huge traces down where they are generated
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{AsArray, BooleanArray};
+use datafusion::arrow::compute::{cast, filter_record_batch};
+use datafusion::arrow::datatypes::{DataType, Int64Type};
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::Deserialize;
+
+/// Matches `*` with any sequence of characters and `?` with any single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // position of the last `*` in the pattern and of the text it started matching
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            // the `*` absorbs one more character
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Spans kept in the results of `query_spans`, all spans when empty
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct SpanFilter {
+    /// glob on the name of the spans, i.e. `render_*`
+    #[serde(default)]
+    pub name_filter: Option<String>,
+    /// glob on the target of the spans, i.e. `my_crate::physics*`
+    #[serde(default)]
+    pub target_filter: Option<String>,
+    #[serde(default)]
+    pub min_duration_ns: Option<i64>,
+}
+
+fn glob_mask(batch: &RecordBatch, column: &str, pattern: &str) -> Result<BooleanArray> {
+    let values = cast(
+        batch
+            .column_by_name(column)
+            .with_context(|| format!("no {column} column"))?,
+        &DataType::Utf8,
+    )?;
+    Ok(values
+        .as_string::<i32>()
+        .iter()
+        .map(|value| Some(value.is_some_and(|value| glob_match(pattern, value))))
+        .collect())
+}
+
+impl SpanFilter {
+    pub fn is_empty(&self) -> bool {
+        self.name_filter.is_none() && self.target_filter.is_none() && self.min_duration_ns.is_none()
+    }
+
+    /// Rows of a batch of the `spans` view matching all the filters
+    pub fn filter(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        if self.is_empty() {
+            return Ok(batch.clone());
+        }
+        let mut mask = BooleanArray::from(vec![true; batch.num_rows()]);
+        if let Some(pattern) = &self.name_filter {
+            mask = datafusion::arrow::compute::and(&mask, &glob_mask(batch, "name", pattern)?)?;
+        }
+        if let Some(pattern) = &self.target_filter {
+            mask = datafusion::arrow::compute::and(&mask, &glob_mask(batch, "target", pattern)?)?;
+        }
+        if let Some(min_duration) = self.min_duration_ns {
+            let durations = batch
+                .column_by_name("duration")
+                .with_context(|| "no duration column")?;
+            let long_enough: BooleanArray = durations
+                .as_primitive::<Int64Type>()
+                .iter()
+                .map(|duration| Some(duration.is_some_and(|duration| duration >= min_duration)))
+                .collect();
+            mask = datafusion::arrow::compute::and(&mask, &long_enough)?;
+        }
+        filter_record_batch(batch, &mask).with_context(|| "filtering spans")
+    }
+}
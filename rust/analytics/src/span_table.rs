@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use crate::call_tree::CallTree;
 use crate::call_tree::CallTreeNode;
+use crate::view_docs::ViewDoc;
 use anyhow::{Context, Result};
 use datafusion::arrow::array::ArrayBuilder;
 use datafusion::arrow::array::PrimitiveBuilder;
@@ -114,39 +115,7 @@ impl SpanRecordBuilder {
     }
 
     pub fn finish(mut self) -> Result<RecordBatch> {
-        let schema = Schema::new(vec![
-            Field::new("id", DataType::Int64, false),
-            Field::new("parent", DataType::Int64, false),
-            Field::new("depth", DataType::UInt32, false),
-            Field::new("hash", DataType::UInt32, false),
-            Field::new(
-                "begin",
-                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
-                false,
-            ),
-            Field::new(
-                "end",
-                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
-                false,
-            ),
-            Field::new("duration", DataType::Int64, false), //DataType::Duration not supported by parquet
-            Field::new(
-                "name",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new(
-                "target",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new(
-                "filename",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new("line", DataType::UInt32, false),
-        ]);
+        let schema = spans_schema();
         RecordBatch::try_new(
             Arc::new(schema),
             vec![
@@ -183,3 +152,72 @@ where
     }
     Ok(())
 }
+
+/// Schema of the `spans` view
+pub fn spans_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new("parent", DataType::Int64, false),
+        Field::new("depth", DataType::UInt32, false),
+        Field::new("hash", DataType::UInt32, false),
+        Field::new(
+            "begin",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new(
+            "end",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("duration", DataType::Int64, false), //DataType::Duration not supported by parquet
+        Field::new(
+            "name",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "target",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "filename",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new("line", DataType::UInt32, false),
+    ])
+}
+
+pub fn spans_view_doc() -> ViewDoc {
+    ViewDoc {
+        name: "spans",
+        description: "spans of the call tree of a thread stream, in a time range",
+        deprecated: None,
+        schema: spans_schema(),
+        columns: &[
+            ("id", "id of the span in its call tree"),
+            ("parent", "id of the enclosing span, -1 for the root"),
+            ("depth", "number of ancestors of the span"),
+            ("hash", "hash identifying the scope of the span"),
+            ("begin", "time the span started"),
+            ("end", "time the span ended"),
+            ("duration", "end - begin, in nanoseconds"),
+            ("name", "name of the scope, usually the instrumented function"),
+            ("target", "module path of the instrumented code"),
+            ("filename", "source file of the instrumented code"),
+            ("line", "line of the instrumented code in its source file"),
+        ],
+        examples: &[
+            (
+                "slowest scopes",
+                "SELECT name, count(*) AS nb_calls, sum(duration) AS total, max(duration) AS slowest FROM spans GROUP BY name ORDER BY total DESC",
+            ),
+            (
+                "top level spans with a readable duration",
+                "SELECT begin, name, format_duration(duration) AS duration FROM spans WHERE depth = 0 ORDER BY begin",
+            ),
+        ],
+    }
+}
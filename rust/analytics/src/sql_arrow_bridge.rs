@@ -7,6 +7,7 @@ use datafusion::arrow::array::StructBuilder;
 use datafusion::arrow::datatypes::DataType;
 use datafusion::arrow::datatypes::Field;
 use datafusion::arrow::datatypes::Fields;
+use datafusion::arrow::datatypes::Float64Type;
 use datafusion::arrow::datatypes::Int32Type;
 use datafusion::arrow::datatypes::Int64Type;
 use datafusion::arrow::datatypes::TimeUnit;
@@ -42,13 +43,13 @@ impl ColumnReader for StringColumnReader {
         row: &PgRow,
         struct_builder: &mut StructBuilder,
     ) -> Result<()> {
-        let value: &str = row
+        let value: Option<&str> = row
             .try_get(self.column_ordinal)
             .with_context(|| "try_get failed on row")?;
         let field_builder = struct_builder
             .field_builder::<StringBuilder>(self.column_ordinal)
             .with_context(|| "getting field builder for string column")?;
-        field_builder.append_value(value);
+        field_builder.append_option(value);
         Ok(())
     }
 
@@ -101,13 +102,38 @@ impl ColumnReader for Int64ColumnReader {
         row: &PgRow,
         struct_builder: &mut StructBuilder,
     ) -> Result<()> {
-        let value: i64 = row
+        let value: Option<i64> = row
             .try_get(self.column_ordinal)
             .with_context(|| "try_get failed on row")?;
         let field_builder = struct_builder
             .field_builder::<PrimitiveBuilder<Int64Type>>(self.column_ordinal)
             .with_context(|| "getting field builder for int64 column")?;
-        field_builder.append_value(value);
+        field_builder.append_option(value);
+        Ok(())
+    }
+    fn field(&self) -> Field {
+        self.field.clone()
+    }
+}
+
+pub struct Float64ColumnReader {
+    pub field: Field,
+    pub column_ordinal: usize,
+}
+
+impl ColumnReader for Float64ColumnReader {
+    fn extract_column_from_row(
+        &self,
+        row: &PgRow,
+        struct_builder: &mut StructBuilder,
+    ) -> Result<()> {
+        let value: Option<f64> = row
+            .try_get(self.column_ordinal)
+            .with_context(|| "try_get failed on row")?;
+        let field_builder = struct_builder
+            .field_builder::<PrimitiveBuilder<Float64Type>>(self.column_ordinal)
+            .with_context(|| "getting field builder for float64 column")?;
+        field_builder.append_option(value);
         Ok(())
     }
     fn field(&self) -> Field {
@@ -152,13 +178,14 @@ impl ColumnReader for TimestampColumnReader {
         struct_builder: &mut StructBuilder,
     ) -> Result<()> {
         use sqlx::types::chrono::{DateTime, Utc};
-        let value: DateTime<Utc> = row
+        // columns added by migrations are null in older rows
+        let value: Option<DateTime<Utc>> = row
             .try_get(self.column_ordinal)
             .with_context(|| "try_get failed on row")?;
         let field_builder = struct_builder
             .field_builder::<PrimitiveBuilder<TimestampNanosecondType>>(self.column_ordinal)
             .with_context(|| "getting field builder for timestamp column")?;
-        field_builder.append_value(value.timestamp_nanos_opt().unwrap_or(0));
+        field_builder.append_option(value.map(|time| time.timestamp_nanos_opt().unwrap_or(0)));
         Ok(())
     }
 
@@ -242,7 +269,7 @@ impl ColumnReader for PropertiesColumnReader {
 
 pub fn make_column_reader(column: &PgColumn) -> Result<Arc<dyn ColumnReader>> {
     match column.type_info().name() {
-        "VARCHAR" => Ok(Arc::new(StringColumnReader {
+        "VARCHAR" | "TEXT" => Ok(Arc::new(StringColumnReader {
             field: Field::new(column.name(), DataType::Utf8, true),
             column_ordinal: column.ordinal(),
         })),
@@ -262,6 +289,10 @@ pub fn make_column_reader(column: &PgColumn) -> Result<Arc<dyn ColumnReader>> {
             field: Field::new(column.name(), DataType::Int64, true),
             column_ordinal: column.ordinal(),
         })),
+        "FLOAT8" => Ok(Arc::new(Float64ColumnReader {
+            field: Field::new(column.name(), DataType::Float64, true),
+            column_ordinal: column.ordinal(),
+        })),
         "INT4" => Ok(Arc::new(Int32ColumnReader {
             field: Field::new(column.name(), DataType::Int32, true),
             column_ordinal: column.ordinal(),
@@ -0,0 +1,179 @@
+//! Exports of sql results too large to be returned in a response
+//!
+//! The result batches are written to parquet files in the blob storage as they are produced,
+//! so the server never holds the whole result in memory and is done once the last file is
+//! written. The response is a manifest of the files, which the client downloads directly
+//! from the object store.
+//!
+//! Files are written under `exports/{export_id}/`: a lifecycle rule on the `exports/` prefix
+//! of the bucket is expected to delete them once they have been downloaded.
+use crate::parquet_config::default_writer_properties;
+use crate::sql_session::plan_client_sql;
+use anyhow::{Context, Result};
+use bytes::BufMut;
+use datafusion::arrow::array::{Int64Builder, StringBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::execution::context::SessionContext;
+use datafusion::parquet::arrow::ArrowWriter;
+use futures::StreamExt;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Default number of rows of each exported file
+pub const DEFAULT_ROWS_PER_FILE: usize = 1024 * 1024;
+
+/// Validity of the pre-signed urls of the manifest
+pub const URL_EXPIRATION: Duration = Duration::from_secs(60 * 60);
+
+struct ExportedFile {
+    path: String,
+    nb_rows: i64,
+    size: i64,
+}
+
+type BufferWriter = ArrowWriter<bytes::buf::Writer<bytes::BytesMut>>;
+
+struct FileWriter {
+    export_id: Uuid,
+    schema: SchemaRef,
+    writer: Option<BufferWriter>,
+    nb_rows: usize,
+    files: Vec<ExportedFile>,
+}
+
+impl FileWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => self.writer.insert(ArrowWriter::try_new(
+                bytes::BytesMut::new().writer(),
+                self.schema.clone(),
+                Some(default_writer_properties()),
+            )?),
+        };
+        writer.write(batch)?;
+        self.nb_rows += batch.num_rows();
+        Ok(())
+    }
+
+    async fn flush(&mut self, blob_storage: &BlobStorage) -> Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let buffer: bytes::Bytes = writer.into_inner()?.into_inner().into();
+        let path = format!(
+            "exports/{}/part-{:05}.parquet",
+            self.export_id,
+            self.files.len()
+        );
+        let size = buffer.len() as i64;
+        blob_storage
+            .put(&path, buffer)
+            .await
+            .with_context(|| format!("writing {path}"))?;
+        self.files.push(ExportedFile {
+            path,
+            nb_rows: self.nb_rows as i64,
+            size,
+        });
+        self.nb_rows = 0;
+        Ok(())
+    }
+}
+
+/// `name type` of each column, in order
+fn describe_schema(schema: &Schema) -> String {
+    schema
+        .fields()
+        .iter()
+        .map(|field| format!("{} {}", field.name(), field.data_type()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Runs the statement in the session and writes its result in parquet files of at most
+/// `rows_per_file` rows, returns the manifest of the files
+///
+/// The manifest has one row per file with its `path` in the blob storage, `nb_rows`, `size`
+/// and a pre-signed `url` if the store supports them. The schema of the result is described
+/// in the `schema` metadata of the manifest.
+pub async fn export_sql(
+    ctx: &SessionContext,
+    sql: &str,
+    blob_storage: &BlobStorage,
+    rows_per_file: usize,
+) -> Result<RecordBatch> {
+    let plan = plan_client_sql(&ctx.state(), sql)
+        .await
+        .with_context(|| format!("planning {sql}"))?;
+    let df = ctx
+        .execute_logical_plan(plan)
+        .await
+        .with_context(|| format!("planning {sql}"))?;
+    let schema: SchemaRef = Arc::new(df.schema().into());
+    let mut stream = df
+        .execute_stream()
+        .await
+        .with_context(|| "executing query")?;
+    let mut file_writer = FileWriter {
+        export_id: Uuid::new_v4(),
+        schema: schema.clone(),
+        writer: None,
+        nb_rows: 0,
+        files: vec![],
+    };
+    let rows_per_file = rows_per_file.max(1);
+    while let Some(batch) = stream.next().await {
+        let mut batch = batch.with_context(|| "executing query")?;
+        while batch.num_rows() > 0 {
+            let nb_rows = batch.num_rows().min(rows_per_file - file_writer.nb_rows);
+            file_writer.write(&batch.slice(0, nb_rows))?;
+            batch = batch.slice(nb_rows, batch.num_rows() - nb_rows);
+            if file_writer.nb_rows >= rows_per_file {
+                file_writer.flush(blob_storage).await?;
+            }
+        }
+    }
+    file_writer.flush(blob_storage).await?;
+
+    let mut paths = StringBuilder::new();
+    let mut nb_rows = Int64Builder::new();
+    let mut sizes = Int64Builder::new();
+    let mut urls = StringBuilder::new();
+    for file in &file_writer.files {
+        paths.append_value(&file.path);
+        nb_rows.append_value(file.nb_rows);
+        sizes.append_value(file.size);
+        // stores without pre-signed urls are read with the credentials of the client
+        match blob_storage.signed_url(&file.path, URL_EXPIRATION).await {
+            Ok(url) => urls.append_value(url.as_str()),
+            Err(_) => urls.append_null(),
+        }
+    }
+    let manifest_schema = Schema::new_with_metadata(
+        vec![
+            Field::new("path", DataType::Utf8, false),
+            Field::new("nb_rows", DataType::Int64, false),
+            Field::new("size", DataType::Int64, false),
+            Field::new("url", DataType::Utf8, true),
+        ],
+        HashMap::from([
+            ("export_id".to_owned(), file_writer.export_id.to_string()),
+            ("schema".to_owned(), describe_schema(&schema)),
+        ]),
+    );
+    RecordBatch::try_new(
+        Arc::new(manifest_schema),
+        vec![
+            Arc::new(paths.finish()),
+            Arc::new(nb_rows.finish()),
+            Arc::new(sizes.finish()),
+            Arc::new(urls.finish()),
+        ],
+    )
+    .with_context(|| "building manifest")
+}
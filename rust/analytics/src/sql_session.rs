@@ -0,0 +1,273 @@
+//! Sql sessions: datafusion contexts kept between requests
+//!
+//! Views created with `CREATE [TEMPORARY] VIEW` and results registered under a name stay
+//! available to the next queries of the session, so multi-step analyses can reuse intermediate
+//! results without recomputing them. Sessions are dropped after being idle for a while.
+//!
+//! The time range of a request, when it has one, is visible to its statements as the
+//! `@query_begin` and `@query_end` variables, i.e. to bucket relative to the start of the range.
+//! They are null when the request has no range.
+//!
+//! Spans queried into a session feed its `span_depth`, `span_ancestor` and `span_ancestor_name`
+//! functions, keyed by the id of their stream.
+use crate::dfext::canned_queries::register_canned_queries;
+use crate::dfext::events_within_spans::register_events_within_spans;
+use crate::dfext::frame_budgets::register_frame_budget_violations;
+use crate::dfext::register_extension_functions;
+use crate::dfext::span_hierarchy::{register_span_hierarchy_functions, SpanHierarchy};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use datafusion::arrow::compute::concat_batches;
+use datafusion::arrow::datatypes::{DataType, SchemaRef, TimeUnit};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::{SQLOptions, SessionContext, SessionState};
+use datafusion::logical_expr::{DdlStatement, LogicalPlan};
+use datafusion::scalar::ScalarValue;
+use datafusion::variable::{VarProvider, VarType};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const MAX_SESSIONS: usize = 1024;
+
+pub const QUERY_BEGIN_VARIABLE: &str = "@query_begin";
+pub const QUERY_END_VARIABLE: &str = "@query_end";
+
+/// Time range requested with a statement
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueryRange {
+    pub begin: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl QueryRange {
+    /// Parses the optional rfc3339 bounds of a request
+    pub fn parse(begin: Option<&str>, end: Option<&str>) -> Result<Self> {
+        let parse = |time: Option<&str>| {
+            time.map(|time| {
+                DateTime::parse_from_rfc3339(time)
+                    .map(|time| time.with_timezone(&Utc))
+                    .with_context(|| format!("parsing query range bound {time}"))
+            })
+            .transpose()
+        };
+        Ok(Self {
+            begin: parse(begin)?,
+            end: parse(end)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.begin.is_none() && self.end.is_none()
+    }
+}
+
+fn query_range_variable_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into()))
+}
+
+/// Answers `@query_begin` and `@query_end`
+#[derive(Debug)]
+struct QueryRangeVariables(QueryRange);
+
+impl VarProvider for QueryRangeVariables {
+    fn get_value(&self, var_names: Vec<String>) -> datafusion::error::Result<ScalarValue> {
+        let time = match var_names.as_slice() {
+            [name] if name == QUERY_BEGIN_VARIABLE => self.0.begin,
+            [name] if name == QUERY_END_VARIABLE => self.0.end,
+            _ => {
+                return Err(DataFusionError::Plan(format!(
+                    "unknown variable {}",
+                    var_names.join(".")
+                )))
+            }
+        };
+        Ok(ScalarValue::TimestampNanosecond(
+            time.and_then(|time| time.timestamp_nanos_opt()),
+            Some("+00:00".into()),
+        ))
+    }
+
+    fn get_type(&self, var_names: &[String]) -> Option<DataType> {
+        match var_names {
+            [name] if name == QUERY_BEGIN_VARIABLE || name == QUERY_END_VARIABLE => {
+                Some(query_range_variable_type())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Context to run the statements of a request with its range
+///
+/// Tables and views are shared with the session. With a range, settings changed with `SET`
+/// only last for the request.
+pub fn with_query_range(ctx: &SessionContext, range: QueryRange) -> SessionContext {
+    if range.is_empty() {
+        return ctx.clone();
+    }
+    let ctx = SessionContext::new_with_state(ctx.state());
+    ctx.register_variable(VarType::UserDefined, Arc::new(QueryRangeVariables(range)));
+    ctx
+}
+
+struct Session {
+    ctx: SessionContext,
+    span_hierarchy: Arc<RwLock<SpanHierarchy>>,
+    last_used: Instant,
+}
+
+pub struct SqlSessions {
+    sessions: Mutex<HashMap<Uuid, Session>>,
+    idle_timeout: Duration,
+}
+
+impl std::fmt::Debug for SqlSessions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqlSessions")
+            .field("idle_timeout", &self.idle_timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for SqlSessions {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30 * 60))
+    }
+}
+
+impl SqlSessions {
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            idle_timeout,
+        }
+    }
+
+    /// Fails when too many sessions are active
+    pub fn create(&self, now: Instant) -> Result<Uuid> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_id, session| now.duration_since(session.last_used) < self.idle_timeout);
+        if sessions.len() >= MAX_SESSIONS {
+            anyhow::bail!("too many sql sessions");
+        }
+        let ctx = SessionContext::new();
+        register_extension_functions(&ctx);
+        register_events_within_spans(&ctx);
+        register_frame_budget_violations(&ctx);
+        register_canned_queries(&ctx);
+        let span_hierarchy = Arc::new(RwLock::new(SpanHierarchy::new()));
+        register_span_hierarchy_functions(&ctx, span_hierarchy.clone());
+        ctx.register_variable(
+            VarType::UserDefined,
+            Arc::new(QueryRangeVariables(QueryRange::default())),
+        );
+        let session_id = Uuid::new_v4();
+        sessions.insert(
+            session_id,
+            Session {
+                ctx,
+                span_hierarchy,
+                last_used: now,
+            },
+        );
+        Ok(session_id)
+    }
+
+    /// Context of the session, which stays alive for another idle timeout
+    pub fn get(&self, session_id: &Uuid, now: Instant) -> Result<SessionContext> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get_mut(session_id)
+            .filter(|session| now.duration_since(session.last_used) < self.idle_timeout)
+            .with_context(|| format!("sql session {session_id} not found or expired"))?;
+        session.last_used = now;
+        Ok(session.ctx.clone())
+    }
+
+    /// Makes the spans of the stream known to `span_depth`, `span_ancestor` and
+    /// `span_ancestor_name` in the session, the stream id being the first argument
+    pub fn add_spans(
+        &self,
+        session_id: &Uuid,
+        stream_id: &str,
+        spans: &RecordBatch,
+        now: Instant,
+    ) -> Result<()> {
+        let span_hierarchy = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let session = sessions
+                .get_mut(session_id)
+                .filter(|session| now.duration_since(session.last_used) < self.idle_timeout)
+                .with_context(|| format!("sql session {session_id} not found or expired"))?;
+            session.last_used = now;
+            session.span_hierarchy.clone()
+        };
+        let mut span_hierarchy = span_hierarchy.write().unwrap();
+        span_hierarchy.add_spans(stream_id, spans)
+    }
+
+    /// Returns false if the session did not exist
+    pub fn close(&self, session_id: &Uuid) -> bool {
+        self.sessions.lock().unwrap().remove(session_id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Makes the batch available to the next queries of the session, replacing any table of that name
+pub fn register_result(ctx: &SessionContext, name: &str, batch: RecordBatch) -> Result<()> {
+    ctx.deregister_table(name)
+        .with_context(|| format!("deregistering {name}"))?;
+    ctx.register_batch(name, batch)
+        .with_context(|| format!("registering {name}"))?;
+    Ok(())
+}
+
+/// Plans a statement sent by a client, which can only query the tables and create views
+///
+/// `COPY ... TO`, `CREATE EXTERNAL TABLE` and the other statements would give access to the
+/// files of the server.
+pub async fn plan_client_sql(
+    state: &SessionState,
+    sql: &str,
+) -> datafusion::error::Result<LogicalPlan> {
+    let plan = state.create_logical_plan(sql).await?;
+    let is_view = matches!(plan, LogicalPlan::Ddl(DdlStatement::CreateView(_)));
+    SQLOptions::new()
+        .with_allow_ddl(is_view)
+        .with_allow_dml(false)
+        .with_allow_statements(false)
+        .verify_plan(&plan)?;
+    Ok(plan)
+}
+
+/// Runs the statement in the session, optionally keeping its result as a table named `register_as`
+pub async fn execute_sql(
+    ctx: &SessionContext,
+    sql: &str,
+    register_as: Option<&str>,
+) -> Result<RecordBatch> {
+    let plan = plan_client_sql(&ctx.state(), sql)
+        .await
+        .with_context(|| format!("planning {sql}"))?;
+    let df = ctx
+        .execute_logical_plan(plan)
+        .await
+        .with_context(|| format!("planning {sql}"))?;
+    let schema: SchemaRef = Arc::new(df.schema().into());
+    let batches = df.collect().await.with_context(|| "executing query")?;
+    let batch = concat_batches(&schema, &batches).with_context(|| "concatenating results")?;
+    if let Some(name) = register_as {
+        register_result(ctx, name, batch.clone())?;
+    }
+    Ok(batch)
+}
@@ -0,0 +1,21 @@
+//! Options of the queries reading the blocks of a single stream
+use crate::query_timeout::QueryDeadline;
+
+pub struct StreamQueryOptions<'a> {
+    /// Process properties added as columns of the result
+    pub promoted_properties: &'a [String],
+    /// Adds the raw tick columns to the result
+    pub with_ticks: bool,
+    pub deadline: &'a QueryDeadline,
+}
+
+impl<'a> StreamQueryOptions<'a> {
+    /// No promoted properties and no tick columns
+    pub fn new(deadline: &'a QueryDeadline) -> Self {
+        Self {
+            promoted_properties: &[],
+            with_ticks: false,
+            deadline,
+        }
+    }
+}
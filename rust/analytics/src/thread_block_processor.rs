@@ -3,6 +3,7 @@ use crate::{fetch_block_payload, parse_block};
 use anyhow::{Context, Result};
 use micromegas_telemetry::blob_storage::BlobStorage;
 use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_telemetry::types::block::BlockMetadata;
 use micromegas_tracing::prelude::*;
 use micromegas_tracing::warn;
 use micromegas_transit::{Object, Value};
@@ -24,6 +25,17 @@ pub trait ThreadBlockProcessor {
         scope: ScopeDesc,
         ts: i64,
     ) -> Result<bool>;
+    /// Instant event within the current span of the thread
+    fn on_span_event(
+        &mut self,
+        _block_id: &str,
+        _event_id: i64,
+        _marker: ScopeDesc,
+        _properties: Arc<String>,
+        _ts: i64,
+    ) -> Result<bool> {
+        Ok(true)
+    }
 }
 
 fn on_thread_event<F>(obj: &micromegas_transit::Object, mut fun: F) -> Result<bool>
@@ -45,6 +57,16 @@ where
     fun(scope, name, tick)
 }
 
+fn on_span_marker_event<F>(obj: &micromegas_transit::Object, mut fun: F) -> Result<bool>
+where
+    F: FnMut(Arc<Object>, Arc<String>, i64) -> Result<bool>,
+{
+    let tick = obj.get::<i64>("time")?;
+    let marker = obj.get::<Arc<Object>>("marker_desc")?;
+    let properties = obj.get::<Arc<String>>("properties")?;
+    fun(marker, properties, tick)
+}
+
 #[span_fn]
 pub fn parse_thread_block_payload<Proc: ThreadBlockProcessor>(
     block_id: &str,
@@ -91,6 +113,15 @@ pub fn parse_thread_block_payload<Proc: ThreadBlockProcessor>(
                     processor.on_end_thread_scope(block_id, event_id, scope_desc, ts)
                 })
                 .with_context(|| "reading EndThreadNamedSpanEvent"),
+                "SpanMarkerEvent" => on_span_marker_event(&obj, |marker, properties, ts| {
+                    let name = marker.get::<Arc<String>>("name")?;
+                    let filename = marker.get::<Arc<String>>("file")?;
+                    let target = marker.get::<Arc<String>>("target")?;
+                    let line = marker.get::<u32>("line")?;
+                    let marker_desc = ScopeDesc::new(name, filename, target, line);
+                    processor.on_span_event(block_id, event_id, marker_desc, properties, ts)
+                })
+                .with_context(|| "reading SpanMarkerEvent"),
                 event_type => {
                     warn!("unknown event type {}", event_type);
                     Ok(true)
@@ -108,15 +139,27 @@ pub fn parse_thread_block_payload<Proc: ThreadBlockProcessor>(
 pub async fn parse_thread_block<Proc: ThreadBlockProcessor>(
     blob_storage: Arc<BlobStorage>,
     stream: &StreamInfo,
-    block_id: sqlx::types::Uuid,
-    object_offset: i64,
+    block: &BlockMetadata,
     processor: &mut Proc,
 ) -> Result<bool> {
-    let payload =
-        fetch_block_payload(blob_storage, stream.process_id, stream.stream_id, block_id).await?;
-    let block_id_str = block_id
+    let payload = fetch_block_payload(
+        blob_storage,
+        stream.process_id,
+        stream.stream_id,
+        block.block_id,
+        block.checksum,
+    )
+    .await?;
+    let block_id_str = block
+        .block_id
         .hyphenated()
         .encode_lower(&mut sqlx::types::uuid::Uuid::encode_buffer())
         .to_owned();
-    parse_thread_block_payload(&block_id_str, object_offset, &payload, stream, processor)
+    parse_thread_block_payload(
+        &block_id_str,
+        block.object_offset,
+        &payload,
+        stream,
+        processor,
+    )
 }
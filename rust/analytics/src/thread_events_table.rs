@@ -10,6 +10,7 @@ use std::sync::Arc;
 
 use crate::thread_block_processor::ThreadBlockProcessor;
 use crate::time::ConvertTicks;
+use crate::view_docs::ViewDoc;
 
 pub struct ThreadEventsRecordBuilder {
     begin_query_ns: i64,
@@ -56,41 +57,7 @@ impl ThreadEventsRecordBuilder {
     }
 
     pub fn finish(mut self) -> Result<RecordBatch> {
-        let schema = Schema::new(vec![
-            Field::new("id", DataType::Int64, false),
-            Field::new(
-                "event_type",
-                DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new(
-                "timestamp",
-                DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
-                false,
-            ),
-            Field::new("hash", DataType::UInt32, false),
-            Field::new(
-                "name",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new(
-                "target",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new(
-                "filename",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-            Field::new("line", DataType::UInt32, false),
-            Field::new(
-                "block_id",
-                DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
-                false,
-            ),
-        ]);
+        let schema = thread_events_schema();
         RecordBatch::try_new(
             Arc::new(schema),
             vec![
@@ -162,3 +129,66 @@ impl ThreadBlockProcessor for ThreadEventsRecordBuilder {
         self.process_event(block_id, event_id, "end", scope, ts)
     }
 }
+
+/// Schema of the `thread_events` view
+pub fn thread_events_schema() -> Schema {
+    Schema::new(vec![
+        Field::new("id", DataType::Int64, false),
+        Field::new(
+            "event_type",
+            DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("hash", DataType::UInt32, false),
+        Field::new(
+            "name",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "target",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new(
+            "filename",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new("line", DataType::UInt32, false),
+        Field::new(
+            "block_id",
+            DataType::Dictionary(Box::new(DataType::Int16), Box::new(DataType::Utf8)),
+            false,
+        ),
+    ])
+}
+
+pub fn thread_events_view_doc() -> ViewDoc {
+    ViewDoc {
+        name: "thread_events",
+        description: "begin and end events of the scopes of a thread stream, in a time range",
+        deprecated: None,
+        schema: thread_events_schema(),
+        columns: &[
+            ("id", "index of the event in the stream"),
+            ("event_type", "begin or end"),
+            ("timestamp", "time of the event"),
+            ("hash", "hash identifying the scope"),
+            ("name", "name of the scope, usually the instrumented function"),
+            ("target", "module path of the instrumented code"),
+            ("filename", "source file of the instrumented code"),
+            ("line", "line of the instrumented code in its source file"),
+            ("block_id", "block holding the event"),
+        ],
+        examples: &[(
+            "events per block",
+            "SELECT block_id, count(*) AS nb_events, min(timestamp), max(timestamp) FROM thread_events GROUP BY block_id",
+        )],
+    }
+}
@@ -0,0 +1,111 @@
+//! Queries over a set of disjoint time ranges, i.e. every match round or business hours only
+//!
+//! Requests accept a `time_ranges` list along with their `begin` and `end`: the rows returned are
+//! those falling in any of the ranges, clipped to the bounds. Overlapping ranges are merged
+//! so that no row is returned twice.
+use anyhow::{Context, Result};
+use datafusion::arrow::compute::concat_batches;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, FixedOffset, Utc};
+use std::future::Future;
+
+/// Range of a request, in RFC 3339
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeRangeArg {
+    pub begin: String,
+    pub end: String,
+}
+
+/// [begin, end)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub begin: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+fn parse_time(time: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::<FixedOffset>::parse_from_rfc3339(time)
+        .with_context(|| format!("parsing time {time}"))?
+        .into())
+}
+
+/// Sorted, non-overlapping ranges clipped to the bounds, empty ranges are dropped
+pub fn make_time_ranges(bounds: TimeRange, ranges: &[TimeRange]) -> Vec<TimeRange> {
+    let mut clipped: Vec<TimeRange> = ranges
+        .iter()
+        .map(|range| TimeRange {
+            begin: range.begin.max(bounds.begin),
+            end: range.end.min(bounds.end),
+        })
+        .filter(|range| range.begin < range.end)
+        .collect();
+    clipped.sort_by_key(|range| range.begin);
+    let mut merged: Vec<TimeRange> = Vec::with_capacity(clipped.len());
+    for range in clipped {
+        match merged.last_mut() {
+            Some(last) if range.begin <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Returns the bounds of the request and the ranges to query, the bounds themselves when no range is requested
+pub fn parse_time_ranges(
+    begin: &str,
+    end: &str,
+    ranges: &[TimeRangeArg],
+) -> Result<(TimeRange, Vec<TimeRange>)> {
+    let bounds = TimeRange {
+        begin: parse_time(begin).with_context(|| "parsing begin time range")?,
+        end: parse_time(end).with_context(|| "parsing end time range")?,
+    };
+    if ranges.is_empty() {
+        return Ok((bounds, vec![bounds]));
+    }
+    let ranges = ranges
+        .iter()
+        .map(|range| {
+            Ok(TimeRange {
+                begin: parse_time(&range.begin)?,
+                end: parse_time(&range.end)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((bounds, make_time_ranges(bounds, &ranges)))
+}
+
+/// Runs the query for each range with the rows left in the limit and concatenates the results
+///
+/// When no range is left or the limit is zero, the bounds are queried with a limit of zero to get an empty result with the right schema.
+pub async fn query_time_ranges<F, Fut>(
+    bounds: TimeRange,
+    ranges: &[TimeRange],
+    limit: i64,
+    mut query: F,
+) -> Result<RecordBatch>
+where
+    F: FnMut(TimeRange, i64) -> Fut,
+    Fut: Future<Output = Result<RecordBatch>>,
+{
+    if ranges.is_empty() || limit <= 0 {
+        return query(bounds, 0).await;
+    }
+    let mut batches = vec![];
+    let mut remaining = limit;
+    for range in ranges {
+        if remaining <= 0 {
+            break;
+        }
+        let batch = query(*range, remaining).await?;
+        remaining -= i64::try_from(batch.num_rows())?;
+        batches.push(batch);
+    }
+    if batches.len() == 1 {
+        return Ok(batches.remove(0));
+    }
+    // a result cut short by the deadline is flagged in the schema of the last batch
+    let schema = batches[batches.len() - 1].schema();
+    concat_batches(&schema, &batches).with_context(|| "concatenating time ranges")
+}
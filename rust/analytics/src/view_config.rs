@@ -0,0 +1,180 @@
+//! Views served by the analytics service, declared in a json configuration file
+//!
+//! ```json
+//! {
+//!     "disabled_builtin_views": ["blocks"],
+//!     "views": [
+//!         {
+//!             "name": "crashed_processes",
+//!             "sql": "SELECT * FROM processes WHERE properties @> ARRAY[('crashed','true')]::micromegas_property[]",
+//!             "time_column": "start_time"
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! A view is a SELECT statement evaluated against the metadata database at query time,
+//! adding one does not require recompiling the server.
+//! The parquet encoding of the results of each view can be tuned, see `parquet_config`.
+//! Process properties can be promoted to columns, see `promoted_properties`.
+//! With `"tick_columns": true`, the `log_entries` and `measures` views have a `ticks` column
+//! holding the raw timestamp of each row next to its converted time.
+use crate::parquet_config::{default_writer_properties, ParquetWriterConfig, STREAM_VIEWS};
+use crate::promoted_properties::{processes_select_list, validate_promoted_properties};
+use anyhow::{Context, Result};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::file::properties::WriterProperties;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViewDefinition {
+    pub name: String,
+    /// SELECT statement used as a subquery
+    pub sql: String,
+    /// timestamp column used to filter the rows by time range
+    pub time_column: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ViewsConfig {
+    #[serde(default)]
+    pub views: Vec<ViewDefinition>,
+    #[serde(default)]
+    pub disabled_builtin_views: Vec<String>,
+    /// view name -> parquet writer settings of its results
+    #[serde(default)]
+    pub parquet: BTreeMap<String, ParquetWriterConfig>,
+    /// process properties exposed as columns of the processes, log_entries and measures views
+    #[serde(default)]
+    pub promoted_properties: Vec<String>,
+    /// adds the raw timestamps as a `ticks` column of the log_entries and measures views
+    #[serde(default)]
+    pub tick_columns: bool,
+}
+
+pub fn load_views_config(path: &Path) -> Result<ViewsConfig> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("reading views config {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("parsing views config {}", path.display()))
+}
+
+pub fn builtin_views() -> Vec<ViewDefinition> {
+    let make = |name: &str, time_column: &str| ViewDefinition {
+        name: name.to_owned(),
+        sql: format!("SELECT * FROM {name}"),
+        time_column: time_column.to_owned(),
+    };
+    vec![
+        make("processes", "start_time"),
+        make("streams", "insert_time"),
+        make("blocks", "begin_time"),
+        make("annotations", "begin_time"),
+        make("data_loss", "begin_time"),
+    ]
+}
+
+fn is_valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+}
+
+#[derive(Debug, Clone)]
+pub struct ViewRegistry {
+    views: BTreeMap<String, ViewDefinition>,
+    parquet: BTreeMap<String, ParquetWriterConfig>,
+    promoted_properties: Vec<String>,
+    tick_columns: bool,
+}
+
+impl Default for ViewRegistry {
+    fn default() -> Self {
+        Self::from_config(&ViewsConfig::default()).expect("builtin views are valid")
+    }
+}
+
+impl ViewRegistry {
+    pub fn from_config(config: &ViewsConfig) -> Result<Self> {
+        validate_promoted_properties(&config.promoted_properties)?;
+        let mut views = BTreeMap::new();
+        for mut view in builtin_views() {
+            if view.name == "processes" {
+                view.sql = format!(
+                    "SELECT {} FROM processes",
+                    processes_select_list(&config.promoted_properties)
+                );
+            }
+            if !config.disabled_builtin_views.contains(&view.name) {
+                views.insert(view.name.clone(), view);
+            }
+        }
+        for view in &config.views {
+            // names and time columns are spliced into sql statements
+            if !is_valid_identifier(&view.name) {
+                anyhow::bail!("invalid view name {:?}", view.name);
+            }
+            if !is_valid_identifier(&view.time_column) {
+                anyhow::bail!(
+                    "invalid time column {:?} in view {}",
+                    view.time_column,
+                    view.name
+                );
+            }
+            if views.insert(view.name.clone(), view.clone()).is_some() {
+                anyhow::bail!("view {} declared more than once", view.name);
+            }
+        }
+        for (view_name, parquet_config) in &config.parquet {
+            if !views.contains_key(view_name) && !STREAM_VIEWS.contains(&view_name.as_str()) {
+                anyhow::bail!("parquet settings for unknown view {view_name}");
+            }
+            // rejected at startup rather than with the first query of the view
+            parquet_config
+                .writer_properties()
+                .with_context(|| format!("parquet settings of view {view_name}"))?;
+        }
+        Ok(Self {
+            views,
+            parquet: config.parquet.clone(),
+            promoted_properties: config.promoted_properties.clone(),
+            tick_columns: config.tick_columns,
+        })
+    }
+
+    pub fn find_view(&self, name: &str) -> Result<&ViewDefinition> {
+        self.views
+            .get(name)
+            .with_context(|| format!("view {name} not found"))
+    }
+
+    /// Rows of a view as they are written: sorted by its sort order, with its writer settings
+    pub fn prepare_batch(
+        &self,
+        view_name: &str,
+        batch: &RecordBatch,
+    ) -> Result<(RecordBatch, WriterProperties)> {
+        match self.parquet.get(view_name) {
+            Some(config) => config
+                .prepare_batch(batch)
+                .with_context(|| format!("preparing the rows of view {view_name}")),
+            None => Ok((batch.clone(), default_writer_properties())),
+        }
+    }
+
+    /// Process properties appended as columns to the rows of the views
+    pub fn promoted_properties(&self) -> &[String] {
+        &self.promoted_properties
+    }
+
+    /// True if the rows of the log_entries and measures views have a `ticks` column
+    pub fn tick_columns(&self) -> bool {
+        self.tick_columns
+    }
+
+    pub fn views(&self) -> impl Iterator<Item = &ViewDefinition> {
+        self.views.values()
+    }
+}
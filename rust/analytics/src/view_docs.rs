@@ -0,0 +1,205 @@
+//! Documentation of the views built from the streams, see the `describe_view` table function
+//!
+//! Each view is documented next to its schema, so sql users can discover the columns without
+//! reading the code. The types are read from the schemas themselves.
+//! The versioned names of the views, see `view_versions`, are described with the columns of their
+//! version.
+use crate::log_entries_table::log_entries_view_doc;
+use crate::metrics_table::measures_view_doc;
+use crate::span_table::spans_view_doc;
+use crate::thread_events_table::thread_events_view_doc;
+use crate::view_versions::{ViewVersion, VIEW_VERSIONS};
+use anyhow::{Context, Result};
+use datafusion::arrow::array::{ArrayRef, BooleanBuilder, StringBuilder};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct ViewDoc {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// why the view should not be used anymore and what replaces it
+    pub deprecated: Option<&'static str>,
+    pub schema: Schema,
+    /// (column name, description)
+    pub columns: &'static [(&'static str, &'static str)],
+    /// (description, sql), the statements read the rows of the view as a table of that name
+    pub examples: &'static [(&'static str, &'static str)],
+}
+
+impl ViewDoc {
+    pub fn column_description(&self, column_name: &str) -> Option<&'static str> {
+        self.columns
+            .iter()
+            .find(|(name, _)| *name == column_name)
+            .map(|(_, description)| *description)
+    }
+}
+
+pub fn documented_views() -> Vec<ViewDoc> {
+    vec![
+        log_entries_view_doc(),
+        measures_view_doc(),
+        spans_view_doc(),
+        thread_events_view_doc(),
+    ]
+}
+
+/// Documentation of the view the version belongs to, restricted to the columns of the version
+fn view_version_doc(version: &ViewVersion) -> Option<ViewDoc> {
+    documented_views()
+        .into_iter()
+        .find(|doc| doc.name == version.view)
+        .map(|doc| ViewDoc {
+            name: version.name,
+            deprecated: version.deprecated,
+            schema: version.schema(&doc.schema),
+            ..doc
+        })
+}
+
+pub fn find_view_doc(name: &str) -> Result<ViewDoc> {
+    documented_views()
+        .into_iter()
+        .find(|doc| doc.name == name)
+        .or_else(|| {
+            VIEW_VERSIONS
+                .iter()
+                .find(|version| version.name == name)
+                .and_then(view_version_doc)
+        })
+        .with_context(|| {
+            let names: Vec<&str> = documented_views()
+                .iter()
+                .map(|doc| doc.name)
+                .chain(VIEW_VERSIONS.iter().map(|version| version.name))
+                .collect();
+            format!(
+                "no documentation for view {name}, documented views: {}",
+                names.join(", ")
+            )
+        })
+}
+
+/// `kind` is `view`, `column` or `example`, `deprecated` is only set on the rows of deprecated views
+pub fn describe_view_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("kind", DataType::Utf8, false),
+        Field::new("name", DataType::Utf8, true),
+        Field::new("data_type", DataType::Utf8, true),
+        Field::new("nullable", DataType::Boolean, true),
+        Field::new("description", DataType::Utf8, true),
+        Field::new("sql", DataType::Utf8, true),
+        Field::new("deprecated", DataType::Utf8, true),
+    ]))
+}
+
+#[derive(Default)]
+struct DescriptionBuilder {
+    kinds: StringBuilder,
+    names: StringBuilder,
+    data_types: StringBuilder,
+    nullables: BooleanBuilder,
+    descriptions: StringBuilder,
+    sqls: StringBuilder,
+    deprecations: StringBuilder,
+}
+
+impl DescriptionBuilder {
+    fn append(
+        &mut self,
+        kind: &str,
+        name: Option<&str>,
+        field: Option<&Field>,
+        description: Option<&str>,
+        sql: Option<&str>,
+        deprecated: Option<&str>,
+    ) {
+        self.kinds.append_value(kind);
+        self.names.append_option(name);
+        self.data_types
+            .append_option(field.map(|field| field.data_type().to_string()));
+        self.nullables
+            .append_option(field.map(|field| field.is_nullable()));
+        self.descriptions.append_option(description);
+        self.sqls.append_option(sql);
+        self.deprecations.append_option(deprecated);
+    }
+
+    fn finish(mut self) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.kinds.finish()),
+            Arc::new(self.names.finish()),
+            Arc::new(self.data_types.finish()),
+            Arc::new(self.nullables.finish()),
+            Arc::new(self.descriptions.finish()),
+            Arc::new(self.sqls.finish()),
+            Arc::new(self.deprecations.finish()),
+        ];
+        RecordBatch::try_new(describe_view_schema(), columns)
+            .with_context(|| "building view description")
+    }
+}
+
+/// One row for the view, one per column of the view, then one per example query
+pub fn describe_view(doc: &ViewDoc) -> Result<RecordBatch> {
+    let mut builder = DescriptionBuilder::default();
+    builder.append(
+        "view",
+        Some(doc.name),
+        None,
+        Some(doc.description),
+        None,
+        doc.deprecated,
+    );
+    for field in doc.schema.fields() {
+        builder.append(
+            "column",
+            Some(field.name()),
+            Some(field),
+            doc.column_description(field.name()),
+            None,
+            doc.deprecated,
+        );
+    }
+    for (description, sql) in doc.examples {
+        builder.append(
+            "example",
+            None,
+            None,
+            Some(description),
+            Some(sql),
+            doc.deprecated,
+        );
+    }
+    builder.finish()
+}
+
+/// One row per documented view, then one per versioned name of a view
+pub fn list_documented_views() -> Result<RecordBatch> {
+    let mut builder = DescriptionBuilder::default();
+    for doc in documented_views() {
+        builder.append(
+            "view",
+            Some(doc.name),
+            None,
+            Some(doc.description),
+            None,
+            doc.deprecated,
+        );
+    }
+    for version in VIEW_VERSIONS {
+        if let Some(doc) = view_version_doc(version) {
+            builder.append(
+                "view",
+                Some(doc.name),
+                None,
+                Some(&format!("version {} of {}", version.version, version.view)),
+                None,
+                doc.deprecated,
+            );
+        }
+    }
+    builder.finish()
+}
@@ -0,0 +1,89 @@
+//! Versioned names of the stream views, i.e. `log_entries_v1`
+//!
+//! The unversioned name of a view follows its latest schema. Saved queries and dashboards can
+//! pin a version by name to keep the columns they were written against: the rows are produced
+//! with the latest schema and translated back to the columns of the requested version.
+//! Old versions are deprecated but keep being served, `describe_view()` lists them.
+use anyhow::{Context, Result};
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::record_batch::RecordBatch;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViewVersion {
+    /// versioned name, i.e. `log_entries_v1`
+    pub name: &'static str,
+    /// unversioned name of the view, i.e. `log_entries`
+    pub view: &'static str,
+    pub version: u32,
+    /// columns of the latest schema absent from this version
+    pub dropped_columns: &'static [&'static str],
+    /// why the version should not be used anymore and what replaces it
+    pub deprecated: Option<&'static str>,
+}
+
+pub const VIEW_VERSIONS: &[ViewVersion] = &[
+    ViewVersion {
+        name: "log_entries_v1",
+        view: "log_entries",
+        version: 1,
+        dropped_columns: &["category", "ticks"],
+        deprecated: Some("use log_entries_v2, which adds the category and ticks columns"),
+    },
+    ViewVersion {
+        name: "log_entries_v2",
+        view: "log_entries",
+        version: 2,
+        dropped_columns: &[],
+        deprecated: None,
+    },
+];
+
+/// Latest version of the view, None if the view is not versioned
+pub fn latest_view_version(view: &str) -> Option<&'static ViewVersion> {
+    VIEW_VERSIONS
+        .iter()
+        .filter(|version| version.view == view)
+        .max_by_key(|version| version.version)
+}
+
+/// Resolves a view name, versioned or not, to the version of `view` serving it
+///
+/// Unversioned names resolve to the latest version, None if `view` is not versioned.
+pub fn resolve_view_version(view: &str, name: &str) -> Result<Option<&'static ViewVersion>> {
+    if name == view {
+        return Ok(latest_view_version(view));
+    }
+    VIEW_VERSIONS
+        .iter()
+        .find(|version| version.name == name && version.view == view)
+        .map(Some)
+        .with_context(|| format!("{name} is not a version of view {view}"))
+}
+
+impl ViewVersion {
+    /// Schema of this version, from the latest schema of the view
+    pub fn schema(&self, latest: &Schema) -> Schema {
+        let fields: Vec<_> = latest
+            .fields()
+            .iter()
+            .filter(|field| !self.dropped_columns.contains(&field.name().as_str()))
+            .cloned()
+            .collect();
+        Schema::new_with_metadata(fields, latest.metadata().clone())
+    }
+
+    /// Translates rows of the latest schema to the columns of this version
+    pub fn translate(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let schema = batch.schema();
+        let indices: Vec<usize> = schema
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_index, field)| !self.dropped_columns.contains(&field.name().as_str()))
+            .map(|(index, _field)| index)
+            .collect();
+        batch
+            .project(&indices)
+            .with_context(|| format!("translating rows to {}", self.name))
+    }
+}
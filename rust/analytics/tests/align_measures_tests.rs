@@ -0,0 +1,57 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::{Float64Type, Int64Type, UInt64Type};
+use micromegas_analytics::align_measures::{align_buckets, BucketStats, MeasureBuckets};
+
+#[test]
+fn test_align_buckets() {
+    let mut a = MeasureBuckets::new();
+    a.insert(
+        0,
+        BucketStats {
+            sum: 10.0,
+            count: 2,
+        },
+    );
+    a.insert(1000, BucketStats { sum: 6.0, count: 1 });
+    let mut b = MeasureBuckets::new();
+    b.insert(
+        1000,
+        BucketStats {
+            sum: 16.0,
+            count: 2,
+        },
+    );
+    b.insert(2000, BucketStats { sum: 1.0, count: 1 });
+    let batch = align_buckets(&a, &b).unwrap();
+    assert_eq!(batch.num_rows(), 3);
+    let offsets: Vec<i64> = batch
+        .column(0)
+        .as_primitive::<Int64Type>()
+        .values()
+        .to_vec();
+    assert_eq!(offsets, vec![0, 1000, 2000]);
+    let value_a: Vec<Option<f64>> = batch
+        .column(1)
+        .as_primitive::<Float64Type>()
+        .iter()
+        .collect();
+    assert_eq!(value_a, vec![Some(5.0), Some(6.0), None]);
+    let value_b: Vec<Option<f64>> = batch
+        .column(2)
+        .as_primitive::<Float64Type>()
+        .iter()
+        .collect();
+    assert_eq!(value_b, vec![None, Some(8.0), Some(1.0)]);
+    let count_b: Vec<u64> = batch
+        .column(4)
+        .as_primitive::<UInt64Type>()
+        .values()
+        .to_vec();
+    assert_eq!(count_b, vec![0, 2, 1]);
+    let delta: Vec<Option<f64>> = batch
+        .column(5)
+        .as_primitive::<Float64Type>()
+        .iter()
+        .collect();
+    assert_eq!(delta, vec![None, Some(2.0), None]);
+}
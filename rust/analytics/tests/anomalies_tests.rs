@@ -0,0 +1,119 @@
+use datafusion::arrow::array::{
+    AsArray, Float64Array, RecordBatch, StringArray, TimestampNanosecondArray,
+};
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::anomalies::{mad_anomalies, seasonal_anomalies, zscore_anomalies};
+use micromegas_analytics::dfext::canned_queries::register_canned_queries;
+use micromegas_analytics::dfext::register_extension_functions;
+use std::sync::Arc;
+
+#[test]
+fn test_zscore_anomalies() {
+    let mut values = vec![Some(10.0); 20];
+    values[5] = Some(100.0);
+    values[6] = None;
+    let anomalies = zscore_anomalies(&values, 3.0);
+    assert_eq!(anomalies[5], Some(true));
+    assert_eq!(anomalies[6], None);
+    assert_eq!(anomalies.iter().flatten().filter(|a| **a).count(), 1);
+    assert_eq!(
+        zscore_anomalies(&[Some(1.0), Some(1.0)], 3.0),
+        vec![Some(false); 2]
+    );
+    assert_eq!(zscore_anomalies(&[None], 3.0), vec![None]);
+}
+
+#[test]
+fn test_mad_anomalies() {
+    let values = [
+        Some(10.0),
+        Some(11.0),
+        Some(9.0),
+        Some(10.5),
+        Some(9.5),
+        Some(50.0),
+        Some(10.0),
+    ];
+    assert_eq!(
+        mad_anomalies(&values, 3.5),
+        vec![
+            Some(false),
+            Some(false),
+            Some(false),
+            Some(false),
+            Some(false),
+            Some(true),
+            Some(false)
+        ]
+    );
+    // no deviation: any different value is an anomaly
+    assert_eq!(
+        mad_anomalies(&[Some(1.0), Some(1.0), Some(1.0), Some(2.0)], 3.5),
+        vec![Some(false), Some(false), Some(false), Some(true)]
+    );
+}
+
+#[test]
+fn test_seasonal_anomalies() {
+    // a cycle of 4 rows with a large amplitude, and a value at the bottom of the cycle
+    // that would be normal at its top
+    let cycle = [0.0, 100.0, 200.0, 100.0];
+    let mut values: Vec<Option<f64>> = (0..40)
+        .map(|index| Some(cycle[index % 4] + (index % 3) as f64))
+        .collect();
+    values[20] = Some(150.0);
+    let seasonal = seasonal_anomalies(&values, 4, 3.5);
+    assert_eq!(seasonal.iter().flatten().filter(|a| **a).count(), 1);
+    assert_eq!(seasonal[20], Some(true));
+    // without the seasonal component the value is within the range of the series
+    assert_eq!(mad_anomalies(&values, 3.5)[20], Some(false));
+}
+
+#[tokio::test]
+async fn test_anomalies_sql() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    register_canned_queries(&ctx);
+    let mut values = vec![1.0, 1.2, 0.9, 1.1, 1.0, 0.8, 1.3, 1.0];
+    values[4] = 25.0;
+    let measures = RecordBatch::try_from_iter(vec![
+        (
+            "time",
+            Arc::new(TimestampNanosecondArray::from_iter_values(0..8).with_timezone_utc()) as _,
+        ),
+        ("name", Arc::new(StringArray::from(vec!["fps"; 8])) as _),
+        ("value", Arc::new(Float64Array::from(values)) as _),
+    ])
+    .unwrap();
+    ctx.register_batch("measures", measures).unwrap();
+
+    let results = ctx
+        .sql("SELECT mad_anomaly(value, 3.5) OVER (ORDER BY time) AS mad, zscore_anomaly(value, 2) OVER (ORDER BY time) AS zscore FROM measures ORDER BY time")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    let mad: Vec<bool> = batch.column(0).as_boolean().iter().flatten().collect();
+    let zscore: Vec<bool> = batch.column(1).as_boolean().iter().flatten().collect();
+    let expected: Vec<bool> = (0..8).map(|index| index == 4).collect();
+    assert_eq!(mad, expected);
+    assert_eq!(zscore, expected);
+
+    let results = ctx
+        .sql("SELECT value FROM anomalies('measures', 'fps')")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(results[0].num_rows(), 1);
+    assert_eq!(
+        results[0]
+            .column(0)
+            .as_primitive::<datafusion::arrow::datatypes::Float64Type>()
+            .value(0),
+        25.0
+    );
+}
@@ -0,0 +1,73 @@
+use micromegas_analytics::fetch_block_payload;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use micromegas_telemetry::block_wire_format::{
+    checksum_to_i64, payload_checksum, verify_payload_checksum, BlockPayload,
+};
+use micromegas_telemetry::wire_format::encode_cbor;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use std::sync::Arc;
+
+#[test]
+fn test_verify_payload_checksum() {
+    let block_id = uuid::Uuid::new_v4();
+    let mut payload = BlockPayload {
+        dependencies: vec![1, 2, 3],
+        objects: vec![4, 5, 6, 7],
+    };
+    let checksum = checksum_to_i64(payload_checksum(&payload));
+    verify_payload_checksum(&block_id, &payload, Some(checksum)).unwrap();
+    // blocks of older clients have no checksum
+    verify_payload_checksum(&block_id, &payload, None).unwrap();
+
+    payload.objects[2] ^= 0x10;
+    let error = verify_payload_checksum(&block_id, &payload, Some(checksum)).unwrap_err();
+    assert!(error.to_string().contains("checksum mismatch"));
+
+    // moving bytes between the parts changes the checksum too
+    let moved = BlockPayload {
+        dependencies: vec![1, 2],
+        objects: vec![3, 4, 5, 6, 7],
+    };
+    assert!(verify_payload_checksum(&block_id, &moved, Some(checksum)).is_err());
+}
+
+#[tokio::test]
+async fn test_fetch_verifies_checksum() {
+    let blob_storage = Arc::new(BlobStorage::new(
+        Arc::new(InMemory::new()),
+        Path::from("lake"),
+    ));
+    let (process_id, stream_id, block_id) = (
+        uuid::Uuid::new_v4(),
+        uuid::Uuid::new_v4(),
+        uuid::Uuid::new_v4(),
+    );
+    let payload = BlockPayload {
+        dependencies: vec![1, 2, 3],
+        objects: vec![4, 5, 6, 7],
+    };
+    let checksum = checksum_to_i64(payload_checksum(&payload));
+    blob_storage
+        .put(
+            &format!("blobs/{process_id}/{stream_id}/{block_id}"),
+            encode_cbor(&payload).unwrap().into(),
+        )
+        .await
+        .unwrap();
+    let fetch = |checksum| {
+        fetch_block_payload(
+            blob_storage.clone(),
+            process_id,
+            stream_id,
+            block_id,
+            checksum,
+        )
+    };
+    assert_eq!(
+        fetch(Some(checksum)).await.unwrap().objects,
+        payload.objects
+    );
+    assert!(fetch(None).await.is_ok());
+    assert!(fetch(Some(checksum ^ 1)).await.is_err());
+}
@@ -0,0 +1,98 @@
+use datafusion::arrow::array::{
+    AsArray, Int32Array, RecordBatch, StringArray, TimestampNanosecondArray,
+};
+use datafusion::arrow::datatypes::Int64Type;
+use datafusion::execution::context::SessionContext;
+use datafusion::logical_expr::lit;
+use micromegas_analytics::dfext::canned_queries::{
+    canned_queries, canned_query_sql, find_canned_query, register_canned_queries,
+};
+use std::sync::Arc;
+
+const MS: i64 = 1_000_000;
+
+fn timestamps(values: Vec<i64>) -> Arc<TimestampNanosecondArray> {
+    Arc::new(TimestampNanosecondArray::from(values).with_timezone_utc())
+}
+
+#[test]
+fn test_canned_query_sql() {
+    assert!(canned_queries().len() >= 4);
+    let query = find_canned_query("frame_spikes").unwrap();
+    let sql = canned_query_sql(query, &[lit("spans"), lit("it's {2}"), lit(33 * MS)]).unwrap();
+    assert!(sql.contains(r#"FROM "spans""#));
+    // quotes are escaped, placeholders in the arguments are not replaced
+    assert!(sql.contains("name = 'it''s {2}'"));
+    assert!(sql.contains(">= 33000000"));
+
+    assert!(canned_query_sql(query, &[lit("spans"), lit("frame")]).is_err());
+    assert!(canned_query_sql(query, &[lit("sp\"ans"), lit("frame"), lit(1)]).is_err());
+    let top = find_canned_query("top_error_targets").unwrap();
+    assert!(canned_query_sql(top, &[lit("log_entries"), lit(0_i64)]).is_err());
+    assert!(find_canned_query("not_a_query").is_none());
+}
+
+#[tokio::test]
+async fn test_canned_query_table_functions() {
+    let ctx = SessionContext::new();
+    register_canned_queries(&ctx);
+    let log_entries = RecordBatch::try_from_iter(vec![
+        (
+            "time",
+            timestamps(vec![0, 10 * MS, 20 * MS, 1500 * MS, 1600 * MS]) as _,
+        ),
+        (
+            "target",
+            Arc::new(StringArray::from(vec![
+                "net", "net", "render", "net", "audio",
+            ])) as _,
+        ),
+        (
+            "level",
+            Arc::new(Int32Array::from(vec![2, 3, 2, 1, 4])) as _,
+        ),
+        (
+            "msg",
+            Arc::new(StringArray::from(vec!["a", "b", "c", "d", "e"])) as _,
+        ),
+    ])
+    .unwrap();
+    ctx.register_batch("log_entries", log_entries).unwrap();
+
+    let results = ctx
+        .sql("SELECT target, nb_errors FROM top_error_targets('log_entries', 10)")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let targets: Vec<_> = results
+        .iter()
+        .flat_map(|batch| {
+            let targets = batch.column(0).as_string::<i32>();
+            let counts = batch.column(1).as_primitive::<Int64Type>();
+            (0..batch.num_rows())
+                .map(|row| (targets.value(row).to_owned(), counts.value(row)))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(
+        targets,
+        vec![("net".to_owned(), 2), ("render".to_owned(), 1)]
+    );
+
+    let results = ctx
+        .sql("SELECT nb_errors, nb_warnings, nb_entries FROM process_health('log_entries', INTERVAL '1 second')")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let rows: usize = results.iter().map(RecordBatch::num_rows).sum();
+    assert_eq!(rows, 2);
+
+    assert!(ctx
+        .sql("SELECT * FROM top_error_targets('not_a_table', 10)")
+        .await
+        .is_err());
+}
@@ -0,0 +1,149 @@
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::datasource::MemTable;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::register_extension_functions;
+use micromegas_analytics::view_docs::{documented_views, find_view_doc};
+use std::sync::Arc;
+
+fn column_strings(batches: &[RecordBatch], name: &str) -> Vec<Option<String>> {
+    batches
+        .iter()
+        .flat_map(|batch| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_string::<i32>()
+                .iter()
+                .map(|value| value.map(str::to_owned))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[test]
+fn test_every_column_is_documented() {
+    for doc in documented_views() {
+        for field in doc.schema.fields() {
+            assert!(
+                doc.column_description(field.name()).is_some(),
+                "{}.{} is not documented",
+                doc.name,
+                field.name()
+            );
+        }
+        for (column, _) in doc.columns {
+            assert!(
+                doc.schema.field_with_name(column).is_ok(),
+                "{}.{} is documented but not in the schema",
+                doc.name,
+                column
+            );
+        }
+        assert!(!doc.examples.is_empty(), "no example for {}", doc.name);
+    }
+    assert!(find_view_doc("not_a_view").is_err());
+}
+
+#[tokio::test]
+async fn test_describe_view_table_function() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let batches = ctx
+        .sql("SELECT * FROM describe_view('log_entries') WHERE kind = 'column'")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let names: Vec<String> = column_strings(&batches, "name")
+        .into_iter()
+        .flatten()
+        .collect();
+    assert_eq!(
+        names,
+        ["time", "target", "level", "msg", "category", "ticks"]
+    );
+    let data_types = column_strings(&batches, "data_type");
+    assert_eq!(
+        data_types[0].as_deref(),
+        Some("Timestamp(Nanosecond, Some(\"+00:00\"))")
+    );
+    assert!(column_strings(&batches, "description")
+        .iter()
+        .all(Option::is_some));
+
+    let views = ctx
+        .sql("SELECT name FROM describe_view() ORDER BY name")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let views: Vec<String> = column_strings(&views, "name")
+        .into_iter()
+        .flatten()
+        .collect();
+    assert_eq!(
+        views,
+        [
+            "log_entries",
+            "log_entries_v1",
+            "log_entries_v2",
+            "measures",
+            "spans",
+            "thread_events"
+        ]
+    );
+
+    let deprecated = ctx
+        .sql("SELECT name, deprecated FROM describe_view() WHERE deprecated IS NOT NULL")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let deprecated: Vec<String> = column_strings(&deprecated, "name")
+        .into_iter()
+        .flatten()
+        .collect();
+    assert_eq!(deprecated, ["log_entries_v1"]);
+
+    let batches = ctx
+        .sql("SELECT * FROM describe_view('log_entries_v1') WHERE kind = 'column'")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let names: Vec<String> = column_strings(&batches, "name")
+        .into_iter()
+        .flatten()
+        .collect();
+    assert_eq!(names, ["time", "target", "level", "msg"]);
+    assert!(column_strings(&batches, "deprecated")
+        .iter()
+        .all(Option::is_some));
+
+    let res = ctx.sql("SELECT * FROM describe_view('not_a_view')").await;
+    assert!(res.is_err());
+}
+
+#[tokio::test]
+async fn test_examples_run() {
+    for doc in documented_views() {
+        let ctx = SessionContext::new();
+        register_extension_functions(&ctx);
+        let schema = Arc::new(doc.schema.clone());
+        let table =
+            MemTable::try_new(schema.clone(), vec![vec![RecordBatch::new_empty(schema)]]).unwrap();
+        ctx.register_table(doc.name, Arc::new(table)).unwrap();
+        for (description, sql) in doc.examples {
+            ctx.sql(sql)
+                .await
+                .unwrap_or_else(|e| panic!("{}: {description}: {e}", doc.name))
+                .collect()
+                .await
+                .unwrap_or_else(|e| panic!("{}: {description}: {e}", doc.name));
+        }
+    }
+}
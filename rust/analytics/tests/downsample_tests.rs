@@ -0,0 +1,68 @@
+use datafusion::arrow::array::{AsArray, Float64Array, RecordBatch, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::{Float64Type, TimestampNanosecondType};
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::downsample::lttb_indices;
+use micromegas_analytics::dfext::register_extension_functions;
+use std::sync::Arc;
+
+#[test]
+fn test_lttb_keeps_peaks() {
+    let times: Vec<f64> = (0..1000).map(f64::from).collect();
+    let mut values = vec![0.0; 1000];
+    values[437] = 100.0;
+    values[712] = -50.0;
+    let kept = lttb_indices(&times, &values, 20);
+    assert_eq!(kept.len(), 20);
+    assert_eq!(kept[0], 0);
+    assert_eq!(kept[19], 999);
+    assert!(kept.contains(&437));
+    assert!(kept.contains(&712));
+    assert!(kept.windows(2).all(|w| w[0] < w[1]));
+
+    assert_eq!(
+        lttb_indices(&times[..5], &values[..5], 10),
+        vec![0, 1, 2, 3, 4]
+    );
+}
+
+#[tokio::test]
+async fn test_downsample_table_function() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let times: TimestampNanosecondArray = (0..500_i64)
+        .map(|i| i * 1_000_000)
+        .collect::<Vec<_>>()
+        .into();
+    let values: Float64Array = (0..500)
+        .map(|i| f64::from(i % 50))
+        .collect::<Vec<_>>()
+        .into();
+    let batch = RecordBatch::try_from_iter(vec![
+        ("time", Arc::new(times.with_timezone_utc()) as _),
+        ("value", Arc::new(values) as _),
+    ])
+    .unwrap();
+    ctx.register_batch("measures", batch).unwrap();
+    let results = ctx
+        .sql("SELECT time, value FROM downsample('SELECT time, value FROM measures ORDER BY time', 50)")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let nb_rows: usize = results.iter().map(RecordBatch::num_rows).sum();
+    assert_eq!(nb_rows, 50);
+    let first = &results[0];
+    assert_eq!(
+        first
+            .column(0)
+            .as_primitive::<TimestampNanosecondType>()
+            .value(0),
+        0
+    );
+    let max_value = results
+        .iter()
+        .flat_map(|b| b.column(1).as_primitive::<Float64Type>().values().to_vec())
+        .fold(f64::MIN, f64::max);
+    assert_eq!(max_value, 49.0);
+}
@@ -0,0 +1,98 @@
+use datafusion::arrow::array::{
+    AsArray, Float64Array, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray,
+    UInt32Array,
+};
+use datafusion::arrow::datatypes::Int64Type;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::events_within_spans::{
+    enclosing_spans, register_events_within_spans,
+};
+use std::sync::Arc;
+
+#[test]
+fn test_enclosing_spans() {
+    // span 0: [0, 100], span 1: [10, 40] in span 0, span 2: [50, 60] in span 0, span 3: [200, 300]
+    let begins = [0, 10, 50, 200];
+    let ends = [100, 40, 60, 300];
+    let events = [-5, 0, 15, 45, 55, 100, 150, 250, 400];
+    assert_eq!(
+        enclosing_spans(&events, &begins, &ends),
+        vec![
+            None,
+            Some(0),
+            Some(1),
+            Some(0),
+            Some(2),
+            Some(0),
+            None,
+            Some(3),
+            None
+        ]
+    );
+}
+
+fn timestamps(values: Vec<i64>) -> Arc<TimestampNanosecondArray> {
+    Arc::new(TimestampNanosecondArray::from(values).with_timezone_utc())
+}
+
+#[tokio::test]
+async fn test_events_within_spans_table_function() {
+    let ctx = SessionContext::new();
+    register_events_within_spans(&ctx);
+    let spans = RecordBatch::try_from_iter(vec![
+        ("id", Arc::new(Int64Array::from(vec![1, 2, 3])) as _),
+        ("depth", Arc::new(UInt32Array::from(vec![0, 1, 0])) as _),
+        (
+            "name",
+            Arc::new(StringArray::from(vec!["frame", "render", "frame"])) as _,
+        ),
+        ("begin", timestamps(vec![0, 10, 100]) as _),
+        ("end", timestamps(vec![90, 50, 190]) as _),
+    ])
+    .unwrap();
+    ctx.register_batch("spans", spans).unwrap();
+    let measures = RecordBatch::try_from_iter(vec![
+        ("time", timestamps(vec![5, 20, 95, 150]) as _),
+        (
+            "value",
+            Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0, 4.0])) as _,
+        ),
+    ])
+    .unwrap();
+    ctx.register_batch("measures", measures).unwrap();
+
+    let results = ctx
+        .sql("SELECT value, span_id, span_name FROM events_within_spans('measures', 'spans') ORDER BY value")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.num_rows(), 3);
+    assert_eq!(
+        batch
+            .column(1)
+            .as_primitive::<Int64Type>()
+            .values()
+            .to_vec(),
+        vec![1, 2, 3]
+    );
+    assert_eq!(batch.column(2).as_string::<i32>().value(1), "render");
+
+    let results = ctx
+        .sql("SELECT span_id FROM events_within_spans('measures', 'spans', 'depth = 0') ORDER BY time")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        results[0]
+            .column(0)
+            .as_primitive::<Int64Type>()
+            .values()
+            .to_vec(),
+        vec![1, 1, 3]
+    );
+}
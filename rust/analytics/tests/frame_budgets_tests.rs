@@ -0,0 +1,97 @@
+use datafusion::arrow::array::{
+    AsArray, Int64Array, RecordBatch, StringArray, TimestampNanosecondArray, UInt32Array,
+};
+use datafusion::arrow::datatypes::Float64Type;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::frame_budgets::{
+    parse_frame_budgets, register_frame_budget_violations, time_spent_per_frame,
+};
+use std::sync::Arc;
+
+#[test]
+fn test_parse_frame_budgets() {
+    assert_eq!(
+        parse_frame_budgets("Physics=4;Render=8.5").unwrap(),
+        vec![("Physics".to_owned(), 4.0), ("Render".to_owned(), 8.5)]
+    );
+    assert!(parse_frame_budgets("").unwrap().is_empty());
+    assert!(parse_frame_budgets("Physics").is_err());
+    assert!(parse_frame_budgets("Physics=fast").is_err());
+}
+
+#[test]
+fn test_time_spent_per_frame() {
+    // frames [0, 100] and [100, 200]
+    // name 0: [10, 30] and [20, 25] nested in it, [40, 50], then [120, 130]
+    // name 1: [60, 90], then [190, 210] crossing the end of the second frame
+    let spent = time_spent_per_frame(
+        &[0, 100],
+        &[100, 200],
+        &[0, 0, 0, 1, 0, 1],
+        &[10, 20, 40, 60, 120, 190],
+        &[30, 25, 50, 90, 130, 210],
+    );
+    assert_eq!(spent, vec![(0, 0, 30), (0, 1, 30), (1, 0, 10)]);
+}
+
+fn timestamps(values: Vec<i64>) -> Arc<TimestampNanosecondArray> {
+    Arc::new(TimestampNanosecondArray::from(values).with_timezone_utc())
+}
+
+#[tokio::test]
+async fn test_frame_budget_violations_table_function() {
+    let ctx = SessionContext::new();
+    register_frame_budget_violations(&ctx);
+    let ms = 1_000_000;
+    let spans = RecordBatch::try_from_iter(vec![
+        ("id", Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])) as _),
+        (
+            "depth",
+            Arc::new(UInt32Array::from(vec![0, 1, 1, 0, 1])) as _,
+        ),
+        (
+            "name",
+            Arc::new(StringArray::from(vec![
+                "frame", "Physics", "Render", "frame", "Physics",
+            ])) as _,
+        ),
+        (
+            "begin",
+            timestamps(vec![0, ms, 6 * ms, 20 * ms, 21 * ms]) as _,
+        ),
+        (
+            "end",
+            timestamps(vec![16 * ms, 6 * ms, 15 * ms, 36 * ms, 24 * ms]) as _,
+        ),
+    ])
+    .unwrap();
+    ctx.register_batch("spans", spans).unwrap();
+
+    let results = ctx
+        .sql("SELECT span_name, spent_ms, budget_ms FROM frame_budget_violations('spans', 'frame', 'Physics=4;Render=8') ORDER BY frame_begin, span_name")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(batch.column(0).as_string::<i32>().value(0), "Physics");
+    assert_eq!(batch.column(0).as_string::<i32>().value(1), "Render");
+    assert_eq!(
+        batch
+            .column(1)
+            .as_primitive::<Float64Type>()
+            .values()
+            .to_vec(),
+        vec![5.0, 9.0]
+    );
+    assert_eq!(
+        batch
+            .column(2)
+            .as_primitive::<Float64Type>()
+            .values()
+            .to_vec(),
+        vec![4.0, 8.0]
+    );
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{AsArray, Float64Array, RecordBatch, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::{DataType, Field, Float64Type, Schema, TimeUnit};
+use datafusion::datasource::MemTable;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::gap_fill::{fill_gaps, GapFillStrategy};
+use micromegas_analytics::dfext::register_extension_functions;
+
+#[test]
+fn test_fill_gaps() {
+    let times = [0, 10, 20, 30, 40];
+    let values = [None, Some(1.0), None, Some(3.0), None];
+    assert_eq!(
+        fill_gaps(&times, &values, GapFillStrategy::Locf),
+        vec![None, Some(1.0), Some(1.0), Some(3.0), Some(3.0)]
+    );
+    assert_eq!(
+        fill_gaps(&times, &values, GapFillStrategy::Linear),
+        vec![None, Some(1.0), Some(2.0), Some(3.0), None]
+    );
+    assert_eq!(
+        fill_gaps(&times, &values, GapFillStrategy::Null),
+        values.to_vec()
+    );
+}
+
+#[tokio::test]
+async fn test_gap_fill_sql() {
+    let second = 1_000_000_000;
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(
+            "time",
+            DataType::Timestamp(TimeUnit::Nanosecond, Some("+00:00".into())),
+            false,
+        ),
+        Field::new("value", DataType::Float64, false),
+    ]));
+    // samples at 0s, 1s, and 4s: buckets 2s and 3s are missing
+    let times = TimestampNanosecondArray::from(vec![0, second, 4 * second]).with_timezone_utc();
+    let values = Float64Array::from(vec![10.0, 20.0, 50.0]);
+    let batch =
+        RecordBatch::try_new(schema.clone(), vec![Arc::new(times), Arc::new(values)]).unwrap();
+    let table = MemTable::try_new(schema, vec![vec![batch]]).unwrap();
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    ctx.register_table("measures", Arc::new(table)).unwrap();
+
+    let sql = "SELECT b.bucket,
+                      gap_fill(b.bucket, avg(m.value), 'linear') OVER (ORDER BY b.bucket) AS linear,
+                      gap_fill(b.bucket, avg(m.value), 'locf') OVER (ORDER BY b.bucket) AS locf
+               FROM time_buckets('1970-01-01T00:00:00Z', '1970-01-01T00:00:05Z', INTERVAL '1 second') b
+               LEFT JOIN measures m ON date_bin(INTERVAL '1 second', m.time, TIMESTAMP '1970-01-01T00:00:00Z') = b.bucket
+               GROUP BY b.bucket
+               ORDER BY b.bucket";
+    let results = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.num_rows(), 5);
+    let linear: Vec<Option<f64>> = batch
+        .column(1)
+        .as_primitive::<Float64Type>()
+        .iter()
+        .collect();
+    let locf: Vec<Option<f64>> = batch
+        .column(2)
+        .as_primitive::<Float64Type>()
+        .iter()
+        .collect();
+    assert_eq!(
+        linear,
+        vec![Some(10.0), Some(20.0), Some(30.0), Some(40.0), Some(50.0)]
+    );
+    assert_eq!(
+        locf,
+        vec![Some(10.0), Some(20.0), Some(20.0), Some(20.0), Some(50.0)]
+    );
+}
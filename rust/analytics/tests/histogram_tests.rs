@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{AsArray, Float64Array, RecordBatch};
+use datafusion::arrow::datatypes::{DataType, Field, Float64Type, Schema};
+use datafusion::datasource::MemTable;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::histogram::{Histogram, HistogramLayout, MAX_HISTOGRAM_BINS};
+use micromegas_analytics::dfext::register_extension_functions;
+
+#[test]
+fn test_exponential_bins() {
+    let layout = HistogramLayout::Exponential {
+        start: 1.0,
+        growth_factor: 2.0,
+    };
+    let mut histogram = Histogram::new(layout, 10).unwrap();
+    for value in [0.5, 1.5, 3.0, 3.5, 100.0, 10_000.0] {
+        histogram.insert(value);
+    }
+    assert_eq!(histogram.bins, vec![2, 2, 0, 0, 0, 0, 1, 0, 0, 1]);
+    assert_eq!(histogram.count, 6);
+    assert_eq!(histogram.quantile(0.0), Some(0.5));
+    assert_eq!(histogram.quantile(1.0), Some(10_000.0));
+
+    let mut other = Histogram::new(layout, 10).unwrap();
+    other.insert(5.0);
+    histogram.merge(&other).unwrap();
+    assert_eq!(histogram.bins[2], 1);
+    let linear = Histogram::new(
+        HistogramLayout::Linear {
+            start: 1.0,
+            end: 2.0,
+        },
+        10,
+    )
+    .unwrap();
+    assert!(histogram.merge(&linear).is_err());
+}
+
+#[test]
+fn test_max_bins() {
+    let layout = HistogramLayout::Linear {
+        start: 0.0,
+        end: 1.0,
+    };
+    assert!(Histogram::new(layout, MAX_HISTOGRAM_BINS).is_ok());
+    assert!(Histogram::new(layout, MAX_HISTOGRAM_BINS + 1).is_err());
+    assert!(Histogram::new(layout, 4_000_000_000_000).is_err());
+}
+
+#[test]
+fn test_convert_layout() {
+    let mut linear = Histogram::new(
+        HistogramLayout::Linear {
+            start: 0.0,
+            end: 1000.0,
+        },
+        100,
+    )
+    .unwrap();
+    for value in 0..1000 {
+        linear.insert(f64::from(value));
+    }
+    let exponential = linear
+        .convert(
+            HistogramLayout::Exponential {
+                start: 1.0,
+                growth_factor: 10.0,
+            },
+            3,
+        )
+        .unwrap();
+    assert_eq!(exponential.count, 1000);
+    assert_eq!(exponential.bins.iter().sum::<u64>(), 1000);
+    assert_eq!(exponential.bins, vec![10, 90, 900]);
+    let median = exponential.quantile(0.5).unwrap();
+    assert!((median - 500.0).abs() < 50.0, "median={median}");
+}
+
+#[tokio::test]
+async fn test_histogram_sql() {
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "value",
+        DataType::Float64,
+        false,
+    )]));
+    let values = Float64Array::from((1..=1000).map(f64::from).collect::<Vec<_>>());
+    let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(values)]).unwrap();
+    let table = MemTable::try_new(schema, vec![vec![batch]]).unwrap();
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    ctx.register_table("measures", Arc::new(table)).unwrap();
+
+    let sql = "SELECT quantile_from_histogram(make_exponential_histogram(1.0, 1.1, 100, value), 0.5) AS p50,
+                      quantile_from_histogram(histogram_to_linear(make_exponential_histogram(1.0, 1.1, 100, value), 0.0, 1000.0, 10), 0.9) AS p90
+               FROM measures";
+    let results = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+    let p50 = results[0].column(0).as_primitive::<Float64Type>().value(0);
+    let p90 = results[0].column(1).as_primitive::<Float64Type>().value(0);
+    assert!((p50 - 500.0).abs() < 50.0, "p50={p50}");
+    assert!((p90 - 900.0).abs() < 50.0, "p90={p90}");
+
+    let sql = "SELECT quantile_from_histogram(sum_histograms(h), 0.5) AS p50
+               FROM (SELECT make_histogram(0.0, 1000.0, 100, value) AS h FROM measures GROUP BY value > 500)";
+    let results = ctx.sql(sql).await.unwrap().collect().await.unwrap();
+    let p50 = results[0].column(0).as_primitive::<Float64Type>().value(0);
+    assert!((p50 - 500.0).abs() < 10.0, "p50={p50}");
+
+    for sql in [
+        "SELECT make_histogram(0, 1, 4000000000000, value) FROM measures",
+        "SELECT histogram_to_linear(make_histogram(0, 1, 10, value), 0, 1, 4000000000000) FROM measures",
+    ] {
+        let res = ctx.sql(sql).await.unwrap().collect().await;
+        assert!(res.is_err(), "{sql}");
+    }
+}
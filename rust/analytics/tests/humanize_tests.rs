@@ -0,0 +1,40 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::humanize::{format_bytes, format_duration};
+use micromegas_analytics::dfext::register_extension_functions;
+
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(0.0), "0 ns");
+    assert_eq!(format_duration(512.0), "512 ns");
+    assert_eq!(format_duration(1_500.0), "1.5 µs");
+    assert_eq!(format_duration(12_345_678.0), "12.3 ms");
+    assert_eq!(format_duration(1_240_000_000.0), "1.24 s");
+    assert_eq!(format_duration(90_000_000_000.0), "1.5 min");
+    assert_eq!(format_duration(-2_000_000_000.0), "-2 s");
+}
+
+#[test]
+fn test_format_bytes() {
+    assert_eq!(format_bytes(0.0), "0 B");
+    assert_eq!(format_bytes(1023.0), "1023 B");
+    assert_eq!(format_bytes(1536.0), "1.5 KiB");
+    assert_eq!(format_bytes(3.1 * 1024.0 * 1024.0 * 1024.0), "3.1 GiB");
+}
+
+#[tokio::test]
+async fn test_humanize_sql() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let results = ctx
+        .sql("SELECT format_duration(1240000000) AS d, format_bytes(2048) AS b, format_bytes(NULL) AS n")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.column(0).as_string::<i32>().value(0), "1.24 s");
+    assert_eq!(batch.column(1).as_string::<i32>().value(0), "2 KiB");
+    assert!(batch.column(2).is_null(0));
+}
@@ -0,0 +1,55 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::log_category::log_category;
+use micromegas_analytics::dfext::register_extension_functions;
+use micromegas_analytics::log_entries_table::LogEntriesRecordBuilder;
+use micromegas_analytics::log_entry::LogEntry;
+use std::sync::Arc;
+
+#[test]
+fn test_log_category() {
+    assert_eq!(log_category("[LogNet] connection lost"), Some("LogNet"));
+    assert_eq!(
+        log_category("[2024.05.01-10.00.00:000][  0][LogTemp] hello"),
+        Some("LogTemp")
+    );
+    assert_eq!(log_category("no category"), None);
+    assert_eq!(log_category("[unclosed"), None);
+    assert_eq!(log_category("[Log Net] spaces"), None);
+}
+
+#[tokio::test]
+async fn test_log_category_sql() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let results = ctx
+        .sql("SELECT log_category('[LogNet] lost') AS c, log_category('plain') AS p")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.column(0).as_string::<i32>().value(0), "LogNet");
+    assert!(batch.column(1).is_null(0));
+}
+
+#[test]
+fn test_category_column() {
+    let mut builder = LogEntriesRecordBuilder::with_capacity(2).with_categories();
+    for msg in ["[LogNet] lost", "plain"] {
+        builder
+            .append(&LogEntry {
+                time: 0,
+                ticks: 0,
+                level: 3,
+                target: Arc::new("unreal".to_owned()),
+                msg: Arc::new(msg.to_owned()),
+            })
+            .unwrap();
+    }
+    let batch = builder.finish().unwrap();
+    let categories = batch.column_by_name("category").unwrap();
+    assert!(categories.is_valid(0));
+    assert!(categories.is_null(1));
+}
@@ -0,0 +1,50 @@
+use micromegas_analytics::parse_block;
+use micromegas_telemetry::compression::{CompressionCodec, COMPRESSION_PROPERTY};
+use micromegas_telemetry_sink::stream_block::StreamBlock;
+use micromegas_telemetry_sink::stream_info::make_stream_info;
+use micromegas_tracing::dispatch::make_process_info;
+use micromegas_tracing::event::TracingBlock;
+use micromegas_tracing::logs::LogBlock;
+use micromegas_tracing::logs::LogStream;
+use micromegas_tracing::logs::LogStringInteropEvent;
+use micromegas_transit::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[test]
+fn test_log_encode_zstd() {
+    let process_id = uuid::Uuid::new_v4();
+    let process_info = make_process_info(process_id, Some(uuid::Uuid::new_v4()));
+    let mut stream = LogStream::new(1024, process_id, &[], HashMap::new());
+    let stream_id = stream.stream_id();
+    stream.get_events_mut().push(LogStringInteropEvent {
+        time: 1,
+        level: 2,
+        target: "target_name".into(),
+        msg: micromegas_transit::DynString(String::from("my message")),
+    });
+    let mut block = stream.replace_block(Arc::new(LogBlock::new(1024, process_id, stream_id, 0)));
+    Arc::get_mut(&mut block).unwrap().close();
+    let encoded = block
+        .encode_bin_with_codec(&process_info, &CompressionCodec::Zstd)
+        .unwrap();
+    let received_block: micromegas_telemetry::block_wire_format::Block =
+        ciborium::from_reader(&encoded[..]).unwrap();
+    let mut stream_info = make_stream_info(&stream);
+    // the codec is declared by the stream, lz4 is assumed otherwise
+    assert!(parse_block(&stream_info, &received_block.payload, |_val| Ok(true)).is_err());
+    stream_info.properties.insert(
+        COMPRESSION_PROPERTY.to_owned(),
+        CompressionCodec::Zstd.name().to_owned(),
+    );
+    let mut nb_objects = 0;
+    parse_block(&stream_info, &received_block.payload, |val| {
+        if let Value::Object(obj) = val {
+            assert_eq!(&*obj.get::<Arc<String>>("msg").unwrap(), "my message");
+            nb_objects += 1;
+        }
+        Ok(true)
+    })
+    .unwrap();
+    assert_eq!(nb_objects, 1);
+}
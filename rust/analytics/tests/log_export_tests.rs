@@ -0,0 +1,48 @@
+use anyhow::Context;
+use micromegas_analytics::log_export::{is_transient_error, LogExportFilter};
+use micromegas_tracing::levels::Level;
+
+#[test]
+fn test_log_export_filter() {
+    let filter = LogExportFilter {
+        max_level: Level::Warn,
+        target_prefixes: vec![],
+    };
+    assert!(filter.matches(Level::Fatal as i32, "any"));
+    assert!(filter.matches(Level::Warn as i32, "any"));
+    assert!(!filter.matches(Level::Info as i32, "any"));
+    assert!(!filter.matches(0, "any"));
+
+    let filter = LogExportFilter {
+        max_level: Level::Error,
+        target_prefixes: vec!["auth".to_owned(), "payments::".to_owned()],
+    };
+    assert!(filter.matches(Level::Error as i32, "auth::login"));
+    assert!(filter.matches(Level::Error as i32, "payments::stripe"));
+    assert!(!filter.matches(Level::Error as i32, "render"));
+    assert!(!filter.matches(Level::Warn as i32, "auth::login"));
+}
+
+#[test]
+fn test_transient_errors() {
+    let unavailable = anyhow::Error::from(sqlx::Error::PoolTimedOut).context("find_process");
+    assert!(is_transient_error(&unavailable));
+    let missing_row = anyhow::Error::from(sqlx::Error::RowNotFound);
+    assert!(!is_transient_error(&missing_row));
+
+    let missing_payload: anyhow::Result<()> = Err(object_store::Error::NotFound {
+        path: "blobs/block".to_owned(),
+        source: "not found".into(),
+    })
+    .with_context(|| "reading block payload from blob storage");
+    assert!(!is_transient_error(&missing_payload.unwrap_err()));
+    let store_error: anyhow::Result<()> = Err(object_store::Error::Generic {
+        store: "S3",
+        source: "connection reset".into(),
+    })
+    .with_context(|| "reading block payload from blob storage");
+    assert!(is_transient_error(&store_error.unwrap_err()));
+
+    let undecodable = anyhow::anyhow!("reading payload").context("parse_block");
+    assert!(!is_transient_error(&undecodable));
+}
@@ -0,0 +1,38 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::Int32Type;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::log_level::{level_name, level_value};
+use micromegas_analytics::dfext::register_extension_functions;
+
+#[test]
+fn test_level_names() {
+    assert_eq!(level_name(1), Some("FATAL"));
+    assert_eq!(level_name(3), Some("WARN"));
+    assert_eq!(level_name(6), Some("TRACE"));
+    assert_eq!(level_name(0), None);
+    assert_eq!(level_name(-1), None);
+    assert_eq!(level_value("WARN"), Some(3));
+    assert_eq!(level_value("error"), Some(2));
+    assert_eq!(level_value("off"), None);
+    assert_eq!(level_value("loud"), None);
+    for level in 1..=6 {
+        assert_eq!(level_value(level_name(level).unwrap()), Some(level as i32));
+    }
+}
+
+#[tokio::test]
+async fn test_level_sql() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let results = ctx
+        .sql("SELECT level_name(CAST(4 AS INT)) AS name, level_value('Warn') AS value, level_name(9) AS unknown")
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    assert_eq!(batch.column(0).as_string::<i32>().value(0), "INFO");
+    assert_eq!(batch.column(1).as_primitive::<Int32Type>().value(0), 3);
+    assert!(batch.column(2).is_null(0));
+}
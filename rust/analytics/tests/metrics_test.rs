@@ -1,13 +1,16 @@
 use std::{collections::HashMap, sync::Arc};
 
-use micromegas_analytics::parse_block;
+use micromegas_analytics::{measure::measure_from_value, parse_block, time::ConvertTicks};
 use micromegas_telemetry_sink::{
     stream_block::StreamBlock, stream_info::make_stream_info, TelemetryGuard,
 };
 use micromegas_tracing::{
     dispatch::make_process_info,
     event::TracingBlock,
-    metrics::{FloatMetricEvent, IntegerMetricEvent, MetricMetadata, MetricsBlock, MetricsStream},
+    metrics::{
+        AggregatedMetricEvent, FloatMetricEvent, IntegerMetricEvent, MetricMetadata, MetricsBlock,
+        MetricsStream,
+    },
     prelude::Verbosity,
 };
 
@@ -39,6 +42,15 @@ fn test_parse_metric_interops() {
         value: 3.0,
         time: 2,
     });
+    stream.get_events_mut().push(AggregatedMetricEvent {
+        desc: &METRIC_DESC,
+        sum: 10.0,
+        count: 4,
+        min: 1.0,
+        max: 4.0,
+        begin_time: 3,
+        time: 4,
+    });
 
     let mut block =
         stream.replace_block(Arc::new(MetricsBlock::new(1024, process_id, stream_id, 0)));
@@ -48,11 +60,14 @@ fn test_parse_metric_interops() {
         ciborium::from_reader(&encoded[..]).unwrap();
 
     let stream_info = make_stream_info(&stream);
-    let mut nb_metric_entries = 0;
-    parse_block(&stream_info, &received_block.payload, |_val| {
-        nb_metric_entries += 1;
+    let convert_ticks = ConvertTicks::new(&process_info);
+    let mut values = vec![];
+    parse_block(&stream_info, &received_block.payload, |val| {
+        let measure = measure_from_value(&convert_ticks, &val)?.unwrap();
+        values.push(measure.value);
         Ok(true)
     })
     .unwrap();
-    assert_eq!(nb_metric_entries, 2);
+    // aggregated values are measured by their mean
+    assert_eq!(values, vec![3.0, 3.0, 2.5]);
 }
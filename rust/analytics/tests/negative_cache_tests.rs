@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use datafusion::arrow::array::Int64Array;
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_analytics::negative_cache::{EmptyResultKey, NegativeCache};
+use sqlx::types::chrono::{TimeZone, Utc};
+
+fn make_key() -> EmptyResultKey {
+    EmptyResultKey {
+        view: "spans",
+        stream_id: uuid::Uuid::new_v4(),
+        begin: Utc.timestamp_opt(0, 0).unwrap(),
+        end: Utc.timestamp_opt(60, 0).unwrap(),
+    }
+}
+
+fn make_batch(values: Vec<i64>) -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(values))]).unwrap()
+}
+
+#[test]
+fn test_negative_cache_backoff() {
+    let cache = NegativeCache::new(Duration::from_secs(10), Duration::from_secs(30));
+    let key = make_key();
+    let start = Instant::now();
+    assert!(cache.get(&key, start).is_none());
+
+    // results with rows are never cached
+    cache.insert(key.clone(), &make_batch(vec![1]), start);
+    assert!(cache.is_empty());
+
+    cache.insert(key.clone(), &make_batch(vec![]), start);
+    let cached = cache.get(&key, start + Duration::from_secs(5)).unwrap();
+    assert_eq!(cached.num_rows(), 0);
+    assert_eq!(cached.schema().field(0).name(), "id");
+    assert!(cache.get(&key, start + Duration::from_secs(10)).is_none());
+
+    // recomputed empty: the ttl doubles
+    let second = start + Duration::from_secs(10);
+    cache.insert(key.clone(), &make_batch(vec![]), second);
+    assert!(cache.get(&key, second + Duration::from_secs(19)).is_some());
+    assert!(cache.get(&key, second + Duration::from_secs(20)).is_none());
+
+    // up to the max ttl
+    let third = second + Duration::from_secs(20);
+    cache.insert(key.clone(), &make_batch(vec![]), third);
+    assert!(cache.get(&key, third + Duration::from_secs(29)).is_some());
+    assert!(cache.get(&key, third + Duration::from_secs(30)).is_none());
+}
+
+#[test]
+fn test_negative_cache_disabled() {
+    let cache = NegativeCache::new(Duration::ZERO, Duration::ZERO);
+    cache.insert(make_key(), &make_batch(vec![]), Instant::now());
+    assert!(cache.is_empty());
+}
@@ -0,0 +1,204 @@
+use datafusion::arrow::datatypes::Schema;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::parquet::basic::{Compression, Encoding, ZstdLevel};
+use datafusion::parquet::file::properties::WriterProperties;
+use datafusion::parquet::schema::types::ColumnPath;
+use micromegas_analytics::view_config::{ViewRegistry, ViewsConfig};
+use std::sync::Arc;
+
+fn writer_properties(registry: &ViewRegistry, view_name: &str) -> WriterProperties {
+    let batch = RecordBatch::new_empty(Arc::new(Schema::empty()));
+    registry.prepare_batch(view_name, &batch).unwrap().1
+}
+
+#[test]
+fn test_parquet_config() {
+    let config: ViewsConfig = serde_json::from_str(
+        r#"{
+            "parquet": {
+                "spans": {
+                    "compression": "zstd(6)",
+                    "dictionary_enabled": false,
+                    "column_encodings": { "begin": "delta_binary_packed" },
+                    "max_row_group_size": 4096
+                },
+                "processes": { "compression": "snappy" }
+            }
+        }"#,
+    )
+    .unwrap();
+    let registry = ViewRegistry::from_config(&config).unwrap();
+    let spans = writer_properties(&registry, "spans");
+    let begin = ColumnPath::from("begin");
+    assert_eq!(
+        spans.compression(&begin),
+        Compression::ZSTD(ZstdLevel::try_new(6).unwrap())
+    );
+    assert!(!spans.dictionary_enabled(&begin));
+    assert_eq!(spans.encoding(&begin), Some(Encoding::DELTA_BINARY_PACKED));
+    assert_eq!(spans.max_row_group_size(), 4096);
+    assert_eq!(
+        writer_properties(&registry, "processes").compression(&begin),
+        Compression::SNAPPY
+    );
+    // views without settings keep the defaults
+    assert_eq!(
+        writer_properties(&registry, "log_entries").compression(&begin),
+        Compression::LZ4_RAW
+    );
+
+    let mut unknown_view = config.clone();
+    unknown_view
+        .parquet
+        .insert("nope".to_owned(), Default::default());
+    assert!(ViewRegistry::from_config(&unknown_view).is_err());
+
+    let mut invalid = config;
+    invalid.parquet.get_mut("spans").unwrap().compression = Some("zstd".to_owned());
+    assert!(ViewRegistry::from_config(&invalid).is_err());
+}
+
+#[test]
+fn test_bloom_filter_config() {
+    use datafusion::arrow::array::StringArray;
+    use datafusion::parquet::arrow::ArrowWriter;
+    use datafusion::parquet::data_type::ByteArray;
+    use datafusion::parquet::file::properties::ReaderProperties;
+    use datafusion::parquet::file::reader::FileReader;
+    use datafusion::parquet::file::serialized_reader::{ReadOptionsBuilder, SerializedFileReader};
+
+    let mut config: ViewsConfig = serde_json::from_str(
+        r#"{
+            "parquet": {
+                "processes": {
+                    "bloom_filter_columns": ["process_id"],
+                    "bloom_filter_fpp": 0.01,
+                    "bloom_filter_ndv": 1000
+                }
+            }
+        }"#,
+    )
+    .unwrap();
+    let registry = ViewRegistry::from_config(&config).unwrap();
+    let props = writer_properties(&registry, "processes");
+    let process_id = ColumnPath::from("process_id");
+    assert!(props.bloom_filter_properties(&process_id).is_some());
+    assert!(props
+        .bloom_filter_properties(&ColumnPath::from("exe"))
+        .is_none());
+
+    let ids: Vec<String> = (0..100).map(|i| format!("process-{i}")).collect();
+    let batch = RecordBatch::try_from_iter(vec![(
+        "process_id",
+        Arc::new(StringArray::from(ids.clone())) as _,
+    )])
+    .unwrap();
+    let mut buffer = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), Some(props)).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let options = ReadOptionsBuilder::new()
+        .with_reader_properties(
+            ReaderProperties::builder()
+                .set_read_bloom_filter(true)
+                .build(),
+        )
+        .build();
+    let reader =
+        SerializedFileReader::new_with_options(bytes::Bytes::from(buffer), options).unwrap();
+    let row_group = reader.get_row_group(0).unwrap();
+    let bloom_filter = row_group.get_column_bloom_filter(0).unwrap();
+    for id in &ids {
+        assert!(bloom_filter.check(&ByteArray::from(id.as_str())));
+    }
+
+    config
+        .parquet
+        .get_mut("processes")
+        .unwrap()
+        .bloom_filter_fpp = Some(1.5);
+    assert!(ViewRegistry::from_config(&config).is_err());
+}
+
+#[test]
+fn test_sort_order_config() {
+    use datafusion::arrow::array::{AsArray, Int64Array, ListArray, StringArray};
+    use datafusion::arrow::datatypes::Int64Type;
+    use datafusion::parquet::arrow::ArrowWriter;
+    use datafusion::parquet::file::reader::FileReader;
+    use datafusion::parquet::file::serialized_reader::SerializedFileReader;
+    use datafusion::parquet::format::SortingColumn;
+    use micromegas_analytics::parquet_config::SortKey;
+
+    assert_eq!(
+        SortKey::parse("start_time desc").unwrap(),
+        SortKey {
+            column: "start_time".to_owned(),
+            descending: true
+        }
+    );
+    assert!(SortKey::parse("start_time sideways").is_err());
+    assert!(SortKey::parse("").is_err());
+
+    let mut config: ViewsConfig = serde_json::from_str(
+        r#"{ "parquet": { "processes": { "sort_order": ["exe", "start_time DESC"] } } }"#,
+    )
+    .unwrap();
+    let registry = ViewRegistry::from_config(&config).unwrap();
+    let tags = ListArray::from_iter_primitive::<Int64Type, _, _>(vec![
+        Some(vec![Some(1)]),
+        None,
+        Some(vec![Some(2), Some(3)]),
+    ]);
+    let batch = RecordBatch::try_from_iter(vec![
+        ("tags", Arc::new(tags) as _),
+        (
+            "exe",
+            Arc::new(StringArray::from(vec!["server", "editor", "server"])) as _,
+        ),
+        ("start_time", Arc::new(Int64Array::from(vec![1, 2, 3])) as _),
+    ])
+    .unwrap();
+    let (sorted, props) = registry.prepare_batch("processes", &batch).unwrap();
+    assert_eq!(
+        sorted.column(2).as_primitive::<Int64Type>().values(),
+        &[2, 3, 1]
+    );
+
+    let mut buffer = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buffer, sorted.schema(), Some(props)).unwrap();
+    writer.write(&sorted).unwrap();
+    writer.close().unwrap();
+    let reader = SerializedFileReader::new(bytes::Bytes::from(buffer)).unwrap();
+    // the list of tags is the first leaf column
+    assert_eq!(
+        reader.metadata().row_group(0).sorting_columns().unwrap(),
+        &vec![
+            SortingColumn {
+                column_idx: 1,
+                descending: false,
+                nulls_first: false
+            },
+            SortingColumn {
+                column_idx: 2,
+                descending: true,
+                nulls_first: false
+            },
+        ]
+    );
+
+    // views without sort order are written as they are
+    let (unsorted, _props) = registry.prepare_batch("streams", &batch).unwrap();
+    assert_eq!(unsorted, batch);
+
+    let by_tags = ViewRegistry::from_config(
+        &serde_json::from_str(r#"{ "parquet": { "processes": { "sort_order": ["tags"] } } }"#)
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(by_tags.prepare_batch("processes", &batch).is_err());
+
+    config.parquet.get_mut("processes").unwrap().sort_order = vec!["exe up".to_owned()];
+    assert!(ViewRegistry::from_config(&config).is_err());
+}
@@ -0,0 +1,34 @@
+use micromegas_telemetry::blob_storage::BlobStorage;
+use object_store::aws::AmazonS3Builder;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_signed_payload_url() {
+    let s3 = Arc::new(
+        AmazonS3Builder::new()
+            .with_bucket_name("telemetry")
+            .with_region("us-east-1")
+            .with_access_key_id("key")
+            .with_secret_access_key("secret")
+            .build()
+            .unwrap(),
+    );
+    let storage = BlobStorage::new(s3.clone(), Path::from("lake")).with_signer(s3);
+    let url = storage
+        .signed_url("blobs/p/s/b", Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert!(url.path().ends_with("/lake/blobs/p/s/b"));
+    let query = url.query().unwrap();
+    assert!(query.contains("X-Amz-Expires=60"));
+    assert!(query.contains("X-Amz-Signature="));
+
+    let storage = BlobStorage::new(Arc::new(InMemory::new()), Path::from("lake"));
+    assert!(storage
+        .signed_url("blobs/p/s/b", Duration::from_secs(60))
+        .await
+        .is_err());
+}
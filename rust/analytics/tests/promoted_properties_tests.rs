@@ -0,0 +1,57 @@
+use datafusion::arrow::array::{Array, AsArray, Float64Array, RecordBatch};
+use micromegas_analytics::promoted_properties::{
+    append_property_columns, processes_select_list, validate_promoted_properties,
+};
+use micromegas_analytics::view_config::{ViewRegistry, ViewsConfig};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|&name| name.to_owned()).collect()
+}
+
+#[test]
+fn test_promoted_properties_validation() {
+    validate_promoted_properties(&names(&["version", "platform"])).unwrap();
+    assert!(validate_promoted_properties(&names(&["version'; DROP TABLE blocks"])).is_err());
+    // would shadow columns of the views
+    assert!(validate_promoted_properties(&names(&["exe"])).is_err());
+    assert!(validate_promoted_properties(&names(&["msg"])).is_err());
+    assert!(validate_promoted_properties(&names(&["version", "version"])).is_err());
+}
+
+#[test]
+fn test_promoted_processes_view() {
+    assert_eq!(processes_select_list(&[]), "*");
+    let config: ViewsConfig =
+        serde_json::from_str(r#"{"promoted_properties": ["version"]}"#).unwrap();
+    let registry = ViewRegistry::from_config(&config).unwrap();
+    assert_eq!(registry.promoted_properties(), &names(&["version"]));
+    assert_eq!(
+        registry.find_view("processes").unwrap().sql,
+        "SELECT *, (SELECT p.value FROM unnest(properties) AS p WHERE p.key = 'version' LIMIT 1) AS version FROM processes"
+    );
+    let invalid: ViewsConfig =
+        serde_json::from_str(r#"{"promoted_properties": ["start_time"]}"#).unwrap();
+    assert!(ViewRegistry::from_config(&invalid).is_err());
+}
+
+#[test]
+fn test_append_property_columns() {
+    let values = Arc::new(Float64Array::from(vec![1.0, 2.0, 3.0]));
+    let batch = RecordBatch::try_from_iter(vec![("value", values as _)]).unwrap();
+    let properties = HashMap::from([("version".to_owned(), "1.2.3".to_owned())]);
+    let unchanged = append_property_columns(batch.clone(), &properties, &[]).unwrap();
+    assert_eq!(unchanged, batch);
+
+    let promoted =
+        append_property_columns(batch, &properties, &names(&["version", "region"])).unwrap();
+    assert_eq!(promoted.num_columns(), 3);
+    let versions = promoted
+        .column_by_name("version")
+        .unwrap()
+        .as_string::<i32>();
+    assert!(versions.iter().all(|version| version == Some("1.2.3")));
+    let regions = promoted.column_by_name("region").unwrap();
+    assert_eq!(regions.null_count(), 3);
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::{TimestampNanosecondType, UInt64Type};
+use micromegas_analytics::query_heatmap::QueryHeatmap;
+use sqlx::types::chrono::{DateTime, Utc};
+
+const NS_PER_DAY: i64 = 24 * 60 * 60 * 1_000_000_000;
+
+fn time(text: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(text).unwrap().into()
+}
+
+#[test]
+fn test_query_heatmap() {
+    let heatmap = QueryHeatmap::new(4);
+    // spans the end of the first day and the beginning of the second
+    heatmap.record(
+        "spans",
+        "stream_a",
+        time("2024-05-01T20:00:00Z"),
+        time("2024-05-02T04:00:00Z"),
+        true,
+        Duration::from_millis(100),
+    );
+    heatmap.record(
+        "spans",
+        "stream_a",
+        time("2024-05-02T10:00:00Z"),
+        time("2024-05-02T11:00:00Z"),
+        false,
+        Duration::from_millis(300),
+    );
+    heatmap.record(
+        "processes",
+        "",
+        time("2024-05-02T00:00:00Z"),
+        time("2024-05-03T00:00:00Z"),
+        true,
+        Duration::from_millis(50),
+    );
+    let cells = heatmap.cells();
+    assert_eq!(cells.len(), 3);
+    let second_day = cells
+        .iter()
+        .find(|(cell, _)| cell.view == "spans" && cell.day == 19845 * NS_PER_DAY)
+        .unwrap()
+        .1;
+    assert_eq!(second_day.nb_queries, 2);
+    assert_eq!(second_day.nb_errors, 1);
+    assert_eq!(second_day.duration_ns, 400_000_000);
+    assert_eq!(second_day.max_duration_ns, 300_000_000);
+
+    // the oldest day is forgotten to make room for a more recent one
+    heatmap.record(
+        "log_entries",
+        "stream_b",
+        time("2024-05-03T10:00:00Z"),
+        time("2024-05-03T11:00:00Z"),
+        true,
+        Duration::from_millis(10),
+    );
+    heatmap.record(
+        "log_entries",
+        "stream_b",
+        time("2024-05-04T10:00:00Z"),
+        time("2024-05-04T11:00:00Z"),
+        true,
+        Duration::from_millis(10),
+    );
+    let cells = heatmap.cells();
+    assert_eq!(cells.len(), 4);
+    assert!(cells.keys().all(|cell| cell.day >= 19845 * NS_PER_DAY));
+    // but not for an older one
+    heatmap.record(
+        "spans",
+        "stream_c",
+        time("2024-04-01T10:00:00Z"),
+        time("2024-04-01T11:00:00Z"),
+        true,
+        Duration::from_millis(10),
+    );
+    assert_eq!(heatmap.cells(), cells);
+
+    let batch = heatmap.to_record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 4);
+    let days = batch
+        .column_by_name("day")
+        .unwrap()
+        .as_primitive::<TimestampNanosecondType>();
+    assert!(days.values().iter().all(|day| day % NS_PER_DAY == 0));
+    let nb_queries = batch
+        .column_by_name("nb_queries")
+        .unwrap()
+        .as_primitive::<UInt64Type>();
+    assert_eq!(nb_queries.values().iter().sum::<u64>(), 5);
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use datafusion::arrow::array::{AsArray, BooleanArray};
+use datafusion::arrow::datatypes::UInt64Type;
+use micromegas_analytics::query_log::{QueryLog, QueryLogEntry};
+use sqlx::types::chrono::Utc;
+
+fn make_entry(route: &str, duration_ms: u64, concurrency: u64) -> QueryLogEntry {
+    QueryLogEntry {
+        begin: Utc::now(),
+        route: route.to_owned(),
+        query_tag: "untagged".to_owned(),
+        duration: Duration::from_millis(duration_ms),
+        response_bytes: 10,
+        success: duration_ms < 3000,
+        concurrency,
+    }
+}
+
+#[test]
+fn test_slow_query_log() {
+    let log = QueryLog::new(Duration::from_secs(1), 2);
+    log.record(make_entry("/analytics/query_blocks", 10, 1));
+    assert!(log.slow_queries().is_empty());
+
+    log.record(make_entry("/analytics/query_spans", 1000, 1));
+    log.record(make_entry("/analytics/query_metrics", 2000, 2));
+    log.record(make_entry("/analytics/query_view", 3000, 3));
+    // the oldest slow query is evicted
+    let routes: Vec<String> = log.slow_queries().into_iter().map(|e| e.route).collect();
+    assert_eq!(
+        routes,
+        vec!["/analytics/query_metrics", "/analytics/query_view"]
+    );
+
+    let batch = log.slow_queries_record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    let durations = batch
+        .column_by_name("duration_ns")
+        .unwrap()
+        .as_primitive::<UInt64Type>();
+    assert_eq!(durations.value(0), 2_000_000_000);
+    let success = batch.column_by_name("success").unwrap().as_boolean();
+    assert_eq!(success, &BooleanArray::from(vec![true, false]));
+}
+
+#[test]
+fn test_concurrent_queries() {
+    let log = QueryLog::default();
+    let first = log.begin_query();
+    let second = log.begin_query();
+    assert_eq!(first.concurrency(), 1);
+    assert_eq!(second.concurrency(), 2);
+    assert_eq!(log.nb_in_flight(), 2);
+    drop(first);
+    assert_eq!(log.nb_in_flight(), 1);
+    drop(second);
+    assert_eq!(log.nb_in_flight(), 0);
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use datafusion::arrow::array::{AsArray, UInt64Array};
+use micromegas_analytics::query_tags::{sanitize_query_tag, QueryTagStats, TagLoad, OTHER_TAGS};
+
+#[test]
+fn test_sanitize_query_tag() {
+    assert_eq!(sanitize_query_tag(None), "untagged");
+    assert_eq!(sanitize_query_tag(Some(" \t")), "untagged");
+    assert_eq!(sanitize_query_tag(Some("team a\n")), "teama");
+    assert_eq!(sanitize_query_tag(Some(&"x".repeat(100))).len(), 64);
+}
+
+#[test]
+fn test_record_query_tags() {
+    let stats = QueryTagStats::default();
+    let ms = Duration::from_millis(1);
+    stats.record("dashboard", None, "/query", true, ms, 100);
+    stats.record("dashboard", Some("grafana"), "/query", false, ms * 2, 50);
+    stats.record("notebook", None, "/query", true, ms, 10);
+    let loads = stats.loads();
+    assert_eq!(loads.len(), 2);
+    assert_eq!(
+        loads["dashboard"],
+        TagLoad {
+            nb_queries: 2,
+            nb_errors: 1,
+            response_bytes: 150,
+            duration_ns: 3_000_000,
+        }
+    );
+    assert_eq!(loads["notebook"].nb_queries, 1);
+    assert_eq!(loads["notebook"].nb_errors, 0);
+
+    let batch = stats.to_record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    let tags: Vec<&str> = batch
+        .column_by_name("query_tag")
+        .unwrap()
+        .as_string::<i32>()
+        .iter()
+        .flatten()
+        .collect();
+    assert_eq!(tags, ["dashboard", "notebook"]);
+    let nb_queries = batch
+        .column_by_name("nb_queries")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .unwrap();
+    assert_eq!(nb_queries.values(), &[2, 1]);
+}
+
+#[test]
+fn test_query_tags_cap() {
+    let stats = QueryTagStats::new(2);
+    let ms = Duration::from_millis(1);
+    for tag in ["a", "b", "c", "d", "a"] {
+        stats.record(tag, None, "/query", true, ms, 1);
+    }
+    let loads = stats.loads();
+    let tags: Vec<&str> = loads.keys().map(String::as_str).collect();
+    assert_eq!(tags, ["a", "b", OTHER_TAGS]);
+    assert_eq!(loads["a"].nb_queries, 2);
+    assert_eq!(loads["b"].nb_queries, 1);
+    assert_eq!(loads[OTHER_TAGS].nb_queries, 2);
+}
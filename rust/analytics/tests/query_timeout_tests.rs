@@ -0,0 +1,73 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use datafusion::arrow::array::Int64Array;
+use datafusion::arrow::datatypes::{DataType, Field, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_analytics::query_timeout::{
+    QueryDeadline, QueryTimeout, TimeoutMode, TRUNCATED_METADATA_KEY,
+};
+
+fn make_batch() -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::Int64, false)]));
+    RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(vec![1, 2]))]).unwrap()
+}
+
+#[test]
+fn test_parse_query_timeout() {
+    let timeout = QueryTimeout::parse("1500", None).unwrap();
+    assert_eq!(timeout.timeout, Duration::from_millis(1500));
+    assert_eq!(timeout.mode, TimeoutMode::Abort);
+    let timeout = QueryTimeout::parse("10", Some("partial")).unwrap();
+    assert_eq!(timeout.mode, TimeoutMode::Partial);
+    assert!(QueryTimeout::parse("ten", None).is_err());
+    assert!(QueryTimeout::parse("10", Some("later")).is_err());
+}
+
+#[test]
+fn test_query_deadline() {
+    let unbounded = QueryDeadline::unbounded();
+    assert!(!unbounded.expired().unwrap());
+    let batch = unbounded.finish(make_batch()).unwrap();
+    assert!(batch.schema().metadata().is_empty());
+
+    let abort = QueryDeadline::new(Some(&QueryTimeout::parse("0", Some("abort")).unwrap()));
+    assert!(abort.expired().is_err());
+
+    let partial = QueryDeadline::new(Some(&QueryTimeout::parse("0", Some("partial")).unwrap()));
+    assert!(partial.expired().unwrap());
+    assert!(partial.is_truncated());
+    let batch = partial.finish(make_batch()).unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(
+        batch
+            .schema()
+            .metadata()
+            .get(TRUNCATED_METADATA_KEY)
+            .map(String::as_str),
+        Some("true")
+    );
+}
+
+async fn slow_query() -> anyhow::Result<i64> {
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    Ok(1)
+}
+
+#[tokio::test]
+async fn test_query_deadline_cancels_awaits() {
+    let unbounded = QueryDeadline::unbounded();
+    assert_eq!(unbounded.bound(async { Ok(1) }).await.unwrap(), Some(1));
+    assert_eq!(
+        unbounded.abort_at_deadline(async { Ok(1) }).await.unwrap(),
+        1
+    );
+
+    let abort = QueryDeadline::new(Some(&QueryTimeout::parse("10", Some("abort")).unwrap()));
+    assert!(abort.bound(slow_query()).await.is_err());
+    assert!(abort.abort_at_deadline(slow_query()).await.is_err());
+
+    let partial = QueryDeadline::new(Some(&QueryTimeout::parse("10", Some("partial")).unwrap()));
+    assert_eq!(partial.bound(slow_query()).await.unwrap(), None);
+    assert!(partial.is_truncated());
+}
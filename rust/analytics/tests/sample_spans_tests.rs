@@ -0,0 +1,101 @@
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::sample_spans::register_sample_spans;
+use micromegas_analytics::sample_spans::{sample_blocks, SampleSize, SamplingStrategy};
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use micromegas_telemetry::types::block::BlockMetadata;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use std::sync::Arc;
+
+fn make_blocks(nb_blocks: i64) -> Vec<BlockMetadata> {
+    let stream_id = uuid::Uuid::new_v4();
+    let process_id = uuid::Uuid::new_v4();
+    (0..nb_blocks)
+        .map(|index| BlockMetadata {
+            block_id: uuid::Uuid::new_v4(),
+            stream_id,
+            process_id,
+            begin_time: chrono::Utc::now(),
+            end_time: chrono::Utc::now(),
+            begin_ticks: index * 100,
+            end_ticks: (index + 1) * 100,
+            nb_objects: 200,
+            payload_size: 1024,
+            object_offset: index * 200,
+            checksum: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_sample_blocks() {
+    let sampled = sample_blocks(
+        make_blocks(100),
+        SampleSize::Fraction(0.1),
+        SamplingStrategy::Uniform,
+    );
+    assert_eq!(sampled.len(), 10);
+    assert_eq!(sampled[1].begin_ticks - sampled[0].begin_ticks, 1000);
+
+    // 100 spans per block
+    let sampled = sample_blocks(
+        make_blocks(100),
+        SampleSize::MaxRows(2000),
+        SamplingStrategy::Uniform,
+    );
+    assert_eq!(sampled.len(), 20);
+
+    let sampled = sample_blocks(
+        make_blocks(1000),
+        SampleSize::Fraction(0.5),
+        SamplingStrategy::Random,
+    );
+    assert!(sampled.len() > 400 && sampled.len() < 600);
+
+    let blocks = make_blocks(10);
+    let sampled = sample_blocks(
+        blocks.clone(),
+        SampleSize::MaxRows(10_000),
+        SamplingStrategy::Random,
+    );
+    assert_eq!(sampled.len(), blocks.len());
+}
+
+#[tokio::test]
+async fn test_sample_spans_arguments() {
+    // never connected: only the planning of the calls is tested
+    let db_pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let blob_storage = Arc::new(BlobStorage::new(
+        Arc::new(InMemory::new()),
+        Path::from("lake"),
+    ));
+    let ctx = SessionContext::new();
+    register_sample_spans(&ctx, DataLakeConnection::new(db_pool, blob_storage));
+    let stream_id = uuid::Uuid::new_v4();
+    let begin = "'2024-05-01T00:00:00Z'";
+    let end = "'2024-05-02T00:00:00Z'";
+    for args in [
+        format!("'{stream_id}', {begin}, {end}, 0.1"),
+        format!("'{stream_id}', {begin}, {end}, 1000, 'random'"),
+    ] {
+        let plan = ctx
+            .state()
+            .create_logical_plan(&format!("SELECT name FROM sample_spans({args})"))
+            .await;
+        assert!(plan.is_ok(), "{args}: {plan:?}");
+    }
+    for args in [
+        format!("'not an id', {begin}, {end}, 0.1"),
+        format!("'{stream_id}', 'yesterday', {end}, 0.1"),
+        format!("'{stream_id}', {begin}, {end}, 1.5"),
+        format!("'{stream_id}', {begin}, {end}, 0.1, 'every other'"),
+        format!("'{stream_id}', {begin}, {end}"),
+    ] {
+        let plan = ctx
+            .state()
+            .create_logical_plan(&format!("SELECT name FROM sample_spans({args})"))
+            .await;
+        assert!(plan.is_err(), "{args}");
+    }
+}
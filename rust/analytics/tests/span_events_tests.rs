@@ -0,0 +1,127 @@
+use std::{collections::HashMap, sync::Arc};
+
+use datafusion::arrow::array::{Array, Int64Array};
+use micromegas_analytics::{
+    span_events_table::SpanEventsRecordBuilder, thread_block_processor::parse_thread_block_payload,
+    time::ConvertTicks,
+};
+use micromegas_telemetry_sink::{
+    stream_block::StreamBlock, stream_info::make_stream_info, TelemetryGuard,
+};
+use micromegas_tracing::{
+    dispatch::make_process_info,
+    event::TracingBlock,
+    prelude::Verbosity,
+    spans::{
+        BeginThreadSpanEvent, EndThreadSpanEvent, SpanLocation, SpanMarkerEvent, SpanMetadata,
+        ThreadBlock, ThreadStream,
+    },
+};
+
+static OUTER_SPAN: SpanMetadata = SpanMetadata {
+    name: "outer",
+    location: SpanLocation {
+        lod: Verbosity::Med,
+        target: "target",
+        module_path: "module_path",
+        file: "file",
+        line: 1,
+    },
+};
+
+static INNER_SPAN: SpanMetadata = SpanMetadata {
+    name: "inner",
+    location: SpanLocation {
+        lod: Verbosity::Med,
+        target: "target",
+        module_path: "module_path",
+        file: "file",
+        line: 2,
+    },
+};
+
+static CHECKPOINT: SpanMetadata = SpanMetadata {
+    name: "checkpoint",
+    location: SpanLocation {
+        lod: Verbosity::Med,
+        target: "target",
+        module_path: "module_path",
+        file: "file",
+        line: 3,
+    },
+};
+
+#[test]
+fn test_span_events() {
+    let _telemetry_guard = TelemetryGuard::new();
+
+    let process_id = uuid::Uuid::new_v4();
+    let process_info = make_process_info(process_id, Some(uuid::Uuid::new_v4()));
+    let mut stream = ThreadStream::new(1024, process_id, &[], HashMap::new());
+    let stream_id = stream.stream_id();
+
+    // the outer span began before this block
+    stream.get_events_mut().push(SpanMarkerEvent {
+        marker_desc: &CHECKPOINT,
+        properties: "stage=begin".into(),
+        time: 1,
+    });
+    stream.get_events_mut().push(EndThreadSpanEvent {
+        thread_span_desc: &OUTER_SPAN,
+        time: 2,
+    });
+    stream.get_events_mut().push(BeginThreadSpanEvent {
+        thread_span_desc: &INNER_SPAN,
+        time: 3,
+    });
+    stream.get_events_mut().push(SpanMarkerEvent {
+        marker_desc: &CHECKPOINT,
+        properties: "stage=load".into(),
+        time: 4,
+    });
+    stream.get_events_mut().push(EndThreadSpanEvent {
+        thread_span_desc: &INNER_SPAN,
+        time: 5,
+    });
+    // outside of any span
+    stream.get_events_mut().push(SpanMarkerEvent {
+        marker_desc: &CHECKPOINT,
+        properties: "".into(),
+        time: 6,
+    });
+
+    let mut block =
+        stream.replace_block(Arc::new(ThreadBlock::new(1024, process_id, stream_id, 0)));
+    Arc::get_mut(&mut block).unwrap().close();
+    let encoded = block.encode_bin(&process_info).unwrap();
+    let received_block: micromegas_telemetry::block_wire_format::Block =
+        ciborium::from_reader(&encoded[..]).unwrap();
+    let stream_info = make_stream_info(&stream);
+
+    let mut builder =
+        SpanEventsRecordBuilder::new(0, i64::MAX, 100, ConvertTicks::from_meta_data(0, 0, 1));
+    parse_thread_block_payload(
+        "block",
+        0,
+        &received_block.payload,
+        &stream_info,
+        &mut builder,
+    )
+    .unwrap();
+    let batch = builder.finish().unwrap();
+    assert_eq!(batch.num_rows(), 3);
+    let ids = batch
+        .column_by_name("id")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(ids.values(), &[0, 3, 5]);
+    let span_ids = batch
+        .column_by_name("span_id")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(span_ids.values(), &[1, 2, -1]);
+}
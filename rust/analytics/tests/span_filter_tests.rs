@@ -0,0 +1,68 @@
+use datafusion::arrow::array::{AsArray, DictionaryArray, Int64Array, RecordBatch, StringArray};
+use datafusion::arrow::datatypes::Int16Type;
+use micromegas_analytics::span_filter::{glob_match, SpanFilter};
+use std::sync::Arc;
+
+#[test]
+fn test_glob_match() {
+    assert!(glob_match("render", "render"));
+    assert!(!glob_match("render", "render_frame"));
+    assert!(glob_match("render*", "render_frame"));
+    assert!(glob_match("*frame", "render_frame"));
+    assert!(glob_match("r*d*r_?rame", "render_frame"));
+    assert!(glob_match("*", ""));
+    assert!(!glob_match("?", ""));
+    assert!(glob_match("a*b*c", "aXbYbZc"));
+    assert!(!glob_match("a*b*c", "aXbYbZ"));
+}
+
+#[test]
+fn test_span_filter() {
+    let names: DictionaryArray<Int16Type> =
+        vec!["render_frame", "render_ui", "physics", "render_frame"]
+            .into_iter()
+            .collect();
+    let spans = RecordBatch::try_from_iter(vec![
+        ("name", Arc::new(names) as _),
+        (
+            "target",
+            Arc::new(StringArray::from(vec![
+                "game::render",
+                "game::ui",
+                "game::physics",
+                "engine::render",
+            ])) as _,
+        ),
+        (
+            "duration",
+            Arc::new(Int64Array::from(vec![100, 5, 50, 10])) as _,
+        ),
+    ])
+    .unwrap();
+    assert_eq!(SpanFilter::default().filter(&spans).unwrap(), spans);
+
+    let filter = SpanFilter {
+        name_filter: Some("render_*".into()),
+        target_filter: Some("game::*".into()),
+        min_duration_ns: None,
+    };
+    let filtered = filter.filter(&spans).unwrap();
+    assert_eq!(
+        filtered
+            .column(1)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .collect::<Vec<_>>(),
+        vec!["game::render", "game::ui"]
+    );
+
+    let filter = SpanFilter {
+        name_filter: Some("render_*".into()),
+        target_filter: None,
+        min_duration_ns: Some(10),
+    };
+    let filtered = filter.filter(&spans).unwrap();
+    assert_eq!(filtered.num_rows(), 2);
+    assert_eq!(filtered.schema(), spans.schema());
+}
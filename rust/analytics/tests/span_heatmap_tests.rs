@@ -0,0 +1,88 @@
+use datafusion::arrow::array::{AsArray, RecordBatch, StringArray, TimestampNanosecondArray};
+use datafusion::arrow::datatypes::{Int64Type, TimestampNanosecondType, UInt64Type};
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::dfext::register_extension_functions;
+use micromegas_analytics::dfext::span_heatmap::duration_heatmap;
+use std::sync::Arc;
+
+const MS: i64 = 1_000_000;
+
+#[test]
+fn test_duration_heatmap() {
+    // frames of 16ms and 33ms in the first second, a 200ms hitch in the next one
+    let begins = [0, 16 * MS, 50 * MS, 1000 * MS, -5 * MS];
+    let ends = [16 * MS, 49 * MS, 66 * MS, 1200 * MS, 11 * MS];
+    let cells = duration_heatmap(&begins, &ends, 1000 * MS, 50 * MS, 5).unwrap();
+    let cells: Vec<_> = cells.into_iter().collect();
+    assert_eq!(
+        cells,
+        vec![
+            ((-1000 * MS, 1), 1),
+            ((0, 1), 2),
+            ((0, 3), 1),
+            ((1000 * MS, 4), 1),
+        ]
+    );
+    assert!(duration_heatmap(&begins, &ends, 1000 * MS, 50 * MS, 0).is_err());
+}
+
+#[tokio::test]
+async fn test_span_heatmap_table_function() {
+    let ctx = SessionContext::new();
+    register_extension_functions(&ctx);
+    let timestamps =
+        |values: Vec<i64>| Arc::new(TimestampNanosecondArray::from(values).with_timezone_utc());
+    let spans = RecordBatch::try_from_iter(vec![
+        (
+            "name",
+            Arc::new(StringArray::from(vec!["frame", "frame", "render", "frame"])) as _,
+        ),
+        (
+            "begin",
+            timestamps(vec![0, 16 * MS, 20 * MS, 1000 * MS]) as _,
+        ),
+        (
+            "end",
+            timestamps(vec![16 * MS, 49 * MS, 30 * MS, 1040 * MS]) as _,
+        ),
+    ])
+    .unwrap();
+    ctx.register_batch("spans", spans).unwrap();
+    let results = ctx
+        .sql(
+            "SELECT time_bucket, min_duration, max_duration, count
+             FROM span_heatmap('spans', 'frame', INTERVAL '1 second', INTERVAL '40 milliseconds', 4)
+             ORDER BY time_bucket, min_duration",
+        )
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 1);
+    let batch = &results[0];
+    assert_eq!(
+        batch
+            .column(0)
+            .as_primitive::<TimestampNanosecondType>()
+            .values(),
+        &[0, 0, 1000 * MS]
+    );
+    assert_eq!(
+        batch.column(1).as_primitive::<Int64Type>().values(),
+        &[10 * MS, 30 * MS, 30 * MS]
+    );
+    assert_eq!(
+        batch.column(2).as_primitive::<Int64Type>().values(),
+        &[20 * MS, 40 * MS, 40 * MS]
+    );
+    assert_eq!(
+        batch.column(3).as_primitive::<UInt64Type>().values(),
+        &[1, 1, 1]
+    );
+
+    assert!(ctx
+        .sql("SELECT * FROM span_heatmap('spans', 'frame', INTERVAL '1 second', INTERVAL '40 milliseconds', 0)")
+        .await
+        .is_err());
+}
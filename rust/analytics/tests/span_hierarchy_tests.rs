@@ -0,0 +1,146 @@
+use datafusion::arrow::array::{AsArray, Int64Array, StringArray};
+use datafusion::arrow::datatypes::{Int64Type, UInt32Type};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::execution::context::SessionContext;
+use micromegas_analytics::call_tree::{CallTree, CallTreeNode};
+use micromegas_analytics::dfext::span_hierarchy::{
+    register_span_hierarchy_functions, SpanHierarchy,
+};
+use micromegas_analytics::scope::{ScopeDesc, ScopeHashMap};
+use micromegas_analytics::span_table::SpanRecordBuilder;
+use micromegas_analytics::sql_session::{execute_sql, SqlSessions};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+fn make_node(id: Option<i64>, scope: &ScopeDesc, children: Vec<CallTreeNode>) -> CallTreeNode {
+    CallTreeNode {
+        id,
+        hash: scope.hash,
+        begin: 0,
+        end: 0,
+        children,
+    }
+}
+
+fn make_call_tree() -> CallTree {
+    let make_scope = |name: &str| {
+        ScopeDesc::new(
+            Arc::new(name.to_owned()),
+            Arc::new(String::new()),
+            Arc::new(String::new()),
+            0,
+        )
+    };
+    let thread = make_scope("main");
+    let frame = make_scope("frame");
+    let physics = make_scope("physics");
+    let solve = make_scope("solve");
+    let render = make_scope("render");
+    let root = make_node(
+        None,
+        &thread,
+        vec![make_node(
+            Some(0),
+            &frame,
+            vec![
+                make_node(Some(1), &physics, vec![make_node(Some(2), &solve, vec![])]),
+                make_node(Some(3), &render, vec![]),
+            ],
+        )],
+    );
+    let mut scopes = ScopeHashMap::new();
+    for scope in [thread, frame, physics, solve, render] {
+        scopes.insert(scope.hash, scope);
+    }
+    CallTree {
+        scopes,
+        call_tree_root: Some(root),
+    }
+}
+
+#[test]
+fn test_span_hierarchy() {
+    let mut hierarchy = SpanHierarchy::new();
+    hierarchy.add_call_tree("p1", &make_call_tree());
+    assert_eq!(hierarchy.depth("p1", 2), Some(3));
+    assert_eq!(hierarchy.ancestor("p1", 2, 2), Some(1));
+    assert_eq!(hierarchy.ancestor("p1", 2, 3), Some(2));
+    assert_eq!(hierarchy.ancestor("p1", 2, 4), None);
+    assert_eq!(hierarchy.ancestor("p1", 3, 1), Some(0));
+    assert_eq!(hierarchy.ancestor_name("p1", 2, 2), Some("physics"));
+    assert_eq!(hierarchy.ancestor("p2", 2, 2), None);
+}
+
+#[tokio::test]
+async fn test_span_hierarchy_sql() {
+    let mut hierarchy = SpanHierarchy::new();
+    hierarchy.add_call_tree("p1", &make_call_tree());
+    let ctx = SessionContext::new();
+    register_span_hierarchy_functions(&ctx, Arc::new(RwLock::new(hierarchy)));
+    let spans = RecordBatch::try_from_iter(vec![
+        (
+            "process_id",
+            Arc::new(StringArray::from(vec!["p1", "p1", "p1", "p1"])) as _,
+        ),
+        ("id", Arc::new(Int64Array::from(vec![0, 1, 2, 3])) as _),
+    ])
+    .unwrap();
+    ctx.register_batch("spans", spans).unwrap();
+    let results = ctx
+        .sql(
+            "SELECT id, span_depth(process_id, id) AS depth,
+                    span_ancestor(process_id, id, 2) AS ancestor,
+                    span_ancestor_name(process_id, id, 2) AS system
+             FROM spans
+             ORDER BY id",
+        )
+        .await
+        .unwrap()
+        .collect()
+        .await
+        .unwrap();
+    let batch = &results[0];
+    let depths: Vec<Option<u32>> = batch
+        .column(1)
+        .as_primitive::<UInt32Type>()
+        .iter()
+        .collect();
+    assert_eq!(depths, vec![Some(1), Some(2), Some(3), Some(2)]);
+    let ancestors: Vec<Option<i64>> = batch.column(2).as_primitive::<Int64Type>().iter().collect();
+    assert_eq!(ancestors, vec![None, Some(1), Some(1), Some(3)]);
+    let systems: Vec<Option<&str>> = batch.column(3).as_string::<i32>().iter().collect();
+    assert_eq!(
+        systems,
+        vec![None, Some("physics"), Some("physics"), Some("render")]
+    );
+}
+
+#[tokio::test]
+async fn test_span_hierarchy_in_sql_session() {
+    let sessions = SqlSessions::new(Duration::from_secs(60));
+    let now = Instant::now();
+    let session_id = sessions.create(now).unwrap();
+    let mut spans = SpanRecordBuilder::with_capacity(8);
+    spans.append_call_tree(&make_call_tree()).unwrap();
+    sessions
+        .add_spans(&session_id, "s1", &spans.finish().unwrap(), now)
+        .unwrap();
+    let ctx = sessions.get(&session_id, now).unwrap();
+    let batch = execute_sql(
+        &ctx,
+        "SELECT span_depth('s1', 2) AS depth, span_ancestor_name('s1', 2, 2) AS system",
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(batch.column(0).as_primitive::<UInt32Type>().value(0), 3);
+    assert_eq!(batch.column(1).as_string::<i32>().value(0), "physics");
+
+    // the hierarchy belongs to the session
+    let other_id = sessions.create(now).unwrap();
+    let other = sessions.get(&other_id, now).unwrap();
+    let batch = execute_sql(&other, "SELECT span_depth('s1', 2) AS depth", None)
+        .await
+        .unwrap();
+    assert!(batch.column(0).is_null(0));
+}
@@ -0,0 +1,78 @@
+use bytes::Bytes;
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::Int64Type;
+use datafusion::execution::context::SessionContext;
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use micromegas_analytics::sql_export::export_sql;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_export_sql() {
+    let blob_storage = BlobStorage::new(Arc::new(InMemory::new()), Path::from("lake"));
+    let ctx = SessionContext::new();
+    let manifest = export_sql(
+        &ctx,
+        "SELECT * FROM (VALUES (1), (2), (3), (4), (5)) AS t(x)",
+        &blob_storage,
+        2,
+    )
+    .await
+    .unwrap();
+    assert_eq!(manifest.num_rows(), 3);
+    let nb_rows: Vec<i64> = manifest
+        .column_by_name("nb_rows")
+        .unwrap()
+        .as_primitive::<Int64Type>()
+        .values()
+        .to_vec();
+    assert_eq!(nb_rows, vec![2, 2, 1]);
+    // the in-memory store can't sign urls
+    assert_eq!(manifest.column_by_name("url").unwrap().null_count(), 3);
+    assert_eq!(
+        manifest.schema().metadata().get("schema").unwrap(),
+        "x Int64"
+    );
+
+    let paths = manifest.column_by_name("path").unwrap().as_string::<i32>();
+    let mut values: Vec<i64> = vec![];
+    for path in paths.iter() {
+        let content: Bytes = blob_storage.read_blob(path.unwrap()).await.unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(content)
+            .unwrap()
+            .build()
+            .unwrap();
+        for batch in reader {
+            let batch = batch.unwrap();
+            values.extend(batch.column(0).as_primitive::<Int64Type>().values().iter());
+        }
+    }
+    assert_eq!(values, vec![1, 2, 3, 4, 5]);
+}
+
+#[tokio::test]
+async fn test_export_empty_result() {
+    let blob_storage = BlobStorage::new(Arc::new(InMemory::new()), Path::from("lake"));
+    let ctx = SessionContext::new();
+    let manifest = export_sql(
+        &ctx,
+        "SELECT * FROM (VALUES (1)) AS t(x) WHERE x > 1",
+        &blob_storage,
+        2,
+    )
+    .await
+    .unwrap();
+    assert_eq!(manifest.num_rows(), 0);
+}
+
+#[tokio::test]
+async fn test_export_copy_refused() {
+    let blob_storage = BlobStorage::new(Arc::new(InMemory::new()), Path::from("lake"));
+    let ctx = SessionContext::new();
+    let path = std::env::temp_dir().join(format!("sql_export_{}.csv", uuid::Uuid::new_v4()));
+    let sql = format!("COPY (SELECT 1 AS x) TO '{}' STORED AS CSV", path.display());
+    assert!(export_sql(&ctx, &sql, &blob_storage, 2).await.is_err());
+    assert!(!path.exists());
+}
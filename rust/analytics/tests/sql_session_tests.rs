@@ -0,0 +1,123 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::{Int64Type, TimestampNanosecondType};
+use micromegas_analytics::sql_session::{execute_sql, with_query_range, QueryRange, SqlSessions};
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn test_sql_session() {
+    let sessions = SqlSessions::new(Duration::from_secs(60));
+    let now = Instant::now();
+    let session_id = sessions.create(now).unwrap();
+    let ctx = sessions.get(&session_id, now).unwrap();
+    execute_sql(
+        &ctx,
+        "SELECT * FROM (VALUES (1), (2), (3)) AS t(x)",
+        Some("numbers"),
+    )
+    .await
+    .unwrap();
+    execute_sql(
+        &ctx,
+        "CREATE TEMPORARY VIEW big AS SELECT x FROM numbers WHERE x > 1",
+        None,
+    )
+    .await
+    .unwrap();
+
+    // later requests see the registered result and the view
+    let ctx = sessions.get(&session_id, now).unwrap();
+    let batch = execute_sql(&ctx, "SELECT sum(x) AS s FROM big", None)
+        .await
+        .unwrap();
+    assert_eq!(batch.column(0).as_primitive::<Int64Type>().value(0), 5);
+
+    // sessions don't share their tables
+    let other_id = sessions.create(now).unwrap();
+    let other = sessions.get(&other_id, now).unwrap();
+    assert!(execute_sql(&other, "SELECT * FROM numbers", None)
+        .await
+        .is_err());
+
+    assert!(sessions
+        .get(&session_id, now + Duration::from_secs(61))
+        .is_err());
+    assert!(sessions.close(&other_id));
+    assert!(!sessions.close(&other_id));
+}
+
+#[tokio::test]
+async fn test_query_range_variables() {
+    let sessions = SqlSessions::new(Duration::from_secs(60));
+    let now = Instant::now();
+    let session_id = sessions.create(now).unwrap();
+    let ctx = sessions.get(&session_id, now).unwrap();
+    let sql = "SELECT @query_begin AS b, @query_end AS e";
+
+    // null without a range
+    let batch = execute_sql(&ctx, sql, None).await.unwrap();
+    assert!(batch.column(0).is_null(0));
+    assert!(batch.column(1).is_null(0));
+
+    let range = QueryRange::parse(
+        Some("2024-05-01T10:00:00+02:00"),
+        Some("2024-05-01T09:00:00Z"),
+    )
+    .unwrap();
+    let batch = execute_sql(
+        &with_query_range(&ctx, range),
+        "SELECT @query_begin AS b, @query_end AS e,
+                date_bin(INTERVAL '30 minutes', @query_end - INTERVAL '1 minute', @query_begin) AS bucket",
+        Some("in_range"),
+    )
+    .await
+    .unwrap();
+    let begin = batch.column(0).as_primitive::<TimestampNanosecondType>();
+    let end = batch.column(1).as_primitive::<TimestampNanosecondType>();
+    let bucket = batch.column(2).as_primitive::<TimestampNanosecondType>();
+    assert_eq!(
+        begin.value(0),
+        range.begin.unwrap().timestamp_nanos_opt().unwrap()
+    );
+    assert_eq!(
+        end.value(0),
+        range.end.unwrap().timestamp_nanos_opt().unwrap()
+    );
+    assert_eq!(bucket.value(0), begin.value(0) + 30 * 60 * 1_000_000_000);
+
+    // the result is registered in the session, which keeps no range
+    let batch = execute_sql(&ctx, "SELECT count(*) FROM in_range", None)
+        .await
+        .unwrap();
+    assert_eq!(batch.column(0).as_primitive::<Int64Type>().value(0), 1);
+    let batch = execute_sql(&ctx, sql, None).await.unwrap();
+    assert!(batch.column(0).is_null(0));
+
+    assert!(QueryRange::parse(Some("yesterday"), None).is_err());
+}
+
+#[tokio::test]
+async fn test_server_files_refused() {
+    let sessions = SqlSessions::new(Duration::from_secs(60));
+    let now = Instant::now();
+    let session_id = sessions.create(now).unwrap();
+    let ctx = sessions.get(&session_id, now).unwrap();
+    let directory = std::env::temp_dir().join(format!("sql_session_{}", uuid::Uuid::new_v4()));
+    let statements = [
+        format!(
+            "COPY (SELECT 1 AS x) TO '{}' STORED AS CSV",
+            directory.join("out.csv").display()
+        ),
+        "CREATE EXTERNAL TABLE passwd (line VARCHAR) STORED AS CSV LOCATION '/etc/passwd'"
+            .to_owned(),
+        "CREATE TABLE t AS SELECT 1 AS x".to_owned(),
+        "SET datafusion.execution.batch_size = 1".to_owned(),
+        format!(
+            "SELECT * FROM downsample('COPY (SELECT 1 AS x) TO ''{}'' STORED AS CSV', 10)",
+            directory.join("nested.csv").display()
+        ),
+    ];
+    for sql in &statements {
+        assert!(execute_sql(&ctx, sql, None).await.is_err(), "{sql}");
+    }
+    assert!(!directory.exists());
+}
@@ -0,0 +1,61 @@
+use datafusion::arrow::array::AsArray;
+use datafusion::arrow::datatypes::Int64Type;
+use micromegas_analytics::log_entries_table::LogEntriesRecordBuilder;
+use micromegas_analytics::log_entry::LogEntry;
+use micromegas_analytics::measure::Measure;
+use micromegas_analytics::metrics_table::MetricsRecordBuilder;
+use micromegas_analytics::view_config::{ViewRegistry, ViewsConfig};
+use std::sync::Arc;
+
+#[test]
+fn test_log_entries_ticks_column() {
+    let entry = LogEntry {
+        time: 1_000,
+        ticks: 42,
+        level: 4,
+        target: Arc::new("game".to_owned()),
+        msg: Arc::new("hello".to_owned()),
+    };
+    let mut builder = LogEntriesRecordBuilder::with_capacity(1);
+    builder.append(&entry).unwrap();
+    assert!(builder.finish().unwrap().column_by_name("ticks").is_none());
+
+    let mut builder = LogEntriesRecordBuilder::with_capacity(1).with_ticks();
+    builder.append(&entry).unwrap();
+    let batch = builder.finish().unwrap();
+    let ticks = batch.column_by_name("ticks").unwrap();
+    assert_eq!(ticks.as_primitive::<Int64Type>().value(0), 42);
+}
+
+#[test]
+fn test_measures_ticks_column() {
+    let mut builder = MetricsRecordBuilder::with_capacity(1).with_ticks();
+    builder
+        .append(&Measure {
+            time: 1_000,
+            ticks: 7,
+            target: Arc::new("game".to_owned()),
+            name: Arc::new("frame_time".to_owned()),
+            unit: Arc::new("ms".to_owned()),
+            value: 16.0,
+        })
+        .unwrap();
+    let batch = builder.finish().unwrap();
+    assert_eq!(
+        batch.schema().field(batch.num_columns() - 1).name(),
+        "ticks"
+    );
+    let ticks = batch.column_by_name("ticks").unwrap();
+    assert_eq!(ticks.as_primitive::<Int64Type>().value(0), 7);
+}
+
+#[test]
+fn test_tick_columns_config() {
+    assert!(!ViewRegistry::default().tick_columns());
+    let config: ViewsConfig = serde_json::from_str(r#"{"tick_columns": true}"#).unwrap();
+    assert!(ViewRegistry::from_config(&config).unwrap().tick_columns());
+    // a promoted property can't shadow the ticks column
+    let config: ViewsConfig =
+        serde_json::from_str(r#"{"promoted_properties": ["ticks"]}"#).unwrap();
+    assert!(ViewRegistry::from_config(&config).is_err());
+}
@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use datafusion::arrow::array::{AsArray, Int64Array};
+use datafusion::arrow::datatypes::{DataType, Field, Int64Type, Schema};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_analytics::time_ranges::{
+    make_time_ranges, parse_time_ranges, query_time_ranges, TimeRange, TimeRangeArg,
+};
+use sqlx::types::chrono::{TimeZone, Utc};
+
+fn range(begin: i64, end: i64) -> TimeRange {
+    TimeRange {
+        begin: Utc.timestamp_opt(begin, 0).unwrap(),
+        end: Utc.timestamp_opt(end, 0).unwrap(),
+    }
+}
+
+#[test]
+fn test_make_time_ranges() {
+    let bounds = range(10, 100);
+    let ranges = make_time_ranges(
+        bounds,
+        &[
+            range(50, 60),
+            range(0, 20),
+            range(55, 70),
+            range(90, 200),
+            range(30, 30),
+            range(150, 160),
+        ],
+    );
+    assert_eq!(ranges, vec![range(10, 20), range(50, 70), range(90, 100)]);
+}
+
+#[test]
+fn test_parse_time_ranges() {
+    let (bounds, ranges) =
+        parse_time_ranges("2024-01-01T00:00:00Z", "2024-01-02T00:00:00Z", &[]).unwrap();
+    assert_eq!(ranges, vec![bounds]);
+
+    let (_bounds, ranges) = parse_time_ranges(
+        "2024-01-01T00:00:00Z",
+        "2024-01-02T00:00:00Z",
+        &[TimeRangeArg {
+            begin: "2023-12-31T23:00:00Z".to_owned(),
+            end: "2024-01-01T01:00:00+00:00".to_owned(),
+        }],
+    )
+    .unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].begin.to_rfc3339(), "2024-01-01T00:00:00+00:00");
+    assert_eq!(ranges[0].end.to_rfc3339(), "2024-01-01T01:00:00+00:00");
+
+    assert!(parse_time_ranges(
+        "2024-01-01T00:00:00Z",
+        "2024-01-02T00:00:00Z",
+        &[TimeRangeArg {
+            begin: "yesterday".to_owned(),
+            end: "today".to_owned(),
+        }],
+    )
+    .is_err());
+}
+
+fn seconds_batch(range: TimeRange, limit: i64) -> RecordBatch {
+    let schema = Arc::new(Schema::new(vec![Field::new(
+        "time",
+        DataType::Int64,
+        false,
+    )]));
+    let seconds: Vec<i64> = (range.begin.timestamp()..range.end.timestamp())
+        .take(usize::try_from(limit).unwrap())
+        .collect();
+    RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from(seconds))]).unwrap()
+}
+
+#[tokio::test]
+async fn test_query_time_ranges() {
+    let bounds = range(0, 100);
+    let ranges = [range(10, 13), range(50, 55)];
+    let batch = query_time_ranges(bounds, &ranges, 6, |range, limit| async move {
+        Ok(seconds_batch(range, limit))
+    })
+    .await
+    .unwrap();
+    let seconds: Vec<i64> = batch
+        .column(0)
+        .as_primitive::<Int64Type>()
+        .values()
+        .to_vec();
+    // the limit applies to the union of the ranges
+    assert_eq!(seconds, vec![10, 11, 12, 50, 51, 52]);
+
+    let empty = query_time_ranges(bounds, &[], 6, |range, limit| async move {
+        Ok(seconds_batch(range, limit))
+    })
+    .await
+    .unwrap();
+    assert_eq!(empty.num_rows(), 0);
+    assert_eq!(empty.schema().field(0).name(), "time");
+}
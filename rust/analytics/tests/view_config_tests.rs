@@ -0,0 +1,34 @@
+use micromegas_analytics::view_config::{ViewRegistry, ViewsConfig};
+
+#[test]
+fn test_views_config() {
+    let config: ViewsConfig = serde_json::from_str(
+        r#"{
+            "disabled_builtin_views": ["blocks"],
+            "views": [
+                {
+                    "name": "recent_processes",
+                    "sql": "SELECT * FROM processes WHERE exe LIKE '%srv%'",
+                    "time_column": "start_time"
+                }
+            ]
+        }"#,
+    )
+    .unwrap();
+    let registry = ViewRegistry::from_config(&config).unwrap();
+    assert!(registry.find_view("blocks").is_err());
+    assert!(registry.find_view("processes").is_ok());
+    assert!(registry.find_view("data_loss").is_ok());
+    assert_eq!(
+        registry.find_view("recent_processes").unwrap().time_column,
+        "start_time"
+    );
+
+    let mut invalid = config.clone();
+    invalid.views[0].time_column = "start_time; DROP TABLE blocks".to_owned();
+    assert!(ViewRegistry::from_config(&invalid).is_err());
+
+    let mut duplicate = config;
+    duplicate.views[0].name = "processes".to_owned();
+    assert!(ViewRegistry::from_config(&duplicate).is_err());
+}
@@ -0,0 +1,78 @@
+use micromegas_analytics::log_entries_table::{log_entries_schema, LogEntriesRecordBuilder};
+use micromegas_analytics::log_entry::LogEntry;
+use micromegas_analytics::view_docs::find_view_doc;
+use micromegas_analytics::view_versions::{latest_view_version, resolve_view_version};
+use std::sync::Arc;
+
+fn column_names(schema: &datafusion::arrow::datatypes::Schema) -> Vec<String> {
+    schema
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect()
+}
+
+#[test]
+fn test_resolve_view_version() {
+    let latest = latest_view_version("log_entries").unwrap();
+    assert_eq!(latest.name, "log_entries_v2");
+    assert!(latest.deprecated.is_none());
+    assert_eq!(
+        resolve_view_version("log_entries", "log_entries").unwrap(),
+        Some(latest)
+    );
+    let v1 = resolve_view_version("log_entries", "log_entries_v1")
+        .unwrap()
+        .unwrap();
+    assert_eq!(v1.version, 1);
+    assert!(v1.deprecated.is_some());
+    assert!(resolve_view_version("log_entries", "log_entries_v3").is_err());
+    assert!(resolve_view_version("measures", "log_entries_v1").is_err());
+    assert_eq!(resolve_view_version("measures", "measures").unwrap(), None);
+}
+
+#[test]
+fn test_translate_to_old_version() {
+    let mut builder = LogEntriesRecordBuilder::with_capacity(1)
+        .with_categories()
+        .with_ticks();
+    builder
+        .append(&LogEntry {
+            time: 1_000,
+            ticks: 42,
+            level: 4,
+            target: Arc::new("game".to_owned()),
+            msg: Arc::new("[LogNet] connected".to_owned()),
+        })
+        .unwrap();
+    let batch = builder.finish().unwrap();
+    let v1 = resolve_view_version("log_entries", "log_entries_v1")
+        .unwrap()
+        .unwrap();
+    let translated = v1.translate(&batch).unwrap();
+    assert_eq!(
+        column_names(&translated.schema()),
+        ["time", "target", "level", "msg"]
+    );
+    assert_eq!(translated.num_rows(), 1);
+    assert_eq!(
+        column_names(&v1.schema(&log_entries_schema(true, true))),
+        column_names(&translated.schema())
+    );
+    let v2 = latest_view_version("log_entries").unwrap();
+    assert_eq!(v2.translate(&batch).unwrap(), batch);
+}
+
+#[test]
+fn test_versioned_view_doc() {
+    let doc = find_view_doc("log_entries_v1").unwrap();
+    assert_eq!(doc.name, "log_entries_v1");
+    assert!(doc.deprecated.is_some());
+    assert_eq!(
+        column_names(&doc.schema),
+        ["time", "target", "level", "msg"]
+    );
+    let doc = find_view_doc("log_entries_v2").unwrap();
+    assert!(doc.deprecated.is_none());
+    assert_eq!(doc.schema, find_view_doc("log_entries").unwrap().schema);
+}
@@ -0,0 +1,49 @@
+use micromegas_analytics::parse_block;
+use micromegas_telemetry::block_wire_format::BlockPayload;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_telemetry::wire_format::{
+    stream_wire_format_version, WIRE_FORMAT_VERSION, WIRE_FORMAT_VERSION_PROPERTY,
+};
+use std::collections::HashMap;
+
+fn properties(version: Option<&str>) -> HashMap<String, String> {
+    version
+        .map(|version| {
+            HashMap::from([(WIRE_FORMAT_VERSION_PROPERTY.to_owned(), version.to_owned())])
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_stream_wire_format_version() {
+    // streams of older clients don't declare their version
+    assert_eq!(stream_wire_format_version(&properties(None)).unwrap(), 1);
+    assert_eq!(
+        stream_wire_format_version(&properties(Some(&WIRE_FORMAT_VERSION.to_string()))).unwrap(),
+        WIRE_FORMAT_VERSION
+    );
+    assert!(stream_wire_format_version(&properties(Some("0"))).is_err());
+    assert!(stream_wire_format_version(&properties(Some("nope"))).is_err());
+    let newer = (WIRE_FORMAT_VERSION + 1).to_string();
+    let error = stream_wire_format_version(&properties(Some(&newer))).unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("unsupported wire format version"));
+}
+
+#[test]
+fn test_parse_block_of_newer_client() {
+    let stream = StreamInfo {
+        process_id: uuid::Uuid::new_v4(),
+        stream_id: uuid::Uuid::new_v4(),
+        dependencies_metadata: vec![],
+        objects_metadata: vec![],
+        tags: vec![],
+        properties: properties(Some(&(WIRE_FORMAT_VERSION + 1).to_string())),
+    };
+    let payload = BlockPayload {
+        dependencies: vec![],
+        objects: vec![],
+    };
+    assert!(parse_block(&stream, &payload, |_value| Ok(true)).is_err());
+}
@@ -0,0 +1,37 @@
+// cbor bodies of the insert_block requests, decoded like the ingestion service does
+// and then parsed like the analytics service does
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use micromegas_fuzz::{parse_payload, stream_info, StreamKind};
+use micromegas_telemetry::block_wire_format::{checksum_to_i64, verify_payload_checksum, Block};
+use micromegas_telemetry::compression::decompress_with_codec;
+use micromegas_telemetry::wire_format::check_wire_format_version;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(block) = ciborium::from_reader::<Block, _>(data) else {
+        return;
+    };
+    let _ = check_wire_format_version(block.wire_format_version);
+    let _ = verify_payload_checksum(
+        &block.block_id,
+        &block.payload,
+        block.checksum.map(checksum_to_i64),
+    );
+    for codec in ["lz4", "zstd"] {
+        let (Ok(dependencies), Ok(objects)) = (
+            decompress_with_codec(Some(codec), &block.payload.dependencies),
+            decompress_with_codec(Some(codec), &block.payload.objects),
+        ) else {
+            continue;
+        };
+        for kind in [StreamKind::Log, StreamKind::Metrics, StreamKind::Thread] {
+            let stream = stream_info(kind);
+            parse_payload(
+                &stream.dependencies_metadata,
+                &stream.objects_metadata,
+                &dependencies,
+                &objects,
+            );
+        }
+    }
+});
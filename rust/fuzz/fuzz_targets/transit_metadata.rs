@@ -0,0 +1,26 @@
+// payloads of blocks sent with altered metadata: members out of their object, wrong sizes,
+// types containing themselves
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use micromegas_fuzz::{edit_metadata, parse_payload, stream_info, MetadataEdit, StreamKind};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    kind: StreamKind,
+    dependencies_edits: Vec<MetadataEdit>,
+    objects_edits: Vec<MetadataEdit>,
+    dependencies: &'a [u8],
+    objects: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let mut stream = stream_info(input.kind);
+    edit_metadata(&mut stream.dependencies_metadata, &input.dependencies_edits);
+    edit_metadata(&mut stream.objects_metadata, &input.objects_edits);
+    parse_payload(
+        &stream.dependencies_metadata,
+        &stream.objects_metadata,
+        input.dependencies,
+        input.objects,
+    );
+});
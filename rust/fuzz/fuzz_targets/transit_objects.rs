@@ -0,0 +1,21 @@
+// payloads of blocks sent with the metadata of the telemetry sink
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use micromegas_fuzz::{parse_payload, stream_info, StreamKind};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    kind: StreamKind,
+    dependencies: &'a [u8],
+    objects: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let stream = stream_info(input.kind);
+    parse_payload(
+        &stream.dependencies_metadata,
+        &stream.objects_metadata,
+        input.dependencies,
+        input.objects,
+    );
+});
@@ -0,0 +1,108 @@
+//! Inputs shared by the fuzz targets
+//!
+//! Blocks come from the instrumented processes, the ingestion and analytics services must
+//! reject a malformed or hostile block with an error. Run a target with
+//! `cargo +nightly fuzz run <target>` from this directory.
+use arbitrary::Arbitrary;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_telemetry_sink::stream_info::make_stream_info;
+use micromegas_tracing::event::EventStream;
+use micromegas_tracing::logs::LogBlock;
+use micromegas_tracing::metrics::MetricsBlock;
+use micromegas_tracing::spans::ThreadBlock;
+use micromegas_transit::{parse_object_buffer, read_dependencies, UserDefinedType};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Arbitrary)]
+pub enum StreamKind {
+    Log,
+    Metrics,
+    Thread,
+}
+
+/// Metadata of the streams sent by the telemetry sink
+pub fn stream_info(kind: StreamKind) -> StreamInfo {
+    let process_id = uuid::Uuid::new_v4();
+    match kind {
+        StreamKind::Log => make_stream_info(&EventStream::<LogBlock>::new(
+            1024,
+            process_id,
+            &[],
+            HashMap::new(),
+        )),
+        StreamKind::Metrics => make_stream_info(&EventStream::<MetricsBlock>::new(
+            1024,
+            process_id,
+            &[],
+            HashMap::new(),
+        )),
+        StreamKind::Thread => make_stream_info(&EventStream::<ThreadBlock>::new(
+            1024,
+            process_id,
+            &[],
+            HashMap::new(),
+        )),
+    }
+}
+
+const INTRINSIC_TYPE_NAMES: &[&str] = &["u8", "u32", "u64", "i64", "f64", "usize"];
+
+/// Change to the metadata of a stream, as a client could declare it
+#[derive(Debug, Arbitrary)]
+pub struct MetadataEdit {
+    udt_index: u8,
+    member_index: u8,
+    offset: Option<u16>,
+    size: Option<u8>,
+    type_index: Option<u8>,
+    is_reference: Option<bool>,
+    udt_size: Option<u8>,
+}
+
+/// Applies the edits to the members of the types, member types can be swapped for any
+/// intrinsic or declared type, including the type of the member itself
+pub fn edit_metadata(udts: &mut [UserDefinedType], edits: &[MetadataEdit]) {
+    if udts.is_empty() {
+        return;
+    }
+    let mut type_names: Vec<String> = INTRINSIC_TYPE_NAMES
+        .iter()
+        .map(|name| (*name).to_owned())
+        .collect();
+    type_names.extend(udts.iter().map(|udt| udt.name.clone()));
+    for edit in edits {
+        let nb_udts = udts.len();
+        let udt = &mut udts[edit.udt_index as usize % nb_udts];
+        if let Some(size) = edit.udt_size {
+            udt.size = size as usize;
+        }
+        if udt.members.is_empty() {
+            continue;
+        }
+        let nb_members = udt.members.len();
+        let member = &mut udt.members[edit.member_index as usize % nb_members];
+        if let Some(offset) = edit.offset {
+            member.offset = offset as usize;
+        }
+        if let Some(size) = edit.size {
+            member.size = size as usize;
+        }
+        if let Some(type_index) = edit.type_index {
+            member.type_name = type_names[type_index as usize % type_names.len()].clone();
+        }
+        if let Some(is_reference) = edit.is_reference {
+            member.is_reference = is_reference;
+        }
+    }
+}
+
+/// Parses the decompressed payload of a block the way the analytics service does
+pub fn parse_payload(
+    dependencies_metadata: &[UserDefinedType],
+    objects_metadata: &[UserDefinedType],
+    dependencies: &[u8],
+    objects: &[u8],
+) {
+    let dependencies = read_dependencies(dependencies_metadata, dependencies).unwrap_or_default();
+    let _ = parse_object_buffer(&dependencies, objects_metadata, objects, |_| Ok(true));
+}
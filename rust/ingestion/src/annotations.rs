@@ -0,0 +1,163 @@
+//! Annotations: labeled time ranges (deploys, incidents, ...) stored next to the telemetry
+use crate::sql_instrumentation::instrument_query;
+use crate::sql_property::{into_hashmap, make_properties, Property};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sqlx::Row;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub annotation_id: uuid::Uuid,
+    pub label: String,
+    pub begin_time: DateTime<Utc>,
+    /// equal to begin_time for instant markers like deploys
+    pub end_time: DateTime<Utc>,
+    pub properties: HashMap<String, String>,
+}
+
+impl Annotation {
+    pub fn new(
+        label: String,
+        begin_time: DateTime<Utc>,
+        end_time: Option<DateTime<Utc>>,
+        properties: HashMap<String, String>,
+    ) -> Result<Self> {
+        let annotation = Self {
+            annotation_id: uuid::Uuid::new_v4(),
+            label,
+            begin_time,
+            end_time: end_time.unwrap_or(begin_time),
+            properties,
+        };
+        annotation.validate()?;
+        Ok(annotation)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.label.is_empty() {
+            anyhow::bail!("annotation label can't be empty");
+        }
+        if self.end_time < self.begin_time {
+            anyhow::bail!("annotation ends before it begins");
+        }
+        Ok(())
+    }
+}
+
+fn annotation_from_row(row: &sqlx::postgres::PgRow) -> Result<Annotation> {
+    let properties: Vec<Property> = row.try_get("properties")?;
+    Ok(Annotation {
+        annotation_id: row.try_get("annotation_id")?,
+        label: row.try_get("label")?,
+        begin_time: row.try_get("begin_time")?,
+        end_time: row.try_get("end_time")?,
+        properties: into_hashmap(properties),
+    })
+}
+
+pub async fn insert_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation: &Annotation,
+) -> Result<()> {
+    annotation.validate()?;
+    let sql = "INSERT INTO annotations VALUES($1,$2,$3,$4,$5,$6);";
+    instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(annotation.annotation_id)
+            .bind(&annotation.label)
+            .bind(annotation.begin_time)
+            .bind(annotation.end_time)
+            .bind(make_properties(&annotation.properties))
+            .bind(Utc::now())
+            .execute(connection),
+    )
+    .await
+    .with_context(|| "inserting into annotations")?;
+    Ok(())
+}
+
+/// Fails if the annotation does not exist
+pub async fn update_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation: &Annotation,
+) -> Result<()> {
+    annotation.validate()?;
+    let sql = "UPDATE annotations
+         SET label = $2, begin_time = $3, end_time = $4, properties = $5
+         WHERE annotation_id = $1;";
+    let result = instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(annotation.annotation_id)
+            .bind(&annotation.label)
+            .bind(annotation.begin_time)
+            .bind(annotation.end_time)
+            .bind(make_properties(&annotation.properties))
+            .execute(connection),
+    )
+    .await
+    .with_context(|| "updating annotations")?;
+    if result.rows_affected() == 0 {
+        anyhow::bail!("annotation {} not found", annotation.annotation_id);
+    }
+    Ok(())
+}
+
+/// Returns false if the annotation did not exist
+pub async fn delete_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation_id: uuid::Uuid,
+) -> Result<bool> {
+    let sql = "DELETE FROM annotations WHERE annotation_id = $1;";
+    let result = instrument_query(
+        sql,
+        sqlx::query(sql).bind(annotation_id).execute(connection),
+    )
+    .await
+    .with_context(|| "deleting from annotations")?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn find_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation_id: uuid::Uuid,
+) -> Result<Annotation> {
+    let sql = "SELECT annotation_id, label, begin_time, end_time, properties
+         FROM annotations
+         WHERE annotation_id = $1;";
+    let row = instrument_query(
+        sql,
+        sqlx::query(sql).bind(annotation_id).fetch_one(connection),
+    )
+    .await
+    .with_context(|| format!("annotation {annotation_id} not found"))?;
+    annotation_from_row(&row)
+}
+
+/// Annotations overlapping [begin, end), ordered by begin_time
+pub async fn list_annotations(
+    connection: &mut sqlx::PgConnection,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<Annotation>> {
+    let sql = "SELECT annotation_id, label, begin_time, end_time, properties
+         FROM annotations
+         WHERE end_time >= $1
+         AND begin_time < $2
+         ORDER BY begin_time
+         LIMIT $3;";
+    let rows = instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(begin)
+            .bind(end)
+            .bind(limit)
+            .fetch_all(connection),
+    )
+    .await
+    .with_context(|| "listing annotations")?;
+    rows.iter().map(annotation_from_row).collect()
+}
@@ -0,0 +1,117 @@
+//! Attachments: named binary artifacts (screenshots, savegames, dumps) sent by a process
+//!
+//! The content is stored in the object store next to the blocks, the metadata in postgresql.
+use crate::data_lake_connection::DataLakeConnection;
+use crate::sql_instrumentation::instrument_query;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use micromegas_telemetry::attachment::attachment_path;
+use sqlx::Row;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub attachment_id: uuid::Uuid,
+    pub process_id: uuid::Uuid,
+    pub name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub insert_time: DateTime<Utc>,
+}
+
+fn attachment_from_row(row: &sqlx::postgres::PgRow) -> Result<Attachment> {
+    Ok(Attachment {
+        attachment_id: row.try_get("attachment_id")?,
+        process_id: row.try_get("process_id")?,
+        name: row.try_get("name")?,
+        content_type: row.try_get("content_type")?,
+        size: row.try_get("size")?,
+        insert_time: row.try_get("insert_time")?,
+    })
+}
+
+/// Writes the content before recording it, a failed insert leaves an unreferenced object
+pub async fn insert_attachment(
+    lake: &DataLakeConnection,
+    process_id: uuid::Uuid,
+    name: &str,
+    content_type: &str,
+    content: bytes::Bytes,
+) -> Result<Attachment> {
+    if name.is_empty() {
+        anyhow::bail!("attachment name can't be empty");
+    }
+    let attachment = Attachment {
+        attachment_id: uuid::Uuid::new_v4(),
+        process_id,
+        name: name.to_owned(),
+        content_type: content_type.to_owned(),
+        size: content.len() as i64,
+        insert_time: Utc::now(),
+    };
+    lake.blob_storage
+        .put(
+            &attachment_path(&process_id, &attachment.attachment_id),
+            content,
+        )
+        .await
+        .with_context(|| "writing attachment to blob storage")?;
+    let sql = "INSERT INTO attachments VALUES($1,$2,$3,$4,$5,$6);";
+    instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(attachment.attachment_id)
+            .bind(attachment.process_id)
+            .bind(&attachment.name)
+            .bind(&attachment.content_type)
+            .bind(attachment.size)
+            .bind(attachment.insert_time)
+            .execute(&lake.db_pool),
+    )
+    .await
+    .with_context(|| "inserting into attachments")?;
+    Ok(attachment)
+}
+
+pub async fn find_attachment(
+    connection: &mut sqlx::PgConnection,
+    attachment_id: uuid::Uuid,
+) -> Result<Attachment> {
+    let sql = "SELECT attachment_id, process_id, name, content_type, size, insert_time
+         FROM attachments
+         WHERE attachment_id = $1;";
+    let row = instrument_query(
+        sql,
+        sqlx::query(sql).bind(attachment_id).fetch_one(connection),
+    )
+    .await
+    .with_context(|| format!("attachment {attachment_id} not found"))?;
+    attachment_from_row(&row)
+}
+
+/// Attachments of the process, in the order they were received
+pub async fn list_attachments(
+    connection: &mut sqlx::PgConnection,
+    process_id: uuid::Uuid,
+) -> Result<Vec<Attachment>> {
+    let sql = "SELECT attachment_id, process_id, name, content_type, size, insert_time
+         FROM attachments
+         WHERE process_id = $1
+         ORDER BY insert_time;";
+    let rows = instrument_query(sql, sqlx::query(sql).bind(process_id).fetch_all(connection))
+        .await
+        .with_context(|| "listing attachments")?;
+    rows.iter().map(attachment_from_row).collect()
+}
+
+pub async fn read_attachment(
+    lake: &DataLakeConnection,
+    attachment: &Attachment,
+) -> Result<bytes::Bytes> {
+    lake.blob_storage
+        .read_blob(&attachment_path(
+            &attachment.process_id,
+            &attachment.attachment_id,
+        ))
+        .await
+        .with_context(|| "reading attachment from blob storage")
+}
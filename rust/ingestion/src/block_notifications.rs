@@ -0,0 +1,46 @@
+//! Notifications of newly ingested blocks, published through postgresql's NOTIFY
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+pub const NEW_BLOCKS_CHANNEL: &str = "micromegas_new_blocks";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewBlockNotification {
+    pub block_id: uuid::Uuid,
+    pub stream_id: uuid::Uuid,
+    pub process_id: uuid::Uuid,
+    /// RFC 3339
+    pub begin_time: String,
+    /// RFC 3339
+    pub end_time: String,
+    pub nb_objects: i32,
+    pub payload_size: i64,
+    /// xxh3 of the payload, see `block_wire_format::payload_checksum`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<i64>,
+    /// path of the payload in the object store, filled on demand by subscribers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_path: Option<String>,
+}
+
+/// Queues the notifications, which are delivered to listeners when the transaction commits
+pub async fn notify_new_blocks(
+    tr: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    notifications: &[NewBlockNotification],
+) -> Result<()> {
+    if notifications.is_empty() {
+        return Ok(());
+    }
+    let payloads = notifications
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<Result<Vec<String>, _>>()
+        .with_context(|| "encoding NewBlockNotification")?;
+    sqlx::query("SELECT pg_notify($1, payload) FROM unnest($2::text[]) AS payload;")
+        .bind(NEW_BLOCKS_CHANNEL)
+        .bind(payloads)
+        .execute(&mut **tr)
+        .await
+        .with_context(|| "pg_notify")?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! Spool of block metadata, written to local disk when postgresql can't record it
+//!
+//! The payloads are already in the object store when the metadata is spooled, so short outages
+//! of the database don't lose telemetry: the spooled metadata is replayed into the blocks table
+//! when the database recovers.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use micromegas_tracing::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Row of the blocks table, with the times as sent by the client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockMetadata {
+    pub block_id: uuid::Uuid,
+    pub stream_id: uuid::Uuid,
+    pub process_id: uuid::Uuid,
+    /// RFC 3339
+    pub begin_time: String,
+    pub begin_ticks: i64,
+    /// RFC 3339
+    pub end_time: String,
+    pub end_ticks: i64,
+    pub nb_objects: i32,
+    pub object_offset: i64,
+    pub payload_size: i64,
+    pub insert_time: DateTime<Utc>,
+    pub clock_skew_ms: i64,
+    pub checksum: Option<u64>,
+}
+
+/// Directory of spooled block metadata, one cbor file per block named after its block_id
+#[derive(Debug)]
+pub struct BlockSpool {
+    directory: PathBuf,
+}
+
+const SPOOL_EXTENSION: &str = "cbor";
+/// Spooled files that could not be read, kept aside for inspection
+const CORRUPT_EXTENSION: &str = "corrupt";
+
+impl BlockSpool {
+    pub fn new(directory: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("creating spool directory {}", directory.display()))?;
+        Ok(Self { directory })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn block_path(&self, block_id: &uuid::Uuid) -> PathBuf {
+        self.directory.join(format!("{block_id}.{SPOOL_EXTENSION}"))
+    }
+
+    /// Writes the metadata of the blocks, a block spooled twice is replayed once
+    pub async fn spool(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        for block in blocks {
+            let mut buffer = vec![];
+            ciborium::into_writer(block, &mut buffer).with_context(|| "encoding BlockMetadata")?;
+            // renamed once complete, replays never read a partial file
+            let path = self.block_path(&block.block_id);
+            let tmp_path = path.with_extension("tmp");
+            tokio::fs::write(&tmp_path, buffer)
+                .await
+                .with_context(|| format!("writing {}", tmp_path.display()))?;
+            tokio::fs::rename(&tmp_path, &path)
+                .await
+                .with_context(|| format!("renaming {}", tmp_path.display()))?;
+        }
+        Ok(())
+    }
+
+    async fn read_block(path: &Path) -> Result<BlockMetadata> {
+        let buffer = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("reading {}", path.display()))?;
+        ciborium::from_reader(&buffer[..]).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Spooled blocks waiting to be recorded, in no particular order
+    ///
+    /// Files that can't be read are set aside with the `corrupt` extension, the other blocks
+    /// are still replayed.
+    pub async fn pending(&self) -> Result<Vec<BlockMetadata>> {
+        let mut blocks = vec![];
+        let mut entries = tokio::fs::read_dir(&self.directory)
+            .await
+            .with_context(|| format!("listing {}", self.directory.display()))?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .with_context(|| format!("listing {}", self.directory.display()))?
+        {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SPOOL_EXTENSION) {
+                continue;
+            }
+            match Self::read_block(&path).await {
+                Ok(block) => blocks.push(block),
+                Err(e) => {
+                    warn!("skipping spooled block: {e:?}");
+                    let corrupt_path = path.with_extension(CORRUPT_EXTENSION);
+                    if let Err(e) = tokio::fs::rename(&path, &corrupt_path).await {
+                        warn!("error renaming {}: {e:?}", path.display());
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Forgets blocks once they are recorded in the database
+    pub async fn remove(&self, block_ids: &[uuid::Uuid]) -> Result<()> {
+        for block_id in block_ids {
+            let path = self.block_path(block_id);
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(e).with_context(|| format!("removing {}", path.display()));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
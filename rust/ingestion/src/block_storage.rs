@@ -0,0 +1,175 @@
+//! Where the ingestion service stores the blocks, the data lake by default
+//!
+//! Servers embedding the ingestion service can store the blocks elsewhere, or wrap
+//! `DataLakeBlockStorage` to copy them to other systems.
+use crate::block_notifications::{notify_new_blocks, NewBlockNotification};
+use crate::block_spool::BlockMetadata;
+use crate::data_lake_connection::DataLakeConnection;
+use crate::sql_instrumentation::instrument_query;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, FixedOffset, Utc};
+use micromegas_telemetry::block_wire_format::{self, checksum_to_i64};
+use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_tracing::prelude::*;
+use sqlx::Row;
+use std::collections::HashSet;
+
+#[async_trait]
+pub trait BlockStorage: Send + Sync {
+    /// Writes the payload of the block, returns its size in bytes
+    async fn write_payload(&self, block: &block_wire_format::Block) -> Result<i64>;
+
+    /// Records the metadata of blocks whose payload was written, ignoring the blocks already recorded
+    async fn record_blocks(&self, blocks: &[BlockMetadata]) -> Result<()>;
+}
+
+/// Payloads in the object store, metadata in the blocks table
+#[derive(Debug, Clone)]
+pub struct DataLakeBlockStorage {
+    lake: DataLakeConnection,
+}
+
+impl DataLakeBlockStorage {
+    pub fn new(lake: DataLakeConnection) -> Self {
+        Self { lake }
+    }
+}
+
+#[async_trait]
+impl BlockStorage for DataLakeBlockStorage {
+    async fn write_payload(&self, block: &block_wire_format::Block) -> Result<i64> {
+        let encoded_payload = encode_cbor(&block.payload)?;
+        let payload_size = encoded_payload.len();
+        let process_id = &block.process_id;
+        let stream_id = &block.stream_id;
+        let block_id = &block.block_id;
+        let obj_path = format!("blobs/{process_id}/{stream_id}/{block_id}");
+        debug!("writing {obj_path}");
+        self.lake
+            .blob_storage
+            .put(&obj_path, encoded_payload.into())
+            .await
+            .with_context(|| "Error writing block to blob storage")?;
+        Ok(payload_size as i64)
+    }
+
+    async fn record_blocks(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        let mut block_ids = Vec::with_capacity(blocks.len());
+        let mut stream_ids = Vec::with_capacity(blocks.len());
+        let mut process_ids = Vec::with_capacity(blocks.len());
+        let mut begin_times = Vec::with_capacity(blocks.len());
+        let mut begin_ticks = Vec::with_capacity(blocks.len());
+        let mut end_times = Vec::with_capacity(blocks.len());
+        let mut end_ticks = Vec::with_capacity(blocks.len());
+        let mut nb_objects = Vec::with_capacity(blocks.len());
+        let mut object_offsets = Vec::with_capacity(blocks.len());
+        let mut payload_sizes = Vec::with_capacity(blocks.len());
+        let mut insert_times = Vec::with_capacity(blocks.len());
+        let mut clock_skews = Vec::with_capacity(blocks.len());
+        let mut checksums = Vec::with_capacity(blocks.len());
+        let mut recorded_ids = HashSet::with_capacity(blocks.len());
+        for block in blocks {
+            if !recorded_ids.insert(block.block_id) {
+                // retransmitted in the same request
+                continue;
+            }
+            let begin_time = DateTime::<FixedOffset>::parse_from_rfc3339(&block.begin_time)
+                .with_context(|| "parsing begin_time")?;
+            let end_time = DateTime::<FixedOffset>::parse_from_rfc3339(&block.end_time)
+                .with_context(|| "parsing end_time")?;
+            debug!(
+                "recording block_id={} stream_id={} process_id={}",
+                block.block_id, block.stream_id, block.process_id
+            );
+            block_ids.push(block.block_id);
+            stream_ids.push(block.stream_id);
+            process_ids.push(block.process_id);
+            begin_times.push(begin_time.with_timezone(&Utc));
+            begin_ticks.push(block.begin_ticks);
+            end_times.push(end_time.with_timezone(&Utc));
+            end_ticks.push(block.end_ticks);
+            nb_objects.push(block.nb_objects);
+            object_offsets.push(block.object_offset);
+            payload_sizes.push(block.payload_size);
+            insert_times.push(block.insert_time);
+            clock_skews.push(block.clock_skew_ms);
+            checksums.push(block.checksum.map(checksum_to_i64));
+        }
+        let mut tr = self.lake.db_pool.begin().await?;
+        // serializes concurrent retransmissions of the same blocks, locked in order to avoid deadlocks
+        sqlx::query(
+            "SELECT pg_advisory_xact_lock(hashtext(id))
+             FROM (SELECT DISTINCT unnest($1::uuid[])::text AS id ORDER BY id) AS ids;",
+        )
+        .bind(&block_ids)
+        .execute(&mut *tr)
+        .await
+        .with_context(|| "locking block_ids")?;
+        // retransmitted blocks are ignored, the payload was written at the same path
+        let sql = "INSERT INTO blocks (block_id, stream_id, process_id, begin_time, begin_ticks,
+                                      end_time, end_ticks, nb_objects, object_offset,
+                                      payload_size, insert_time, clock_skew_ms, checksum)
+                   SELECT * FROM unnest(
+                       $1::uuid[], $2::uuid[], $3::uuid[],
+                       $4::timestamptz[], $5::bigint[], $6::timestamptz[], $7::bigint[],
+                       $8::int[], $9::bigint[], $10::bigint[], $11::timestamptz[], $12::bigint[],
+                       $13::bigint[])
+                       AS new_blocks(block_id, stream_id, process_id, begin_time, begin_ticks,
+                                     end_time, end_ticks, nb_objects, object_offset,
+                                     payload_size, insert_time, clock_skew_ms, checksum)
+                   WHERE NOT EXISTS (SELECT 1 FROM blocks WHERE blocks.block_id = new_blocks.block_id)
+                   RETURNING block_id;";
+        let inserted = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(&block_ids)
+                .bind(&stream_ids)
+                .bind(&process_ids)
+                .bind(&begin_times)
+                .bind(&begin_ticks)
+                .bind(&end_times)
+                .bind(&end_ticks)
+                .bind(&nb_objects)
+                .bind(&object_offsets)
+                .bind(&payload_sizes)
+                .bind(&insert_times)
+                .bind(&clock_skews)
+                .bind(&checksums)
+                .fetch_all(&mut *tr),
+        )
+        .await
+        .with_context(|| "inserting into blocks")?;
+        let mut inserted: HashSet<uuid::Uuid> = inserted
+            .iter()
+            .map(|row| row.try_get("block_id"))
+            .collect::<Result<_, _>>()?;
+        let nb_inserted = inserted.len();
+        let mut notifications = Vec::with_capacity(nb_inserted);
+        for block in blocks {
+            let block_id = &block.block_id;
+            let stream_id = &block.stream_id;
+            let process_id = &block.process_id;
+            if !inserted.remove(block_id) {
+                imetric!("duplicate_blocks", "count", 1);
+                warn!("ignoring duplicate block_id={block_id} stream_id={stream_id} process_id={process_id}");
+                continue;
+            }
+            notifications.push(NewBlockNotification {
+                block_id: *block_id,
+                stream_id: *stream_id,
+                process_id: *process_id,
+                begin_time: block.begin_time.clone(),
+                end_time: block.end_time.clone(),
+                nb_objects: block.nb_objects,
+                payload_size: block.payload_size,
+                checksum: block.checksum.map(checksum_to_i64),
+                payload_path: None,
+            });
+        }
+        notify_new_blocks(&mut tr, &notifications).await?;
+        tr.commit().await.with_context(|| "committing blocks")?;
+        debug!("recorded {nb_inserted} blocks");
+        Ok(())
+    }
+}
@@ -0,0 +1,27 @@
+//! Hooks called by the ingestion service with what the clients send, before it is stored
+//!
+//! Servers embedding the ingestion service can use them to mirror the blocks to other systems
+//! or to add properties to the processes and streams. Failing a hook fails the request.
+use anyhow::Result;
+use async_trait::async_trait;
+use micromegas_telemetry::block_wire_format;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_tracing::prelude::*;
+
+#[async_trait]
+pub trait IngestionHooks: Send + Sync {
+    /// Called for each accepted block, before its payload is written
+    async fn on_block_received(&self, _block: &block_wire_format::Block) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before the process is inserted, the process can be modified
+    async fn on_process_received(&self, _process: &mut ProcessInfo) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called before the stream is inserted, the stream can be modified
+    async fn on_stream_received(&self, _stream: &mut StreamInfo) -> Result<()> {
+        Ok(())
+    }
+}
@@ -3,8 +3,16 @@
 // crate-specific lint exceptions:
 #![allow(clippy::missing_errors_doc)]
 
+pub mod annotations;
+pub mod attachments;
+pub mod block_notifications;
+pub mod block_spool;
+pub mod block_storage;
 pub mod data_lake_connection;
+pub mod ingestion_hooks;
 pub mod remote_data_lake;
+pub mod screens;
+pub mod sql_instrumentation;
 pub mod sql_migration;
 pub mod sql_property;
 pub mod sql_telemetry_db;
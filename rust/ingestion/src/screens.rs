@@ -0,0 +1,198 @@
+//! Screens: dashboards of query cells bound to named data sources, served to the web frontends
+//!
+//! The definition of a screen is stored as json, it is read by the frontends which run the
+//! queries of its data sources in a sql session, with the time range shown as `@query_begin`
+//! and `@query_end`.
+use crate::sql_instrumentation::instrument_query;
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSource {
+    /// cells refer to the source by this name, the rows are registered as a table of that name
+    pub name: String,
+    pub sql: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueryCell {
+    pub title: String,
+    pub data_source: String,
+    /// how the frontend renders the rows, i.e. `table` or `time_series`
+    pub kind: String,
+    /// query over the table of the data source, all its rows when absent
+    #[serde(default)]
+    pub sql: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenDefinition {
+    /// condition on the processes view selecting the processes shown, i.e. `exe LIKE '%server%'`
+    #[serde(default)]
+    pub process_selector: Option<String>,
+    #[serde(default)]
+    pub data_sources: Vec<DataSource>,
+    #[serde(default)]
+    pub cells: Vec<QueryCell>,
+}
+
+fn is_valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+}
+
+impl ScreenDefinition {
+    pub fn validate(&self) -> Result<()> {
+        let mut names = HashSet::new();
+        for source in &self.data_sources {
+            // the names are registered as tables in the sql sessions
+            if !is_valid_identifier(&source.name) {
+                anyhow::bail!("invalid data source name {:?}", source.name);
+            }
+            if source.sql.trim().is_empty() {
+                anyhow::bail!("data source {} has no sql", source.name);
+            }
+            if !names.insert(source.name.as_str()) {
+                anyhow::bail!("data source {} declared more than once", source.name);
+            }
+        }
+        for cell in &self.cells {
+            if !names.contains(cell.data_source.as_str()) {
+                anyhow::bail!(
+                    "cell {:?} bound to unknown data source {}",
+                    cell.title,
+                    cell.data_source
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub screen_id: uuid::Uuid,
+    pub name: String,
+    /// name of the api key that created the screen, `None` when the server does not authenticate
+    pub owner: Option<String>,
+    pub definition: ScreenDefinition,
+}
+
+impl Screen {
+    pub fn new(name: String, owner: Option<String>, definition: ScreenDefinition) -> Result<Self> {
+        let screen = Self {
+            screen_id: uuid::Uuid::new_v4(),
+            name,
+            owner,
+            definition,
+        };
+        screen.validate()?;
+        Ok(screen)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            anyhow::bail!("screen name can't be empty");
+        }
+        self.definition
+            .validate()
+            .with_context(|| format!("validating screen {}", self.name))
+    }
+
+    /// Screens are readable by everyone, but only their owner can change them.
+    /// Screens without owner were created without authentication and can be changed by anyone.
+    pub fn can_be_modified_by(&self, key_name: Option<&str>) -> bool {
+        match &self.owner {
+            None => true,
+            Some(owner) => Some(owner.as_str()) == key_name,
+        }
+    }
+}
+
+fn screen_from_row(row: &sqlx::postgres::PgRow) -> Result<Screen> {
+    let definition: String = row.try_get("definition")?;
+    let screen_id: uuid::Uuid = row.try_get("screen_id")?;
+    Ok(Screen {
+        screen_id,
+        name: row.try_get("name")?,
+        owner: row.try_get("owner")?,
+        definition: serde_json::from_str(&definition)
+            .with_context(|| format!("parsing definition of screen {screen_id}"))?,
+    })
+}
+
+pub async fn insert_screen(connection: &mut sqlx::PgConnection, screen: &Screen) -> Result<()> {
+    screen.validate()?;
+    let now = Utc::now();
+    let sql = "INSERT INTO screens VALUES($1,$2,$3,$4,$5,$6);";
+    instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(screen.screen_id)
+            .bind(&screen.name)
+            .bind(&screen.owner)
+            .bind(serde_json::to_string(&screen.definition)?)
+            .bind(now)
+            .bind(now)
+            .execute(connection),
+    )
+    .await
+    .with_context(|| "inserting into screens")?;
+    Ok(())
+}
+
+/// Replaces the name and definition of the screen, the owner does not change.
+/// Fails if the screen does not exist.
+pub async fn update_screen(connection: &mut sqlx::PgConnection, screen: &Screen) -> Result<()> {
+    screen.validate()?;
+    let sql = "UPDATE screens
+         SET name = $2, definition = $3, update_time = $4
+         WHERE screen_id = $1;";
+    let result = instrument_query(
+        sql,
+        sqlx::query(sql)
+            .bind(screen.screen_id)
+            .bind(&screen.name)
+            .bind(serde_json::to_string(&screen.definition)?)
+            .bind(Utc::now())
+            .execute(connection),
+    )
+    .await
+    .with_context(|| "updating screens")?;
+    if result.rows_affected() == 0 {
+        anyhow::bail!("screen {} not found", screen.screen_id);
+    }
+    Ok(())
+}
+
+/// Returns false if the screen did not exist
+pub async fn delete_screen(
+    connection: &mut sqlx::PgConnection,
+    screen_id: uuid::Uuid,
+) -> Result<bool> {
+    let sql = "DELETE FROM screens WHERE screen_id = $1;";
+    let result = instrument_query(sql, sqlx::query(sql).bind(screen_id).execute(connection))
+        .await
+        .with_context(|| "deleting from screens")?;
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn find_screen(
+    connection: &mut sqlx::PgConnection,
+    screen_id: uuid::Uuid,
+) -> Result<Screen> {
+    let sql = "SELECT screen_id, name, owner, definition
+         FROM screens
+         WHERE screen_id = $1;";
+    let row = instrument_query(sql, sqlx::query(sql).bind(screen_id).fetch_one(connection))
+        .await
+        .with_context(|| format!("screen {screen_id} not found"))?;
+    screen_from_row(&row)
+}
@@ -0,0 +1,130 @@
+//! Spans and metrics for sqlx queries
+//!
+//! ```ignore
+//! let rows = instrument_query(sql, sqlx::query(sql).fetch_all(&pool)).await?;
+//! ```
+use micromegas_tracing::dispatch::int_metric;
+use micromegas_tracing::guards::AsyncNamedSpanGuard;
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::metrics::make_metric_metadata;
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::SpanLocation;
+use sqlx::postgres::{PgQueryResult, PgRow};
+use std::future::Future;
+use std::time::Instant;
+use xxhash_rust::xxh3::xxh3_64;
+
+static QUERY_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Max,
+    target: module_path!(),
+    module_path: module_path!(),
+    file: file!(),
+    line: line!(),
+};
+
+/// Longer fingerprints keep a prefix of the statement followed by a hash of the whole statement
+pub const MAX_FINGERPRINT_LEN: usize = 128;
+
+/// Number of rows returned or affected by a query
+pub trait RowCount {
+    fn row_count(&self) -> u64;
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl RowCount for PgRow {
+    fn row_count(&self) -> u64 {
+        1
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> u64 {
+        u64::from(self.is_some())
+    }
+}
+
+impl RowCount for PgQueryResult {
+    fn row_count(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+/// Identifies a statement regardless of its formatting and literal values
+pub fn statement_fingerprint(sql: &str) -> String {
+    let mut fingerprint = String::with_capacity(sql.len());
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_whitespace() {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if !fingerprint.is_empty() && chars.peek().is_some() {
+                fingerprint.push(' ');
+            }
+        } else if c == '\'' {
+            // string literal, '' is an escaped quote
+            loop {
+                match chars.next() {
+                    Some('\'') => {
+                        if chars.next_if_eq(&'\'').is_none() {
+                            break;
+                        }
+                    }
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+            fingerprint.push('?');
+        } else if c.is_ascii_digit()
+            && !fingerprint.ends_with(|p: char| p.is_alphanumeric() || p == '_' || p == '$')
+        {
+            while chars.next_if(char::is_ascii_digit).is_some() {}
+            fingerprint.push('?');
+        } else {
+            fingerprint.push(c);
+        }
+    }
+    let fingerprint = fingerprint.trim_end_matches(';');
+    if fingerprint.len() <= MAX_FINGERPRINT_LEN {
+        return fingerprint.to_owned();
+    }
+    let hash = format!("#{:016x}", xxh3_64(fingerprint.as_bytes()));
+    let mut prefix_len = MAX_FINGERPRINT_LEN - hash.len();
+    while !fingerprint.is_char_boundary(prefix_len) {
+        prefix_len -= 1;
+    }
+    format!("{}{hash}", &fingerprint[..prefix_len])
+}
+
+/// Runs a sqlx query future inside a span named after the statement fingerprint
+/// and records its duration and row count
+pub async fn instrument_query<F, T>(sql: &str, query: F) -> Result<T, sqlx::Error>
+where
+    F: Future<Output = Result<T, sqlx::Error>>,
+    T: RowCount,
+{
+    let fingerprint = intern_string(&statement_fingerprint(sql));
+    let _span_guard = AsyncNamedSpanGuard::new(&QUERY_SPAN_LOCATION, fingerprint);
+    let begin = Instant::now();
+    let result = query.await;
+    let duration = begin.elapsed();
+    int_metric(
+        make_metric_metadata("sql_query_duration", "ns", fingerprint),
+        u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX),
+    );
+    match &result {
+        Ok(value) => {
+            int_metric(
+                make_metric_metadata("sql_query_rows", "count", fingerprint),
+                value.row_count(),
+            );
+        }
+        Err(e) => {
+            warn!("sql query failed after {duration:?}: {fingerprint}: {e}");
+        }
+    }
+    result
+}
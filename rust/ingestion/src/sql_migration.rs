@@ -1,9 +1,10 @@
 use crate::sql_telemetry_db::create_tables;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use micromegas_tracing::prelude::*;
+use sqlx::Executor;
 use sqlx::Row;
 
-pub const LATEST_SCHEMA_VERSION: i32 = 1;
+pub const LATEST_SCHEMA_VERSION: i32 = 10;
 
 pub async fn read_schema_version(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> i32 {
     match sqlx::query(
@@ -21,15 +22,168 @@ pub async fn read_schema_version(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>)
     }
 }
 
-// pub async fn upgrade_schema_v2(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
-//     tr.execute("ALTER TABLE blocks ADD payload_size BIGINT;")
-//         .await
-//         .with_context(|| "Adding column payload_size to table blocks")?;
-//     tr.execute("UPDATE migration SET version=2;")
-//         .await
-//         .with_context(|| "Updating schema version to 2")?;
-//     Ok(())
-// }
+/// v2: blocks can be looked up by process without going through the streams,
+/// which spares per-process queries from scanning every block in the time range
+pub async fn upgrade_schema_v2(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute("CREATE INDEX block_process_id_begin_time on blocks(process_id, begin_time);")
+        .await
+        .with_context(|| "Creating index block_process_id_begin_time")?;
+    tr.execute("UPDATE migration SET version=2;")
+        .await
+        .with_context(|| "Updating schema version to 2")?;
+    Ok(())
+}
+
+/// v3: blocks record when they were received and how far the client clock was from the server's.
+/// clock_skew_ms is the block's end_time minus insert_time: a positive value is a clock
+/// running ahead, a negative one is the sum of the skew and the delivery delay.
+pub async fn upgrade_schema_v3(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute("ALTER TABLE blocks ADD insert_time TIMESTAMPTZ, ADD clock_skew_ms BIGINT;")
+        .await
+        .with_context(|| "Adding insert_time and clock_skew_ms to blocks")?;
+    tr.execute("UPDATE migration SET version=3;")
+        .await
+        .with_context(|| "Updating schema version to 3")?;
+    Ok(())
+}
+
+/// v4: annotations label time ranges (deploys, incidents) to overlay them on the telemetry
+pub async fn upgrade_schema_v4(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "CREATE TABLE annotations(
+                  annotation_id UUID PRIMARY KEY,
+                  label VARCHAR(255),
+                  begin_time TIMESTAMPTZ,
+                  end_time TIMESTAMPTZ,
+                  properties micromegas_property[],
+                  insert_time TIMESTAMPTZ
+                  );
+         CREATE INDEX annotation_begin_time on annotations(begin_time);
+         CREATE INDEX annotation_end_time on annotations(end_time);",
+    )
+    .await
+    .with_context(|| "Creating table annotations and its indices")?;
+    tr.execute("UPDATE migration SET version=4;")
+        .await
+        .with_context(|| "Updating schema version to 4")?;
+    Ok(())
+}
+
+/// v5: attachments are binary artifacts sent by processes, their content is in the object store
+pub async fn upgrade_schema_v5(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "CREATE TABLE attachments(
+                  attachment_id UUID PRIMARY KEY,
+                  process_id UUID,
+                  name VARCHAR(255),
+                  content_type VARCHAR(255),
+                  size BIGINT,
+                  insert_time TIMESTAMPTZ
+                  );
+         CREATE INDEX attachment_process_id on attachments(process_id);",
+    )
+    .await
+    .with_context(|| "Creating table attachments and its index")?;
+    tr.execute("UPDATE migration SET version=5;")
+        .await
+        .with_context(|| "Updating schema version to 5")?;
+    Ok(())
+}
+
+/// v6: log exports forward new log entries in the order their blocks were inserted,
+/// and remember the last exported block in a checkpoint
+pub async fn upgrade_schema_v6(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "CREATE INDEX block_insert_time on blocks(insert_time);
+         CREATE TABLE log_export_checkpoints(
+                  name VARCHAR(255) PRIMARY KEY,
+                  insert_time TIMESTAMPTZ,
+                  block_id UUID,
+                  update_time TIMESTAMPTZ
+                  );",
+    )
+    .await
+    .with_context(|| "Creating index block_insert_time and table log_export_checkpoints")?;
+    tr.execute("UPDATE migration SET version=6;")
+        .await
+        .with_context(|| "Updating schema version to 6")?;
+    Ok(())
+}
+
+/// v7: blocks dropped by the clients before they reached the ingestion service
+pub async fn upgrade_schema_v7(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "CREATE TABLE data_loss(
+                  process_id UUID,
+                  stream_id UUID,
+                  begin_time TIMESTAMPTZ,
+                  end_time TIMESTAMPTZ,
+                  nb_dropped_blocks BIGINT,
+                  nb_dropped_events BIGINT,
+                  insert_time TIMESTAMPTZ
+                  );
+         CREATE INDEX data_loss_begin_time on data_loss(begin_time);
+         CREATE INDEX data_loss_process_id on data_loss(process_id);",
+    )
+    .await
+    .with_context(|| "Creating table data_loss")?;
+    tr.execute("UPDATE migration SET version=7;")
+        .await
+        .with_context(|| "Updating schema version to 7")?;
+    Ok(())
+}
+
+/// v8: checksums of the payloads computed by the clients, verified when the blocks are read
+pub async fn upgrade_schema_v8(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute("ALTER TABLE blocks ADD checksum BIGINT;")
+        .await
+        .with_context(|| "Adding column checksum to blocks")?;
+    tr.execute("UPDATE migration SET version=8;")
+        .await
+        .with_context(|| "Updating schema version to 8")?;
+    Ok(())
+}
+
+/// v9: screens are dashboards served to the web frontends, see `screens`
+pub async fn upgrade_schema_v9(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "CREATE TABLE screens(
+                  screen_id UUID PRIMARY KEY,
+                  name VARCHAR(255),
+                  owner VARCHAR(255),
+                  definition TEXT,
+                  insert_time TIMESTAMPTZ,
+                  update_time TIMESTAMPTZ
+                  );
+         CREATE INDEX screen_name on screens(name);",
+    )
+    .await
+    .with_context(|| "Creating table screens")?;
+    tr.execute("UPDATE migration SET version=9;")
+        .await
+        .with_context(|| "Updating schema version to 9")?;
+    Ok(())
+}
+
+/// v10: stream tags are validated against `StreamTag` at ingestion, the tags of the existing
+/// streams are lowercased and trimmed to match them. Streams whose tags are still unknown
+/// stay queryable by their tags but are ignored by the views.
+pub async fn upgrade_schema_v10(tr: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<()> {
+    tr.execute(
+        "UPDATE streams
+         SET tags = ARRAY(
+             SELECT lower(btrim(tag))
+             FROM unnest(tags) WITH ORDINALITY AS t(tag, i)
+             ORDER BY i)
+         WHERE EXISTS (SELECT 1 FROM unnest(tags) AS tag WHERE tag <> lower(btrim(tag)));",
+    )
+    .await
+    .with_context(|| "Normalizing stream tags")?;
+    tr.execute("UPDATE migration SET version=10;")
+        .await
+        .with_context(|| "Updating schema version to 10")?;
+    Ok(())
+}
 
 pub async fn execute_migration(pool: sqlx::Pool<sqlx::Postgres>) -> Result<()> {
     let mut current_version = read_schema_version(&mut pool.begin().await?).await;
@@ -40,13 +194,69 @@ pub async fn execute_migration(pool: sqlx::Pool<sqlx::Postgres>) -> Result<()> {
         current_version = read_schema_version(&mut tr).await;
         tr.commit().await?;
     }
-    // if 1 == current_version {
-    //     info!("upgrading schema to v2");
-    //     let mut tr = pool.begin().await?;
-    //     upgrade_schema_v2(&mut tr).await?;
-    //     current_version = read_schema_version(&mut tr).await;
-    //     tr.commit().await?;
-    // }
+    if 1 == current_version {
+        info!("upgrading schema to v2");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v2(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 2 == current_version {
+        info!("upgrading schema to v3");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v3(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 3 == current_version {
+        info!("upgrading schema to v4");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v4(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 4 == current_version {
+        info!("upgrading schema to v5");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v5(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 5 == current_version {
+        info!("upgrading schema to v6");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v6(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 6 == current_version {
+        info!("upgrading schema to v7");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v7(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 7 == current_version {
+        info!("upgrading schema to v8");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v8(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 8 == current_version {
+        info!("upgrading schema to v9");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v9(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
+    if 9 == current_version {
+        info!("upgrading schema to v10");
+        let mut tr = pool.begin().await?;
+        upgrade_schema_v10(&mut tr).await?;
+        current_version = read_schema_version(&mut tr).await;
+        tr.commit().await?;
+    }
     assert_eq!(current_version, LATEST_SCHEMA_VERSION);
     Ok(())
 }
@@ -1,113 +1,521 @@
+use crate::attachments::insert_attachment;
+use crate::block_spool::{BlockMetadata, BlockSpool};
+use crate::block_storage::{BlockStorage, DataLakeBlockStorage};
 use crate::data_lake_connection::DataLakeConnection;
+use crate::ingestion_hooks::IngestionHooks;
+use crate::sql_instrumentation::instrument_query;
 use crate::sql_property::make_properties;
 use anyhow::Context;
 use anyhow::Result;
 use bytes::Buf;
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use micromegas_telemetry::ack_level::AckLevel;
 use micromegas_telemetry::block_wire_format;
+use micromegas_telemetry::block_wire_format::{checksum_to_i64, verify_payload_checksum};
+use micromegas_telemetry::loss_report::LossReport;
 use micromegas_telemetry::stream_info::StreamInfo;
-use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_telemetry::wire_format::{
+    check_wire_format_version, encode_cbor, stream_wire_format_version,
+};
 use micromegas_tracing::prelude::*;
+use micromegas_tracing::stream_tags::validate_stream_tags;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Default number of writes in flight after their request was acknowledged
+pub const DEFAULT_MAX_BACKGROUND_WRITES: usize = 256;
 
 #[derive(Clone)]
 pub struct WebIngestionService {
     lake: DataLakeConnection,
+    max_clock_skew: Option<Duration>,
+    spool: Option<Arc<BlockSpool>>,
+    storage: Arc<dyn BlockStorage>,
+    hooks: Vec<Arc<dyn IngestionHooks>>,
+    custom_stream_tags: Vec<String>,
+    background_writes: Arc<Semaphore>,
+}
+
+/// Time the block was received by the server, authoritative for insert_time
+#[derive(Debug, Clone, Copy)]
+struct Reception {
+    insert_time: DateTime<Utc>,
+    /// client end_time minus insert_time
+    clock_skew: Duration,
 }
 
 impl WebIngestionService {
     pub fn new(lake: DataLakeConnection) -> Self {
-        Self { lake }
+        Self {
+            storage: Arc::new(DataLakeBlockStorage::new(lake.clone())),
+            lake,
+            max_clock_skew: None,
+            spool: None,
+            hooks: vec![],
+            custom_stream_tags: vec![],
+            background_writes: Arc::new(Semaphore::new(DEFAULT_MAX_BACKGROUND_WRITES)),
+        }
     }
 
-    #[span_fn]
-    pub async fn insert_block(&self, body: bytes::Bytes) -> Result<()> {
-        let block: block_wire_format::Block = ciborium::from_reader(body.reader())
-            .with_context(|| "parsing block_wire_format::Block")?;
-        let encoded_payload = encode_cbor(&block.payload)?;
-        let payload_size = encoded_payload.len();
+    /// Stores the blocks in `storage` instead of the data lake, processes and streams are still
+    /// inserted in the data lake
+    #[must_use]
+    pub fn with_block_storage(mut self, storage: Arc<dyn BlockStorage>) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    /// Calls the hooks with what the clients send, in the order they were added
+    #[must_use]
+    pub fn with_hooks(mut self, hooks: Arc<dyn IngestionHooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
 
-        let process_id = &block.process_id;
-        let stream_id = &block.stream_id;
-        let block_id = &block.block_id;
-        let obj_path = format!("blobs/{process_id}/{stream_id}/{block_id}");
-        debug!("writing {obj_path}");
+    /// Accepts streams tagged with `tag`, in addition to the tags of `StreamTag`
+    #[must_use]
+    pub fn with_custom_stream_tag(mut self, tag: String) -> Self {
+        self.custom_stream_tags.push(tag);
+        self
+    }
+
+    /// Bounds the writes continuing after the request was acknowledged, see `AckLevel`.
+    /// Once the bound is reached, the requests wait for a write to complete before being acknowledged.
+    #[must_use]
+    pub fn with_max_background_writes(mut self, max_background_writes: usize) -> Self {
+        self.background_writes = Arc::new(Semaphore::new(max_background_writes));
+        self
+    }
+
+    /// Rejects blocks ending further in the future than `max_clock_skew`, according to the server's clock.
+    /// Blocks in the past are accepted regardless: their lag can't be told apart from delivery delays.
+    #[must_use]
+    pub fn with_max_clock_skew(mut self, max_clock_skew: std::time::Duration) -> Self {
+        self.max_clock_skew =
+            Some(Duration::from_std(max_clock_skew).unwrap_or_else(|_| Duration::max_value()));
+        self
+    }
 
-        use sqlx::types::chrono::{DateTime, FixedOffset};
-        let begin_time = DateTime::<FixedOffset>::parse_from_rfc3339(&block.begin_time)
+    /// Spools the metadata of the blocks when postgresql can't record it, see `block_spool`.
+    /// With a spool, `AckLevel::MetadataCommit` acknowledges blocks recorded in the database or in the spool.
+    #[must_use]
+    pub fn with_spool(mut self, spool: Arc<BlockSpool>) -> Self {
+        self.spool = Some(spool);
+        self
+    }
+
+    fn receive_block(&self, block: &block_wire_format::Block) -> Result<Reception> {
+        let insert_time = Utc::now();
+        check_wire_format_version(block.wire_format_version)
+            .with_context(|| format!("block {}", block.block_id))?;
+        // a mismatch here is the fault of the client or of the network, not of the object store
+        if let Err(e) = verify_payload_checksum(
+            &block.block_id,
+            &block.payload,
+            block.checksum.map(checksum_to_i64),
+        ) {
+            imetric!("rejected_corrupted_blocks", "count", 1);
+            return Err(e);
+        }
+        // checked before the block can be spooled, where it would fail every replay
+        DateTime::<FixedOffset>::parse_from_rfc3339(&block.begin_time)
             .with_context(|| "parsing begin_time")?;
         let end_time = DateTime::<FixedOffset>::parse_from_rfc3339(&block.end_time)
             .with_context(|| "parsing end_time")?;
+        let clock_skew = end_time.with_timezone(&Utc) - insert_time;
+        #[allow(clippy::cast_precision_loss)]
+        let clock_skew_ms = clock_skew.num_milliseconds() as f64;
+        fmetric!("block_clock_skew", "ms", clock_skew_ms);
+        if let Some(max_clock_skew) = self.max_clock_skew {
+            if clock_skew > max_clock_skew {
+                imetric!("rejected_skewed_blocks", "count", 1);
+                anyhow::bail!(
+                    "rejecting block_id={} process_id={}: clock skew of {clock_skew_ms}ms",
+                    block.block_id,
+                    block.process_id
+                );
+            }
+        }
+        Ok(Reception {
+            insert_time,
+            clock_skew,
+        })
+    }
 
-        self.lake
-            .blob_storage
-            .put(&obj_path, encoded_payload.into())
+    #[span_fn]
+    pub async fn insert_block(&self, body: bytes::Bytes, ack_level: AckLevel) -> Result<()> {
+        let block: block_wire_format::Block = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing block_wire_format::Block")?;
+        let reception = self.receive_block(&block)?;
+        self.dispatch_blocks(vec![(block, reception)], ack_level)
             .await
-            .with_context(|| "Error writing block to blob storage")?;
-
-        debug!("recording block_id={block_id} stream_id={stream_id} process_id={process_id}");
-        sqlx::query("INSERT INTO blocks VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10);")
-            .bind(block_id)
-            .bind(stream_id)
-            .bind(process_id)
-            .bind(begin_time)
-            .bind(block.begin_ticks)
-            .bind(end_time)
-            .bind(block.end_ticks)
-            .bind(block.nb_objects)
-            .bind(block.object_offset)
-            .bind(payload_size as i64)
-            .execute(&self.lake.db_pool)
+    }
+
+    /// Inserts the blocks packed in the body, a sequence of cbor-encoded blocks (RFC 8742),
+    /// with a single multi-row insert in the blocks table
+    ///
+    /// Blocks rejected for their clock skew don't prevent the others from being inserted.
+    #[span_fn]
+    pub async fn insert_blocks(&self, body: bytes::Bytes, ack_level: AckLevel) -> Result<()> {
+        let mut reader = body.reader();
+        let mut blocks = vec![];
+        let mut nb_rejected = 0;
+        while reader.get_ref().has_remaining() {
+            let block: block_wire_format::Block = ciborium::from_reader(&mut reader)
+                .with_context(|| "parsing block_wire_format::Block")?;
+            match self.receive_block(&block) {
+                Ok(reception) => blocks.push((block, reception)),
+                Err(e) => {
+                    error!("{e:?}");
+                    nb_rejected += 1;
+                }
+            }
+        }
+        imetric!("blocks_per_request", "count", blocks.len() as u64);
+        self.dispatch_blocks(blocks, ack_level).await?;
+        if nb_rejected > 0 {
+            anyhow::bail!("{nb_rejected} blocks rejected");
+        }
+        Ok(())
+    }
+
+    async fn dispatch_blocks(
+        &self,
+        blocks: Vec<(block_wire_format::Block, Reception)>,
+        ack_level: AckLevel,
+    ) -> Result<()> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        match ack_level {
+            AckLevel::FireAndForget => {
+                let permit = self.acquire_background_write().await?;
+                let service = self.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = service.write_blocks(blocks).await {
+                        error!("Error writing blocks: {e:?}");
+                    }
+                    drop(permit);
+                });
+                Ok(())
+            }
+            AckLevel::ObjectStorePut => {
+                let written = self.write_payloads(blocks).await?;
+                let permit = self.acquire_background_write().await?;
+                let service = self.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = service.record_or_spool_blocks(&written).await {
+                        error!("Error recording blocks: {e:?}");
+                    }
+                    drop(permit);
+                });
+                Ok(())
+            }
+            AckLevel::MetadataCommit => self.write_blocks(blocks).await,
+        }
+    }
+
+    /// Waits for a slot when too many writes are in flight, which slows down the clients
+    async fn acquire_background_write(&self) -> Result<tokio::sync::OwnedSemaphorePermit> {
+        if self.background_writes.available_permits() == 0 {
+            imetric!("background_writes_saturated", "count", 1);
+        }
+        self.background_writes
+            .clone()
+            .acquire_owned()
             .await
-            .with_context(|| "inserting into blocks")?;
-        debug!("recorded block_id={block_id} stream_id={stream_id} process_id={process_id}");
+            .with_context(|| "acquiring background write permit")
+    }
+
+    async fn write_blocks(&self, blocks: Vec<(block_wire_format::Block, Reception)>) -> Result<()> {
+        let written = self.write_payloads(blocks).await?;
+        self.record_or_spool_blocks(&written).await
+    }
 
+    async fn record_or_spool_blocks(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        let Err(e) = self.record_blocks(blocks).await else {
+            return Ok(());
+        };
+        let Some(spool) = &self.spool else {
+            return Err(e);
+        };
+        warn!("spooling {} blocks: {e:?}", blocks.len());
+        spool
+            .spool(blocks)
+            .await
+            .with_context(|| format!("spooling blocks after {e:?}"))?;
+        imetric!("spooled_blocks", "count", blocks.len() as u64);
+        Ok(())
+    }
+
+    /// Records the spooled blocks in the database, returns the number of blocks replayed
+    #[span_fn]
+    pub async fn replay_spool(&self) -> Result<usize> {
+        let Some(spool) = &self.spool else {
+            return Ok(0);
+        };
+        let blocks = spool.pending().await?;
+        if blocks.is_empty() {
+            return Ok(0);
+        }
+        self.record_blocks(&blocks).await?;
+        let block_ids: Vec<uuid::Uuid> = blocks.iter().map(|block| block.block_id).collect();
+        spool.remove(&block_ids).await?;
+        info!("replayed {} spooled blocks", blocks.len());
+        Ok(blocks.len())
+    }
+
+    /// Replays the spool periodically, until the database recovers and after each later outage
+    pub fn spawn_spool_replay_task(&self, period: std::time::Duration) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = service.replay_spool().await {
+                    warn!("replaying spooled blocks: {e:?}");
+                }
+            }
+        });
+    }
+
+    async fn write_payloads(
+        &self,
+        blocks: Vec<(block_wire_format::Block, Reception)>,
+    ) -> Result<Vec<BlockMetadata>> {
+        let mut written = Vec::with_capacity(blocks.len());
+        for (block, reception) in blocks {
+            let payload_size = self.write_payload(&block).await?;
+            written.push(BlockMetadata {
+                block_id: block.block_id,
+                stream_id: block.stream_id,
+                process_id: block.process_id,
+                begin_time: block.begin_time,
+                begin_ticks: block.begin_ticks,
+                end_time: block.end_time,
+                end_ticks: block.end_ticks,
+                nb_objects: block.nb_objects,
+                object_offset: block.object_offset,
+                payload_size,
+                insert_time: reception.insert_time,
+                clock_skew_ms: reception.clock_skew.num_milliseconds(),
+                checksum: block.checksum,
+            });
+        }
+        Ok(written)
+    }
+
+    #[span_fn]
+    async fn write_payload(&self, block: &block_wire_format::Block) -> Result<i64> {
+        for hooks in &self.hooks {
+            hooks
+                .on_block_received(block)
+                .await
+                .with_context(|| format!("on_block_received of block {}", block.block_id))?;
+        }
+        self.storage.write_payload(block).await
+    }
+
+    #[span_fn]
+    async fn record_blocks(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        self.storage.record_blocks(blocks).await
+    }
+
+    #[span_fn]
+    pub async fn insert_attachment(
+        &self,
+        process_id: uuid::Uuid,
+        name: &str,
+        content_type: &str,
+        body: bytes::Bytes,
+    ) -> Result<()> {
+        let attachment =
+            insert_attachment(&self.lake, process_id, name, content_type, body).await?;
+        info!(
+            "new attachment {} {name} of process {process_id} ({} bytes)",
+            attachment.attachment_id, attachment.size
+        );
+        Ok(())
+    }
+
+    /// Appends how the process ended to its properties: exit_time, exit_code, panicked, panic_message
+    #[span_fn]
+    pub async fn insert_process_exit(&self, body: bytes::Bytes) -> Result<()> {
+        let process_exit: ProcessExit =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing ProcessExit")?;
+        let mut properties = HashMap::new();
+        properties.insert(
+            String::from("exit_time"),
+            process_exit.exit_time.to_rfc3339(),
+        );
+        properties.insert(
+            String::from("panicked"),
+            process_exit.panic_message.is_some().to_string(),
+        );
+        if let Some(exit_code) = process_exit.exit_code {
+            properties.insert(String::from("exit_code"), exit_code.to_string());
+        }
+        if let Some(panic_message) = process_exit.panic_message {
+            properties.insert(String::from("panic_message"), panic_message);
+        }
+        let sql = "UPDATE processes
+             SET properties = properties || $2
+             WHERE process_id = $1;";
+        let result = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(process_exit.process_id)
+                .bind(make_properties(&properties))
+                .execute(&self.lake.db_pool),
+        )
+        .await
+        .with_context(|| "updating properties of process")?;
+        if result.rows_affected() == 0 {
+            anyhow::bail!("process {} not found", process_exit.process_id);
+        }
+        Ok(())
+    }
+
+    /// Merges the properties set while the process runs with its properties, replacing the
+    /// values of the same keys. The sink sends the updates in order: the last one received wins.
+    #[span_fn]
+    pub async fn insert_process_properties_update(&self, body: bytes::Bytes) -> Result<()> {
+        let update: ProcessPropertiesUpdate = ciborium::from_reader(body.reader())
+            .with_context(|| "parsing ProcessPropertiesUpdate")?;
+        debug!(
+            "updating properties of process {}: {:?}",
+            update.process_id, &update.properties
+        );
+        let keys: Vec<String> = update.properties.keys().cloned().collect();
+        let sql = "UPDATE processes
+             SET properties = ARRAY(
+                 SELECT p FROM unnest(properties) AS p
+                 WHERE NOT (p.key = ANY($2))) || $3
+             WHERE process_id = $1;";
+        let result = instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(update.process_id)
+                .bind(&keys)
+                .bind(make_properties(&update.properties))
+                .execute(&self.lake.db_pool),
+        )
+        .await
+        .with_context(|| "updating properties of process")?;
+        if result.rows_affected() == 0 {
+            anyhow::bail!("process {} not found", update.process_id);
+        }
+        Ok(())
+    }
+
+    #[span_fn]
+    pub async fn insert_loss_reports(&self, body: bytes::Bytes) -> Result<()> {
+        let reports: Vec<LossReport> =
+            ciborium::from_reader(body.reader()).with_context(|| "parsing LossReport")?;
+        let insert_time = Utc::now();
+        let mut tr = self.lake.db_pool.begin().await?;
+        for report in &reports {
+            warn!(
+                "process {} dropped {} events in {} blocks of stream {}",
+                report.process_id,
+                report.nb_dropped_events,
+                report.nb_dropped_blocks,
+                report.stream_id
+            );
+            let sql = "INSERT INTO data_loss VALUES($1,$2,$3,$4,$5,$6,$7);";
+            instrument_query(
+                sql,
+                sqlx::query(sql)
+                    .bind(report.process_id)
+                    .bind(report.stream_id)
+                    .bind(report.begin_time)
+                    .bind(report.end_time)
+                    .bind(report.nb_dropped_blocks)
+                    .bind(report.nb_dropped_events)
+                    .bind(insert_time)
+                    .execute(&mut *tr),
+            )
+            .await
+            .with_context(|| "inserting into data_loss")?;
+        }
+        tr.commit().await?;
         Ok(())
     }
 
     #[span_fn]
     pub async fn insert_stream(&self, body: bytes::Bytes) -> Result<()> {
-        let stream_info: StreamInfo =
+        let mut stream_info: StreamInfo =
             ciborium::from_reader(body.reader()).with_context(|| "parsing StreamInfo")?;
+        for hooks in &self.hooks {
+            hooks
+                .on_stream_received(&mut stream_info)
+                .await
+                .with_context(|| "on_stream_received")?;
+        }
+        // a misspelled tag would hide the stream from the views
+        if let Err(e) = validate_stream_tags(&stream_info.tags, &self.custom_stream_tags) {
+            imetric!("rejected_stream_tags", "count", 1);
+            anyhow::bail!("rejecting stream {}: {e}", stream_info.stream_id);
+        }
+        // the blocks of a stream in an unknown format could not be parsed
+        stream_wire_format_version(&stream_info.properties)
+            .with_context(|| format!("stream {}", stream_info.stream_id))?;
         info!(
             "new stream {} {:?} {:?}",
             stream_info.stream_id, &stream_info.tags, &stream_info.properties
         );
-        sqlx::query("INSERT INTO streams VALUES($1,$2,$3,$4,$5,$6,$7);")
-            .bind(stream_info.stream_id)
-            .bind(stream_info.process_id)
-            .bind(encode_cbor(&stream_info.dependencies_metadata)?)
-            .bind(encode_cbor(&stream_info.objects_metadata)?)
-            .bind(&stream_info.tags)
-            .bind(make_properties(&stream_info.properties))
-            .bind(sqlx::types::chrono::Utc::now())
-            .execute(&self.lake.db_pool)
-            .await
-            .with_context(|| "inserting into streams")?;
+        let sql = "INSERT INTO streams VALUES($1,$2,$3,$4,$5,$6,$7);";
+        instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(stream_info.stream_id)
+                .bind(stream_info.process_id)
+                .bind(encode_cbor(&stream_info.dependencies_metadata)?)
+                .bind(encode_cbor(&stream_info.objects_metadata)?)
+                .bind(&stream_info.tags)
+                .bind(make_properties(&stream_info.properties))
+                .bind(sqlx::types::chrono::Utc::now())
+                .execute(&self.lake.db_pool),
+        )
+        .await
+        .with_context(|| "inserting into streams")?;
         Ok(())
     }
 
     #[span_fn]
     pub async fn insert_process(&self, body: bytes::Bytes) -> Result<()> {
-        let process_info: ProcessInfo =
+        let mut process_info: ProcessInfo =
             ciborium::from_reader(body.reader()).with_context(|| "parsing ProcessInfo")?;
+        for hooks in &self.hooks {
+            hooks
+                .on_process_received(&mut process_info)
+                .await
+                .with_context(|| "on_process_received")?;
+        }
 
         let insert_time = sqlx::types::chrono::Utc::now();
-        sqlx::query("INSERT INTO processes VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13);")
-            .bind(process_info.process_id)
-            .bind(process_info.exe)
-            .bind(process_info.username)
-            .bind(process_info.realname)
-            .bind(process_info.computer)
-            .bind(process_info.distro)
-            .bind(process_info.cpu_brand)
-            .bind(process_info.tsc_frequency)
-            .bind(process_info.start_time)
-            .bind(process_info.start_ticks)
-            .bind(insert_time)
-            .bind(process_info.parent_process_id)
-            .bind(make_properties(&process_info.properties))
-            .execute(&self.lake.db_pool)
-            .await
-            .with_context(|| "executing sql insert into processes")?;
+        let sql = "INSERT INTO processes VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13);";
+        instrument_query(
+            sql,
+            sqlx::query(sql)
+                .bind(process_info.process_id)
+                .bind(process_info.exe)
+                .bind(process_info.username)
+                .bind(process_info.realname)
+                .bind(process_info.computer)
+                .bind(process_info.distro)
+                .bind(process_info.cpu_brand)
+                .bind(process_info.tsc_frequency)
+                .bind(process_info.start_time)
+                .bind(process_info.start_ticks)
+                .bind(insert_time)
+                .bind(process_info.parent_process_id)
+                .bind(make_properties(&process_info.properties))
+                .execute(&self.lake.db_pool),
+        )
+        .await
+        .with_context(|| "executing sql insert into processes")?;
         Ok(())
     }
 }
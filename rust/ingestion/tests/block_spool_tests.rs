@@ -0,0 +1,69 @@
+use chrono::Utc;
+use micromegas_ingestion::block_spool::{BlockMetadata, BlockSpool};
+
+fn sample_block() -> BlockMetadata {
+    BlockMetadata {
+        block_id: uuid::Uuid::new_v4(),
+        stream_id: uuid::Uuid::new_v4(),
+        process_id: uuid::Uuid::new_v4(),
+        begin_time: "2024-05-01T10:00:00+00:00".to_owned(),
+        begin_ticks: 1000,
+        end_time: "2024-05-01T10:00:01+00:00".to_owned(),
+        end_ticks: 2000,
+        nb_objects: 12,
+        object_offset: 24,
+        payload_size: 512,
+        insert_time: Utc::now(),
+        clock_skew_ms: -3,
+        checksum: Some(u64::MAX),
+    }
+}
+
+#[tokio::test]
+async fn test_block_spool() {
+    let directory = std::env::temp_dir().join(format!("block_spool_{}", uuid::Uuid::new_v4()));
+    let spool = BlockSpool::new(directory.clone()).unwrap();
+    assert!(spool.pending().await.unwrap().is_empty());
+
+    let first = sample_block();
+    let second = sample_block();
+    spool.spool(&[first.clone(), second.clone()]).await.unwrap();
+    // retried after a failed replay
+    spool.spool(std::slice::from_ref(&first)).await.unwrap();
+    let mut pending = spool.pending().await.unwrap();
+    pending.sort_by_key(|block| block.block_id != first.block_id);
+    assert_eq!(pending, vec![first.clone(), second.clone()]);
+
+    spool.remove(&[first.block_id]).await.unwrap();
+    assert_eq!(spool.pending().await.unwrap(), vec![second.clone()]);
+    // removing twice is not an error
+    spool
+        .remove(&[first.block_id, second.block_id])
+        .await
+        .unwrap();
+    assert!(spool.pending().await.unwrap().is_empty());
+
+    // the spool survives restarts of the server
+    spool.spool(std::slice::from_ref(&first)).await.unwrap();
+    drop(spool);
+    let reopened = BlockSpool::new(directory.clone()).unwrap();
+    assert_eq!(reopened.pending().await.unwrap(), vec![first]);
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+#[tokio::test]
+async fn test_block_spool_corrupt_file() {
+    let directory = std::env::temp_dir().join(format!("block_spool_{}", uuid::Uuid::new_v4()));
+    let spool = BlockSpool::new(directory.clone()).unwrap();
+    let block = sample_block();
+    spool.spool(std::slice::from_ref(&block)).await.unwrap();
+    let corrupt_path = directory.join(format!("{}.cbor", uuid::Uuid::new_v4()));
+    std::fs::write(&corrupt_path, b"not cbor").unwrap();
+
+    // the valid blocks are still replayed
+    assert_eq!(spool.pending().await.unwrap(), vec![block.clone()]);
+    assert!(!corrupt_path.exists());
+    assert!(corrupt_path.with_extension("corrupt").exists());
+    assert_eq!(spool.pending().await.unwrap(), vec![block]);
+    std::fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use micromegas_ingestion::block_spool::BlockMetadata;
+use micromegas_ingestion::block_storage::BlockStorage;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::ingestion_hooks::IngestionHooks;
+use micromegas_ingestion::web_ingestion_service::WebIngestionService;
+use micromegas_telemetry::ack_level::AckLevel;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use micromegas_telemetry::block_wire_format::{payload_checksum, Block, BlockPayload};
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_telemetry::wire_format::{encode_cbor, WIRE_FORMAT_VERSION};
+use micromegas_tracing::dispatch::make_process_info;
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::stream_tags::StreamTag;
+use object_store::memory::InMemory;
+use object_store::path::Path;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct MemoryBlockStorage {
+    payloads: Mutex<Vec<uuid::Uuid>>,
+    recorded: Mutex<Vec<BlockMetadata>>,
+}
+
+#[async_trait]
+impl BlockStorage for MemoryBlockStorage {
+    async fn write_payload(&self, block: &Block) -> Result<i64> {
+        self.payloads.lock().unwrap().push(block.block_id);
+        Ok(block.payload.objects.len() as i64)
+    }
+
+    async fn record_blocks(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        self.recorded.lock().unwrap().extend_from_slice(blocks);
+        Ok(())
+    }
+}
+
+/// Records the blocks once the test opens the gate
+struct GatedBlockStorage {
+    gate: tokio::sync::Semaphore,
+    recorded: Mutex<Vec<BlockMetadata>>,
+}
+
+#[async_trait]
+impl BlockStorage for GatedBlockStorage {
+    async fn write_payload(&self, block: &Block) -> Result<i64> {
+        Ok(block.payload.objects.len() as i64)
+    }
+
+    async fn record_blocks(&self, blocks: &[BlockMetadata]) -> Result<()> {
+        self.gate.acquire().await?.forget();
+        self.recorded.lock().unwrap().extend_from_slice(blocks);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MirrorHooks {
+    received: Mutex<Vec<uuid::Uuid>>,
+}
+
+#[async_trait]
+impl IngestionHooks for MirrorHooks {
+    async fn on_block_received(&self, block: &Block) -> Result<()> {
+        self.received.lock().unwrap().push(block.block_id);
+        Ok(())
+    }
+
+    async fn on_process_received(&self, _process: &mut ProcessInfo) -> Result<()> {
+        anyhow::bail!("processes are not accepted")
+    }
+}
+
+fn make_service() -> WebIngestionService {
+    // never connected: the blocks are stored in memory and the process is rejected by the hooks
+    let db_pool = sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let blob_storage = Arc::new(BlobStorage::new(
+        Arc::new(InMemory::new()),
+        Path::from("lake"),
+    ));
+    WebIngestionService::new(DataLakeConnection::new(db_pool, blob_storage))
+}
+
+fn make_block() -> Block {
+    let payload = BlockPayload {
+        dependencies: vec![1, 2],
+        objects: vec![3, 4, 5],
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    Block {
+        block_id: uuid::Uuid::new_v4(),
+        stream_id: uuid::Uuid::new_v4(),
+        process_id: uuid::Uuid::new_v4(),
+        begin_time: now.clone(),
+        begin_ticks: 0,
+        end_time: now,
+        end_ticks: 10,
+        checksum: Some(payload_checksum(&payload)),
+        payload,
+        object_offset: 0,
+        nb_objects: 1,
+        wire_format_version: WIRE_FORMAT_VERSION,
+    }
+}
+
+#[tokio::test]
+async fn test_custom_block_storage_and_hooks() {
+    let storage = Arc::new(MemoryBlockStorage::default());
+    let hooks = Arc::new(MirrorHooks::default());
+    let service = make_service()
+        .with_block_storage(storage.clone())
+        .with_hooks(hooks.clone());
+    let block = make_block();
+    service
+        .insert_block(
+            encode_cbor(&block).unwrap().into(),
+            AckLevel::MetadataCommit,
+        )
+        .await
+        .unwrap();
+    assert_eq!(*hooks.received.lock().unwrap(), [block.block_id]);
+    assert_eq!(*storage.payloads.lock().unwrap(), [block.block_id]);
+    let recorded = storage.recorded.lock().unwrap();
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].block_id, block.block_id);
+    assert_eq!(recorded[0].payload_size, 3);
+}
+
+#[tokio::test]
+async fn test_failing_hook_rejects_process() {
+    let service = make_service().with_hooks(Arc::new(MirrorHooks::default()));
+    let process = make_process_info(uuid::Uuid::new_v4(), None);
+    let res = service
+        .insert_process(encode_cbor(&process).unwrap().into())
+        .await;
+    assert!(format!("{:?}", res.unwrap_err()).contains("processes are not accepted"));
+}
+
+#[tokio::test]
+async fn test_unknown_stream_tag_rejected() {
+    let make_stream = |tags: Vec<String>| StreamInfo {
+        process_id: uuid::Uuid::new_v4(),
+        stream_id: uuid::Uuid::new_v4(),
+        dependencies_metadata: vec![],
+        objects_metadata: vec![],
+        tags,
+        properties: HashMap::new(),
+    };
+    let service = make_service();
+    let res = service
+        .insert_stream(
+            encode_cbor(&make_stream(vec!["metric".to_owned()]))
+                .unwrap()
+                .into(),
+        )
+        .await;
+    assert!(format!("{:?}", res.unwrap_err()).contains("Unknown stream tag"));
+    let res = service
+        .insert_stream(
+            encode_cbor(&make_stream(vec![StreamTag::Cpu.into(), "gpu".to_owned()]))
+                .unwrap()
+                .into(),
+        )
+        .await;
+    assert!(format!("{:?}", res.unwrap_err()).contains("Unknown stream tag"));
+}
+
+#[tokio::test]
+async fn test_background_writes_are_bounded() {
+    let storage = Arc::new(GatedBlockStorage {
+        gate: tokio::sync::Semaphore::new(0),
+        recorded: Mutex::new(vec![]),
+    });
+    let service = make_service()
+        .with_block_storage(storage.clone())
+        .with_max_background_writes(1);
+    let insert = |service: WebIngestionService| async move {
+        service
+            .insert_block(
+                encode_cbor(&make_block()).unwrap().into(),
+                AckLevel::ObjectStorePut,
+            )
+            .await
+    };
+    insert(service.clone()).await.unwrap();
+    // the first write is still in flight: the second request waits for it
+    let second = tokio::spawn(insert(service.clone()));
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!second.is_finished());
+    storage.gate.add_permits(2);
+    second.await.unwrap().unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(storage.recorded.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,82 @@
+use micromegas_ingestion::screens::{DataSource, QueryCell, Screen, ScreenDefinition};
+
+fn sample_definition() -> ScreenDefinition {
+    serde_json::from_str(
+        r#"{
+            "process_selector": "exe LIKE '%server%'",
+            "data_sources": [
+                {
+                    "name": "errors",
+                    "sql": "SELECT time, msg FROM log_entries WHERE level <= 2 AND time >= @query_begin"
+                }
+            ],
+            "cells": [
+                {"title": "errors", "data_source": "errors", "kind": "table"},
+                {
+                    "title": "errors per minute",
+                    "data_source": "errors",
+                    "kind": "time_series",
+                    "sql": "SELECT date_bin(INTERVAL '1 minute', time) AS time, count(*) AS value FROM errors GROUP BY 1"
+                }
+            ]
+        }"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_screen_definition_round_trip() {
+    let definition = sample_definition();
+    definition.validate().unwrap();
+    assert_eq!(definition.cells[0].sql, None);
+    let json = serde_json::to_string(&definition).unwrap();
+    assert_eq!(
+        serde_json::from_str::<ScreenDefinition>(&json).unwrap(),
+        definition
+    );
+    assert!(serde_json::from_str::<ScreenDefinition>(r#"{"layout": "grid"}"#).is_err());
+}
+
+#[test]
+fn test_screen_validation() {
+    assert!(Screen::new(String::new(), None, sample_definition()).is_err());
+
+    let mut unbound = sample_definition();
+    unbound.cells.push(QueryCell {
+        title: "warnings".to_owned(),
+        data_source: "warnings".to_owned(),
+        kind: "table".to_owned(),
+        sql: None,
+    });
+    assert!(unbound.validate().is_err());
+
+    let mut duplicate = sample_definition();
+    duplicate
+        .data_sources
+        .push(duplicate.data_sources[0].clone());
+    assert!(duplicate.validate().is_err());
+
+    let mut invalid_name = sample_definition();
+    invalid_name.data_sources.push(DataSource {
+        name: "errors; DROP TABLE screens".to_owned(),
+        sql: "SELECT 1".to_owned(),
+    });
+    assert!(invalid_name.validate().is_err());
+}
+
+#[test]
+fn test_screen_ownership() {
+    let owned = Screen::new(
+        "servers".to_owned(),
+        Some("ops".to_owned()),
+        sample_definition(),
+    )
+    .unwrap();
+    assert!(owned.can_be_modified_by(Some("ops")));
+    assert!(!owned.can_be_modified_by(Some("build-farm")));
+    assert!(!owned.can_be_modified_by(None));
+
+    let shared = Screen::new("servers".to_owned(), None, sample_definition()).unwrap();
+    assert!(shared.can_be_modified_by(None));
+    assert!(shared.can_be_modified_by(Some("ops")));
+}
@@ -0,0 +1,34 @@
+use micromegas_ingestion::sql_instrumentation::{statement_fingerprint, MAX_FINGERPRINT_LEN};
+
+#[test]
+fn test_fingerprint_normalizes() {
+    assert_eq!(
+        statement_fingerprint(
+            "SELECT *\n  FROM blocks\n  WHERE nb_objects > 10 AND name = 'it''s';"
+        ),
+        "SELECT * FROM blocks WHERE nb_objects > ? AND name = ?"
+    );
+    assert_eq!(
+        statement_fingerprint("SELECT * FROM blocks WHERE stream_id = $1"),
+        statement_fingerprint("SELECT *  FROM blocks WHERE stream_id = $1;")
+    );
+}
+
+#[test]
+fn test_long_statements_dont_collide() {
+    let columns =
+        "block_id, stream_id, process_id, begin_time, begin_ticks, end_time, end_ticks, nb_objects";
+    let by_stream = format!("SELECT {columns}, payload_size FROM blocks WHERE stream_id = $1");
+    let by_process = format!("SELECT {columns}, payload_size FROM blocks WHERE process_id = $1");
+    let fingerprint = statement_fingerprint(&by_stream);
+    assert!(fingerprint.len() <= MAX_FINGERPRINT_LEN);
+    assert!(fingerprint.starts_with("SELECT block_id, stream_id"));
+    assert_ne!(fingerprint, statement_fingerprint(&by_process));
+    assert_eq!(
+        fingerprint,
+        statement_fingerprint(&format!("{};", by_stream.replace(", ", ",\n    ")))
+    );
+
+    let multibyte = "é".repeat(200);
+    assert!(statement_fingerprint(&multibyte).len() <= MAX_FINGERPRINT_LEN);
+}
@@ -0,0 +1,128 @@
+//! synchronous client, for build scripts, tools and editor plugins without an async runtime
+use super::endpoints::EndpointSelection;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use datafusion::arrow::array::RecordBatch;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::Serialize;
+
+/// Drives the async client on its own single-threaded runtime
+///
+/// Must not be called from within an async runtime, where blocking on a request would panic.
+#[derive(Debug)]
+pub struct AnalyticsClient {
+    client: super::AnalyticsClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+impl AnalyticsClient {
+    /// `analytics_base_url` is the root of the endpoints, i.e. `http://localhost:8082/analytics/`
+    pub fn new(analytics_base_url: &str) -> Result<Self> {
+        Self::with_client(super::AnalyticsClient::new(analytics_base_url))
+    }
+
+    /// Spreads the requests over equivalent analytics servers, see [`super::AnalyticsClient::new_balanced`]
+    pub fn new_balanced(
+        analytics_base_urls: &[&str],
+        selection: EndpointSelection,
+    ) -> Result<Self> {
+        Self::with_client(super::AnalyticsClient::new_balanced(
+            analytics_base_urls,
+            selection,
+        )?)
+    }
+
+    fn with_client(client: super::AnalyticsClient) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .with_context(|| "creating client runtime")?;
+        Ok(Self { client, runtime })
+    }
+
+    /// Header sent with every request, usually for authentication
+    #[must_use]
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.client = self.client.with_header(name, value);
+        self
+    }
+
+    /// Http client of the requests, see [`super::AnalyticsClient::with_http_client`]
+    #[must_use]
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = self.client.with_http_client(client);
+        self
+    }
+
+    pub fn request<Args: Serialize>(
+        &self,
+        endpoint: &str,
+        args: &Args,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime.block_on(self.client.request(endpoint, args))
+    }
+
+    pub fn query_processes(
+        &self,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime
+            .block_on(self.client.query_processes(begin, end, limit))
+    }
+
+    pub fn query_view(
+        &self,
+        view: &str,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime
+            .block_on(self.client.query_view(view, begin, end, limit))
+    }
+
+    pub fn query_view_ranges(
+        &self,
+        view: &str,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        ranges: &[(DateTime<Utc>, DateTime<Utc>)],
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime.block_on(
+            self.client
+                .query_view_ranges(view, begin, end, ranges, limit),
+        )
+    }
+
+    pub fn create_sql_session(&self) -> Result<String> {
+        self.runtime.block_on(self.client.create_sql_session())
+    }
+
+    pub fn execute_sql(
+        &self,
+        session_id: &str,
+        sql: &str,
+        register_as: Option<&str>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime
+            .block_on(self.client.execute_sql(session_id, sql, register_as))
+    }
+
+    pub fn export_sql(
+        &self,
+        session_id: &str,
+        sql: &str,
+        rows_per_file: Option<usize>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.runtime
+            .block_on(self.client.export_sql(session_id, sql, rows_per_file))
+    }
+
+    pub fn close_sql_session(&self, session_id: &str) -> Result<()> {
+        self.runtime
+            .block_on(self.client.close_sql_session(session_id))
+    }
+}
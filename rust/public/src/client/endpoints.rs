@@ -0,0 +1,105 @@
+//! choice of the analytics server of each request, when the analytics tier is scaled horizontally
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How the first server to try is chosen for each request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointSelection {
+    /// the servers take turns
+    RoundRobin,
+    /// the server with the fewest requests in flight from this client, taking turns on ties
+    LeastLoaded,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    base_url: String,
+    in_flight: AtomicUsize,
+}
+
+/// Base urls of equivalent analytics servers
+#[derive(Debug)]
+pub struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    selection: EndpointSelection,
+    next: AtomicUsize,
+}
+
+impl Endpoint {
+    fn new(base_url: &str) -> Self {
+        let mut base_url = base_url.to_owned();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        Self {
+            base_url,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl Endpoints {
+    pub fn new(base_urls: &[&str], selection: EndpointSelection) -> Result<Self> {
+        if base_urls.is_empty() {
+            anyhow::bail!("no analytics endpoint");
+        }
+        Ok(Self {
+            endpoints: base_urls.iter().map(|url| Endpoint::new(url)).collect(),
+            selection,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn single(base_url: &str) -> Self {
+        Self {
+            endpoints: vec![Endpoint::new(base_url)],
+            selection: EndpointSelection::RoundRobin,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    pub fn base_url(&self, index: usize) -> &str {
+        &self.endpoints[index].base_url
+    }
+
+    /// Indices of the endpoints in the order a request should try them
+    pub fn failover_order(&self) -> Vec<usize> {
+        let nb_endpoints = self.endpoints.len();
+        let turn = self.next.fetch_add(1, Ordering::Relaxed) % nb_endpoints;
+        let first = match self.selection {
+            EndpointSelection::RoundRobin => turn,
+            EndpointSelection::LeastLoaded => (0..nb_endpoints)
+                .map(|offset| (turn + offset) % nb_endpoints)
+                .min_by_key(|index| self.endpoints[*index].in_flight.load(Ordering::Relaxed))
+                .unwrap_or(turn),
+        };
+        (0..nb_endpoints)
+            .map(|offset| (first + offset) % nb_endpoints)
+            .collect()
+    }
+
+    /// Counts the request as in flight until the guard is dropped
+    pub fn begin_request(&self, index: usize) -> InFlightGuard<'_> {
+        let endpoint = &self.endpoints[index];
+        endpoint.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard { endpoint }
+    }
+}
+
+pub struct InFlightGuard<'a> {
+    endpoint: &'a Endpoint,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.endpoint.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,436 @@
+//! client of the analytics service
+//!
+//! Requests are sent as cbor and the results come back as parquet, decoded into record batches.
+//! Use [`blocking::AnalyticsClient`] from code that does not run in an async runtime.
+//!
+//! A client can spread its requests over several analytics servers with
+//! [`AnalyticsClient::new_balanced`]: a request goes to the next server when the chosen one
+//! can't be reached, and the statements of a sql session go to the server that created it.
+pub mod blocking;
+pub mod endpoints;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use datafusion::arrow::array::{AsArray, RecordBatch};
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use endpoints::{EndpointSelection, Endpoints};
+use micromegas_analytics::time_ranges::TimeRangeArg;
+use micromegas_tracing::prelude::*;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Serialize)]
+struct TimeRangeRequest {
+    begin: String,
+    end: String,
+    limit: i64,
+}
+
+#[derive(Serialize)]
+struct QueryViewRequest<'a> {
+    view: &'a str,
+    begin: String,
+    end: String,
+    limit: i64,
+    time_ranges: Vec<TimeRangeArg>,
+}
+
+#[derive(Serialize)]
+struct ExecuteSqlRequest<'a> {
+    session_id: &'a str,
+    sql: &'a str,
+    register_as: Option<&'a str>,
+    begin: Option<String>,
+    end: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ExportSqlRequest<'a> {
+    session_id: &'a str,
+    sql: &'a str,
+    rows_per_file: Option<usize>,
+    begin: Option<String>,
+    end: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SessionRequest<'a> {
+    session_id: &'a str,
+}
+
+enum SendError {
+    /// the server could not take the request, another one can be tried
+    Unavailable(anyhow::Error),
+    Failed(anyhow::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyticsClient {
+    client: reqwest::Client,
+    endpoints: Arc<Endpoints>,
+    // index of the endpoint that created each open sql session
+    sessions: Arc<Mutex<HashMap<String, usize>>>,
+    headers: HeaderMap,
+}
+
+impl AnalyticsClient {
+    /// `analytics_base_url` is the root of the endpoints, i.e. `http://localhost:8082/analytics/`
+    pub fn new(analytics_base_url: &str) -> Self {
+        Self::with_endpoints(Endpoints::single(analytics_base_url))
+    }
+
+    /// Spreads the requests over equivalent analytics servers, with failover
+    pub fn new_balanced(
+        analytics_base_urls: &[&str],
+        selection: EndpointSelection,
+    ) -> Result<Self> {
+        Ok(Self::with_endpoints(Endpoints::new(
+            analytics_base_urls,
+            selection,
+        )?))
+    }
+
+    fn with_endpoints(endpoints: Endpoints) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            endpoints: Arc::new(endpoints),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Header sent with every request, usually for authentication
+    #[must_use]
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Http client of the requests, i.e. configured with
+    /// [`configure_client_tls`](micromegas_telemetry_sink::client_tls::configure_client_tls) for mutual tls
+    #[must_use]
+    pub fn with_http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Sends the arguments to the endpoint and decodes the parquet response
+    pub async fn request<Args: Serialize>(
+        &self,
+        endpoint: &str,
+        args: &Args,
+    ) -> Result<Vec<RecordBatch>> {
+        let (_index, batches) = self.request_any(endpoint, args).await?;
+        Ok(batches)
+    }
+
+    /// Tries the servers in the order of the selection, returns the index of the one that answered
+    async fn request_any<Args: Serialize>(
+        &self,
+        endpoint: &str,
+        args: &Args,
+    ) -> Result<(usize, Vec<RecordBatch>)> {
+        let body = encode_request(args)?;
+        let mut last_error = None;
+        for index in self.endpoints.failover_order() {
+            match self.send(index, endpoint, body.clone()).await {
+                Ok(content) => return Ok((index, decode_response(content)?)),
+                Err(SendError::Unavailable(e)) => {
+                    warn!("{e:?}");
+                    last_error = Some(e);
+                }
+                Err(SendError::Failed(e)) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("no analytics endpoint")))
+    }
+
+    /// Sends the request to one server, without failover
+    async fn request_to<Args: Serialize>(
+        &self,
+        index: usize,
+        endpoint: &str,
+        args: &Args,
+    ) -> Result<Vec<RecordBatch>> {
+        match self.send(index, endpoint, encode_request(args)?).await {
+            Ok(content) => decode_response(content),
+            Err(SendError::Unavailable(e) | SendError::Failed(e)) => Err(e),
+        }
+    }
+
+    async fn send(&self, index: usize, endpoint: &str, body: Vec<u8>) -> Result<Bytes, SendError> {
+        let _in_flight = self.endpoints.begin_request(index);
+        let url = format!("{}{endpoint}", self.endpoints.base_url(index));
+        let response = match self
+            .client
+            .post(&url)
+            .headers(self.headers.clone())
+            .body(body)
+            .send()
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => {
+                // the request was not sent if the connection could not be established
+                let unavailable = e.is_connect();
+                let error = anyhow::Error::new(e).context(format!("sending request to {url}"));
+                return Err(if unavailable {
+                    SendError::Unavailable(error)
+                } else {
+                    SendError::Failed(error)
+                });
+            }
+        };
+        let status = response.status();
+        let content = response
+            .bytes()
+            .await
+            .with_context(|| "reading response")
+            .map_err(SendError::Failed)?;
+        if !status.is_success() {
+            let error = anyhow::anyhow!(
+                "request to {url} failed with code={status} text={}",
+                String::from_utf8_lossy(&content)
+            );
+            // the load balancer or the server itself reports that the request was not processed
+            return Err(match status {
+                StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT => SendError::Unavailable(error),
+                _ => SendError::Failed(error),
+            });
+        }
+        Ok(content)
+    }
+
+    pub async fn query_processes(
+        &self,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.request(
+            "query_processes",
+            &TimeRangeRequest {
+                begin: begin.to_rfc3339(),
+                end: end.to_rfc3339(),
+                limit,
+            },
+        )
+        .await
+    }
+
+    /// Rows of the view in any of the disjoint `ranges`, clipped to [begin, end)
+    pub async fn query_view_ranges(
+        &self,
+        view: &str,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        ranges: &[(DateTime<Utc>, DateTime<Utc>)],
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.request(
+            "query_view",
+            &QueryViewRequest {
+                view,
+                begin: begin.to_rfc3339(),
+                end: end.to_rfc3339(),
+                limit,
+                time_ranges: ranges
+                    .iter()
+                    .map(|(begin, end)| TimeRangeArg {
+                        begin: begin.to_rfc3339(),
+                        end: end.to_rfc3339(),
+                    })
+                    .collect(),
+            },
+        )
+        .await
+    }
+
+    pub async fn query_view(
+        &self,
+        view: &str,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<RecordBatch>> {
+        self.request(
+            "query_view",
+            &QueryViewRequest {
+                view,
+                begin: begin.to_rfc3339(),
+                end: end.to_rfc3339(),
+                limit,
+                time_ranges: vec![],
+            },
+        )
+        .await
+    }
+
+    /// Returns the id of a sql session keeping its views and registered results between queries
+    ///
+    /// The session lives in the memory of the server that created it: the other statements of
+    /// the session are sent to that server, without failover.
+    pub async fn create_sql_session(&self) -> Result<String> {
+        let (index, batches) = self.request_any("create_sql_session", &()).await?;
+        let batch = batches
+            .first()
+            .with_context(|| "empty create_sql_session response")?;
+        let session_ids = batch
+            .column_by_name("session_id")
+            .with_context(|| "missing session_id column")?
+            .as_string_opt::<i32>()
+            .with_context(|| "session_id should be a string")?;
+        let session_id = session_ids.value(0).to_owned();
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sessions lock poisoned"))?
+            .insert(session_id.clone(), index);
+        Ok(session_id)
+    }
+
+    /// Index of the endpoint that created the session, if it was created by this client
+    fn session_endpoint(&self, session_id: &str) -> Result<Option<usize>> {
+        Ok(self
+            .sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sessions lock poisoned"))?
+            .get(session_id)
+            .copied())
+    }
+
+    async fn session_request<Args: Serialize>(
+        &self,
+        session_id: &str,
+        endpoint: &str,
+        args: &Args,
+    ) -> Result<Vec<RecordBatch>> {
+        if let Some(index) = self.session_endpoint(session_id)? {
+            self.request_to(index, endpoint, args).await
+        } else {
+            self.request(endpoint, args).await
+        }
+    }
+
+    pub async fn execute_sql(
+        &self,
+        session_id: &str,
+        sql: &str,
+        register_as: Option<&str>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.session_request(
+            session_id,
+            "execute_sql",
+            &ExecuteSqlRequest {
+                session_id,
+                sql,
+                register_as,
+                begin: None,
+                end: None,
+            },
+        )
+        .await
+    }
+
+    /// The statement can refer to the range as `@query_begin` and `@query_end`
+    pub async fn execute_sql_in_range(
+        &self,
+        session_id: &str,
+        sql: &str,
+        register_as: Option<&str>,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.session_request(
+            session_id,
+            "execute_sql",
+            &ExecuteSqlRequest {
+                session_id,
+                sql,
+                register_as,
+                begin: Some(begin.to_rfc3339()),
+                end: Some(end.to_rfc3339()),
+            },
+        )
+        .await
+    }
+
+    /// Writes the result to parquet files in the object store, returns the manifest of the files
+    pub async fn export_sql(
+        &self,
+        session_id: &str,
+        sql: &str,
+        rows_per_file: Option<usize>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.session_request(
+            session_id,
+            "export_sql",
+            &ExportSqlRequest {
+                session_id,
+                sql,
+                rows_per_file,
+                begin: None,
+                end: None,
+            },
+        )
+        .await
+    }
+
+    /// `export_sql` of a statement that can refer to the range as `@query_begin` and `@query_end`
+    pub async fn export_sql_in_range(
+        &self,
+        session_id: &str,
+        sql: &str,
+        rows_per_file: Option<usize>,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<RecordBatch>> {
+        self.session_request(
+            session_id,
+            "export_sql",
+            &ExportSqlRequest {
+                session_id,
+                sql,
+                rows_per_file,
+                begin: Some(begin.to_rfc3339()),
+                end: Some(end.to_rfc3339()),
+            },
+        )
+        .await
+    }
+
+    pub async fn close_sql_session(&self, session_id: &str) -> Result<()> {
+        self.session_request(
+            session_id,
+            "close_sql_session",
+            &SessionRequest { session_id },
+        )
+        .await?;
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow::anyhow!("sessions lock poisoned"))?
+            .remove(session_id);
+        Ok(())
+    }
+}
+
+fn encode_request<Args: Serialize>(args: &Args) -> Result<Vec<u8>> {
+    let mut body = vec![];
+    ciborium::into_writer(args, &mut body).with_context(|| "encoding request")?;
+    Ok(body)
+}
+
+fn decode_response(content: Bytes) -> Result<Vec<RecordBatch>> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(content)
+        .with_context(|| "reading parquet metadata")?
+        .build()
+        .with_context(|| "building parquet reader")?;
+    reader
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| "decoding parquet")
+}
@@ -23,3 +23,15 @@ pub mod ingestion {
 pub mod analytics {
     pub use micromegas_analytics::*;
 }
+
+/// Clients of the analytics service, async and blocking
+pub mod client;
+
+/// Settings of the servers, from the environment and a config file
+pub mod config;
+
+/// Https with optional client certificate verification for the servers
+pub mod server_tls;
+
+/// Http routes of the services, to embed them in other servers
+pub mod servers;
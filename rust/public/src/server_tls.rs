@@ -0,0 +1,113 @@
+//! Https for the micromegas servers, with optional verification of client certificates
+//!
+//! When a client certificate authority is configured, connections without a certificate signed
+//! by it are refused during the handshake: services and their clients authenticate each other
+//! without relying on a service mesh.
+use anyhow::{Context, Result};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use micromegas_tracing::prelude::*;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+
+/// Connections that don't complete their handshake in time are dropped
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept, which usually persists for a while, i.e. when out of file descriptors
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Pem files of the server
+#[derive(Debug, Clone)]
+pub struct ServerTlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// clients must present a certificate signed by this authority when set
+    pub client_ca: Option<PathBuf>,
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parsing certificates of {}", path.display()))
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let pem = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .with_context(|| format!("parsing private key of {}", path.display()))?
+        .with_context(|| format!("no private key in {}", path.display()))
+}
+
+pub fn make_tls_acceptor(config: &ServerTlsConfig) -> Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .with_context(|| "selecting tls versions")?;
+    let builder = if let Some(client_ca) = &config.client_ca {
+        let mut roots = RootCertStore::empty();
+        for cert in load_certs(client_ca)? {
+            roots
+                .add(cert)
+                .with_context(|| "adding client certificate authority")?;
+        }
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .with_context(|| "building client certificate verifier")?;
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let mut server_config = builder
+        .with_single_cert(load_certs(&config.cert)?, load_key(&config.key)?)
+        .with_context(|| "configuring server certificate")?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Serves the router over tls, an alternative to `axum::serve`
+pub async fn serve_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: axum::Router,
+) -> Result<()> {
+    loop {
+        let (stream, remote_addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                error!("accepting connection: {e:?}");
+                tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        warn!("tls handshake with {remote_addr} failed: {e:?}");
+                        return;
+                    }
+                    Err(_elapsed) => {
+                        warn!("tls handshake with {remote_addr} timed out");
+                        return;
+                    }
+                };
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("connection with {remote_addr}: {e:?}");
+            }
+        });
+    }
+}
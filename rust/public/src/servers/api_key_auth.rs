@@ -0,0 +1,238 @@
+//! Tower middleware authenticating requests with api keys, reloadable without restarting the server
+//!
+//! Keys are read from `MICROMEGAS_API_KEYS_FILE`, a json file reloaded on SIGHUP and when it is
+//! modified, or from `MICROMEGAS_API_KEYS`. Both use the format
+//! `[{"name": "build-farm", "key": "..."}]`, where the name identifies the key in the
+//! `authenticated_requests` metric so rotations can be audited.
+//! Clients send the key in the `Authorization: Bearer <key>` header.
+use anyhow::{Context, Result};
+use micromegas_tracing::dispatch::int_metric;
+use micromegas_tracing::metrics::make_metric_metadata;
+use micromegas_tracing::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, SystemTime};
+use tower::{Layer, Service};
+
+pub const API_KEYS_ENV_VAR: &str = "MICROMEGAS_API_KEYS";
+pub const API_KEYS_FILE_ENV_VAR: &str = "MICROMEGAS_API_KEYS_FILE";
+
+#[derive(Debug, Deserialize)]
+struct ApiKeyEntry {
+    name: String,
+    key: String,
+}
+
+/// Name of the key that authenticated the request, available in the request extensions
+#[derive(Debug, Clone)]
+pub struct AuthenticatedKey(pub String);
+
+#[derive(Debug)]
+enum KeyringSource {
+    Env,
+    File(PathBuf),
+}
+
+/// Maps api keys to their names
+#[derive(Debug)]
+pub struct ApiKeyAuthProvider {
+    source: KeyringSource,
+    keys: RwLock<HashMap<String, String>>,
+    file_modified: Mutex<Option<SystemTime>>,
+}
+
+fn parse_keyring(json: &str) -> Result<HashMap<String, String>> {
+    let entries: Vec<ApiKeyEntry> =
+        serde_json::from_str(json).with_context(|| "parsing keyring")?;
+    let mut keys = HashMap::new();
+    for entry in entries {
+        if entry.key.is_empty() {
+            anyhow::bail!("empty api key for {}", entry.name);
+        }
+        keys.insert(entry.key, entry.name);
+    }
+    Ok(keys)
+}
+
+impl ApiKeyAuthProvider {
+    /// Keys of a keyring file, see [`Self::spawn_reload_tasks`]
+    pub fn from_file(path: PathBuf) -> Result<Self> {
+        let provider = Self {
+            source: KeyringSource::File(path),
+            keys: RwLock::new(HashMap::new()),
+            file_modified: Mutex::new(None),
+        };
+        provider.reload()?;
+        Ok(provider)
+    }
+
+    /// `None` when neither `MICROMEGAS_API_KEYS_FILE` nor `MICROMEGAS_API_KEYS` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        if let Ok(path) = std::env::var(API_KEYS_FILE_ENV_VAR) {
+            return Ok(Some(Self::from_file(PathBuf::from(path))?));
+        }
+        if std::env::var(API_KEYS_ENV_VAR).is_err() {
+            return Ok(None);
+        }
+        let provider = Self {
+            source: KeyringSource::Env,
+            keys: RwLock::new(HashMap::new()),
+            file_modified: Mutex::new(None),
+        };
+        provider.reload()?;
+        Ok(Some(provider))
+    }
+
+    /// Replaces the keys with the current content of the source, keeping the previous keys on error
+    pub fn reload(&self) -> Result<()> {
+        let keys = match &self.source {
+            KeyringSource::Env => parse_keyring(
+                &std::env::var(API_KEYS_ENV_VAR)
+                    .with_context(|| format!("reading {API_KEYS_ENV_VAR}"))?,
+            )?,
+            KeyringSource::File(path) => {
+                let modified = std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok();
+                let json = std::fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?;
+                let keys = parse_keyring(&json)?;
+                *self.file_modified.lock().unwrap() = modified;
+                keys
+            }
+        };
+        info!("loaded {} api keys", keys.len());
+        *self.keys.write().unwrap() = keys;
+        Ok(())
+    }
+
+    /// Name of the key, `None` if it is not in the keyring
+    pub fn authenticate(&self, key: &str) -> Option<String> {
+        self.keys.read().unwrap().get(key).cloned()
+    }
+
+    fn file_changed(&self) -> bool {
+        let KeyringSource::File(path) = &self.source else {
+            return false;
+        };
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        modified.is_some() && modified != *self.file_modified.lock().unwrap()
+    }
+
+    /// Reloads the keys on SIGHUP and when the keyring file is modified
+    pub fn spawn_reload_tasks(self: &Arc<Self>, poll_interval: Duration) {
+        #[cfg(unix)]
+        {
+            let provider = self.clone();
+            tokio::spawn(async move {
+                use tokio::signal::unix::{signal, SignalKind};
+                let mut hangups = match signal(SignalKind::hangup()) {
+                    Ok(hangups) => hangups,
+                    Err(e) => {
+                        error!("listening to SIGHUP: {e:?}");
+                        return;
+                    }
+                };
+                while hangups.recv().await.is_some() {
+                    info!("SIGHUP: reloading api keys");
+                    if let Err(e) = provider.reload() {
+                        error!("reloading api keys: {e:?}");
+                    }
+                }
+            });
+        }
+        if matches!(self.source, KeyringSource::File(_)) {
+            let provider = self.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(poll_interval);
+                loop {
+                    interval.tick().await;
+                    if provider.file_changed() {
+                        info!("keyring file modified: reloading api keys");
+                        if let Err(e) = provider.reload() {
+                            error!("reloading api keys: {e:?}");
+                        }
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Rejects requests without a valid api key with a 401
+#[derive(Debug, Clone)]
+pub struct ApiKeyAuthLayer {
+    provider: Arc<ApiKeyAuthProvider>,
+}
+
+impl ApiKeyAuthLayer {
+    pub fn new(provider: Arc<ApiKeyAuthProvider>) -> Self {
+        Self { provider }
+    }
+}
+
+impl<S> Layer<S> for ApiKeyAuthLayer {
+    type Service = ApiKeyAuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ApiKeyAuthService {
+            inner,
+            provider: self.provider.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiKeyAuthService<S> {
+    inner: S,
+    provider: Arc<ApiKeyAuthProvider>,
+}
+
+fn bearer_token<ReqBody>(request: &http::Request<ReqBody>) -> Option<&str> {
+    request
+        .headers()
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ApiKeyAuthService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<ReqBody>) -> Self::Future {
+        let Some(key_name) = bearer_token(&request).and_then(|key| self.provider.authenticate(key))
+        else {
+            imetric!("rejected_requests", "count", 1);
+            warn!("rejecting unauthenticated request uri={}", request.uri());
+            let mut response = http::Response::new(ResBody::default());
+            *response.status_mut() = http::StatusCode::UNAUTHORIZED;
+            return Box::pin(async move { Ok(response) });
+        };
+        int_metric(
+            make_metric_metadata("authenticated_requests", "count", &key_name),
+            1,
+        );
+        request.extensions_mut().insert(AuthenticatedKey(key_name));
+        Box::pin(self.inner.call(request))
+    }
+}
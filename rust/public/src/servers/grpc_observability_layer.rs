@@ -0,0 +1,345 @@
+//! Tower middleware instrumenting gRPC calls: method, status, duration and message counts
+//!
+//! Works on the server (`tonic::transport::Server::builder().layer(...)`) and on the client
+//! (`tower::ServiceBuilder::new().layer(...).service(channel)`). The call is recorded when
+//! the response stream ends, so streaming calls are measured over their whole lifetime.
+//! The request body is wrapped to count the request messages: a client channel expecting a
+//! specific body type needs a `map_request` boxing it.
+use bytes::Bytes;
+use http_body::{Body, Frame};
+use micromegas_tracing::guards::AsyncNamedSpanGuard;
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::SpanLocation;
+use std::collections::HashSet;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+static GRPC_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Max,
+    target: module_path!(),
+    module_path: module_path!(),
+    file: file!(),
+    line: line!(),
+};
+
+const GRPC_STATUS_HEADER: &str = "grpc-status";
+
+/// Label of the methods past the limit
+pub const OTHER_METHODS: &str = "other";
+
+/// Interned names of the called methods, up to a limit
+///
+/// Paths are chosen by the caller: a server receiving arbitrary paths would leak one
+/// interned string per path otherwise.
+#[derive(Debug)]
+pub struct MethodLabels {
+    max_methods: usize,
+    methods: Mutex<HashSet<&'static str>>,
+}
+
+impl MethodLabels {
+    pub fn new(max_methods: usize) -> Self {
+        Self {
+            max_methods,
+            methods: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Label of the method, `other` once the limit is reached
+    pub fn label(&self, path: &str) -> &'static str {
+        let mut methods = self.methods.lock().unwrap();
+        if let Some(method) = methods.get(path) {
+            return method;
+        }
+        if methods.len() >= self.max_methods {
+            return OTHER_METHODS;
+        }
+        let method = intern_string(path);
+        methods.insert(method);
+        method
+    }
+}
+
+impl Default for MethodLabels {
+    fn default() -> Self {
+        Self::new(256)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GrpcObservabilityLayer {
+    methods: Arc<MethodLabels>,
+}
+
+impl GrpcObservabilityLayer {
+    /// Maximum number of distinct methods in the spans, metrics and logs
+    #[must_use]
+    pub fn with_max_methods(mut self, max_methods: usize) -> Self {
+        self.methods = Arc::new(MethodLabels::new(max_methods));
+        self
+    }
+}
+
+impl<S> Layer<S> for GrpcObservabilityLayer {
+    type Service = GrpcObservabilityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        GrpcObservabilityService {
+            inner,
+            methods: self.methods.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GrpcObservabilityService<S> {
+    inner: S,
+    methods: Arc<MethodLabels>,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for GrpcObservabilityService<S>
+where
+    S: Service<http::Request<ObservedGrpcRequestBody<ReqBody>>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Display,
+    ReqBody: Body<Data = Bytes> + Unpin,
+    ResBody: Body<Data = Bytes> + Unpin,
+{
+    type Response = http::Response<ObservedGrpcBody<ResBody>>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let method = self.methods.label(request.uri().path());
+        let span_guard = AsyncNamedSpanGuard::new(&GRPC_SPAN_LOCATION, method);
+        let begin = Instant::now();
+        let request_messages = Arc::new(AtomicU64::new(0));
+        let request = request.map(|body| ObservedGrpcRequestBody {
+            inner: body,
+            message_counter: MessageCounter::default(),
+            nb_messages: request_messages.clone(),
+        });
+        let response_future = self.inner.call(request);
+        Box::pin(async move {
+            match response_future.await {
+                Ok(response) => {
+                    // trailers-only responses carry the status in the headers
+                    let status = grpc_status(response.headers());
+                    Ok(response.map(|body| ObservedGrpcBody {
+                        inner: body,
+                        request_messages,
+                        response_messages: MessageCounter::default(),
+                        call: Some(GrpcCall {
+                            method,
+                            begin,
+                            status,
+                            _span_guard: span_guard,
+                        }),
+                    }))
+                }
+                Err(e) => {
+                    error!(
+                        "grpc call failed method={method} duration={:?}: {e}",
+                        begin.elapsed()
+                    );
+                    Err(e)
+                }
+            }
+        })
+    }
+}
+
+fn grpc_status(headers: &http::HeaderMap) -> Option<String> {
+    headers
+        .get(GRPC_STATUS_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Counts length-prefixed grpc messages across data frames
+#[derive(Debug, Default)]
+struct MessageCounter {
+    nb_messages: u64,
+    header: Vec<u8>,
+    remaining_in_message: usize,
+}
+
+impl MessageCounter {
+    fn consume(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.remaining_in_message > 0 {
+                let skipped = self.remaining_in_message.min(data.len());
+                self.remaining_in_message -= skipped;
+                data = &data[skipped..];
+                continue;
+            }
+            // 1 byte compression flag + 4 bytes big-endian message length
+            let needed = 5 - self.header.len();
+            let taken = needed.min(data.len());
+            self.header.extend_from_slice(&data[..taken]);
+            data = &data[taken..];
+            if self.header.len() == 5 {
+                let length = u32::from_be_bytes([
+                    self.header[1],
+                    self.header[2],
+                    self.header[3],
+                    self.header[4],
+                ]);
+                self.remaining_in_message = length as usize;
+                self.header.clear();
+                self.nb_messages += 1;
+            }
+        }
+    }
+}
+
+struct GrpcCall {
+    method: &'static str,
+    begin: Instant,
+    status: Option<String>,
+    _span_guard: AsyncNamedSpanGuard,
+}
+
+impl GrpcCall {
+    fn record(self, request_messages: u64, response_messages: u64) {
+        let duration = self.begin.elapsed();
+        imetric!(
+            "grpc_call_duration",
+            "ns",
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+        );
+        imetric!("grpc_request_messages", "count", request_messages);
+        imetric!("grpc_response_messages", "count", response_messages);
+        // a missing status means the stream was interrupted
+        let status = self.status.as_deref().unwrap_or("unknown");
+        if status == "0" {
+            info!(
+                "grpc method={} status={status} requests={request_messages} responses={response_messages} duration={duration:?}",
+                self.method
+            );
+        } else {
+            warn!(
+                "grpc method={} status={status} requests={request_messages} responses={response_messages} duration={duration:?}",
+                self.method
+            );
+        }
+    }
+}
+
+/// Request body counting the messages sent to the inner service
+pub struct ObservedGrpcRequestBody<B> {
+    inner: B,
+    message_counter: MessageCounter,
+    nb_messages: Arc<AtomicU64>,
+}
+
+impl<B> Body for ObservedGrpcRequestBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &polled {
+            if let Some(data) = frame.data_ref() {
+                let this = &mut *self;
+                this.message_counter.consume(data);
+                this.nb_messages
+                    .store(this.message_counter.nb_messages, Ordering::Relaxed);
+            }
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// Response body recording the call once the stream ends or is dropped
+pub struct ObservedGrpcBody<B> {
+    inner: B,
+    request_messages: Arc<AtomicU64>,
+    response_messages: MessageCounter,
+    call: Option<GrpcCall>,
+}
+
+impl<B> ObservedGrpcBody<B> {
+    /// Number of messages read from the request so far
+    pub fn request_messages(&self) -> u64 {
+        self.request_messages.load(Ordering::Relaxed)
+    }
+
+    /// Number of messages in the response so far
+    pub fn response_messages(&self) -> u64 {
+        self.response_messages.nb_messages
+    }
+
+    fn record(&mut self) {
+        if let Some(call) = self.call.take() {
+            call.record(self.request_messages(), self.response_messages());
+        }
+    }
+}
+
+impl<B> Body for ObservedGrpcBody<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.response_messages.consume(data);
+                } else if let Some(trailers) = frame.trailers_ref() {
+                    if let Some(call) = &mut self.call {
+                        call.status = grpc_status(trailers).or(call.status.take());
+                    }
+                }
+            }
+            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => self.record(),
+            Poll::Pending => {}
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for ObservedGrpcBody<B> {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
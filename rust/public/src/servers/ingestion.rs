@@ -0,0 +1,236 @@
+//! Http routes of the ingestion service
+//!
+//! ```no_run
+//! # use micromegas::ingestion::data_lake_connection::DataLakeConnection;
+//! # use micromegas::ingestion::web_ingestion_service::WebIngestionService;
+//! # use micromegas::servers::ingestion::ingestion_router;
+//! # async fn serve(lake: DataLakeConnection) -> anyhow::Result<()> {
+//! let app = axum::Router::new().merge(ingestion_router(WebIngestionService::new(lake)));
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:8081").await?;
+//! axum::serve(listener, app).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The storage of the blocks and the hooks called with what the clients send are configured
+//! on the `WebIngestionService`, see `block_storage` and `ingestion_hooks`.
+//!
+//! Every route reports its failures: 422 when the wire format of the client is not supported,
+//! 500 otherwise. The routes of the processes and streams used to answer 200 regardless, the
+//! sink still ignores their status besides 422 and logs the failures of the blocks that follow.
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::extract::DefaultBodyLimit;
+use axum::http::HeaderMap;
+use axum::response::Response;
+use axum::routing::post;
+use axum::{Extension, Router};
+use micromegas_ingestion::web_ingestion_service::WebIngestionService;
+use micromegas_telemetry::ack_level::{AckLevel, ACK_LEVEL_HEADER};
+use micromegas_telemetry::attachment::{ATTACHMENT_NAME_HEADER, ATTACHMENT_PROCESS_ID_HEADER};
+use micromegas_telemetry::wire_format::UnsupportedWireFormat;
+use micromegas_tracing::prelude::*;
+use sqlx::types::Uuid;
+use std::str::FromStr;
+
+/// Status of the response of every route, see the status codes above
+fn status_response(result: Result<()>) -> Response {
+    match result {
+        // retrying would not help, the client has to be downgraded or the server upgraded
+        Err(e) if e.downcast_ref::<UnsupportedWireFormat>().is_some() => {
+            error!("Rejected request: {e:?}");
+            Response::builder()
+                .status(422)
+                .body(format!("{e:?}").into())
+                .unwrap()
+        }
+        Err(e) => {
+            error!("Error in request: {e:?}");
+            Response::builder()
+                .status(500)
+                .body(format!("{e:?}").into())
+                .unwrap()
+        }
+        Ok(()) => Response::builder().status(200).body(Body::empty()).unwrap(),
+    }
+}
+
+async fn insert_process_request(
+    Extension(service): Extension<WebIngestionService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_process_request");
+    status_response(
+        service
+            .insert_process(body)
+            .await
+            .with_context(|| "insert_process"),
+    )
+}
+
+async fn insert_process_exit_request(
+    Extension(service): Extension<WebIngestionService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_process_exit_request");
+    status_response(
+        service
+            .insert_process_exit(body)
+            .await
+            .with_context(|| "insert_process_exit"),
+    )
+}
+
+async fn insert_process_properties_update_request(
+    Extension(service): Extension<WebIngestionService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_process_properties_update_request");
+    status_response(
+        service
+            .insert_process_properties_update(body)
+            .await
+            .with_context(|| "insert_process_properties_update"),
+    )
+}
+
+async fn insert_loss_reports_request(
+    Extension(service): Extension<WebIngestionService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_loss_reports_request");
+    status_response(
+        service
+            .insert_loss_reports(body)
+            .await
+            .with_context(|| "insert_loss_reports"),
+    )
+}
+
+async fn insert_stream_request(
+    Extension(service): Extension<WebIngestionService>,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_stream_request");
+    status_response(
+        service
+            .insert_stream(body)
+            .await
+            .with_context(|| "insert_stream"),
+    )
+}
+
+fn parse_ack_level(headers: &HeaderMap) -> Result<AckLevel> {
+    match headers.get(ACK_LEVEL_HEADER) {
+        Some(value) => match value.to_str().map(AckLevel::from_str) {
+            Ok(Ok(level)) => Ok(level),
+            _ => anyhow::bail!("invalid {ACK_LEVEL_HEADER} header: {value:?}"),
+        },
+        None => Ok(AckLevel::default()),
+    }
+}
+
+async fn insert_block_request(
+    Extension(service): Extension<WebIngestionService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    if body.is_empty() {
+        return status_response(Err(anyhow::anyhow!("insert_block_request: empty body")));
+    }
+    let ack_level = match parse_ack_level(&headers) {
+        Ok(level) => level,
+        Err(e) => return status_response(Err(e)),
+    };
+    status_response(
+        service
+            .insert_block(body, ack_level)
+            .await
+            .with_context(|| "insert_block"),
+    )
+}
+
+async fn insert_blocks_request(
+    Extension(service): Extension<WebIngestionService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    let ack_level = match parse_ack_level(&headers) {
+        Ok(level) => level,
+        Err(e) => return status_response(Err(e)),
+    };
+    status_response(
+        service
+            .insert_blocks(body, ack_level)
+            .await
+            .with_context(|| "insert_blocks"),
+    )
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<&'a str> {
+    headers
+        .get(name)
+        .with_context(|| format!("missing {name} header"))?
+        .to_str()
+        .with_context(|| format!("invalid {name} header"))
+}
+
+async fn insert_attachment_request(
+    Extension(service): Extension<WebIngestionService>,
+    headers: HeaderMap,
+    body: bytes::Bytes,
+) -> Response {
+    info!("insert_attachment_request");
+    let process_id = match header_str(&headers, ATTACHMENT_PROCESS_ID_HEADER)
+        .and_then(|text| Uuid::parse_str(text).with_context(|| "parsing process_id"))
+    {
+        Ok(process_id) => process_id,
+        Err(e) => return status_response(Err(e)),
+    };
+    let name = match header_str(&headers, ATTACHMENT_NAME_HEADER) {
+        Ok(name) => name,
+        Err(e) => return status_response(Err(e)),
+    };
+    let content_type = header_str(&headers, "content-type").unwrap_or("application/octet-stream");
+    status_response(
+        service
+            .insert_attachment(process_id, name, content_type, body)
+            .await
+            .with_context(|| "insert_attachment"),
+    )
+}
+
+/// Adds the `/ingestion/*` routes, the `WebIngestionService` has to be provided as an `Extension`
+pub fn register_routes(router: Router) -> Router {
+    router
+        .route("/ingestion/insert_process", post(insert_process_request))
+        .route(
+            "/ingestion/insert_process_exit",
+            post(insert_process_exit_request),
+        )
+        .route(
+            "/ingestion/insert_process_properties_update",
+            post(insert_process_properties_update_request),
+        )
+        .route("/ingestion/insert_stream", post(insert_stream_request))
+        .route("/ingestion/insert_block", post(insert_block_request))
+        .route("/ingestion/insert_blocks", post(insert_blocks_request))
+        .route(
+            "/ingestion/insert_attachment",
+            post(insert_attachment_request),
+        )
+        .route(
+            "/ingestion/insert_loss_reports",
+            post(insert_loss_reports_request),
+        )
+}
+
+/// Router serving the ingestion service, to merge in the router of an application
+///
+/// The default body limit of axum is disabled, large blocks are expected: the server should
+/// set its own limit.
+pub fn ingestion_router(service: WebIngestionService) -> Router {
+    register_routes(Router::new())
+        .layer(DefaultBodyLimit::disable())
+        .layer(Extension(service))
+}
@@ -0,0 +1,8 @@
+/// Tower middleware authenticating the requests with reloadable api keys
+pub mod api_key_auth;
+/// Tower middleware recording the calls of grpc servers and clients
+pub mod grpc_observability_layer;
+/// Routes of the ingestion service, to serve it from any axum server
+pub mod ingestion;
+/// Tower middleware recording the requests of any http server
+pub mod observability_layer;
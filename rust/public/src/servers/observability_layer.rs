@@ -0,0 +1,115 @@
+//! Tower middleware recording request logs, duration metrics and optional per-route spans
+//!
+//! Usable with any tower-based server (axum, hyper, tonic, warp). Per-route spans are named
+//! after the matched axum route, they need the layer to be added with `Router::layer`.
+use axum::extract::MatchedPath;
+use micromegas_tracing::guards::AsyncNamedSpanGuard;
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::SpanLocation;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+static REQUEST_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Max,
+    target: module_path!(),
+    module_path: module_path!(),
+    file: file!(),
+    line: line!(),
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ObservabilityLayer {
+    per_route_spans: bool,
+}
+
+impl ObservabilityLayer {
+    /// Creates an async span named after the matched route around each request.
+    /// Requests outside of the routes share the `unmatched` span: the names stay bounded.
+    #[must_use]
+    pub fn with_per_route_spans(mut self, enabled: bool) -> Self {
+        self.per_route_spans = enabled;
+        self
+    }
+}
+
+impl<S> Layer<S> for ObservabilityLayer {
+    type Service = ObservabilityService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ObservabilityService {
+            inner,
+            per_route_spans: self.per_route_spans,
+        }
+    }
+}
+
+/// Name of the route matched by axum, interned once per route
+fn route_name<ReqBody>(request: &http::Request<ReqBody>) -> &'static str {
+    match request.extensions().get::<MatchedPath>() {
+        Some(matched_path) => intern_string(matched_path.as_str()),
+        None => "unmatched",
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObservabilityService<S> {
+    inner: S,
+    per_route_spans: bool,
+}
+
+impl<S, ReqBody, ResBody> Service<http::Request<ReqBody>> for ObservabilityService<S>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: std::fmt::Display,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<ReqBody>) -> Self::Future {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        let span_guard = if self.per_route_spans {
+            Some(AsyncNamedSpanGuard::new(
+                &REQUEST_SPAN_LOCATION,
+                route_name(&request),
+            ))
+        } else {
+            None
+        };
+        debug!("request method={method} uri={uri}");
+        let begin = Instant::now();
+        let response_future = self.inner.call(request);
+        Box::pin(async move {
+            let result = response_future.await;
+            let duration = begin.elapsed();
+            imetric!(
+                "request_duration",
+                "ns",
+                u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+            );
+            match &result {
+                Ok(response) => {
+                    info!(
+                        "response method={method} uri={uri} status={} duration={duration:?}",
+                        response.status()
+                    );
+                }
+                Err(e) => {
+                    error!("request failed method={method} uri={uri} duration={duration:?}: {e}");
+                }
+            }
+            drop(span_guard);
+            result
+        })
+    }
+}
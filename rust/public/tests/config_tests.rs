@@ -0,0 +1,80 @@
+use micromegas::config::{ConfigFile, ServerConfig};
+use std::collections::HashMap;
+
+fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_env_overrides_file() {
+    let file = ConfigFile {
+        sql_connection_string: Some("postgres://file/db".to_owned()),
+        object_store_uri: Some("s3://file-bucket/root".to_owned()),
+        ..ConfigFile::default()
+    };
+    let config = ServerConfig::resolve(
+        file,
+        env_of(&[("MICROMEGAS_OBJECT_STORE_URI", "file:///tmp/lake")]),
+    )
+    .unwrap();
+    assert_eq!(config.sql_connection_string, "postgres://file/db");
+    assert_eq!(config.object_store_uri, "file:///tmp/lake");
+    assert!(config.views_config.is_none());
+    // the connection string may hold a password
+    assert!(!format!("{config:?}").contains("postgres://"));
+}
+
+#[test]
+fn test_all_problems_reported() {
+    let error = ServerConfig::resolve(
+        ConfigFile::default(),
+        env_of(&[("MICROMEGAS_VIEWS_CONFIG", "/does/not/exist.json")]),
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("missing MICROMEGAS_SQL_CONNECTION_STRING"));
+    assert!(error.contains("missing MICROMEGAS_OBJECT_STORE_URI"));
+    assert!(error.contains("/does/not/exist.json is not a file"));
+}
+
+#[test]
+fn test_invalid_values() {
+    let error = ServerConfig::resolve(
+        ConfigFile::default(),
+        env_of(&[
+            ("MICROMEGAS_SQL_CONNECTION_STRING", "mysql://host/db"),
+            ("MICROMEGAS_OBJECT_STORE_URI", "not a url"),
+        ]),
+    )
+    .unwrap_err()
+    .to_string();
+    assert!(error.contains("should start with postgres://"));
+    assert!(error.contains("is not a valid url"));
+}
+
+#[test]
+fn test_custom_stream_tags() {
+    let file = ConfigFile {
+        sql_connection_string: Some("postgres://file/db".to_owned()),
+        object_store_uri: Some("s3://file-bucket/root".to_owned()),
+        custom_stream_tags: Some(vec!["physics".to_owned()]),
+        ..ConfigFile::default()
+    };
+    let config = ServerConfig::resolve(file, env_of(&[])).unwrap();
+    assert_eq!(config.custom_stream_tags, vec!["physics"]);
+
+    let config = ServerConfig::resolve(
+        ConfigFile::default(),
+        env_of(&[
+            ("MICROMEGAS_SQL_CONNECTION_STRING", "postgres://host/db"),
+            ("MICROMEGAS_OBJECT_STORE_URI", "file:///tmp/lake"),
+            ("MICROMEGAS_CUSTOM_STREAM_TAGS", "physics, audio,"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(config.custom_stream_tags, vec!["physics", "audio"]);
+}
@@ -0,0 +1,45 @@
+use micromegas::client::endpoints::{EndpointSelection, Endpoints};
+
+#[test]
+fn test_round_robin() {
+    let endpoints = Endpoints::new(
+        &[
+            "http://a/analytics",
+            "http://b/analytics/",
+            "http://c/analytics",
+        ],
+        EndpointSelection::RoundRobin,
+    )
+    .unwrap();
+    assert_eq!(endpoints.base_url(0), "http://a/analytics/");
+    assert_eq!(endpoints.base_url(1), "http://b/analytics/");
+    assert_eq!(endpoints.failover_order(), vec![0, 1, 2]);
+    assert_eq!(endpoints.failover_order(), vec![1, 2, 0]);
+    assert_eq!(endpoints.failover_order(), vec![2, 0, 1]);
+    assert_eq!(endpoints.failover_order(), vec![0, 1, 2]);
+}
+
+#[test]
+fn test_least_loaded() {
+    let endpoints = Endpoints::new(
+        &["http://a/", "http://b/", "http://c/"],
+        EndpointSelection::LeastLoaded,
+    )
+    .unwrap();
+    let _a = endpoints.begin_request(0);
+    let _b = endpoints.begin_request(1);
+    let c = endpoints.begin_request(2);
+    let _c2 = endpoints.begin_request(2);
+    // a and b are tied, the turn decides
+    assert_eq!(endpoints.failover_order(), vec![0, 1, 2]);
+    assert_eq!(endpoints.failover_order(), vec![1, 2, 0]);
+    drop(c);
+    let _a2 = endpoints.begin_request(0);
+    // b and c are tied, c starts the third turn
+    assert_eq!(endpoints.failover_order(), vec![2, 0, 1]);
+}
+
+#[test]
+fn test_no_endpoint() {
+    assert!(Endpoints::new(&[], EndpointSelection::RoundRobin).is_err());
+}
@@ -0,0 +1,78 @@
+use bytes::Bytes;
+use http_body::{Body, Frame};
+use http_body_util::BodyExt;
+use micromegas::servers::grpc_observability_layer::{
+    GrpcObservabilityLayer, MethodLabels, ObservedGrpcRequestBody, OTHER_METHODS,
+};
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Body made of the given data frames
+struct Chunks(VecDeque<Bytes>);
+
+impl Body for Chunks {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Poll::Ready(self.0.pop_front().map(|data| Ok(Frame::data(data))))
+    }
+}
+
+fn grpc_message(content: &[u8]) -> Vec<u8> {
+    let mut message = vec![0];
+    message.extend_from_slice(&(content.len() as u32).to_be_bytes());
+    message.extend_from_slice(content);
+    message
+}
+
+#[test]
+fn test_method_labels() {
+    let labels = MethodLabels::new(2);
+    assert_eq!(labels.label("/pkg.Service/First"), "/pkg.Service/First");
+    assert_eq!(labels.label("/pkg.Service/Second"), "/pkg.Service/Second");
+    assert_eq!(labels.label("/pkg.Service/Third"), OTHER_METHODS);
+    assert_eq!(labels.label("/pkg.Service/First"), "/pkg.Service/First");
+}
+
+#[tokio::test]
+async fn test_count_messages() {
+    let mut request_data = grpc_message(b"first");
+    request_data.extend(grpc_message(b""));
+    request_data.extend(grpc_message(b"third request"));
+    let expected_request = Bytes::from(request_data.clone());
+    // messages and headers split across frames
+    let request_body = Chunks(VecDeque::from([
+        Bytes::from(request_data[..3].to_vec()),
+        Bytes::from(request_data[3..12].to_vec()),
+        Bytes::from(request_data[12..].to_vec()),
+    ]));
+
+    let mut service = GrpcObservabilityLayer::default().layer(tower::service_fn(
+        move |request: http::Request<ObservedGrpcRequestBody<Chunks>>| {
+            let expected_request = expected_request.clone();
+            async move {
+                let received = request.into_body().collect().await.unwrap().to_bytes();
+                assert_eq!(received, expected_request);
+                let response_body = Chunks(VecDeque::from([
+                    Bytes::from(grpc_message(b"first response")),
+                    Bytes::from(grpc_message(b"second response")),
+                ]));
+                Ok::<_, Infallible>(http::Response::new(response_body))
+            }
+        },
+    ));
+    let request = http::Request::post("/pkg.Service/Method")
+        .body(request_body)
+        .unwrap();
+    let mut response_body = service.call(request).await.unwrap().into_body();
+    while response_body.frame().await.is_some() {}
+    assert_eq!(response_body.request_messages(), 3);
+    assert_eq!(response_body.response_messages(), 2);
+}
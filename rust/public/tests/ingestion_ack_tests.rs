@@ -0,0 +1,133 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::Request;
+use micromegas::ingestion::block_spool::BlockMetadata;
+use micromegas::ingestion::block_storage::BlockStorage;
+use micromegas::ingestion::data_lake_connection::DataLakeConnection;
+use micromegas::ingestion::web_ingestion_service::WebIngestionService;
+use micromegas::object_store::memory::InMemory;
+use micromegas::object_store::path::Path;
+use micromegas::servers::ingestion::ingestion_router;
+use micromegas::sqlx::types::Uuid;
+use micromegas::telemetry::ack_level::ACK_LEVEL_HEADER;
+use micromegas::telemetry::blob_storage::BlobStorage;
+use micromegas::telemetry::block_wire_format::{payload_checksum, Block, BlockPayload};
+use micromegas::telemetry::wire_format::{encode_cbor, WIRE_FORMAT_VERSION};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+/// Block storage failing on demand, to see what the clients are told
+struct FailingBlockStorage {
+    fail_payload: bool,
+    fail_record: bool,
+}
+
+#[async_trait]
+impl BlockStorage for FailingBlockStorage {
+    async fn write_payload(&self, block: &Block) -> Result<i64> {
+        if self.fail_payload {
+            anyhow::bail!("object store unavailable");
+        }
+        Ok(block.payload.objects.len() as i64)
+    }
+
+    async fn record_blocks(&self, _blocks: &[BlockMetadata]) -> Result<()> {
+        if self.fail_record {
+            anyhow::bail!("database unavailable");
+        }
+        Ok(())
+    }
+}
+
+fn make_block() -> Block {
+    let payload = BlockPayload {
+        dependencies: vec![1, 2],
+        objects: vec![3, 4, 5],
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+    Block {
+        block_id: Uuid::new_v4(),
+        stream_id: Uuid::new_v4(),
+        process_id: Uuid::new_v4(),
+        begin_time: now.clone(),
+        begin_ticks: 0,
+        end_time: now,
+        end_ticks: 10,
+        checksum: Some(payload_checksum(&payload)),
+        payload,
+        object_offset: 0,
+        nb_objects: 1,
+        wire_format_version: WIRE_FORMAT_VERSION,
+    }
+}
+
+async fn insert_block_status(ack_level: &str, fail_payload: bool, fail_record: bool) -> u16 {
+    // never connected: the blocks go to the failing storage
+    let db_pool = micromegas::sqlx::PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+    let blob_storage = Arc::new(BlobStorage::new(
+        Arc::new(InMemory::new()),
+        Path::from("lake"),
+    ));
+    let service = WebIngestionService::new(DataLakeConnection::new(db_pool, blob_storage))
+        .with_block_storage(Arc::new(FailingBlockStorage {
+            fail_payload,
+            fail_record,
+        }));
+    let request = Request::post("/ingestion/insert_block")
+        .header(ACK_LEVEL_HEADER, ack_level)
+        .body(Body::from(encode_cbor(&make_block()).unwrap()))
+        .unwrap();
+    let response = ingestion_router(service).oneshot(request).await.unwrap();
+    response.status().as_u16()
+}
+
+#[tokio::test]
+async fn test_fire_and_forget_ignores_storage_errors() {
+    assert_eq!(
+        insert_block_status("fire_and_forget", false, false).await,
+        200
+    );
+    assert_eq!(
+        insert_block_status("fire_and_forget", true, true).await,
+        200
+    );
+}
+
+#[tokio::test]
+async fn test_object_store_put_waits_for_payload() {
+    assert_eq!(
+        insert_block_status("object_store_put", false, false).await,
+        200
+    );
+    // the metadata is recorded after the response
+    assert_eq!(
+        insert_block_status("object_store_put", false, true).await,
+        200
+    );
+    assert_eq!(
+        insert_block_status("object_store_put", true, false).await,
+        500
+    );
+}
+
+#[tokio::test]
+async fn test_metadata_commit_waits_for_record() {
+    assert_eq!(
+        insert_block_status("metadata_commit", false, false).await,
+        200
+    );
+    assert_eq!(
+        insert_block_status("metadata_commit", false, true).await,
+        500
+    );
+    assert_eq!(
+        insert_block_status("metadata_commit", true, false).await,
+        500
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_ack_level() {
+    assert_eq!(insert_block_status("eventually", false, false).await, 500);
+}
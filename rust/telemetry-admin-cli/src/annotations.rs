@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use micromegas_ingestion::annotations::{
+    delete_annotation, find_annotation, insert_annotation, list_annotations, update_annotation,
+    Annotation,
+};
+use std::collections::HashMap;
+
+pub fn parse_time(text: &str) -> Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(text)
+        .with_context(|| format!("parsing rfc3339 time {text}"))?
+        .into())
+}
+
+/// `key=value` pairs
+pub fn parse_properties(properties: &[String]) -> Result<HashMap<String, String>> {
+    properties
+        .iter()
+        .map(|property| {
+            let (key, value) = property
+                .split_once('=')
+                .with_context(|| format!("property {property} should be key=value"))?;
+            Ok((key.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+fn print_annotation(annotation: &Annotation) {
+    let mut properties: Vec<String> = annotation
+        .properties
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+    properties.sort();
+    println!(
+        "{} {} [{}, {}] {}",
+        annotation.annotation_id,
+        annotation.label,
+        annotation.begin_time.to_rfc3339(),
+        annotation.end_time.to_rfc3339(),
+        properties.join(" ")
+    );
+}
+
+pub async fn add_annotation(
+    connection: &mut sqlx::PgConnection,
+    label: String,
+    begin: &str,
+    end: Option<&str>,
+    properties: &[String],
+) -> Result<()> {
+    let annotation = Annotation::new(
+        label,
+        parse_time(begin)?,
+        end.map(parse_time).transpose()?,
+        parse_properties(properties)?,
+    )?;
+    insert_annotation(connection, &annotation).await?;
+    print_annotation(&annotation);
+    Ok(())
+}
+
+pub async fn edit_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation_id: uuid::Uuid,
+    label: Option<String>,
+    begin: Option<&str>,
+    end: Option<&str>,
+    properties: &[String],
+) -> Result<()> {
+    let mut annotation = find_annotation(connection, annotation_id).await?;
+    if let Some(label) = label {
+        annotation.label = label;
+    }
+    if let Some(begin) = begin {
+        annotation.begin_time = parse_time(begin)?;
+    }
+    if let Some(end) = end {
+        annotation.end_time = parse_time(end)?;
+    }
+    annotation.properties.extend(parse_properties(properties)?);
+    update_annotation(connection, &annotation).await?;
+    print_annotation(&annotation);
+    Ok(())
+}
+
+pub async fn remove_annotation(
+    connection: &mut sqlx::PgConnection,
+    annotation_id: uuid::Uuid,
+) -> Result<()> {
+    if !delete_annotation(connection, annotation_id).await? {
+        anyhow::bail!("annotation {annotation_id} not found");
+    }
+    println!("Deleted annotation {annotation_id}");
+    Ok(())
+}
+
+pub async fn print_annotations(
+    connection: &mut sqlx::PgConnection,
+    begin: &str,
+    end: &str,
+    limit: i64,
+) -> Result<()> {
+    for annotation in
+        list_annotations(connection, parse_time(begin)?, parse_time(end)?, limit).await?
+    {
+        print_annotation(&annotation);
+    }
+    Ok(())
+}
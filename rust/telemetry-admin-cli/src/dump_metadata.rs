@@ -0,0 +1,22 @@
+use anyhow::{Context, Result};
+use micromegas_analytics::metadata::fetch_process_metadata;
+use std::io::Write;
+
+/// Writes the process and the metadata of its streams to stdout, as pretty json or cbor
+pub async fn dump_metadata(
+    connection: &mut sqlx::PgConnection,
+    process_id: uuid::Uuid,
+    cbor: bool,
+) -> Result<()> {
+    let metadata = fetch_process_metadata(connection, process_id)
+        .await
+        .with_context(|| format!("fetching metadata of process {process_id}"))?;
+    let mut stdout = std::io::stdout().lock();
+    if cbor {
+        ciborium::into_writer(&metadata, &mut stdout).with_context(|| "writing cbor")?;
+    } else {
+        serde_json::to_writer_pretty(&mut stdout, &metadata).with_context(|| "writing json")?;
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use anyhow::{Context, Result};
+
+/// Deletes the rows of blocks that were inserted more than once
+///
+/// Duplicates share the same payload path in the object store, so only the metadata is removed.
+pub async fn delete_duplicate_blocks(connection: &mut sqlx::PgConnection) -> Result<()> {
+    let result = sqlx::query(
+        "DELETE FROM blocks a
+         USING blocks b
+         WHERE a.block_id = b.block_id
+         AND a.ctid > b.ctid;",
+    )
+    .execute(&mut *connection)
+    .await
+    .with_context(|| "deleting duplicate blocks")?;
+    println!("Deleted {} duplicate blocks", result.rows_affected());
+    Ok(())
+}
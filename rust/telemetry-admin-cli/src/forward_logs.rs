@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use micromegas_analytics::log_export::{
+    is_transient_error, next_log_blocks, read_checkpoint, write_checkpoint, LogBlock,
+    LogExportCheckpoint, LogExportFilter, LogExporter,
+};
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_tracing::prelude::*;
+use std::time::Duration;
+
+const BLOCKS_PER_BATCH: i64 = 100;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+async fn post_lines(client: &reqwest::Client, webhook_url: &str, lines: Vec<u8>) -> Result<()> {
+    let response = client
+        .post(webhook_url)
+        .header("content-type", "application/x-ndjson")
+        .body(lines)
+        .send()
+        .await
+        .with_context(|| format!("posting to {webhook_url}"))?;
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!(
+            "{webhook_url} answered {status}: {}",
+            response.text().await.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Posts the entries until the webhook accepts them, the checkpoint must not move past them before
+async fn deliver(client: &reqwest::Client, webhook_url: &str, lines: Vec<u8>) {
+    let mut delay = Duration::from_secs(1);
+    while let Err(e) = post_lines(client, webhook_url, lines.clone()).await {
+        warn!("log export delivery failed, retrying in {delay:?}: {e:?}");
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+    }
+}
+
+/// Appends the entries of the block to `lines`, retrying until the lake is available
+///
+/// A block that can't be decoded is skipped, it would block the export forever.
+async fn encode_block(exporter: &mut LogExporter, block: &LogBlock, lines: &mut Vec<u8>) -> usize {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        // entries of a failed attempt are not kept
+        let mut block_lines = vec![];
+        match exporter.encode_block(block, &mut block_lines).await {
+            Ok(nb_entries) => {
+                lines.extend(block_lines);
+                return nb_entries;
+            }
+            Err(e) if is_transient_error(&e) => {
+                warn!(
+                    "error reading block {} in log export, retrying in {delay:?}: {e:?}",
+                    block.block_id
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+            Err(e) => {
+                imetric!("log_export_skipped_blocks", "count", 1);
+                error!("skipping block {} in log export: {e:?}", block.block_id);
+                return 0;
+            }
+        }
+    }
+}
+
+/// Forwards the new log entries matching the filter to the webhook as json lines, until interrupted
+///
+/// The export named `name` resumes from its checkpoint, a new export starts with the blocks
+/// inserted from now on.
+///
+/// There is no daemon in this repository to host the export, so the forwarder runs as the
+/// `forward-logs` command, to be supervised like any other service: its checkpoint lets it
+/// resume where it stopped when it is restarted.
+pub async fn forward_logs(
+    lake: DataLakeConnection,
+    name: &str,
+    webhook_url: &str,
+    filter: LogExportFilter,
+    poll_interval: Duration,
+    settle_delay: Duration,
+) -> Result<()> {
+    let settle_delay = chrono::Duration::from_std(settle_delay)?;
+    let client = reqwest::Client::new();
+    let mut checkpoint = match read_checkpoint(&lake.db_pool, name).await? {
+        Some(checkpoint) => checkpoint,
+        None => {
+            let checkpoint = LogExportCheckpoint::starting_now();
+            write_checkpoint(&lake.db_pool, name, &checkpoint).await?;
+            checkpoint
+        }
+    };
+    println!(
+        "forwarding logs of export {name} inserted after {}",
+        checkpoint.insert_time
+    );
+    let mut exporter = LogExporter::new(lake.clone(), filter);
+    loop {
+        let blocks =
+            next_log_blocks(&lake.db_pool, &checkpoint, settle_delay, BLOCKS_PER_BATCH).await?;
+        let Some(last_block) = blocks.last() else {
+            tokio::time::sleep(poll_interval).await;
+            continue;
+        };
+        let mut lines = vec![];
+        let mut nb_entries = 0;
+        for block in &blocks {
+            nb_entries += encode_block(&mut exporter, block, &mut lines).await;
+        }
+        if nb_entries > 0 {
+            deliver(&client, webhook_url, lines).await;
+        }
+        checkpoint = last_block.checkpoint();
+        write_checkpoint(&lake.db_pool, name, &checkpoint).await?;
+        imetric!("log_export_entries", "count", nb_entries as u64);
+    }
+}
@@ -3,16 +3,28 @@
 // crate-specific lint exceptions:
 //#![]
 
+mod annotations;
+mod dump_metadata;
+mod duplicates;
+mod forward_logs;
 mod lake_size;
+mod sql_shell;
+mod unreal_import;
+mod watch;
 
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use duplicates::delete_duplicate_blocks;
 use lake_size::delete_old_blocks;
+use micromegas_analytics::log_export::LogExportFilter;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
 use micromegas_telemetry::blob_storage::BlobStorage;
 use micromegas_telemetry_sink::TelemetryGuard;
+use micromegas_tracing::levels::Level;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[clap(name = "Legion Telemetry Admin")]
@@ -34,6 +46,127 @@ enum Commands {
     /// Delete blocks x days old or older
     #[clap(name = "delete-old-blocks")]
     DeleteoldBlocks { min_days_old: i32 },
+
+    /// Delete the metadata of blocks that were inserted more than once
+    #[clap(name = "delete-duplicate-blocks")]
+    DeleteDuplicateBlocks,
+
+    /// Label a time range, i.e. a deploy or an incident
+    #[clap(name = "add-annotation")]
+    AddAnnotation {
+        label: String,
+        /// rfc3339
+        begin: String,
+        /// rfc3339, defaults to begin for instant markers
+        #[clap(long)]
+        end: Option<String>,
+        /// key=value
+        #[clap(long = "property")]
+        properties: Vec<String>,
+    },
+
+    /// Change an annotation, the properties are merged with the existing ones
+    #[clap(name = "update-annotation")]
+    UpdateAnnotation {
+        annotation_id: uuid::Uuid,
+        #[clap(long)]
+        label: Option<String>,
+        #[clap(long)]
+        begin: Option<String>,
+        #[clap(long)]
+        end: Option<String>,
+        /// key=value
+        #[clap(long = "property")]
+        properties: Vec<String>,
+    },
+
+    #[clap(name = "delete-annotation")]
+    DeleteAnnotation { annotation_id: uuid::Uuid },
+
+    /// List the annotations overlapping a time range
+    #[clap(name = "list-annotations")]
+    ListAnnotations {
+        begin: String,
+        end: String,
+        #[clap(long, default_value_t = 100)]
+        limit: i64,
+    },
+
+    /// Import a capture exported by Unreal Insights (threads.csv, timers.csv, timing_events.csv, counters/*.csv)
+    #[clap(name = "import-unreal-insights")]
+    ImportUnrealInsights {
+        export_dir: std::path::PathBuf,
+        /// name of the captured executable
+        #[clap(long)]
+        exe: String,
+        /// rfc3339 time of the start of the capture, defaults to now
+        #[clap(long)]
+        start_time: Option<String>,
+    },
+
+    /// Print a process and the layout of the events of its streams, to debug wire format issues
+    #[clap(name = "dump-metadata")]
+    DumpMetadata {
+        process_id: uuid::Uuid,
+        /// writes cbor instead of json, as stored in the lake
+        #[clap(long)]
+        cbor: bool,
+    },
+
+    /// Run sql statements in a session of the analytics server, interactively when no statement is given
+    #[clap(name = "sql")]
+    Sql {
+        /// statement to run instead of reading them from stdin
+        #[clap(short = 'c', long)]
+        command: Option<String>,
+        #[clap(long, default_value = "http://localhost:8082")]
+        analytics_url: String,
+        /// queries the last [number][m|h|d], i.e. 1h
+        #[clap(long)]
+        last: Option<String>,
+        /// prints csv instead of a table
+        #[clap(long)]
+        csv: bool,
+        /// prints the results as they are instead of through $PAGER
+        #[clap(long)]
+        no_pager: bool,
+    },
+
+    /// Run a query repeatedly and print the rows added and removed since the previous run
+    #[clap(name = "watch")]
+    Watch {
+        sql: String,
+        /// [number][s|m|h|d]
+        #[clap(long, default_value = "10s")]
+        interval: String,
+        #[clap(long, default_value = "http://localhost:8082")]
+        analytics_url: String,
+        /// queries the last [number][s|m|h|d] at each run, i.e. 15m
+        #[clap(long)]
+        last: Option<String>,
+        /// exits with an error as soon as the query returns rows, i.e. to alert on errors
+        #[clap(long)]
+        fail_on_rows: bool,
+    },
+
+    /// Forward new log entries to a webhook as json lines, resuming from the checkpoint of the export
+    #[clap(name = "forward-logs")]
+    ForwardLogs {
+        /// name of the export, identifies its checkpoint
+        name: String,
+        webhook_url: String,
+        /// least severe level forwarded
+        #[clap(long, default_value = "warn")]
+        level: String,
+        /// forwards only the targets starting with one of these prefixes
+        #[clap(long = "target")]
+        targets: Vec<String>,
+        #[clap(long, default_value_t = 5)]
+        poll_interval_seconds: u64,
+        /// time given to the blocks being inserted to commit before the export moves past them
+        #[clap(long, default_value_t = 10)]
+        settle_delay_seconds: u64,
+    },
 }
 
 #[tokio::main]
@@ -42,6 +175,57 @@ async fn main() -> Result<()> {
 
     let args = Cli::parse();
 
+    // talks to the analytics server, not to the data lake
+    if let Commands::Sql {
+        command,
+        analytics_url,
+        last,
+        csv,
+        no_pager,
+    } = args.command
+    {
+        let options = sql_shell::SqlShellOptions {
+            analytics_url,
+            api_key: std::env::var("MICROMEGAS_API_KEY").ok(),
+            last: last
+                .as_deref()
+                .map(sql_shell::parse_time_delta)
+                .transpose()?,
+            format: if csv {
+                sql_shell::OutputFormat::Csv
+            } else {
+                sql_shell::OutputFormat::Table
+            },
+            use_pager: !no_pager,
+        };
+        return sql_shell::sql_shell(options, command).await;
+    }
+    if let Commands::Watch {
+        sql,
+        interval,
+        analytics_url,
+        last,
+        fail_on_rows,
+    } = args.command
+    {
+        let options = watch::WatchOptions {
+            interval: sql_shell::parse_time_delta(&interval)?,
+            last: last
+                .as_deref()
+                .map(sql_shell::parse_time_delta)
+                .transpose()?,
+            fail_on_rows,
+        };
+        let session =
+            sql_shell::SqlSession::create(analytics_url, std::env::var("MICROMEGAS_API_KEY").ok())
+                .await?;
+        let result = watch::watch(&session, &sql, &options).await;
+        if let Err(e) = session.close().await {
+            eprintln!("closing sql session: {e:?}");
+        }
+        return result;
+    }
+
     if args.remote_db_url.is_none() {
         bail!("remote-db-url or local path has to be specified");
     }
@@ -60,6 +244,90 @@ async fn main() -> Result<()> {
         Commands::DeleteoldBlocks { min_days_old } => {
             delete_old_blocks(&mut connection, blob_storage, min_days_old).await?;
         }
+        Commands::DeleteDuplicateBlocks => {
+            delete_duplicate_blocks(&mut connection).await?;
+        }
+        Commands::AddAnnotation {
+            label,
+            begin,
+            end,
+            properties,
+        } => {
+            annotations::add_annotation(
+                &mut connection,
+                label,
+                &begin,
+                end.as_deref(),
+                &properties,
+            )
+            .await?;
+        }
+        Commands::UpdateAnnotation {
+            annotation_id,
+            label,
+            begin,
+            end,
+            properties,
+        } => {
+            annotations::edit_annotation(
+                &mut connection,
+                annotation_id,
+                label,
+                begin.as_deref(),
+                end.as_deref(),
+                &properties,
+            )
+            .await?;
+        }
+        Commands::DeleteAnnotation { annotation_id } => {
+            annotations::remove_annotation(&mut connection, annotation_id).await?;
+        }
+        Commands::ListAnnotations { begin, end, limit } => {
+            annotations::print_annotations(&mut connection, &begin, &end, limit).await?;
+        }
+        Commands::ImportUnrealInsights {
+            export_dir,
+            exe,
+            start_time,
+        } => {
+            let start_time = match start_time {
+                Some(text) => annotations::parse_time(&text)?,
+                None => chrono::Utc::now(),
+            };
+            let lake = DataLakeConnection::new(pool.clone(), blob_storage.clone());
+            unreal_import::import_unreal_insights(lake, &export_dir, exe, start_time).await?;
+        }
+        Commands::DumpMetadata { process_id, cbor } => {
+            dump_metadata::dump_metadata(&mut connection, process_id, cbor).await?;
+        }
+        Commands::Sql { .. } | Commands::Watch { .. } => {
+            unreachable!("handled before connecting to the data lake")
+        }
+        Commands::ForwardLogs {
+            name,
+            webhook_url,
+            level,
+            targets,
+            poll_interval_seconds,
+            settle_delay_seconds,
+        } => {
+            let max_level: Level = level
+                .parse()
+                .map_err(|_| anyhow::anyhow!("unknown level {level}"))?;
+            let lake = DataLakeConnection::new(pool.clone(), blob_storage.clone());
+            forward_logs::forward_logs(
+                lake,
+                &name,
+                &webhook_url,
+                LogExportFilter {
+                    max_level,
+                    target_prefixes: targets,
+                },
+                Duration::from_secs(poll_interval_seconds),
+                Duration::from_secs(settle_delay_seconds),
+            )
+            .await?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,284 @@
+//! Interactive sql shell over a sql session of the analytics server
+//!
+//! Authenticates with `MICROMEGAS_API_KEY` when it is set.
+//! Statements end with `;`, they can refer to the time range as `@query_begin` and `@query_end`.
+//! Commands of the shell:
+//!  - `\tables` : views and table functions of the session
+//!  - `\last 1h` : queries the last hour, `30m` and `2d` are also valid
+//!  - `\csv` and `\table` : output format
+//!  - `\q` : quit
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use datafusion::arrow::array::{Array, AsArray};
+use datafusion::arrow::csv::WriterBuilder;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use std::collections::BTreeMap;
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+const TABLES_SQL: &str = "SELECT name, description FROM describe_view() ORDER BY name";
+
+/// `10s`, `30m`, `1h` or `2d`
+pub fn parse_time_delta(text: &str) -> Result<Duration> {
+    let unit_index = text
+        .find(|c: char| !c.is_ascii_digit())
+        .with_context(|| format!("missing unit in time delta {text}"))?;
+    let (number, unit) = text.split_at(unit_index);
+    let number: i64 = number
+        .parse()
+        .with_context(|| format!("parsing time delta {text}"))?;
+    match unit {
+        "s" => Ok(Duration::seconds(number)),
+        "m" => Ok(Duration::minutes(number)),
+        "h" => Ok(Duration::hours(number)),
+        "d" => Ok(Duration::days(number)),
+        _ => anyhow::bail!("invalid time delta {text}, expected [number][s|m|h|d]"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Csv,
+}
+
+pub fn format_batches(batches: &[RecordBatch], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Table => Ok(pretty_format_batches(batches)?.to_string()),
+        OutputFormat::Csv => {
+            let mut buffer = vec![];
+            let mut writer = WriterBuilder::new().with_header(true).build(&mut buffer);
+            for batch in batches {
+                writer.write(batch)?;
+            }
+            drop(writer);
+            Ok(String::from_utf8(buffer)?)
+        }
+    }
+}
+
+/// Shows the output through `$PAGER`, `less` by default, when writing to a terminal
+fn display(output: &str, use_pager: bool) -> Result<()> {
+    if use_pager && std::io::stdout().is_terminal() {
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -FRSX".to_owned());
+        let mut words = pager.split_whitespace();
+        if let Some(program) = words.next() {
+            if let Ok(mut child) = Command::new(program)
+                .args(words)
+                .stdin(Stdio::piped())
+                .spawn()
+            {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // the user can quit the pager before reading everything
+                    let _ = stdin.write_all(output.as_bytes());
+                }
+                child.wait()?;
+                return Ok(());
+            }
+        }
+    }
+    println!("{output}");
+    Ok(())
+}
+
+pub struct SqlSession {
+    client: reqwest::Client,
+    analytics_url: String,
+    api_key: Option<String>,
+    session_id: String,
+}
+
+impl SqlSession {
+    async fn post(
+        &self,
+        route: &str,
+        args: &BTreeMap<&str, Option<String>>,
+    ) -> Result<Vec<RecordBatch>> {
+        let mut body = vec![];
+        ciborium::into_writer(args, &mut body).with_context(|| "encoding request")?;
+        let url = format!(
+            "{}/analytics/{route}",
+            self.analytics_url.trim_end_matches('/')
+        );
+        let mut request = self.client.post(&url).body(body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("posting to {url}"))?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!(
+                "{url} answered {status}: {}",
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let reader = ParquetRecordBatchReaderBuilder::try_new(response.bytes().await?)?.build()?;
+        Ok(reader.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    pub async fn create(analytics_url: String, api_key: Option<String>) -> Result<Self> {
+        let mut session = Self {
+            client: reqwest::Client::new(),
+            analytics_url,
+            api_key,
+            session_id: String::new(),
+        };
+        let batches = session
+            .post("create_sql_session", &BTreeMap::new())
+            .await
+            .with_context(|| "creating sql session")?;
+        session.session_id = batches
+            .first()
+            .and_then(|batch| batch.column_by_name("session_id"))
+            .and_then(|column| column.as_string_opt::<i32>())
+            .filter(|ids| !ids.is_empty())
+            .map(|ids| ids.value(0).to_owned())
+            .with_context(|| "reading session_id")?;
+        Ok(session)
+    }
+
+    pub async fn execute(
+        &self,
+        sql: &str,
+        range: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    ) -> Result<Vec<RecordBatch>> {
+        let mut args = BTreeMap::new();
+        args.insert("session_id", Some(self.session_id.clone()));
+        args.insert("sql", Some(sql.to_owned()));
+        args.insert("register_as", None);
+        args.insert("begin", range.map(|(begin, _)| begin.to_rfc3339()));
+        args.insert("end", range.map(|(_, end)| end.to_rfc3339()));
+        self.post("execute_sql", &args).await
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        let mut args = BTreeMap::new();
+        args.insert("session_id", Some(self.session_id.clone()));
+        self.post("close_sql_session", &args).await?;
+        Ok(())
+    }
+}
+
+pub struct SqlShellOptions {
+    pub analytics_url: String,
+    pub api_key: Option<String>,
+    /// queries the last `last` before each statement, the whole data otherwise
+    pub last: Option<Duration>,
+    pub format: OutputFormat,
+    pub use_pager: bool,
+}
+
+/// Runs `statement`, or the statements read from stdin when absent
+pub async fn sql_shell(options: SqlShellOptions, statement: Option<String>) -> Result<()> {
+    let session =
+        SqlSession::create(options.analytics_url.clone(), options.api_key.clone()).await?;
+    let result = match statement {
+        Some(sql) => {
+            run_statement(
+                &session,
+                &sql,
+                options.last,
+                options.format,
+                options.use_pager,
+            )
+            .await
+        }
+        None => repl(&session, options).await,
+    };
+    if let Err(e) = session.close().await {
+        eprintln!("closing sql session: {e:?}");
+    }
+    result
+}
+
+async fn run_statement(
+    session: &SqlSession,
+    sql: &str,
+    last: Option<Duration>,
+    format: OutputFormat,
+    use_pager: bool,
+) -> Result<()> {
+    let range = last.map(|last| {
+        let now = Utc::now();
+        (now - last, now)
+    });
+    let batches = session.execute(sql, range).await?;
+    display(&format_batches(&batches, format)?, use_pager)
+}
+
+async fn repl(session: &SqlSession, mut options: SqlShellOptions) -> Result<()> {
+    let interactive = std::io::stdin().is_terminal();
+    let mut statement = String::new();
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!(
+                "{}",
+                if statement.is_empty() {
+                    "sql> "
+                } else {
+                    "...> "
+                }
+            );
+            std::io::stdout().flush()?;
+        }
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let trimmed = line.trim();
+        if statement.is_empty() && trimmed.starts_with('\\') {
+            let (command, arg) = trimmed.split_once(' ').unwrap_or((trimmed, ""));
+            let result = match command {
+                "\\q" => break,
+                "\\tables" => {
+                    run_statement(session, TABLES_SQL, None, options.format, options.use_pager)
+                        .await
+                }
+                "\\last" => parse_time_delta(arg.trim()).map(|last| {
+                    options.last = Some(last);
+                }),
+                "\\csv" => {
+                    options.format = OutputFormat::Csv;
+                    Ok(())
+                }
+                "\\table" => {
+                    options.format = OutputFormat::Table;
+                    Ok(())
+                }
+                _ => Err(anyhow::anyhow!("unknown command {command}")),
+            };
+            if let Err(e) = result {
+                eprintln!("{e:?}");
+            }
+            continue;
+        }
+        statement.push_str(&line);
+        statement.push('\n');
+        if !trimmed.ends_with(';') {
+            continue;
+        }
+        let sql = std::mem::take(&mut statement);
+        let result = run_statement(
+            session,
+            sql.trim().trim_end_matches(';'),
+            options.last,
+            options.format,
+            options.use_pager,
+        )
+        .await;
+        if let Err(e) = result {
+            // the session stays usable after a failed statement
+            eprintln!("{e:?}");
+            if !interactive {
+                return Err(e);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,409 @@
+//! Offline import of Unreal Insights captures
+//!
+//! The .utrace files are read through the exports of Unreal Insights, which is the only
+//! reader that keeps up with the versions of the trace format:
+//! `TimingInsights.ExportThreads threads.csv`, `TimingInsights.ExportTimers timers.csv`,
+//! `TimingInsights.ExportTimingEvents timing_events.csv` and, for each counter,
+//! `TimingInsights.ExportCounterValues counters/<name>.csv`.
+//!
+//! The exported directory becomes a new process with one thread stream per cpu thread
+//! and a metrics stream for the counters. Insights exports times in seconds relative to the
+//! start of the capture, the process is given a tick frequency of 1GHz to keep them exact.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::web_ingestion_service::WebIngestionService;
+use micromegas_telemetry::ack_level::AckLevel;
+use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_telemetry_sink::stream_block::StreamBlock;
+use micromegas_telemetry_sink::stream_info::make_stream_info;
+use micromegas_tracing::event::{EventBlock, EventStream, ExtractDeps, TracingBlock};
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::metrics::{
+    make_metric_metadata, FloatMetricEvent, MetricMetadata, MetricsStream,
+};
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::{
+    BeginThreadNamedSpanEvent, EndThreadNamedSpanEvent, SpanLocation, ThreadStream,
+};
+use micromegas_tracing::stream_tags::StreamTag;
+use micromegas_transit::HeterogeneousQueue;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const BUFFER_SIZE: usize = 10 * 1024 * 1024;
+const TICKS_PER_SECOND: f64 = 1_000_000_000.0;
+
+static UNREAL_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Max,
+    target: "unreal",
+    module_path: "",
+    file: "",
+    line: 0,
+};
+
+/// Rows of a csv or tsv export, with the columns indexed by name
+struct Table {
+    columns: HashMap<String, usize>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn read(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let separator = if path.extension().is_some_and(|ext| ext == "tsv") {
+            '\t'
+        } else {
+            ','
+        };
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines
+            .next()
+            .with_context(|| format!("{} is empty", path.display()))?;
+        let columns = split_row(header, separator)
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| (name.trim().to_lowercase(), index))
+            .collect();
+        let rows = lines.map(|line| split_row(line, separator)).collect();
+        Ok(Self { columns, rows })
+    }
+
+    fn column(&self, name: &str) -> Result<usize> {
+        self.columns
+            .get(name)
+            .copied()
+            .with_context(|| format!("missing column {name}"))
+    }
+}
+
+/// Splits a line, honoring double quotes around values containing the separator
+fn split_row(line: &str, separator: char) -> Vec<String> {
+    let mut values = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => values.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    values.push(current);
+    values
+}
+
+fn parse_field<T: std::str::FromStr>(row: &[String], index: usize) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let text = row
+        .get(index)
+        .with_context(|| format!("missing field {index}"))?;
+    text.trim()
+        .parse()
+        .with_context(|| format!("parsing field {text}"))
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn seconds_to_ticks(seconds: f64) -> i64 {
+    (seconds * TICKS_PER_SECOND).round() as i64
+}
+
+/// Name of the counter and its (time, value) samples
+type Counter = (String, Vec<(i64, f64)>);
+
+#[derive(Debug)]
+struct TimingEvent {
+    begin: i64,
+    end: i64,
+    depth: u32,
+    name: &'static str,
+}
+
+/// Timing events by thread id
+fn read_timing_events(dir: &Path) -> Result<HashMap<u32, Vec<TimingEvent>>> {
+    let timers = Table::read(&dir.join("timers.csv")).with_context(|| "reading timers")?;
+    let timer_id = timers.column("id")?;
+    let timer_name = timers.column("name")?;
+    let mut timer_names = HashMap::new();
+    for row in &timers.rows {
+        let id: u32 = parse_field(row, timer_id)?;
+        let name = row.get(timer_name).map_or("", String::as_str);
+        timer_names.insert(id, intern_string(name));
+    }
+
+    let events =
+        Table::read(&dir.join("timing_events.csv")).with_context(|| "reading timing events")?;
+    let thread_column = events.column("threadid")?;
+    let timer_column = events.column("timerid")?;
+    let begin_column = events.column("starttime")?;
+    let end_column = events.column("endtime")?;
+    let depth_column = events.column("depth")?;
+    let mut threads: HashMap<u32, Vec<TimingEvent>> = HashMap::new();
+    for row in &events.rows {
+        let timer: u32 = parse_field(row, timer_column)?;
+        threads
+            .entry(parse_field(row, thread_column)?)
+            .or_default()
+            .push(TimingEvent {
+                begin: seconds_to_ticks(parse_field(row, begin_column)?),
+                end: seconds_to_ticks(parse_field(row, end_column)?),
+                depth: parse_field(row, depth_column)?,
+                name: timer_names.get(&timer).copied().unwrap_or("unknown"),
+            });
+    }
+    Ok(threads)
+}
+
+fn read_thread_names(dir: &Path) -> Result<HashMap<u32, String>> {
+    let path = dir.join("threads.csv");
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let threads = Table::read(&path).with_context(|| "reading threads")?;
+    let id_column = threads.column("id")?;
+    let name_column = threads.column("name")?;
+    threads
+        .rows
+        .iter()
+        .map(|row| {
+            Ok((
+                parse_field(row, id_column)?,
+                row.get(name_column).cloned().unwrap_or_default(),
+            ))
+        })
+        .collect()
+}
+
+/// (time, value) of each counter, named after the files of the counters directory
+fn read_counters(dir: &Path) -> Result<Vec<Counter>> {
+    let dir = dir.join("counters");
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut counters = vec![];
+    for entry in std::fs::read_dir(&dir).with_context(|| "listing counters")? {
+        let path = entry?.path();
+        let Some(name) = path.file_stem().map(|s| s.to_string_lossy().into_owned()) else {
+            continue;
+        };
+        let table = Table::read(&path)?;
+        let time_column = table.column("time")?;
+        let value_column = table.column("value")?;
+        let values = table
+            .rows
+            .iter()
+            .map(|row| {
+                Ok((
+                    seconds_to_ticks(parse_field(row, time_column)?),
+                    parse_field(row, value_column)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("reading counter {name}"))?;
+        counters.push((name, values));
+    }
+    Ok(counters)
+}
+
+/// Sends the blocks of imported streams, with the time range of their events
+struct Uploader {
+    service: WebIngestionService,
+    process_info: ProcessInfo,
+}
+
+impl Uploader {
+    fn dual_time(&self, ticks: i64) -> DualTime {
+        DualTime {
+            ticks,
+            time: self.process_info.start_time + Duration::nanoseconds(ticks),
+        }
+    }
+
+    async fn send_block<Q>(
+        &self,
+        stream: &mut EventStream<EventBlock<Q>>,
+        begin_ticks: i64,
+        end_ticks: i64,
+    ) -> Result<()>
+    where
+        Q: HeterogeneousQueue + ExtractDeps,
+        EventBlock<Q>: TracingBlock + StreamBlock,
+    {
+        if stream.is_empty() {
+            return Ok(());
+        }
+        let next_offset =
+            stream.get_block_ref().object_offset() + stream.get_block_ref().nb_objects();
+        let mut block = stream.replace_block(Arc::new(EventBlock::<Q>::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            stream.stream_id(),
+            next_offset,
+        )));
+        let block_mut = Arc::get_mut(&mut block).with_context(|| "block should not be shared")?;
+        block_mut.begin = self.dual_time(begin_ticks);
+        block_mut.end = Some(self.dual_time(end_ticks));
+        let encoded = block.encode_bin(&self.process_info)?;
+        self.service
+            .insert_block(encoded.into(), AckLevel::MetadataCommit)
+            .await
+            .with_context(|| "inserting block")
+    }
+
+    async fn import_thread(&self, name: &str, mut events: Vec<TimingEvent>) -> Result<()> {
+        events.sort_by_key(|event| (event.begin, event.depth));
+        let mut properties = HashMap::new();
+        properties.insert("thread-name".to_owned(), name.to_owned());
+        let mut stream = ThreadStream::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            &[StreamTag::Cpu.into()],
+            properties,
+        );
+        self.service
+            .insert_stream(encode_cbor(&make_stream_info(&stream))?.into())
+            .await
+            .with_context(|| "inserting thread stream")?;
+        let mut open: Vec<&TimingEvent> = vec![];
+        let mut block_begin = events.first().map_or(0, |e| e.begin);
+        let mut last_time = block_begin;
+        for event in &events {
+            // close the scopes that are not parents of this one
+            while let Some(parent) = open.last() {
+                if parent.depth < event.depth {
+                    break;
+                }
+                stream.get_events_mut().push(EndThreadNamedSpanEvent {
+                    thread_span_location: &UNREAL_SPAN_LOCATION,
+                    name: parent.name.into(),
+                    time: parent.end,
+                });
+                last_time = last_time.max(parent.end);
+                open.pop();
+            }
+            stream.get_events_mut().push(BeginThreadNamedSpanEvent {
+                thread_span_location: &UNREAL_SPAN_LOCATION,
+                name: event.name.into(),
+                time: event.begin,
+            });
+            last_time = last_time.max(event.begin);
+            open.push(event);
+            if stream.is_full() {
+                self.send_block(&mut stream, block_begin, last_time).await?;
+                block_begin = last_time;
+            }
+        }
+        while let Some(scope) = open.pop() {
+            stream.get_events_mut().push(EndThreadNamedSpanEvent {
+                thread_span_location: &UNREAL_SPAN_LOCATION,
+                name: scope.name.into(),
+                time: scope.end,
+            });
+            last_time = last_time.max(scope.end);
+        }
+        self.send_block(&mut stream, block_begin, last_time).await
+    }
+
+    async fn import_counters(&self, counters: Vec<Counter>) -> Result<()> {
+        let mut stream = MetricsStream::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            &[StreamTag::Metrics.into()],
+            HashMap::new(),
+        );
+        self.service
+            .insert_stream(encode_cbor(&make_stream_info(&stream))?.into())
+            .await
+            .with_context(|| "inserting metrics stream")?;
+        let mut values: Vec<(i64, &'static MetricMetadata, f64)> = vec![];
+        for (name, counter_values) in &counters {
+            let desc = make_metric_metadata(intern_string(name), "", "unreal");
+            values.extend(
+                counter_values
+                    .iter()
+                    .map(|(time, value)| (*time, desc, *value)),
+            );
+        }
+        values.sort_by_key(|(time, _desc, _value)| *time);
+        let mut block_begin = values.first().map_or(0, |v| v.0);
+        let mut last_time = block_begin;
+        for (time, desc, value) in values {
+            stream
+                .get_events_mut()
+                .push(FloatMetricEvent { desc, value, time });
+            last_time = time;
+            if stream.is_full() {
+                self.send_block(&mut stream, block_begin, last_time).await?;
+                block_begin = last_time;
+            }
+        }
+        self.send_block(&mut stream, block_begin, last_time).await
+    }
+}
+
+/// Imports an Unreal Insights export directory as a new process, returns its id
+pub async fn import_unreal_insights(
+    lake: DataLakeConnection,
+    dir: &Path,
+    exe: String,
+    start_time: DateTime<Utc>,
+) -> Result<uuid::Uuid> {
+    let threads = read_timing_events(dir)?;
+    let thread_names = read_thread_names(dir)?;
+    let counters = read_counters(dir)?;
+
+    let mut properties = HashMap::new();
+    properties.insert("imported-from".to_owned(), "unreal-insights".to_owned());
+    properties.insert("import-source".to_owned(), dir.display().to_string());
+    let process_info = ProcessInfo {
+        process_id: uuid::Uuid::new_v4(),
+        exe,
+        username: String::new(),
+        realname: String::new(),
+        computer: String::new(),
+        distro: String::new(),
+        cpu_brand: String::new(),
+        tsc_frequency: 1_000_000_000,
+        start_time,
+        start_ticks: 0,
+        parent_process_id: None,
+        properties,
+    };
+    let uploader = Uploader {
+        service: WebIngestionService::new(lake),
+        process_info,
+    };
+    uploader
+        .service
+        .insert_process(encode_cbor(&uploader.process_info)?.into())
+        .await
+        .with_context(|| "inserting process")?;
+
+    let nb_threads = threads.len();
+    for (thread_id, events) in threads {
+        let name = thread_names
+            .get(&thread_id)
+            .cloned()
+            .unwrap_or_else(|| format!("thread {thread_id}"));
+        uploader
+            .import_thread(&name, events)
+            .await
+            .with_context(|| format!("importing thread {name}"))?;
+    }
+    let nb_counters = counters.len();
+    if !counters.is_empty() {
+        uploader.import_counters(counters).await?;
+    }
+    let process_id = uploader.process_info.process_id;
+    println!("imported process {process_id}: {nb_threads} threads, {nb_counters} counters");
+    Ok(process_id)
+}
@@ -0,0 +1,126 @@
+//! Runs a query repeatedly and prints the rows that appeared or disappeared since the last run
+use crate::sql_shell::SqlSession;
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::array_value_to_string;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+pub struct WatchOptions {
+    pub interval: Duration,
+    /// range queried by each run, ending when it runs
+    pub last: Option<Duration>,
+    /// stops with an error as soon as the query returns rows
+    pub fail_on_rows: bool,
+}
+
+/// Rows formatted as ` | ` separated values, with the header first
+pub fn format_rows(batches: &[RecordBatch]) -> Result<(String, Vec<String>)> {
+    let header = batches
+        .first()
+        .map(|batch| {
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .collect::<Vec<_>>()
+                .join(" | ")
+        })
+        .unwrap_or_default();
+    let mut rows = vec![];
+    for batch in batches {
+        for row in 0..batch.num_rows() {
+            let values = batch
+                .columns()
+                .iter()
+                .map(|column| array_value_to_string(column, row))
+                .collect::<Result<Vec<_>, _>>()?;
+            rows.push(values.join(" | "));
+        }
+    }
+    Ok((header, rows))
+}
+
+/// Rows added and removed between two runs, duplicated rows are counted
+pub fn diff_rows<'a>(
+    previous: &'a [String],
+    current: &'a [String],
+) -> (Vec<&'a str>, Vec<&'a str>) {
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    for row in previous {
+        *counts.entry(row.as_str()).or_default() -= 1;
+    }
+    let mut added = vec![];
+    for row in current {
+        let count = counts.entry(row.as_str()).or_default();
+        *count += 1;
+        if *count > 0 {
+            added.push(row.as_str());
+        }
+    }
+    let mut removed = vec![];
+    for row in previous.iter().rev() {
+        let count = counts.entry(row.as_str()).or_default();
+        if *count < 0 {
+            *count += 1;
+            removed.push(row.as_str());
+        }
+    }
+    removed.reverse();
+    (added, removed)
+}
+
+/// Runs until interrupted, or until the query returns rows with `fail_on_rows`
+pub async fn watch(session: &SqlSession, sql: &str, options: &WatchOptions) -> Result<()> {
+    let colored = std::io::stdout().is_terminal();
+    let paint = |color: &str, line: String| {
+        if colored {
+            format!("{color}{line}{RESET}")
+        } else {
+            line
+        }
+    };
+    let interval = options.interval.to_std()?;
+    let mut previous: Option<Vec<String>> = None;
+    loop {
+        let now = Utc::now();
+        let range = options.last.map(|last| (now - last, now));
+        let batches = session.execute(sql, range).await?;
+        let (header, rows) = format_rows(&batches)?;
+        match &previous {
+            None => {
+                println!("{now} {} rows", rows.len());
+                println!("  {header}");
+                for row in &rows {
+                    println!("  {row}");
+                }
+            }
+            Some(previous) => {
+                let (added, removed) = diff_rows(previous, &rows);
+                println!(
+                    "{now} {} rows, {} added, {} removed",
+                    rows.len(),
+                    added.len(),
+                    removed.len()
+                );
+                for row in removed {
+                    println!("{}", paint(RED, format!("- {row}")));
+                }
+                for row in added {
+                    println!("{}", paint(GREEN, format!("+ {row}")));
+                }
+            }
+        }
+        if options.fail_on_rows && !rows.is_empty() {
+            anyhow::bail!("query returned {} rows", rows.len());
+        }
+        previous = Some(rows);
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -6,19 +6,29 @@
 //! Env variables:
 //!  - `MICROMEGAS_SQL_CONNECTION_STRING` : to connect to postgresql
 //!  - `MICROMEGAS_OBJECT_STORE_URI` : to write the payloads
+//!  - `MICROMEGAS_API_KEYS_FILE` or `MICROMEGAS_API_KEYS` : optional keyring, see `api_key_auth`
+//!  - `MICROMEGAS_CUSTOM_STREAM_TAGS` : optional comma-separated tags accepted besides the well-known ones
+//!  - `MICROMEGAS_CONFIG_FILE` : optional json file of the other settings, see `micromegas::config`
 
-use anyhow::{Context, Result};
-use axum::extract::DefaultBodyLimit;
-use axum::routing::post;
-use axum::Extension;
-use axum::Router;
+use anyhow::Result;
 use clap::Parser;
+use micromegas::config::ServerConfig;
+use micromegas::ingestion::block_spool::BlockSpool;
 use micromegas::ingestion::data_lake_connection::DataLakeConnection;
 use micromegas::ingestion::remote_data_lake::connect_to_remote_data_lake;
-use micromegas::ingestion::web_ingestion_service::WebIngestionService;
+use micromegas::ingestion::web_ingestion_service::{
+    WebIngestionService, DEFAULT_MAX_BACKGROUND_WRITES,
+};
+use micromegas::server_tls::{make_tls_acceptor, serve_tls, ServerTlsConfig};
+use micromegas::servers::api_key_auth::{ApiKeyAuthLayer, ApiKeyAuthProvider};
+use micromegas::servers::ingestion::ingestion_router;
+use micromegas::telemetry_sink::system_monitor::spawn_system_monitor;
 use micromegas::telemetry_sink::TelemetryGuardBuilder;
 use micromegas::tracing::prelude::*;
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tower_http::limit::RequestBodyLimitLayer;
 
 #[derive(Parser, Debug)]
@@ -27,59 +37,81 @@ use tower_http::limit::RequestBodyLimitLayer;
 struct Cli {
     #[clap(long, default_value = "127.0.0.1:8081")]
     listen_endpoint_http: SocketAddr,
-}
 
-async fn insert_process_request(
-    Extension(service): Extension<WebIngestionService>,
-    body: bytes::Bytes,
-) {
-    info!("insert_process_request");
-    if let Err(e) = service.insert_process(body).await {
-        error!("Error in insert_process_request: {:?}", e);
-    }
-}
+    /// rejects blocks ending further in the future than this, according to the server's clock
+    #[clap(long)]
+    max_clock_skew_seconds: Option<u64>,
 
-async fn insert_stream_request(
-    Extension(service): Extension<WebIngestionService>,
-    body: bytes::Bytes,
-) {
-    info!("insert_stream_request");
-    if let Err(e) = service.insert_stream(body).await {
-        error!("Error in insert_stream_request: {:?}", e);
-    }
+    /// serves https with this pem certificate chain
+    #[clap(long, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// pem private key of the certificate
+    #[clap(long, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+
+    /// requires clients to present a certificate signed by this pem certificate authority
+    #[clap(long, requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+
+    /// spools the metadata of the blocks in this directory when postgresql is unavailable,
+    /// it is replayed into the database when it recovers
+    #[clap(long)]
+    spool_directory: Option<PathBuf>,
+
+    /// writes continuing after their request was acknowledged, the clients wait beyond that
+    #[clap(long, default_value_t = DEFAULT_MAX_BACKGROUND_WRITES)]
+    max_background_writes: usize,
+
+    /// json settings, overridden by the environment variables, see `micromegas::config`
+    #[clap(long)]
+    config: Option<PathBuf>,
 }
 
-async fn insert_block_request(
-    Extension(service): Extension<WebIngestionService>,
-    body: bytes::Bytes,
-) {
-    if body.is_empty() {
-        error!("insert_block_request: empty body");
-        return;
-    }
-    if let Err(e) = service.insert_block(body).await {
-        error!("Error in insert_block_request: {:?}", e);
+impl Cli {
+    fn tls_config(&self) -> Option<ServerTlsConfig> {
+        Some(ServerTlsConfig {
+            cert: self.tls_cert.clone()?,
+            key: self.tls_key.clone()?,
+            client_ca: self.tls_client_ca.clone(),
+        })
     }
 }
 
 async fn serve_http(
     args: &Cli,
+    config: &ServerConfig,
     lake: DataLakeConnection,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let service = WebIngestionService::new(lake);
+    let mut service =
+        WebIngestionService::new(lake).with_max_background_writes(args.max_background_writes);
+    for tag in &config.custom_stream_tags {
+        service = service.with_custom_stream_tag(tag.clone());
+    }
+    if let Some(max_clock_skew_seconds) = args.max_clock_skew_seconds {
+        service = service.with_max_clock_skew(Duration::from_secs(max_clock_skew_seconds));
+    }
+    if let Some(spool_directory) = &args.spool_directory {
+        service = service.with_spool(Arc::new(BlockSpool::new(spool_directory.clone())?));
+        service.spawn_spool_replay_task(Duration::from_secs(10));
+    }
 
-    let app = Router::new()
-        .route("/ingestion/insert_process", post(insert_process_request))
-        .route("/ingestion/insert_stream", post(insert_stream_request))
-        .route("/ingestion/insert_block", post(insert_block_request))
-        .layer(DefaultBodyLimit::disable())
-        .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024))
-        .layer(Extension(service));
+    let mut app = ingestion_router(service).layer(RequestBodyLimitLayer::new(100 * 1024 * 1024));
+    if let Some(provider) = ApiKeyAuthProvider::from_env()? {
+        let provider = Arc::new(provider);
+        provider.spawn_reload_tasks(Duration::from_secs(10));
+        app = app.layer(ApiKeyAuthLayer::new(provider));
+    }
     let listener = tokio::net::TcpListener::bind(args.listen_endpoint_http)
         .await
         .unwrap();
-    info!("serving");
-    axum::serve(listener, app).await.unwrap();
+    if let Some(tls_config) = args.tls_config() {
+        info!("serving https");
+        serve_tls(listener, make_tls_acceptor(&tls_config)?, app).await?;
+    } else {
+        info!("serving");
+        axum::serve(listener, app).await.unwrap();
+    }
 
     Ok(())
 }
@@ -90,12 +122,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_ctrlc_handling()
         .with_local_sink_max_level(LevelFilter::Debug)
         .build();
+    spawn_system_monitor();
     let args = Cli::parse();
-    let connection_string = std::env::var("MICROMEGAS_SQL_CONNECTION_STRING")
-        .with_context(|| "reading MICROMEGAS_SQL_CONNECTION_STRING")?;
-    let object_store_uri = std::env::var("MICROMEGAS_OBJECT_STORE_URI")
-        .with_context(|| "reading MICROMEGAS_OBJECT_STORE_URI")?;
-    let data_lake = connect_to_remote_data_lake(&connection_string, &object_store_uri).await?;
-    serve_http(&args, data_lake).await?;
+    let config = ServerConfig::load(args.config.as_deref())?;
+    let data_lake =
+        connect_to_remote_data_lake(&config.sql_connection_string, &config.object_store_uri)
+            .await?;
+    serve_http(&args, &config, data_lake).await?;
     Ok(())
 }
@@ -0,0 +1,43 @@
+//! Spawning of child processes that report their telemetry linked to the current process
+use micromegas_tracing::dispatch::process_id;
+use std::process::{Child, Command};
+
+pub use micromegas_tracing::dispatch::parent_process_id;
+
+/// Process id of the parent, read by the dispatch of the child at startup
+pub const PARENT_PROCESS_ENV_VAR: &str = "MICROMEGAS_TELEMETRY_PARENT_PROCESS";
+/// Url of the ingestion server
+pub const TELEMETRY_URL_ENV_VAR: &str = "MICROMEGAS_TELEMETRY_URL";
+
+const PROPAGATED_ENV_PREFIX: &str = "MICROMEGAS_";
+
+/// Environment variables a child process needs to send its telemetry to the same place as the current process.
+///
+/// Includes every `MICROMEGAS_*` variable (url, keys, ...) and overrides the parent process id with the current one.
+pub fn telemetry_env_vars() -> Vec<(String, String)> {
+    let mut vars: Vec<(String, String)> = std::env::vars()
+        .filter(|(key, _value)| {
+            key.starts_with(PROPAGATED_ENV_PREFIX) && key != PARENT_PROCESS_ENV_VAR
+        })
+        .collect();
+    if let Some(id) = process_id() {
+        vars.push((PARENT_PROCESS_ENV_VAR.to_owned(), id.to_string()));
+    }
+    vars
+}
+
+pub trait CommandTelemetryExt {
+    /// Propagates the telemetry configuration, even if the environment of the command was cleared
+    fn with_telemetry_env(&mut self) -> &mut Self;
+}
+
+impl CommandTelemetryExt for Command {
+    fn with_telemetry_env(&mut self) -> &mut Self {
+        self.envs(telemetry_env_vars())
+    }
+}
+
+/// Spawns a child process linked to the current process in the telemetry
+pub fn spawn_child_process(command: &mut Command) -> std::io::Result<Child> {
+    command.with_telemetry_env().spawn()
+}
@@ -0,0 +1,43 @@
+//! Tls settings of the http clients, read from the environment
+//!
+//!  - `MICROMEGAS_TLS_CA_CERT` : pem of an additional certificate authority trusted to sign the server's certificate
+//!  - `MICROMEGAS_TLS_CLIENT_CERT` and `MICROMEGAS_TLS_CLIENT_KEY` : pem of the certificate and
+//!    pkcs8 private key presented to servers requiring client certificates
+use anyhow::{Context, Result};
+
+pub const CA_CERT_ENV_VAR: &str = "MICROMEGAS_TLS_CA_CERT";
+pub const CLIENT_CERT_ENV_VAR: &str = "MICROMEGAS_TLS_CLIENT_CERT";
+pub const CLIENT_KEY_ENV_VAR: &str = "MICROMEGAS_TLS_CLIENT_KEY";
+
+fn read_env_file(var_name: &str) -> Result<Option<Vec<u8>>> {
+    match std::env::var(var_name) {
+        Ok(path) => Ok(Some(
+            std::fs::read(&path).with_context(|| format!("reading {var_name}={path}"))?,
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Adds the certificate authority and client identity of the environment to the client
+pub fn configure_client_tls(mut builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder> {
+    if let Some(ca_pem) = read_env_file(CA_CERT_ENV_VAR)? {
+        builder = builder.add_root_certificate(
+            reqwest::Certificate::from_pem(&ca_pem)
+                .with_context(|| format!("parsing {CA_CERT_ENV_VAR}"))?,
+        );
+    }
+    match (
+        read_env_file(CLIENT_CERT_ENV_VAR)?,
+        read_env_file(CLIENT_KEY_ENV_VAR)?,
+    ) {
+        (Some(cert_pem), Some(key_pem)) => {
+            builder = builder.identity(
+                reqwest::Identity::from_pkcs8_pem(&cert_pem, &key_pem)
+                    .with_context(|| "parsing client certificate")?,
+            );
+        }
+        (None, None) => {}
+        _ => anyhow::bail!("{CLIENT_CERT_ENV_VAR} and {CLIENT_KEY_ENV_VAR} go together"),
+    }
+    Ok(builder)
+}
@@ -1,5 +1,5 @@
 use micromegas_tracing::{
-    event::{BoxedEventSink, EventSink},
+    event::{BoxedEventSink, EventSink, ProcessAttachment},
     logs::{LogBlock, LogMetadata, LogStream},
     metrics::{MetricsBlock, MetricsStream},
     prelude::*,
@@ -141,6 +141,24 @@ impl EventSink for CompositeSink {
             .for_each(|(_, sink)| sink.on_process_thread_block(old_event_block.clone()));
     }
 
+    fn on_attachment(&self, attachment: Arc<ProcessAttachment>) {
+        self.sinks
+            .iter()
+            .for_each(|(_, sink)| sink.on_attachment(attachment.clone()));
+    }
+
+    fn on_process_properties_update(&self, update: Arc<ProcessPropertiesUpdate>) {
+        self.sinks
+            .iter()
+            .for_each(|(_, sink)| sink.on_process_properties_update(update.clone()));
+    }
+
+    fn on_process_exit(&self, process_exit: Arc<ProcessExit>) {
+        self.sinks
+            .iter()
+            .for_each(|(_, sink)| sink.on_process_exit(process_exit.clone()));
+    }
+
     fn is_busy(&self) -> bool {
         for (_, sink) in &self.sinks {
             if sink.is_busy() {
@@ -1,24 +1,28 @@
 use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use micromegas_telemetry::ack_level::{AckLevel, ACK_LEVEL_HEADER};
+use micromegas_telemetry::attachment::{ATTACHMENT_NAME_HEADER, ATTACHMENT_PROCESS_ID_HEADER};
+use micromegas_telemetry::compression::{CompressionCodec, COMPRESSION_PROPERTY};
+use micromegas_telemetry::loss_report::LossReport;
 use micromegas_telemetry::stream_info::StreamInfo;
-use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_telemetry::wire_format::{
+    encode_cbor, WIRE_FORMAT_VERSION, WIRE_FORMAT_VERSION_PROPERTY,
+};
 use micromegas_tracing::{
-    event::EventSink,
+    event::{EventBlock, EventSink, ExtractDeps, ProcessAttachment, TracingBlock},
     flush_monitor::FlushMonitor,
     logs::{LogBlock, LogMetadata, LogStream},
     metrics::{MetricsBlock, MetricsStream},
     prelude::*,
     spans::{ThreadBlock, ThreadStream},
 };
-use std::{
-    cmp::max,
-    fmt,
-    sync::{Arc, Mutex},
-};
+use std::{cmp::max, collections::HashMap, fmt, sync::Arc};
 use std::{
     sync::atomic::{AtomicIsize, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use crate::client_tls::configure_client_tls;
 use crate::request_decorator::RequestDecorator;
 use crate::stream_block::StreamBlock;
 use crate::stream_info::make_stream_info;
@@ -30,19 +34,205 @@ enum SinkEvent {
     ProcessLogBlock(Arc<LogBlock>),
     ProcessMetricsBlock(Arc<MetricsBlock>),
     ProcessThreadBlock(Arc<ThreadBlock>),
+    Attachment(Arc<ProcessAttachment>),
+    ProcessPropertiesUpdate(Arc<ProcessPropertiesUpdate>),
+    ProcessExit(Arc<ProcessExit>),
+}
+
+impl SinkEvent {
+    /// Block to send, with the ack level and codec of its kind
+    fn as_block<'a>(
+        &'a self,
+        ack_levels: &BlockAckLevels,
+        codecs: &'a BlockCodecs,
+    ) -> Option<(&'a dyn StreamBlock, AckLevel, &'a CompressionCodec)> {
+        match self {
+            Self::ProcessLogBlock(block) => Some((&**block, ack_levels.logs, &codecs.logs)),
+            Self::ProcessMetricsBlock(block) => {
+                Some((&**block, ack_levels.metrics, &codecs.metrics))
+            }
+            Self::ProcessThreadBlock(block) => {
+                Some((&**block, ack_levels.threads, &codecs.threads))
+            }
+            _ => None,
+        }
+    }
+
+    /// Opening and closing times of the block, the closing time being when it was handed to the sink
+    fn block_times(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        fn times<Q>(block: &EventBlock<Q>) -> (DateTime<Utc>, DateTime<Utc>)
+        where
+            Q: micromegas_transit::HeterogeneousQueue + ExtractDeps,
+        {
+            let begin = block.begin.time;
+            (begin, block.end.as_ref().map_or(begin, |end| end.time))
+        }
+        match self {
+            Self::ProcessLogBlock(block) => Some(times(block)),
+            Self::ProcessMetricsBlock(block) => Some(times(block)),
+            Self::ProcessThreadBlock(block) => Some(times(block)),
+            _ => None,
+        }
+    }
+
+    /// Measures how long the block took to get to the sender thread
+    ///
+    /// Recorded when the block leaves the queue: the handoff itself happens while the stream of
+    /// the block is locked, where recording a metric could deadlock.
+    fn record_block_latency(&self) {
+        if let Some((begin, end)) = self.block_times() {
+            // the longest an event waited in the block before the block was flushed
+            imetric!("sink_block_flush_delay", "ns", nanoseconds(end - begin));
+            imetric!("sink_queue_latency", "ns", nanoseconds(Utc::now() - end));
+        }
+    }
+
+    fn record_loss(&self, losses: &mut Losses) {
+        match self {
+            Self::ProcessLogBlock(block) => losses.record(block),
+            Self::ProcessMetricsBlock(block) => losses.record(block),
+            Self::ProcessThreadBlock(block) => losses.record(block),
+            _ => {}
+        }
+    }
+}
+
+fn nanoseconds(delay: chrono::TimeDelta) -> u64 {
+    delay
+        .num_nanoseconds()
+        .and_then(|nanos| u64::try_from(nanos).ok())
+        .unwrap_or_default()
+}
+
+/// Minimum delay between two loss reports, the last one is sent when the sink shuts down
+const LOSS_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Blocks dropped since the last loss report, by stream
+#[derive(Debug)]
+struct Losses {
+    reports: HashMap<uuid::Uuid, LossReport>,
+    last_report: Instant,
+}
+
+impl Losses {
+    fn new() -> Self {
+        Self {
+            reports: HashMap::new(),
+            last_report: Instant::now(),
+        }
+    }
+
+    fn record<Q>(&mut self, block: &EventBlock<Q>)
+    where
+        Q: micromegas_transit::HeterogeneousQueue + ExtractDeps,
+    {
+        let begin_time = block.begin.time;
+        let end_time = block.end.as_ref().map_or(begin_time, |end| end.time);
+        let nb_events = block.nb_objects() as i64;
+        self.reports
+            .entry(block.stream_id)
+            .and_modify(|report| report.add_block(begin_time, end_time, nb_events))
+            .or_insert_with(|| {
+                LossReport::new(
+                    block.process_id,
+                    block.stream_id,
+                    begin_time,
+                    end_time,
+                    nb_events,
+                )
+            });
+    }
+
+    /// Reports accumulated since the last call, if it's time to send them
+    fn take_reports(&mut self, force: bool) -> Option<Vec<LossReport>> {
+        if self.reports.is_empty() || (!force && self.last_report.elapsed() < LOSS_REPORT_INTERVAL)
+        {
+            return None;
+        }
+        self.last_report = Instant::now();
+        Some(
+            self.reports
+                .drain()
+                .map(|(_stream_id, report)| report)
+                .collect(),
+        )
+    }
+}
+
+/// Ack level requested from the ingestion service for each kind of block
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockAckLevels {
+    pub logs: AckLevel,
+    pub metrics: AckLevel,
+    pub threads: AckLevel,
+}
+
+/// Compression of the payloads of each kind of block, declared in the properties of the streams
+#[derive(Debug, Default, Clone)]
+pub struct BlockCodecs {
+    pub logs: CompressionCodec,
+    pub metrics: CompressionCodec,
+    pub threads: CompressionCodec,
+}
+
+/// Retry, acknowledgment and compression settings of the requests sent by the sink
+#[derive(Debug, Clone)]
+pub struct HttpEventSinkConfig {
+    /// retries of the requests sending the process, streams and blocks
+    pub metadata_retry: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+    pub ack_levels: BlockAckLevels,
+    pub codecs: BlockCodecs,
+    /// blocks packed in a single request when they are waiting in the queue
+    pub max_blocks_per_request: usize,
+}
+
+impl Default for HttpEventSinkConfig {
+    fn default() -> Self {
+        Self {
+            metadata_retry: tokio_retry::strategy::ExponentialBackoff::from_millis(10).take(3),
+            ack_levels: BlockAckLevels::default(),
+            codecs: BlockCodecs::default(),
+            max_blocks_per_request: 1,
+        }
+    }
+}
+
+/// How the background thread of the sink drives its http requests
+///
+/// The sink never needs a runtime from the application: the requests are sent
+/// from a dedicated std thread that owns its own tokio runtime.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SenderRuntime {
+    /// multi-threaded runtime, with its own pool of worker threads
+    #[default]
+    MultiThread,
+    /// single-threaded runtime driven by the sender thread itself, no other thread is spawned:
+    /// suited to game engines and tools with their own schedulers
+    CurrentThread,
+}
+
+impl SenderRuntime {
+    fn build(self) -> std::io::Result<tokio::runtime::Runtime> {
+        match self {
+            Self::MultiThread => tokio::runtime::Runtime::new(),
+            Self::CurrentThread => tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build(),
+        }
+    }
 }
 
 pub struct HttpEventSink {
     thread: Option<std::thread::JoinHandle<()>>,
-    // TODO: simplify this?
-    sender: Mutex<Option<std::sync::mpsc::Sender<SinkEvent>>>,
+    // shared by the threads flushing their blocks: sending does not lock, taken on drop
+    sender: Option<std::sync::mpsc::Sender<SinkEvent>>,
     queue_size: Arc<AtomicIsize>,
+    codecs: BlockCodecs,
 }
 
 impl Drop for HttpEventSink {
     fn drop(&mut self) {
-        let mut sender_guard = self.sender.lock().unwrap();
-        *sender_guard = None;
+        self.sender = None;
         if let Some(handle) = self.thread.take() {
             handle.join().expect("Error joining telemetry thread");
         }
@@ -53,13 +243,15 @@ impl HttpEventSink {
     pub fn new(
         addr_server: &str,
         max_queue_size: isize,
-        metadata_retry: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        config: HttpEventSinkConfig,
+        sender_runtime: SenderRuntime,
         make_decorator: Box<dyn FnOnce() -> Arc<dyn RequestDecorator> + Send>,
     ) -> Self {
         let addr = addr_server.to_owned();
         let (sender, receiver) = std::sync::mpsc::channel::<SinkEvent>();
         let queue_size = Arc::new(AtomicIsize::new(0));
         let thread_queue_size = queue_size.clone();
+        let codecs = config.codecs.clone();
         Self {
             thread: Some(std::thread::spawn(move || {
                 Self::thread_proc(
@@ -67,18 +259,30 @@ impl HttpEventSink {
                     receiver,
                     thread_queue_size,
                     max_queue_size,
-                    metadata_retry,
+                    config,
+                    sender_runtime,
                     make_decorator,
                 );
             })),
-            sender: Mutex::new(Some(sender)),
+            sender: Some(sender),
             queue_size,
+            codecs,
         }
     }
 
+    fn init_stream(&self, mut stream_info: StreamInfo, codec: &CompressionCodec) {
+        stream_info
+            .properties
+            .insert(COMPRESSION_PROPERTY.to_owned(), codec.name().to_owned());
+        stream_info.properties.insert(
+            WIRE_FORMAT_VERSION_PROPERTY.to_owned(),
+            WIRE_FORMAT_VERSION.to_string(),
+        );
+        self.send(SinkEvent::InitStream(Arc::new(stream_info)));
+    }
+
     fn send(&self, event: SinkEvent) {
-        let guard = self.sender.lock().unwrap();
-        if let Some(sender) = guard.as_ref() {
+        if let Some(sender) = &self.sender {
             self.queue_size.fetch_add(1, Ordering::Relaxed);
             if let Err(e) = sender.send(event) {
                 self.queue_size.fetch_sub(1, Ordering::Relaxed);
@@ -116,6 +320,64 @@ impl HttpEventSink {
         Ok(())
     }
 
+    async fn push_process_exit(
+        client: &mut reqwest::Client,
+        root_path: &str,
+        process_exit: Arc<ProcessExit>,
+        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        decorator: &dyn RequestDecorator,
+    ) -> Result<()> {
+        debug!("sending process exit {process_exit:?}");
+        let url = format!("{root_path}/ingestion/insert_process_exit");
+        tokio_retry::Retry::start(retry_strategy, || async {
+            let body = encode_cbor(&*process_exit)?;
+            let mut request = client.post(&url).body(body).build()?;
+            decorator
+                .decorate(&mut request)
+                .await
+                .with_context(|| "decorating request")?;
+            let result = client
+                .execute(request)
+                .await
+                .with_context(|| "executing request");
+            if let Err(e) = &result {
+                debug!("insert_process_exit error: {e:?}");
+            }
+            result
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn push_process_properties_update(
+        client: &mut reqwest::Client,
+        root_path: &str,
+        update: Arc<ProcessPropertiesUpdate>,
+        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        decorator: &dyn RequestDecorator,
+    ) -> Result<()> {
+        debug!("sending process properties update {update:?}");
+        let url = format!("{root_path}/ingestion/insert_process_properties_update");
+        tokio_retry::Retry::start(retry_strategy, || async {
+            let body = encode_cbor(&*update)?;
+            let mut request = client.post(&url).body(body).build()?;
+            decorator
+                .decorate(&mut request)
+                .await
+                .with_context(|| "decorating request")?;
+            let result = client
+                .execute(request)
+                .await
+                .with_context(|| "executing request");
+            if let Err(e) = &result {
+                debug!("insert_process_properties_update error: {e:?}");
+            }
+            result
+        })
+        .await?;
+        Ok(())
+    }
+
     async fn push_stream(
         client: &mut reqwest::Client,
         root_path: &str,
@@ -124,7 +386,7 @@ impl HttpEventSink {
         decorator: &dyn RequestDecorator,
     ) -> Result<()> {
         let url = format!("{root_path}/ingestion/insert_stream");
-        tokio_retry::Retry::spawn(retry_strategy, || async {
+        let response = tokio_retry::Retry::spawn(retry_strategy, || async {
             let body = encode_cbor(&*stream_info)?;
             let mut request = client.post(&url).body(body).build()?;
             decorator.decorate(&mut request).await?;
@@ -138,55 +400,189 @@ impl HttpEventSink {
             result
         })
         .await?;
+        // a rejected stream is not retried, i.e. when the server is older than the wire format
+        if response.status() == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            anyhow::bail!(
+                "insert_stream rejected: {}",
+                response.text().await.unwrap_or_default()
+            );
+        }
         Ok(())
     }
 
-    async fn push_block(
+    async fn push_loss_reports(
         client: &mut reqwest::Client,
         root_path: &str,
-        buffer: &dyn StreamBlock,
-        current_queue_size: &AtomicIsize,
-        max_queue_size: isize,
+        reports: Vec<LossReport>,
+        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        decorator: &dyn RequestDecorator,
+    ) -> Result<()> {
+        debug!("sending {} loss reports", reports.len());
+        let url = format!("{root_path}/ingestion/insert_loss_reports");
+        tokio_retry::Retry::start(retry_strategy, || async {
+            let body = encode_cbor(&reports)?;
+            let mut request = client.post(&url).body(body).build()?;
+            decorator
+                .decorate(&mut request)
+                .await
+                .with_context(|| "decorating request")?;
+            let result = client
+                .execute(request)
+                .await
+                .with_context(|| "executing request")
+                .and_then(|response| {
+                    response
+                        .error_for_status()
+                        .with_context(|| "insert_loss_reports rejected")
+                });
+            if let Err(e) = &result {
+                debug!("insert_loss_reports error: {e:?}");
+            }
+            result
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Blocks packed in a single request are sent to `insert_blocks`, a lone block to `insert_block`
+    async fn push_blocks(
+        client: &mut reqwest::Client,
+        root_path: &str,
+        batch: &[SinkEvent],
+        ack_levels: &BlockAckLevels,
+        codecs: &BlockCodecs,
         decorator: &dyn RequestDecorator,
         process_info: &ProcessInfo,
     ) -> Result<()> {
-        debug!("push_block");
-        if current_queue_size.load(Ordering::Relaxed) >= max_queue_size {
-            // could be better to have a budget for each block type
-            // this way thread data would not starve the other streams
-            debug!("dropping data, queue over max_queue_size");
-            return Ok(());
+        debug!("push_blocks");
+        let mut ack_level = AckLevel::default();
+        let mut body = vec![];
+        let serialization_begin = Instant::now();
+        // the cbor-encoded blocks are concatenated into a cbor sequence
+        for event in batch {
+            if let Some((block, block_ack_level, codec)) = event.as_block(ack_levels, codecs) {
+                ack_level = block_ack_level;
+                body.append(&mut block.encode_bin_with_codec(process_info, codec)?);
+            }
         }
-        let encoded_block = buffer.encode_bin(process_info)?;
+        imetric!(
+            "sink_serialization_duration",
+            "ns",
+            u64::try_from(serialization_begin.elapsed().as_nanos()).unwrap_or(u64::MAX)
+        );
+        imetric!("sink_request_size", "bytes", body.len() as u64);
+        let route = if batch.len() > 1 {
+            "insert_blocks"
+        } else {
+            "insert_block"
+        };
         let mut request = client
-            .post(format!("{root_path}/ingestion/insert_block"))
-            .body(encoded_block)
+            .post(format!("{root_path}/ingestion/{route}"))
+            .header(ACK_LEVEL_HEADER, ack_level.as_str())
+            .body(body)
             .build()
             .with_context(|| "building request")?;
         decorator
             .decorate(&mut request)
             .await
             .with_context(|| "decorating request")?;
-        debug!("push_block: executing request");
-        client
+        debug!("push_blocks: executing request");
+        let send_begin = Instant::now();
+        let response = client
             .execute(request)
             .await
-            .with_context(|| "executing request")?;
+            .with_context(|| "executing request");
+        imetric!(
+            "sink_send_duration",
+            "ns",
+            u64::try_from(send_begin.elapsed().as_nanos()).unwrap_or(u64::MAX)
+        );
+        response?
+            .error_for_status()
+            .with_context(|| format!("{route} rejected"))?;
+        Ok(())
+    }
+
+    async fn push_attachment(
+        client: &mut reqwest::Client,
+        root_path: &str,
+        attachment: &ProcessAttachment,
+        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        decorator: &dyn RequestDecorator,
+        process_info: &ProcessInfo,
+    ) -> Result<()> {
+        debug!("sending attachment {}", attachment.name);
+        let url = format!("{root_path}/ingestion/insert_attachment");
+        tokio_retry::Retry::start(retry_strategy, || async {
+            let mut request = client
+                .post(&url)
+                .header(
+                    ATTACHMENT_PROCESS_ID_HEADER,
+                    process_info.process_id.to_string(),
+                )
+                .header(ATTACHMENT_NAME_HEADER, &attachment.name)
+                .header(reqwest::header::CONTENT_TYPE, &attachment.content_type)
+                .body(attachment.data.clone())
+                .build()?;
+            decorator
+                .decorate(&mut request)
+                .await
+                .with_context(|| "decorating request")?;
+            let result = client
+                .execute(request)
+                .await
+                .with_context(|| "executing request")
+                .and_then(|response| {
+                    response
+                        .error_for_status()
+                        .with_context(|| "insert_attachment rejected")
+                });
+            if let Err(e) = &result {
+                debug!("insert_attachment error: {e:?}");
+            }
+            result
+        })
+        .await?;
         Ok(())
     }
 
+    /// Sends the accumulated loss reports, unless one was sent recently and `force` is false
+    async fn report_losses(
+        client: &mut reqwest::Client,
+        root_path: &str,
+        losses: &mut Losses,
+        force: bool,
+        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        decorator: &dyn RequestDecorator,
+    ) {
+        if let Some(reports) = losses.take_reports(force) {
+            if let Err(e) =
+                Self::push_loss_reports(client, root_path, reports, retry_strategy, decorator).await
+            {
+                error!("error sending loss reports: {e:?}");
+            }
+        }
+    }
+
     async fn thread_proc_impl(
         addr: String,
         receiver: std::sync::mpsc::Receiver<SinkEvent>,
         queue_size: Arc<AtomicIsize>,
         max_queue_size: isize,
-        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        config: HttpEventSinkConfig,
         decorator: &dyn RequestDecorator,
     ) {
+        let HttpEventSinkConfig {
+            metadata_retry: retry_strategy,
+            ack_levels,
+            codecs,
+            max_blocks_per_request,
+        } = config;
         let mut opt_process_info = None;
-        let client_res = reqwest::Client::builder()
-            .pool_idle_timeout(Some(core::time::Duration::from_secs(2)))
-            .build();
+        let client_res = configure_client_tls(
+            reqwest::Client::builder().pool_idle_timeout(Some(core::time::Duration::from_secs(2))),
+        )
+        .and_then(|builder| Ok(builder.build()?));
         if let Err(e) = client_res {
             error!("Error creating http client: {e:?}");
             return;
@@ -205,9 +601,17 @@ impl HttpEventSink {
             );
         }
         let flusher = FlushMonitor::default();
+        // event received while packing blocks, to process before the queue
+        let mut pending = None;
+        let mut losses = Losses::new();
         loop {
             let timeout = max(0, flusher.time_to_flush_seconds());
-            match receiver.recv_timeout(Duration::from_secs(timeout as u64)) {
+            let received = match pending.take() {
+                Some(message) => Ok(message),
+                None => receiver.recv_timeout(Duration::from_secs(timeout as u64)),
+            };
+            let mut nb_messages = 1;
+            match received {
                 Ok(message) => match message {
                     SinkEvent::Startup(process_info) => {
                         opt_process_info = Some(process_info.clone());
@@ -236,61 +640,120 @@ impl HttpEventSink {
                             error!("error sending stream: {e:?}");
                         }
                     }
-                    SinkEvent::ProcessLogBlock(buffer) => {
-                        if let Some(process_info) = &opt_process_info {
-                            if let Err(e) = Self::push_block(
-                                &mut client,
-                                &addr,
-                                &*buffer,
-                                &queue_size,
-                                max_queue_size,
-                                decorator,
-                                process_info,
-                            )
-                            .await
-                            {
-                                error!("error sending log block: {e:?}");
+                    SinkEvent::ProcessLogBlock(_)
+                    | SinkEvent::ProcessMetricsBlock(_)
+                    | SinkEvent::ProcessThreadBlock(_) => {
+                        // packs the blocks already in queue that share the ack level of this one
+                        let ack_level = message
+                            .as_block(&ack_levels, &codecs)
+                            .map(|(_block, ack_level, _codec)| ack_level);
+                        let mut batch = vec![message];
+                        while batch.len() < max_blocks_per_request {
+                            match receiver.try_recv() {
+                                Ok(next)
+                                    if next
+                                        .as_block(&ack_levels, &codecs)
+                                        .map(|(_block, ack_level, _codec)| ack_level)
+                                        == ack_level =>
+                                {
+                                    batch.push(next);
+                                }
+                                Ok(next) => {
+                                    pending = Some(next);
+                                    break;
+                                }
+                                Err(_) => break,
                             }
-                        } else {
-                            error!("trying to send blocks before Startup message");
                         }
-                    }
-                    SinkEvent::ProcessMetricsBlock(buffer) => {
-                        if let Some(process_info) = &opt_process_info {
-                            if let Err(e) = Self::push_block(
+                        nb_messages = batch.len();
+                        for event in &batch {
+                            event.record_block_latency();
+                        }
+                        let sent = if queue_size.load(Ordering::Relaxed) >= max_queue_size {
+                            // could be better to have a budget for each block type
+                            // this way thread data would not starve the other streams
+                            debug!("dropping data, queue over max_queue_size");
+                            false
+                        } else if let Some(process_info) = &opt_process_info {
+                            match Self::push_blocks(
                                 &mut client,
                                 &addr,
-                                &*buffer,
-                                &queue_size,
-                                max_queue_size,
+                                &batch,
+                                &ack_levels,
+                                &codecs,
                                 decorator,
                                 process_info,
                             )
                             .await
                             {
-                                error!("error sending metrics block: {e:?}");
+                                Ok(()) => true,
+                                Err(e) => {
+                                    error!("error sending blocks: {e:?}");
+                                    false
+                                }
                             }
                         } else {
                             error!("trying to send blocks before Startup message");
+                            false
+                        };
+                        if !sent {
+                            for event in &batch {
+                                event.record_loss(&mut losses);
+                            }
                         }
                     }
-                    SinkEvent::ProcessThreadBlock(buffer) => {
+                    SinkEvent::Attachment(attachment) => {
                         if let Some(process_info) = &opt_process_info {
-                            if let Err(e) = Self::push_block(
+                            if let Err(e) = Self::push_attachment(
                                 &mut client,
                                 &addr,
-                                &*buffer,
-                                &queue_size,
-                                max_queue_size,
+                                &attachment,
+                                retry_strategy.clone(),
                                 decorator,
                                 process_info,
                             )
                             .await
                             {
-                                error!("error sending thread block: {e:?}");
+                                error!("error sending attachment: {e:?}");
                             }
                         } else {
-                            error!("trying to send blocks before Startup message");
+                            error!("trying to send an attachment before Startup message");
+                        }
+                    }
+                    SinkEvent::ProcessPropertiesUpdate(update) => {
+                        if let Err(e) = Self::push_process_properties_update(
+                            &mut client,
+                            &addr,
+                            update,
+                            retry_strategy.clone(),
+                            decorator,
+                        )
+                        .await
+                        {
+                            error!("error sending process properties update: {e:?}");
+                        }
+                    }
+                    SinkEvent::ProcessExit(process_exit) => {
+                        // the last report must reach the lake while the process is still known to be alive
+                        Self::report_losses(
+                            &mut client,
+                            &addr,
+                            &mut losses,
+                            true,
+                            retry_strategy.clone(),
+                            decorator,
+                        )
+                        .await;
+                        if let Err(e) = Self::push_process_exit(
+                            &mut client,
+                            &addr,
+                            process_exit,
+                            retry_strategy.clone(),
+                            decorator,
+                        )
+                        .await
+                        {
+                            error!("error sending process exit: {e:?}");
                         }
                     }
                 },
@@ -300,10 +763,28 @@ impl HttpEventSink {
                 Err(_e) => {
                     // can only fail when the sending half is disconnected
                     // println!("Error in telemetry thread: {}", e);
+                    Self::report_losses(
+                        &mut client,
+                        &addr,
+                        &mut losses,
+                        true,
+                        retry_strategy.clone(),
+                        decorator,
+                    )
+                    .await;
                     return;
                 }
             }
-            queue_size.fetch_sub(1, Ordering::Relaxed);
+            queue_size.fetch_sub(nb_messages as isize, Ordering::Relaxed);
+            Self::report_losses(
+                &mut client,
+                &addr,
+                &mut losses,
+                false,
+                retry_strategy.clone(),
+                decorator,
+            )
+            .await;
         }
     }
 
@@ -313,18 +794,24 @@ impl HttpEventSink {
         receiver: std::sync::mpsc::Receiver<SinkEvent>,
         queue_size: Arc<AtomicIsize>,
         max_queue_size: isize,
-        retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
+        config: HttpEventSinkConfig,
+        sender_runtime: SenderRuntime,
         make_decorator: Box<dyn FnOnce() -> Arc<dyn RequestDecorator> + Send>,
     ) {
-        // TODO: add runtime as configuration option (or create one only if global don't exist)
-        let tokio_runtime = tokio::runtime::Runtime::new().unwrap();
+        let tokio_runtime = match sender_runtime.build() {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                error!("Error creating telemetry runtime: {e:?}");
+                return;
+            }
+        };
         let decorator = make_decorator();
         tokio_runtime.block_on(Self::thread_proc_impl(
             addr,
             receiver,
             queue_size,
             max_queue_size,
-            retry_strategy,
+            config,
             decorator.as_ref(),
         ));
     }
@@ -347,9 +834,7 @@ impl EventSink for HttpEventSink {
     fn on_log(&self, _metadata: &LogMetadata, _time: i64, _args: fmt::Arguments<'_>) {}
 
     fn on_init_log_stream(&self, log_stream: &LogStream) {
-        self.send(SinkEvent::InitStream(Arc::new(make_stream_info(
-            log_stream,
-        ))));
+        self.init_stream(make_stream_info(log_stream), &self.codecs.logs);
     }
 
     fn on_process_log_block(&self, log_block: Arc<LogBlock>) {
@@ -357,9 +842,7 @@ impl EventSink for HttpEventSink {
     }
 
     fn on_init_metrics_stream(&self, metrics_stream: &MetricsStream) {
-        self.send(SinkEvent::InitStream(Arc::new(make_stream_info(
-            metrics_stream,
-        ))));
+        self.init_stream(make_stream_info(metrics_stream), &self.codecs.metrics);
     }
 
     fn on_process_metrics_block(&self, metrics_block: Arc<MetricsBlock>) {
@@ -367,15 +850,25 @@ impl EventSink for HttpEventSink {
     }
 
     fn on_init_thread_stream(&self, thread_stream: &ThreadStream) {
-        self.send(SinkEvent::InitStream(Arc::new(make_stream_info(
-            thread_stream,
-        ))));
+        self.init_stream(make_stream_info(thread_stream), &self.codecs.threads);
     }
 
     fn on_process_thread_block(&self, thread_block: Arc<ThreadBlock>) {
         self.send(SinkEvent::ProcessThreadBlock(thread_block));
     }
 
+    fn on_attachment(&self, attachment: Arc<ProcessAttachment>) {
+        self.send(SinkEvent::Attachment(attachment));
+    }
+
+    fn on_process_properties_update(&self, update: Arc<ProcessPropertiesUpdate>) {
+        self.send(SinkEvent::ProcessPropertiesUpdate(update));
+    }
+
+    fn on_process_exit(&self, process_exit: Arc<ProcessExit>) {
+        self.send(SinkEvent::ProcessExit(process_exit));
+    }
+
     fn is_busy(&self) -> bool {
         self.queue_size.load(Ordering::Relaxed) > 0
     }
@@ -9,7 +9,10 @@ use std::any::TypeId;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
 
+pub mod child_process;
+pub mod client_tls;
 pub mod composite_event_sink;
 pub mod http_event_sink;
 pub mod local_event_sink;
@@ -17,6 +20,7 @@ pub mod log_interop;
 pub mod request_decorator;
 pub mod stream_block;
 pub mod stream_info;
+pub mod system_monitor;
 pub mod tracing_interop;
 
 use crate::log_interop::install_log_interop;
@@ -31,7 +35,7 @@ use micromegas_tracing::{
 };
 
 use composite_event_sink::CompositeSink;
-use local_event_sink::LocalEventSink;
+use local_event_sink::{LocalEventSink, LocalSinkFormat};
 
 pub mod tokio_retry {
     pub use tokio_retry::*;
@@ -41,7 +45,9 @@ pub mod reqwest {
     pub use reqwest::*;
 }
 
-use crate::http_event_sink::HttpEventSink;
+use crate::http_event_sink::{HttpEventSink, HttpEventSinkConfig, SenderRuntime};
+use micromegas_telemetry::ack_level::AckLevel;
+use micromegas_telemetry::compression::CompressionCodec;
 
 pub struct TelemetryGuardBuilder {
     logs_buffer_size: usize,
@@ -55,9 +61,15 @@ pub struct TelemetryGuardBuilder {
     install_tracing_capture: bool,
     local_sink_enabled: bool,
     local_sink_max_level: LevelFilter,
+    local_sink_format: LocalSinkFormat,
+    local_sink_target_max_levels: Vec<(String, LevelFilter)>,
+    local_sink_ticks: bool,
     telemetry_sink_max_level: LevelFilter,
-    telemetry_metadata_retry: Option<core::iter::Take<tokio_retry::strategy::ExponentialBackoff>>,
+    telemetry_config: HttpEventSinkConfig,
     telemetry_make_request_decorator: Box<dyn FnOnce() -> Arc<dyn RequestDecorator> + Send>,
+    telemetry_sender_runtime: SenderRuntime,
+    metrics_aggregation_min_lod: Option<Verbosity>,
+    max_block_age: Option<Duration>,
     extra_sinks: HashMap<TypeId, (LevelFilter, BoxedEventSink)>,
 }
 
@@ -69,11 +81,17 @@ impl Default for TelemetryGuardBuilder {
             threads_buffer_size: 10 * 1024 * 1024,
             local_sink_enabled: true,
             local_sink_max_level: LevelFilter::Info,
+            local_sink_format: LocalSinkFormat::default(),
+            local_sink_target_max_levels: vec![],
+            local_sink_ticks: false,
             telemetry_sink_max_level: LevelFilter::Debug,
-            telemetry_metadata_retry: None,
-            telemetry_make_request_decorator: Box::new(|| {
-                Arc::new(request_decorator::TrivialRequestDecorator {})
-            }),
+            telemetry_config: HttpEventSinkConfig::default(),
+            telemetry_make_request_decorator: Box::new(
+                request_decorator::default_request_decorator,
+            ),
+            telemetry_sender_runtime: SenderRuntime::default(),
+            metrics_aggregation_min_lod: None,
+            max_block_age: None,
             target_max_levels: HashMap::default(),
             max_queue_size: 16, //todo: change to nb_threads * 2
             max_level_override: None,
@@ -138,6 +156,32 @@ impl TelemetryGuardBuilder {
         self
     }
 
+    /// Pretty by default, overridden by `MICROMEGAS_LOCAL_SINK_FORMAT` (pretty, compact or json)
+    #[must_use]
+    pub fn with_local_sink_format(mut self, format: LocalSinkFormat) -> Self {
+        self.local_sink_format = format;
+        self
+    }
+
+    /// Max level of the local sink for the targets starting with `target`, the longest prefix applies
+    #[must_use]
+    pub fn with_local_sink_target_max_level(
+        mut self,
+        target: &str,
+        level_filter: LevelFilter,
+    ) -> Self {
+        self.local_sink_target_max_levels
+            .push((target.to_owned(), level_filter));
+        self
+    }
+
+    /// Prints the raw timestamp of each entry and the time it converts to, to diagnose clock drift
+    #[must_use]
+    pub fn with_local_sink_ticks(mut self, enabled: bool) -> Self {
+        self.local_sink_ticks = enabled;
+        self
+    }
+
     #[must_use]
     pub fn with_ctrlc_handling(self) -> Self {
         ctrlc::set_handler(move || {
@@ -154,7 +198,7 @@ impl TelemetryGuardBuilder {
         mut self,
         retry_strategy: core::iter::Take<tokio_retry::strategy::ExponentialBackoff>,
     ) -> Self {
-        self.telemetry_metadata_retry = Some(retry_strategy);
+        self.telemetry_config.metadata_retry = retry_strategy;
         self
     }
 
@@ -167,6 +211,74 @@ impl TelemetryGuardBuilder {
         self
     }
 
+    #[must_use]
+    pub fn with_logs_ack_level(mut self, ack_level: AckLevel) -> Self {
+        self.telemetry_config.ack_levels.logs = ack_level;
+        self
+    }
+
+    #[must_use]
+    pub fn with_metrics_ack_level(mut self, ack_level: AckLevel) -> Self {
+        self.telemetry_config.ack_levels.metrics = ack_level;
+        self
+    }
+
+    #[must_use]
+    pub fn with_threads_ack_level(mut self, ack_level: AckLevel) -> Self {
+        self.telemetry_config.ack_levels.threads = ack_level;
+        self
+    }
+
+    /// lz4 by default, see `micromegas_telemetry::compression`
+    #[must_use]
+    pub fn with_logs_compression(mut self, codec: CompressionCodec) -> Self {
+        self.telemetry_config.codecs.logs = codec;
+        self
+    }
+
+    #[must_use]
+    pub fn with_metrics_compression(mut self, codec: CompressionCodec) -> Self {
+        self.telemetry_config.codecs.metrics = codec;
+        self
+    }
+
+    #[must_use]
+    pub fn with_threads_compression(mut self, codec: CompressionCodec) -> Self {
+        self.telemetry_config.codecs.threads = codec;
+        self
+    }
+
+    /// Blocks waiting in the queue are packed in requests of up to `max_blocks_per_request` blocks,
+    /// inserted together by the ingestion service. One by default, for older ingestion services.
+    #[must_use]
+    pub fn with_max_blocks_per_request(mut self, max_blocks_per_request: usize) -> Self {
+        self.telemetry_config.max_blocks_per_request = max_blocks_per_request.max(1);
+        self
+    }
+
+    /// Apps without a tokio runtime of their own can avoid the worker pool with `SenderRuntime::CurrentThread`
+    #[must_use]
+    pub fn with_telemetry_sender_runtime(mut self, sender_runtime: SenderRuntime) -> Self {
+        self.telemetry_sender_runtime = sender_runtime;
+        self
+    }
+
+    /// Metrics of verbosity `min_lod` and above are summed per thread between flushes
+    /// instead of being recorded individually
+    #[must_use]
+    pub fn with_metrics_aggregation(mut self, min_lod: Verbosity) -> Self {
+        self.metrics_aggregation_min_lod = Some(min_lod);
+        self
+    }
+
+    /// Flushes the blocks older than `max_age` with the next event of their stream, so that
+    /// long-running processes with few events still send blocks covering short time ranges
+    #[must_use]
+    pub fn with_max_block_age(mut self, max_age: Duration) -> Self {
+        self.max_block_age = Some(max_age);
+        self
+    }
+
     pub fn build(self) -> anyhow::Result<TelemetryGuard> {
         let target_max_level: Vec<_> = self
             .target_max_levels
@@ -192,21 +304,29 @@ impl TelemetryGuardBuilder {
             } else {
                 let mut sinks: Vec<(LevelFilter, BoxedEventSink)> = vec![];
                 if let Ok(url) = std::env::var("MICROMEGAS_TELEMETRY_URL") {
-                    let retry_strategy = self.telemetry_metadata_retry.unwrap_or_else(|| {
-                        tokio_retry::strategy::ExponentialBackoff::from_millis(10).take(3)
-                    });
                     sinks.push((
                         self.telemetry_sink_max_level,
                         Box::new(HttpEventSink::new(
                             &url,
                             self.max_queue_size,
-                            retry_strategy,
+                            self.telemetry_config,
+                            self.telemetry_sender_runtime,
                             self.telemetry_make_request_decorator,
                         )),
                     ));
                 }
                 if self.local_sink_enabled {
-                    sinks.push((self.local_sink_max_level, Box::new(LocalEventSink::new())));
+                    let format = match std::env::var("MICROMEGAS_LOCAL_SINK_FORMAT") {
+                        Ok(format) => LocalSinkFormat::from_str(&format)?,
+                        Err(_) => self.local_sink_format,
+                    };
+                    let local_sink = LocalEventSink::with_format(
+                        format,
+                        self.local_sink_max_level,
+                        self.local_sink_target_max_levels,
+                    )
+                    .with_ticks(self.local_sink_ticks);
+                    sinks.push((local_sink.max_level(), Box::new(local_sink)));
                 }
                 let mut extra_sinks = self.extra_sinks.into_values().collect();
                 sinks.append(&mut extra_sinks);
@@ -225,6 +345,13 @@ impl TelemetryGuardBuilder {
                     install_tracing_interop(self.interop_max_level_override);
                 }
 
+                if let Some(min_lod) = self.metrics_aggregation_min_lod {
+                    micromegas_tracing::metrics::enable_metrics_aggregation(min_lod);
+                }
+                if let Some(max_age) = self.max_block_age {
+                    micromegas_tracing::event::set_max_block_age(max_age);
+                }
+
                 let arc = Arc::<TracingSystemGuard>::new(TracingSystemGuard::new(
                     self.logs_buffer_size,
                     self.metrics_buffer_size,
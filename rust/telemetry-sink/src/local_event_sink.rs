@@ -5,19 +5,63 @@ use micromegas_tracing::{
     prelude::*,
     spans::{ThreadBlock, ThreadStream},
 };
-use std::{fmt, sync::Arc};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
 
 // Based on simple logger
 #[cfg(feature = "colored")]
 use colored::Colorize;
 
+/// How the local sink prints log entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LocalSinkFormat {
+    /// timestamp, colored level, target and message, for development
+    #[default]
+    Pretty,
+    /// level initial, target and message without timestamp or colors, for CI logs
+    Compact,
+    /// one json object per line, for log shippers
+    JsonLines,
+}
+
+impl FromStr for LocalSinkFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "json" | "jsonl" | "json_lines" => Ok(Self::JsonLines),
+            _ => anyhow::bail!("unknown local sink format {s}, expected pretty, compact or json"),
+        }
+    }
+}
+
 pub struct LocalEventSink {
+    format: LocalSinkFormat,
+
+    /// Max level per target prefix, the longest matching prefix applies.
+    target_max_levels: Vec<(String, LevelFilter)>,
+
+    /// Max level of the targets not matching any prefix
+    max_level: LevelFilter,
+
     /// Control how timestamps are displayed.
     ///
     /// This field is only available if the `timestamps` feature is enabled.
     #[cfg(feature = "timestamps")]
     timestamps: bool,
 
+    /// Whether to print the raw timestamp of each entry and the time it converts to,
+    /// next to the wall clock time, see `with_ticks`
+    show_ticks: bool,
+
+    /// Used to convert the ticks, known from startup
+    process_info: OnceLock<Arc<ProcessInfo>>,
+
     /// Whether to use color output or not.
     ///
     /// This field is only available if the `color` feature is enabled.
@@ -25,27 +69,119 @@ pub struct LocalEventSink {
     colors: bool,
 }
 
+fn log_target<'a>(metadata: &LogMetadata<'a>) -> &'a str {
+    if !metadata.target.is_empty() {
+        metadata.target
+    } else {
+        metadata.module_path
+    }
+}
+
 impl LocalEventSink {
     pub fn new() -> Self {
+        Self::with_format(LocalSinkFormat::Pretty, LevelFilter::Trace, vec![])
+    }
+
+    pub fn with_format(
+        format: LocalSinkFormat,
+        max_level: LevelFilter,
+        mut target_max_levels: Vec<(String, LevelFilter)>,
+    ) -> Self {
+        target_max_levels.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
         Self {
+            format,
+            target_max_levels,
+            max_level,
+            show_ticks: false,
+            process_info: OnceLock::new(),
             #[cfg(feature = "timestamps")]
             timestamps: true,
             #[cfg(feature = "colored")]
-            colors: true,
+            colors: format == LocalSinkFormat::Pretty,
         }
     }
-}
 
-impl EventSink for LocalEventSink {
-    fn on_startup(&self, _proc_info: Arc<ProcessInfo>) {}
-    fn on_shutdown(&self) {}
+    /// Prints the raw timestamp of each entry and the time it converts to, to diagnose clock drift
+    pub fn with_ticks(mut self, show_ticks: bool) -> Self {
+        self.show_ticks = show_ticks;
+        self
+    }
 
-    fn on_log_enabled(&self, _metadata: &LogMetadata) -> bool {
-        // reaching here we accept everything
-        true
+    /// Max level among the default and the targets, the sink is given at least that much
+    pub fn max_level(&self) -> LevelFilter {
+        self.target_max_levels
+            .iter()
+            .fold(self.max_level, |max, (_, level)| max.max(*level))
+    }
+
+    fn target_max_level(&self, target: &str) -> LevelFilter {
+        self.target_max_levels
+            .iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map_or(self.max_level, |(_, level)| *level)
+    }
+
+    fn timestamp(&self) -> Option<String> {
+        #[cfg(feature = "timestamps")]
+        if self.timestamps {
+            return Some(chrono::Utc::now().to_rfc3339());
+        }
+        None
+    }
+
+    /// Wall clock time of the ticks, the way the analytics service converts them
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn ticks_to_time(&self, ticks: i64) -> Option<String> {
+        let process_info = self.process_info.get()?;
+        if process_info.tsc_frequency <= 0 {
+            return None;
+        }
+        let delta_ns = (ticks - process_info.start_ticks) as f64 * 1_000_000_000.0
+            / process_info.tsc_frequency as f64;
+        let time = process_info.start_time + chrono::Duration::nanoseconds(delta_ns as i64);
+        Some(time.to_rfc3339())
     }
 
-    fn on_log(&self, metadata: &LogMetadata, _time: i64, args: fmt::Arguments<'_>) {
+    /// `ticks=... (converted time)` when ticks are shown
+    fn ticks_prefix(&self, ticks: i64) -> String {
+        if !self.show_ticks {
+            return String::new();
+        }
+        match self.ticks_to_time(ticks) {
+            Some(time) => format!("ticks={ticks} ({time}) "),
+            None => format!("ticks={ticks} "),
+        }
+    }
+
+    fn format_json(
+        &self,
+        metadata: &LogMetadata,
+        target: &str,
+        ticks: i64,
+        args: fmt::Arguments<'_>,
+    ) -> String {
+        let mut line = serde_json::json!({
+            "time": self.timestamp(),
+            "level": metadata.level.as_str(),
+            "target": target,
+            "msg": args.to_string(),
+            "file": metadata.file,
+            "line": metadata.line,
+        });
+        if self.show_ticks {
+            line["ticks"] = ticks.into();
+            line["ticks_time"] = self.ticks_to_time(ticks).into();
+        }
+        line.to_string()
+    }
+
+    fn format_pretty(
+        &self,
+        metadata: &LogMetadata,
+        target: &str,
+        ticks: i64,
+        args: fmt::Arguments<'_>,
+    ) -> String {
         let level_string = {
             #[cfg(feature = "colored")]
             {
@@ -64,29 +200,53 @@ impl EventSink for LocalEventSink {
             }
             #[cfg(not(feature = "colored"))]
             {
-                record.level().to_string()
+                metadata.level.to_string()
             }
         };
+        let timestamp = self
+            .timestamp()
+            .map(|time| format!("{time} "))
+            .unwrap_or_default();
+        format!(
+            "{}{}{:<5} [{}] {}",
+            timestamp,
+            self.ticks_prefix(ticks),
+            level_string,
+            target,
+            args
+        )
+    }
 
-        let target = if !metadata.target.is_empty() {
-            metadata.target
-        } else {
-            metadata.module_path
-        };
+    fn format_compact(
+        &self,
+        metadata: &LogMetadata,
+        target: &str,
+        ticks: i64,
+        args: fmt::Arguments<'_>,
+    ) -> String {
+        let initial = &metadata.level.as_str()[..1];
+        format!("{}{initial} {target}: {args}", self.ticks_prefix(ticks))
+    }
+}
 
-        let timestamp = {
-            #[cfg(feature = "timestamps")]
-            if self.timestamps {
-                format!("{} ", chrono::Utc::now().to_rfc3339())
-            } else {
-                "".to_string()
-            }
+impl EventSink for LocalEventSink {
+    fn on_startup(&self, proc_info: Arc<ProcessInfo>) {
+        let _ = self.process_info.set(proc_info);
+    }
+    fn on_shutdown(&self) {}
 
-            #[cfg(not(feature = "timestamps"))]
-            ""
-        };
+    fn on_log_enabled(&self, metadata: &LogMetadata) -> bool {
+        let target = log_target(metadata);
+        metadata.level <= self.target_max_level(target)
+    }
 
-        let message = format!("{}{:<5} [{}] {}", timestamp, level_string, target, args);
+    fn on_log(&self, metadata: &LogMetadata, time: i64, args: fmt::Arguments<'_>) {
+        let target = log_target(metadata);
+        let message = match self.format {
+            LocalSinkFormat::Pretty => self.format_pretty(metadata, target, time, args),
+            LocalSinkFormat::Compact => self.format_compact(metadata, target, time, args),
+            LocalSinkFormat::JsonLines => self.format_json(metadata, target, time, args),
+        };
 
         #[cfg(not(feature = "stderr"))]
         println!("{}", message);
@@ -110,3 +270,91 @@ impl EventSink for LocalEventSink {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use micromegas_tracing::logs::FILTER_LEVEL_UNSET_VALUE;
+    use std::sync::atomic::AtomicU32;
+
+    #[test]
+    fn test_format_from_str() {
+        assert_eq!(
+            "Pretty".parse::<LocalSinkFormat>().unwrap(),
+            LocalSinkFormat::Pretty
+        );
+        assert_eq!(
+            "compact".parse::<LocalSinkFormat>().unwrap(),
+            LocalSinkFormat::Compact
+        );
+        for json in ["json", "JSONL", "json_lines"] {
+            assert_eq!(
+                json.parse::<LocalSinkFormat>().unwrap(),
+                LocalSinkFormat::JsonLines
+            );
+        }
+        assert!("xml".parse::<LocalSinkFormat>().is_err());
+    }
+
+    #[test]
+    fn test_target_max_level() {
+        let sink = LocalEventSink::with_format(
+            LocalSinkFormat::Compact,
+            LevelFilter::Warn,
+            vec![
+                ("micromegas".to_owned(), LevelFilter::Info),
+                ("micromegas_analytics".to_owned(), LevelFilter::Trace),
+                (
+                    "micromegas_analytics::lakehouse".to_owned(),
+                    LevelFilter::Off,
+                ),
+            ],
+        );
+        assert_eq!(sink.target_max_level("sqlx::query"), LevelFilter::Warn);
+        assert_eq!(
+            sink.target_max_level("micromegas_ingestion"),
+            LevelFilter::Info
+        );
+        assert_eq!(
+            sink.target_max_level("micromegas_analytics::time"),
+            LevelFilter::Trace
+        );
+        assert_eq!(
+            sink.target_max_level("micromegas_analytics::lakehouse::view"),
+            LevelFilter::Off
+        );
+        assert_eq!(sink.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_json_line() {
+        static METADATA: LogMetadata = LogMetadata {
+            level: Level::Warn,
+            level_filter: AtomicU32::new(FILTER_LEVEL_UNSET_VALUE),
+            fmt_str: "",
+            target: "my_target",
+            module_path: "my_module",
+            file: "main.rs",
+            line: 12,
+        };
+        let sink =
+            LocalEventSink::with_format(LocalSinkFormat::JsonLines, LevelFilter::Trace, vec![])
+                .with_ticks(true);
+        let line = sink.format_json(
+            &METADATA,
+            log_target(&METADATA),
+            42,
+            format_args!("hello \"{}\"", 1),
+        );
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["target"], "my_target");
+        assert_eq!(value["msg"], "hello \"1\"");
+        assert_eq!(value["file"], "main.rs");
+        assert_eq!(value["line"], 12);
+        assert_eq!(value["ticks"], 42);
+        // the ticks can't be converted before the process info is known
+        assert!(value["ticks_time"].is_null());
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use micromegas_tracing::error;
+use std::sync::Arc;
 
 #[async_trait] // otherwise we get: cannot be made into an object
 pub trait RequestDecorator: Send {
@@ -14,3 +16,39 @@ impl RequestDecorator for TrivialRequestDecorator {
         Ok(())
     }
 }
+
+/// Sends an api key in the `Authorization` header, see `micromegas::servers::api_key_auth`
+pub struct ApiKeyRequestDecorator {
+    authorization: reqwest::header::HeaderValue,
+}
+
+impl ApiKeyRequestDecorator {
+    pub fn new(api_key: &str) -> Result<Self> {
+        let mut authorization =
+            reqwest::header::HeaderValue::from_str(&format!("Bearer {api_key}"))?;
+        authorization.set_sensitive(true);
+        Ok(Self { authorization })
+    }
+}
+
+#[async_trait]
+impl RequestDecorator for ApiKeyRequestDecorator {
+    async fn decorate(&self, request: &mut reqwest::Request) -> Result<()> {
+        request
+            .headers_mut()
+            .insert(reqwest::header::AUTHORIZATION, self.authorization.clone());
+        Ok(())
+    }
+}
+
+/// Authenticates with `MICROMEGAS_API_KEY` when it is set
+pub fn default_request_decorator() -> Arc<dyn RequestDecorator> {
+    match std::env::var("MICROMEGAS_API_KEY").map(|key| ApiKeyRequestDecorator::new(&key)) {
+        Ok(Ok(decorator)) => Arc::new(decorator),
+        Ok(Err(e)) => {
+            error!("invalid MICROMEGAS_API_KEY: {e:?}");
+            Arc::new(TrivialRequestDecorator {})
+        }
+        Err(_) => Arc::new(TrivialRequestDecorator {}),
+    }
+}
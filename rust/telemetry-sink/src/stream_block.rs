@@ -1,5 +1,9 @@
 use anyhow::Result;
-use micromegas_telemetry::{block_wire_format, compression::compress, wire_format::encode_cbor};
+use micromegas_telemetry::{
+    block_wire_format,
+    compression::CompressionCodec,
+    wire_format::{encode_cbor, WIRE_FORMAT_VERSION},
+};
 use micromegas_tracing::{
     event::{EventBlock, ExtractDeps, TracingBlock},
     logs::LogBlock,
@@ -10,10 +14,23 @@ use micromegas_tracing::{
 use micromegas_transit::HeterogeneousQueue;
 
 pub trait StreamBlock {
-    fn encode_bin(&self, process_info: &ProcessInfo) -> Result<Vec<u8>>;
+    /// The codec must match the `compression` property of the stream
+    fn encode_bin_with_codec(
+        &self,
+        process_info: &ProcessInfo,
+        codec: &CompressionCodec,
+    ) -> Result<Vec<u8>>;
+
+    fn encode_bin(&self, process_info: &ProcessInfo) -> Result<Vec<u8>> {
+        self.encode_bin_with_codec(process_info, &CompressionCodec::Lz4)
+    }
 }
 
-fn encode_block<Q>(block: &EventBlock<Q>, process_info: &ProcessInfo) -> Result<Vec<u8>>
+fn encode_block<Q>(
+    block: &EventBlock<Q>,
+    process_info: &ProcessInfo,
+    codec: &CompressionCodec,
+) -> Result<Vec<u8>>
 where
     Q: HeterogeneousQueue + ExtractDeps,
     <Q as ExtractDeps>::DepsQueue: HeterogeneousQueue,
@@ -23,10 +40,11 @@ where
     let end = block.end.as_ref().unwrap();
 
     let payload = block_wire_format::BlockPayload {
-        dependencies: compress(block.events.extract().as_bytes())?,
-        objects: compress(block.events.as_bytes())?,
+        dependencies: codec.compress(block.events.extract().as_bytes())?,
+        objects: codec.compress(block.events.as_bytes())?,
     };
 
+    let checksum = block_wire_format::payload_checksum(&payload);
     let block = block_wire_format::Block {
         block_id,
         stream_id: block.stream_id,
@@ -43,24 +61,38 @@ where
         payload,
         nb_objects: block.nb_objects() as i32,
         object_offset: block.object_offset() as i64,
+        checksum: Some(checksum),
+        wire_format_version: WIRE_FORMAT_VERSION,
     };
     encode_cbor(&block)
 }
 
 impl StreamBlock for LogBlock {
-    fn encode_bin(&self, process_info: &ProcessInfo) -> Result<Vec<u8>> {
-        encode_block(self, process_info)
+    fn encode_bin_with_codec(
+        &self,
+        process_info: &ProcessInfo,
+        codec: &CompressionCodec,
+    ) -> Result<Vec<u8>> {
+        encode_block(self, process_info, codec)
     }
 }
 
 impl StreamBlock for MetricsBlock {
-    fn encode_bin(&self, process_info: &ProcessInfo) -> Result<Vec<u8>> {
-        encode_block(self, process_info)
+    fn encode_bin_with_codec(
+        &self,
+        process_info: &ProcessInfo,
+        codec: &CompressionCodec,
+    ) -> Result<Vec<u8>> {
+        encode_block(self, process_info, codec)
     }
 }
 
 impl StreamBlock for ThreadBlock {
-    fn encode_bin(&self, process_info: &ProcessInfo) -> Result<Vec<u8>> {
-        encode_block(self, process_info)
+    fn encode_bin_with_codec(
+        &self,
+        process_info: &ProcessInfo,
+        codec: &CompressionCodec,
+    ) -> Result<Vec<u8>> {
+        encode_block(self, process_info, codec)
     }
 }
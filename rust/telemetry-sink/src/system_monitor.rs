@@ -0,0 +1,334 @@
+//! System monitor: periodically records cpu, memory, disk, network and gpu metrics,
+//! along with the usage relative to the limits of the cgroup the process runs in
+//!
+//! The throughput of the disks is read from `/proc/diskstats`, it is only available on linux.
+use micromegas_tracing::container_info::{
+    read_cgroup_limits, read_cgroup_memory_usage, CgroupLimits,
+};
+use micromegas_tracing::dispatch::{float_metric, int_metric};
+use micromegas_tracing::metrics::make_metric_metadata;
+use micromegas_tracing::prelude::*;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use sysinfo::{Networks, Pid, System};
+
+const SYSTEM_MONITOR_PERIOD: Duration = Duration::from_secs(1);
+/// unit of the sector counts of /proc/diskstats, whatever the sector size of the device
+const DISKSTATS_SECTOR_SIZE: u64 = 512;
+
+#[allow(clippy::cast_precision_loss)]
+fn per_second(value: u64, elapsed: Duration) -> f64 {
+    value as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+/// Bytes read and written by a block device since boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DiskCounters {
+    read_bytes: u64,
+    written_bytes: u64,
+}
+
+/// counters of the devices accepted by `is_disk`, from the content of /proc/diskstats
+fn parse_diskstats(content: &str, is_disk: impl Fn(&str) -> bool) -> HashMap<String, DiskCounters> {
+    let mut disks = HashMap::new();
+    for line in content.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 || !is_disk(fields[2]) {
+            continue;
+        }
+        if let (Ok(sectors_read), Ok(sectors_written)) =
+            (fields[5].parse::<u64>(), fields[9].parse::<u64>())
+        {
+            disks.insert(
+                fields[2].to_owned(),
+                DiskCounters {
+                    read_bytes: sectors_read * DISKSTATS_SECTOR_SIZE,
+                    written_bytes: sectors_written * DISKSTATS_SECTOR_SIZE,
+                },
+            );
+        }
+    }
+    disks
+}
+
+/// whole devices, partitions would count the same bytes twice
+fn is_physical_disk(name: &str) -> bool {
+    !name.starts_with("loop")
+        && !name.starts_with("ram")
+        && std::path::Path::new("/sys/block").join(name).exists()
+}
+
+fn read_disk_counters() -> HashMap<String, DiskCounters> {
+    std::fs::read_to_string("/proc/diskstats")
+        .map(|content| parse_diskstats(&content, is_physical_disk))
+        .unwrap_or_default()
+}
+
+/// read and write throughput of the devices present in both samples, sorted by name
+fn disk_rates(
+    previous: &HashMap<String, DiskCounters>,
+    current: &HashMap<String, DiskCounters>,
+    elapsed: Duration,
+) -> Vec<(String, f64, f64)> {
+    let mut rates: Vec<(String, f64, f64)> = current
+        .iter()
+        .filter_map(|(name, counters)| {
+            let before = previous.get(name)?;
+            Some((
+                name.clone(),
+                per_second(
+                    counters.read_bytes.saturating_sub(before.read_bytes),
+                    elapsed,
+                ),
+                per_second(
+                    counters.written_bytes.saturating_sub(before.written_bytes),
+                    elapsed,
+                ),
+            ))
+        })
+        .collect();
+    rates.sort_by(|a, b| a.0.cmp(&b.0));
+    rates
+}
+
+struct SystemMonitor {
+    system: System,
+    networks: Networks,
+    pid: Option<Pid>,
+    disk_counters: HashMap<String, DiskCounters>,
+    cgroup_limits: CgroupLimits,
+    last_refresh: Instant,
+    #[cfg(feature = "gpu")]
+    nvml: Option<nvml_wrapper::Nvml>,
+}
+
+impl SystemMonitor {
+    fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu();
+        let pid = sysinfo::get_current_pid().ok();
+        if let Some(pid) = pid {
+            system.refresh_process(pid);
+        }
+        #[cfg(feature = "gpu")]
+        let nvml = nvml_wrapper::Nvml::init()
+            .map_err(|e| debug!("gpu metrics unavailable: {e}"))
+            .ok();
+        Self {
+            system,
+            networks: Networks::new_with_refreshed_list(),
+            pid,
+            disk_counters: read_disk_counters(),
+            cgroup_limits: read_cgroup_limits(),
+            last_refresh: Instant::now(),
+            #[cfg(feature = "gpu")]
+            nvml,
+        }
+    }
+
+    fn record_cpu_and_memory(&mut self) {
+        self.system.refresh_cpu();
+        self.system.refresh_memory();
+        float_metric(
+            make_metric_metadata("cpu_usage", "percent", "system"),
+            f64::from(self.system.global_cpu_info().cpu_usage()),
+        );
+        int_metric(
+            make_metric_metadata("used_memory", "bytes", "system"),
+            self.system.used_memory(),
+        );
+        int_metric(
+            make_metric_metadata("total_memory", "bytes", "system"),
+            self.system.total_memory(),
+        );
+    }
+
+    fn record_disk_io(&mut self, elapsed: Duration) {
+        let disk_counters = read_disk_counters();
+        for (disk, read, written) in disk_rates(&self.disk_counters, &disk_counters, elapsed) {
+            let target = format!("disk/{disk}");
+            float_metric(make_metric_metadata("disk_read", "bytes/s", &target), read);
+            float_metric(
+                make_metric_metadata("disk_write", "bytes/s", &target),
+                written,
+            );
+        }
+        self.disk_counters = disk_counters;
+    }
+
+    /// reads and writes of the process, including the ones served by the page cache
+    fn record_process_io(&mut self, elapsed: Duration) {
+        let Some(pid) = self.pid else {
+            return;
+        };
+        if !self.system.refresh_process(pid) {
+            return;
+        }
+        if let Some(process) = self.system.process(pid) {
+            let usage = process.disk_usage();
+            float_metric(
+                make_metric_metadata("io_read", "bytes/s", "process"),
+                per_second(usage.read_bytes, elapsed),
+            );
+            float_metric(
+                make_metric_metadata("io_write", "bytes/s", "process"),
+                per_second(usage.written_bytes, elapsed),
+            );
+        }
+    }
+
+    /// usage relative to the limits of the container, if any
+    #[allow(clippy::cast_precision_loss)]
+    fn record_cgroup_usage(&self) {
+        if let (Some(cpu_quota), Some(process)) = (
+            self.cgroup_limits.cpu_quota,
+            self.pid.and_then(|pid| self.system.process(pid)),
+        ) {
+            // process cpu usage is a percentage of a single core
+            float_metric(
+                make_metric_metadata("cpu_quota_usage", "percent", "cgroup"),
+                f64::from(process.cpu_usage()) / cpu_quota,
+            );
+        }
+        if let (Some(memory_limit), Some(memory_usage)) =
+            (self.cgroup_limits.memory_limit, read_cgroup_memory_usage())
+        {
+            int_metric(
+                make_metric_metadata("memory_usage", "bytes", "cgroup"),
+                memory_usage,
+            );
+            float_metric(
+                make_metric_metadata("memory_limit_usage", "percent", "cgroup"),
+                memory_usage as f64 * 100.0 / memory_limit as f64,
+            );
+        }
+    }
+
+    fn record_network(&mut self, elapsed: Duration) {
+        self.networks.refresh();
+        for (interface, data) in &self.networks {
+            let target = format!("network/{interface}");
+            float_metric(
+                make_metric_metadata("network_received", "bytes/s", &target),
+                per_second(data.received(), elapsed),
+            );
+            float_metric(
+                make_metric_metadata("network_transmitted", "bytes/s", &target),
+                per_second(data.transmitted(), elapsed),
+            );
+        }
+    }
+
+    #[cfg(feature = "gpu")]
+    fn record_gpu(&mut self) {
+        let Some(nvml) = &self.nvml else {
+            return;
+        };
+        let device_count = match nvml.device_count() {
+            Ok(count) => count,
+            Err(e) => {
+                debug!("error reading gpu count: {e}");
+                return;
+            }
+        };
+        for index in 0..device_count {
+            let Ok(device) = nvml.device_by_index(index) else {
+                continue;
+            };
+            let target = format!("gpu/{index}");
+            if let Ok(utilization) = device.utilization_rates() {
+                float_metric(
+                    make_metric_metadata("gpu_usage", "percent", &target),
+                    f64::from(utilization.gpu),
+                );
+            }
+            if let Ok(memory) = device.memory_info() {
+                int_metric(
+                    make_metric_metadata("gpu_used_memory", "bytes", &target),
+                    memory.used,
+                );
+                int_metric(
+                    make_metric_metadata("gpu_total_memory", "bytes", &target),
+                    memory.total,
+                );
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_refresh;
+        self.last_refresh = now;
+        self.record_cpu_and_memory();
+        self.record_disk_io(elapsed);
+        self.record_process_io(elapsed);
+        self.record_cgroup_usage();
+        self.record_network(elapsed);
+        #[cfg(feature = "gpu")]
+        self.record_gpu();
+    }
+}
+
+/// Starts a background thread recording system metrics every second
+pub fn spawn_system_monitor() {
+    let spawn_result = std::thread::Builder::new()
+        .name("system_monitor".to_owned())
+        .spawn(|| {
+            let mut monitor = SystemMonitor::new();
+            loop {
+                std::thread::sleep(SYSTEM_MONITOR_PERIOD);
+                monitor.tick();
+            }
+        });
+    if let Err(e) = spawn_result {
+        error!("error spawning system monitor thread: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISKSTATS: &str = "\
+ 259       0 nvme0n1 5000 10 8000 300 2000 20 4000 100 0 400 400 0 0 0 0
+ 259       1 nvme0n1p1 4000 10 6000 200 1000 20 2000 50 0 250 250 0 0 0 0
+   7       0 loop0 10 0 20 0 0 0 0 0 0 0 0 0 0 0 0
+   8       0 sda 1
+";
+
+    #[test]
+    fn test_parse_diskstats() {
+        let disks = parse_diskstats(DISKSTATS, |name| name == "nvme0n1" || name == "sda");
+        assert_eq!(disks.len(), 1);
+        assert_eq!(
+            disks["nvme0n1"],
+            DiskCounters {
+                read_bytes: 8000 * 512,
+                written_bytes: 4000 * 512,
+            }
+        );
+    }
+
+    #[test]
+    fn test_disk_rates() {
+        let disk = |read_bytes, written_bytes| DiskCounters {
+            read_bytes,
+            written_bytes,
+        };
+        let previous = HashMap::from([("sda".to_owned(), disk(1000, 500))]);
+        let current = HashMap::from([
+            ("sda".to_owned(), disk(3000, 1500)),
+            // appeared since the previous sample
+            ("sdb".to_owned(), disk(100, 100)),
+        ]);
+        assert_eq!(
+            disk_rates(&previous, &current, Duration::from_secs(2)),
+            vec![("sda".to_owned(), 1000.0, 500.0)]
+        );
+        // counters reset by a device replaced under the same name
+        assert_eq!(
+            disk_rates(&current, &previous, Duration::from_secs(1)),
+            vec![("sda".to_owned(), 0.0, 0.0)]
+        );
+    }
+}
@@ -65,10 +65,15 @@ where
 pub fn install_tracing_interop(interop_max_level_override: Option<LevelFilter>) {
     let max_level = interop_max_level_override.unwrap_or(micromegas_tracing::levels::max_level());
 
-    tracing_subscriber::registry()
+    // the subscriber is global: installed by the first telemetry system of the process,
+    // it forwards the events to the systems that follow
+    match tracing_subscriber::registry()
         .with(TracingCaptureLayer { max_level })
-        .init();
-    tracing::debug!("installed tracing interop");
+        .try_init()
+    {
+        Ok(()) => tracing::debug!("installed tracing interop"),
+        Err(e) => tracing::debug!("tracing interop already installed: {e}"),
+    }
 }
 
 fn tracing_level_to_mm_level(level: &tracing_core::Level) -> micromegas_tracing::levels::Level {
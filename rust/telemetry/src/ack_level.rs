@@ -0,0 +1,41 @@
+//! acknowledgment semantics requested by clients when sending blocks
+use anyhow::Result;
+use std::str::FromStr;
+
+/// http header carrying the requested ack level
+pub const ACK_LEVEL_HEADER: &str = "x-micromegas-ack-level";
+
+/// When the ingestion service should respond to an insert request
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AckLevel {
+    /// respond as soon as the request is parsed, the block is written in the background
+    FireAndForget,
+    /// respond once the payload is in the object store, metadata is recorded in the background
+    ObjectStorePut,
+    /// respond once the payload is written and the block metadata is committed
+    #[default]
+    MetadataCommit,
+}
+
+impl AckLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AckLevel::FireAndForget => "fire_and_forget",
+            AckLevel::ObjectStorePut => "object_store_put",
+            AckLevel::MetadataCommit => "metadata_commit",
+        }
+    }
+}
+
+impl FromStr for AckLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fire_and_forget" => Ok(AckLevel::FireAndForget),
+            "object_store_put" => Ok(AckLevel::ObjectStorePut),
+            "metadata_commit" => Ok(AckLevel::MetadataCommit),
+            other => anyhow::bail!("unknown ack level {other}"),
+        }
+    }
+}
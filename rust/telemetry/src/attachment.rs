@@ -0,0 +1,12 @@
+// binary artifacts attached to processes, sent as the raw body of insert_attachment requests
+use uuid::Uuid;
+
+/// http header carrying the id of the process the attachment belongs to
+pub const ATTACHMENT_PROCESS_ID_HEADER: &str = "x-micromegas-process-id";
+/// http header carrying the name of the attachment, usually a file name
+pub const ATTACHMENT_NAME_HEADER: &str = "x-micromegas-attachment-name";
+
+/// Location of the attachment in the object store, next to the blocks of the process
+pub fn attachment_path(process_id: &Uuid, attachment_id: &Uuid) -> String {
+    format!("attachments/{process_id}/{attachment_id}")
+}
@@ -1,11 +1,14 @@
-use anyhow::Result;
-use object_store::{path::Path, ObjectStore};
+use anyhow::{Context, Result};
+use object_store::{aws::AmazonS3Builder, path::Path, signer::Signer, ObjectStore};
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct BlobStorage {
     blob_store: Arc<dyn ObjectStore>,
     blob_store_root: Path,
+    /// available when the store can hand out pre-signed urls
+    signer: Option<Arc<dyn Signer>>,
 }
 
 impl BlobStorage {
@@ -13,16 +16,45 @@ impl BlobStorage {
         Self {
             blob_store,
             blob_store_root,
+            signer: None,
         }
     }
 
+    /// Signs the urls returned by `signed_url`, usually the same object as the store
+    #[must_use]
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
     pub fn connect(object_store_url: &str) -> Result<Self> {
-        let (blob_store, blob_store_root) =
-            object_store::parse_url(&url::Url::parse(object_store_url)?)?;
-        Ok(Self {
-            blob_store: blob_store.into(),
-            blob_store_root,
-        })
+        let url = url::Url::parse(object_store_url)?;
+        let (blob_store, blob_store_root) = object_store::parse_url(&url)?;
+        if matches!(url.scheme(), "s3" | "s3a") {
+            // built again to keep the ability to sign urls, lost behind the ObjectStore trait
+            let s3 = Arc::new(
+                AmazonS3Builder::from_env()
+                    .with_url(object_store_url)
+                    .build()?,
+            );
+            return Ok(Self::new(s3.clone(), blob_store_root).with_signer(s3));
+        }
+        Ok(Self::new(blob_store.into(), blob_store_root))
+    }
+
+    /// Pre-signed url giving read access to the blob without credentials for `expires_in`
+    pub async fn signed_url(&self, obj_path: &str, expires_in: Duration) -> Result<url::Url> {
+        let signer = self
+            .signer
+            .as_ref()
+            .with_context(|| "blob storage does not support pre-signed urls")?;
+        let full_path = Path::from(format!("{}/{obj_path}", self.blob_store_root));
+        // the http method type comes from the http version used by object_store
+        let url = signer
+            .signed_url("GET".parse()?, &full_path, expires_in)
+            .await
+            .with_context(|| format!("signing url of {full_path}"))?;
+        Ok(url)
     }
 
     pub async fn put(&self, obj_path: &str, buffer: bytes::Bytes) -> Result<()> {
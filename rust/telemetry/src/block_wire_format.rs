@@ -1,5 +1,6 @@
 // block wire format
 use serde::{Deserialize, Serialize};
+use xxhash_rust::xxh3::Xxh3;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockPayload {
@@ -32,4 +33,42 @@ pub struct Block {
     pub payload: BlockPayload,
     pub object_offset: i64,
     pub nb_objects: i32,
+    /// `payload_checksum` computed by the client, absent from the blocks of older clients
+    #[serde(default)]
+    pub checksum: Option<u64>,
+    /// see `wire_format::WIRE_FORMAT_VERSION`
+    #[serde(default = "crate::wire_format::legacy_wire_format_version")]
+    pub wire_format_version: u32,
+}
+
+/// xxh3 of the payload as sent by the client, the parts are hashed as they are, compressed
+pub fn payload_checksum(payload: &BlockPayload) -> u64 {
+    let mut hasher = Xxh3::new();
+    // the boundary between the parts is part of the payload
+    hasher.update(&(payload.dependencies.len() as u64).to_le_bytes());
+    hasher.update(&payload.dependencies);
+    hasher.update(&payload.objects);
+    hasher.digest()
+}
+
+/// Checksums are stored in bigint columns, the bits are kept as they are
+pub fn checksum_to_i64(checksum: u64) -> i64 {
+    checksum as i64
+}
+
+/// Fails if the payload does not match the checksum recorded for the block, if any
+pub fn verify_payload_checksum(
+    block_id: &uuid::Uuid,
+    payload: &BlockPayload,
+    expected: Option<i64>,
+) -> anyhow::Result<()> {
+    if let Some(expected) = expected {
+        let actual = checksum_to_i64(payload_checksum(payload));
+        if actual != expected {
+            anyhow::bail!(
+                "checksum mismatch in payload of block {block_id}: expected {expected:016x}, found {actual:016x}"
+            );
+        }
+    }
+    Ok(())
 }
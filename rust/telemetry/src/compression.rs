@@ -1,6 +1,123 @@
+//! Compression of the block payloads
+//!
+//! Streams declare the codec of their payloads in the `compression` property:
+//!  - `lz4` : the default, also assumed when the property is missing. Cheap for cpu-bound clients.
+//!  - `zstd` : better ratio at a higher cpu cost
+//!  - `zstd-dict` : zstd with a trained dictionary (i.e. `zstd --train`), for small blocks of similar logs.
+//!    Frames carry the id of their dictionary, which must be registered in the decoding process,
+//!    see [`register_zstd_dictionary`] and [`load_zstd_dictionaries`].
 use anyhow::{Context, Result};
+use std::collections::BTreeMap;
 use std::io::Read;
 use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Stream property naming the codec of the block payloads
+pub const COMPRESSION_PROPERTY: &str = "compression";
+
+/// Payloads are rejected past this size once decoded: a few bytes can expand to gigabytes
+pub const MAX_DECOMPRESSED_SIZE: usize = 256 * 1024 * 1024;
+
+const LZ4_NAME: &str = "lz4";
+const ZSTD_NAME: &str = "zstd";
+const ZSTD_DICTIONARY_NAME: &str = "zstd-dict";
+
+static ZSTD_DICTIONARIES: RwLock<BTreeMap<u32, ZstdDictionary>> = RwLock::new(BTreeMap::new());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZstdDictionary {
+    id: u32,
+    content: Arc<Vec<u8>>,
+}
+
+impl ZstdDictionary {
+    /// `content` is a dictionary in the zstd format, identified by the id in its header
+    pub fn new(content: Vec<u8>) -> Result<Self> {
+        let id = zstd::zstd_safe::get_dict_id_from_dict(&content)
+            .with_context(|| "zstd dictionary has no id")?
+            .get();
+        Ok(Self {
+            id,
+            content: Arc::new(content),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::new(content).with_context(|| format!("loading zstd dictionary {}", path.display()))
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// Makes the dictionary available to decode the payloads compressed with it
+pub fn register_zstd_dictionary(dictionary: ZstdDictionary) {
+    ZSTD_DICTIONARIES
+        .write()
+        .unwrap()
+        .insert(dictionary.id, dictionary);
+}
+
+/// Registers every dictionary in the directory, returns their number
+pub fn load_zstd_dictionaries(directory: &Path) -> Result<usize> {
+    let mut nb_dictionaries = 0;
+    for entry in std::fs::read_dir(directory)
+        .with_context(|| format!("listing zstd dictionaries in {}", directory.display()))?
+    {
+        let path = entry?.path();
+        if path.is_file() {
+            register_zstd_dictionary(ZstdDictionary::load(&path)?);
+            nb_dictionaries += 1;
+        }
+    }
+    Ok(nb_dictionaries)
+}
+
+fn find_zstd_dictionary(id: u32) -> Result<ZstdDictionary> {
+    ZSTD_DICTIONARIES
+        .read()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .with_context(|| format!("unknown zstd dictionary {id}"))
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CompressionCodec {
+    #[default]
+    Lz4,
+    Zstd,
+    ZstdDictionary(ZstdDictionary),
+}
+
+impl CompressionCodec {
+    /// Value of the `compression` stream property
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Lz4 => LZ4_NAME,
+            Self::Zstd => ZSTD_NAME,
+            Self::ZstdDictionary(_) => ZSTD_DICTIONARY_NAME,
+        }
+    }
+
+    pub fn compress(&self, src: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Self::Lz4 => compress(src),
+            Self::Zstd => zstd::encode_all(src, zstd::DEFAULT_COMPRESSION_LEVEL)
+                .with_context(|| "zstd compression"),
+            Self::ZstdDictionary(dictionary) => zstd::bulk::Compressor::with_dictionary(
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+                &dictionary.content,
+            )
+            .with_context(|| "allocating zstd compressor")?
+            .compress(src)
+            .with_context(|| "zstd compression with dictionary"),
+        }
+    }
+}
 
 pub fn compress(src: &[u8]) -> Result<Vec<u8>> {
     let mut compressed = Vec::new();
@@ -16,13 +133,52 @@ pub fn compress(src: &[u8]) -> Result<Vec<u8>> {
     Ok(compressed)
 }
 
+fn read_bounded(decoder: impl Read, decompressed: &mut Vec<u8>) -> Result<()> {
+    decoder
+        .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+        .read_to_end(decompressed)?;
+    if decompressed.len() > MAX_DECOMPRESSED_SIZE {
+        anyhow::bail!("decompressed payload larger than {MAX_DECOMPRESSED_SIZE} bytes");
+    }
+    Ok(())
+}
+
 pub fn decompress(compressed: &[u8]) -> Result<Vec<u8>> {
     let mut decompressed = Vec::new();
     let mut decoder = lz4::Decoder::new(compressed).with_context(|| "allocating lz4 decoder")?;
-    let _size = decoder
-        .read_to_end(&mut decompressed)
+    read_bounded(&mut decoder, &mut decompressed)
         .with_context(|| "reading lz4-compressed buffer")?;
     let (_reader, res) = decoder.finish();
     res?;
     Ok(decompressed)
 }
+
+fn decompress_zstd(compressed: &[u8]) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    match zstd::zstd_safe::get_dict_id_from_frame(compressed) {
+        Some(dictionary_id) => {
+            let dictionary = find_zstd_dictionary(dictionary_id.get())?;
+            let decoder =
+                zstd::stream::read::Decoder::with_dictionary(compressed, &dictionary.content)
+                    .with_context(|| "allocating zstd decoder")?;
+            read_bounded(decoder, &mut decompressed)
+                .with_context(|| "reading zstd-compressed buffer")?;
+        }
+        None => {
+            let decoder = zstd::stream::read::Decoder::with_buffer(compressed)
+                .with_context(|| "allocating zstd decoder")?;
+            read_bounded(decoder, &mut decompressed)
+                .with_context(|| "reading zstd-compressed buffer")?;
+        }
+    }
+    Ok(decompressed)
+}
+
+/// Decodes a payload compressed with the codec named by the `compression` property of its stream
+pub fn decompress_with_codec(codec_name: Option<&str>, compressed: &[u8]) -> Result<Vec<u8>> {
+    match codec_name.unwrap_or(LZ4_NAME) {
+        LZ4_NAME => decompress(compressed),
+        ZSTD_NAME | ZSTD_DICTIONARY_NAME => decompress_zstd(compressed),
+        other => anyhow::bail!("unknown compression codec {other}"),
+    }
+}
@@ -1,7 +1,10 @@
 //! structures and functions common to both analytics and ingestion
+pub mod ack_level;
+pub mod attachment;
 pub mod blob_storage;
 pub mod block_wire_format;
 pub mod compression;
+pub mod loss_report;
 pub mod stream_info;
 pub mod types;
 pub mod wire_format;
@@ -0,0 +1,60 @@
+// accounting of the telemetry a client dropped before it reached the ingestion service
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Blocks of a stream dropped by the sink of a process, i.e. when its queue overflowed
+///
+/// Sent periodically to `insert_loss_reports` as a cbor-encoded `Vec<LossReport>`,
+/// each report covers the blocks dropped since the previous one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LossReport {
+    #[serde(
+        deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string",
+        serialize_with = "micromegas_transit::uuid_utils::uuid_to_string"
+    )]
+    pub process_id: Uuid,
+    #[serde(
+        deserialize_with = "micromegas_transit::uuid_utils::uuid_from_string",
+        serialize_with = "micromegas_transit::uuid_utils::uuid_to_string"
+    )]
+    pub stream_id: Uuid,
+    /// begin time of the earliest dropped block
+    pub begin_time: DateTime<Utc>,
+    /// end time of the latest dropped block
+    pub end_time: DateTime<Utc>,
+    pub nb_dropped_blocks: i64,
+    pub nb_dropped_events: i64,
+}
+
+impl LossReport {
+    pub fn new(
+        process_id: Uuid,
+        stream_id: Uuid,
+        begin_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        nb_events: i64,
+    ) -> Self {
+        Self {
+            process_id,
+            stream_id,
+            begin_time,
+            end_time,
+            nb_dropped_blocks: 1,
+            nb_dropped_events: nb_events,
+        }
+    }
+
+    /// Accounts for another dropped block of the same stream
+    pub fn add_block(
+        &mut self,
+        begin_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        nb_events: i64,
+    ) {
+        self.begin_time = self.begin_time.min(begin_time);
+        self.end_time = self.end_time.max(end_time);
+        self.nb_dropped_blocks += 1;
+        self.nb_dropped_events += nb_events;
+    }
+}
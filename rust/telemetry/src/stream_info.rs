@@ -3,6 +3,10 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+/// Stream property declaring how categories are embedded in the log messages, i.e. `bracketed`
+/// for Unreal streams: the category is then extracted in a column of the log entries
+pub const LOG_CATEGORIES_PROPERTY: &str = "log-categories";
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamInfo {
     #[serde(
@@ -10,4 +10,6 @@ pub struct BlockMetadata {
     pub nb_objects: i32,
     pub payload_size: i64,
     pub object_offset: i64,
+    /// xxh3 of the payload computed by the client, see `block_wire_format::payload_checksum`
+    pub checksum: Option<i64>,
 }
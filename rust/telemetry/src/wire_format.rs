@@ -1,4 +1,46 @@
 use anyhow::Result;
+use std::collections::HashMap;
+
+/// Version of the format of the streams and blocks produced by this crate
+///
+/// 1: blocks sent before the format was versioned, without checksum
+/// 2: blocks carry the checksum of their payload
+pub const WIRE_FORMAT_VERSION: u32 = 2;
+/// Oldest version still accepted by the ingestion service and parsed by the analytics service
+pub const MIN_WIRE_FORMAT_VERSION: u32 = 1;
+/// Stream property holding the wire format version of its blocks
+pub const WIRE_FORMAT_VERSION_PROPERTY: &str = "wire-format-version";
+
+/// Version of the streams and blocks that predate versioning
+pub fn legacy_wire_format_version() -> u32 {
+    1
+}
+
+/// Data sent in a format this build doesn't know, i.e. by a client upgraded before the servers
+#[derive(Debug, thiserror::Error)]
+#[error("unsupported wire format version {version}, supported versions are {MIN_WIRE_FORMAT_VERSION} to {WIRE_FORMAT_VERSION}")]
+pub struct UnsupportedWireFormat {
+    pub version: u32,
+}
+
+pub fn check_wire_format_version(version: u32) -> Result<u32, UnsupportedWireFormat> {
+    if (MIN_WIRE_FORMAT_VERSION..=WIRE_FORMAT_VERSION).contains(&version) {
+        Ok(version)
+    } else {
+        Err(UnsupportedWireFormat { version })
+    }
+}
+
+/// Wire format version declared in the properties of a stream, validated
+pub fn stream_wire_format_version(properties: &HashMap<String, String>) -> Result<u32> {
+    let Some(text) = properties.get(WIRE_FORMAT_VERSION_PROPERTY) else {
+        return Ok(legacy_wire_format_version());
+    };
+    let version = text
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid {WIRE_FORMAT_VERSION_PROPERTY} {text:?}"))?;
+    Ok(check_wire_format_version(version)?)
+}
 
 pub fn encode_cbor<T: serde::Serialize>(obj: &T) -> Result<Vec<u8>> {
     let mut bytes = Vec::new();
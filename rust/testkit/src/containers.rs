@@ -0,0 +1,67 @@
+//! Docker containers of the data lake, removed when dropped
+use anyhow::{Context, Result};
+use testcontainers::core::ExecCommand;
+use testcontainers::runners::AsyncRunner;
+use testcontainers::ContainerAsync;
+use testcontainers_modules::minio::MinIO;
+use testcontainers_modules::postgres::Postgres;
+
+pub const MINIO_USER: &str = "minioadmin";
+pub const MINIO_PASSWORD: &str = "minioadmin";
+
+pub struct PostgresContainer {
+    _container: ContainerAsync<Postgres>,
+    pub connection_string: String,
+}
+
+pub async fn start_postgres() -> Result<PostgresContainer> {
+    let container = Postgres::default()
+        .start()
+        .await
+        .with_context(|| "starting postgres container")?;
+    let host = container.get_host().await?;
+    let port = container.get_host_port_ipv4(5432).await?;
+    Ok(PostgresContainer {
+        _container: container,
+        connection_string: format!("postgres://postgres:postgres@{host}:{port}/postgres"),
+    })
+}
+
+pub struct MinioContainer {
+    _container: ContainerAsync<MinIO>,
+    /// http url of the s3 api
+    pub endpoint: String,
+    pub bucket: String,
+}
+
+/// Starts minio with an empty bucket
+pub async fn start_minio(bucket: &str) -> Result<MinioContainer> {
+    let container = MinIO::default()
+        .start()
+        .await
+        .with_context(|| "starting minio container")?;
+    // recent images ship with the minio client, older ones run in fs mode where directories are buckets
+    let script = format!(
+        "(mc alias set local http://localhost:9000 {MINIO_USER} {MINIO_PASSWORD} && mc mb local/{bucket}) || mkdir -p /data/{bucket}"
+    );
+    let mut result = container
+        .exec(ExecCommand::new(["sh".to_owned(), "-c".to_owned(), script]))
+        .await
+        .with_context(|| "creating bucket")?;
+    // the command is complete once its output is consumed
+    let output = result.stdout_to_vec().await?;
+    let exit_code = result.exit_code().await?;
+    if exit_code != Some(0) {
+        anyhow::bail!(
+            "creating bucket {bucket}: exit code {exit_code:?}: {}",
+            String::from_utf8_lossy(&output)
+        );
+    }
+    let host = container.get_host().await?;
+    let port = container.get_host_port_ipv4(9000).await?;
+    Ok(MinioContainer {
+        _container: container,
+        endpoint: format!("http://{host}:{port}"),
+        bucket: bucket.to_owned(),
+    })
+}
@@ -0,0 +1,22 @@
+//! testkit : ephemeral data lake to test micromegas end to end
+//!
+//! [`TestStack::start`] runs postgresql and minio in docker containers, migrates the schema
+//! and serves the ingestion and analytics services in-process. Telemetry is emitted through
+//! the ingestion service with [`ProcessEmitter`] and read back through the analytics queries.
+//!
+//! ```ignore
+//! let stack = TestStack::start().await?;
+//! let process = stack.emit_process("my_exe").await?;
+//! let stream_id = process.emit_logs(&[(0, Level::Info, "hello")]).await?;
+//! let entries = stack.log_entries(stream_id, process.start_time(), Utc::now(), 10).await?;
+//! ```
+
+// crate-specific lint exceptions:
+#![allow(clippy::missing_errors_doc)]
+
+pub mod containers;
+pub mod process_emitter;
+pub mod test_stack;
+
+pub use process_emitter::ProcessEmitter;
+pub use test_stack::TestStack;
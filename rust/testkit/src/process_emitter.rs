@@ -0,0 +1,213 @@
+//! Emits the telemetry of a synthetic process through the ingestion service
+//!
+//! The process has a tick frequency of 1GHz and starts at tick 0: event times are given
+//! in nanoseconds relative to the start of the process.
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use micromegas_ingestion::web_ingestion_service::WebIngestionService;
+use micromegas_telemetry::ack_level::AckLevel;
+use micromegas_telemetry::stream_info::StreamInfo;
+use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_telemetry_sink::stream_block::StreamBlock;
+use micromegas_telemetry_sink::stream_info::make_stream_info;
+use micromegas_tracing::event::{EventBlock, EventStream, ExtractDeps, TracingBlock};
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::logs::{LogStream, LogStringInteropEvent};
+use micromegas_tracing::metrics::{make_metric_metadata, FloatMetricEvent, MetricsStream};
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::{
+    BeginThreadNamedSpanEvent, EndThreadNamedSpanEvent, SpanLocation, ThreadStream,
+};
+use micromegas_tracing::stream_tags::StreamTag;
+use micromegas_transit::{DynString, HeterogeneousQueue};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const BUFFER_SIZE: usize = 1024 * 1024;
+const TARGET: &str = "testkit";
+
+static TESTKIT_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Max,
+    target: TARGET,
+    module_path: "",
+    file: "",
+    line: 0,
+};
+
+pub struct ProcessEmitter {
+    service: WebIngestionService,
+    process_info: ProcessInfo,
+}
+
+impl ProcessEmitter {
+    /// Inserts a new process started now
+    pub async fn new(service: WebIngestionService, exe: &str) -> Result<Self> {
+        let process_info = ProcessInfo {
+            process_id: uuid::Uuid::new_v4(),
+            exe: exe.to_owned(),
+            username: String::new(),
+            realname: String::new(),
+            computer: String::new(),
+            distro: String::new(),
+            cpu_brand: String::new(),
+            tsc_frequency: 1_000_000_000,
+            start_time: Utc::now(),
+            start_ticks: 0,
+            parent_process_id: None,
+            properties: HashMap::new(),
+        };
+        service
+            .insert_process(encode_cbor(&process_info)?.into())
+            .await
+            .with_context(|| "inserting process")?;
+        Ok(Self {
+            service,
+            process_info,
+        })
+    }
+
+    pub fn process_id(&self) -> uuid::Uuid {
+        self.process_info.process_id
+    }
+
+    pub fn start_time(&self) -> DateTime<Utc> {
+        self.process_info.start_time
+    }
+
+    fn dual_time(&self, ticks: i64) -> DualTime {
+        DualTime {
+            ticks,
+            time: self.process_info.start_time + Duration::nanoseconds(ticks),
+        }
+    }
+
+    async fn insert_stream(&self, stream_info: &StreamInfo) -> Result<()> {
+        self.service
+            .insert_stream(encode_cbor(stream_info)?.into())
+            .await
+            .with_context(|| "inserting stream")
+    }
+
+    async fn send_block<Q>(
+        &self,
+        stream: &mut EventStream<EventBlock<Q>>,
+        begin_ticks: i64,
+        end_ticks: i64,
+    ) -> Result<()>
+    where
+        Q: HeterogeneousQueue + ExtractDeps,
+        EventBlock<Q>: TracingBlock + StreamBlock,
+    {
+        let mut block = stream.replace_block(Arc::new(EventBlock::<Q>::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            stream.stream_id(),
+            0,
+        )));
+        let block_mut = Arc::get_mut(&mut block).with_context(|| "block should not be shared")?;
+        block_mut.begin = self.dual_time(begin_ticks);
+        block_mut.end = Some(self.dual_time(end_ticks));
+        let encoded = block.encode_bin(&self.process_info)?;
+        self.service
+            .insert_block(encoded.into(), AckLevel::MetadataCommit)
+            .await
+            .with_context(|| "inserting block")
+    }
+
+    /// Sends the log entries `(time, level, message)` in a new log stream, returns its id
+    pub async fn emit_logs(&self, entries: &[(i64, Level, &str)]) -> Result<uuid::Uuid> {
+        let mut stream = LogStream::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            &[StreamTag::Log.into()],
+            HashMap::new(),
+        );
+        self.insert_stream(&make_stream_info(&stream)).await?;
+        for (time, level, msg) in entries {
+            stream.get_events_mut().push(LogStringInteropEvent {
+                time: *time,
+                level: *level as u32,
+                target: TARGET.into(),
+                msg: DynString((*msg).to_owned()),
+            });
+        }
+        let (begin, end) = time_range(entries.iter().map(|entry| entry.0));
+        self.send_block(&mut stream, begin, end).await?;
+        Ok(stream.stream_id())
+    }
+
+    /// Sends the measures `(time, metric name, value)` in a new metrics stream, returns its id
+    pub async fn emit_metrics(&self, measures: &[(i64, &str, f64)]) -> Result<uuid::Uuid> {
+        let mut stream = MetricsStream::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            &[StreamTag::Metrics.into()],
+            HashMap::new(),
+        );
+        self.insert_stream(&make_stream_info(&stream)).await?;
+        for (time, name, value) in measures {
+            stream.get_events_mut().push(FloatMetricEvent {
+                desc: make_metric_metadata(intern_string(name), "", TARGET),
+                value: *value,
+                time: *time,
+            });
+        }
+        let (begin, end) = time_range(measures.iter().map(|measure| measure.0));
+        self.send_block(&mut stream, begin, end).await?;
+        Ok(stream.stream_id())
+    }
+
+    /// Sends the spans `(begin, end, name)` in a new thread stream, returns its id
+    ///
+    /// Spans can be nested but must not partially overlap.
+    pub async fn emit_spans(&self, spans: &[(i64, i64, &str)]) -> Result<uuid::Uuid> {
+        let mut properties = HashMap::new();
+        properties.insert("thread-name".to_owned(), TARGET.to_owned());
+        let mut stream = ThreadStream::new(
+            BUFFER_SIZE,
+            self.process_info.process_id,
+            &[StreamTag::Cpu.into()],
+            properties,
+        );
+        self.insert_stream(&make_stream_info(&stream)).await?;
+        let mut sorted: Vec<&(i64, i64, &str)> = spans.iter().collect();
+        // parents first
+        sorted.sort_by_key(|(begin, end, _name)| (*begin, -*end));
+        let mut open: Vec<&(i64, i64, &str)> = vec![];
+        for span in sorted {
+            while let Some(parent) = open.last() {
+                if parent.1 > span.0 {
+                    break;
+                }
+                stream.get_events_mut().push(EndThreadNamedSpanEvent {
+                    thread_span_location: &TESTKIT_SPAN_LOCATION,
+                    name: intern_string(parent.2).into(),
+                    time: parent.1,
+                });
+                open.pop();
+            }
+            stream.get_events_mut().push(BeginThreadNamedSpanEvent {
+                thread_span_location: &TESTKIT_SPAN_LOCATION,
+                name: intern_string(span.2).into(),
+                time: span.0,
+            });
+            open.push(span);
+        }
+        while let Some(span) = open.pop() {
+            stream.get_events_mut().push(EndThreadNamedSpanEvent {
+                thread_span_location: &TESTKIT_SPAN_LOCATION,
+                name: intern_string(span.2).into(),
+                time: span.1,
+            });
+        }
+        let (begin, end) = time_range(spans.iter().flat_map(|span| [span.0, span.1]));
+        self.send_block(&mut stream, begin, end).await?;
+        Ok(stream.stream_id())
+    }
+}
+
+fn time_range(times: impl Iterator<Item = i64>) -> (i64, i64) {
+    times.fold((0, 0), |(begin, end), time| {
+        (begin.min(time), end.max(time))
+    })
+}
@@ -0,0 +1,125 @@
+//! Data lake in docker containers, with the services running in-process
+use crate::containers::{
+    start_minio, start_postgres, MinioContainer, PostgresContainer, MINIO_PASSWORD, MINIO_USER,
+};
+use crate::process_emitter::ProcessEmitter;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use datafusion::arrow::record_batch::RecordBatch;
+use micromegas_analytics::analytics_service::AnalyticsService;
+use micromegas_analytics::query_log_entries::query_log_entries;
+use micromegas_analytics::query_metrics::query_metrics;
+use micromegas_analytics::query_spans::query_spans;
+use micromegas_analytics::query_timeout::QueryDeadline;
+use micromegas_analytics::stream_query_options::StreamQueryOptions;
+use micromegas_ingestion::data_lake_connection::DataLakeConnection;
+use micromegas_ingestion::remote_data_lake::migrate_db;
+use micromegas_ingestion::web_ingestion_service::WebIngestionService;
+use micromegas_telemetry::blob_storage::BlobStorage;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path;
+use sqlx::postgres::PgPoolOptions;
+use std::sync::Arc;
+
+const BUCKET: &str = "micromegas-testkit";
+
+pub struct TestStack {
+    pub lake: DataLakeConnection,
+    pub ingestion: WebIngestionService,
+    pub analytics: AnalyticsService,
+    // the containers are removed when the stack is dropped
+    _postgres: PostgresContainer,
+    _minio: MinioContainer,
+}
+
+impl TestStack {
+    /// Starts the containers and migrates the schema of the database
+    pub async fn start() -> Result<Self> {
+        let postgres = start_postgres().await?;
+        let minio = start_minio(BUCKET).await?;
+        let s3 = Arc::new(
+            AmazonS3Builder::new()
+                .with_endpoint(&minio.endpoint)
+                .with_allow_http(true)
+                .with_bucket_name(&minio.bucket)
+                .with_access_key_id(MINIO_USER)
+                .with_secret_access_key(MINIO_PASSWORD)
+                .with_region("us-east-1")
+                .build()
+                .with_context(|| "building s3 client")?,
+        );
+        let blob_storage = BlobStorage::new(s3.clone(), Path::from("micromegas")).with_signer(s3);
+        let pool = PgPoolOptions::new()
+            .connect(&postgres.connection_string)
+            .await
+            .with_context(|| "connecting to postgres")?;
+        migrate_db(pool.clone()).await?;
+        let lake = DataLakeConnection::new(pool, Arc::new(blob_storage));
+        Ok(Self {
+            ingestion: WebIngestionService::new(lake.clone()),
+            analytics: AnalyticsService::new(lake.clone()),
+            lake,
+            _postgres: postgres,
+            _minio: minio,
+        })
+    }
+
+    /// Inserts a new process, ready to emit telemetry
+    pub async fn emit_process(&self, exe: &str) -> Result<ProcessEmitter> {
+        ProcessEmitter::new(self.ingestion.clone(), exe).await
+    }
+
+    pub async fn log_entries(
+        &self,
+        stream_id: uuid::Uuid,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<RecordBatch> {
+        query_log_entries(
+            &self.lake,
+            stream_id,
+            begin,
+            end,
+            limit,
+            &StreamQueryOptions::new(&QueryDeadline::unbounded()),
+        )
+        .await
+    }
+
+    pub async fn metrics(
+        &self,
+        stream_id: uuid::Uuid,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<RecordBatch> {
+        query_metrics(
+            &self.lake,
+            limit,
+            stream_id,
+            begin,
+            end,
+            &StreamQueryOptions::new(&QueryDeadline::unbounded()),
+        )
+        .await
+    }
+
+    pub async fn spans(
+        &self,
+        stream_id: uuid::Uuid,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<RecordBatch> {
+        query_spans(
+            &self.lake,
+            limit,
+            stream_id,
+            begin,
+            end,
+            &QueryDeadline::unbounded(),
+        )
+        .await
+    }
+}
@@ -0,0 +1,39 @@
+use futures::StreamExt;
+use micromegas_ingestion::block_notifications::NewBlockNotification;
+use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_testkit::TestStack;
+use micromegas_tracing::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_subscribers_share_a_connection() {
+    let stack = TestStack::start().await.unwrap();
+    // more subscribers than connections in the pool
+    let mut subscriptions = Vec::new();
+    for _ in 0..20 {
+        let request = encode_cbor(&HashMap::<String, String>::new()).unwrap();
+        let subscription = stack
+            .analytics
+            .subscribe_blocks(request.into())
+            .await
+            .unwrap();
+        subscriptions.push(Box::pin(subscription));
+    }
+    let process = stack.emit_process("testkit_subscription").await.unwrap();
+    let stream_id = process
+        .emit_logs(&[(1_000, Level::Info, "notified")])
+        .await
+        .unwrap();
+    for subscription in &mut subscriptions {
+        let line = tokio::time::timeout(Duration::from_secs(10), subscription.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        let block: NewBlockNotification = serde_json::from_slice(&line).unwrap();
+        assert_eq!(block.process_id, process.process_id());
+        assert_eq!(block.stream_id, stream_id);
+    }
+}
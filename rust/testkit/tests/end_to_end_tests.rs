@@ -0,0 +1,57 @@
+use chrono::{Duration, Utc};
+use micromegas_analytics::metadata::fetch_process_metadata;
+use micromegas_testkit::TestStack;
+use micromegas_tracing::prelude::*;
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_end_to_end() {
+    let stack = TestStack::start().await.unwrap();
+    let process = stack.emit_process("testkit_e2e").await.unwrap();
+    let begin = process.start_time() - Duration::seconds(1);
+    let end = Utc::now() + Duration::seconds(10);
+
+    let log_stream_id = process
+        .emit_logs(&[
+            (1_000, Level::Info, "first"),
+            (2_000, Level::Warn, "second"),
+            (3_000, Level::Error, "third"),
+        ])
+        .await
+        .unwrap();
+    let entries = stack
+        .log_entries(log_stream_id, begin, end, 1024)
+        .await
+        .unwrap();
+    assert_eq!(entries.num_rows(), 3);
+
+    let metrics_stream_id = process
+        .emit_metrics(&[(1_000, "frame_time", 16.0), (2_000, "frame_time", 17.0)])
+        .await
+        .unwrap();
+    let measures = stack
+        .metrics(metrics_stream_id, begin, end, 1024)
+        .await
+        .unwrap();
+    assert_eq!(measures.num_rows(), 2);
+
+    let thread_stream_id = process
+        .emit_spans(&[(1_000, 9_000, "frame"), (2_000, 5_000, "update")])
+        .await
+        .unwrap();
+    let spans = stack
+        .spans(thread_stream_id, begin, end, 1024)
+        .await
+        .unwrap();
+    assert_eq!(spans.num_rows(), 2);
+
+    let mut connection = stack.lake.db_pool.acquire().await.unwrap();
+    let metadata = fetch_process_metadata(&mut connection, process.process_id())
+        .await
+        .unwrap();
+    assert_eq!(metadata.streams.len(), 3);
+    assert!(metadata
+        .streams
+        .iter()
+        .all(|stream| !stream.objects_metadata.is_empty()));
+}
@@ -0,0 +1,55 @@
+use chrono::{Duration, Utc};
+use datafusion::arrow::array::{AsArray, Int64Array};
+use micromegas_analytics::property_histogram::query_property_histogram;
+use micromegas_analytics::view_config::ViewRegistry;
+use micromegas_telemetry::wire_format::encode_cbor;
+use micromegas_testkit::TestStack;
+use micromegas_tracing::dispatch::make_process_info;
+use std::collections::HashMap;
+
+#[tokio::test]
+#[ignore = "requires docker"]
+async fn test_property_histogram() {
+    let stack = TestStack::start().await.unwrap();
+    let begin = Utc::now() - Duration::seconds(1);
+    for map in ["arena", "lobby", "arena", "arena"] {
+        let mut process = make_process_info(uuid::Uuid::new_v4(), None);
+        process.properties = HashMap::from([("map".to_owned(), map.to_owned())]);
+        stack
+            .ingestion
+            .insert_process(encode_cbor(&process).unwrap().into())
+            .await
+            .unwrap();
+    }
+    let end = Utc::now() + Duration::seconds(1);
+
+    let views = ViewRegistry::default();
+    let processes = views.find_view("processes").unwrap();
+    let histogram = query_property_histogram(&stack.lake, processes, "map", begin, end, 10)
+        .await
+        .unwrap();
+    let values: Vec<&str> = histogram
+        .column_by_name("value")
+        .unwrap()
+        .as_string::<i32>()
+        .iter()
+        .flatten()
+        .collect();
+    assert_eq!(values, ["arena", "lobby"]);
+    let counts = histogram
+        .column_by_name("count")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<Int64Array>()
+        .unwrap();
+    assert_eq!(counts.values(), &[3, 1]);
+
+    let histogram = query_property_histogram(&stack.lake, processes, "map", begin, end, 1)
+        .await
+        .unwrap();
+    assert_eq!(histogram.num_rows(), 1);
+    let histogram = query_property_histogram(&stack.lake, processes, "user", begin, end, 10)
+        .await
+        .unwrap();
+    assert_eq!(histogram.num_rows(), 0);
+}
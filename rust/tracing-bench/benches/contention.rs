@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use micromegas_tracing::{
+    guards::{TracingSystemGuard, TracingThreadGuard},
+    imetric, info,
+    levels::{set_max_level, LevelFilter},
+    span_scope,
+};
+use micromegas_tracing_bench::ChannelEventSink;
+
+const NB_THREADS: usize = 64;
+const NB_EVENTS_PER_THREAD: usize = 1000;
+
+fn on_many_threads(record: fn(usize)) {
+    std::thread::scope(|scope| {
+        for _ in 0..NB_THREADS {
+            scope.spawn(move || {
+                let _thread_guard = TracingThreadGuard::new();
+                for index in 0..NB_EVENTS_PER_THREAD {
+                    record(index);
+                }
+            });
+        }
+    });
+}
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    // small buffers: the blocks fill up and are handed off often
+    set_max_level(LevelFilter::Trace);
+    let _tracing_guard = TracingSystemGuard::new(
+        64 * 1024,
+        64 * 1024,
+        16 * 1024,
+        Arc::new(ChannelEventSink::new()),
+    );
+
+    let mut group = c.benchmark_group("contention");
+    group.sample_size(20);
+    group.bench_function("static_logs", |b| {
+        b.iter(|| on_many_threads(|_index| info!("static message")));
+    });
+    group.bench_function("formatted_logs", |b| {
+        b.iter(|| on_many_threads(|index| info!("formatted message {index}")));
+    });
+    group.bench_function("metrics", |b| {
+        b.iter(|| on_many_threads(|index| imetric!("name", "unit", index as u64)));
+    });
+    group.bench_function("span_scopes", |b| {
+        b.iter(|| {
+            on_many_threads(|_index| {
+                span_scope!("test");
+            })
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use micromegas_tracing::{
+    dispatch::{flush_log_buffer, flush_thread_buffer},
+    fmetric,
+    guards::{TracingSystemGuard, TracingThreadGuard},
+    imetric, info,
+    levels::{set_max_level, LevelFilter},
+    span_scope,
+};
+use micromegas_tracing_bench::ChannelEventSink;
+
+pub fn criterion_benchmark(c: &mut Criterion) {
+    set_max_level(LevelFilter::Trace);
+    let _tracing_guard = TracingSystemGuard::new(
+        10 * 1024 * 1024,
+        1024 * 1024,
+        10 * 1024 * 1024,
+        Arc::new(ChannelEventSink::new()),
+    );
+    let _thread_guard = TracingThreadGuard::new();
+
+    let mut group = c.benchmark_group("overhead");
+    group.bench_function("static_log", |b| {
+        b.iter(|| {
+            info!("static message");
+        });
+    });
+    group.bench_function("formatted_log", |b| {
+        let mut index = 0;
+        b.iter(|| {
+            index += 1;
+            info!("formatted message {index}");
+        });
+    });
+    group.bench_function("int_metric", |b| {
+        b.iter(|| {
+            imetric!("name", "unit", 0);
+        });
+    });
+    group.bench_function("float_metric", |b| {
+        b.iter(|| {
+            fmetric!("name", "unit", 0.0);
+        });
+    });
+    group.bench_function("span_scope", |b| {
+        b.iter(|| {
+            span_scope!("test");
+        });
+    });
+    group.finish();
+
+    // blocks of 100 events closed and handed to the sink
+    let mut group = c.benchmark_group("flush");
+    group.bench_function("thread_block", |b| {
+        b.iter(|| {
+            for _ in 0..50 {
+                span_scope!("test");
+            }
+            flush_thread_buffer();
+        });
+    });
+    group.bench_function("log_block", |b| {
+        b.iter(|| {
+            for _ in 0..100 {
+                info!("static message");
+            }
+            flush_log_buffer();
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+//! Mean times of the benchmarks, saved to json and compared across runs
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Mean time of each benchmark in nanoseconds, by benchmark id, i.e. `overhead/span_scope`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Baseline {
+    pub mean_ns: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub bench: String,
+    pub baseline_ns: f64,
+    pub current_ns: f64,
+}
+
+impl Regression {
+    /// Slowdown in percent of the baseline
+    pub fn percent(&self) -> f64 {
+        (self.current_ns - self.baseline_ns) / self.baseline_ns * 100.0
+    }
+}
+
+fn read_estimates(dir: &Path, root: &Path, baseline: &mut Baseline) -> Result<()> {
+    let estimates_path = dir.join("new").join("estimates.json");
+    if estimates_path.is_file() {
+        let estimates: serde_json::Value = serde_json::from_slice(
+            &std::fs::read(&estimates_path)
+                .with_context(|| format!("reading {}", estimates_path.display()))?,
+        )
+        .with_context(|| format!("parsing {}", estimates_path.display()))?;
+        let mean = estimates["mean"]["point_estimate"]
+            .as_f64()
+            .with_context(|| format!("no mean in {}", estimates_path.display()))?;
+        let bench = dir
+            .strip_prefix(root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        baseline.mean_ns.insert(bench, mean);
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir).with_context(|| format!("listing {}", dir.display()))? {
+        let path = entry?.path();
+        // criterion keeps the html reports next to the results
+        if path.is_dir() && path.file_name().is_some_and(|name| name != "report") {
+            read_estimates(&path, root, baseline)?;
+        }
+    }
+    Ok(())
+}
+
+impl Baseline {
+    /// Latest results written by criterion, usually in `target/criterion`
+    pub fn from_criterion_dir(criterion_dir: &Path) -> Result<Self> {
+        let mut baseline = Self::default();
+        read_estimates(criterion_dir, criterion_dir, &mut baseline)?;
+        Ok(baseline)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_slice(&content).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// Benchmarks of `current` slower than in this baseline by more than `tolerance_percent`
+    ///
+    /// Benchmarks absent from either side are not compared.
+    pub fn regressions(&self, current: &Baseline, tolerance_percent: f64) -> Vec<Regression> {
+        self.mean_ns
+            .iter()
+            .filter_map(|(bench, baseline_ns)| {
+                let current_ns = *current.mean_ns.get(bench)?;
+                let regression = Regression {
+                    bench: bench.clone(),
+                    baseline_ns: *baseline_ns,
+                    current_ns,
+                };
+                (regression.percent() > tolerance_percent).then_some(regression)
+            })
+            .collect()
+    }
+}
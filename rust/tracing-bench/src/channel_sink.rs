@@ -0,0 +1,66 @@
+use micromegas_tracing::{
+    event::EventSink,
+    logs::{LogBlock, LogMetadata, LogStream},
+    metrics::{MetricsBlock, MetricsStream},
+    prelude::*,
+    spans::{ThreadBlock, ThreadStream},
+};
+use std::fmt;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+
+#[allow(dead_code)]
+enum HandedOffBlock {
+    Log(Arc<LogBlock>),
+    Metrics(Arc<MetricsBlock>),
+    Thread(Arc<ThreadBlock>),
+}
+
+/// Hands the full blocks to another thread which drops them, like the http sink without the network
+///
+/// Unlike `NullEventSink`, logs are enabled: the benchmarks measure their recording.
+pub struct ChannelEventSink {
+    sender: Sender<HandedOffBlock>,
+}
+
+impl ChannelEventSink {
+    pub fn new() -> Self {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || for _block in receiver {});
+        Self { sender }
+    }
+}
+
+impl Default for ChannelEventSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventSink for ChannelEventSink {
+    fn on_startup(&self, _: Arc<ProcessInfo>) {}
+    fn on_shutdown(&self) {}
+
+    fn on_log_enabled(&self, _: &LogMetadata) -> bool {
+        true
+    }
+    fn on_log(&self, _: &LogMetadata, _: i64, _: fmt::Arguments<'_>) {}
+    fn on_init_log_stream(&self, _: &LogStream) {}
+    fn on_process_log_block(&self, block: Arc<LogBlock>) {
+        let _ = self.sender.send(HandedOffBlock::Log(block));
+    }
+
+    fn on_init_metrics_stream(&self, _: &MetricsStream) {}
+    fn on_process_metrics_block(&self, block: Arc<MetricsBlock>) {
+        let _ = self.sender.send(HandedOffBlock::Metrics(block));
+    }
+
+    fn on_init_thread_stream(&self, _: &ThreadStream) {}
+    fn on_process_thread_block(&self, block: Arc<ThreadBlock>) {
+        let _ = self.sender.send(HandedOffBlock::Thread(block));
+    }
+
+    fn is_busy(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,21 @@
+//! tracing-bench : benchmarks tracking the overhead of the instrumentation across releases
+//!
+//! `cargo bench -p micromegas-tracing-bench` measures the cost of recording events and flushing
+//! their blocks. `bench-baseline save` then writes the mean times to a json baseline and
+//! `bench-baseline compare` reports the benchmarks that got slower than the baseline.
+//!
+//! ```text
+//! cargo bench -p micromegas-tracing-bench
+//! cargo run -p micromegas-tracing-bench --bin bench-baseline -- save baseline.json
+//! # ... changes ...
+//! cargo bench -p micromegas-tracing-bench
+//! cargo run -p micromegas-tracing-bench --bin bench-baseline -- compare baseline.json
+//! ```
+
+// crate-specific lint exceptions:
+#![allow(clippy::missing_errors_doc)]
+
+pub mod baseline;
+pub mod channel_sink;
+
+pub use channel_sink::ChannelEventSink;
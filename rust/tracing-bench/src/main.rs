@@ -0,0 +1,70 @@
+//! Saves the results of the benchmarks as a json baseline and compares new results to it
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use micromegas_tracing_bench::baseline::Baseline;
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[clap(name = "bench-baseline")]
+#[clap(about = "Tracks the results of the micromegas benchmarks", version)]
+#[clap(arg_required_else_help(true))]
+struct Cli {
+    /// Where criterion writes its results
+    #[clap(long, default_value = "target/criterion")]
+    criterion_dir: PathBuf,
+
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Writes the mean times of the latest run to a json file
+    #[clap(name = "save")]
+    Save { baseline: PathBuf },
+
+    /// Lists the benchmarks of the latest run slower than the baseline, fails if there are any
+    #[clap(name = "compare")]
+    Compare {
+        baseline: PathBuf,
+        /// Slowdown in percent tolerated as noise
+        #[clap(long, default_value_t = 10.0)]
+        tolerance: f64,
+    },
+}
+
+fn main() -> Result<()> {
+    let args = Cli::parse();
+    let current = Baseline::from_criterion_dir(&args.criterion_dir)?;
+    match args.command {
+        Commands::Save { baseline } => {
+            current.save(&baseline)?;
+            println!(
+                "saved {} benchmarks to {}",
+                current.mean_ns.len(),
+                baseline.display()
+            );
+        }
+        Commands::Compare {
+            baseline,
+            tolerance,
+        } => {
+            let regressions = Baseline::load(&baseline)?.regressions(&current, tolerance);
+            for regression in &regressions {
+                println!(
+                    "{}: {:.1}ns -> {:.1}ns (+{:.1}%)",
+                    regression.bench,
+                    regression.baseline_ns,
+                    regression.current_ns,
+                    regression.percent()
+                );
+            }
+            if !regressions.is_empty() {
+                anyhow::bail!("{} benchmarks regressed", regressions.len());
+            }
+            println!("no regression beyond {tolerance}%");
+        }
+    }
+    Ok(())
+}
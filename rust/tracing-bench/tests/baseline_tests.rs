@@ -0,0 +1,47 @@
+use micromegas_tracing_bench::baseline::Baseline;
+use std::path::Path;
+
+fn write_estimates(criterion_dir: &Path, bench: &str, mean: f64) {
+    let dir = criterion_dir.join(bench).join("new");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("estimates.json"),
+        format!(r#"{{"mean":{{"point_estimate":{mean},"standard_error":0.1}},"median":{{"point_estimate":1.0}}}}"#),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_baseline_from_criterion_results() {
+    let criterion_dir =
+        std::env::temp_dir().join(format!("micromegas-bench-{}", std::process::id()));
+    write_estimates(&criterion_dir, "overhead/span_scope", 20.0);
+    write_estimates(&criterion_dir, "overhead/static_log", 30.0);
+    std::fs::create_dir_all(criterion_dir.join("report")).unwrap();
+
+    let baseline = Baseline::from_criterion_dir(&criterion_dir).unwrap();
+    assert_eq!(baseline.mean_ns.len(), 2);
+    assert_eq!(baseline.mean_ns["overhead/span_scope"], 20.0);
+
+    let saved = criterion_dir.join("baseline.json");
+    baseline.save(&saved).unwrap();
+    assert_eq!(Baseline::load(&saved).unwrap(), baseline);
+    std::fs::remove_dir_all(&criterion_dir).unwrap();
+}
+
+#[test]
+fn test_regressions() {
+    let mut baseline = Baseline::default();
+    baseline.mean_ns.insert("overhead/span_scope".into(), 20.0);
+    baseline.mean_ns.insert("overhead/static_log".into(), 30.0);
+    baseline.mean_ns.insert("overhead/removed".into(), 10.0);
+    let mut current = Baseline::default();
+    current.mean_ns.insert("overhead/span_scope".into(), 25.0);
+    current.mean_ns.insert("overhead/static_log".into(), 31.0);
+    current.mean_ns.insert("overhead/added".into(), 100.0);
+    let regressions = baseline.regressions(&current, 10.0);
+    assert_eq!(regressions.len(), 1);
+    assert_eq!(regressions[0].bench, "overhead/span_scope");
+    assert_eq!(regressions[0].percent(), 25.0);
+    assert!(baseline.regressions(&current, 30.0).is_empty());
+}
@@ -0,0 +1,45 @@
+/* C interface to micromegas tracing, implemented by the micromegas-tracing-ffi crate */
+#ifndef MICROMEGAS_H
+#define MICROMEGAS_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct MicromegasTelemetryGuard MicromegasTelemetryGuard;
+
+/* to be called before micromegas_init */
+void micromegas_set_process_property(const char* key, const char* value);
+/* can be called at any time, the latest value of a property wins */
+void micromegas_update_process_property(const char* key, const char* value);
+/* at most max_ms of spans named span_name per frame, returns false if invalid */
+bool micromegas_declare_frame_budget(const char* span_name, double max_ms);
+
+/* sends to MICROMEGAS_TELEMETRY_URL if set, returns NULL on failure */
+MicromegasTelemetryGuard* micromegas_init(void);
+/* from the thread that called micromegas_init */
+void micromegas_shutdown(MicromegasTelemetryGuard* guard);
+
+/* required before sending spans from a thread */
+void micromegas_register_thread(const char* thread_name);
+void micromegas_unregister_thread(void);
+
+/* level: 1 fatal, 2 error, 3 warn, 4 info, 5 debug, 6 trace */
+void micromegas_log(uint32_t level, const char* target, const char* message);
+
+void micromegas_int_metric(const char* name, const char* unit, uint64_t value);
+void micromegas_float_metric(const char* name, const char* unit, double value);
+
+void micromegas_begin_span(const char* name);
+void micromegas_end_span(const char* name);
+
+void micromegas_flush(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,222 @@
+//! C interface to micromegas tracing, for engines and tools written in C or C++
+//!
+//! See `include/micromegas.h` for the declarations. Strings are expected to be null-terminated
+//! and valid utf-8, invalid strings are replaced lossily. Span and metric names are interned:
+//! they should come from a bounded set.
+
+// crate-specific lint exceptions:
+#![allow(clippy::missing_safety_doc)]
+
+use micromegas_telemetry_sink::{TelemetryGuard, TelemetryGuardBuilder};
+use micromegas_tracing::dispatch::{
+    float_metric, flush_log_buffer, flush_metrics_buffer, flush_thread_buffer,
+    init_named_thread_stream, int_metric, log_interop, on_begin_named_scope, on_end_named_scope,
+    set_process_property, update_process_property,
+};
+use micromegas_tracing::frame_budgets::declare_frame_budget;
+use micromegas_tracing::intern_string::intern_string;
+use micromegas_tracing::levels::{Level, Verbosity};
+use micromegas_tracing::logs::{LogMetadata, FILTER_LEVEL_UNSET_VALUE};
+use micromegas_tracing::metrics::{make_metric_metadata, MetricMetadata};
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::SpanLocation;
+use std::borrow::Cow;
+use std::ffi::{c_char, CStr};
+use std::sync::atomic::AtomicU32;
+
+static FFI_SPAN_LOCATION: SpanLocation = SpanLocation {
+    lod: Verbosity::Min,
+    target: "ffi",
+    module_path: "ffi",
+    file: "ffi",
+    line: 0,
+};
+
+unsafe fn to_str<'a>(text: *const c_char) -> Cow<'a, str> {
+    if text.is_null() {
+        return Cow::Borrowed("");
+    }
+    CStr::from_ptr(text).to_string_lossy()
+}
+
+unsafe fn metric_metadata(name: *const c_char, unit: *const c_char) -> &'static MetricMetadata {
+    make_metric_metadata(
+        intern_string(&to_str(name)),
+        intern_string(&to_str(unit)),
+        "ffi",
+    )
+}
+
+/// Adds a property to the process, to be called before `micromegas_init`
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_set_process_property(key: *const c_char, value: *const c_char) {
+    set_process_property(&to_str(key), &to_str(value));
+}
+
+/// Adds or updates a property of the process, can be called at any time
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_update_process_property(
+    key: *const c_char,
+    value: *const c_char,
+) {
+    update_process_property(&to_str(key), &to_str(value));
+}
+
+/// Declares the time budget of spans per frame, to be called before `micromegas_init`.
+/// Returns false if the budget is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_declare_frame_budget(
+    span_name: *const c_char,
+    max_ms: f64,
+) -> bool {
+    match declare_frame_budget(&to_str(span_name), max_ms) {
+        Ok(()) => true,
+        Err(e) => {
+            error!("Error declaring frame budget: {e:?}");
+            false
+        }
+    }
+}
+
+/// Initializes the telemetry, sent to `MICROMEGAS_TELEMETRY_URL` if it is set.
+/// Returns null on failure. The calling thread is registered.
+#[no_mangle]
+pub extern "C" fn micromegas_init() -> *mut TelemetryGuard {
+    match TelemetryGuardBuilder::default().build() {
+        Ok(guard) => Box::into_raw(Box::new(guard)),
+        Err(e) => {
+            error!("Error initializing micromegas telemetry: {e:?}");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Flushes and stops the telemetry, from the thread that called `micromegas_init`
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_shutdown(guard: *mut TelemetryGuard) {
+    if !guard.is_null() {
+        drop(Box::from_raw(guard));
+    }
+}
+
+/// Registers the calling thread under that name, required before sending spans from it
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_register_thread(thread_name: *const c_char) {
+    init_named_thread_stream(&to_str(thread_name));
+}
+
+/// Sends the spans of the calling thread, to be called before the thread exits
+#[no_mangle]
+pub extern "C" fn micromegas_unregister_thread() {
+    flush_thread_buffer();
+}
+
+/// `level`: 1 fatal, 2 error, 3 warn, 4 info, 5 debug, 6 trace
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_log(level: u32, target: *const c_char, message: *const c_char) {
+    let Some(level) = Level::from_value(level) else {
+        return;
+    };
+    let target = to_str(target);
+    let metadata = LogMetadata {
+        level,
+        level_filter: AtomicU32::new(FILTER_LEVEL_UNSET_VALUE),
+        fmt_str: "",
+        target: &target,
+        module_path: "ffi",
+        file: "ffi",
+        line: 0,
+    };
+    log_interop(&metadata, format_args!("{}", to_str(message)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_int_metric(
+    name: *const c_char,
+    unit: *const c_char,
+    value: u64,
+) {
+    int_metric(metric_metadata(name, unit), value);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_float_metric(
+    name: *const c_char,
+    unit: *const c_char,
+    value: f64,
+) {
+    float_metric(metric_metadata(name, unit), value);
+}
+
+/// Begins a span on the calling thread, closed by `micromegas_end_span` with the same name
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_begin_span(name: *const c_char) {
+    on_begin_named_scope(&FFI_SPAN_LOCATION, intern_string(&to_str(name)));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn micromegas_end_span(name: *const c_char) {
+    on_end_named_scope(&FFI_SPAN_LOCATION, intern_string(&to_str(name)));
+}
+
+/// Sends the buffered logs and metrics, and the spans of the calling thread
+#[no_mangle]
+pub extern "C" fn micromegas_flush() {
+    flush_log_buffer();
+    flush_metrics_buffer();
+    flush_thread_buffer();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::null;
+
+    #[test]
+    fn test_to_str() {
+        unsafe {
+            assert_eq!(to_str(null()), "");
+            assert_eq!(to_str(c"hello".as_ptr()), "hello");
+            let invalid = c"caf\xe9";
+            assert_eq!(to_str(invalid.as_ptr()), "caf\u{fffd}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let invalid = c"\xff\xfe".as_ptr();
+        unsafe {
+            assert!(!micromegas_declare_frame_budget(null(), 16.0));
+            assert!(!micromegas_declare_frame_budget(c"frame".as_ptr(), -1.0));
+            assert!(micromegas_declare_frame_budget(invalid, 16.0));
+            micromegas_set_process_property(null(), null());
+            micromegas_update_process_property(invalid, invalid);
+            micromegas_log(0, c"target".as_ptr(), c"unknown level".as_ptr());
+            micromegas_log(3, null(), null());
+            micromegas_int_metric(null(), null(), 1);
+            micromegas_float_metric(invalid, invalid, 1.0);
+            micromegas_begin_span(null());
+            micromegas_end_span(null());
+            micromegas_shutdown(std::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_init_and_shutdown() {
+        unsafe {
+            micromegas_set_process_property(c"build".as_ptr(), c"test".as_ptr());
+            let guard = micromegas_init();
+            assert!(!guard.is_null());
+            micromegas_register_thread(c"main".as_ptr());
+            micromegas_begin_span(c"frame".as_ptr());
+            micromegas_log(4, c"ffi_test".as_ptr(), c"\xc3\x28".as_ptr());
+            micromegas_int_metric(c"frame_count".as_ptr(), c"count".as_ptr(), 1);
+            micromegas_float_metric(c"frame_time".as_ptr(), c"ms".as_ptr(), 16.6);
+            micromegas_end_span(c"frame".as_ptr());
+            micromegas_update_process_property(c"state".as_ptr(), c"done".as_ptr());
+            micromegas_flush();
+            micromegas_unregister_thread();
+            micromegas_shutdown(guard);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-//! `log_fn` and `span_fn` procedural macros
+//! `log_fn`, `span_fn` and `span_all_fns` procedural macros
 //!
 //! Injects instrumentation into sync and async functions.
 //!     async trait functions not supported
@@ -14,7 +14,7 @@ use syn::{
     parse::{Parse, ParseStream, Result},
     parse_macro_input, parse_quote,
     punctuated::Punctuated,
-    Ident, ItemFn, Token,
+    Attribute, Block, Ident, ImplItem, Item, ItemFn, ItemImpl, ItemMod, Signature, Token, Type,
 };
 
 struct TraceArgs {
@@ -55,15 +55,104 @@ pub fn span_fn(
         .alternative_name
         .map_or(function.sig.ident.to_string(), |n| n.to_string());
 
-    function.block.stmts.insert(
+    instrument_block(&mut function.block, &function_name);
+
+    proc_macro::TokenStream::from(quote! {
+        #function
+    })
+}
+
+fn instrument_block(block: &mut Block, function_name: &str) {
+    block.stmts.insert(
         0,
         parse_quote! {
             micromegas_tracing::span_scope!(_METADATA_FUNC, concat!(module_path!(), "::", #function_name));
         },
     );
+}
+
+const NO_SPAN_ATTRIBUTE: &str = "no_span";
+
+/// Removes the opt-out attribute, returns whether the function should be instrumented
+fn take_span_attributes(attrs: &mut Vec<Attribute>, sig: &Signature) -> bool {
+    let nb_attrs = attrs.len();
+    attrs.retain(|attr| !attr.path.is_ident(NO_SPAN_ATTRIBUTE));
+    let opted_out = attrs.len() != nb_attrs;
+    let already_instrumented = attrs.iter().any(|attr| {
+        attr.path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "span_fn")
+    });
+    // like span_fn, async functions are left alone
+    !opted_out && !already_instrumented && sig.asyncness.is_none() && sig.constness.is_none()
+}
+
+fn type_name(self_ty: &Type) -> String {
+    match self_ty {
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .last()
+            .map_or_else(|| quote!(#self_ty).to_string(), |s| s.ident.to_string()),
+        _ => quote!(#self_ty).to_string(),
+    }
+}
+
+fn instrument_impl(item_impl: &mut ItemImpl) {
+    let type_name = type_name(&item_impl.self_ty);
+    for item in &mut item_impl.items {
+        if let ImplItem::Method(method) = item {
+            if take_span_attributes(&mut method.attrs, &method.sig) {
+                let function_name = format!("{type_name}::{}", method.sig.ident);
+                instrument_block(&mut method.block, &function_name);
+            }
+        }
+    }
+}
+
+fn instrument_fn(function: &mut ItemFn) {
+    if take_span_attributes(&mut function.attrs, &function.sig) {
+        let function_name = function.sig.ident.to_string();
+        instrument_block(&mut function.block, &function_name);
+    }
+}
+
+fn instrument_mod(item_mod: &mut ItemMod) {
+    let Some((_brace, items)) = &mut item_mod.content else {
+        return;
+    };
+    for item in items {
+        match item {
+            Item::Fn(function) => instrument_fn(function),
+            Item::Impl(item_impl) => instrument_impl(item_impl),
+            _ => {}
+        }
+    }
+}
 
+/// Applies `span_fn` to every method of an `impl` block, or to every function and method of an inline module
+///
+/// Functions marked with `#[no_span]` are left alone, as are async and const functions.
+#[proc_macro_attribute]
+pub fn span_all_fns(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    assert!(args.is_empty());
+    let mut item = parse_macro_input!(input as Item);
+    match &mut item {
+        Item::Impl(item_impl) => instrument_impl(item_impl),
+        Item::Mod(item_mod) => instrument_mod(item_mod),
+        _ => {
+            return proc_macro::TokenStream::from(
+                syn::Error::new_spanned(&item, "span_all_fns applies to impl blocks and modules")
+                    .to_compile_error(),
+            )
+        }
+    }
     proc_macro::TokenStream::from(quote! {
-        #function
+        #item
     })
 }
 
@@ -0,0 +1,160 @@
+//! Container and orchestrator metadata, recorded as process properties
+//!
+//! cgroup v2 is read first, falling back on cgroup v1 paths.
+//! Orchestrator metadata is taken from the environment,
+//! which is how kubernetes exposes it through the downward API.
+use std::collections::HashMap;
+
+/// Resource limits imposed on the process by its cgroup
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CgroupLimits {
+    /// number of cores the cgroup is allowed to use
+    pub cpu_quota: Option<f64>,
+    /// bytes
+    pub memory_limit: Option<u64>,
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_owned())
+}
+
+/// parses the content of cgroup v2's cpu.max, formatted as `$MAX $PERIOD`
+pub fn parse_cpu_max(content: &str) -> Option<f64> {
+    let mut fields = content.split_whitespace();
+    let quota = fields.next()?.parse::<f64>().ok()?;
+    let period = fields.next()?.parse::<f64>().ok()?;
+    if period > 0.0 {
+        Some(quota / period)
+    } else {
+        None
+    }
+}
+
+/// parses a memory limit where `max` or an absurdly large value means unlimited
+pub fn parse_memory_limit(content: &str) -> Option<u64> {
+    let limit = content.parse::<u64>().ok()?;
+    // cgroup v1 reports unlimited as a page-aligned i64::MAX
+    if limit >= (i64::MAX as u64) / 2 {
+        None
+    } else {
+        Some(limit)
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn read_cpu_quota() -> Option<f64> {
+    if let Some(content) = read_trimmed("/sys/fs/cgroup/cpu.max") {
+        return parse_cpu_max(&content);
+    }
+    let quota = read_trimmed("/sys/fs/cgroup/cpu/cpu.cfs_quota_us")?
+        .parse::<i64>()
+        .ok()?;
+    let period = read_trimmed("/sys/fs/cgroup/cpu/cpu.cfs_period_us")?
+        .parse::<i64>()
+        .ok()?;
+    if quota > 0 && period > 0 {
+        Some(quota as f64 / period as f64)
+    } else {
+        None
+    }
+}
+
+fn read_memory_limit() -> Option<u64> {
+    read_trimmed("/sys/fs/cgroup/memory.max")
+        .or_else(|| read_trimmed("/sys/fs/cgroup/memory/memory.limit_in_bytes"))
+        .and_then(|content| parse_memory_limit(&content))
+}
+
+/// Memory currently charged to the cgroup, in bytes
+pub fn read_cgroup_memory_usage() -> Option<u64> {
+    read_trimmed("/sys/fs/cgroup/memory.current")
+        .or_else(|| read_trimmed("/sys/fs/cgroup/memory/memory.usage_in_bytes"))
+        .and_then(|content| content.parse::<u64>().ok())
+}
+
+pub fn read_cgroup_limits() -> CgroupLimits {
+    CgroupLimits {
+        cpu_quota: read_cpu_quota(),
+        memory_limit: read_memory_limit(),
+    }
+}
+
+/// finds a container id (64 hex digits) in the content of /proc/self/cgroup
+pub fn parse_container_id(cgroup_content: &str) -> Option<String> {
+    for line in cgroup_content.lines() {
+        let path = line.rsplit(':').next().unwrap_or_default();
+        for segment in path.split('/').rev() {
+            // docker-<id>.scope, cri-containerd-<id>.scope, crio-<id>.scope or <id>
+            let candidate = segment.trim_end_matches(".scope");
+            let candidate = candidate.rsplit('-').next().unwrap_or(candidate);
+            if candidate.len() == 64 && candidate.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Some(candidate.to_owned());
+            }
+        }
+    }
+    None
+}
+
+pub fn container_properties() -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    let limits = read_cgroup_limits();
+    if let Some(cpu_quota) = limits.cpu_quota {
+        properties.insert("cgroup_cpu_quota".to_owned(), cpu_quota.to_string());
+    }
+    if let Some(memory_limit) = limits.memory_limit {
+        properties.insert("cgroup_memory_limit".to_owned(), memory_limit.to_string());
+    }
+    if let Some(container_id) =
+        read_trimmed("/proc/self/cgroup").and_then(|content| parse_container_id(&content))
+    {
+        properties.insert("container_id".to_owned(), container_id);
+    }
+    if std::env::var("KUBERNETES_SERVICE_HOST").is_ok() {
+        properties.insert("orchestrator".to_owned(), "kubernetes".to_owned());
+        let pod_name = std::env::var("POD_NAME").or_else(|_| std::env::var("HOSTNAME"));
+        if let Ok(pod_name) = pod_name {
+            properties.insert("k8s_pod_name".to_owned(), pod_name);
+        }
+        if let Ok(namespace) = std::env::var("POD_NAMESPACE") {
+            properties.insert("k8s_namespace".to_owned(), namespace);
+        }
+        if let Ok(node_name) = std::env::var("NODE_NAME") {
+            properties.insert("k8s_node_name".to_owned(), node_name);
+        }
+    }
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_max() {
+        assert_eq!(parse_cpu_max("200000 100000"), Some(2.0));
+        assert_eq!(parse_cpu_max("max 100000"), None);
+    }
+
+    #[test]
+    fn test_parse_memory_limit() {
+        assert_eq!(parse_memory_limit("536870912"), Some(536_870_912));
+        assert_eq!(parse_memory_limit("max"), None);
+        assert_eq!(parse_memory_limit("9223372036854771712"), None);
+    }
+
+    #[test]
+    fn test_parse_container_id() {
+        let id = "4f1b2a9c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f7a6b5c4d3e2f1a";
+        assert_eq!(
+            parse_container_id(&format!("0::/system.slice/docker-{id}.scope")),
+            Some(id.to_owned())
+        );
+        assert_eq!(
+            parse_container_id(&format!("12:memory:/kubepods/burstable/pod1234/{id}")),
+            Some(id.to_owned())
+        );
+        assert_eq!(parse_container_id("0::/"), None);
+    }
+}
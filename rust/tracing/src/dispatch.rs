@@ -2,19 +2,23 @@ pub use crate::errors::{Error, Result};
 use crate::intern_string::intern_string;
 use crate::prelude::*;
 use crate::{
-    event::{EventSink, NullEventSink, TracingBlock},
+    event::{EventSink, NullEventSink, ProcessAttachment, TracingBlock},
     info,
     logs::{
         LogBlock, LogMetadata, LogStaticStrEvent, LogStaticStrInteropEvent, LogStream,
         LogStringEvent, LogStringInteropEvent,
     },
-    metrics::{FloatMetricEvent, IntegerMetricEvent, MetricMetadata, MetricsBlock, MetricsStream},
+    metrics::{
+        aggregate_metric, drain_aggregated_metrics, is_metric_aggregated, FloatMetricEvent,
+        IntegerMetricEvent, MetricMetadata, MetricsBlock, MetricsStream,
+    },
     spans::{
         BeginAsyncNamedSpanEvent, BeginAsyncSpanEvent, BeginThreadNamedSpanEvent,
         BeginThreadSpanEvent, EndAsyncNamedSpanEvent, EndAsyncSpanEvent, EndThreadNamedSpanEvent,
-        EndThreadSpanEvent, SpanLocation, SpanMetadata, ThreadBlock, ThreadEventQueueTypeIndex,
-        ThreadStream,
+        EndThreadSpanEvent, SpanLocation, SpanMarkerEvent, SpanMetadata, ThreadBlock,
+        ThreadEventQueueTypeIndex, ThreadStream,
     },
+    stream_tags::StreamTag,
     warn,
 };
 use chrono::Utc;
@@ -57,10 +61,43 @@ pub fn process_id() -> Option<uuid::Uuid> {
     unsafe { G_DISPATCH.as_ref().map(Dispatch::get_process_id) }
 }
 
+/// Process that spawned the current one, as advertised by `MICROMEGAS_TELEMETRY_PARENT_PROCESS` at startup
+pub fn parent_process_id() -> Option<uuid::Uuid> {
+    #[allow(static_mut_refs)]
+    unsafe {
+        G_DISPATCH.as_ref().and_then(|d| d.parent_process_id)
+    }
+}
+
 pub fn get_sink() -> Option<Arc<dyn EventSink>> {
     unsafe { G_DISPATCH.as_ref().map(Dispatch::get_sink) }
 }
 
+/// Sends a named binary artifact along with the telemetry of the process, e.g. the savegame
+/// or screenshot needed to debug a crash
+pub fn attach_to_process(name: &str, content_type: &str, data: Vec<u8>) {
+    if let Some(sink) = get_sink() {
+        sink.on_attachment(Arc::new(ProcessAttachment {
+            name: name.to_owned(),
+            content_type: content_type.to_owned(),
+            data,
+        }));
+    }
+}
+
+// exit code and panic message reported before shutdown
+static PROCESS_EXIT_STATE: Mutex<(Option<i32>, Option<String>)> = Mutex::new((None, None));
+
+/// Exit code sent with the process exit at shutdown
+pub fn set_exit_code(exit_code: i32) {
+    PROCESS_EXIT_STATE.lock().unwrap().0 = Some(exit_code);
+}
+
+/// Flags the process as having panicked, called by the panic hook
+pub fn record_panic(message: String) {
+    PROCESS_EXIT_STATE.lock().unwrap().1 = Some(message);
+}
+
 pub fn shutdown_dispatch() {
     unsafe {
         #[allow(static_mut_refs)]
@@ -146,13 +183,22 @@ pub fn flush_metrics_buffer() {
 // threads
 #[inline(always)]
 pub fn init_thread_stream() {
+    init_thread_stream_impl(std::thread::current().name());
+}
+
+/// For threads not created by rust, which have no name in `std::thread`
+pub fn init_named_thread_stream(thread_name: &str) {
+    init_thread_stream_impl(Some(thread_name));
+}
+
+fn init_thread_stream_impl(thread_name: Option<&str>) {
     LOCAL_THREAD_STREAM.with(|cell| unsafe {
         if (*cell.as_ptr()).is_some() {
             return;
         }
         #[allow(static_mut_refs)]
         if let Some(d) = &mut G_DISPATCH {
-            d.init_thread_stream(cell);
+            d.init_thread_stream(cell, thread_name);
         } else {
             warn!("dispatch not initialized, cannot init thread stream, events will be lost for this thread");
         }
@@ -220,6 +266,15 @@ pub fn on_end_named_scope(thread_span_location: &'static SpanLocation, name: &'s
     });
 }
 
+#[inline(always)]
+pub fn on_span_event(marker_desc: &'static SpanMetadata, properties: &'static str) {
+    on_thread_event(SpanMarkerEvent {
+        marker_desc,
+        properties: properties.into(),
+        time: now(),
+    });
+}
+
 #[inline(always)]
 pub fn on_begin_async_scope(scope: &'static SpanMetadata) -> u64 {
     let id = unsafe { G_ASYNC_SPAN_COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed) };
@@ -292,6 +347,7 @@ where
 
 struct Dispatch {
     process_id: uuid::Uuid,
+    parent_process_id: Option<uuid::Uuid>,
     logs_buffer_size: usize,
     metrics_buffer_size: usize,
     threads_buffer_size: usize,
@@ -311,19 +367,20 @@ impl Dispatch {
         let process_id = uuid::Uuid::new_v4();
         let mut obj = Self {
             process_id,
+            parent_process_id: None,
             logs_buffer_size,
             metrics_buffer_size,
             threads_buffer_size,
             log_stream: Mutex::new(LogStream::new(
                 logs_buffer_size,
                 process_id,
-                &[String::from("log")],
+                &[StreamTag::Log.into()],
                 HashMap::new(),
             )),
             metrics_stream: Mutex::new(MetricsStream::new(
                 metrics_buffer_size,
                 process_id,
-                &[String::from("metrics")],
+                &[StreamTag::Metrics.into()],
                 HashMap::new(),
             )),
             thread_streams: Mutex::new(vec![]),
@@ -344,6 +401,13 @@ impl Dispatch {
     }
 
     fn shutdown(&mut self) {
+        let (exit_code, panic_message) = PROCESS_EXIT_STATE.lock().unwrap().clone();
+        self.sink.on_process_exit(Arc::new(ProcessExit {
+            process_id: self.process_id,
+            exit_time: Utc::now(),
+            exit_code,
+            panic_message,
+        }));
         self.sink.on_shutdown();
         self.sink = Arc::new(NullEventSink {});
     }
@@ -359,6 +423,7 @@ impl Dispatch {
             "MICROMEGAS_TELEMETRY_PARENT_PROCESS",
             self.process_id.to_string(),
         );
+        self.parent_process_id = parent_process;
         let process_info = Arc::new(make_process_info(self.process_id, parent_process));
         self.sink.on_startup(process_info);
     }
@@ -373,16 +438,16 @@ impl Dispatch {
         self.sink.on_init_metrics_stream(&metrics_stream);
     }
 
-    fn init_thread_stream(&mut self, cell: &Cell<Option<ThreadStream>>) {
+    fn init_thread_stream(&mut self, cell: &Cell<Option<ThreadStream>>, thread_name: Option<&str>) {
         let mut properties = HashMap::new();
         properties.insert(String::from("thread-id"), thread_id::get().to_string());
-        if let Some(name) = std::thread::current().name() {
+        if let Some(name) = thread_name {
             properties.insert("thread-name".to_owned(), name.to_owned());
         }
         let thread_stream = ThreadStream::new(
             self.threads_buffer_size,
             self.process_id,
-            &["cpu".to_owned()],
+            &[StreamTag::Cpu.into()],
             properties,
         );
         unsafe {
@@ -404,6 +469,11 @@ impl Dispatch {
     #[inline]
     fn int_metric(&mut self, desc: &'static MetricMetadata, value: u64) {
         let time = now();
+        if is_metric_aggregated(desc) {
+            #[allow(clippy::cast_precision_loss)]
+            aggregate_metric(desc, value as f64, time);
+            return;
+        }
         let mut metrics_stream = self.metrics_stream.lock().unwrap();
         metrics_stream
             .get_events_mut()
@@ -418,6 +488,10 @@ impl Dispatch {
     #[inline]
     fn float_metric(&mut self, desc: &'static MetricMetadata, value: f64) {
         let time = now();
+        if is_metric_aggregated(desc) {
+            aggregate_metric(desc, value, time);
+            return;
+        }
         let mut metrics_stream = self.metrics_stream.lock().unwrap();
         metrics_stream
             .get_events_mut()
@@ -431,7 +505,11 @@ impl Dispatch {
 
     #[inline]
     fn flush_metrics_buffer(&mut self) {
+        let aggregated = drain_aggregated_metrics();
         let mut metrics_stream = self.metrics_stream.lock().unwrap();
+        for event in aggregated {
+            metrics_stream.get_events_mut().push(event);
+        }
         if metrics_stream.is_empty() {
             return;
         }
@@ -444,7 +522,7 @@ impl Dispatch {
             stream_id,
             next_offset,
         )));
-        assert!(!metrics_stream.is_full());
+        assert!(metrics_stream.is_empty());
         Arc::get_mut(&mut old_event_block).unwrap().close();
         self.sink.on_process_metrics_block(old_event_block);
     }
@@ -460,19 +538,24 @@ impl Dispatch {
         }
         let time = now();
         self.sink.on_log(metadata, time, args);
-        let mut log_stream = self.log_stream.lock().unwrap();
-        if args.as_str().is_some() {
+        let log_stream = if args.as_str().is_some() {
+            let mut log_stream = self.log_stream.lock().unwrap();
             log_stream.get_events_mut().push(LogStaticStrEvent {
                 desc: metadata,
                 time,
             });
+            log_stream
         } else {
+            // formatted before locking the stream shared by all threads
+            let dyn_str = micromegas_transit::DynString(args.to_string());
+            let mut log_stream = self.log_stream.lock().unwrap();
             log_stream.get_events_mut().push(LogStringEvent {
                 desc: metadata,
                 time,
-                dyn_str: micromegas_transit::DynString(args.to_string()),
+                dyn_str,
             });
-        }
+            log_stream
+        };
         if log_stream.is_full() {
             // Release the lock before calling flush_log_buffer
             drop(log_stream);
@@ -484,22 +567,27 @@ impl Dispatch {
     fn log_interop(&mut self, desc: &LogMetadata, args: fmt::Arguments<'_>) {
         let time = now();
         self.sink.on_log(desc, time, args);
-        let mut log_stream = self.log_stream.lock().unwrap();
-        if let Some(msg) = args.as_str() {
+        let target = intern_string(desc.target);
+        let log_stream = if let Some(msg) = args.as_str() {
+            let mut log_stream = self.log_stream.lock().unwrap();
             log_stream.get_events_mut().push(LogStaticStrInteropEvent {
                 time,
                 level: desc.level as u32,
-                target: intern_string(desc.target).into(),
+                target: target.into(),
                 msg: msg.into(),
             });
+            log_stream
         } else {
+            let msg = micromegas_transit::DynString(args.to_string());
+            let mut log_stream = self.log_stream.lock().unwrap();
             log_stream.get_events_mut().push(LogStringInteropEvent {
                 time,
                 level: desc.level as u32,
-                target: intern_string(desc.target).into(),
-                msg: micromegas_transit::DynString(args.to_string()),
+                target: target.into(),
+                msg,
             });
-        }
+            log_stream
+        };
         if log_stream.is_full() {
             // Release the lock before calling flush_log_buffer
             drop(log_stream);
@@ -522,7 +610,7 @@ impl Dispatch {
             stream_id,
             next_offset,
         )));
-        assert!(!log_stream.is_full());
+        assert!(log_stream.is_empty());
         Arc::get_mut(&mut old_event_block).unwrap().close();
         self.sink.on_process_log_block(old_event_block);
     }
@@ -540,7 +628,7 @@ impl Dispatch {
             stream.stream_id(),
             next_offset,
         )));
-        assert!(!stream.is_full());
+        assert!(stream.is_empty());
         Arc::get_mut(&mut old_block).unwrap().close();
         self.sink.on_process_thread_block(old_block);
     }
@@ -577,6 +665,55 @@ pub fn make_process_info(
         start_time,
         start_ticks,
         parent_process_id,
-        properties: HashMap::new(),
+        properties: process_properties(),
+    }
+}
+
+lazy_static! {
+    static ref EXTRA_PROCESS_PROPERTIES: Mutex<HashMap<String, String>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Adds a property to the process, only effective before the telemetry is initialized
+pub fn set_process_property(key: &str, value: &str) {
+    EXTRA_PROCESS_PROPERTIES
+        .lock()
+        .unwrap()
+        .insert(key.to_owned(), value.to_owned());
+}
+
+/// Adds or updates a property of the running process, e.g. the current map or the user id
+/// after login. Before the telemetry is initialized, behaves like `set_process_property`.
+///
+/// The latest value of a property replaces the previous ones in the processes table.
+pub fn update_process_property(key: &str, value: &str) {
+    set_process_property(key, value);
+    let (Some(process_id), Some(sink)) = (process_id(), get_sink()) else {
+        return;
+    };
+    let mut properties = HashMap::new();
+    properties.insert(key.to_owned(), value.to_owned());
+    sink.on_process_properties_update(Arc::new(ProcessPropertiesUpdate {
+        process_id,
+        time: Utc::now(),
+        properties,
+    }));
+}
+
+fn process_properties() -> HashMap<String, String> {
+    let mut properties = crate::container_info::container_properties();
+    if let Some(source) = crate::time::tick_source() {
+        properties.insert(
+            crate::time::TICK_SOURCE_PROPERTY.to_owned(),
+            source.name.to_owned(),
+        );
     }
+    properties.extend(
+        EXTRA_PROCESS_PROPERTIES
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone())),
+    );
+    properties
 }
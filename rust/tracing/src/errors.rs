@@ -4,6 +4,12 @@ use thiserror::Error;
 pub enum Error {
     #[error("Event dispatch already initialized")]
     AlreadyInitialized(),
+    #[error("Tick source already registered")]
+    TickSourceAlreadySet(),
+    #[error("Invalid frame budget: {0}")]
+    InvalidFrameBudget(String),
+    #[error("Unknown stream tag: {0:?}")]
+    UnknownStreamTag(String),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
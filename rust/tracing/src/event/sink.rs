@@ -9,6 +9,14 @@ use crate::{
 
 pub type BoxedEventSink = Box<dyn EventSink>;
 
+/// Named binary artifact (screenshot, savegame, dump) attached to the process
+#[derive(Debug)]
+pub struct ProcessAttachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
 pub trait EventSink {
     fn on_startup(&self, process_info: Arc<ProcessInfo>);
     fn on_shutdown(&self);
@@ -24,6 +32,15 @@ pub trait EventSink {
     fn on_init_thread_stream(&self, thread_stream: &ThreadStream);
     fn on_process_thread_block(&self, thread_block: Arc<ThreadBlock>);
 
+    /// ignored by the sinks that can't store attachments
+    fn on_attachment(&self, _attachment: Arc<ProcessAttachment>) {}
+
+    /// ignored by the sinks that can't update the process
+    fn on_process_properties_update(&self, _update: Arc<ProcessPropertiesUpdate>) {}
+
+    /// last event sent before `on_shutdown`
+    fn on_process_exit(&self, _process_exit: Arc<ProcessExit>) {}
+
     fn is_busy(&self) -> bool; // sink is busy writing to disk or network, avoid extra flushing
 }
 
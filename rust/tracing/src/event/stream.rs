@@ -1,9 +1,38 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::event::TracingBlock;
+use crate::time::{frequency, now};
+
+// 0 when blocks are only flushed when full
+static MAX_BLOCK_AGE_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Blocks older than `max_age` are flushed with the next event of their stream, however full they are.
+/// Applies to the blocks created after the call, a zero duration disables it. A stream without
+/// events keeps its block open: the thread streams can only be flushed by their thread.
+pub fn set_max_block_age(max_age: Duration) {
+    let max_age_ms = if max_age.is_zero() {
+        0
+    } else {
+        u64::try_from(max_age.as_millis())
+            .unwrap_or(u64::MAX)
+            .max(1)
+    };
+    MAX_BLOCK_AGE_MS.store(max_age_ms, Ordering::Relaxed);
+}
+
+// ticks after which a block created now has to be flushed
+fn block_deadline() -> i64 {
+    let max_age_ms = MAX_BLOCK_AGE_MS.load(Ordering::Relaxed);
+    if max_age_ms == 0 {
+        return i64::MAX;
+    }
+    let max_age_ticks = u128::from(max_age_ms) * u128::from(frequency()) / 1000;
+    now().saturating_add(i64::try_from(max_age_ticks).unwrap_or(i64::MAX))
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StreamDesc {
@@ -34,6 +63,7 @@ pub struct EventStream<Block> {
     stream_desc: Arc<StreamDesc>,
     current_block: Arc<Block>,
     full_threshold: AtomicUsize,
+    flush_deadline: i64,
 }
 
 impl<Block> EventStream<Block>
@@ -58,6 +88,7 @@ where
             stream_desc,
             current_block: block,
             full_threshold: AtomicUsize::new(buffer_size - max_obj_size),
+            flush_deadline: block_deadline(),
         }
     }
 
@@ -75,12 +106,15 @@ where
         self.full_threshold
             .store(new_block.capacity_bytes() - max_obj_size, Ordering::Relaxed);
         self.current_block = new_block;
+        self.flush_deadline = block_deadline();
         old_block
     }
 
+    /// True when the block is too full to take another event, or older than the max block age
     pub fn is_full(&self) -> bool {
         let full_size = self.full_threshold.load(Ordering::Relaxed);
         self.current_block.len_bytes() > full_size
+            || (self.flush_deadline != i64::MAX && now() > self.flush_deadline)
     }
 
     pub fn is_empty(&self) -> bool {
@@ -0,0 +1,46 @@
+//! Time budgets of spans per frame, declared by the application
+//!
+//! The budgets are recorded in the `frame_budgets` property of the process, i.e.
+//! `Physics=4;Render=8` for at most 4ms of `Physics` spans and 8ms of `Render` spans per frame.
+//! The analytics compare them to the spans of each frame, see `frame_budget_violations`.
+use crate::dispatch::set_process_property;
+use crate::errors::{Error, Result};
+use std::sync::Mutex;
+
+/// Property of the process holding its frame budgets
+pub const FRAME_BUDGETS_PROPERTY: &str = "frame_budgets";
+
+lazy_static! {
+    static ref FRAME_BUDGETS: Mutex<Vec<(String, f64)>> = Mutex::new(Vec::new());
+}
+
+/// `name=ms` pairs separated by `;`, in the order they were declared
+pub fn format_frame_budgets(budgets: &[(String, f64)]) -> String {
+    budgets
+        .iter()
+        .map(|(span_name, max_ms)| format!("{span_name}={max_ms}"))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+/// Declares that the spans named `span_name` should take at most `max_ms` milliseconds per frame,
+/// replacing any previous budget of these spans
+///
+/// Has to be called before the telemetry is initialized, like `set_process_property`.
+pub fn declare_frame_budget(span_name: &str, max_ms: f64) -> Result<()> {
+    if span_name.is_empty() || span_name.contains([';', '=']) {
+        return Err(Error::InvalidFrameBudget(format!(
+            "invalid span name {span_name:?}"
+        )));
+    }
+    if !max_ms.is_finite() || max_ms < 0.0 {
+        return Err(Error::InvalidFrameBudget(format!(
+            "invalid budget of {span_name}: {max_ms}ms"
+        )));
+    }
+    let mut budgets = FRAME_BUDGETS.lock().unwrap();
+    budgets.retain(|(name, _)| name != span_name);
+    budgets.push((span_name.to_owned(), max_ms));
+    set_process_property(FRAME_BUDGETS_PROPERTY, &format_frame_budgets(&budgets));
+    Ok(())
+}
@@ -5,7 +5,7 @@ use crate::{
         flush_log_buffer, flush_metrics_buffer, flush_thread_buffer, init_event_dispatch,
         init_thread_stream, on_begin_async_named_scope, on_begin_async_scope, on_begin_named_scope,
         on_begin_scope, on_end_async_named_scope, on_end_async_scope, on_end_named_scope,
-        on_end_scope, shutdown_dispatch,
+        on_end_scope, set_exit_code, shutdown_dispatch,
     },
     errors::Result,
     event::EventSink,
@@ -60,6 +60,15 @@ pub fn shutdown_telemetry() {
     shutdown_dispatch();
 }
 
+/// Records the exit code as a final property of the process before exiting:
+/// `std::process::exit` would skip the drop of the telemetry guards
+pub fn exit_process(exit_code: i32) -> ! {
+    set_exit_code(exit_code);
+    flush_thread_buffer();
+    shutdown_telemetry();
+    std::process::exit(exit_code)
+}
+
 pub struct TracingThreadGuard {
     _dummy_ptr: *mut u8,
 }
@@ -48,10 +48,12 @@
 // crate-specific lint exceptions:
 #![allow(unsafe_code, clippy::missing_errors_doc, clippy::inline_always)]
 
+pub mod container_info;
 pub mod dispatch;
 pub mod errors;
 pub mod event;
 pub mod flush_monitor;
+pub mod frame_budgets;
 pub mod guards;
 pub mod levels;
 pub mod logs;
@@ -59,6 +61,7 @@ pub mod metrics;
 pub mod panic_hook;
 pub mod process_info;
 pub mod spans;
+pub mod stream_tags;
 pub mod string_id;
 pub mod time;
 
@@ -74,8 +77,8 @@ pub mod prelude {
     pub use crate::process_info::*;
     pub use crate::time::*;
     pub use crate::{
-        async_span_scope, debug, error, fmetric, imetric, info, log, log_enabled, span_scope,
-        trace, warn,
+        async_span_scope, debug, error, fmetric, imetric, info, instrument_block, log, log_enabled,
+        span_closure, span_event, span_scope, trace, warn,
     };
     pub use micromegas_tracing_proc_macros::*;
 }
@@ -61,6 +61,43 @@ macro_rules! span_scope_named {
     };
 }
 
+/// Records an instant event in the current span of the thread, with optional properties.
+///
+/// The properties are interned: their number of distinct values should stay low.
+///
+/// # Examples
+///
+/// ```
+/// use micromegas_tracing::span_event;
+///
+/// # fn main() {
+/// span_event!("checkpoint");
+/// span_event!("checkpoint", "stage=load");
+/// # }
+/// ```
+#[macro_export]
+macro_rules! span_event {
+    ($name:expr) => {
+        $crate::span_event!($name, "");
+    };
+    ($name:expr, $properties:expr) => {{
+        static SPAN_EVENT_METADATA: $crate::spans::SpanMetadata = $crate::spans::SpanMetadata {
+            name: $name,
+            location: $crate::spans::SpanLocation {
+                lod: $crate::levels::Verbosity::Max,
+                target: module_path!(),
+                module_path: module_path!(),
+                file: file!(),
+                line: line!(),
+            },
+        };
+        $crate::dispatch::on_span_event(
+            &SPAN_EVENT_METADATA,
+            $crate::intern_string::intern_string($properties),
+        );
+    }};
+}
+
 #[macro_export]
 macro_rules! async_span_scope {
     ($scope_name:ident, $name:expr) => {
@@ -98,6 +135,93 @@ macro_rules! async_span_scope_named {
     };
 }
 
+/// Wraps a closure in a span named after its location, `module::path::{closure}:line`.
+///
+/// The closure keeps its arguments and its captures, the span covers each call.
+///
+/// # Examples
+///
+/// ```
+/// use micromegas_tracing::span_closure;
+///
+/// # fn main() {
+/// let add = span_closure!(|a: i32, b: i32| a + b);
+/// assert_eq!(add(1, 2), 3);
+/// let values = vec![1, 2, 3];
+/// let sum = span_closure!(move || values.iter().sum::<i32>());
+/// assert_eq!(sum(), 6);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! span_closure {
+    (move || $body:expr) => {
+        $crate::span_closure!(move | | $body)
+    };
+    (|| $body:expr) => {
+        $crate::span_closure!(| | $body)
+    };
+    (move |$($arg:ident $(: $arg_type:ty)?),*| $body:expr) => {{
+        static SPAN_CLOSURE_METADATA: $crate::spans::SpanMetadata =
+            $crate::__located_span_metadata!("{closure}");
+        move |$($arg $(: $arg_type)?),*| {
+            let _guard = $crate::guards::ThreadSpanGuard::new(&SPAN_CLOSURE_METADATA);
+            $body
+        }
+    }};
+    (|$($arg:ident $(: $arg_type:ty)?),*| $body:expr) => {{
+        static SPAN_CLOSURE_METADATA: $crate::spans::SpanMetadata =
+            $crate::__located_span_metadata!("{closure}");
+        |$($arg $(: $arg_type)?),*| {
+            let _guard = $crate::guards::ThreadSpanGuard::new(&SPAN_CLOSURE_METADATA);
+            $body
+        }
+    }};
+}
+
+/// Records a block, or an async block, in a span named after its location,
+/// `module::path::{block}:line` or `module::path::{async block}:line`.
+///
+/// # Examples
+///
+/// ```
+/// use micromegas_tracing::instrument_block;
+///
+/// # fn main() {
+/// let total = instrument_block!({
+///     let items = [1, 2, 3];
+///     items.len()
+/// });
+/// assert_eq!(total, 3);
+/// let future = instrument_block!(async move { total * 2 });
+/// # drop(future);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! instrument_block {
+    (async move $body:block) => {
+        async move {
+            static SPAN_BLOCK_METADATA: $crate::spans::SpanMetadata =
+                $crate::__located_span_metadata!("{async block}");
+            let _guard = $crate::guards::AsyncSpanGuard::new(&SPAN_BLOCK_METADATA);
+            async move $body.await
+        }
+    };
+    (async $body:block) => {
+        async {
+            static SPAN_BLOCK_METADATA: $crate::spans::SpanMetadata =
+                $crate::__located_span_metadata!("{async block}");
+            let _guard = $crate::guards::AsyncSpanGuard::new(&SPAN_BLOCK_METADATA);
+            async $body.await
+        }
+    };
+    ($body:block) => {{
+        static SPAN_BLOCK_METADATA: $crate::spans::SpanMetadata =
+            $crate::__located_span_metadata!("{block}");
+        let _guard = $crate::guards::ThreadSpanGuard::new(&SPAN_BLOCK_METADATA);
+        $body
+    }};
+}
+
 /// Records a integer metric.
 ///
 /// # Examples
@@ -426,3 +550,21 @@ macro_rules! __log_line {
         line!()
     };
 }
+
+/// Metadata of a span named after the location of the macro call, used by the ad-hoc spans
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __located_span_metadata {
+    ($kind:literal) => {
+        $crate::spans::SpanMetadata {
+            name: concat!(module_path!(), "::", $kind, ":", line!()),
+            location: $crate::spans::SpanLocation {
+                lod: $crate::levels::Verbosity::Max,
+                target: module_path!(),
+                module_path: module_path!(),
+                file: file!(),
+                line: line!(),
+            },
+        }
+    };
+}
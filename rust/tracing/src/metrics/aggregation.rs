@@ -0,0 +1,107 @@
+//! Optional pre-aggregation of high frequency metrics
+//!
+//! When enabled for a verbosity level, the values of the metrics of that level (and above)
+//! are accumulated in thread-local storage instead of being recorded as individual events.
+//! Each thread then contributes one `AggregatedMetricEvent` (sum, count, min, max) per metric
+//! every time the metrics buffer is flushed.
+use super::{AggregatedMetricEvent, MetricMetadata};
+use crate::levels::Verbosity;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 0 when disabled, the lowest aggregated verbosity otherwise
+static MIN_AGGREGATED_LOD: AtomicU32 = AtomicU32::new(0);
+
+/// Aggregates the metrics of verbosity `min_lod` and above
+pub fn enable_metrics_aggregation(min_lod: Verbosity) {
+    MIN_AGGREGATED_LOD.store(min_lod as u32, Ordering::Relaxed);
+}
+
+pub fn disable_metrics_aggregation() {
+    MIN_AGGREGATED_LOD.store(0, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_metric_aggregated(desc: &MetricMetadata) -> bool {
+    let min_lod = MIN_AGGREGATED_LOD.load(Ordering::Relaxed);
+    min_lod != 0 && desc.lod as u32 >= min_lod
+}
+
+struct Accumulator {
+    desc: &'static MetricMetadata,
+    sum: f64,
+    count: u64,
+    min: f64,
+    max: f64,
+    begin_time: i64,
+    time: i64,
+}
+
+impl Accumulator {
+    fn into_event(self) -> AggregatedMetricEvent {
+        AggregatedMetricEvent {
+            desc: self.desc,
+            sum: self.sum,
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            begin_time: self.begin_time,
+            time: self.time,
+        }
+    }
+}
+
+// keyed by the address of the metadata
+type Accumulators = HashMap<usize, Accumulator>;
+
+lazy_static! {
+    // kept alive after the threads exit so that their last values are not lost
+    static ref THREAD_ACCUMULATORS: Mutex<Vec<Arc<Mutex<Accumulators>>>> = Mutex::new(vec![]);
+}
+
+thread_local! {
+    static LOCAL_ACCUMULATORS: Arc<Mutex<Accumulators>> = {
+        let accumulators = Arc::new(Mutex::new(Accumulators::new()));
+        THREAD_ACCUMULATORS.lock().unwrap().push(accumulators.clone());
+        accumulators
+    };
+}
+
+/// Accumulates the value in the storage of the calling thread
+pub fn aggregate_metric(desc: &'static MetricMetadata, value: f64, time: i64) {
+    LOCAL_ACCUMULATORS.with(|accumulators| {
+        let mut accumulators = accumulators.lock().unwrap();
+        accumulators
+            .entry(desc as *const _ as usize)
+            .and_modify(|acc| {
+                acc.sum += value;
+                acc.count += 1;
+                acc.min = acc.min.min(value);
+                acc.max = acc.max.max(value);
+                acc.time = time;
+            })
+            .or_insert(Accumulator {
+                desc,
+                sum: value,
+                count: 1,
+                min: value,
+                max: value,
+                begin_time: time,
+                time,
+            });
+    });
+}
+
+/// Empties the accumulators of all threads, called when the metrics buffer is flushed
+pub fn drain_aggregated_metrics() -> Vec<AggregatedMetricEvent> {
+    let mut events = vec![];
+    let mut all_accumulators = THREAD_ACCUMULATORS.lock().unwrap();
+    for accumulators in all_accumulators.iter() {
+        let mut accumulators = accumulators.lock().unwrap();
+        events.extend(accumulators.drain().map(|(_key, acc)| acc.into_event()));
+    }
+    // forget the threads that exited
+    all_accumulators.retain(|accumulators| Arc::strong_count(accumulators) > 1);
+    events
+}
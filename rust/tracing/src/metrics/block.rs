@@ -1,12 +1,15 @@
 use crate::{
     event::{EventBlock, EventStream, ExtractDeps},
-    metrics::{FloatMetricEvent, IntegerMetricEvent, MetricMetadata, MetricMetadataRecord},
+    metrics::{
+        AggregatedMetricEvent, FloatMetricEvent, IntegerMetricEvent, MetricMetadata,
+        MetricMetadataRecord,
+    },
 };
 use micromegas_transit::prelude::*;
 use std::collections::HashSet;
 
 declare_queue_struct!(
-    struct MetricsMsgQueue<IntegerMetricEvent, FloatMetricEvent> {}
+    struct MetricsMsgQueue<IntegerMetricEvent, FloatMetricEvent, AggregatedMetricEvent> {}
 );
 
 declare_queue_struct!(
@@ -67,6 +70,9 @@ impl ExtractDeps for MetricsMsgQueue {
                 MetricsMsgQueueAny::FloatMetricEvent(evt) => {
                     record_metric_event_dependencies(evt.desc, &mut recorded_deps, &mut deps);
                 }
+                MetricsMsgQueueAny::AggregatedMetricEvent(evt) => {
+                    record_metric_event_dependencies(evt.desc, &mut recorded_deps, &mut deps);
+                }
             }
         }
         deps
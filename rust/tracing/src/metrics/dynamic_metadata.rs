@@ -0,0 +1,32 @@
+use super::MetricMetadata;
+use crate::intern_string::intern_string;
+use crate::levels::Verbosity;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Returns the metadata of a metric whose target is only known at runtime
+///
+/// The target carries what the measure is about (network interface, gpu index, sql statement),
+/// metadata is leaked once per (name, target) pair since events reference it statically.
+pub fn make_metric_metadata(
+    name: &'static str,
+    unit: &'static str,
+    target: &str,
+) -> &'static MetricMetadata {
+    lazy_static! {
+        static ref METRIC_METADATA: Mutex<HashMap<(&'static str, String), &'static MetricMetadata>> =
+            Mutex::new(HashMap::new());
+    }
+    let mut cache = METRIC_METADATA.lock().unwrap();
+    cache.entry((name, target.to_owned())).or_insert_with(|| {
+        Box::leak(Box::new(MetricMetadata {
+            lod: Verbosity::Max,
+            name,
+            unit,
+            target: intern_string(target),
+            module_path: module_path!(),
+            file: file!(),
+            line: line!(),
+        }))
+    })
+}
@@ -29,6 +29,22 @@ pub struct FloatMetricEvent {
 }
 
 impl InProcSerialize for FloatMetricEvent {}
+/// Values of a metric recorded by a thread between two flushes of the metrics buffer
+#[derive(Debug, TransitReflect)]
+pub struct AggregatedMetricEvent {
+    pub desc: &'static MetricMetadata,
+    pub sum: f64,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+    /// time of the first value
+    pub begin_time: i64,
+    /// time of the last value
+    pub time: i64,
+}
+
+impl InProcSerialize for AggregatedMetricEvent {}
+
 #[derive(Debug, TransitReflect)]
 pub struct MetricMetadataRecord {
     pub id: u64,
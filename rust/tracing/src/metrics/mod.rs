@@ -1,5 +1,11 @@
+mod aggregation;
+pub use aggregation::*;
+
 mod block;
 pub use block::*;
 
 mod events;
 pub use events::*;
+
+mod dynamic_metadata;
+pub use dynamic_metadata::*;
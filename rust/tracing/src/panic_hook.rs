@@ -1,6 +1,7 @@
 use std::io::Write;
 use std::panic::{take_hook, PanicInfo};
 
+use crate::dispatch::record_panic;
 use crate::error;
 use crate::guards::shutdown_telemetry;
 
@@ -14,6 +15,7 @@ pub fn init_panic_hook() {
 
     std::panic::set_hook(Box::new(|panic_info| unsafe {
         error!("panic: {:?}", panic_info);
+        record_panic(panic_info.to_string());
         shutdown_telemetry();
         if let Some(hook) = PREVIOUS_HOOK.as_ref() {
             std::io::stdout().flush().unwrap();
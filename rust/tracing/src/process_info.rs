@@ -26,3 +26,29 @@ pub struct ProcessInfo {
     pub parent_process_id: Option<uuid::Uuid>,
     pub properties: HashMap<String, String>,
 }
+
+/// How the process ended, sent at shutdown and recorded as final properties of the process
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessExit {
+    #[serde(
+        deserialize_with = "uuid_utils::uuid_from_string",
+        serialize_with = "uuid_utils::uuid_to_string"
+    )]
+    pub process_id: uuid::Uuid,
+    pub exit_time: chrono::DateTime<chrono::Utc>,
+    /// when the application reported it, see `guards::exit_process`
+    pub exit_code: Option<i32>,
+    pub panic_message: Option<String>,
+}
+
+/// Properties set while the process runs, they replace the values of the same keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessPropertiesUpdate {
+    #[serde(
+        deserialize_with = "uuid_utils::uuid_from_string",
+        serialize_with = "uuid_utils::uuid_to_string"
+    )]
+    pub process_id: uuid::Uuid,
+    pub time: chrono::DateTime<chrono::Utc>,
+    pub properties: HashMap<String, String>,
+}
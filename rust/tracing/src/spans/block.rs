@@ -1,7 +1,7 @@
 use super::{
     BeginAsyncNamedSpanEvent, BeginAsyncSpanEvent, BeginThreadNamedSpanEvent, BeginThreadSpanEvent,
     EndAsyncNamedSpanEvent, EndAsyncSpanEvent, EndThreadNamedSpanEvent, EndThreadSpanEvent,
-    SpanLocation, SpanLocationRecord, SpanMetadata, SpanRecord,
+    SpanLocation, SpanLocationRecord, SpanMarkerEvent, SpanMetadata, SpanRecord,
 };
 use crate::{
     event::{EventBlock, EventStream, ExtractDeps},
@@ -20,6 +20,7 @@ declare_queue_struct!(
         EndAsyncSpanEvent,
         BeginAsyncNamedSpanEvent,
         EndAsyncNamedSpanEvent,
+        SpanMarkerEvent,
     > {}
 );
 
@@ -157,6 +158,12 @@ impl ExtractDeps for ThreadEventQueue {
                         &mut deps,
                     );
                 }
+                ThreadEventQueueAny::SpanMarkerEvent(evt) => {
+                    record_scope_event_dependencies(evt.marker_desc, &mut recorded_deps, &mut deps);
+                    if recorded_deps.insert(evt.properties.id()) {
+                        deps.push(StaticString::from(&evt.properties));
+                    }
+                }
             }
         }
         deps
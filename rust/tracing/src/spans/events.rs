@@ -79,6 +79,16 @@ pub struct EndThreadNamedSpanEvent {
 
 impl InProcSerialize for EndThreadNamedSpanEvent {}
 
+/// Instant event within the current span of the thread, see `span_event!`
+#[derive(Debug, TransitReflect)]
+pub struct SpanMarkerEvent {
+    pub marker_desc: &'static SpanMetadata,
+    pub properties: StringId,
+    pub time: i64,
+}
+
+impl InProcSerialize for SpanMarkerEvent {}
+
 //
 // async events
 //
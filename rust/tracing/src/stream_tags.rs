@@ -0,0 +1,64 @@
+//! Tags of the streams, telling the analytics which kind of events they contain
+//!
+//! The tags are sent and stored as strings, `StreamTag` is the registry of the tags known to the
+//! ingestion and analytics services: streams with other tags are rejected at ingestion unless
+//! the server registers them as custom tags.
+use crate::errors::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamTag {
+    /// log entries
+    Log,
+    /// measures
+    Metrics,
+    /// thread spans
+    Cpu,
+}
+
+impl StreamTag {
+    pub const ALL: [StreamTag; 3] = [StreamTag::Log, StreamTag::Metrics, StreamTag::Cpu];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StreamTag::Log => "log",
+            StreamTag::Metrics => "metrics",
+            StreamTag::Cpu => "cpu",
+        }
+    }
+}
+
+impl fmt::Display for StreamTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StreamTag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|tag| tag.as_str() == s)
+            .ok_or_else(|| Error::UnknownStreamTag(s.to_owned()))
+    }
+}
+
+impl From<StreamTag> for String {
+    fn from(tag: StreamTag) -> Self {
+        tag.as_str().to_owned()
+    }
+}
+
+/// Checks that every tag is a `StreamTag` or one of the `custom` tags
+pub fn validate_stream_tags(tags: &[String], custom: &[String]) -> Result<()> {
+    for tag in tags {
+        if custom.contains(tag) {
+            continue;
+        }
+        tag.parse::<StreamTag>()?;
+    }
+    Ok(())
+}
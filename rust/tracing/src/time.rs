@@ -1,4 +1,38 @@
+use crate::errors::{Error, Result};
 use chrono::{DateTime, Utc};
+use std::sync::OnceLock;
+
+/// Property of the process naming its custom tick source, absent for the cpu tick counter
+pub const TICK_SOURCE_PROPERTY: &str = "tick_source";
+
+/// Clock timestamping the events instead of the cpu tick counter,
+/// i.e. the high resolution clock of a game engine or the time of a simulation
+#[derive(Debug, Clone, Copy)]
+pub struct TickSource {
+    /// recorded in the `tick_source` property of the process
+    pub name: &'static str,
+    pub now: fn() -> i64,
+    /// ticks per second
+    pub frequency: u64,
+}
+
+static TICK_SOURCE: OnceLock<TickSource> = OnceLock::new();
+
+/// Replaces the cpu tick counter as the clock of the events of the process
+///
+/// Has to be called before the telemetry is initialized: the frequency and the ticks at the
+/// start of the process are recorded in its process info, and the analytics convert the ticks
+/// of the events with them, whatever their source.
+pub fn set_tick_source(source: TickSource) -> Result<()> {
+    TICK_SOURCE
+        .set(source)
+        .map_err(|_| Error::TickSourceAlreadySet())
+}
+
+/// The custom tick source, `None` when the events are timestamped with the cpu tick counter
+pub fn tick_source() -> Option<&'static TickSource> {
+    TICK_SOURCE.get()
+}
 
 #[derive(Debug)]
 pub struct DualTime {
@@ -15,9 +49,18 @@ impl DualTime {
     }
 }
 
+/// Ticks of the custom tick source if one was registered, of the cpu otherwise
+#[inline(always)]
+pub fn now() -> i64 {
+    match TICK_SOURCE.get() {
+        Some(source) => (source.now)(),
+        None => cpu_ticks(),
+    }
+}
+
 #[allow(clippy::cast_possible_wrap)]
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-pub fn now() -> i64 {
+fn cpu_ticks() -> i64 {
     //_rdtsc does not wait for previous instructions to be retired
     // we could use __rdtscp if we needed more precision at the cost of slightly
     // higher overhead
@@ -27,7 +70,7 @@ pub fn now() -> i64 {
 
 #[allow(clippy::cast_possible_wrap)]
 #[cfg(target_arch = "aarch64")]
-pub fn now() -> i64 {
+fn cpu_ticks() -> i64 {
     //essentially from https://github.com/sheroz/tick_counter/blob/main/src/lib.rs
     //(MIT license)
     let tick_counter: i64;
@@ -40,7 +83,15 @@ pub fn now() -> i64 {
     tick_counter
 }
 
+/// Ticks per second of [`now`]
 pub fn frequency() -> u64 {
+    match TICK_SOURCE.get() {
+        Some(source) => source.frequency,
+        None => cpu_frequency(),
+    }
+}
+
+fn cpu_frequency() -> u64 {
     #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
     {
         let cpuid = raw_cpuid::CpuId::new();
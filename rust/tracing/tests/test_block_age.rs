@@ -0,0 +1,44 @@
+use micromegas_tracing::event::set_max_block_age;
+use micromegas_tracing::logs::{
+    LogMetadata, LogStaticStrEvent, LogStream, FILTER_LEVEL_UNSET_VALUE,
+};
+use micromegas_tracing::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU32;
+use std::time::Duration;
+
+static METADATA: LogMetadata = LogMetadata {
+    level: Level::Info,
+    level_filter: AtomicU32::new(FILTER_LEVEL_UNSET_VALUE),
+    fmt_str: "test",
+    target: "test",
+    module_path: "test",
+    file: file!(),
+    line: line!(),
+};
+
+fn push_event(stream: &mut LogStream) {
+    stream.get_events_mut().push(LogStaticStrEvent {
+        desc: &METADATA,
+        time: now(),
+    });
+}
+
+#[test]
+fn test_max_block_age() {
+    let mut stream = LogStream::new(1024 * 1024, uuid::Uuid::new_v4(), &[], HashMap::new());
+    push_event(&mut stream);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!stream.is_full());
+
+    set_max_block_age(Duration::from_millis(20));
+    let mut stream = LogStream::new(1024 * 1024, uuid::Uuid::new_v4(), &[], HashMap::new());
+    push_event(&mut stream);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(stream.is_full());
+
+    set_max_block_age(Duration::ZERO);
+    let stream = LogStream::new(1024 * 1024, uuid::Uuid::new_v4(), &[], HashMap::new());
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(!stream.is_full());
+}
@@ -0,0 +1,18 @@
+use micromegas_tracing::dispatch::make_process_info;
+use micromegas_tracing::frame_budgets::{declare_frame_budget, FRAME_BUDGETS_PROPERTY};
+
+#[test]
+fn test_declare_frame_budgets() {
+    declare_frame_budget("Physics", 4.0).unwrap();
+    declare_frame_budget("Render", 8.5).unwrap();
+    declare_frame_budget("Physics", 5.0).unwrap();
+    assert!(declare_frame_budget("Audio;Render", 1.0).is_err());
+    assert!(declare_frame_budget("Audio", f64::NAN).is_err());
+    assert!(declare_frame_budget("Audio", -1.0).is_err());
+
+    let process_info = make_process_info(uuid::Uuid::new_v4(), None);
+    assert_eq!(
+        process_info.properties.get(FRAME_BUDGETS_PROPERTY).unwrap(),
+        "Render=8.5;Physics=5"
+    );
+}
@@ -6,10 +6,13 @@ use micromegas_tracing::dispatch::{
     flush_log_buffer, flush_metrics_buffer, flush_thread_buffer, init_event_dispatch,
     init_thread_stream, process_id,
 };
-use micromegas_tracing::levels::{set_max_level, LevelFilter};
+use micromegas_tracing::levels::{set_max_level, LevelFilter, Verbosity};
+use micromegas_tracing::metrics::{disable_metrics_aggregation, enable_metrics_aggregation};
 use micromegas_tracing::time::frequency;
-use micromegas_tracing::{fmetric, imetric, info, span_scope};
-use micromegas_tracing_proc_macros::{log_fn, span_fn};
+use micromegas_tracing::{
+    fmetric, imetric, info, instrument_block, span_closure, span_event, span_scope,
+};
+use micromegas_tracing_proc_macros::{log_fn, span_all_fns, span_fn};
 use utils::{DebugEventSink, LogDispatch, SharedState, State};
 
 fn test_log_str(state: &SharedState) {
@@ -58,6 +61,28 @@ fn test_thread_spans(state: &SharedState) {
     expect_state!(state, Some(State::ProcessThreadBlock(2048)));
 }
 
+fn test_span_events(state: &SharedState) {
+    {
+        span_scope!("outer");
+        span_event!("checkpoint");
+        span_event!("checkpoint", &format!("stage={}", "load"));
+    }
+    flush_thread_buffer();
+    expect_state!(state, Some(State::ProcessThreadBlock(4)));
+}
+
+fn test_located_spans(state: &SharedState) {
+    let offset = 10;
+    let add = span_closure!(|a: i32, b| a + b + offset);
+    assert_eq!(add(1, 2), 13);
+    assert_eq!(add(3, 4), 17);
+    let doubled = instrument_block!({ add(0, 0) * 2 });
+    assert_eq!(doubled, 20);
+    flush_thread_buffer();
+    // begin and end of the block and the three calls
+    expect_state!(state, Some(State::ProcessThreadBlock(8)));
+}
+
 fn test_metrics(state: &SharedState) {
     imetric!("Frame Time", "ticks", 1000);
     fmetric!("Frame Time", "ticks", 1.0);
@@ -65,6 +90,20 @@ fn test_metrics(state: &SharedState) {
     expect_state!(state, Some(State::ProcessMetricsBlock(2)));
 }
 
+fn test_aggregated_metrics(state: &SharedState) {
+    enable_metrics_aggregation(Verbosity::Max);
+    for x in 0..1000 {
+        imetric!("Frame Time", "ticks", x);
+    }
+    thread::spawn(|| fmetric!("Frame Time", "ticks", 1.0))
+        .join()
+        .unwrap();
+    flush_metrics_buffer();
+    // one event per metric per thread
+    expect_state!(state, Some(State::ProcessMetricsBlock(2)));
+    disable_metrics_aggregation();
+}
+
 #[span_fn]
 fn trace_func() {}
 
@@ -74,12 +113,31 @@ fn trace_func_named() {}
 #[log_fn]
 fn log_func() {}
 
+struct Instrumented;
+
+#[span_all_fns]
+impl Instrumented {
+    fn first(&self) {}
+
+    fn second(&self) {}
+
+    #[no_span]
+    fn ignored(&self) {}
+}
+
 fn test_proc_macros(state: &SharedState) {
     trace_func();
     trace_func_named();
     flush_thread_buffer();
     expect_state!(&state.clone(), Some(utils::State::ProcessThreadBlock(4)));
 
+    let instrumented = Instrumented;
+    instrumented.first();
+    instrumented.second();
+    instrumented.ignored();
+    flush_thread_buffer();
+    expect_state!(&state.clone(), Some(utils::State::ProcessThreadBlock(4)));
+
     log_func();
     expect_state!(state, Some(State::Log(String::from("log_func"))));
 }
@@ -104,5 +162,8 @@ fn test_log() {
     test_log_interop_str(&state);
     test_thread_spans(&state);
     test_proc_macros(&state);
+    test_span_events(&state);
+    test_located_spans(&state);
     test_metrics(&state);
+    test_aggregated_metrics(&state);
 }
@@ -0,0 +1,60 @@
+use micromegas_tracing::dispatch::{
+    init_event_dispatch, make_process_info, process_id, shutdown_dispatch, update_process_property,
+};
+use micromegas_tracing::event::EventSink;
+use micromegas_tracing::logs::{LogBlock, LogMetadata, LogStream};
+use micromegas_tracing::metrics::{MetricsBlock, MetricsStream};
+use micromegas_tracing::prelude::*;
+use micromegas_tracing::spans::{ThreadBlock, ThreadStream};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct UpdatesSink {
+    updates: Mutex<Vec<Arc<ProcessPropertiesUpdate>>>,
+}
+
+impl EventSink for UpdatesSink {
+    fn on_startup(&self, _: Arc<ProcessInfo>) {}
+    fn on_shutdown(&self) {}
+    fn on_log_enabled(&self, _: &LogMetadata) -> bool {
+        false
+    }
+    fn on_log(&self, _: &LogMetadata, _: i64, _: fmt::Arguments<'_>) {}
+    fn on_init_log_stream(&self, _: &LogStream) {}
+    fn on_process_log_block(&self, _: Arc<LogBlock>) {}
+    fn on_init_metrics_stream(&self, _: &MetricsStream) {}
+    fn on_process_metrics_block(&self, _: Arc<MetricsBlock>) {}
+    fn on_init_thread_stream(&self, _: &ThreadStream) {}
+    fn on_process_thread_block(&self, _: Arc<ThreadBlock>) {}
+    fn on_process_properties_update(&self, update: Arc<ProcessPropertiesUpdate>) {
+        self.updates.lock().unwrap().push(update);
+    }
+    fn is_busy(&self) -> bool {
+        false
+    }
+}
+
+#[test]
+fn test_update_process_property() {
+    // before the telemetry is initialized, the property is sent with the process
+    update_process_property("map", "lobby");
+    let sink = Arc::new(UpdatesSink::default());
+    init_event_dispatch(1024, 1024, 1024, sink.clone()).unwrap();
+    assert!(sink.updates.lock().unwrap().is_empty());
+
+    update_process_property("map", "arena");
+    update_process_property("user_id", "42");
+    {
+        let updates = sink.updates.lock().unwrap();
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].process_id, process_id().unwrap());
+        assert_eq!(updates[0].properties.get("map").unwrap(), "arena");
+        assert_eq!(updates[1].properties.get("user_id").unwrap(), "42");
+        assert!(updates[0].time <= updates[1].time);
+    }
+    shutdown_dispatch();
+
+    let process_info = make_process_info(uuid::Uuid::new_v4(), None);
+    assert_eq!(process_info.properties.get("map").unwrap(), "arena");
+}
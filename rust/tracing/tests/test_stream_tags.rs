@@ -0,0 +1,22 @@
+use micromegas_tracing::stream_tags::{validate_stream_tags, StreamTag};
+
+#[test]
+fn test_stream_tag_names() {
+    for tag in StreamTag::ALL {
+        assert_eq!(tag.as_str().parse::<StreamTag>().unwrap(), tag);
+        assert_eq!(String::from(tag), tag.to_string());
+    }
+    assert_eq!("cpu".parse::<StreamTag>().unwrap(), StreamTag::Cpu);
+    assert!("metric".parse::<StreamTag>().is_err());
+    assert!("Log".parse::<StreamTag>().is_err());
+}
+
+#[test]
+fn test_validate_stream_tags() {
+    let custom = vec!["gpu".to_owned()];
+    assert!(validate_stream_tags(&[], &custom).is_ok());
+    assert!(validate_stream_tags(&["log".to_owned()], &[]).is_ok());
+    assert!(validate_stream_tags(&["cpu".to_owned(), "gpu".to_owned()], &custom).is_ok());
+    assert!(validate_stream_tags(&["cpu".to_owned(), "gpu".to_owned()], &[]).is_err());
+    assert!(validate_stream_tags(&["metrix".to_owned()], &custom).is_err());
+}
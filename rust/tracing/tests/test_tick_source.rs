@@ -0,0 +1,35 @@
+use micromegas_tracing::dispatch::make_process_info;
+use micromegas_tracing::time::{
+    frequency, now, set_tick_source, tick_source, TickSource, TICK_SOURCE_PROPERTY,
+};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+static SIMULATION_TICKS: AtomicI64 = AtomicI64::new(1000);
+
+fn simulation_now() -> i64 {
+    SIMULATION_TICKS.load(Ordering::Relaxed)
+}
+
+#[test]
+fn test_custom_tick_source() {
+    let source = TickSource {
+        name: "simulation",
+        now: simulation_now,
+        frequency: 60,
+    };
+    set_tick_source(source).unwrap();
+    assert!(set_tick_source(source).is_err());
+    assert_eq!(tick_source().unwrap().name, "simulation");
+    assert_eq!(now(), 1000);
+    SIMULATION_TICKS.store(1060, Ordering::Relaxed);
+    assert_eq!(now(), 1060);
+    assert_eq!(frequency(), 60);
+
+    let process_info = make_process_info(uuid::Uuid::new_v4(), None);
+    assert_eq!(process_info.start_ticks, 1060);
+    assert_eq!(process_info.tsc_frequency, 60);
+    assert_eq!(
+        process_info.properties.get(TICK_SOURCE_PROPERTY).unwrap(),
+        "simulation"
+    );
+}
@@ -79,6 +79,7 @@ impl EventSink for DebugEventSink {
             match event {
                 MetricsMsgQueueAny::IntegerMetricEvent(_evt) => {}
                 MetricsMsgQueueAny::FloatMetricEvent(_evt) => {}
+                MetricsMsgQueueAny::AggregatedMetricEvent(_evt) => {}
             }
         }
         *self.0.lock().unwrap() = Some(State::ProcessMetricsBlock(
@@ -101,6 +102,7 @@ impl EventSink for DebugEventSink {
                 ThreadEventQueueAny::EndAsyncSpanEvent(_evt) => {}
                 ThreadEventQueueAny::BeginAsyncNamedSpanEvent(_evt) => {}
                 ThreadEventQueueAny::EndAsyncNamedSpanEvent(_evt) => {}
+                ThreadEventQueueAny::SpanMarkerEvent(_evt) => {}
             }
         }
         *self.0.lock().unwrap() = Some(State::ProcessThreadBlock(thread_block.events.nb_objects()));
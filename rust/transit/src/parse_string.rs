@@ -1,39 +1,39 @@
 use anyhow::{bail, Result};
 
-use crate::read_any;
+use crate::{read_checked, slice_checked};
 
-#[allow(unsafe_code, clippy::cast_ptr_alignment)]
 pub fn parse_string(buffer: &[u8], cursor: &mut usize) -> Result<String> {
-    unsafe {
-        let codec_id = buffer[*cursor];
-        *cursor += 1;
-        let string_len_bytes = read_any::<u32>(buffer.as_ptr().add(*cursor)) as usize;
-        *cursor += std::mem::size_of::<u32>();
-        let string_buffer = &buffer[(*cursor)..(*cursor + string_len_bytes)];
-        *cursor += string_len_bytes;
-        const ANSI_CODE: u8 = 0;
-        const WIDE_CODE: u8 = 1;
-        const UTF8_CODE: u8 = 2;
-        match codec_id {
-            ANSI_CODE => {
-                // this would be typically be windows 1252, an extension to ISO-8859-1/latin1
-                // random people on the interwebs tell me that latin1's codepoints are a subset of utf8
-                // so I guess it's ok to treat it as utf8
-                Ok(String::from_utf8_lossy(string_buffer).to_string())
-            }
-            WIDE_CODE => {
-                //wide
-                let ptr = string_buffer.as_ptr().cast::<u16>();
-                if string_len_bytes % 2 != 0 {
-                    anyhow::bail!("wrong utf-16 buffer size");
-                }
-                let wide_slice = std::ptr::slice_from_raw_parts(ptr, string_len_bytes as usize / 2);
-                Ok(String::from_utf16_lossy(&*wide_slice))
-            }
-            UTF8_CODE => Ok(String::from_utf8_lossy(string_buffer).to_string()),
-            other => {
-                bail!("invalid codec [{}] in string", other);
+    let codec_id = read_checked::<u8>(buffer, *cursor)?;
+    *cursor += 1;
+    let string_len_bytes = read_checked::<u32>(buffer, *cursor)? as usize;
+    *cursor += std::mem::size_of::<u32>();
+    let string_buffer = slice_checked(buffer, *cursor, string_len_bytes)?;
+    *cursor += string_len_bytes;
+    const ANSI_CODE: u8 = 0;
+    const WIDE_CODE: u8 = 1;
+    const UTF8_CODE: u8 = 2;
+    match codec_id {
+        ANSI_CODE => {
+            // this would be typically be windows 1252, an extension to ISO-8859-1/latin1
+            // random people on the interwebs tell me that latin1's codepoints are a subset of utf8
+            // so I guess it's ok to treat it as utf8
+            Ok(String::from_utf8_lossy(string_buffer).to_string())
+        }
+        WIDE_CODE => {
+            //wide
+            if !string_len_bytes.is_multiple_of(2) {
+                anyhow::bail!("wrong utf-16 buffer size");
             }
+            // the buffer is not aligned for u16
+            let wide: Vec<u16> = string_buffer
+                .chunks_exact(2)
+                .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+                .collect();
+            Ok(String::from_utf16_lossy(&wide))
+        }
+        UTF8_CODE => Ok(String::from_utf8_lossy(string_buffer).to_string()),
+        other => {
+            bail!("invalid codec [{}] in string", other);
         }
     }
 }
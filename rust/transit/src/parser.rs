@@ -1,6 +1,6 @@
 use std::{collections::HashMap, hash::BuildHasher, sync::Arc};
 
-use crate::{parse_string::parse_string, read_any, DynString, InProcSerialize, UserDefinedType};
+use crate::{parse_string::parse_string, read_checked, slice_checked, Member, UserDefinedType};
 use anyhow::{bail, Context, Result};
 
 #[derive(Debug, Clone)]
@@ -137,64 +137,75 @@ impl Value {
     }
 }
 
+// objects nested deeper are rejected, the metadata of a hostile stream could describe a type
+// that contains itself
+const MAX_NESTING_DEPTH: usize = 32;
+
+/// Type and size of the object starting at `offset`, returns the offset of its first byte
+fn read_object_header<'a>(
+    udts: &'a [UserDefinedType],
+    buffer: &[u8],
+    mut offset: usize,
+) -> Result<(&'a UserDefinedType, usize, usize)> {
+    let type_index = buffer[offset] as usize;
+    if type_index >= udts.len() {
+        bail!("Invalid type index parsing transit objects: {}", type_index);
+    }
+    offset += 1;
+    let udt = &udts[type_index];
+    let object_size = match udt.size {
+        0 => {
+            //dynamic size
+            let obj_size = read_checked::<u32>(buffer, offset)?;
+            offset += std::mem::size_of::<u32>();
+            obj_size as usize
+        }
+        static_size => static_size,
+    };
+    Ok((udt, offset, object_size))
+}
+
+fn insert_dependency(hash: &mut HashMap<u64, Value>, id: u64, value: Value) -> Result<()> {
+    if hash.insert(id, value).is_some() {
+        bail!("duplicate dependency {}", id);
+    }
+    Ok(())
+}
+
 pub fn read_dependencies(udts: &[UserDefinedType], buffer: &[u8]) -> Result<HashMap<u64, Value>> {
     let mut hash = HashMap::new();
     let mut offset = 0;
     while offset < buffer.len() {
-        let type_index = buffer[offset] as usize;
-        if type_index >= udts.len() {
-            bail!(
-                "Invalid type index parsing transit dependencies: {}",
-                type_index
-            );
-        }
-        offset += 1;
-        let udt = &udts[type_index];
-        let object_size = match udt.size {
-            0 => {
-                //dynamic size
-                unsafe {
-                    let size_ptr = buffer.as_ptr().add(offset);
-                    let obj_size = read_any::<u32>(size_ptr);
-                    offset += std::mem::size_of::<u32>();
-                    obj_size as usize
-                }
-            }
-            static_size => static_size,
-        };
+        let (udt, object_offset, object_size) = read_object_header(udts, buffer, offset)?;
+        let object_buffer = slice_checked(buffer, object_offset, object_size)
+            .with_context(|| format!("reading dependency {}", udt.name))?;
 
         match udt.name.as_str() {
-            "StaticString" => unsafe {
-                let id_ptr = buffer.as_ptr().add(offset);
-                let string_id = read_any::<u64>(id_ptr);
-                let nb_utf8_bytes = object_size - std::mem::size_of::<usize>();
-                let utf8_ptr = buffer.as_ptr().add(offset + std::mem::size_of::<usize>());
-                let slice = std::ptr::slice_from_raw_parts(utf8_ptr, nb_utf8_bytes);
-                let string = String::from(std::str::from_utf8(&*slice).unwrap());
-                let insert_res = hash.insert(string_id, Value::String(Arc::new(string)));
-                assert!(insert_res.is_none());
-            },
-            "StaticStringDependency" => unsafe {
-                let mut cursor = offset;
-                let string_id = read_any::<u64>(buffer.as_ptr().add(cursor));
-                cursor += std::mem::size_of::<u64>();
-                let string = parse_string(buffer, &mut cursor).with_context(|| "parsing string")?;
-                let insert_res = hash.insert(string_id, Value::String(Arc::new(string)));
-                assert!(insert_res.is_none());
-            },
+            "StaticString" => {
+                let string_id = read_checked::<u64>(object_buffer, 0)?;
+                let utf8 = &object_buffer[std::mem::size_of::<u64>()..];
+                let string = String::from_utf8_lossy(utf8).into_owned();
+                insert_dependency(&mut hash, string_id, Value::String(Arc::new(string)))?;
+            }
+            "StaticStringDependency" => {
+                let string_id = read_checked::<u64>(object_buffer, 0)?;
+                let mut cursor = std::mem::size_of::<u64>();
+                let string =
+                    parse_string(object_buffer, &mut cursor).with_context(|| "parsing string")?;
+                insert_dependency(&mut hash, string_id, Value::String(Arc::new(string)))?;
+            }
 
             _ => {
                 if udt.size == 0 {
                     anyhow::bail!("invalid user-defined type {:?}", udt);
                 }
-                let instance = parse_pod_instance(udt, udts, &hash, offset, buffer);
+                let instance = parse_pod_instance(udt, udts, &hash, 0, object_buffer, 0)?;
                 if let Value::Object(obj) = instance {
-                    let insert_res = hash.insert(obj.get::<u64>("id")?, Value::Object(obj));
-                    assert!(insert_res.is_none());
+                    insert_dependency(&mut hash, obj.get::<u64>("id")?, Value::Object(obj))?;
                 }
             }
         }
-        offset += object_size;
+        offset = object_offset + object_size;
     }
 
     Ok(hash)
@@ -202,35 +213,27 @@ pub fn read_dependencies(udts: &[UserDefinedType], buffer: &[u8]) -> Result<Hash
 
 fn parse_log_string_event<S>(
     dependencies: &HashMap<u64, Value, S>,
-    offset: usize,
-    object_size: usize,
-    buffer: &[u8],
-) -> Vec<(String, Value)>
+    object_buffer: &[u8],
+) -> Result<Vec<(String, Value)>>
 where
     S: BuildHasher,
 {
-    unsafe {
-        let begin_obj_ptr = buffer.as_ptr().add(offset);
-        let desc_id = read_any::<u64>(begin_obj_ptr);
-        let time_ptr = buffer.as_ptr().add(offset + 8);
-        let time = read_any::<i64>(time_ptr);
-        let msg_offset = 8 * 2;
-        let msg = <DynString as InProcSerialize>::read_value(
-            buffer.as_ptr().add(offset + msg_offset),
-            Some((object_size - msg_offset) as u32),
-        );
-        let mut desc: Value = Value::None;
-        if let Some(found_desc) = dependencies.get(&desc_id) {
-            desc = found_desc.clone();
-        } else {
-            log::warn!("desc member {} of LogStringEvent not found", desc_id);
-        }
-        vec![
-            (String::from("time"), Value::I64(time)),
-            (String::from("msg"), Value::String(Arc::new(msg.0))),
-            (String::from("desc"), desc),
-        ]
+    let desc_id = read_checked::<u64>(object_buffer, 0)?;
+    let time = read_checked::<i64>(object_buffer, 8)?;
+    let msg_offset = 8 * 2;
+    // the two members read above guarantee the message offset is in the buffer
+    let msg = String::from_utf8_lossy(&object_buffer[msg_offset..]).into_owned();
+    let mut desc: Value = Value::None;
+    if let Some(found_desc) = dependencies.get(&desc_id) {
+        desc = found_desc.clone();
+    } else {
+        log::warn!("desc member {} of LogStringEvent not found", desc_id);
     }
+    Ok(vec![
+        (String::from("time"), Value::I64(time)),
+        (String::from("msg"), Value::String(Arc::new(msg))),
+        (String::from("desc"), desc),
+    ])
 }
 
 fn parse_log_string_interop_event_v3<S>(
@@ -243,24 +246,21 @@ where
 {
     if let Some(index) = udts.iter().position(|t| t.name == "StaticStringRef") {
         let string_ref_metadata = &udts[index];
-        unsafe {
-            let ptr = buffer.as_ptr();
-            let time = read_any::<i64>(ptr);
-            let mut cursor = std::mem::size_of::<i64>();
-            let level = read_any::<u8>(ptr.add(cursor));
-            cursor += 1;
-            let target =
-                parse_pod_instance(string_ref_metadata, udts, dependencies, cursor, buffer);
-            cursor += string_ref_metadata.size;
-            let msg = parse_string(buffer, &mut cursor)?;
-
-            Ok(vec![
-                (String::from("time"), Value::I64(time)),
-                (String::from("level"), Value::U8(level)),
-                (String::from("target"), target),
-                (String::from("msg"), Value::String(Arc::new(msg))),
-            ])
-        }
+        let time = read_checked::<i64>(buffer, 0)?;
+        let mut cursor = std::mem::size_of::<i64>();
+        let level = read_checked::<u8>(buffer, cursor)?;
+        cursor += 1;
+        let target =
+            parse_pod_instance(string_ref_metadata, udts, dependencies, cursor, buffer, 0)?;
+        cursor += string_ref_metadata.size;
+        let msg = parse_string(buffer, &mut cursor)?;
+
+        Ok(vec![
+            (String::from("time"), Value::I64(time)),
+            (String::from("level"), Value::U8(level)),
+            (String::from("target"), target),
+            (String::from("msg"), Value::String(Arc::new(msg))),
+        ])
     } else {
         bail!("Can't parse log string interop event with no metadata for StaticStringRef");
     }
@@ -269,39 +269,40 @@ where
 fn parse_log_string_interop_event<S>(
     udts: &[UserDefinedType],
     dependencies: &HashMap<u64, Value, S>,
-    offset: usize,
-    object_size: usize,
-    buffer: &[u8],
-) -> Vec<(String, Value)>
+    object_buffer: &[u8],
+) -> Result<Vec<(String, Value)>>
 where
     S: BuildHasher,
 {
     if let Some(index) = udts.iter().position(|t| t.name == "StringId") {
         let stringid_metadata = &udts[index];
-        unsafe {
-            let buffer_ptr = buffer.as_ptr();
-            let time = read_any::<i64>(buffer_ptr.add(offset));
-            let level_offset = offset + std::mem::size_of::<i64>();
-            let level = read_any::<u32>(buffer_ptr.add(level_offset));
-            let target_offset = level_offset + std::mem::size_of::<u32>();
-            let target =
-                parse_pod_instance(stringid_metadata, udts, dependencies, target_offset, buffer);
-            let message_offset = target_offset + stringid_metadata.size;
-            let msg = <DynString as InProcSerialize>::read_value(
-                buffer.as_ptr().add(message_offset),
-                Some((object_size - (message_offset - offset)) as u32),
-            );
-
-            vec![
-                (String::from("time"), Value::I64(time)),
-                (String::from("level"), Value::U32(level)),
-                (String::from("target"), target),
-                (String::from("msg"), Value::String(Arc::new(msg.0))),
-            ]
-        }
+        let time = read_checked::<i64>(object_buffer, 0)?;
+        let level_offset = std::mem::size_of::<i64>();
+        let level = read_checked::<u32>(object_buffer, level_offset)?;
+        let target_offset = level_offset + std::mem::size_of::<u32>();
+        let target = parse_pod_instance(
+            stringid_metadata,
+            udts,
+            dependencies,
+            target_offset,
+            object_buffer,
+            0,
+        )?;
+        let message_offset = target_offset + stringid_metadata.size;
+        let msg_bytes = object_buffer
+            .get(message_offset..)
+            .with_context(|| "message past the end of LogStringInteropEventV2")?;
+        let msg = String::from_utf8_lossy(msg_bytes).into_owned();
+
+        Ok(vec![
+            (String::from("time"), Value::I64(time)),
+            (String::from("level"), Value::U32(level)),
+            (String::from("target"), target),
+            (String::from("msg"), Value::String(Arc::new(msg))),
+        ])
     } else {
         log::warn!("Can't parse log string interop event with no metadata for StringId");
-        vec![]
+        Ok(vec![])
     }
 }
 
@@ -309,10 +310,8 @@ fn parse_custom_instance<S>(
     udt: &UserDefinedType,
     udts: &[UserDefinedType],
     dependencies: &HashMap<u64, Value, S>,
-    offset: usize,
-    object_size: usize,
-    buffer: &[u8],
-) -> Value
+    object_buffer: &[u8],
+) -> Result<Value>
 where
     S: BuildHasher,
 {
@@ -320,12 +319,13 @@ where
         // todo: move out of transit lib.
         // LogStringEvent belongs to the tracing lib
         // we need to inject the serialization logic of custom objects
-        "LogStringEvent" => parse_log_string_event(dependencies, offset, object_size, buffer),
+        "LogStringEvent" => parse_log_string_event(dependencies, object_buffer)
+            .with_context(|| "parsing LogStringEvent")?,
         "LogStringInteropEventV2" => {
-            parse_log_string_interop_event(udts, dependencies, offset, object_size, buffer)
+            parse_log_string_interop_event(udts, dependencies, object_buffer)
+                .with_context(|| "parsing LogStringInteropEventV2")?
         }
         "LogStringInteropEventV3" => {
-            let object_buffer = &buffer[offset..(offset + object_size)];
             match parse_log_string_interop_event_v3(udts, dependencies, object_buffer) {
                 Ok(members) => members,
                 Err(e) => {
@@ -339,10 +339,22 @@ where
             Vec::new()
         }
     };
-    Value::Object(Arc::new(Object {
+    Ok(Value::Object(Arc::new(Object {
         type_name: udt.name.clone(),
         members,
-    }))
+    })))
+}
+
+fn check_member_size<T>(member_meta: &Member) -> Result<()> {
+    if member_meta.size != std::mem::size_of::<T>() {
+        bail!(
+            "member {} of type {} has a size of {} bytes",
+            member_meta.name,
+            member_meta.type_name,
+            member_meta.size
+        );
+    }
+    Ok(())
 }
 
 fn parse_pod_instance<S>(
@@ -351,114 +363,100 @@ fn parse_pod_instance<S>(
     dependencies: &HashMap<u64, Value, S>,
     offset: usize,
     buffer: &[u8],
-) -> Value
+    depth: usize,
+) -> Result<Value>
 where
     S: BuildHasher,
 {
-    let members: Vec<(String, Value)> = udt
-        .members
-        .iter()
-        .map(|member_meta| {
-            let name = member_meta.name.clone();
-            let type_name = member_meta.type_name.clone();
-            let value = if member_meta.is_reference {
-                if member_meta.size < std::mem::size_of::<u64>() {
-                    log::error!(
-                        "member references have to be at least 8 bytes {:?}",
-                        member_meta
-                    );
-                    return (name, Value::None);
+    if depth > MAX_NESTING_DEPTH {
+        bail!("{} is nested too deep", udt.name);
+    }
+    let mut members: Vec<(String, Value)> = Vec::with_capacity(udt.members.len());
+    for member_meta in &udt.members {
+        let name = member_meta.name.clone();
+        let member_offset = offset
+            .checked_add(member_meta.offset)
+            .with_context(|| format!("offset of member {name}"))?;
+        let value = if member_meta.is_reference {
+            if member_meta.size < std::mem::size_of::<u64>() {
+                log::error!(
+                    "member references have to be at least 8 bytes {:?}",
+                    member_meta
+                );
+                members.push((name, Value::None));
+                continue;
+            }
+            let key = read_checked::<u64>(buffer, member_offset)?;
+            if let Some(v) = dependencies.get(&key) {
+                v.clone()
+            } else {
+                log::warn!("dependency not found: {}", key);
+                Value::None
+            }
+        } else {
+            match member_meta.type_name.as_str() {
+                "u8" | "uint8" => {
+                    check_member_size::<u8>(member_meta)?;
+                    Value::U8(read_checked::<u8>(buffer, member_offset)?)
                 }
-                let key =
-                    unsafe { read_any::<u64>(buffer.as_ptr().add(offset + member_meta.offset)) };
-                if let Some(v) = dependencies.get(&key) {
-                    v.clone()
-                } else {
-                    log::warn!("dependency not found: {}", key);
-                    Value::None
+                "u32" | "uint32" => {
+                    check_member_size::<u32>(member_meta)?;
+                    Value::U32(read_checked::<u32>(buffer, member_offset)?)
                 }
-            } else {
-                match type_name.as_str() {
-                    "u8" | "uint8" => {
-                        assert_eq!(std::mem::size_of::<u8>(), member_meta.size);
-                        unsafe {
-                            Value::U8(read_any::<u8>(
-                                buffer.as_ptr().add(offset + member_meta.offset),
-                            ))
-                        }
-                    }
-                    "u32" | "uint32" => {
-                        assert_eq!(std::mem::size_of::<u32>(), member_meta.size);
-                        unsafe {
-                            Value::U32(read_any::<u32>(
-                                buffer.as_ptr().add(offset + member_meta.offset),
-                            ))
-                        }
-                    }
-                    "u64" | "uint64" => {
-                        assert_eq!(std::mem::size_of::<u64>(), member_meta.size);
-                        unsafe {
-                            Value::U64(read_any::<u64>(
-                                buffer.as_ptr().add(offset + member_meta.offset),
-                            ))
-                        }
-                    }
-                    "i64" | "int64" => {
-                        assert_eq!(std::mem::size_of::<i64>(), member_meta.size);
-                        unsafe {
-                            Value::I64(read_any::<i64>(
-                                buffer.as_ptr().add(offset + member_meta.offset),
-                            ))
-                        }
-                    }
-                    "f64" => {
-                        assert_eq!(std::mem::size_of::<f64>(), member_meta.size);
-                        unsafe {
-                            Value::F64(read_any::<f64>(
-                                buffer.as_ptr().add(offset + member_meta.offset),
-                            ))
-                        }
-                    }
-                    non_intrinsic_member_type_name => {
-                        if let Some(index) = udts
-                            .iter()
-                            .position(|t| t.name == non_intrinsic_member_type_name)
-                        {
-                            let member_udt = &udts[index];
-                            parse_pod_instance(
-                                member_udt,
-                                udts,
-                                dependencies,
-                                offset + member_meta.offset,
-                                buffer,
-                            )
-                        } else {
-                            log::warn!("unknown member type {}", non_intrinsic_member_type_name);
-                            Value::None
-                        }
+                "u64" | "uint64" => {
+                    check_member_size::<u64>(member_meta)?;
+                    Value::U64(read_checked::<u64>(buffer, member_offset)?)
+                }
+                "i64" | "int64" => {
+                    check_member_size::<i64>(member_meta)?;
+                    Value::I64(read_checked::<i64>(buffer, member_offset)?)
+                }
+                "f64" => {
+                    check_member_size::<f64>(member_meta)?;
+                    Value::F64(read_checked::<f64>(buffer, member_offset)?)
+                }
+                non_intrinsic_member_type_name => {
+                    if let Some(index) = udts
+                        .iter()
+                        .position(|t| t.name == non_intrinsic_member_type_name)
+                    {
+                        let member_udt = &udts[index];
+                        parse_pod_instance(
+                            member_udt,
+                            udts,
+                            dependencies,
+                            member_offset,
+                            buffer,
+                            depth + 1,
+                        )?
+                    } else {
+                        log::warn!("unknown member type {}", non_intrinsic_member_type_name);
+                        Value::None
                     }
                 }
-            };
-            (name, value)
-        })
-        .collect();
+            }
+        };
+        members.push((name, value));
+    }
 
     if udt.is_reference {
         // reference objects need a member called 'id' which is the key to the dependency
         if let Some(id_index) = members.iter().position(|m| m.0 == "id") {
-            return members[id_index].1.clone();
+            return Ok(members[id_index].1.clone());
         }
         log::error!("reference object has no 'id' member");
     }
 
-    Value::Object(Arc::new(Object {
+    Ok(Value::Object(Arc::new(Object {
         type_name: udt.name.clone(),
         members,
-    }))
+    })))
 }
 
 // parse_object_buffer calls fun for each object in the buffer until fun returns
 // `false`
+//
+// the buffer comes from another process: a malformed buffer is reported as an error
 pub fn parse_object_buffer<F, S>(
     dependencies: &HashMap<u64, Value, S>,
     udts: &[UserDefinedType],
@@ -471,33 +469,18 @@ where
 {
     let mut offset = 0;
     while offset < buffer.len() {
-        let type_index = buffer[offset] as usize;
-        if type_index >= udts.len() {
-            bail!("Invalid type index parsing transit objects: {}", type_index);
-        }
-        offset += 1;
-        let udt = &udts[type_index];
-        let (object_size, is_size_dynamic) = match udt.size {
-            0 => {
-                //dynamic size
-                unsafe {
-                    let size_ptr = buffer.as_ptr().add(offset);
-                    let obj_size = read_any::<u32>(size_ptr);
-                    offset += std::mem::size_of::<u32>();
-                    (obj_size as usize, true)
-                }
-            }
-            static_size => (static_size, false),
-        };
-        let instance = if is_size_dynamic {
-            parse_custom_instance(udt, udts, dependencies, offset, object_size, buffer)
+        let (udt, object_offset, object_size) = read_object_header(udts, buffer, offset)?;
+        let object_buffer = slice_checked(buffer, object_offset, object_size)
+            .with_context(|| format!("reading object {}", udt.name))?;
+        let instance = if udt.size == 0 {
+            parse_custom_instance(udt, udts, dependencies, object_buffer)?
         } else {
-            parse_pod_instance(udt, udts, dependencies, offset, buffer)
+            parse_pod_instance(udt, udts, dependencies, 0, object_buffer, 0)?
         };
         if !fun(instance)? {
             return Ok(false);
         }
-        offset += object_size;
+        offset = object_offset + object_size;
     }
     Ok(true)
 }
@@ -19,6 +19,37 @@ pub unsafe fn read_any<T>(ptr: *const u8) -> T {
     std::ptr::read_unaligned(ptr.cast::<T>())
 }
 
+/// Reads a value of type T at `offset` of a buffer received from another process
+///
+/// Fails instead of reading past the end of the buffer. T must be valid for any bit pattern,
+/// i.e. an integer or a float.
+#[allow(unsafe_code)]
+pub fn read_checked<T: Copy>(buffer: &[u8], offset: usize) -> anyhow::Result<T> {
+    let size = std::mem::size_of::<T>();
+    match offset.checked_add(size) {
+        Some(end) if end <= buffer.len() => unsafe {
+            Ok(read_any::<T>(buffer.as_ptr().add(offset)))
+        },
+        _ => anyhow::bail!(
+            "reading {size} bytes at offset {offset} of a buffer of {} bytes",
+            buffer.len()
+        ),
+    }
+}
+
+/// Slice of `len` bytes at `offset`, fails if the buffer is too short
+pub fn slice_checked(buffer: &[u8], offset: usize, len: usize) -> anyhow::Result<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| buffer.get(offset..end))
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "reading {len} bytes at offset {offset} of a buffer of {} bytes",
+                buffer.len()
+            )
+        })
+}
+
 /// Helps speed up the serialization of types which size is known at compile time.
 pub enum InProcSize {
     Const(usize),
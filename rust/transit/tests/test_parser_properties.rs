@@ -0,0 +1,234 @@
+// Blocks are sent by the instrumented processes: whatever their content, parsing them
+// must fail with an error, not panic.
+use arbitrary::{Arbitrary, Unstructured};
+use micromegas_transit::prelude::*;
+use micromegas_transit::{parse_object_buffer, read_dependencies};
+use proptest::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, TransitReflect)]
+pub struct SampleEvent {
+    pub desc: &'static str,
+    pub time: i64,
+    pub value: f64,
+    pub count: u64,
+    pub line: u32,
+}
+
+impl InProcSerialize for SampleEvent {}
+
+declare_queue_struct!(
+    struct SampleQueue<SampleEvent> {}
+);
+
+declare_queue_struct!(
+    struct SampleDepsQueue<StaticString> {}
+);
+
+const DESC: &str = "sample description";
+
+fn sample_buffers() -> (Vec<u8>, Vec<u8>) {
+    let mut deps = SampleDepsQueue::new(1024);
+    deps.push(StaticString::from(DESC));
+    let mut objects = SampleQueue::new(1024);
+    for i in 0..3 {
+        objects.push(SampleEvent {
+            desc: DESC,
+            time: i,
+            value: 1.5,
+            count: 7,
+            line: 42,
+        });
+    }
+    (deps.as_bytes().to_vec(), objects.as_bytes().to_vec())
+}
+
+fn parse_all(
+    dependencies: &HashMap<u64, Value>,
+    udts: &[UserDefinedType],
+    buffer: &[u8],
+) -> anyhow::Result<Vec<Value>> {
+    let mut values = vec![];
+    parse_object_buffer(dependencies, udts, buffer, |value| {
+        values.push(value);
+        Ok(true)
+    })?;
+    Ok(values)
+}
+
+/// Metadata of the custom objects the parser knows about, with plausible sizes
+fn custom_udts() -> Vec<UserDefinedType> {
+    let reference = |name: &str, size| UserDefinedType {
+        name: name.to_owned(),
+        size,
+        members: vec![Member {
+            name: "id".to_owned(),
+            type_name: "usize".to_owned(),
+            offset: 0,
+            size: 8,
+            is_reference: true,
+        }],
+        is_reference: true,
+        secondary_udts: vec![],
+    };
+    let dynamic = |name: &str| UserDefinedType {
+        name: name.to_owned(),
+        size: 0,
+        members: vec![],
+        is_reference: false,
+        secondary_udts: vec![],
+    };
+    let mut udts = SampleQueue::reflect_contained();
+    udts.extend([
+        StaticString::reflect(),
+        dynamic("StaticStringDependency"),
+        dynamic("LogStringEvent"),
+        dynamic("LogStringInteropEventV2"),
+        dynamic("LogStringInteropEventV3"),
+        reference("StringId", 16),
+        reference("StaticStringRef", 8),
+    ]);
+    udts
+}
+
+const TYPE_NAMES: &[&str] = &[
+    "u8",
+    "u32",
+    "u64",
+    "i64",
+    "f64",
+    "usize",
+    "StringId",
+    "StaticStringRef",
+    "Nested",
+];
+
+#[derive(Debug, Arbitrary)]
+struct ArbitraryMember {
+    type_index: u8,
+    offset: u16,
+    size: u8,
+    is_reference: bool,
+}
+
+#[derive(Debug, Arbitrary)]
+struct ArbitraryUdt {
+    // 0 is a dynamic size
+    size: u8,
+    is_reference: bool,
+    members: Vec<ArbitraryMember>,
+}
+
+/// Metadata as a hostile process could describe it, members can overlap or exceed the object
+/// and `Nested` can contain itself
+fn hostile_udts(u: &mut Unstructured) -> arbitrary::Result<Vec<UserDefinedType>> {
+    let mut udts = custom_udts();
+    let arbitrary_udts: Vec<ArbitraryUdt> = u.arbitrary()?;
+    for (index, udt) in arbitrary_udts.into_iter().enumerate() {
+        udts.push(UserDefinedType {
+            name: if index == 0 {
+                "Nested".to_owned()
+            } else {
+                format!("Hostile{index}")
+            },
+            size: udt.size as usize,
+            members: udt
+                .members
+                .into_iter()
+                .enumerate()
+                .map(|(member_index, member)| Member {
+                    name: if member_index == 0 {
+                        "id".to_owned()
+                    } else {
+                        format!("member{member_index}")
+                    },
+                    type_name: TYPE_NAMES[member.type_index as usize % TYPE_NAMES.len()].to_owned(),
+                    offset: member.offset as usize,
+                    size: member.size as usize,
+                    is_reference: member.is_reference,
+                })
+                .collect(),
+            is_reference: udt.is_reference,
+            secondary_udts: vec![],
+        });
+    }
+    Ok(udts)
+}
+
+#[test]
+fn test_parse_sample() {
+    let (deps_buffer, objects_buffer) = sample_buffers();
+    let dependencies =
+        read_dependencies(&SampleDepsQueue::reflect_contained(), &deps_buffer).unwrap();
+    let values = parse_all(
+        &dependencies,
+        &SampleQueue::reflect_contained(),
+        &objects_buffer,
+    )
+    .unwrap();
+    assert_eq!(values.len(), 3);
+    let Value::Object(obj) = &values[2] else {
+        panic!("not an object: {:?}", values[2]);
+    };
+    assert_eq!(obj.get::<Arc<String>>("desc").unwrap().as_str(), DESC);
+    assert_eq!(obj.get::<i64>("time").unwrap(), 2);
+    assert_eq!(obj.get::<f64>("value").unwrap(), 1.5);
+    assert_eq!(obj.get::<u64>("count").unwrap(), 7);
+    assert_eq!(obj.get::<u32>("line").unwrap(), 42);
+}
+
+proptest! {
+    #[test]
+    fn truncated_objects_are_errors(cut in 1usize..100) {
+        let (deps_buffer, objects_buffer) = sample_buffers();
+        let dependencies =
+            read_dependencies(&SampleDepsQueue::reflect_contained(), &deps_buffer).unwrap();
+        let udts = SampleQueue::reflect_contained();
+        let object_size = objects_buffer.len() / 3;
+        let cut = cut % objects_buffer.len();
+        let res = parse_all(&dependencies, &udts, &objects_buffer[..cut]);
+        if cut % object_size == 0 {
+            prop_assert_eq!(res.unwrap().len(), cut / object_size);
+        } else {
+            prop_assert!(res.is_err());
+        }
+        prop_assert!(read_dependencies(
+            &SampleDepsQueue::reflect_contained(),
+            &deps_buffer[..cut.min(deps_buffer.len() - 1)]
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn corrupted_objects_dont_panic(
+        position in any::<prop::sample::Index>(),
+        byte in any::<u8>()
+    ) {
+        let (mut deps_buffer, mut objects_buffer) = sample_buffers();
+        let deps_position = position.index(deps_buffer.len());
+        deps_buffer[deps_position] = byte;
+        let objects_position = position.index(objects_buffer.len());
+        objects_buffer[objects_position] = byte;
+        let udts = custom_udts();
+        let dependencies = read_dependencies(&udts, &deps_buffer).unwrap_or_default();
+        let _ = parse_all(&dependencies, &udts, &objects_buffer);
+    }
+
+    #[test]
+    fn random_buffers_dont_panic(buffer in prop::collection::vec(any::<u8>(), 0..512)) {
+        let udts = custom_udts();
+        let dependencies = read_dependencies(&udts, &buffer).unwrap_or_default();
+        let _ = parse_all(&dependencies, &udts, &buffer);
+    }
+
+    #[test]
+    fn hostile_metadata_doesnt_panic(data in prop::collection::vec(any::<u8>(), 0..2048)) {
+        let mut u = Unstructured::new(&data);
+        if let Ok(udts) = hostile_udts(&mut u) {
+            let buffer = u.take_rest();
+            let dependencies = read_dependencies(&udts, buffer).unwrap_or_default();
+            let _ = parse_all(&dependencies, &udts, buffer);
+        }
+    }
+}
@@ -47,6 +47,23 @@ namespace
 #endif
 	}
 
+	// in declaration order, formatted like the rust frame_budgets module: Physics=4;Render=8
+	TArray<TPair<FString, double>>& GetFrameBudgets()
+	{
+		static TArray<TPair<FString, double>> FrameBudgets;
+		return FrameBudgets;
+	}
+
+	FString FormatFrameBudgets()
+	{
+		TArray<FString> Budgets;
+		for (const TPair<FString, double>& Budget : GetFrameBudgets())
+		{
+			Budgets.Add(FString::Printf(TEXT("%s=%g"), *Budget.Key, Budget.Value));
+		}
+		return FString::Join(Budgets, TEXT(";"));
+	}
+
 } // namespace
 
 HttpEventSink::HttpEventSink(const FString& InBaseUrl,
@@ -246,6 +263,18 @@ FString CreateGuid()
 	return FGuid::NewGuid().ToString(EGuidFormats::DigitsWithHyphens);
 }
 
+void DeclareFrameBudget(const FString& SpanName, double MaxMs)
+{
+	if (SpanName.IsEmpty() || SpanName.Contains(TEXT(";")) || SpanName.Contains(TEXT("=")) || !FMath::IsFinite(MaxMs) || MaxMs < 0.0)
+	{
+		UE_LOG(LogMicromegasTelemetrySink, Warning, TEXT("Ignoring invalid frame budget of %s: %fms"), *SpanName, MaxMs);
+		return;
+	}
+	TArray<TPair<FString, double>>& FrameBudgets = GetFrameBudgets();
+	FrameBudgets.RemoveAll([&SpanName](const TPair<FString, double>& Budget) { return Budget.Key == SpanName; });
+	FrameBudgets.Emplace(SpanName, MaxMs);
+}
+
 FString GetDistro()
 {
 	return FString::Printf(TEXT("%s %s"), ANSI_TO_TCHAR(FPlatformProperties::PlatformName()), *FPlatformMisc::GetOSVersion());
@@ -280,6 +309,10 @@ TSharedPtr<MicromegasTracing::EventSink, ESPMode::ThreadSafe> InitHttpEventSink(
 	Process->TscFrequency = GetTscFrequency();
 	Process->StartTime = StartTime;
 	Process->Properties.Add(TEXT("build-version"), FApp::GetBuildVersion());
+	if (GetFrameBudgets().Num() > 0)
+	{
+		Process->Properties.Add(TEXT("frame_budgets"), FormatFrameBudgets());
+	}
 
 	TSharedPtr<MicromegasTracing::EventSink, ESPMode::ThreadSafe> Sink = MakeShared<HttpEventSink>(BaseUrl, Process, Auth, Sampling, Flusher);
 	const size_t LOG_BUFFER_SIZE = 10 * 1024 * 1024;
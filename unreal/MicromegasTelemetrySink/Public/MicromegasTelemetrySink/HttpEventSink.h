@@ -68,3 +68,7 @@ MICROMEGASTELEMETRYSINK_API TSharedPtr<MicromegasTracing::EventSink, ESPMode::Th
 	const SharedTelemetryAuthenticator& Auth,
 	const SharedSamplingController& Sampling,
 	const SharedFlushMonitor& Flusher);
+
+// At most MaxMs of spans named SpanName per frame, to be called before InitHttpEventSink.
+// Compared to the spans of each frame by the frame_budget_violations table function.
+MICROMEGASTELEMETRYSINK_API void DeclareFrameBudget(const FString& SpanName, double MaxMs);
@@ -40,6 +40,8 @@ namespace MicromegasTracing
 			logStreamId,
 			logBlock,
 			TArray<FString>({ TEXT("log") }));
+		// UE_LOG messages embed their categories, i.e. [LogNet], extracted in the category column by the analytics service
+		LogEntries->SetProperty(TEXT("log-categories"), TEXT("bracketed"));
 
 		FString metricStreamId = allocNewGuid();
 		MetricsBlockPtr metricBlock = MakeShared<MetricBlock>(metricStreamId,